@@ -0,0 +1,244 @@
+use std::path::{Path, PathBuf};
+
+use clap::builder::{PossibleValuesParser, TypedValueParser};
+use clap::{Arg, ArgAction, ArgMatches, Command, ValueHint};
+use parse_utils::cli::{init_tracing, print_error, write_mode};
+use parse_utils::output::in_out_dir;
+use parse_utils::watch::watch;
+
+use crate::{
+    disassemble_file, histogram_file, index_file, load_symbol_map, parse_and_convert_file,
+    save_symbol_map, AssemblyOptions, ErrorType, OutputFormat,
+};
+
+/// The command line interface of the assembler, shared by the standalone binary and n2t
+pub fn command() -> Command {
+    Command::new("Hack Assembler")
+        .about("Compile hack assembly files into machine code")
+        .arg(
+            Arg::new("INPUT")
+                .index(1)
+                .required(true)
+                .value_name("FILE")
+                .value_hint(ValueHint::FilePath)
+                .help("A Hack assembly file, or a .hack file with --disassemble"),
+        )
+        .arg(
+            Arg::new("symbol")
+                .short('s')
+                .long("symbol")
+                .action(ArgAction::SetTrue)
+                .required(false)
+                .help("Save a symbol file in the same directory as the output"),
+        )
+        .arg(
+            Arg::new("with_sidecar")
+                .long("with-sidecar")
+                .action(ArgAction::SetTrue)
+                .required(false)
+                .help("Also write a .hack.txt file listing the address, binary word and assembly of each instruction"),
+        )
+        .arg(
+            Arg::new("index")
+                .long("index")
+                .action(ArgAction::SetTrue)
+                .required(false)
+                .help("Print a JSON index of labels, references and diagnostics instead of assembling"),
+        )
+        .arg(
+            Arg::new("histogram")
+                .long("histogram")
+                .action(ArgAction::SetTrue)
+                .required(false)
+                .help("Print how often each C-instruction is used instead of assembling"),
+        )
+        .arg(
+            Arg::new("disassemble")
+                .long("disassemble")
+                .action(ArgAction::SetTrue)
+                .required(false)
+                .help("Print the assembly for a .hack file instead of assembling"),
+        )
+        .arg(
+            Arg::new("symbols")
+                .long("symbols")
+                .value_name("FILE")
+                .value_hint(ValueHint::FilePath)
+                .requires("disassemble")
+                .help("The symbol file to restore labels from when disassembling. Defaults to the .symbol file next to the input if there is one"),
+        )
+        .arg(
+            Arg::new("listing")
+                .long("listing")
+                .action(ArgAction::SetTrue)
+                .required(false)
+                .help("Also write a .lst file giving the ROM address, the binary and hex encoding and the source line of each instruction, with the value of each symbol"),
+        )
+        .arg(
+            Arg::new("symbol_map")
+                .long("symbol-map")
+                .action(ArgAction::SetTrue)
+                .required(false)
+                .help("Save the address of each label and variable as a .symbols.json file next to the output"),
+        )
+        .arg(
+            Arg::new("compare_symbols")
+                .long("compare-symbols")
+                .value_name("FILE")
+                .value_hint(ValueHint::FilePath)
+                .help("Report the labels and variables whose address changed since the build which saved this .symbols.json file"),
+        )
+        .arg(
+            Arg::new("bare")
+                .long("bare")
+                .action(ArgAction::SetTrue)
+                .required(false)
+                .help("Start without predefined symbols and don't allocate variables"),
+        )
+        .arg(
+            Arg::new("strict")
+                .long("strict")
+                .action(ArgAction::SetTrue)
+                .required(false)
+                .help("Reject the pseudo-instructions GOTO, LOAD, PUSH and POP, which the course's assembler doesn't accept"),
+        )
+        .arg(
+            Arg::new("bank_at")
+                .long("bank-at")
+                .value_name("LABEL")
+                .action(ArgAction::Append)
+                .help("Start a new ROM bank at this label, for Hack variants with a BANK register. Banks after the first are written to X.bank1.hack, X.bank2.hack, ..."),
+        )
+        .arg(
+            Arg::new("format")
+                .long("format")
+                .value_name("FORMAT")
+                .value_parser(PossibleValuesParser::new(OutputFormat::NAMES).map(|name| {
+                    name.parse::<OutputFormat>()
+                        .expect("Only possible values are parsed")
+                }))
+                .default_value("text")
+                .help("How to write the program: text lines of binary digits in a .hack file, 16-bit big-endian words in a .bin file, lines of hex digits in a .hex file, or Intel HEX with word addresses in a .ihx file for FPGA toolchains"),
+        )
+        .arg(
+            Arg::new("out_dir")
+                .long("out-dir")
+                .value_name("DIR")
+                .value_hint(ValueHint::DirPath)
+                .help("Write the .hack, .hack.txt, .symbol and .symbols.json files to this directory instead of next to the source"),
+        )
+        .arg(
+            Arg::new("dry_run")
+                .long("dry-run")
+                .action(ArgAction::SetTrue)
+                .required(false)
+                .help("Report the files which would be written without writing them"),
+        )
+        .arg(
+            Arg::new("trace")
+                .long("trace")
+                .action(ArgAction::SetTrue)
+                .required(false)
+                .help("Log every pass and file processed to stderr"),
+        )
+        .arg(
+            Arg::new("timings")
+                .long("timings")
+                .action(ArgAction::SetTrue)
+                .required(false)
+                .help("Report the time spent in each pass to stderr"),
+        )
+        .arg(
+            Arg::new("watch")
+                .long("watch")
+                .action(ArgAction::SetTrue)
+                .required(false)
+                .help("Assemble again whenever the source changes, until stopped with Ctrl-C"),
+        )
+}
+
+pub fn run(matches: &ArgMatches) -> Result<(), ErrorType> {
+    init_tracing(matches);
+
+    let path = matches
+        .get_one::<String>("INPUT")
+        .expect("User to provide an input path");
+
+    if matches.get_flag("watch") {
+        watch(
+            &[Path::new(path)],
+            &[],
+            || assemble(matches, path),
+            |error| print_error(error),
+        );
+    }
+    assemble(matches, path)
+}
+
+fn assemble(matches: &ArgMatches, path: &str) -> Result<(), ErrorType> {
+    if matches.get_flag("index") {
+        println!("{}", index_file(path)?);
+        return Ok(());
+    }
+    if matches.get_flag("disassemble") {
+        let symbol_path = match matches.get_one::<String>("symbols") {
+            Some(symbols) => Some(PathBuf::from(symbols)),
+            None => Some(Path::new(path).with_extension("symbol")).filter(|path| path.exists()),
+        };
+        print!("{}", disassemble_file(path, symbol_path.as_deref())?);
+        return Ok(());
+    }
+    if matches.get_flag("histogram") {
+        print!("{}", histogram_file(path)?);
+        return Ok(());
+    }
+
+    let generate_symbol_file = matches.get_flag("symbol");
+    let options = AssemblyOptions {
+        bare: matches.get_flag("bare"),
+        strict: matches.get_flag("strict"),
+        listing: matches.get_flag("listing"),
+        format: *matches
+            .get_one::<OutputFormat>("format")
+            .expect("format has a default"),
+        bank_starts: matches
+            .get_many::<String>("bank_at")
+            .into_iter()
+            .flatten()
+            .cloned()
+            .collect(),
+    };
+
+    // Load the previous build's symbols first, as this build may be about to replace them
+    let previous = matches
+        .get_one::<String>("compare_symbols")
+        .map(|previous| load_symbol_map(Path::new(previous)))
+        .transpose()?;
+
+    // Load the assembly
+    let mode = write_mode(matches);
+    let out_dir = matches.get_one::<String>("out_dir").map(Path::new);
+    let symbol_map = parse_and_convert_file(
+        path,
+        generate_symbol_file,
+        matches.get_flag("with_sidecar"),
+        &options,
+        mode,
+        out_dir,
+    )?;
+
+    if let Some(previous) = previous {
+        let changes = symbol_map.changes_since(&previous);
+        if changes.is_empty() {
+            println!("No label or variable has moved");
+        }
+        for change in changes {
+            println!("{}", change);
+        }
+    }
+    if matches.get_flag("symbol_map") {
+        let map_path = in_out_dir(&Path::new(path).with_extension("symbols.json"), out_dir);
+        save_symbol_map(&map_path, &symbol_map, mode)?;
+    }
+    Ok(())
+}
@@ -16,6 +16,19 @@ pub fn find_variables(statements: &Vec<Stmt>, symbol_table: &mut HashMap<String,
     }
 }
 
+/// Find the first symbol which isn't in the table, for when variables aren't being allocated
+pub fn find_undefined_symbol<'a>(
+    statements: &'a [Stmt],
+    symbol_table: &HashMap<String, u16>,
+) -> Option<&'a str> {
+    statements.iter().find_map(|stmt| match stmt {
+        Stmt::A(Address::Symbol(symbol)) if !symbol_table.contains_key(symbol) => {
+            Some(symbol.as_str())
+        }
+        _ => None,
+    })
+}
+
 #[test]
 fn test_convert_variables() {
     let mut symbol_table = crate::symbol_table::create_symbol_table();
@@ -30,3 +43,20 @@ fn test_convert_variables() {
     assert_eq!(*symbol_table.get("i").unwrap(), 16);
     assert_eq!(*symbol_table.get("i2").unwrap(), 17);
 }
+
+#[test]
+fn test_find_undefined_symbol() {
+    let mut symbol_table = HashMap::new();
+    symbol_table.insert("LOOP".to_owned(), 0);
+
+    let statements = vec![
+        Stmt::A(Address::Symbol("LOOP".to_string())),
+        Stmt::A(Address::Value(3)),
+        Stmt::A(Address::Symbol("R0".to_string())),
+    ];
+
+    assert_eq!(
+        find_undefined_symbol(&statements, &symbol_table),
+        Some("R0")
+    );
+}
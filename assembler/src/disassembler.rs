@@ -0,0 +1,212 @@
+use std::collections::HashMap;
+use std::fmt::Write;
+
+use crate::convert_labels::{find_labels, remove_all_labels};
+use crate::convert_variables::find_variables;
+use crate::interpreter::convert_operation;
+use crate::listing::word_columns;
+use crate::parser::{Address, Command, Dest, Jump, Line, Operation, Stmt};
+use crate::symbol_table::create_symbol_table;
+
+/// Names to restore when disassembling: labels by ROM address and the symbol each A-instruction
+/// was written with
+#[derive(Debug, Default)]
+pub struct Symbols {
+    labels: HashMap<usize, Vec<String>>,
+    references: HashMap<usize, String>,
+}
+
+impl Symbols {
+    /// Recover the names from the source lines recorded in a symbol file.
+    ///
+    /// A reference is only kept if the symbol still resolves to the value in the binary, so a
+    /// hand-edited instruction is shown as a number rather than with a stale name.
+    pub fn from_lines(lines: &[Line], words: &[u16]) -> Self {
+        let statements: Vec<Stmt> = lines
+            .iter()
+            .filter(|line| !matches!(line.stmt, Stmt::Empty))
+            .map(|line| line.stmt.clone())
+            .collect();
+
+        let mut symbol_table = create_symbol_table();
+        find_labels(&statements, &mut symbol_table);
+        let mut symbols = Symbols::default();
+        let mut address = 0;
+        for stmt in &statements {
+            match stmt {
+                Stmt::Label(name) => symbols
+                    .labels
+                    .entry(address)
+                    .or_default()
+                    .push(name.clone()),
+                Stmt::A(_) | Stmt::C(_) => address += 1,
+                Stmt::Empty => {}
+            }
+        }
+
+        let instructions = remove_all_labels(statements);
+        find_variables(&instructions, &mut symbol_table);
+        for (index, stmt) in instructions.iter().enumerate() {
+            if let (Stmt::A(Address::Symbol(name)), Some(word)) = (stmt, words.get(index)) {
+                if symbol_table.get(name) == Some(word) {
+                    symbols.references.insert(index, name.clone());
+                }
+            }
+        }
+        symbols
+    }
+
+    /// Invent a label for every address which is loaded right before a jump
+    pub fn generate(words: &[u16]) -> Self {
+        let mut symbols = Symbols::default();
+        for (index, pair) in words.windows(2).enumerate() {
+            let (target, next) = (pair[0], pair[1]);
+            let is_jump = next & 0x8000 != 0 && next & 0b111 != 0;
+            if target & 0x8000 == 0 && is_jump && (target as usize) < words.len() {
+                let name = format!("LABEL_{}", target);
+                symbols.labels.insert(target as usize, vec![name.clone()]);
+                symbols.references.insert(index, name);
+            }
+        }
+        symbols
+    }
+}
+
+/// Decode a C-instruction, or None if its computation bits don't match an operation
+fn decode_c_instruction(word: u16) -> Option<Command> {
+    let comp = (word >> 6) & 0b111_1111;
+    let operation = Operation::ALL
+        .into_iter()
+        .find(|operation| convert_operation(*operation) == comp)?;
+
+    Some(Command {
+        dest: Some(Dest::ALL[((word >> 3) & 0b111) as usize]),
+        operation,
+        jump: Some(Jump::ALL[(word & 0b111) as usize]),
+    })
+}
+
+/// The assembly for one word, or None if it isn't a valid instruction
+fn instruction(index: usize, word: u16, symbols: &Symbols) -> Option<String> {
+    if word & 0x8000 == 0 {
+        Some(match symbols.references.get(&index) {
+            Some(name) => format!("@{}", name),
+            None => format!("@{}", word),
+        })
+    } else {
+        decode_c_instruction(word).map(|command| command.to_string())
+    }
+}
+
+/// Turn machine words back into Hack assembly.
+///
+/// Returns the index of the first word which isn't a valid instruction on failure.
+pub fn disassemble(words: &[u16], symbols: &Symbols) -> Result<String, usize> {
+    let mut output = String::new();
+    for (index, word) in words.iter().enumerate() {
+        if let Some(labels) = symbols.labels.get(&index) {
+            for label in labels {
+                writeln!(output, "({})", label).expect("Writing to a String cannot fail");
+            }
+        }
+
+        let instruction = instruction(index, *word, symbols).ok_or(index)?;
+        writeln!(output, "{}", instruction).expect("Writing to a String cannot fail");
+    }
+
+    // Labels can point one past the end of the program
+    if let Some(labels) = symbols.labels.get(&words.len()) {
+        for label in labels {
+            writeln!(output, "({})", label).expect("Writing to a String cannot fail");
+        }
+    }
+    Ok(output)
+}
+
+/// A listing of machine words for reading alongside a .hack file: the address, the word in
+/// binary and its assembly on each line, with labels on lines of their own
+pub fn sidecar(words: &[u16], symbols: &Symbols) -> String {
+    let mut output = String::new();
+    for (index, word) in words.iter().enumerate() {
+        for label in symbols.labels.get(&index).into_iter().flatten() {
+            writeln!(output, "{}  ({})", word_columns(None), label)
+                .expect("Writing to a String cannot fail");
+        }
+        let instruction = instruction(index, *word, symbols).unwrap_or_else(|| "???".to_owned());
+        writeln!(
+            output,
+            "{}  {}",
+            word_columns(Some((index, *word))),
+            instruction
+        )
+        .expect("Writing to a String cannot fail");
+    }
+    output
+}
+
+#[test]
+fn test_disassemble_restores_symbols() {
+    let source = "// Count down from 3\n@3\nD=A\n@i\nM=D\n(LOOP)\n@i\nMD=M-1\n@LOOP\nD;JGT\n(END)";
+    let binary = crate::assemble_string(source).unwrap();
+    let words: Vec<u16> = binary
+        .lines()
+        .map(|line| u16::from_str_radix(line, 2).unwrap())
+        .collect();
+
+    let lines = crate::parser::parse_hack(source).unwrap();
+    assert_eq!(
+        disassemble(&words, &Symbols::from_lines(&lines, &words)).unwrap(),
+        "@3\nD=A\n@i\nM=D\n(LOOP)\n@i\nMD=M-1\n@LOOP\nD;JGT\n(END)\n"
+    );
+    assert_eq!(
+        disassemble(&words, &Symbols::generate(&words)).unwrap(),
+        "@3\nD=A\n@16\nM=D\n(LABEL_4)\n@16\nMD=M-1\n@LABEL_4\nD;JGT\n"
+    );
+
+    // A hand-edited jump target no longer matches the label
+    let mut edited = words.clone();
+    edited[6] = 2;
+    assert!(disassemble(&edited, &Symbols::from_lines(&lines, &edited))
+        .unwrap()
+        .contains("(LOOP)\n@i\nMD=M-1\n@2\nD;JGT"));
+
+    assert_eq!(
+        disassemble(&[0b1110_0000_0100_0000], &Symbols::default()),
+        Err(0)
+    );
+}
+
+#[test]
+fn test_sidecar() {
+    let source = "@2\nD=A\n(LOOP)\n@LOOP\nD;JGT";
+    let binary = crate::assemble_string(source).unwrap();
+    let words: Vec<u16> = binary
+        .lines()
+        .map(|line| u16::from_str_radix(line, 2).unwrap())
+        .collect();
+    let lines = crate::parser::parse_hack(source).unwrap();
+
+    assert_eq!(
+        sidecar(&words, &Symbols::from_lines(&lines, &words)),
+        "    0  0000000000000010  @2
+    1  1110110000010000  D=A
+                         (LOOP)
+    2  0000000000000010  @LOOP
+    3  1110001100000001  D;JGT
+"
+    );
+}
+
+#[test]
+fn test_parse_line_round_trips_decoded_instructions() {
+    for operation in Operation::ALL {
+        for control_bits in [0b010_000, 0b000_011] {
+            let word = 0xE000 | (convert_operation(operation) << 6) | control_bits;
+            let text = decode_c_instruction(word).unwrap().to_string();
+            match crate::parser::parse_line(&text) {
+                Ok(Stmt::C(command)) => assert_eq!(command.to_string(), text),
+                other => panic!("{} parsed as {:?}", text, other),
+            }
+        }
+    }
+}
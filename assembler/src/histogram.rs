@@ -0,0 +1,60 @@
+use std::collections::HashMap;
+use std::fmt::Write;
+
+use crate::parser::{Line, Stmt};
+
+/// Count how often each distinct C-instruction appears, most common first, so students can see
+/// which parts of the instruction set a program exercises
+pub fn instruction_histogram(lines: &[Line]) -> String {
+    let mut a_instructions = 0;
+    let mut counts: HashMap<String, usize> = HashMap::new();
+    for line in lines {
+        match &line.stmt {
+            Stmt::A(_) => a_instructions += 1,
+            Stmt::C(command) => *counts.entry(command.to_string()).or_default() += 1,
+            _ => {}
+        }
+    }
+
+    let mut counts: Vec<(String, usize)> = counts.into_iter().collect();
+    counts.sort_by(|(a_text, a_count), (b_text, b_count)| {
+        b_count.cmp(a_count).then_with(|| a_text.cmp(b_text))
+    });
+
+    let c_instructions: usize = counts.iter().map(|(_, count)| count).sum();
+    let mut output = format!(
+        "{} A-instructions, {} C-instructions ({} distinct)\n",
+        a_instructions,
+        c_instructions,
+        counts.len()
+    );
+    for (text, count) in counts {
+        writeln!(output, "{:>8}  {}", count, text).expect("Writing to a String cannot fail");
+    }
+    output
+}
+
+#[test]
+fn test_instruction_histogram() {
+    let lines = crate::parser::parse_hack(
+        "(LOOP)
+        @SP
+        AM=M-1
+        D=M
+        @SP
+        AM=M-1
+        D;JGT
+        0;JMP",
+    )
+    .unwrap();
+
+    assert_eq!(
+        instruction_histogram(&lines),
+        "2 A-instructions, 5 C-instructions (4 distinct)
+       2  AM=M-1
+       1  0;JMP
+       1  D;JGT
+       1  D=M
+"
+    );
+}
@@ -0,0 +1,130 @@
+use serde::Serialize;
+
+use crate::parser::{parse_line, Address, Stmt};
+
+/// Navigation information for a Hack assembly file, intended for editors.
+///
+/// Line numbers are 1-based.
+#[derive(Debug, Default, Serialize)]
+pub struct SourceIndex {
+    pub definitions: Vec<Definition>,
+    pub references: Vec<Reference>,
+    pub diagnostics: Vec<Diagnostic>,
+}
+
+/// A label declaration along with the ROM address it resolves to
+#[derive(Debug, PartialEq, Serialize)]
+pub struct Definition {
+    pub name: String,
+    pub line: usize,
+    pub address: u16,
+}
+
+/// A symbolic A-instruction
+#[derive(Debug, PartialEq, Serialize)]
+pub struct Reference {
+    pub name: String,
+    pub line: usize,
+}
+
+#[derive(Debug, PartialEq, Serialize)]
+pub struct Diagnostic {
+    pub line: usize,
+    pub message: String,
+}
+
+/// Index a Hack assembly file.
+///
+/// Unlike assembly this doesn't stop at the first bad line, every parse failure is reported as
+/// a diagnostic.
+pub fn index_hack(contents: &str) -> SourceIndex {
+    let mut index = SourceIndex::default();
+    let mut address = 0u16;
+
+    for (line_index, line) in contents.lines().enumerate() {
+        let line_number = line_index + 1;
+
+        match parse_line(line) {
+            Ok(Stmt::Label(name)) => {
+                if let Some(existing) = index.definitions.iter().find(|def| def.name == name) {
+                    index.diagnostics.push(Diagnostic {
+                        line: line_number,
+                        message: format!(
+                            "Label {} is already defined on line {}",
+                            name, existing.line
+                        ),
+                    });
+                }
+                index.definitions.push(Definition {
+                    name,
+                    line: line_number,
+                    address,
+                });
+            }
+            Ok(Stmt::A(Address::Symbol(name))) => {
+                index.references.push(Reference {
+                    name,
+                    line: line_number,
+                });
+                address = address.wrapping_add(1);
+            }
+            Ok(Stmt::A(_)) | Ok(Stmt::C(_)) => address = address.wrapping_add(1),
+            Ok(Stmt::Empty) => {}
+            Err(message) => index.diagnostics.push(Diagnostic {
+                line: line_number,
+                message,
+            }),
+        }
+    }
+
+    index
+}
+
+#[test]
+fn test_index_hack() {
+    let index = index_hack(
+        r#"// Loop forever
+@i
+M=0
+(LOOP)
+@LOOP
+0;JMP
+D=D+
+(LOOP)"#,
+    );
+
+    assert_eq!(
+        index.definitions,
+        vec![
+            Definition {
+                name: "LOOP".to_owned(),
+                line: 4,
+                address: 2
+            },
+            Definition {
+                name: "LOOP".to_owned(),
+                line: 8,
+                address: 4
+            }
+        ]
+    );
+    assert_eq!(
+        index.references,
+        vec![
+            Reference {
+                name: "i".to_owned(),
+                line: 2
+            },
+            Reference {
+                name: "LOOP".to_owned(),
+                line: 5
+            }
+        ]
+    );
+    assert_eq!(index.diagnostics.len(), 2);
+    assert_eq!(index.diagnostics[0].line, 7);
+    assert_eq!(
+        index.diagnostics[1].message,
+        "Label LOOP is already defined on line 4"
+    );
+}
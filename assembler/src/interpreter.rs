@@ -2,12 +2,12 @@ use std::collections::HashMap;
 
 use crate::parser::{Address, Command, Dest, Operation, Stmt};
 
-fn convert_a_statement(address: Address, symbol_table: &HashMap<String, u16>) -> u16 {
+fn convert_a_statement(address: &Address, symbol_table: &HashMap<String, u16>) -> u16 {
     const MASK: u16 = 0b01111111_11111111;
     match address {
         Address::Value(val) => val & MASK,
         Address::Symbol(symbol) => {
-            let symbol_value = symbol_table.get(&symbol);
+            let symbol_value = symbol_table.get(symbol);
             match symbol_value {
                 Some(value) => *value & MASK,
                 None => panic!("Unable to find symbol in table {}", symbol),
@@ -16,7 +16,7 @@ fn convert_a_statement(address: Address, symbol_table: &HashMap<String, u16>) ->
     }
 }
 
-fn convert_operation(operation: Operation) -> u16 {
+pub fn convert_operation(operation: Operation) -> u16 {
     match operation {
         Operation::Zero => 0b0101010,
         Operation::One => 0b0111111,
@@ -49,7 +49,7 @@ fn convert_operation(operation: Operation) -> u16 {
     }
 }
 
-fn convert_c_statement(command: Command) -> u16 {
+fn convert_c_statement(command: &Command) -> u16 {
     0b1110_0000_0000_0000 as u16
         | (convert_operation(command.operation) << 6)
         | ((command.dest.unwrap_or(Dest::NULL) as u16) << 3)
@@ -60,8 +60,8 @@ pub fn interpret_ast(statements: &[Stmt], symbol_table: &HashMap<String, u16>) -
     let vals: Vec<u16> = statements
         .iter()
         .map(|s| match s {
-            Stmt::A(a_statement) => convert_a_statement(a_statement.clone(), symbol_table),
-            Stmt::C(c_statement) => convert_c_statement(c_statement.clone()),
+            Stmt::A(a_statement) => convert_a_statement(a_statement, symbol_table),
+            Stmt::C(c_statement) => convert_c_statement(c_statement),
             _ => panic!("Unable to convert label"),
         })
         .collect();
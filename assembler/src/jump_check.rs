@@ -0,0 +1,75 @@
+use std::collections::BTreeMap;
+use std::fmt;
+
+use crate::parser::{Address, Jump, Line, Stmt};
+
+/// A jump straight after loading a variable's address. A label which is misspelt, or never
+/// declared, is allocated as a variable, so the program would jump into the RAM addresses instead.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct JumpToVariable {
+    /// The line of the A-instruction loading the variable
+    pub line: usize,
+    pub symbol: String,
+    pub address: u16,
+}
+
+impl fmt::Display for JumpToVariable {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{}: the jump after @{} goes to {}, the address of a variable rather than a label. Is the label misspelt?",
+            self.line, self.symbol, self.address
+        )
+    }
+}
+
+/// Find the jumps whose target was loaded from a variable, given the variables the assembler
+/// allocated
+pub fn jumps_to_variables(
+    lines: &[Line],
+    variables: &BTreeMap<String, u16>,
+) -> Vec<JumpToVariable> {
+    let mut jumps = Vec::new();
+    let mut loaded = None;
+    for line in lines {
+        match &line.stmt {
+            Stmt::A(Address::Symbol(symbol)) => {
+                loaded = variables
+                    .get(symbol)
+                    .map(|address| (line.number, symbol, *address));
+            }
+            Stmt::A(Address::Value(_)) | Stmt::Label(_) => loaded = None,
+            Stmt::C(command) => {
+                let jumps_away = command.jump.is_some_and(|jump| jump != Jump::NULL);
+                if let (Some((number, symbol, address)), true) = (loaded, jumps_away) {
+                    jumps.push(JumpToVariable {
+                        line: number,
+                        symbol: symbol.clone(),
+                        address,
+                    });
+                }
+                loaded = None;
+            }
+            Stmt::Empty => {}
+        }
+    }
+    jumps
+}
+
+#[test]
+fn test_jumps_to_variables() {
+    let lines = crate::parser::parse_hack(
+        "(LOOP)\n@LOOP\n0;JMP\n@LOPP // typo\n// comment\nD;JGT\n@count\nM=M+1\n@count\nA=M\n0;JMP",
+    )
+    .unwrap();
+    let variables = BTreeMap::from([("LOPP".to_owned(), 16), ("count".to_owned(), 17)]);
+
+    assert_eq!(
+        jumps_to_variables(&lines, &variables),
+        [JumpToVariable {
+            line: 4,
+            symbol: "LOPP".to_owned(),
+            address: 16
+        }]
+    );
+}
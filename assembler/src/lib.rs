@@ -0,0 +1,759 @@
+pub mod cli;
+mod convert_labels;
+mod convert_variables;
+mod disassembler;
+mod histogram;
+mod index;
+mod interpreter;
+mod jump_check;
+mod listing;
+mod output_format;
+mod parser;
+mod symbol_map;
+mod symbol_table;
+mod tokens;
+
+use convert_labels::{find_labels, remove_all_labels};
+use convert_variables::{find_undefined_symbol, find_variables};
+use disassembler::{disassemble, sidecar, Symbols};
+use histogram::instruction_histogram;
+use index::index_hack;
+use interpreter::interpret_ast;
+use jump_check::jumps_to_variables;
+pub use jump_check::JumpToVariable;
+use listing::listing;
+pub use output_format::OutputFormat;
+use parse_utils::output::{in_out_dir, write_output, WriteMode};
+use parse_utils::source::Source;
+pub use parser::{parse_hack, parse_hack_strict, Address, Command, Line, Stmt};
+use std::collections::HashMap;
+use std::io;
+use std::path::{Path, PathBuf};
+pub use symbol_map::{SymbolChange, SymbolMap};
+use symbol_table::create_symbol_table;
+use thiserror::Error;
+pub use tokens::tokenize_hack;
+use tracing::{debug, info_span};
+
+use crate::parser::parse_line;
+
+#[derive(Debug, Error)]
+pub enum ErrorType {
+    #[error("Failed to read {}", .path.display())]
+    ReadError {
+        path: PathBuf,
+        #[source]
+        source: io::Error,
+    },
+    #[error("Failed to write {}", .path.display())]
+    WriteError {
+        path: PathBuf,
+        #[source]
+        source: io::Error,
+    },
+    #[error("Failed to save symbol file {}", .path.display())]
+    SaveSymbolFileError {
+        path: PathBuf,
+        #[source]
+        source: io::Error,
+    },
+    #[error(transparent)]
+    AssembleError(#[from] AssembleError),
+    #[error("Failed to serialize the index to JSON")]
+    SerdeError(#[source] serde_json::Error),
+    #[error("Line {line}: `{text}` is not a valid Hack instruction")]
+    InvalidMachineCode { line: usize, text: String },
+    #[error("{} is not a symbol map saved by --symbol-map", .path.display())]
+    InvalidSymbolMap {
+        path: PathBuf,
+        #[source]
+        source: serde_json::Error,
+    },
+}
+
+/// Why a program didn't assemble. Lines are 1-based and count the lines of the source, so
+/// everything a macro or pseudo-instruction expands to is on the line where it's used.
+#[derive(Debug, Clone, PartialEq, Eq, Error)]
+pub enum AssembleError {
+    #[error("Line {line}: {message}")]
+    Syntax { line: usize, message: String },
+    #[error(
+        "Line {line}: symbol {symbol} is not defined and variables aren't allocated in bare mode"
+    )]
+    UndefinedSymbol { line: usize, symbol: String },
+    #[error("Line {line}: `{text}` is past the end of ROM, the program is {instructions} instructions long but only {ROM_SIZE} fit")]
+    ProgramTooLarge {
+        line: usize,
+        text: String,
+        instructions: usize,
+    },
+    #[error(
+        "Line {line}: `{text}` loads {value}, which doesn't fit in the 15 bits of an A-instruction"
+    )]
+    AddressOutOfRange {
+        line: usize,
+        text: String,
+        value: u16,
+    },
+    #[error("There is no label called {0} to start a ROM bank at")]
+    UnknownBankLabel(String),
+}
+
+impl AssembleError {
+    /// The line of the source the error is on, if it's about a single line
+    pub fn line(&self) -> Option<usize> {
+        match self {
+            AssembleError::Syntax { line, .. }
+            | AssembleError::UndefinedSymbol { line, .. }
+            | AssembleError::ProgramTooLarge { line, .. }
+            | AssembleError::AddressOutOfRange { line, .. } => Some(*line),
+            AssembleError::UnknownBankLabel(_) => None,
+        }
+    }
+}
+
+/// The number of instructions the Hack ROM holds
+pub const ROM_SIZE: usize = 32 * 1024;
+
+/// The largest value an A-instruction can load
+const MAX_ADDRESS: u16 = 0x7fff;
+
+/// The bank-switch register of the extended Hack variants with several ROM banks
+const BANK: u16 = 0x6001;
+
+#[derive(Debug, Clone, Default)]
+pub struct AssemblyOptions {
+    /// Start with an empty symbol table and don't allocate variables, so every symbol must be
+    /// defined as a label
+    pub bare: bool,
+    /// Labels which each start a new ROM bank. Label addresses are relative to their bank, and
+    /// the `BANK` register is predefined for switching between them.
+    pub bank_starts: Vec<String>,
+    /// Reject pseudo-instructions such as `GOTO label`, so that the source also assembles with
+    /// the course's tools
+    pub strict: bool,
+    /// Also produce a .lst file giving the address, encoding and source line of each instruction
+    pub listing: bool,
+    /// How the .hack file, or the file for each ROM bank, is written
+    pub format: OutputFormat,
+}
+
+/// Produce a JSON index of the labels, label references and parse errors in a file
+pub fn index_file(path: &str) -> Result<String, ErrorType> {
+    let contents = Source::open(Path::new(path)).map_err(|source| ErrorType::ReadError {
+        path: PathBuf::from(path),
+        source,
+    })?;
+
+    serde_json::to_string_pretty(&index_hack(&contents)).map_err(ErrorType::SerdeError)
+}
+
+/// Count how often each C-instruction is used in a file
+pub fn histogram_file(path: &str) -> Result<String, ErrorType> {
+    let contents = Source::open(Path::new(path)).map_err(|source| ErrorType::ReadError {
+        path: PathBuf::from(path),
+        source,
+    })?;
+    let lines = parse_hack(&contents)?;
+
+    Ok(instruction_histogram(&lines))
+}
+
+/// Turn a .hack file back into assembly.
+///
+/// Labels and symbols are restored from the symbol file if one is given, otherwise every jump
+/// target is given a generated label.
+pub fn disassemble_file(path: &str, symbol_path: Option<&Path>) -> Result<String, ErrorType> {
+    let contents = Source::open(Path::new(path)).map_err(|source| ErrorType::ReadError {
+        path: PathBuf::from(path),
+        source,
+    })?;
+
+    let mut line_numbers = Vec::new();
+    let mut words = Vec::new();
+    for (index, text) in contents.lines().enumerate() {
+        let text = text.trim();
+        if text.is_empty() {
+            continue;
+        }
+        let word = (text.len() == 16)
+            .then(|| u16::from_str_radix(text, 2).ok())
+            .flatten()
+            .ok_or_else(|| ErrorType::InvalidMachineCode {
+                line: index + 1,
+                text: text.to_owned(),
+            })?;
+        line_numbers.push(index + 1);
+        words.push(word);
+    }
+
+    let symbols = match symbol_path {
+        Some(symbol_path) => {
+            let symbol_file = Source::open(symbol_path).map_err(|source| ErrorType::ReadError {
+                path: symbol_path.to_owned(),
+                source,
+            })?;
+            // Each line of a symbol file is an instruction address followed by the source line
+            let mut lines = Vec::new();
+            for (index, line) in symbol_file.lines().enumerate() {
+                let text = line.split_once(' ').map_or("", |(_, text)| text);
+                let stmt = parse_line(text).map_err(|message| AssembleError::Syntax {
+                    line: index + 1,
+                    message,
+                })?;
+                lines.push(Line {
+                    number: index + 1,
+                    text: text.into(),
+                    source: text,
+                    stmt,
+                });
+            }
+            Symbols::from_lines(&lines, &words)
+        }
+        None => Symbols::generate(&words),
+    };
+
+    disassemble(&words, &symbols).map_err(|index| ErrorType::InvalidMachineCode {
+        line: line_numbers[index],
+        text: format!("{:016b}", words[index]),
+    })
+}
+
+/// Assemble a file, returning the addresses given to its labels and variables
+pub fn parse_and_convert_file(
+    path: &str,
+    generate_symbol_file: bool,
+    with_sidecar: bool,
+    options: &AssemblyOptions,
+    mode: WriteMode,
+    out_dir: Option<&Path>,
+) -> Result<SymbolMap, ErrorType> {
+    let contents = Source::open(Path::new(path)).map_err(|source| ErrorType::ReadError {
+        path: PathBuf::from(path),
+        source,
+    })?;
+    let _span = info_span!("assemble", file = path).entered();
+    let parse = if options.strict {
+        parse_hack_strict
+    } else {
+        parse_hack
+    };
+    let lines = info_span!("parse").in_scope(|| parse(&contents))?;
+
+    // Every symbol is resolved before anything is written, so a failure leaves no output behind
+    let Assembly {
+        banks,
+        symbol_map,
+        warnings,
+        listing,
+    } = assemble_lines(&lines, options)?;
+    if generate_symbol_file {
+        let symbol_file_path = in_out_dir(&Path::new(path).with_extension("symbol"), out_dir);
+        save_symbol_file(&symbol_file_path, symbol_file(&lines), mode)?;
+    }
+    for warning in warnings {
+        eprintln!("warning: {}:{}", path, warning);
+    }
+    if let Some(listing) = listing {
+        let listing_file = in_out_dir(&Path::new(path).with_extension("lst"), out_dir);
+        write_output(&listing_file, listing.as_bytes(), mode).map_err(|source| {
+            ErrorType::WriteError {
+                path: listing_file.clone(),
+                source,
+            }
+        })?;
+    }
+
+    let format = options.format;
+    for (bank, words) in banks.iter().enumerate() {
+        // Get the output filename. Banks after the first go in X.bank1.hack, X.bank2.hack, ...
+        let extension = match bank {
+            0 => format.extension().to_owned(),
+            _ => format!("bank{}.{}", bank, format.extension()),
+        };
+        let out_file = in_out_dir(&Path::new(path).with_extension(extension), out_dir);
+        let binary_data = format.encode(words);
+        debug!(path = %out_file.display(), bytes = binary_data.len(), "writing output");
+
+        if with_sidecar {
+            // The source's names are only restored without banks, as addresses restart in each
+            let symbols = match parse_hack(&contents) {
+                Ok(lines) if banks.len() == 1 => Symbols::from_lines(&lines, words),
+                _ => Symbols::default(),
+            };
+            let sidecar_file = out_file.with_extension(format!("{}.txt", format.extension()));
+            write_output(&sidecar_file, sidecar(words, &symbols).as_bytes(), mode).map_err(
+                |source| ErrorType::WriteError {
+                    path: sidecar_file.clone(),
+                    source,
+                },
+            )?;
+        }
+
+        // Write into a file
+        write_output(&out_file, &binary_data, mode).map_err(|source| ErrorType::WriteError {
+            path: out_file,
+            source,
+        })?;
+    }
+
+    Ok(symbol_map)
+}
+
+/// Save the addresses of a build's labels and variables as JSON
+pub fn save_symbol_map(
+    path: &Path,
+    symbol_map: &SymbolMap,
+    mode: WriteMode,
+) -> Result<(), ErrorType> {
+    let json = serde_json::to_string_pretty(symbol_map).map_err(ErrorType::SerdeError)?;
+    write_output(path, json.as_bytes(), mode).map_err(|source| ErrorType::WriteError {
+        path: path.to_owned(),
+        source,
+    })
+}
+
+/// Load a symbol map saved by an earlier build
+pub fn load_symbol_map(path: &Path) -> Result<SymbolMap, ErrorType> {
+    let contents = Source::open(path).map_err(|source| ErrorType::ReadError {
+        path: path.to_owned(),
+        source,
+    })?;
+    serde_json::from_str(&contents).map_err(|source| ErrorType::InvalidSymbolMap {
+        path: path.to_owned(),
+        source,
+    })
+}
+
+/// Assemble Hack source held in memory into the instructions of the program
+pub fn assemble(source: &str) -> Result<Vec<u16>, AssembleError> {
+    let lines = parse_hack(source)?;
+    // Without bank starts the whole program is in the first bank
+    let mut assembly = assemble_lines(&lines, &AssemblyOptions::default())?;
+    Ok(assembly.banks.swap_remove(0))
+}
+
+/// Assemble Hack source held in memory into the text form of a .hack file
+pub fn assemble_string(contents: &str) -> Result<String, ErrorType> {
+    Ok(output_format::text(&assemble(contents)?))
+}
+
+/// Assemble Hack source held in memory, also returning the addresses given to its labels and
+/// variables
+pub fn assemble_string_with_symbols(contents: &str) -> Result<(String, SymbolMap), ErrorType> {
+    let lines = parse_hack(contents)?;
+    let assembly = assemble_lines(&lines, &AssemblyOptions::default())?;
+    Ok((output_format::text(&assembly.banks[0]), assembly.symbol_map))
+}
+
+/// Give each label and variable of a parsed program its address, alongside the predefined
+/// symbols unless the options ask for bare mode. With bank starts, labels are relative to their
+/// bank.
+pub fn resolve_symbols(
+    lines: &[Line],
+    options: &AssemblyOptions,
+) -> Result<HashMap<String, u16>, AssembleError> {
+    let banks = split_banks(lines, &options.bank_starts)?;
+    resolve_bank_symbols(&banks, options)
+}
+
+/// Encode the instructions of a parsed program, whose symbols have been resolved
+pub fn encode(
+    lines: &[Line],
+    symbol_table: &HashMap<String, u16>,
+) -> Result<Vec<u16>, AssembleError> {
+    // Encoding keeps only the low 15 bits, so anything larger would silently load the wrong value
+    check_addresses(lines, symbol_table)?;
+    let instructions: Vec<Stmt> = lines
+        .iter()
+        .filter(|line| matches!(line.stmt, Stmt::A(_) | Stmt::C(_)))
+        .map(|line| line.stmt.clone())
+        .collect();
+    Ok(interpret_ast(&instructions, symbol_table))
+}
+
+/// An assembled program
+struct Assembly {
+    /// The words of each ROM bank
+    banks: Vec<Vec<u16>>,
+    symbol_map: SymbolMap,
+    warnings: Vec<JumpToVariable>,
+    /// The listing, if the options asked for one
+    listing: Option<String>,
+}
+
+/// Assemble a program into the words of each of its ROM banks
+fn assemble_lines(lines: &[Line], options: &AssemblyOptions) -> Result<Assembly, AssembleError> {
+    // Remove empty statements
+    let lines: Vec<Line> = lines
+        .iter()
+        .filter(|line| !matches!(line.stmt, Stmt::Empty))
+        .cloned()
+        .collect();
+    let banks = split_banks(&lines, &options.bank_starts)?;
+    for bank in &banks {
+        check_program_size(bank)?;
+    }
+
+    let symbols_span = info_span!("symbols").entered();
+    let symbol_table = resolve_bank_symbols(&banks, options)?;
+    debug!(symbols = symbol_table.len(), "resolved symbols");
+    drop(symbols_span);
+
+    let symbol_map = SymbolMap::new(
+        lines.iter().map(|line| &line.stmt),
+        &symbol_table,
+        &predefined_symbols(&banks),
+    );
+    let warnings = jumps_to_variables(&lines, &symbol_map.variables);
+
+    // Convert to binary
+    let _span = info_span!("encode").entered();
+    let binaries = banks
+        .iter()
+        .map(|bank| encode(bank, &symbol_table))
+        .collect::<Result<Vec<_>, _>>()?;
+    let listing = options
+        .listing
+        .then(|| listing(&banks, &binaries, &symbol_table));
+
+    Ok(Assembly {
+        banks: binaries,
+        symbol_map,
+        warnings,
+        listing,
+    })
+}
+
+/// The symbols every program of this many banks starts with
+fn predefined_symbols(banks: &[&[Line]]) -> HashMap<String, u16> {
+    let mut predefined = create_symbol_table();
+    if banks.len() > 1 {
+        predefined.insert("BANK".to_owned(), BANK);
+    }
+    predefined
+}
+
+/// The symbol table of a program split into banks
+fn resolve_bank_symbols(
+    banks: &[&[Line]],
+    options: &AssemblyOptions,
+) -> Result<HashMap<String, u16>, AssembleError> {
+    // Create a symbol table
+    let mut symbol_table = if options.bare {
+        HashMap::new()
+    } else {
+        predefined_symbols(banks)
+    };
+
+    // Find all the labels (& their expected addresses within their bank), then remove them
+    let mut statements = Vec::new();
+    for bank in banks {
+        let bank_statements = bank
+            .iter()
+            .filter(|line| !matches!(line.stmt, Stmt::Empty))
+            .map(|line| line.stmt.clone())
+            .collect();
+        find_labels(&bank_statements, &mut symbol_table);
+        statements.extend(remove_all_labels(bank_statements));
+    }
+
+    // Find all the variables
+    if options.bare {
+        if let Some(symbol) = find_undefined_symbol(&statements, &symbol_table) {
+            let line = banks
+                .iter()
+                .flat_map(|bank| bank.iter())
+                .find(|line| matches!(&line.stmt, Stmt::A(Address::Symbol(name)) if name == symbol))
+                .map_or(0, |line| line.number);
+            return Err(AssembleError::UndefinedSymbol {
+                line,
+                symbol: symbol.to_owned(),
+            });
+        }
+    } else {
+        find_variables(&statements, &mut symbol_table);
+    }
+    Ok(symbol_table)
+}
+
+/// Split a program into ROM banks, each after the first starting at one of the bank labels. A bank
+/// label at the very start leaves the first bank empty, so banks are numbered the same way
+/// whatever comes before them.
+fn split_banks<'a, 'b>(
+    lines: &'b [Line<'a>],
+    bank_starts: &[String],
+) -> Result<Vec<&'b [Line<'a>]>, AssembleError> {
+    for label in bank_starts {
+        if !lines
+            .iter()
+            .any(|line| matches!(&line.stmt, Stmt::Label(name) if name == label))
+        {
+            return Err(AssembleError::UnknownBankLabel(label.clone()));
+        }
+    }
+
+    let mut banks = Vec::new();
+    let mut start = 0;
+    for (index, line) in lines.iter().enumerate() {
+        if matches!(&line.stmt, Stmt::Label(name) if bank_starts.contains(name)) {
+            banks.push(&lines[start..index]);
+            start = index;
+        }
+    }
+    banks.push(&lines[start..]);
+    Ok(banks)
+}
+
+/// Check every instruction has a place in ROM, naming the first which doesn't
+fn check_program_size(lines: &[Line]) -> Result<(), AssembleError> {
+    let mut instructions = lines
+        .iter()
+        .filter(|line| matches!(line.stmt, Stmt::A(_) | Stmt::C(_)));
+    let Some(first_past_end) = instructions.nth(ROM_SIZE) else {
+        return Ok(());
+    };
+    Err(AssembleError::ProgramTooLarge {
+        line: first_past_end.number,
+        text: first_past_end.text.trim().to_owned(),
+        instructions: ROM_SIZE + 1 + instructions.count(),
+    })
+}
+
+fn check_addresses(
+    lines: &[Line],
+    symbol_table: &HashMap<String, u16>,
+) -> Result<(), AssembleError> {
+    for line in lines {
+        let value = match &line.stmt {
+            Stmt::A(Address::Value(value)) => *value,
+            Stmt::A(Address::Symbol(symbol)) => match symbol_table.get(symbol) {
+                Some(value) => *value,
+                None => continue,
+            },
+            _ => continue,
+        };
+        if value > MAX_ADDRESS {
+            return Err(AssembleError::AddressOutOfRange {
+                line: line.number,
+                text: line.text.trim().to_owned(),
+                value,
+            });
+        }
+    }
+    Ok(())
+}
+
+fn save_symbol_file(
+    symbol_file_path: &Path,
+    symbol_file: String,
+    mode: WriteMode,
+) -> Result<(), ErrorType> {
+    // Save the symbol file
+    write_output(symbol_file_path, symbol_file.as_bytes(), mode).map_err(|source| {
+        ErrorType::SaveSymbolFileError {
+            path: symbol_file_path.to_owned(),
+            source,
+        }
+    })?;
+
+    Ok(())
+}
+
+/// Prefix every line with the address of the next instruction. Labels are followed by a comment
+/// giving the number of instructions up to the next label.
+fn symbol_file(lines: &[Line]) -> String {
+    let mut label_addresses = Vec::new();
+    let mut instruction_count = 0;
+    for line in lines {
+        match line.stmt {
+            Stmt::A(_) | Stmt::C(_) => instruction_count += 1,
+            Stmt::Label(_) => label_addresses.push(instruction_count),
+            Stmt::Empty => {}
+        }
+    }
+    label_addresses.push(instruction_count);
+
+    let mut symbols: Vec<String> = Vec::new();
+    let mut line_counter = 0;
+    let mut label_index = 0;
+
+    for line in lines {
+        match line.stmt {
+            Stmt::A(_) | Stmt::C(_) => {
+                // Use the line number & increase
+                symbols.push(format!("{} {}", line_counter, line.text));
+                line_counter += 1;
+            }
+            Stmt::Label(_) => {
+                label_index += 1;
+                let size = label_addresses[label_index] - line_counter;
+                symbols.push(format!(
+                    "{} {} // {} instructions",
+                    line_counter,
+                    line.text.trim_end(),
+                    size
+                ));
+            }
+            Stmt::Empty => {
+                // Print the line but don't increase line number
+                symbols.push(format!("{} {}", line_counter, line.text));
+            }
+        }
+    }
+    symbols.join("\n")
+}
+
+#[test]
+fn test_symbol_file_gives_label_sizes() {
+    let lines = parse_hack("@2\n(START)\n(LOOP) // top\nD=D-1\n@LOOP\nD;JGT\n(END)").unwrap();
+
+    assert_eq!(
+        symbol_file(&lines),
+        "0 @2\n\
+         1 (START) // 0 instructions\n\
+         1 (LOOP) // top // 3 instructions\n\
+         1 D=D-1\n\
+         2 @LOOP\n\
+         3 D;JGT\n\
+         4 (END) // 0 instructions"
+    );
+}
+
+#[test]
+fn test_bare_mode_has_no_predefined_symbols() {
+    let bare = AssemblyOptions {
+        bare: true,
+        ..Default::default()
+    };
+    let assemble = |source, options| {
+        assemble_lines(&parse_hack(source).unwrap(), options)
+            .map(|assembly| output_format::text(&assembly.banks[0]))
+    };
+
+    assert_eq!(
+        assemble("(LOOP)\n@LOOP\n0;JMP", &bare).unwrap(),
+        "0000000000000000\n1110101010000111"
+    );
+    assert!(matches!(
+        assemble("@SCREEN", &bare),
+        Err(AssembleError::UndefinedSymbol { line: 1, symbol }) if symbol == "SCREEN"
+    ));
+    assert!(matches!(
+        assemble("@counter", &bare),
+        Err(AssembleError::UndefinedSymbol { line: 1, symbol }) if symbol == "counter"
+    ));
+    assert!(assemble("@SCREEN\n@counter", &AssemblyOptions::default()).is_ok());
+}
+
+#[test]
+fn test_programs_must_fit_in_rom() {
+    assert!(assemble("@32767\n@SCREEN").is_ok());
+    assert!(matches!(
+        assemble("D=0\n\n@32768"),
+        Err(AssembleError::AddressOutOfRange {
+            line: 3,
+            value: 32768,
+            ..
+        })
+    ));
+
+    let mut source = "D=0\n".repeat(ROM_SIZE);
+    assert!(assemble(&source).is_ok());
+    source.push_str("// past the end\n@1\nD=A\n");
+    assert!(matches!(
+        assemble(&source),
+        Err(AssembleError::ProgramTooLarge { line, text, instructions })
+            if line == ROM_SIZE + 2 && text == "@1" && instructions == ROM_SIZE + 2
+    ));
+}
+
+#[test]
+fn test_bank_starts_split_the_program() {
+    let options = AssemblyOptions {
+        bank_starts: vec!["FAR".to_owned()],
+        ..Default::default()
+    };
+    let lines = parse_hack("@FAR\n0;JMP\n(FAR)\n@BANK\nM=0\n@FAR\n0;JMP").unwrap();
+    let assembly = assemble_lines(&lines, &options).unwrap();
+
+    let banks: Vec<String> = assembly
+        .banks
+        .iter()
+        .map(|words| output_format::text(words))
+        .collect();
+    assert_eq!(
+        banks,
+        [
+            "0000000000000000\n1110101010000111",
+            "0110000000000001\n1110101010001000\n0000000000000000\n1110101010000111"
+        ]
+    );
+
+    let options = AssemblyOptions {
+        bank_starts: vec!["NEAR".to_owned()],
+        ..Default::default()
+    };
+    let lines = parse_hack("(FAR)\n@FAR").unwrap();
+    assert!(matches!(
+        assemble_lines(&lines, &options),
+        Err(AssembleError::UnknownBankLabel(label)) if label == "NEAR"
+    ));
+}
+
+#[test]
+fn test_assemble_in_memory() {
+    let source = "@i\nM=1\n(LOOP)\n@LOOP\n0;JMP";
+    assert_eq!(assemble(source), Ok(vec![0x0010, 0xEFC8, 0x0002, 0xEA87]));
+
+    let lines = parse_hack(source).unwrap();
+    let symbol_table = resolve_symbols(&lines, &AssemblyOptions::default()).unwrap();
+    assert_eq!((symbol_table["i"], symbol_table["LOOP"]), (16, 2));
+    assert_eq!(encode(&lines, &symbol_table), assemble(source));
+
+    let error = assemble("@1\n\nD=Q").unwrap_err();
+    assert_eq!(error.line(), Some(3));
+    assert!(error.to_string().starts_with("Line 3: "));
+    assert_eq!(
+        assemble(".macro LOAD x\n@%x\n.endmacro\nD=0\nLOAD 32768")
+            .unwrap_err()
+            .line(),
+        Some(5)
+    );
+}
+
+#[test]
+fn test_failed_assembly_leaves_no_symbol_file() {
+    let dir = std::env::temp_dir().join(format!("assembler-symbols-{}", std::process::id()));
+    std::fs::create_dir_all(&dir).unwrap();
+    let source = dir.join("Bare.asm");
+    std::fs::write(&source, "@undefined\n0;JMP").unwrap();
+    let bare = AssemblyOptions {
+        bare: true,
+        ..Default::default()
+    };
+
+    let result = parse_and_convert_file(
+        source.to_str().unwrap(),
+        true,
+        false,
+        &bare,
+        WriteMode::Write,
+        None,
+    );
+    assert!(result.is_err());
+    assert!(!dir.join("Bare.symbol").exists());
+    assert!(!dir.join("Bare.hack").exists());
+
+    parse_and_convert_file(
+        source.to_str().unwrap(),
+        true,
+        false,
+        &AssemblyOptions::default(),
+        WriteMode::Write,
+        None,
+    )
+    .unwrap();
+    assert!(dir.join("Bare.symbol").exists());
+    std::fs::remove_dir_all(&dir).unwrap();
+}
@@ -0,0 +1,127 @@
+use std::collections::HashMap;
+use std::fmt::Write;
+
+use crate::parser::{Address, Line, Stmt};
+
+const HEADER: &str = "  ROM  Binary            Hex    Line  Source";
+
+/// A listing of an assembled program with a row for each instruction and label: the ROM address,
+/// the word in binary and hex, the source line and its text. Symbols are followed by the value
+/// they resolved to. A macro or pseudo-instruction is shown as written on the first row of the
+/// instructions it stands for, leaving the source columns of the rest blank.
+///
+/// `banks` holds the lines of each ROM bank and `words` what they were assembled to. Each bank
+/// after the first starts under a heading of its own, as its addresses start again from 0.
+pub fn listing(
+    banks: &[&[Line]],
+    words: &[Vec<u16>],
+    symbol_table: &HashMap<String, u16>,
+) -> String {
+    let mut output = String::new();
+    for (bank, (lines, words)) in banks.iter().zip(words).enumerate() {
+        if bank > 0 {
+            writeln!(output, "\n// Bank {}", bank).expect("Writing to a String cannot fail");
+        }
+        writeln!(output, "{}", HEADER).expect("Writing to a String cannot fail");
+
+        let mut words = words.iter().enumerate();
+        let mut previous_line = None;
+        for line in lines.iter() {
+            if matches!(line.stmt, Stmt::Empty) {
+                continue;
+            }
+            let (number, source) = if previous_line == Some(line.number) {
+                (String::new(), "")
+            } else {
+                (line.number.to_string(), line.source.trim())
+            };
+            previous_line = Some(line.number);
+            let row = match &line.stmt {
+                Stmt::Label(name) => format!(
+                    "{}  {:4}  {:>5}  {}  [{} = {}]",
+                    word_columns(None),
+                    "",
+                    number,
+                    source,
+                    name,
+                    symbol_table[name]
+                ),
+                Stmt::A(_) | Stmt::C(_) => {
+                    let (address, word) = words
+                        .next()
+                        .expect("Every instruction is assembled to a word");
+                    let mut row = format!(
+                        "{}  {:04X}  {:>5}  {}",
+                        word_columns(Some((address, *word))),
+                        word,
+                        number,
+                        source
+                    );
+                    if let Stmt::A(Address::Symbol(symbol)) = &line.stmt {
+                        write!(row, "  [{} = {}]", symbol, symbol_table[symbol])
+                            .expect("Writing to a String cannot fail");
+                    }
+                    row
+                }
+                Stmt::Empty => unreachable!("Empty lines are skipped"),
+            };
+            writeln!(output, "{}", row.trim_end()).expect("Writing to a String cannot fail");
+        }
+    }
+    output
+}
+
+/// The ROM address and binary word which start each row of a listing, or blanks for a label.
+/// Shared with the sidecar written by the disassembler, so the two line up.
+pub(crate) fn word_columns(instruction: Option<(usize, u16)>) -> String {
+    match instruction {
+        Some((address, word)) => format!("{:>5}  {:016b}", address, word),
+        None => format!("{:5}  {:16}", "", ""),
+    }
+}
+
+#[test]
+fn test_listing() {
+    let lines = crate::parser::parse_hack("@i // counter\nM=0\n(LOOP)\n@LOOP\n0;JMP").unwrap();
+    let words = vec![vec![16, 0xEA88, 2, 0xEA87]];
+    let symbol_table = HashMap::from([("i".to_owned(), 16), ("LOOP".to_owned(), 2)]);
+    let listing = listing(&[&lines], &words, &symbol_table);
+    let rows: Vec<&str> = listing.lines().collect();
+
+    assert_eq!(rows[0], HEADER);
+    assert_eq!(
+        rows[1],
+        "    0  0000000000010000  0010      1  @i // counter  [i = 16]"
+    );
+    assert_eq!(rows[2], "    1  1110101010001000  EA88      2  M=0");
+    assert_eq!(
+        rows[3],
+        "                                   3  (LOOP)  [LOOP = 2]"
+    );
+    assert_eq!(rows.len(), 6);
+}
+
+#[test]
+fn test_listing_shows_macros_as_written() {
+    let source = ".macro INC reg\n@%reg\nM=M+1\n.endmacro\nINC R1\nPUSH D";
+    let lines = crate::parser::parse_hack(source).unwrap();
+    let words: Vec<u16> = crate::assemble_string(source)
+        .unwrap()
+        .lines()
+        .map(|line| u16::from_str_radix(line, 2).unwrap())
+        .collect();
+    let symbol_table = HashMap::from([("R1".to_owned(), 1), ("SP".to_owned(), 0)]);
+    let listing = listing(&[&lines], &[words], &symbol_table);
+    let rows: Vec<&str> = listing.lines().collect();
+
+    assert_eq!(
+        rows[1],
+        "    0  0000000000000001  0001      5  INC R1  [R1 = 1]"
+    );
+    assert_eq!(rows[2], "    1  1111110111001000  FDC8");
+    assert_eq!(
+        rows[3],
+        "    2  0000000000000000  0000      6  PUSH D  [SP = 0]"
+    );
+    assert!(rows[4..].iter().all(|row| !row.contains("PUSH")));
+}
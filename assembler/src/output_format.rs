@@ -0,0 +1,120 @@
+use std::fmt::Write;
+use std::str::FromStr;
+
+/// The words of an Intel HEX data record
+const RECORD_WORDS: usize = 8;
+
+/// How an assembled program is written out
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum OutputFormat {
+    /// A line of 16 binary digits per instruction, as the course's tools read
+    #[default]
+    Text,
+    /// Each instruction as a 16-bit big-endian word
+    Binary,
+    /// A line of 4 hex digits per instruction, as Verilog's `$readmemh` reads
+    Hex,
+    /// Intel HEX records whose addresses count words, as FPGA memory initialization files do
+    IntelHex,
+}
+
+impl OutputFormat {
+    pub const NAMES: [&'static str; 4] = ["text", "binary", "hex", "intel-hex"];
+
+    /// The extension of the file a ROM bank is written to
+    pub fn extension(self) -> &'static str {
+        match self {
+            OutputFormat::Text => "hack",
+            OutputFormat::Binary => "bin",
+            OutputFormat::Hex => "hex",
+            OutputFormat::IntelHex => "ihx",
+        }
+    }
+
+    pub fn encode(self, words: &[u16]) -> Vec<u8> {
+        match self {
+            OutputFormat::Text => text(words).into_bytes(),
+            OutputFormat::Binary => words.iter().flat_map(|word| word.to_be_bytes()).collect(),
+            OutputFormat::Hex => words
+                .iter()
+                .map(|word| format!("{:04X}", word))
+                .collect::<Vec<_>>()
+                .join("\n")
+                .into_bytes(),
+            OutputFormat::IntelHex => intel_hex(words).into_bytes(),
+        }
+    }
+}
+
+impl FromStr for OutputFormat {
+    type Err = String;
+
+    fn from_str(name: &str) -> Result<Self, Self::Err> {
+        match name {
+            "text" => Ok(OutputFormat::Text),
+            "binary" => Ok(OutputFormat::Binary),
+            "hex" => Ok(OutputFormat::Hex),
+            "intel-hex" => Ok(OutputFormat::IntelHex),
+            _ => Err(format!("{} is not an output format", name)),
+        }
+    }
+}
+
+/// The text of a .hack file
+pub(crate) fn text(words: &[u16]) -> String {
+    let mut text = String::with_capacity(words.len() * 17);
+    for (index, word) in words.iter().enumerate() {
+        if index > 0 {
+            text.push('\n');
+        }
+        write!(text, "{:016b}", word).expect("Writing to a String cannot fail");
+    }
+    text
+}
+
+fn intel_hex(words: &[u16]) -> String {
+    let mut hex = String::new();
+    for (record, chunk) in words.chunks(RECORD_WORDS).enumerate() {
+        let address = (record * RECORD_WORDS) as u16;
+        let mut bytes = vec![(chunk.len() * 2) as u8];
+        bytes.extend(address.to_be_bytes());
+        // A data record
+        bytes.push(0);
+        bytes.extend(chunk.iter().flat_map(|word| word.to_be_bytes()));
+        let sum = bytes.iter().fold(0u8, |sum, byte| sum.wrapping_add(*byte));
+        bytes.push(sum.wrapping_neg());
+
+        hex.push(':');
+        for byte in bytes {
+            write!(hex, "{:02X}", byte).expect("Writing to a String cannot fail");
+        }
+        hex.push('\n');
+    }
+    hex.push_str(":00000001FF\n");
+    hex
+}
+
+#[test]
+fn test_output_formats() {
+    let words = [
+        0x0002, 0xEC10, 0x0003, 0xE090, 0x0000, 0xE308, 0x0006, 0xEA87, 0x7FFF,
+    ];
+    assert_eq!(
+        OutputFormat::Text.encode(&words[..2]),
+        b"0000000000000010\n1110110000010000"
+    );
+    assert_eq!(
+        OutputFormat::Binary.encode(&words[..2]),
+        [0x00, 0x02, 0xEC, 0x10]
+    );
+    assert_eq!(OutputFormat::Hex.encode(&words[..2]), b"0002\nEC10");
+    assert_eq!(
+        String::from_utf8(OutputFormat::IntelHex.encode(&words)).unwrap(),
+        ":100000000002EC100003E0900000E3080006EA871D
+:020008007FFF78
+:00000001FF
+"
+    );
+    assert_eq!("intel-hex".parse(), Ok(OutputFormat::IntelHex));
+    assert!("octal".parse::<OutputFormat>().is_err());
+}
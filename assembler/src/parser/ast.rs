@@ -1,3 +1,5 @@
+use std::fmt;
+
 #[derive(Debug, PartialEq, Eq, Clone)]
 pub enum Address {
     Value(u16),
@@ -74,3 +76,137 @@ pub enum Stmt {
     Label(String),
     Empty,
 }
+
+impl Dest {
+    /// Indexed by the three destination bits of a C-instruction
+    pub const ALL: [Dest; 8] = [
+        Dest::NULL,
+        Dest::M,
+        Dest::D,
+        Dest::MD,
+        Dest::A,
+        Dest::AM,
+        Dest::AD,
+        Dest::AMD,
+    ];
+
+    pub fn mnemonic(self) -> &'static str {
+        match self {
+            Dest::NULL => "",
+            Dest::M => "M",
+            Dest::D => "D",
+            Dest::MD => "MD",
+            Dest::A => "A",
+            Dest::AM => "AM",
+            Dest::AD => "AD",
+            Dest::AMD => "AMD",
+        }
+    }
+}
+
+impl Jump {
+    /// Indexed by the three jump bits of a C-instruction
+    pub const ALL: [Jump; 8] = [
+        Jump::NULL,
+        Jump::JGT,
+        Jump::JEQ,
+        Jump::JGE,
+        Jump::JLT,
+        Jump::JNE,
+        Jump::JLE,
+        Jump::JMP,
+    ];
+
+    pub fn mnemonic(self) -> &'static str {
+        match self {
+            Jump::NULL => "",
+            Jump::JGT => "JGT",
+            Jump::JEQ => "JEQ",
+            Jump::JGE => "JGE",
+            Jump::JLT => "JLT",
+            Jump::JNE => "JNE",
+            Jump::JLE => "JLE",
+            Jump::JMP => "JMP",
+        }
+    }
+}
+
+impl Operation {
+    pub const ALL: [Operation; 28] = [
+        Operation::Zero,
+        Operation::One,
+        Operation::MinusOne,
+        Operation::D,
+        Operation::A,
+        Operation::M,
+        Operation::NotD,
+        Operation::NotA,
+        Operation::NotM,
+        Operation::MinusD,
+        Operation::MinusA,
+        Operation::MinusM,
+        Operation::DPlus1,
+        Operation::APlus1,
+        Operation::MPlus1,
+        Operation::DMinus1,
+        Operation::AMinus1,
+        Operation::MMinus1,
+        Operation::DPlusA,
+        Operation::DPlusM,
+        Operation::DMinusA,
+        Operation::DMinusM,
+        Operation::AMinusD,
+        Operation::MMinusD,
+        Operation::DAndA,
+        Operation::DAndM,
+        Operation::DOrA,
+        Operation::DOrM,
+    ];
+
+    pub fn mnemonic(self) -> &'static str {
+        match self {
+            Operation::Zero => "0",
+            Operation::One => "1",
+            Operation::MinusOne => "-1",
+            Operation::D => "D",
+            Operation::A => "A",
+            Operation::M => "M",
+            Operation::NotD => "!D",
+            Operation::NotA => "!A",
+            Operation::NotM => "!M",
+            Operation::MinusD => "-D",
+            Operation::MinusA => "-A",
+            Operation::MinusM => "-M",
+            Operation::DPlus1 => "D+1",
+            Operation::APlus1 => "A+1",
+            Operation::MPlus1 => "M+1",
+            Operation::DMinus1 => "D-1",
+            Operation::AMinus1 => "A-1",
+            Operation::MMinus1 => "M-1",
+            Operation::DPlusA => "D+A",
+            Operation::DPlusM => "D+M",
+            Operation::DMinusA => "D-A",
+            Operation::DMinusM => "D-M",
+            Operation::AMinusD => "A-D",
+            Operation::MMinusD => "M-D",
+            Operation::DAndA => "D&A",
+            Operation::DAndM => "D&M",
+            Operation::DOrA => "D|A",
+            Operation::DOrM => "D|M",
+        }
+    }
+}
+
+/// Writes the canonical form of the instruction, e.g. `AM=M-1` or `D;JGT`
+impl fmt::Display for Command {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if let Some(dest) = self.dest.filter(|dest| *dest != Dest::NULL) {
+            write!(f, "{}=", dest.mnemonic())?;
+        }
+        write!(f, "{}", self.operation.mnemonic())?;
+        if let Some(jump) = self.jump.filter(|jump| *jump != Jump::NULL) {
+            write!(f, ";{}", jump.mnemonic())?;
+        }
+        Ok(())
+    }
+}
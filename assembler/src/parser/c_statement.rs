@@ -11,127 +11,67 @@ use super::{Jump, Stmt};
 
 fn parse_destination(i: &str) -> IResult<&str, Dest> {
     // The order of these is important
-    map(
-        alt((
-            tag("AMD"),
-            tag("MD"),
-            tag("AM"),
-            tag("AD"),
-            tag("A"),
-            tag("M"),
-            tag("D"),
-            tag("0"),
-        )),
-        |character| match character {
-            "AMD" => Dest::AMD,
-            "MD" => Dest::MD,
-            "AM" => Dest::AM,
-            "AD" => Dest::AD,
-            "M" => Dest::M,
-            "D" => Dest::D,
-            "A" => Dest::A,
-            "0" => Dest::NULL,
-            _ => panic!("Destination parser failed with character {}", character),
-        },
-    )(i)
+    alt((
+        map(tag("AMD"), |_| Dest::AMD),
+        map(tag("MD"), |_| Dest::MD),
+        map(tag("AM"), |_| Dest::AM),
+        map(tag("AD"), |_| Dest::AD),
+        map(tag("A"), |_| Dest::A),
+        map(tag("M"), |_| Dest::M),
+        map(tag("D"), |_| Dest::D),
+        map(tag("0"), |_| Dest::NULL),
+    ))(i)
 }
 
 fn parse_operation(i: &str) -> IResult<&str, Operation> {
-    map(
+    alt((
         alt((
-            alt((
-                tag("0"),
-                tag("1"),
-                tag("-1"),
-                tag("!D"),
-                tag("!A"),
-                tag("!M"),
-                tag("-D"),
-                tag("-A"),
-                tag("-M"),
-                tag("D+1"),
-                tag("A+1"),
-                tag("M+1"),
-                tag("D-1"),
-                tag("A-1"),
-                tag("M-1"),
-            )),
-            alt((
-                tag("D+A"),
-                tag("A+D"),
-                tag("D+M"),
-                tag("M+D"),
-                tag("D-A"),
-                tag("D-M"),
-                tag("A-D"),
-                tag("M-D"),
-                tag("D&A"),
-                tag("D&M"),
-                tag("D|A"),
-                tag("D|M"),
-                tag("D"),
-                tag("A"),
-                tag("M"),
-            )),
+            map(tag("0"), |_| Operation::Zero),
+            map(tag("1"), |_| Operation::One),
+            map(tag("-1"), |_| Operation::MinusOne),
+            map(tag("!D"), |_| Operation::NotD),
+            map(tag("!A"), |_| Operation::NotA),
+            map(tag("!M"), |_| Operation::NotM),
+            map(tag("-D"), |_| Operation::MinusD),
+            map(tag("-A"), |_| Operation::MinusA),
+            map(tag("-M"), |_| Operation::MinusM),
+            map(tag("D+1"), |_| Operation::DPlus1),
+            map(tag("A+1"), |_| Operation::APlus1),
+            map(tag("M+1"), |_| Operation::MPlus1),
+            map(tag("D-1"), |_| Operation::DMinus1),
+            map(tag("A-1"), |_| Operation::AMinus1),
+            map(tag("M-1"), |_| Operation::MMinus1),
         )),
-        |operation_text| match operation_text {
-            "0" => Operation::Zero,
-            "1" => Operation::One,
-            "-1" => Operation::MinusOne,
-            "!D" => Operation::NotD,
-            "!A" => Operation::NotA,
-            "!M" => Operation::NotM,
-            "-D" => Operation::MinusD,
-            "-A" => Operation::MinusA,
-            "-M" => Operation::MinusM,
-            "D+1" => Operation::DPlus1,
-            "A+1" => Operation::APlus1,
-            "M+1" => Operation::MPlus1,
-            "D-1" => Operation::DMinus1,
-            "A-1" => Operation::AMinus1,
-            "M-1" => Operation::MMinus1,
-            "D+A" => Operation::DPlusA,
-            "A+D" => Operation::DPlusA,
-            "D+M" => Operation::DPlusM,
-            "M+D" => Operation::DPlusM,
-            "D-A" => Operation::DMinusA,
-            "D-M" => Operation::DMinusM,
-            "A-D" => Operation::AMinusD,
-            "M-D" => Operation::MMinusD,
-            "D&A" => Operation::DAndA,
-            "D&M" => Operation::DAndM,
-            "D|A" => Operation::DOrA,
-            "D|M" => Operation::DOrM,
-            "D" => Operation::D,
-            "A" => Operation::A,
-            "M" => Operation::M,
-            _ => panic!("Missing operation with text {}", operation_text),
-        },
-    )(i)
+        alt((
+            map(tag("D+A"), |_| Operation::DPlusA),
+            map(tag("A+D"), |_| Operation::DPlusA),
+            map(tag("D+M"), |_| Operation::DPlusM),
+            map(tag("M+D"), |_| Operation::DPlusM),
+            map(tag("D-A"), |_| Operation::DMinusA),
+            map(tag("D-M"), |_| Operation::DMinusM),
+            map(tag("A-D"), |_| Operation::AMinusD),
+            map(tag("M-D"), |_| Operation::MMinusD),
+            map(tag("D&A"), |_| Operation::DAndA),
+            map(tag("D&M"), |_| Operation::DAndM),
+            map(tag("D|A"), |_| Operation::DOrA),
+            map(tag("D|M"), |_| Operation::DOrM),
+            map(tag("D"), |_| Operation::D),
+            map(tag("A"), |_| Operation::A),
+            map(tag("M"), |_| Operation::M),
+        )),
+    ))(i)
 }
 
 fn parse_jump(i: &str) -> IResult<&str, Jump> {
-    map(
-        alt((
-            tag("JGT"),
-            tag("JEQ"),
-            tag("JGE"),
-            tag("JLT"),
-            tag("JNE"),
-            tag("JLE"),
-            tag("JMP"),
-        )),
-        |jump_text| match jump_text {
-            "JGT" => Jump::JGT,
-            "JEQ" => Jump::JEQ,
-            "JGE" => Jump::JGE,
-            "JLT" => Jump::JLT,
-            "JNE" => Jump::JNE,
-            "JLE" => Jump::JLE,
-            "JMP" => Jump::JMP,
-            _ => panic!("Failed to handle jump {}", jump_text),
-        },
-    )(i)
+    alt((
+        map(tag("JGT"), |_| Jump::JGT),
+        map(tag("JEQ"), |_| Jump::JEQ),
+        map(tag("JGE"), |_| Jump::JGE),
+        map(tag("JLT"), |_| Jump::JLT),
+        map(tag("JNE"), |_| Jump::JNE),
+        map(tag("JLE"), |_| Jump::JLE),
+        map(tag("JMP"), |_| Jump::JMP),
+    ))(i)
 }
 
 pub fn parse_c_statement(i: &str) -> IResult<&str, Stmt> {
@@ -238,4 +178,6 @@ fn test_c_instruction() {
     // Test that everything is consumed
     assert!(parse_c_statement("D=D+").is_err());
     assert!(parse_c_statement("A=A&D").is_err());
+    assert!(parse_c_statement("X=D").is_err());
+    assert!(parse_c_statement("D;JXX").is_err());
 }
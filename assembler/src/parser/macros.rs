@@ -0,0 +1,236 @@
+use std::borrow::Cow;
+use std::collections::HashMap;
+
+use crate::AssembleError;
+
+/// How deeply macros may expand inside each other before one is assumed to call itself
+const MAX_DEPTH: usize = 16;
+
+/// A macro defined with `.macro NAME param, ...` and ended by `.endmacro`
+struct Macro<'a> {
+    /// The line the definition starts on
+    line: usize,
+    params: Vec<&'a str>,
+    body: Vec<&'a str>,
+}
+
+/// Expand the macros of a Hack source, returning each remaining line with the line number it
+/// came from. Lines produced by a macro have the line number of its use.
+///
+/// A macro is used by writing its name followed by its arguments separated by commas. In its body
+/// `%param` is replaced by an argument and `%%` by a number unique to the expansion, so that each
+/// use can have its own labels. Macros must be defined before they are used.
+pub fn expand_macros(source: &str) -> Result<Vec<(usize, Cow<'_, str>)>, AssembleError> {
+    let mut expander = Expander {
+        macros: HashMap::new(),
+        lines: Vec::new(),
+        expansions: 0,
+    };
+    let mut defining: Option<(&str, Macro)> = None;
+
+    for (index, text) in source.lines().enumerate() {
+        let number = index + 1;
+        let error = |message: String| AssembleError::Syntax {
+            line: number,
+            message,
+        };
+        let code = code(text);
+
+        if let Some(header) = directive(code, ".macro") {
+            if let Some((name, _)) = defining {
+                return Err(error(format!(
+                    "macro {} is defined inside macro {}",
+                    header, name
+                )));
+            }
+            let (name, params) = header
+                .split_once(char::is_whitespace)
+                .unwrap_or((header, ""));
+            check_name(name).map_err(error)?;
+            let params = arguments(params);
+            for param in &params {
+                check_name(param).map_err(error)?;
+            }
+            defining = Some((
+                name,
+                Macro {
+                    line: number,
+                    params,
+                    body: Vec::new(),
+                },
+            ));
+        } else if code == ".endmacro" {
+            let (name, definition) = defining
+                .take()
+                .ok_or_else(|| error(".endmacro without a .macro".to_owned()))?;
+            if let Some(existing) = expander.macros.get(name) {
+                return Err(error(format!(
+                    "macro {} is already defined on line {}",
+                    name, existing.line
+                )));
+            }
+            expander.macros.insert(name, definition);
+        } else if let Some((_, definition)) = &mut defining {
+            definition.body.push(text);
+        } else {
+            expander.expand(number, Cow::Borrowed(text), 0)?;
+        }
+    }
+
+    if let Some((name, definition)) = defining {
+        return Err(AssembleError::Syntax {
+            line: definition.line,
+            message: format!("macro {} has no .endmacro", name),
+        });
+    }
+    Ok(expander.lines)
+}
+
+struct Expander<'a> {
+    macros: HashMap<&'a str, Macro<'a>>,
+    lines: Vec<(usize, Cow<'a, str>)>,
+    /// The number of macro uses expanded so far, which `%%` is replaced by
+    expansions: usize,
+}
+
+impl<'a> Expander<'a> {
+    fn expand(
+        &mut self,
+        number: usize,
+        text: Cow<'a, str>,
+        depth: usize,
+    ) -> Result<(), AssembleError> {
+        let code = code(&text);
+        let (name, rest) = code.split_once(char::is_whitespace).unwrap_or((code, ""));
+        let Some(definition) = self.macros.get(name) else {
+            self.lines.push((number, text));
+            return Ok(());
+        };
+
+        let error = |message: String| AssembleError::Syntax {
+            line: number,
+            message,
+        };
+        if depth == MAX_DEPTH {
+            return Err(error(format!(
+                "macros nest more than {} deep, does {} use itself?",
+                MAX_DEPTH, name
+            )));
+        }
+        let args = arguments(rest);
+        if args.len() != definition.params.len() {
+            return Err(error(format!(
+                "macro {} takes {} arguments but was given {}",
+                name,
+                definition.params.len(),
+                args.len()
+            )));
+        }
+
+        self.expansions += 1;
+        let unique = self.expansions.to_string();
+        // Longer names first, so that %ab isn't replaced as %a followed by b
+        let mut substitutions: Vec<(&str, &str)> = definition
+            .params
+            .iter()
+            .copied()
+            .zip(args.iter().copied())
+            .collect();
+        substitutions.sort_by_key(|(param, _)| std::cmp::Reverse(param.len()));
+        let body: Vec<String> = definition
+            .body
+            .iter()
+            .map(|line| {
+                let mut line = line.replace("%%", &unique);
+                for (param, arg) in &substitutions {
+                    line = line.replace(&format!("%{}", param), arg);
+                }
+                line
+            })
+            .collect();
+
+        for line in body {
+            self.expand(number, Cow::Owned(line), depth + 1)?;
+        }
+        Ok(())
+    }
+}
+
+/// A line without its comment or surrounding whitespace
+pub(super) fn code(text: &str) -> &str {
+    text.split("//").next().unwrap_or_default().trim()
+}
+
+/// The rest of a line which starts with a directive
+fn directive<'a>(code: &'a str, name: &str) -> Option<&'a str> {
+    let rest = code.strip_prefix(name)?;
+    (rest.is_empty() || rest.starts_with(char::is_whitespace)).then(|| rest.trim())
+}
+
+fn arguments(text: &str) -> Vec<&str> {
+    if text.trim().is_empty() {
+        return Vec::new();
+    }
+    text.split(',').map(str::trim).collect()
+}
+
+/// Names are plain words, which no instruction is
+fn check_name(name: &str) -> Result<(), String> {
+    let valid = name.starts_with(|c: char| c.is_ascii_alphabetic() || c == '_')
+        && name.chars().all(|c| c.is_ascii_alphanumeric() || c == '_');
+    if !valid {
+        return Err(format!("{:?} is not a valid macro or parameter name", name));
+    }
+    Ok(())
+}
+
+#[test]
+fn test_expand_macros() {
+    let source = "\
+.macro PUSH value // pushes a constant
+    @%value
+    D=A
+    @SP
+    AM=M+1
+    A=A-1
+    M=D
+.endmacro
+.macro SKIP_IF_ZERO
+    @SKIP_%%
+    D;JEQ
+(SKIP_%%)
+.endmacro
+PUSH 7
+SKIP_IF_ZERO
+SKIP_IF_ZERO";
+    let lines = expand_macros(source).unwrap();
+    let texts: Vec<(usize, &str)> = lines
+        .iter()
+        .map(|(number, text)| (*number, text.trim()))
+        .collect();
+
+    assert_eq!(texts[0], (14, "@7"));
+    assert_eq!(texts.len(), 12);
+    assert_eq!(texts[6], (15, "@SKIP_2"));
+    assert_eq!(texts[11], (16, "(SKIP_3)"));
+}
+
+#[test]
+fn test_macro_errors() {
+    let error = |source: &str| expand_macros(source).unwrap_err().to_string();
+
+    assert_eq!(error(".macro A\n@1"), "Line 1: macro A has no .endmacro");
+    assert_eq!(error("@1\n.endmacro"), "Line 2: .endmacro without a .macro");
+    assert_eq!(
+        error(".macro LOAD x\n@%x\n.endmacro\nLOAD"),
+        "Line 4: macro LOAD takes 1 arguments but was given 0"
+    );
+    assert_eq!(
+        error(".macro LOOP\nLOOP\n.endmacro\nLOOP"),
+        "Line 4: macros nest more than 16 deep, does LOOP use itself?"
+    );
+    assert_eq!(
+        error(".macro 2X\n.endmacro"),
+        "Line 1: \"2X\" is not a valid macro or parameter name"
+    );
+}
@@ -2,8 +2,10 @@ mod a_statement;
 mod ast;
 mod c_statement;
 mod label;
+mod macros;
 mod parse_utils;
 mod parser;
+mod pseudo;
 
 pub use ast::*;
-pub use parser::parse_hack;
+pub use parser::{parse_hack, parse_hack_strict, parse_line, Line};
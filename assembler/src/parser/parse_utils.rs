@@ -1,19 +1,16 @@
 use nom::{
     branch::alt,
-    bytes::complete::tag,
-    character::complete::{
-        alpha1, alphanumeric1, line_ending, multispace0, not_line_ending, space0,
-    },
-    combinator::{all_consuming, map, recognize, value},
-    multi::many0_count,
-    sequence::{pair, tuple},
+    character::complete::{line_ending, multispace0, space0},
+    combinator::{all_consuming, map, value},
+    sequence::pair,
     IResult, Parser,
 };
+use parse_utils::{identifier, line_comment};
 
 use super::Stmt;
 
 pub fn parse_comment(i: &str) -> IResult<&str, Stmt> {
-    value(Stmt::Empty, tuple((space0, tag("//"), not_line_ending))).parse(i)
+    value(Stmt::Empty, pair(space0, line_comment)).parse(i)
 }
 
 pub fn parse_empty_lines(i: &str) -> IResult<&str, Stmt> {
@@ -23,11 +20,7 @@ pub fn parse_empty_lines(i: &str) -> IResult<&str, Stmt> {
 }
 
 pub fn parse_name(i: &str) -> IResult<&str, &str> {
-    recognize(pair(
-        alt((alpha1, tag("_"))),
-        many0_count(alt((alphanumeric1, tag("_"), tag("."), tag("$")))),
-    ))
-    .parse(i)
+    identifier(".$").parse(i)
 }
 
 #[test]
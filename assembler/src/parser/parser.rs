@@ -1,26 +1,122 @@
+use std::borrow::Cow;
+
 use nom::branch::alt;
 
 use super::c_statement::parse_c_statement;
+use super::macros::{code, expand_macros};
 use super::parse_utils::{parse_comment, parse_empty_lines};
+use super::pseudo::expand_pseudo;
 use super::Stmt;
 use super::{a_statement::parse_a_instruction, label::parse_label};
+use crate::AssembleError;
+
+/// A parsed line which borrows its text from the source
+#[derive(Debug, Clone, PartialEq)]
+pub struct Line<'a> {
+    /// 1-based line number in the source
+    pub number: usize,
+    /// The text of the line, or of the instruction a macro expanded to
+    pub text: Cow<'a, str>,
+    /// The line as written in the source, e.g. the use of a macro rather than what it expanded to
+    pub source: &'a str,
+    pub stmt: Stmt,
+}
+
+pub fn parse_hack(i: &str) -> Result<Vec<Line<'_>>, AssembleError> {
+    parse_lines(i, true)
+}
 
-pub fn parse_hack(i: &str) -> Result<Vec<(String, Stmt)>, String> {
-    // Split into lines
-    let lines = i.lines();
+/// Parse a Hack source which sticks to the course's language, without pseudo-instructions
+pub fn parse_hack_strict(i: &str) -> Result<Vec<Line<'_>>, AssembleError> {
+    parse_lines(i, false)
+}
+
+fn parse_lines(i: &str, allow_pseudo: bool) -> Result<Vec<Line<'_>>, AssembleError> {
     let mut statements = Vec::new();
-    for line in lines {
-        let (_, parsed_statement) = alt((
-            parse_comment,
-            parse_empty_lines,
-            parse_label,
-            parse_a_instruction,
-            parse_c_statement,
-        ))(line)
-        .map_err(|err| format!("Found error {} on line {}", err.to_string(), line))?;
-
-        statements.push((line.to_owned(), parsed_statement));
+    let source_lines: Vec<&str> = i.lines().collect();
+    for (number, text) in expand_macros(i)? {
+        let source = source_lines[number - 1];
+        let error = |message: String| AssembleError::Syntax {
+            line: number,
+            message,
+        };
+        let expansion = match expand_pseudo(code(&text)) {
+            Some(_) if !allow_pseudo => {
+                return Err(error(format!(
+                    "`{}` is a pseudo-instruction, which strict mode doesn't allow",
+                    code(&text)
+                )));
+            }
+            Some(expansion) => expansion.map_err(error)?,
+            None => {
+                let stmt = parse_line(&text).map_err(error)?;
+                statements.push(Line {
+                    number,
+                    text,
+                    source,
+                    stmt,
+                });
+                continue;
+            }
+        };
+        // The instructions a pseudo-instruction stands for all have its line number
+        for instruction in expansion {
+            let stmt = parse_line(&instruction).map_err(error)?;
+            statements.push(Line {
+                number,
+                text: Cow::Owned(instruction),
+                source,
+                stmt,
+            });
+        }
     }
 
     Ok(statements)
 }
+
+pub fn parse_line(line: &str) -> Result<Stmt, String> {
+    let (_, parsed_statement) = alt((
+        parse_comment,
+        parse_empty_lines,
+        parse_label,
+        parse_a_instruction,
+        parse_c_statement,
+    ))(line)
+    .map_err(|err| format!("Found error {} on line {}", err, line))?;
+
+    Ok(parsed_statement)
+}
+
+#[test]
+fn test_parse_hack_lines() {
+    let source = "// comment\n@i\n(LOOP)";
+    let lines = parse_hack(source).unwrap();
+
+    assert_eq!(lines.len(), 3);
+    assert_eq!(lines[1].number, 2);
+    assert_eq!(lines[1].text, "@i");
+    assert_eq!(lines[2].stmt, Stmt::Label("LOOP".to_owned()));
+
+    assert_eq!(parse_hack("@i\nD=Q").unwrap_err().line(), Some(2));
+}
+
+#[test]
+fn test_pseudo_instructions() {
+    let source = ".macro JUMP to\nGOTO %to\n.endmacro\n(LOOP)\nJUMP LOOP // forever";
+    let lines = parse_hack(source).unwrap();
+
+    assert_eq!(lines.len(), 3);
+    assert_eq!((lines[1].number, lines[1].text.as_ref()), (5, "@LOOP"));
+    assert_eq!(lines[1].source, "JUMP LOOP // forever");
+    assert_eq!(lines[2].stmt, parse_line("0;JMP").unwrap());
+
+    assert_eq!(
+        parse_hack_strict(source).unwrap_err().to_string(),
+        "Line 5: `GOTO LOOP` is a pseudo-instruction, which strict mode doesn't allow"
+    );
+    assert!(parse_hack("PUSH M")
+        .unwrap_err()
+        .to_string()
+        .starts_with("Line 1: PUSH only"));
+    assert!(parse_hack_strict("(LOOP)\n@LOOP\n0;JMP").is_ok());
+}
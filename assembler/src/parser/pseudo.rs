@@ -0,0 +1,68 @@
+/// Expand a pseudo-instruction into the instructions it stands for, or return `None` for a line
+/// which isn't one. `code` is the line without its comment.
+///
+/// - `GOTO label` jumps unconditionally
+/// - `LOAD dest, value` sets A, D or a RAM address to a constant or symbol. Loading anything but
+///   0, 1 or -1 into RAM goes through D.
+/// - `PUSH D` and `POP D` move D to and from the top of the stack at `SP`
+pub fn expand_pseudo(code: &str) -> Option<Result<Vec<String>, String>> {
+    let (name, rest) = code.split_once(char::is_whitespace)?;
+    let args: Vec<&str> = rest.split(',').map(str::trim).collect();
+    let expansion = match (name, args.as_slice()) {
+        ("GOTO", [label]) => Ok(vec![format!("@{}", label), "0;JMP".to_owned()]),
+        ("LOAD", [dest, value]) => Ok(load(dest, value)),
+        ("PUSH", ["D"]) => Ok(lines(&["@SP", "AM=M+1", "A=A-1", "M=D"])),
+        ("POP", ["D"]) => Ok(lines(&["@SP", "AM=M-1", "D=M"])),
+        ("PUSH" | "POP", _) => Err(format!("{} only takes D", name)),
+        ("GOTO", _) => Err("GOTO takes a label".to_owned()),
+        ("LOAD", _) => Err("LOAD takes a destination and a value".to_owned()),
+        _ => return None,
+    };
+    Some(expansion)
+}
+
+fn load(dest: &str, value: &str) -> Vec<String> {
+    // The ALU produces these constants itself, without touching another register
+    if let "0" | "1" | "-1" = value {
+        return match dest {
+            "A" | "D" => vec![format!("{}={}", dest, value)],
+            _ => vec![format!("@{}", dest), format!("M={}", value)],
+        };
+    }
+    let register = if dest == "A" { "A" } else { "D" };
+    let mut instructions = match value.strip_prefix('-') {
+        Some(magnitude) => vec![format!("@{}", magnitude), format!("{}=-A", register)],
+        None if dest == "A" => vec![format!("@{}", value)],
+        None => vec![format!("@{}", value), "D=A".to_owned()],
+    };
+    if register == "D" && dest != "D" {
+        instructions.extend([format!("@{}", dest), "M=D".to_owned()]);
+    }
+    instructions
+}
+
+fn lines(instructions: &[&str]) -> Vec<String> {
+    instructions.iter().map(|line| (*line).to_owned()).collect()
+}
+
+#[test]
+fn test_expand_pseudo() {
+    let expand = |code: &str| expand_pseudo(code).map(|lines| lines.unwrap().join(" "));
+
+    assert_eq!(expand("GOTO END").as_deref(), Some("@END 0;JMP"));
+    assert_eq!(expand("LOAD R3, 42").as_deref(), Some("@42 D=A @R3 M=D"));
+    assert_eq!(expand("LOAD R3,-1").as_deref(), Some("@R3 M=-1"));
+    assert_eq!(expand("LOAD A, -5").as_deref(), Some("@5 A=-A"));
+    assert_eq!(expand("LOAD A, 300").as_deref(), Some("@300"));
+    assert_eq!(expand("LOAD D, SCREEN").as_deref(), Some("@SCREEN D=A"));
+    assert_eq!(expand("PUSH D").as_deref(), Some("@SP AM=M+1 A=A-1 M=D"));
+    assert_eq!(expand("POP D").as_deref(), Some("@SP AM=M-1 D=M"));
+    assert_eq!(expand("D=M"), None);
+    assert_eq!(expand("(GOTO)"), None);
+
+    assert_eq!(
+        expand_pseudo("PUSH A"),
+        Some(Err("PUSH only takes D".to_owned()))
+    );
+    assert!(matches!(expand_pseudo("LOAD R3"), Some(Err(_))));
+}
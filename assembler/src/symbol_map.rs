@@ -0,0 +1,129 @@
+use std::collections::{BTreeMap, HashMap};
+use std::fmt;
+
+use serde::{Deserialize, Serialize};
+
+use crate::parser::Stmt;
+
+/// The addresses the assembler gave each label and variable, without the predefined symbols.
+/// Saved as JSON so a later build can be compared against it.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct SymbolMap {
+    pub labels: BTreeMap<String, u16>,
+    pub variables: BTreeMap<String, u16>,
+}
+
+impl SymbolMap {
+    /// Split a finished symbol table into the labels declared by the statements and the
+    /// variables allocated for everything else
+    pub fn new<'a>(
+        statements: impl IntoIterator<Item = &'a Stmt>,
+        symbol_table: &HashMap<String, u16>,
+        predefined: &HashMap<String, u16>,
+    ) -> Self {
+        let labels: BTreeMap<String, u16> = statements
+            .into_iter()
+            .filter_map(|stmt| match stmt {
+                Stmt::Label(name) => Some((name.clone(), symbol_table[name])),
+                _ => None,
+            })
+            .collect();
+        let variables = symbol_table
+            .iter()
+            .filter(|(name, _)| !labels.contains_key(*name) && !predefined.contains_key(*name))
+            .map(|(name, address)| (name.clone(), *address))
+            .collect();
+        Self { labels, variables }
+    }
+
+    /// Every label and variable which was added, removed or moved since `previous`
+    pub fn changes_since(&self, previous: &SymbolMap) -> Vec<SymbolChange> {
+        let mut changes = compare("label", &previous.labels, &self.labels);
+        changes.extend(compare("variable", &previous.variables, &self.variables));
+        changes
+    }
+}
+
+fn compare(
+    kind: &'static str,
+    previous: &BTreeMap<String, u16>,
+    current: &BTreeMap<String, u16>,
+) -> Vec<SymbolChange> {
+    let mut names: Vec<&String> = previous.keys().chain(current.keys()).collect();
+    names.sort();
+    names.dedup();
+
+    names
+        .into_iter()
+        .filter_map(|name| {
+            let (before, after) = (previous.get(name).copied(), current.get(name).copied());
+            (before != after).then(|| SymbolChange {
+                kind,
+                name: name.clone(),
+                before,
+                after,
+            })
+        })
+        .collect()
+}
+
+/// A symbol whose address differs between two builds
+#[derive(Debug, Clone, PartialEq)]
+pub struct SymbolChange {
+    /// `label` or `variable`
+    pub kind: &'static str,
+    pub name: String,
+    pub before: Option<u16>,
+    pub after: Option<u16>,
+}
+
+impl fmt::Display for SymbolChange {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match (self.before, self.after) {
+            (Some(before), Some(after)) => write!(
+                f,
+                "{} {} moved from {} to {}",
+                self.kind, self.name, before, after
+            ),
+            (None, Some(after)) => write!(f, "{} {} added at {}", self.kind, self.name, after),
+            (Some(before), None) => {
+                write!(f, "{} {} removed, was at {}", self.kind, self.name, before)
+            }
+            (None, None) => write!(f, "{} {} unchanged", self.kind, self.name),
+        }
+    }
+}
+
+#[test]
+fn test_symbol_changes() {
+    let map = |labels: &[(&str, u16)], variables: &[(&str, u16)]| {
+        let owned = |symbols: &[(&str, u16)]| {
+            symbols
+                .iter()
+                .map(|(name, address)| (name.to_string(), *address))
+                .collect()
+        };
+        SymbolMap {
+            labels: owned(labels),
+            variables: owned(variables),
+        }
+    };
+    let before = map(&[("LOOP", 4), ("END", 10)], &[("i", 16), ("sum", 17)]);
+    let after = map(&[("LOOP", 6), ("END", 10)], &[("sum", 16), ("n", 17)]);
+
+    let changes: Vec<String> = after
+        .changes_since(&before)
+        .iter()
+        .map(|change| change.to_string())
+        .collect();
+    assert_eq!(
+        changes,
+        [
+            "label LOOP moved from 4 to 6",
+            "variable i removed, was at 16",
+            "variable n added at 17",
+            "variable sum moved from 17 to 16",
+        ]
+    );
+    assert!(after.changes_since(&after).is_empty());
+}
@@ -0,0 +1,102 @@
+use std::collections::HashSet;
+
+use nom::character::complete::{digit1, multispace1};
+use nom::IResult;
+use parse_utils::tokens::{Token, TokenKind};
+use parse_utils::{identifier, line_comment};
+
+use crate::parser::{parse_line, Stmt};
+
+const OPERATORS: &str = "@()=;+-!&|";
+
+fn symbol(i: &str) -> IResult<&str, &str> {
+    identifier(".$:")(i)
+}
+
+/// Split Hack assembly into classified tokens for highlighting.
+///
+/// Symbols declared as labels anywhere in the file are reported as labels, every other symbol
+/// is a variable. Registers and jump mnemonics are keywords.
+pub fn tokenize_hack(source: &str) -> Vec<Token> {
+    let labels: HashSet<String> = source
+        .lines()
+        .filter_map(|line| match parse_line(line) {
+            Ok(Stmt::Label(name)) => Some(name),
+            _ => None,
+        })
+        .collect();
+
+    let mut tokens = Vec::new();
+    let mut rest = source;
+    let mut after_at = false;
+
+    while let Some(next_char) = rest.chars().next() {
+        let offset = source.len() - rest.len();
+        let is_operand = after_at;
+        after_at = false;
+
+        if let Ok((remaining, _)) = multispace1::<&str, nom::error::Error<&str>>(rest) {
+            rest = remaining;
+        } else if let Ok((remaining, text)) = line_comment::<&str, nom::error::Error<&str>>(rest) {
+            tokens.push(Token::new(offset, text.len(), TokenKind::Comment));
+            rest = remaining;
+        } else if let Ok((remaining, text)) = digit1::<&str, nom::error::Error<&str>>(rest) {
+            tokens.push(Token::new(offset, text.len(), TokenKind::Number));
+            rest = remaining;
+        } else if let Ok((remaining, text)) = symbol(rest) {
+            let kind = if labels.contains(text) {
+                TokenKind::Label
+            } else if is_operand {
+                TokenKind::Variable
+            } else {
+                TokenKind::Keyword
+            };
+            tokens.push(Token::new(offset, text.len(), kind));
+            rest = remaining;
+        } else {
+            if OPERATORS.contains(next_char) {
+                tokens.push(Token::new(offset, 1, TokenKind::Operator));
+                after_at = next_char == '@';
+            }
+            rest = &rest[next_char.len_utf8()..];
+        }
+    }
+
+    tokens
+}
+
+#[test]
+fn test_tokenize_hack() {
+    let source = "(LOOP) // start\n@i\nM=M+1\n@LOOP\n0;JMP";
+    let kinds: Vec<(&str, TokenKind)> = tokenize_hack(source)
+        .into_iter()
+        .map(|token| {
+            (
+                &source[token.offset..token.offset + token.length],
+                token.kind,
+            )
+        })
+        .collect();
+
+    assert_eq!(
+        kinds,
+        vec![
+            ("(", TokenKind::Operator),
+            ("LOOP", TokenKind::Label),
+            (")", TokenKind::Operator),
+            ("// start", TokenKind::Comment),
+            ("@", TokenKind::Operator),
+            ("i", TokenKind::Variable),
+            ("M", TokenKind::Keyword),
+            ("=", TokenKind::Operator),
+            ("M", TokenKind::Keyword),
+            ("+", TokenKind::Operator),
+            ("1", TokenKind::Number),
+            ("@", TokenKind::Operator),
+            ("LOOP", TokenKind::Label),
+            ("0", TokenKind::Number),
+            (";", TokenKind::Operator),
+            ("JMP", TokenKind::Keyword),
+        ]
+    );
+}
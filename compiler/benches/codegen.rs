@@ -0,0 +1,35 @@
+use criterion::{criterion_group, criterion_main, BenchmarkId, Criterion};
+
+/// A class with a single function which declares `locals` variables and then uses each of them
+fn class_with_locals(locals: usize) -> String {
+    let names: Vec<String> = (0..locals).map(|i| format!("v{}", i)).collect();
+
+    let mut source = String::from("class Main {\n    function void main() {\n");
+    source.push_str(&format!("        var int {};\n", names.join(", ")));
+    for i in 0..locals {
+        source.push_str(&format!(
+            "        let v{} = v{} + v{};\n",
+            i,
+            (i * 7) % locals,
+            (i * 13) % locals
+        ));
+    }
+    source.push_str("        return;\n    }\n}\n");
+    source
+}
+
+fn symbol_lookups(c: &mut Criterion) {
+    let mut group = c.benchmark_group("codegen/locals");
+    for locals in [16, 128, 1024] {
+        let source = class_with_locals(locals);
+        let ast = compiler::parse_strings(&[("Main.jack", &source)]).unwrap();
+
+        group.bench_with_input(BenchmarkId::from_parameter(locals), &ast, |b, ast| {
+            b.iter(|| compiler::compile_ast(ast).unwrap())
+        });
+    }
+    group.finish();
+}
+
+criterion_group!(benches, symbol_lookups);
+criterion_main!(benches);
@@ -1,29 +1,35 @@
 #![allow(dead_code)]
 
-use serde::Serialize;
+use serde::{Deserialize, Serialize};
 
-use super::{subroutine::Subroutine, variables::VariableType};
+use super::{
+    expression::Expr, identifier::Identifier, statement::Statement, subroutine::Subroutine,
+    variables::VariableType,
+};
 
-#[derive(Debug, Clone, Copy, Serialize)]
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
 #[serde(rename_all = "lowercase")]
 pub enum ClassVariableVisibility {
     Field,
     Static,
 }
 
-#[derive(Debug, Serialize)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct ClassVariable {
     visibility: ClassVariableVisibility,
     var_type: VariableType,
-    identifier: String,
+    identifier: Identifier,
+    #[serde(skip)]
+    line: u32,
 }
 
 impl ClassVariable {
     pub fn new(identifier: &str) -> Self {
         Self {
-            identifier: identifier.to_string(),
+            identifier: Identifier::new(identifier),
             var_type: VariableType::Int,
             visibility: ClassVariableVisibility::Field,
+            line: 0,
         }
     }
 
@@ -37,10 +43,20 @@ impl ClassVariable {
         return self;
     }
 
-    pub fn get_identifier(&self) -> &str {
+    pub fn line(mut self, line: u32) -> Self {
+        self.line = line;
+        self
+    }
+
+    pub fn get_identifier(&self) -> &Identifier {
         &self.identifier
     }
 
+    /// The line of the source file the variable is declared on
+    pub fn get_line(&self) -> u32 {
+        self.line
+    }
+
     pub fn get_visibility(&self) -> ClassVariableVisibility {
         self.visibility
     }
@@ -50,9 +66,9 @@ impl ClassVariable {
     }
 }
 
-#[derive(Debug, Serialize)]
+#[derive(Debug, Serialize, Deserialize)]
 pub struct Class {
-    identifier: String,
+    identifier: Identifier,
     subroutines: Vec<Subroutine>,
 
     variables: Vec<ClassVariable>,
@@ -61,7 +77,7 @@ pub struct Class {
 impl Class {
     pub fn new(identifier: &str) -> Self {
         Self {
-            identifier: identifier.to_owned(),
+            identifier: Identifier::new(identifier),
             subroutines: Vec::new(),
             variables: Vec::new(),
         }
@@ -99,16 +115,74 @@ impl Class {
         &self.variables
     }
 
-    pub fn get_name(&self) -> &str {
+    pub fn get_name(&self) -> &Identifier {
         &self.identifier
     }
+
+    /// Load a class from the JSON it serializes to. Like parsing, this fails if an expression
+    /// nests more than `max_expression_depth` deep. Line numbers aren't serialized, so they're all
+    /// 0.
+    pub fn from_json(json: &str, max_expression_depth: usize) -> serde_json::Result<Class> {
+        // Each operator of a long expression nests the JSON a level deeper, far beyond the default
+        // limit, so it's read on a stack which grows as needed
+        let mut deserializer = serde_json::Deserializer::from_str(json);
+        deserializer.disable_recursion_limit();
+        let class = Class::deserialize(serde_stacker::Deserializer::new(&mut deserializer))?;
+        deserializer.end()?;
+
+        let too_deep = class
+            .subroutines
+            .iter()
+            .flat_map(|subroutine| subroutine.get_statements())
+            .any(|statement| nests_too_deeply(statement, max_expression_depth));
+        if too_deep {
+            return Err(serde::de::Error::custom(format!(
+                "an expression is nested more than {} deep",
+                max_expression_depth
+            )));
+        }
+        Ok(class)
+    }
+}
+
+/// Whether an expression of `statement` nests more than `max_depth` deep
+fn nests_too_deeply(statement: &Statement, max_depth: usize) -> bool {
+    let too_deep = |expr: &Expr| expr.nesting_depth() > max_depth;
+    match statement {
+        Statement::Let(details) => {
+            details.identifier.get_index().is_some_and(too_deep) || too_deep(&details.expression)
+        }
+        Statement::While(details) => {
+            too_deep(&details.condition)
+                || details
+                    .body
+                    .iter()
+                    .any(|statement| nests_too_deeply(statement, max_depth))
+        }
+        Statement::If(details) => {
+            too_deep(&details.condition)
+                || details
+                    .if_body
+                    .iter()
+                    .chain(details.else_body.iter().flatten())
+                    .any(|statement| nests_too_deeply(statement, max_depth))
+        }
+        Statement::Do(call) => call.get_parameters().iter().any(too_deep),
+        Statement::Return(details) => details.value.as_ref().is_some_and(too_deep),
+        Statement::VarDecl(_) => false,
+    }
 }
 
 pub struct CompiledClass {
     pub class: Class,
     pub source_filename: String,
+    /// The Jack source the class was parsed from, for pointing errors at it
+    pub source: String,
 }
 
 pub struct AST {
     pub classes: Vec<CompiledClass>,
+    /// Other classes of the program which aren't being compiled, e.g. the rest of a project when
+    /// only one of its files is. Calls to them are checked, but no code is generated for them.
+    pub siblings: Vec<Class>,
 }
@@ -1,71 +1,376 @@
 #![allow(dead_code)]
 
-use serde::Serialize;
+use std::fmt;
+
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
 
 use super::{variables::VariableRef, SubroutineCall};
 
-#[derive(Debug, Clone, PartialEq, Serialize)]
-pub enum Expr {
+/// An expression tree stored as a flat arena of nodes.
+///
+/// Nodes refer to their operands by index rather than through a `Box`, so an expression of any
+/// depth is a single allocation. The root is always the last node.
+#[derive(Clone)]
+pub struct Expr {
+    nodes: Vec<ExprNode>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+struct NodeId(u32);
+
+/// How close to the end of the stack serializing an expression gets before it grows the stack
+const RED_ZONE: usize = 64 * 1024;
+/// How much the stack is grown by each time
+const STACK_SEGMENT: usize = 1024 * 1024;
+
+#[derive(Debug, Clone)]
+enum ExprNode {
     Constant(Constant),
     VarRef(VariableRef),
-    UnaryExpr(UnaryOp, Box<Expr>),
+    UnaryExpr(UnaryOp, NodeId),
     BinaryExpr {
-        lhs: Box<Expr>,
+        lhs: NodeId,
         op: BinaryOp,
-        rhs: Box<Expr>,
+        rhs: NodeId,
     },
-    BracketedExpr(Box<Expr>),
+    BracketedExpr(NodeId),
     Call(SubroutineCall),
 }
 
+impl ExprNode {
+    fn shifted(self, offset: u32) -> ExprNode {
+        let shift = |NodeId(id)| NodeId(id + offset);
+        match self {
+            ExprNode::UnaryExpr(op, expr) => ExprNode::UnaryExpr(op, shift(expr)),
+            ExprNode::BinaryExpr { lhs, op, rhs } => ExprNode::BinaryExpr {
+                lhs: shift(lhs),
+                op,
+                rhs: shift(rhs),
+            },
+            ExprNode::BracketedExpr(expr) => ExprNode::BracketedExpr(shift(expr)),
+            node => node,
+        }
+    }
+}
+
+/// A borrowed node of an [`Expr`]
+#[derive(Clone, Copy)]
+pub struct ExprRef<'a> {
+    nodes: &'a [ExprNode],
+    id: NodeId,
+}
+
+/// The contents of a single expression node, with operands borrowed from the same arena
+#[derive(Debug, PartialEq, Serialize)]
+pub enum ExprKind<'a> {
+    Constant(&'a Constant),
+    VarRef(&'a VariableRef),
+    UnaryExpr(UnaryOp, ExprRef<'a>),
+    BinaryExpr {
+        lhs: ExprRef<'a>,
+        op: BinaryOp,
+        rhs: ExprRef<'a>,
+    },
+    BracketedExpr(ExprRef<'a>),
+    Call(&'a SubroutineCall),
+}
+
+impl<'a> ExprRef<'a> {
+    pub fn kind(self) -> ExprKind<'a> {
+        let child = |id| ExprRef {
+            nodes: self.nodes,
+            id,
+        };
+        match &self.nodes[self.id.0 as usize] {
+            ExprNode::Constant(constant) => ExprKind::Constant(constant),
+            ExprNode::VarRef(var) => ExprKind::VarRef(var),
+            ExprNode::UnaryExpr(op, expr) => ExprKind::UnaryExpr(*op, child(*expr)),
+            ExprNode::BinaryExpr { lhs, op, rhs } => ExprKind::BinaryExpr {
+                lhs: child(*lhs),
+                op: *op,
+                rhs: child(*rhs),
+            },
+            ExprNode::BracketedExpr(expr) => ExprKind::BracketedExpr(child(*expr)),
+            ExprNode::Call(call) => ExprKind::Call(call),
+        }
+    }
+}
+
+/// A step of walking a chain of binary operators with [`ExprRef::postfix`]
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Visit<'a> {
+    /// A node which isn't a binary operator. Its own operands are left to the caller.
+    Operand(ExprRef<'a>),
+    /// A binary operator, which applies to the two values before it
+    Operator(BinaryOp),
+}
+
+impl<'a> ExprRef<'a> {
+    /// Walk the chain of binary operators rooted here in postfix order: the operands left to
+    /// right, with each operator straight after its two operands. A chain may be any length, so
+    /// it's walked with a stack rather than by recursion.
+    pub fn postfix(self) -> impl Iterator<Item = Visit<'a>> {
+        let mut pending = vec![Visit::Operand(self)];
+        std::iter::from_fn(move || loop {
+            match pending.pop()? {
+                Visit::Operand(operand) => match operand.kind() {
+                    ExprKind::BinaryExpr { lhs, op, rhs } => {
+                        pending.push(Visit::Operator(op));
+                        pending.push(Visit::Operand(rhs));
+                        pending.push(Visit::Operand(lhs));
+                    }
+                    _ => return Some(Visit::Operand(operand)),
+                },
+                operator => return Some(operator),
+            }
+        })
+    }
+
+    /// The operands of the chain of binary operators rooted here, left to right
+    pub fn operands(self) -> impl Iterator<Item = ExprRef<'a>> {
+        self.postfix().filter_map(|visit| match visit {
+            Visit::Operand(operand) => Some(operand),
+            Visit::Operator(_) => None,
+        })
+    }
+}
+
+impl PartialEq for ExprRef<'_> {
+    fn eq(&self, other: &Self) -> bool {
+        self.kind() == other.kind()
+    }
+}
+
+impl fmt::Debug for ExprRef<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        self.kind().fmt(f)
+    }
+}
+
+impl Serialize for ExprRef<'_> {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        // Each operator of a chain nests the serialized form a level deeper, so a long chain
+        // needs more stack than it would take to parse
+        stacker::maybe_grow(RED_ZONE, STACK_SEGMENT, || {
+            self.kind().serialize(serializer)
+        })
+    }
+}
+
 impl Expr {
-    pub fn binary_op(lhs: Expr, op: BinaryOp, rhs: Expr) -> Expr {
-        Expr::BinaryExpr {
-            lhs: Box::new(lhs),
-            op,
-            rhs: Box::new(rhs),
+    fn leaf(node: ExprNode) -> Expr {
+        Expr { nodes: vec![node] }
+    }
+
+    /// Add a node whose operands are already in the arena
+    fn with_root(mut self, node: ExprNode) -> Expr {
+        self.nodes.push(node);
+        self
+    }
+
+    fn root_id(&self) -> NodeId {
+        NodeId(self.nodes.len() as u32 - 1)
+    }
+
+    pub fn root(&self) -> ExprRef<'_> {
+        ExprRef {
+            nodes: &self.nodes,
+            id: self.root_id(),
         }
     }
 
+    pub fn kind(&self) -> ExprKind<'_> {
+        self.root().kind()
+    }
+
+    pub fn binary_op(lhs: Expr, op: BinaryOp, rhs: Expr) -> Expr {
+        // Copy the smaller operand into the larger one's arena so long chains stay linear
+        let lhs_is_base = lhs.nodes.len() >= rhs.nodes.len();
+        let (mut base, appended) = if lhs_is_base { (lhs, rhs) } else { (rhs, lhs) };
+
+        let base_root = base.root_id();
+        let offset = base.nodes.len() as u32;
+        base.nodes
+            .extend(appended.nodes.into_iter().map(|node| node.shifted(offset)));
+        let appended_root = base.root_id();
+
+        let (lhs, rhs) = if lhs_is_base {
+            (base_root, appended_root)
+        } else {
+            (appended_root, base_root)
+        };
+        base.with_root(ExprNode::BinaryExpr { lhs, op, rhs })
+    }
+
     pub fn unary_op(op: UnaryOp, rhs: Expr) -> Expr {
-        Expr::UnaryExpr(op, Box::new(rhs))
+        let rhs_id = rhs.root_id();
+        rhs.with_root(ExprNode::UnaryExpr(op, rhs_id))
     }
 
     pub fn var(var: VariableRef) -> Expr {
-        Expr::VarRef(var)
+        Expr::leaf(ExprNode::VarRef(var))
     }
 
     pub fn brackets(expr: Expr) -> Expr {
-        Expr::BracketedExpr(Box::new(expr))
+        let expr_id = expr.root_id();
+        expr.with_root(ExprNode::BracketedExpr(expr_id))
+    }
+
+    pub fn constant(constant: Constant) -> Expr {
+        Expr::leaf(ExprNode::Constant(constant))
     }
 
     pub fn int(val: i32) -> Expr {
-        Expr::Constant(Constant::Int(val))
+        Expr::constant(Constant::Int(val))
     }
 
     pub fn string(val: &str) -> Expr {
-        Expr::Constant(Constant::String(val.to_owned()))
+        Expr::constant(Constant::String(val.to_owned()))
     }
 
     pub fn true_c() -> Expr {
-        Expr::Constant(Constant::Keyword(KeywordConstant::True))
+        Expr::constant(Constant::Keyword(KeywordConstant::True))
     }
     pub fn false_c() -> Expr {
-        Expr::Constant(Constant::Keyword(KeywordConstant::False))
+        Expr::constant(Constant::Keyword(KeywordConstant::False))
     }
     pub fn null() -> Expr {
-        Expr::Constant(Constant::Keyword(KeywordConstant::Null))
+        Expr::constant(Constant::Keyword(KeywordConstant::Null))
     }
     pub fn this() -> Expr {
-        Expr::Constant(Constant::Keyword(KeywordConstant::This))
+        Expr::constant(Constant::Keyword(KeywordConstant::This))
     }
 
     pub fn call() -> SubroutineCall {
         SubroutineCall::new()
     }
+
+    pub fn from_call(call: SubroutineCall) -> Expr {
+        Expr::leaf(ExprNode::Call(call))
+    }
+
+    /// How deeply brackets, unary operators, indices and call arguments nest in the expression,
+    /// counted as the parser does for its `max_expression_depth`
+    pub fn nesting_depth(&self) -> usize {
+        let mut deepest = 0;
+        let mut pending = vec![(self.root(), 0)];
+        while let Some((expr, depth)) = pending.pop() {
+            deepest = deepest.max(depth);
+            match expr.kind() {
+                ExprKind::Constant(_) => {}
+                ExprKind::VarRef(var) => {
+                    if let Some(index) = var.get_index() {
+                        pending.push((index.root(), depth + 1));
+                    }
+                }
+                ExprKind::UnaryExpr(_, operand) | ExprKind::BracketedExpr(operand) => {
+                    pending.push((operand, depth + 1))
+                }
+                ExprKind::BinaryExpr { lhs, rhs, .. } => {
+                    pending.push((lhs, depth));
+                    pending.push((rhs, depth));
+                }
+                ExprKind::Call(call) => pending.extend(
+                    call.get_parameters()
+                        .iter()
+                        .map(|parameter| (parameter.root(), depth + 1)),
+                ),
+            }
+        }
+        deepest
+    }
+}
+
+impl PartialEq for Expr {
+    fn eq(&self, other: &Self) -> bool {
+        self.root() == other.root()
+    }
+}
+
+impl fmt::Debug for Expr {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        self.root().fmt(f)
+    }
+}
+
+impl Serialize for Expr {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        self.root().serialize(serializer)
+    }
+}
+
+/// An expression in the nested form it's serialized in, which is built back into an arena
+#[derive(Deserialize)]
+enum ExprTree {
+    Constant(Constant),
+    VarRef(VariableRef),
+    UnaryExpr(UnaryOp, Box<ExprTree>),
+    BinaryExpr {
+        lhs: Box<ExprTree>,
+        op: BinaryOp,
+        rhs: Box<ExprTree>,
+    },
+    BracketedExpr(Box<ExprTree>),
+    Call(SubroutineCall),
+}
+
+impl From<ExprTree> for Expr {
+    /// A deserialized tree may be any depth, so it's built with a stack rather than recursion.
+    /// Each box is emptied as it's taken apart, so dropping them doesn't recurse either.
+    fn from(tree: ExprTree) -> Expr {
+        enum Step {
+            Build(ExprTree),
+            Unary(UnaryOp),
+            Binary(BinaryOp),
+            Brackets,
+        }
+
+        let mut steps = vec![Step::Build(tree)];
+        let mut built: Vec<Expr> = Vec::new();
+        while let Some(step) = steps.pop() {
+            match step {
+                Step::Build(ExprTree::Constant(constant)) => built.push(Expr::constant(constant)),
+                Step::Build(ExprTree::VarRef(var)) => built.push(Expr::var(var)),
+                Step::Build(ExprTree::Call(call)) => built.push(Expr::from_call(call)),
+                Step::Build(ExprTree::UnaryExpr(op, expr)) => {
+                    steps.push(Step::Unary(op));
+                    steps.push(Step::Build(*expr));
+                }
+                Step::Build(ExprTree::BinaryExpr { lhs, op, rhs }) => {
+                    steps.push(Step::Binary(op));
+                    steps.push(Step::Build(*rhs));
+                    steps.push(Step::Build(*lhs));
+                }
+                Step::Build(ExprTree::BracketedExpr(expr)) => {
+                    steps.push(Step::Brackets);
+                    steps.push(Step::Build(*expr));
+                }
+                Step::Unary(op) => {
+                    let expr = built.pop().expect("A unary operator has an operand");
+                    built.push(Expr::unary_op(op, expr));
+                }
+                Step::Binary(op) => {
+                    let rhs = built.pop().expect("Every operator has two operands");
+                    let lhs = built.pop().expect("Every operator has two operands");
+                    built.push(Expr::binary_op(lhs, op, rhs));
+                }
+                Step::Brackets => {
+                    let expr = built.pop().expect("Brackets hold an expression");
+                    built.push(Expr::brackets(expr));
+                }
+            }
+        }
+        built.pop().expect("A tree builds into one expression")
+    }
+}
+
+impl<'de> Deserialize<'de> for Expr {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        ExprTree::deserialize(deserializer).map(Expr::from)
+    }
 }
 
-#[derive(Debug, Clone, PartialEq, Serialize)]
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 pub enum Constant {
     Int(i32),
     String(String),
@@ -74,11 +379,11 @@ pub enum Constant {
 
 impl Constant {
     pub fn as_expr(self) -> Expr {
-        Expr::Constant(self)
+        Expr::constant(self)
     }
 }
 
-#[derive(Debug, PartialEq, Clone, Copy, Serialize)]
+#[derive(Debug, PartialEq, Clone, Copy, Serialize, Deserialize)]
 #[serde(rename_all = "lowercase")]
 pub enum KeywordConstant {
     True,
@@ -87,7 +392,7 @@ pub enum KeywordConstant {
     This,
 }
 
-#[derive(Debug, PartialEq, Clone, Copy, Serialize)]
+#[derive(Debug, PartialEq, Clone, Copy, Serialize, Deserialize)]
 pub enum BinaryOp {
     Plus,
     Minus,
@@ -100,7 +405,7 @@ pub enum BinaryOp {
     Eq,
 }
 
-#[derive(Debug, Clone, Copy, Serialize, PartialEq)]
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq)]
 pub enum UnaryOp {
     Minus,
     Not,
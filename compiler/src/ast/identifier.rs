@@ -0,0 +1,170 @@
+use std::borrow::Borrow;
+use std::cell::RefCell;
+use std::collections::HashSet;
+use std::fmt;
+use std::hash::{Hash, Hasher};
+use std::ops::Deref;
+use std::sync::Arc;
+
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+
+/// The fewest names the interner holds before it drops those which are no longer used
+const MIN_SWEEP: usize = 1024;
+
+thread_local! {
+    static INTERNER: RefCell<Interner> = RefCell::new(Interner {
+        names: HashSet::new(),
+        sweep_at: MIN_SWEEP,
+    });
+}
+
+/// The names of a thread's identifiers. A long-running process such as the language server parses
+/// new names with every edit, so names which no identifier holds any more are dropped whenever
+/// the set has doubled since the last time.
+struct Interner {
+    names: HashSet<Arc<str>>,
+    sweep_at: usize,
+}
+
+impl Interner {
+    fn intern(&mut self, name: &str) -> Arc<str> {
+        if let Some(interned) = self.names.get(name) {
+            return interned.clone();
+        }
+        if self.names.len() >= self.sweep_at {
+            // A name held only here can't be in use anywhere else, even on another thread
+            self.names.retain(|name| Arc::strong_count(name) > 1);
+            self.sweep_at = (self.names.len() * 2).max(MIN_SWEEP);
+        }
+        let interned: Arc<str> = Arc::from(name);
+        self.names.insert(interned.clone());
+        interned
+    }
+}
+
+/// An interned class, subroutine, variable or type name.
+///
+/// Every identifier with the same text created on a thread shares one allocation while any of
+/// them is alive, so cloning is a reference count bump and comparisons can usually stop at the
+/// pointer.
+#[derive(Clone, Eq)]
+pub struct Identifier(Arc<str>);
+
+impl Identifier {
+    pub fn new(name: &str) -> Self {
+        INTERNER.with(|interner| Identifier(interner.borrow_mut().intern(name)))
+    }
+
+    pub fn as_str(&self) -> &str {
+        &self.0
+    }
+}
+
+impl Default for Identifier {
+    fn default() -> Self {
+        Identifier::new("")
+    }
+}
+
+impl PartialEq for Identifier {
+    fn eq(&self, other: &Self) -> bool {
+        Arc::ptr_eq(&self.0, &other.0) || self.0 == other.0
+    }
+}
+
+impl Borrow<str> for Identifier {
+    fn borrow(&self) -> &str {
+        &self.0
+    }
+}
+
+impl Hash for Identifier {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        self.0.hash(state);
+    }
+}
+
+impl PartialEq<str> for Identifier {
+    fn eq(&self, other: &str) -> bool {
+        &*self.0 == other
+    }
+}
+
+impl PartialEq<&str> for Identifier {
+    fn eq(&self, other: &&str) -> bool {
+        &*self.0 == *other
+    }
+}
+
+impl Deref for Identifier {
+    type Target = str;
+
+    fn deref(&self) -> &str {
+        &self.0
+    }
+}
+
+impl From<&str> for Identifier {
+    fn from(name: &str) -> Self {
+        Identifier::new(name)
+    }
+}
+
+impl From<String> for Identifier {
+    fn from(name: String) -> Self {
+        Identifier::new(&name)
+    }
+}
+
+impl From<&Identifier> for Identifier {
+    fn from(identifier: &Identifier) -> Self {
+        identifier.clone()
+    }
+}
+
+impl fmt::Display for Identifier {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.0)
+    }
+}
+
+impl fmt::Debug for Identifier {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        fmt::Debug::fmt(&*self.0, f)
+    }
+}
+
+impl Serialize for Identifier {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_str(&self.0)
+    }
+}
+
+impl<'de> Deserialize<'de> for Identifier {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        String::deserialize(deserializer).map(Identifier::from)
+    }
+}
+
+#[test]
+fn test_identifiers_are_interned() {
+    let a = Identifier::new("counter");
+    let b = Identifier::from(String::from("counter"));
+
+    assert!(Arc::ptr_eq(&a.0, &b.0));
+    assert_eq!(a, b);
+    assert_eq!(a, "counter");
+    assert_ne!(a, Identifier::new("count"));
+}
+
+#[test]
+fn test_unused_names_are_dropped() {
+    let kept = Identifier::new("kept");
+    for index in 0..10 * MIN_SWEEP {
+        Identifier::new(&format!("name{}", index));
+    }
+
+    let interned = INTERNER.with(|interner| interner.borrow().names.len());
+    assert!(interned <= 2 * MIN_SWEEP);
+    assert!(Arc::ptr_eq(&kept.0, &Identifier::new("kept").0));
+}
@@ -1,11 +1,13 @@
 mod ast;
 mod expression;
+mod identifier;
 mod statement;
 mod subroutine;
 mod variables;
 
 pub use ast::*;
 pub use expression::*;
+pub use identifier::Identifier;
 pub use statement::*;
 pub use subroutine::*;
 pub use variables::*;
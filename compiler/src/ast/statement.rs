@@ -1,16 +1,26 @@
 #![allow(dead_code)]
 
-use serde::Serialize;
+use serde::{Deserialize, Serialize};
 
 use super::{
     expression::Expr,
+    identifier::Identifier,
     variables::{Variable, VariableRef},
 };
 
-#[derive(Debug, Clone, Serialize)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct LetDetails {
     pub identifier: VariableRef,
     pub expression: Expr,
+    /// The line of the source file the statement starts on
+    #[serde(skip)]
+    pub line: u32,
+}
+
+impl Default for LetDetails {
+    fn default() -> Self {
+        Self::new()
+    }
 }
 
 impl LetDetails {
@@ -18,6 +28,7 @@ impl LetDetails {
         Self {
             identifier: VariableRef::new(""),
             expression: Expr::int(0),
+            line: 0,
         }
     }
 
@@ -44,10 +55,19 @@ impl LetDetails {
     }
 }
 
-#[derive(Debug, Clone, Serialize)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct WhileDetails {
     pub condition: Expr,
     pub body: Vec<Statement>,
+    /// The line of the source file the loop starts on
+    #[serde(skip)]
+    pub line: u32,
+}
+
+impl Default for WhileDetails {
+    fn default() -> Self {
+        Self::new()
+    }
 }
 
 impl WhileDetails {
@@ -55,6 +75,7 @@ impl WhileDetails {
         Self {
             condition: Expr::true_c(),
             body: Vec::new(),
+            line: 0,
         }
     }
 
@@ -81,11 +102,20 @@ impl WhileDetails {
     }
 }
 
-#[derive(Debug, Clone, Serialize)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct IfDetails {
     pub condition: Expr,
     pub if_body: Vec<Statement>,
     pub else_body: Option<Vec<Statement>>,
+    /// The line of the source file the statement starts on
+    #[serde(skip)]
+    pub line: u32,
+}
+
+impl Default for IfDetails {
+    fn default() -> Self {
+        Self::new()
+    }
 }
 
 impl IfDetails {
@@ -94,6 +124,7 @@ impl IfDetails {
             condition: Expr::true_c(),
             if_body: Vec::new(),
             else_body: None,
+            line: 0,
         }
     }
 
@@ -133,11 +164,14 @@ impl IfDetails {
     }
 }
 
-#[derive(Debug, Clone, PartialEq, Serialize, Default)]
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, Default)]
 pub struct SubroutineCall {
-    target_name: Option<String>,
-    subroutine_name: String,
+    target_name: Option<Identifier>,
+    subroutine_name: Identifier,
     parameters: Vec<Expr>,
+    /// The line of the source file a do statement starts on. Calls within expressions leave it 0.
+    #[serde(skip)]
+    pub line: u32,
 }
 
 impl SubroutineCall {
@@ -152,24 +186,24 @@ impl SubroutineCall {
     }
 
     pub fn as_expr(self) -> Expr {
-        Expr::Call(self)
+        Expr::from_call(self)
     }
 
     pub fn set_target(mut self, target_name: &str) -> Self {
-        self.target_name = Some(target_name.to_owned());
+        self.target_name = Some(Identifier::new(target_name));
         self
     }
 
-    pub fn get_target(&self) -> &Option<String> {
+    pub fn get_target(&self) -> &Option<Identifier> {
         &self.target_name
     }
 
-    pub fn get_name(&self) -> &str {
+    pub fn get_name(&self) -> &Identifier {
         &self.subroutine_name
     }
 
     pub fn name(mut self, name: &str) -> Self {
-        self.subroutine_name = name.to_owned();
+        self.subroutine_name = Identifier::new(name);
         self
     }
 
@@ -188,7 +222,7 @@ impl SubroutineCall {
     pub fn name_as_string(&self) -> String {
         let type_portion = self
             .target_name
-            .clone()
+            .as_ref()
             .map(|type_name| format!("{}.", type_name));
         format!(
             "{}{}",
@@ -200,9 +234,17 @@ impl SubroutineCall {
     pub fn get_parameters(&self) -> &Vec<Expr> {
         &self.parameters
     }
+
+    /// The same call with different arguments
+    pub fn with_parameters(&self, parameters: Vec<Expr>) -> Self {
+        SubroutineCall {
+            parameters,
+            ..self.clone()
+        }
+    }
 }
 
-#[derive(Debug, Clone, Serialize, Default)]
+#[derive(Debug, Clone, Serialize, Deserialize, Default)]
 pub struct VarDeclDetails {
     variables: Vec<Variable>,
 }
@@ -228,13 +270,28 @@ impl VarDeclDetails {
     }
 }
 
-#[derive(Debug, Clone, Serialize)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(transparent)]
+pub struct ReturnDetails {
+    pub value: Option<Expr>,
+    /// The line of the source file the statement starts on
+    #[serde(skip)]
+    pub line: u32,
+}
+
+impl ReturnDetails {
+    pub fn get_value(&self) -> Option<&Expr> {
+        self.value.as_ref()
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub enum Statement {
     Let(LetDetails),
     While(WhileDetails),
     Do(SubroutineCall),
     If(IfDetails),
-    Return(Option<Expr>),
+    Return(ReturnDetails),
     VarDecl(VarDeclDetails),
 }
 
@@ -252,11 +309,17 @@ impl Statement {
     }
 
     pub fn return_void() -> Statement {
-        Statement::Return(None)
+        Statement::Return(ReturnDetails {
+            value: None,
+            line: 0,
+        })
     }
 
     pub fn return_expr(expr: Expr) -> Statement {
-        Statement::Return(Some(expr))
+        Statement::Return(ReturnDetails {
+            value: Some(expr),
+            line: 0,
+        })
     }
 
     pub fn while_loop() -> WhileDetails {
@@ -266,4 +329,16 @@ impl Statement {
     pub fn if_statement() -> IfDetails {
         IfDetails::new()
     }
+
+    /// The line of the source file the statement starts on, or 0 if it wasn't parsed
+    pub fn line(&self) -> u32 {
+        match self {
+            Statement::Let(details) => details.line,
+            Statement::While(details) => details.line,
+            Statement::Do(call) => call.line,
+            Statement::If(details) => details.line,
+            Statement::Return(details) => details.line,
+            Statement::VarDecl(_) => 0,
+        }
+    }
 }
@@ -1,9 +1,9 @@
 #![allow(dead_code)]
-use serde::Serialize;
+use serde::{Deserialize, Serialize};
 
-use super::{statement::Statement, variables::Variable};
+use super::{identifier::Identifier, statement::Statement, variables::Variable};
 
-#[derive(Debug, Clone, Copy, Serialize, Default, PartialEq)]
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, Default, PartialEq)]
 #[serde(rename_all = "lowercase")]
 pub enum SubroutineType {
     #[default]
@@ -12,7 +12,7 @@ pub enum SubroutineType {
     Method,
 }
 
-#[derive(Debug, Serialize, Default)]
+#[derive(Debug, Clone, Serialize, Deserialize, Default)]
 #[serde(rename_all = "snake_case")]
 pub enum ReturnType {
     Int,
@@ -20,22 +20,24 @@ pub enum ReturnType {
     Boolean,
     #[default]
     Void,
-    ClassName(String),
+    ClassName(Identifier),
 }
 
-#[derive(Debug, Serialize, Default)]
+#[derive(Debug, Clone, Serialize, Deserialize, Default)]
 pub struct Subroutine {
     subroutine_type: SubroutineType,
-    identifier: String,
+    identifier: Identifier,
     parameters: Vec<Variable>,
     return_type: ReturnType,
     statements: Vec<Statement>,
+    #[serde(skip)]
+    line: u32,
 }
 
 impl Subroutine {
     pub fn new(identifier: &str) -> Self {
         Self {
-            identifier: identifier.to_owned(),
+            identifier: Identifier::new(identifier),
             ..Default::default()
         }
     }
@@ -52,6 +54,14 @@ impl Subroutine {
         self
     }
 
+    /// The same subroutine with different statements
+    pub fn with_statements(&self, statements: Vec<Statement>) -> Self {
+        Self {
+            statements,
+            ..self.clone()
+        }
+    }
+
     pub fn return_type(mut self, return_type: ReturnType) -> Self {
         self.return_type = return_type;
         self
@@ -74,14 +84,23 @@ impl Subroutine {
         self
     }
 
+    pub fn line(mut self, line: u32) -> Self {
+        self.line = line;
+        self
+    }
+
     pub fn get_subroutine_type(&self) -> SubroutineType {
         self.subroutine_type
     }
 
-    pub fn get_name(&self) -> &String {
+    pub fn get_name(&self) -> &Identifier {
         &self.identifier
     }
 
+    pub fn get_return_type(&self) -> &ReturnType {
+        &self.return_type
+    }
+
     pub fn get_statements(&self) -> &Vec<Statement> {
         &self.statements
     }
@@ -89,4 +108,9 @@ impl Subroutine {
     pub fn get_parameters(&self) -> &Vec<Variable> {
         &self.parameters
     }
+
+    /// The line of the source file the subroutine is declared on
+    pub fn get_line(&self) -> u32 {
+        self.line
+    }
 }
@@ -1,15 +1,15 @@
-use serde::Serialize;
+use serde::{Deserialize, Serialize};
 
-use super::expression::Expr;
+use super::{expression::Expr, identifier::Identifier};
 
-#[derive(Debug, Clone, Serialize)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 #[serde(rename_all = "lowercase")]
 pub enum VariableType {
     Array,
     Int,
     Char,
     Boolean,
-    ClassName(String),
+    ClassName(Identifier),
 }
 
 impl ToString for VariableType {
@@ -19,26 +19,49 @@ impl ToString for VariableType {
             VariableType::Int => "Int".to_owned(),
             VariableType::Char => "Char".to_owned(),
             VariableType::Boolean => "Bool".to_owned(),
+            VariableType::ClassName(name) => name.to_string(),
+        }
+    }
+}
+
+impl VariableType {
+    /// The name of the type as recorded in the symbol table
+    pub fn type_name(&self) -> Identifier {
+        match self {
             VariableType::ClassName(name) => name.clone(),
+            _ => Identifier::new(&self.to_string()),
         }
     }
 }
 
-#[derive(Debug, Clone, Serialize)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct Variable {
-    identifier: String,
+    identifier: Identifier,
     var_type: VariableType,
+    /// The line of the source file the variable is declared on
+    #[serde(skip)]
+    line: u32,
 }
 
 impl Variable {
     pub fn new(identifier: &str, var_type: VariableType) -> Self {
         Self {
-            identifier: identifier.to_owned(),
+            identifier: Identifier::new(identifier),
             var_type,
+            line: 0,
         }
     }
 
-    pub fn get_identifier(&self) -> &str {
+    pub fn line(mut self, line: u32) -> Self {
+        self.line = line;
+        self
+    }
+
+    pub fn get_line(&self) -> u32 {
+        self.line
+    }
+
+    pub fn get_identifier(&self) -> &Identifier {
         &self.identifier
     }
 
@@ -47,32 +70,32 @@ impl Variable {
     }
 }
 
-#[derive(Debug, Clone, PartialEq, Serialize)]
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 pub struct VariableRef {
-    name: String,
-    index: Option<Box<Expr>>,
+    name: Identifier,
+    index: Option<Expr>,
 }
 
 impl VariableRef {
     pub fn new(identifier: &str) -> Self {
         Self {
-            name: identifier.to_owned(),
+            name: Identifier::new(identifier),
             index: None,
         }
     }
 
     pub fn new_with_index(identifier: &str, index: Expr) -> Self {
         Self {
-            name: identifier.to_owned(),
-            index: Some(Box::new(index)),
+            name: Identifier::new(identifier),
+            index: Some(index),
         }
     }
 
-    pub fn get_name(&self) -> &str {
+    pub fn get_name(&self) -> &Identifier {
         &self.name
     }
 
-    pub fn get_index(&self) -> Option<&Box<Expr>> {
+    pub fn get_index(&self) -> Option<&Expr> {
         self.index.as_ref()
     }
 }
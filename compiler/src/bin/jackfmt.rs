@@ -0,0 +1,14 @@
+use compiler::fmt_cli as cli;
+use parse_utils::cli::print_error;
+
+fn main() {
+    let matches = cli::command().get_matches();
+
+    match cli::run(&matches) {
+        Ok(_) => std::process::exit(0),
+        Err(err) => {
+            print_error(&err);
+            std::process::exit(1);
+        }
+    }
+}
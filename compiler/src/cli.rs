@@ -0,0 +1,297 @@
+use std::path::{Path, PathBuf};
+
+use clap::builder::{PossibleValuesParser, TypedValueParser};
+use clap::{value_parser, Arg, ArgAction, ArgMatches, Command, ValueHint};
+use parse_utils::cli::{init_tracing, print_error, write_mode};
+use parse_utils::watch::watch;
+
+use crate::lowering::parse_lowering;
+use crate::{
+    explain, process_source, CodegenOptions, ErrorType, Language, Lowering, ParseOptions, Reports,
+    DEFAULT_MAX_EXPRESSION_DEPTH, LANGUAGE_VARIABLE,
+};
+
+/// The command line interface of the compiler, shared by the standalone binary and n2t
+pub fn command() -> Command {
+    Command::new("Jack Compiler")
+        .about("A compiler for the Jack programming language")
+        .arg(
+            Arg::new("ast_output")
+                .required(false)
+                .action(ArgAction::SetTrue)
+                .long("ast_output")
+                .num_args(0)
+                .help("Output JSON version of the AST instead of .vm files"),
+        )
+        .arg(
+            Arg::new("SOURCE")
+                .required_unless_present("explain")
+                .value_name("FILE")
+                .value_hint(ValueHint::FilePath)
+                .help("A Jack source file or directory, or the .json AST of a class written by --ast_output. A directory without .jack files compiles the .json ASTs in it"),
+        )
+        .arg(
+            Arg::new("explain")
+                .long("explain")
+                .value_name("CODE")
+                .help("Explain a diagnostic code such as J0101, with an example and how to fix it, instead of compiling"),
+        )
+        .arg(
+            Arg::new("name_report")
+                .long("name-report")
+                .action(ArgAction::SetTrue)
+                .required(false)
+                .help("Write a .names file per class mapping each function, label and static back to its Jack source"),
+        )
+        .arg(
+            Arg::new("source_map")
+                .long("source-map")
+                .action(ArgAction::SetTrue)
+                .required(false)
+                .help("Write a .vm.map file per class giving the Jack line each VM command was compiled from"),
+        )
+        .arg(
+            Arg::new("sarif")
+                .long("sarif")
+                .value_name("FILE")
+                .value_hint(ValueHint::FilePath)
+                .help("Also write the warnings to FILE as a SARIF log, for code review tools to annotate"),
+        )
+        .arg(
+            Arg::new("metrics")
+                .long("metrics")
+                .action(ArgAction::SetTrue)
+                .required(false)
+                .help("Print the statement count, nesting depth and cyclomatic complexity of each subroutine"),
+        )
+        .arg(
+            Arg::new("canonical_booleans")
+                .long("canonical-booleans")
+                .action(ArgAction::SetTrue)
+                .required(false)
+                .help("Compile true as `push constant 0, not` to match the reference compiler"),
+        )
+        .arg(
+            Arg::new("extensions")
+                .long("extensions")
+                .action(ArgAction::SetTrue)
+                .required(false)
+                .help("Scope var declarations in if and while bodies to those bodies. Without this, they belong to the whole subroutine as if declared at its top"),
+        )
+        .arg(
+            Arg::new("strict_types")
+                .long("strict-types")
+                .action(ArgAction::SetTrue)
+                .required(false)
+                .help("Reject programs which use values as the wrong type, e.g. a boolean as an int"),
+        )
+        .arg(
+            Arg::new("auto_dispose")
+                .long("auto-dispose")
+                .action(ArgAction::SetTrue)
+                .required(false)
+                .help("Experimental: free the objects of local variables which never escape their subroutine when it returns"),
+        )
+        .arg(
+            Arg::new("optimize")
+                .short('O')
+                .action(ArgAction::SetTrue)
+                .required(false)
+                .help("Fold constant expressions and remove branches which can never run"),
+        )
+        .arg(
+            Arg::new("check_this")
+                .long("check-this")
+                .action(ArgAction::SetTrue)
+                .required(false)
+                .help("Debugging: on entering each method, call Sys.error(21) unless `this` is non-zero and below 16384, to catch a method called like a function"),
+        )
+        .arg(
+            Arg::new("check_order")
+                .long("check-order")
+                .action(ArgAction::SetTrue)
+                .required(false)
+                .help("Compile the classes a second time in reverse order and fail if any output differs, to catch a dependence on the order files are found in"),
+        )
+        .arg(
+            Arg::new("eliminate_dead_code")
+                .long("eliminate-dead-code")
+                .action(ArgAction::SetTrue)
+                .required(false)
+                .help("Leave out statements after a return and subroutines never called from Main.main or Sys.init, warning about each"),
+        )
+        .arg(
+            Arg::new("deny_warnings")
+                .long("deny-warnings")
+                .action(ArgAction::SetTrue)
+                .required(false)
+                .help("Fail without writing any files if there are warnings"),
+        )
+        .arg(
+            Arg::new("lang")
+                .long("lang")
+                .value_name("LANGUAGE")
+                .value_parser(PossibleValuesParser::new(Language::NAMES).map(|name| {
+                    name.parse::<Language>()
+                        .expect("Only possible values are parsed")
+                }))
+                .help(format!(
+                    "The language of error and warning messages. Defaults to ${}, then English",
+                    LANGUAGE_VARIABLE
+                )),
+        )
+        .arg(
+            Arg::new("lower")
+                .long("lower")
+                .value_name("OPERATION=Class.function")
+                .action(ArgAction::Append)
+                .value_parser(parse_lowering)
+                .help(format!(
+                    "Call your own subroutine instead of the OS for one of: {}",
+                    Lowering::OPERATIONS.join(", ")
+                )),
+        )
+        .arg(
+            Arg::new("max_expression_depth")
+                .long("max-expression-depth")
+                .value_name("DEPTH")
+                .value_parser(value_parser!(usize))
+                .help("How deeply expressions may nest before they are rejected"),
+        )
+        .arg(
+            Arg::new("precedence")
+                .long("precedence")
+                .action(ArgAction::SetTrue)
+                .required(false)
+                .help("Give binary operators conventional precedence, so `1 + 2 * 3` is 7 and `-2 * 3 + 1` is -5, rather than evaluating them right to left as the Jack spec does"),
+        )
+        .arg(
+            Arg::new("out_dir")
+                .long("out-dir")
+                .value_name("DIR")
+                .value_hint(ValueHint::DirPath)
+                .help("Write the .vm, .json, .names and .vm.map files to this directory instead of next to the sources"),
+        )
+        .arg(
+            Arg::new("dry_run")
+                .long("dry-run")
+                .action(ArgAction::SetTrue)
+                .required(false)
+                .help("Report the files which would be written without writing them"),
+        )
+        .arg(
+            Arg::new("trace")
+                .long("trace")
+                .action(ArgAction::SetTrue)
+                .required(false)
+                .help("Log every pass and file processed to stderr"),
+        )
+        .arg(
+            Arg::new("timings")
+                .long("timings")
+                .action(ArgAction::SetTrue)
+                .required(false)
+                .help("Report the time spent in each pass to stderr"),
+        )
+        .arg(
+            Arg::new("watch")
+                .long("watch")
+                .action(ArgAction::SetTrue)
+                .required(false)
+                .help("Compile again whenever a .jack file of the source changes, until stopped with Ctrl-C"),
+        )
+}
+
+pub fn run(matches: &ArgMatches) -> Result<(), ErrorType> {
+    init_tracing(matches);
+
+    if let Some(code) = matches.get_one::<String>("explain") {
+        let explanation = explain(code).ok_or_else(|| ErrorType::UnknownCode(code.clone()))?;
+        println!("{}", explanation);
+        return Ok(());
+    }
+
+    // Get the file
+    let path = matches
+        .get_one::<String>("SOURCE")
+        .expect("User to provide a source file");
+
+    if matches.get_flag("watch") {
+        watch(
+            &[Path::new(path)],
+            &["jack", "json"],
+            || compile(matches, path),
+            |error| report_error(error, "--explain"),
+        );
+    }
+    compile(matches, path)
+}
+
+/// Print an error, and how to find out more about it if it has a code. `explain` is the command
+/// which explains codes, e.g. `compiler --explain`.
+pub fn report_error(error: &ErrorType, explain: &str) {
+    print_error(error);
+    if let Some(code) = error.code() {
+        println!(
+            "For more information about this error, try `{} {}`",
+            explain, code
+        );
+    }
+}
+
+fn compile(matches: &ArgMatches, path: &str) -> Result<(), ErrorType> {
+    let reports = Reports {
+        ast_json: matches.get_flag("ast_output"),
+        names: matches.get_flag("name_report"),
+        metrics: matches.get_flag("metrics"),
+        source_map: matches.get_flag("source_map"),
+        sarif: matches.get_one::<String>("sarif").map(PathBuf::from),
+    };
+    let parse_options = ParseOptions {
+        max_expression_depth: matches
+            .get_one::<usize>("max_expression_depth")
+            .copied()
+            .unwrap_or(DEFAULT_MAX_EXPRESSION_DEPTH),
+        precedence: matches.get_flag("precedence"),
+    };
+    let language = matches
+        .get_one::<Language>("lang")
+        .copied()
+        .or_else(Language::from_env)
+        .unwrap_or_default();
+    let mut lowering = Lowering::default();
+    for (operation, target) in matches
+        .get_many::<(String, String)>("lower")
+        .into_iter()
+        .flatten()
+    {
+        lowering
+            .set(operation, target)
+            .expect("Lowerings are checked when parsing arguments");
+    }
+    let options = CodegenOptions {
+        canonical_booleans: matches.get_flag("canonical_booleans"),
+        extensions: matches.get_flag("extensions"),
+        lowering,
+        strict_types: matches.get_flag("strict_types"),
+        auto_dispose: matches.get_flag("auto_dispose"),
+        optimize: matches.get_flag("optimize"),
+        check_this: matches.get_flag("check_this"),
+        check_order: matches.get_flag("check_order"),
+        eliminate_dead_code: matches.get_flag("eliminate_dead_code"),
+        deny_warnings: matches.get_flag("deny_warnings"),
+        language,
+    };
+
+    let out_dir = matches.get_one::<String>("out_dir").map(Path::new);
+
+    process_source(
+        path,
+        reports,
+        parse_options,
+        &options,
+        write_mode(matches),
+        out_dir,
+    )
+    .map_err(|error| error.localized(language))
+}
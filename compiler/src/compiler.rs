@@ -1,42 +1,362 @@
 use crate::{
     ast::{
-        BinaryOp, Class, ClassVariableVisibility, Constant, Expr, Statement, Subroutine,
-        SubroutineType, UnaryOp, AST,
+        BinaryOp, Class, ClassVariableVisibility, CompiledClass, Constant, Expr, ExprKind, ExprRef,
+        Identifier, Statement, Subroutine, SubroutineCall, SubroutineType, UnaryOp, Variable,
+        Visit, AST,
     },
-    symbol_table::SymbolTable,
+    dead_code::{dead_subroutines, reachable_statements},
+    diagnostics::Diagnostic,
+    escape::disposable_locals,
+    fold::Folder,
+    hoist::hoist_var_decls,
+    lowering::Lowering,
+    messages::Language,
+    semantics::check_calls,
+    signatures::Signatures,
+    symbol_table::{Scope, SymbolTable, SymbolTableVariable},
+    type_check::{check_types, program_subroutines},
+    uninitialized::uninitialized_locals,
+    unused::unused_variables,
+    vm_writer::VmWriter,
 };
+use rayon::prelude::*;
+use rustc_hash::{FxHashMap, FxHashSet};
+use std::borrow::Cow;
+use std::path::Path;
+use thiserror::Error;
+use tracing::{info_span, trace};
 
+/// Enough room for a typical class so the buffer rarely has to grow
+const INITIAL_CAPACITY: usize = 16 * 1024;
+
+/// Every character of a string constant is a call to String.appendChar, so longer strings bloat
+/// the program as well as the heap
+pub(crate) const LONG_STRING_LENGTH: usize = 255;
+
+#[derive(Debug, Clone)]
 pub struct CompilationOutput {
     pub source_filename: String,
-    pub vm_code: Vec<String>,
+    pub vm_code: String,
+    pub warnings: Vec<CompilationWarning>,
+    /// Every function, label and static the VM code defines
+    pub names: Vec<MangledName>,
+    /// The Jack line each VM command was compiled from
+    pub source_lines: Vec<u32>,
 }
 
-#[derive(Debug, Clone)]
+impl CompilationOutput {
+    /// The name of the .vm file for this class, e.g. `Main.vm` for `Main.jack`
+    pub fn vm_filename(&self) -> String {
+        Path::new(&self.source_filename)
+            .with_extension("vm")
+            .display()
+            .to_string()
+    }
+
+    /// A report mapping each generated name back to the Jack construct it came from. Each line
+    /// holds the name, the construct and the source location, separated by tabs.
+    pub fn name_report(&self) -> String {
+        let mut report = String::new();
+        for name in &self.names {
+            report.push_str(&format!(
+                "{}\t{}\t{}:{}\n",
+                name.symbol, name.origin, self.source_filename, name.line
+            ));
+        }
+        report
+    }
+
+    /// A map from each line of the VM code to the Jack line it was compiled from. Each line holds
+    /// the VM line and the source location, separated by a tab.
+    pub fn source_map(&self) -> String {
+        let mut map = String::new();
+        for (index, line) in self.source_lines.iter().enumerate() {
+            map.push_str(&format!(
+                "{}\t{}:{}\n",
+                index + 1,
+                self.source_filename,
+                line
+            ));
+        }
+        map
+    }
+}
+
+/// A name in the generated code and the Jack construct it was generated for
+#[derive(Debug, Clone, PartialEq)]
+pub struct MangledName {
+    /// The symbol as the assembler will see it, e.g. `Main.main` or `Main.0` for a static
+    pub symbol: String,
+    /// What the name was generated for, e.g. `while loop in Main.main`
+    pub origin: String,
+    pub line: u32,
+}
+
+#[derive(Debug, Clone, Error)]
 pub enum CompilationError {
+    #[error("Variable {var_name} has not been declared")]
     MissingVariable { var_name: String },
+    #[error("Class {class_name} has no subroutine called {subroutine_name}")]
+    MissingSubroutine {
+        class_name: String,
+        subroutine_name: String,
+    },
+    #[error("{subroutine} calls a subroutine of {class_name}, which is not a class")]
+    UnknownClass {
+        subroutine: String,
+        class_name: String,
+    },
+    #[error("{subroutine} calls {callee} with {found} arguments but it takes {expected}")]
+    WrongArgumentCount {
+        subroutine: String,
+        callee: String,
+        expected: usize,
+        found: usize,
+    },
+    #[error("{subroutine} {message}")]
+    TypeError { subroutine: String, message: String },
+    #[error("{subroutine} calls method {callee} without an object to call it on")]
+    MethodWithoutObject { subroutine: String, callee: String },
+    #[error("{subroutine} calls {callee} on an object, but it's a function or constructor rather than a method")]
+    NotAMethod { subroutine: String, callee: String },
 }
 
-struct CompilationContext {
+impl CompilationError {
+    /// The name in the source the error is about, which diagnostics point at
+    fn subject(&self) -> Option<&str> {
+        match self {
+            CompilationError::MissingVariable { var_name } => Some(var_name),
+            CompilationError::MissingSubroutine {
+                subroutine_name, ..
+            } => Some(subroutine_name),
+            CompilationError::UnknownClass { class_name, .. } => Some(class_name),
+            // The callee is qualified by its class, which the source may name through a variable
+            CompilationError::WrongArgumentCount { callee, .. }
+            | CompilationError::MethodWithoutObject { callee, .. }
+            | CompilationError::NotAMethod { callee, .. } => callee.rsplit('.').next(),
+            CompilationError::TypeError { .. } => None,
+        }
+    }
+
+    /// The stable code of the error, which `--explain` describes
+    pub fn code(&self) -> &'static str {
+        match self {
+            CompilationError::MissingVariable { .. } => "J0101",
+            CompilationError::MissingSubroutine { .. } => "J0102",
+            CompilationError::UnknownClass { .. } => "J0104",
+            CompilationError::WrongArgumentCount { .. } => "J0105",
+            CompilationError::TypeError { .. } => "J0106",
+            CompilationError::MethodWithoutObject { .. } => "J0107",
+            CompilationError::NotAMethod { .. } => "J0108",
+        }
+    }
+}
+
+/// The error code methods compiled with [`CodegenOptions::check_this`] pass to Sys.error. The OS's
+/// own codes stop at 20.
+pub const THIS_CHECK_ERROR: i32 = 21;
+
+/// Choices about the shape of the generated code and the checks made while compiling it
+#[derive(Debug, Clone, Default)]
+pub struct CodegenOptions {
+    /// Compile `true` as `push constant 0 / not` like the reference compiler, rather than
+    /// `push constant 1 / neg`
+    pub canonical_booleans: bool,
+    /// Accept language extensions: `var` declarations scoped to if and while bodies. Without
+    /// them, such declarations belong to the whole subroutine.
+    pub extensions: bool,
+    /// Where multiplication, division, allocation and string constants are sent
+    pub lowering: Lowering,
+    /// Reject programs which mix up types, e.g. assigning a boolean to an int
+    pub strict_types: bool,
+    /// Experimental: free the objects of locals which never escape their subroutine when it
+    /// returns
+    pub auto_dispose: bool,
+    /// Fold constant expressions and remove branches which can never run
+    pub optimize: bool,
+    /// Debugging: check on entering each method that `this` could point to an object, calling
+    /// Sys.error with [`THIS_CHECK_ERROR`] if it can't
+    pub check_this: bool,
+    /// Compile the classes again in reverse order and fail if the output changes, to catch code
+    /// which depends on the order the files were found in
+    pub check_order: bool,
+    /// Leave out statements which follow a return, and subroutines which can't be reached from
+    /// Main.main or Sys.init, warning about each
+    pub eliminate_dead_code: bool,
+    /// Fail rather than write any output if there are warnings
+    pub deny_warnings: bool,
+    /// The language warnings are printed in
+    pub language: Language,
+}
+
+/// A compilation error along with where it was found
+#[derive(Debug)]
+pub struct SubroutineError<'a> {
+    pub subroutine: &'a Subroutine,
+    /// The line of the statement which caused the error, or 0 if it isn't known
+    pub line: u32,
+    pub error: CompilationError,
+}
+
+/// A compilation error pointing at the code which caused it
+#[derive(Debug, Clone, Error)]
+#[error("{diagnostic}")]
+pub struct LocatedCompilationError {
+    #[source]
+    pub error: CompilationError,
+    pub diagnostic: Box<Diagnostic>,
+}
+
+/// Problems which don't stop compilation but probably mean the program is wrong
+#[derive(Debug, Clone, PartialEq, Error)]
+pub enum CompilationWarning {
+    #[error("{subroutine}: the result of {callee} is used but it returns void")]
+    VoidResultUsed { subroutine: String, callee: String },
+    #[error("{subroutine}: a string constant is {length} characters long, more than {LONG_STRING_LENGTH}")]
+    LongString { subroutine: String, length: usize },
+    #[error("{method}: field {field} is read but no constructor assigns it")]
+    UninitializedField { method: String, field: String },
+    #[error("{scope}: {kind} {name} is never read")]
+    UnusedVariable {
+        /// The subroutine, or the class for a field or static
+        scope: String,
+        kind: &'static str,
+        name: String,
+        line: u32,
+    },
+    #[error("{subroutine}: the statements from line {line} follow a return and can never run")]
+    UnreachableCode { subroutine: String, line: u32 },
+    #[error("{subroutine} is never called from Main.main or Sys.init")]
+    UnusedSubroutine { subroutine: String, line: u32 },
+    #[error("{subroutine}: local {name} may be read before it's assigned")]
+    UninitializedLocal {
+        subroutine: String,
+        name: String,
+        line: u32,
+    },
+}
+
+impl CompilationWarning {
+    /// The stable code of the warning, which `--explain` describes
+    pub fn code(&self) -> &'static str {
+        match self {
+            CompilationWarning::VoidResultUsed { .. } => "J0201",
+            CompilationWarning::LongString { .. } => "J0202",
+            CompilationWarning::UninitializedField { .. } => "J0203",
+            CompilationWarning::UnusedVariable { .. } => "J0204",
+            CompilationWarning::UnreachableCode { .. } => "J0205",
+            CompilationWarning::UnusedSubroutine { .. } => "J0206",
+            CompilationWarning::UninitializedLocal { .. } => "J0207",
+        }
+    }
+
+    /// The line of the source file the warning is about, if it's about a single line
+    pub fn line(&self) -> Option<u32> {
+        match self {
+            CompilationWarning::UnusedVariable { line, .. }
+            | CompilationWarning::UnreachableCode { line, .. }
+            | CompilationWarning::UnusedSubroutine { line, .. }
+            | CompilationWarning::UninitializedLocal { line, .. }
+                if *line > 0 =>
+            {
+                Some(*line)
+            }
+            _ => None,
+        }
+    }
+}
+
+struct CompilationContext<'a> {
+    class: &'a Class,
+    signatures: &'a Signatures,
+    options: &'a CodegenOptions,
+    warnings: Vec<CompilationWarning>,
+    names: Vec<MangledName>,
     symbol_table: SymbolTable,
-    class_name: String,
-    subroutine_name: String,
+    class_name: Identifier,
+    subroutine_name: Identifier,
+    subroutine_type: SubroutineType,
+    /// Each field read by a method, with the first method to read it
+    fields_read: FxHashMap<Identifier, Identifier>,
+    /// Fields assigned by a constructor
+    fields_assigned: FxHashSet<Identifier>,
+    /// The locals to free before each return, with the subroutine which frees them
+    disposals: Vec<(i32, String)>,
     while_count: i32,
     if_count: i32,
+    dispose_count: i32,
+    /// The line of the statement being compiled
+    line: u32,
 }
 
-impl CompilationContext {
-    pub fn new(class_name: &str) -> Self {
+impl<'a> CompilationContext<'a> {
+    pub fn new(class: &'a Class, signatures: &'a Signatures, options: &'a CodegenOptions) -> Self {
         Self {
+            class,
+            signatures,
+            options,
+            warnings: Vec::new(),
+            names: Vec::new(),
             symbol_table: SymbolTable::new(),
-            class_name: class_name.to_owned(),
+            class_name: class.get_name().clone(),
             if_count: 0,
             while_count: 0,
-            subroutine_name: "".to_owned(),
+            dispose_count: 0,
+            disposals: Vec::new(),
+            subroutine_name: Identifier::default(),
+            subroutine_type: SubroutineType::default(),
+            fields_read: FxHashMap::default(),
+            fields_assigned: FxHashSet::default(),
+            line: 0,
         }
     }
 
-    pub fn set_subroutine_name(&mut self, name: &str) {
-        self.subroutine_name = name.to_owned();
+    /// Start compiling a subroutine. Label counters restart, as labels are scoped to it, so a
+    /// change to one subroutine doesn't renumber the labels of the rest of the class.
+    pub fn set_subroutine(&mut self, subroutine: &Subroutine) {
+        self.subroutine_name = subroutine.get_name().clone();
+        self.subroutine_type = subroutine.get_subroutine_type();
+        self.while_count = 0;
+        self.if_count = 0;
+        self.dispose_count = 0;
+        self.line = 0;
+    }
+
+    /// Note a variable being read, to check that the fields methods use are initialized
+    fn record_read(&mut self, variable: &SymbolTableVariable) {
+        if variable.scope() == Scope::Field
+            && self.subroutine_type == SubroutineType::Method
+            && !self.fields_read.contains_key(variable.name())
+        {
+            self.fields_read
+                .insert(variable.name().clone(), self.subroutine_name.clone());
+        }
+    }
+
+    /// Note a variable being assigned
+    fn record_assignment(&mut self, variable: &SymbolTableVariable) {
+        if variable.scope() == Scope::Field && self.subroutine_type == SubroutineType::Constructor {
+            self.fields_assigned.insert(variable.name().clone());
+        }
+    }
+
+    /// Warn about fields which methods read but no constructor assigns. Memory.alloc doesn't zero
+    /// the memory it returns, so they would start out holding garbage.
+    fn check_field_initialization(&mut self) {
+        for variable in self.class.variables() {
+            let field = variable.get_identifier();
+            if matches!(variable.get_visibility(), ClassVariableVisibility::Static)
+                || self.fields_assigned.contains(field)
+            {
+                continue;
+            }
+            if let Some(method) = self.fields_read.get(field) {
+                self.warnings.push(CompilationWarning::UninitializedField {
+                    method: format!("{}.{}", self.class_name, method),
+                    field: field.to_string(),
+                });
+            }
+        }
     }
 
     pub fn symbol_table(&mut self) -> &mut SymbolTable {
@@ -46,72 +366,287 @@ impl CompilationContext {
     /// Create a label for a while loop & increment the counter.
     ///
     /// A label will look like: main.while.0
-    pub fn next_while_label(&mut self) -> String {
+    pub fn next_while_label(&mut self, line: u32) -> String {
         // main.while.0.condition
         let while_label = format!("{}.while.{}", self.subroutine_name, self.while_count);
         self.while_count += 1;
+        for suffix in ["condition", "while_body", "while_end"] {
+            self.record_name(format!("{}.{}", while_label, suffix), "while loop", line);
+        }
         while_label
     }
 
     /// Create a label for a if statement & increment the counter.
     ///
     /// A label will look like: main.if.0
-    pub fn next_if_label(&mut self) -> String {
+    pub fn next_if_label(&mut self, line: u32) -> String {
         let if_label = format!("{}.if.{}", self.subroutine_name, self.if_count);
         self.if_count += 1;
+        for suffix in ["if_body", "if_end"] {
+            self.record_name(format!("{}.{}", if_label, suffix), "if statement", line);
+        }
         if_label
     }
+
+    /// Create a label for freeing a local before a return & increment the counter.
+    ///
+    /// A label will look like: main.dispose.0
+    pub fn next_dispose_label(&mut self, line: u32) -> String {
+        let dispose_label = format!("{}.dispose.{}", self.subroutine_name, self.dispose_count);
+        self.dispose_count += 1;
+        for suffix in ["free", "end"] {
+            self.record_name(
+                format!("{}.{}", dispose_label, suffix),
+                "automatic dispose",
+                line,
+            );
+        }
+        dispose_label
+    }
+
+    /// Note a label generated for a construct of the current subroutine. The VM translator
+    /// scopes labels to their function.
+    fn record_name(&mut self, label: String, construct: &str, line: u32) {
+        let function = format!("{}.{}", self.class_name, self.subroutine_name);
+        self.names.push(MangledName {
+            symbol: format!("{}${}", function, label),
+            origin: format!("{} in {}", construct, function),
+            line,
+        });
+    }
+}
+
+pub fn translate_ast(
+    ast: &AST,
+    options: &CodegenOptions,
+) -> Result<Vec<CompilationOutput>, LocatedCompilationError> {
+    let classes: Vec<&CompiledClass> = ast.classes.iter().collect();
+    translate_classes(&classes, &ast.siblings, options)
 }
 
-pub fn translate_ast(ast: &AST) -> Result<Vec<CompilationOutput>, CompilationError> {
-    let mut output = Vec::with_capacity(ast.classes.len());
+/// Compile the classes of a program, given in the order their files were found. Calls to
+/// `siblings` are checked against them, but they aren't compiled.
+pub fn translate_classes(
+    classes: &[&CompiledClass],
+    siblings: &[Class],
+    options: &CodegenOptions,
+) -> Result<Vec<CompilationOutput>, LocatedCompilationError> {
+    run_passes(classes, siblings, options, Passes::All)
+}
+
+/// Which passes over a program to run
+#[derive(Clone, Copy, PartialEq)]
+pub(crate) enum Passes {
+    All,
+    /// The call checks, and the type checks with strict types, without generating any code
+    Check,
+    /// Generate code for a program which has already been checked
+    Codegen,
+}
 
-    for compiled_class in &ast.classes {
-        let vm_code = compile_class(&compiled_class.class)?;
-        output.push(CompilationOutput {
-            source_filename: compiled_class.source_filename.clone(),
-            vm_code,
+pub(crate) fn run_passes(
+    classes: &[&CompiledClass],
+    siblings: &[Class],
+    options: &CodegenOptions,
+    passes: Passes,
+) -> Result<Vec<CompilationOutput>, LocatedCompilationError> {
+    // Without extensions, a var declared in an if or while body belongs to the whole subroutine
+    let hoisted: Vec<Option<CompiledClass>> = classes
+        .iter()
+        .map(|compiled| {
+            if options.extensions {
+                return None;
+            }
+            let class = hoist_var_decls(&compiled.class)?;
+            Some(CompiledClass {
+                class,
+                source_filename: compiled.source_filename.clone(),
+                source: compiled.source.clone(),
+            })
         })
+        .collect();
+    let classes: Vec<&CompiledClass> = classes
+        .iter()
+        .zip(&hoisted)
+        .map(|(compiled, hoisted)| hoisted.as_ref().unwrap_or(compiled))
+        .collect();
+    let classes = &classes[..];
+
+    let program = || {
+        classes
+            .iter()
+            .map(|compiled| &compiled.class)
+            .chain(siblings)
+    };
+    let signatures = Signatures::new(program());
+
+    // Types are checked first as they explain a call on a non-object better than the call check
+    if options.strict_types && passes != Passes::Codegen {
+        let program = program_subroutines(program());
+        for compiled_class in classes {
+            check_types(&compiled_class.class, &program, &signatures)
+                .map_err(|error| locate_error(compiled_class, error))?;
+        }
+    }
+
+    // Check calls across the whole program before codegen, so a typo is reported rather than left for
+    // the emulator to find
+    if passes != Passes::Codegen {
+        for compiled_class in classes {
+            check_calls(&compiled_class.class, &signatures)
+                .map_err(|error| locate_error(compiled_class, error))?;
+        }
+    }
+    if passes == Passes::Check {
+        return Ok(Vec::new());
+    }
+
+    let dead_subroutines = if options.eliminate_dead_code {
+        dead_subroutines(classes.iter().map(|compiled| &compiled.class), options)
+    } else {
+        FxHashSet::default()
+    };
+
+    // Classes are compiled independently. Results are collected in source order so the output,
+    // and which error gets reported, doesn't depend on scheduling.
+    let results: Vec<_> = classes
+        .par_iter()
+        .map(|compiled_class| {
+            let _span = info_span!("codegen", file = %compiled_class.source_filename).entered();
+            compile_class(
+                &compiled_class.class,
+                &signatures,
+                options,
+                &dead_subroutines,
+            )
+            .map(|code| CompilationOutput {
+                source_filename: compiled_class.source_filename.clone(),
+                vm_code: code.vm_code,
+                warnings: code.warnings,
+                names: code.names,
+                source_lines: code.source_lines,
+            })
+            .map_err(|error| locate_error(compiled_class, error))
+        })
+        .collect();
+
+    results.into_iter().collect()
+}
+
+/// Point an error at the name it's about in the statement which caused it, or at the declaration
+/// of its subroutine if the statement isn't known
+fn locate_error(compiled_class: &CompiledClass, found: SubroutineError) -> LocatedCompilationError {
+    let SubroutineError {
+        subroutine,
+        line,
+        error,
+    } = found;
+    let message = format!(
+        "failed to compile {}.{}",
+        compiled_class.class.get_name(),
+        subroutine.get_name()
+    );
+    let (file, source) = (&compiled_class.source_filename, &compiled_class.source);
+    let diagnostic = match error.subject() {
+        Some(subject) if line > 0 => Diagnostic::at_word(file, source, line, subject, message),
+        _ if line > 0 => Diagnostic::at_line(file, source, line, message),
+        _ => Diagnostic::at_line(file, source, subroutine.get_line(), message),
+    };
+    LocatedCompilationError {
+        diagnostic: Box::new(diagnostic.with_code(error.code())),
+        error,
     }
+}
 
-    Ok(output)
+/// The VM code of a class, along with what else compiling it produced
+pub struct ClassCode {
+    pub vm_code: String,
+    /// The Jack line of each VM command
+    pub source_lines: Vec<u32>,
+    pub warnings: Vec<CompilationWarning>,
+    pub names: Vec<MangledName>,
 }
 
-pub fn compile_class(class: &Class) -> Result<Vec<String>, CompilationError> {
-    let mut output = Vec::new();
+/// Compile a class, leaving out the subroutines in `dead_subroutines`. Errors come with the
+/// subroutine which caused them.
+pub fn compile_class<'a>(
+    class: &'a Class,
+    signatures: &Signatures,
+    options: &CodegenOptions,
+    dead_subroutines: &FxHashSet<String>,
+) -> Result<ClassCode, SubroutineError<'a>> {
+    let mut output = VmWriter::with_capacity(INITIAL_CAPACITY);
 
-    let mut context = CompilationContext::new(class.get_name());
+    let mut context = CompilationContext::new(class, signatures, options);
 
     // Find all the local variables
     for variable in class.variables() {
         match variable.get_visibility() {
             ClassVariableVisibility::Field => {
                 context.symbol_table().add_field(
-                    &variable.get_identifier(),
-                    &variable.get_var_type().to_string(),
+                    variable.get_identifier(),
+                    variable.get_var_type().type_name(),
                 );
             }
             ClassVariableVisibility::Static => {
                 context.symbol_table().add_static(
-                    &variable.get_identifier(),
-                    &variable.get_var_type().to_string(),
+                    variable.get_identifier(),
+                    variable.get_var_type().type_name(),
                 );
+                let index = context
+                    .symbol_table()
+                    .find_variable(variable.get_identifier())
+                    .expect("The static was just added")
+                    .index();
+                context.names.push(MangledName {
+                    symbol: format!("{}.{}", context.class_name, index),
+                    origin: format!(
+                        "static {}.{}",
+                        context.class_name,
+                        variable.get_identifier()
+                    ),
+                    line: variable.get_line(),
+                });
             }
         }
     }
 
     for subroutine in class.subroutines() {
+        let name = format!("{}.{}", class.get_name(), subroutine.get_name());
+        if dead_subroutines.contains(&name) {
+            context.warnings.push(CompilationWarning::UnusedSubroutine {
+                subroutine: name,
+                line: subroutine.get_line(),
+            });
+            continue;
+        }
+        trace!(subroutine = %subroutine.get_name(), "compiling subroutine");
         context.symbol_table().create_scope();
-        context.set_subroutine_name(subroutine.get_name());
-        compile_subroutines(&mut output, subroutine, &mut context)?;
+        context.set_subroutine(subroutine);
+        compile_subroutines(&mut output, subroutine, &mut context).map_err(|error| {
+            SubroutineError {
+                subroutine,
+                line: context.line,
+                error,
+            }
+        })?;
         context.symbol_table().pop_scope();
     }
+    context.check_field_initialization();
+    context.warnings.extend(unused_variables(class));
+    context.warnings.extend(uninitialized_locals(class));
 
-    Ok(output)
+    let (vm_code, source_lines) = output.finish();
+    Ok(ClassCode {
+        vm_code,
+        source_lines,
+        warnings: context.warnings,
+        names: context.names,
+    })
 }
 
 fn compile_subroutines(
-    output: &mut Vec<String>,
+    output: &mut VmWriter,
     subroutine: &Subroutine,
     context: &mut CompilationContext,
 ) -> Result<(), CompilationError> {
@@ -122,55 +657,138 @@ fn compile_subroutines(
 
     // create the symbol table for the subroutine
     for parameter in subroutine.get_parameters() {
-        context.symbol_table().add_argument(
-            parameter.get_identifier(),
-            &format!("{}", parameter.get_type().to_string()),
-        );
+        context
+            .symbol_table()
+            .add_argument(parameter.get_identifier(), parameter.get_type().type_name());
     }
 
-    // Find all the var declarations
+    // Find all the var declarations. Those nested in if and while bodies are added to the symbol
+    // table when their block is compiled, but still need a slot in the function's locals.
     for s in subroutine.get_statements() {
-        find_var_decl_in_statement_tree(s, context.symbol_table());
+        if let Statement::VarDecl(var_details) = s {
+            for var in var_details.get_variables() {
+                add_local_variable(var, context.symbol_table());
+            }
+        }
+    }
+    let nested_locals: usize = subroutine
+        .get_statements()
+        .iter()
+        .map(count_nested_locals)
+        .sum();
+
+    let num_args = context.symbol_table().count_locals() + nested_locals as i32;
+
+    context.disposals.clear();
+    if context.options.auto_dispose {
+        for local in disposable_locals(subroutine) {
+            let index = context
+                .symbol_table()
+                .find_variable(&local.name)
+                .expect("Disposable locals are declared at the top of the subroutine")
+                .index();
+            // Classes without a dispose method are handed straight back to the heap
+            let dispose = format!("{}.dispose", local.class);
+            let free = if context.signatures.get(&dispose).is_some() {
+                dispose
+            } else {
+                "Memory.deAlloc".to_owned()
+            };
+            context.disposals.push((index, free));
+        }
     }
 
-    let num_args = context.symbol_table().count_locals();
+    let kind = match subroutine.get_subroutine_type() {
+        SubroutineType::Function => "function",
+        SubroutineType::Constructor => "constructor",
+        SubroutineType::Method => "method",
+    };
+    context.names.push(MangledName {
+        symbol: format!("{}.{}", context.class_name, subroutine.get_name()),
+        origin: format!("{} {}.{}", kind, context.class_name, subroutine.get_name()),
+        line: subroutine.get_line(),
+    });
 
-    output.push(format!(
-        "function {}.{} {}",
-        context.class_name,
-        subroutine.get_name(),
-        num_args
-    ));
+    output.set_source_line(subroutine.get_line());
+    output.function(
+        format_args!("{}.{}", context.class_name, subroutine.get_name()),
+        num_args,
+    );
 
     match subroutine.get_subroutine_type() {
         SubroutineType::Constructor => {
             // Count the number of class fields
-            output.push(format!(
-                "push constant {}",
-                context.symbol_table().count_fields()
-            ));
-            output.push("call Memory.alloc 1".to_owned());
-            output.push("pop pointer 0".to_owned());
+            output.push("constant", context.symbol_table().count_fields());
+            output.call(&context.options.lowering.alloc, 1);
+            output.pop("pointer", 0);
         }
         SubroutineType::Method => {
-            output.push("push argument 0".to_owned());
-            output.push("pop pointer 0".to_owned());
+            if context.options.check_this {
+                compile_this_check(output, context, subroutine.get_line());
+            }
+            output.push("argument", 0);
+            output.pop("pointer", 0);
         }
         _ => {}
     }
 
-    for statement in subroutine.get_statements() {
+    let mut statements = if context.options.optimize {
+        Cow::Owned(
+            Folder::new(&context.options.lowering).fold_statements(subroutine.get_statements()),
+        )
+    } else {
+        Cow::Borrowed(subroutine.get_statements().as_slice())
+    };
+    // After folding, which can leave a return in the middle of a block
+    if context.options.eliminate_dead_code {
+        let mut removed = Vec::new();
+        statements = Cow::Owned(reachable_statements(&statements, &mut removed));
+        for line in removed {
+            context.warnings.push(CompilationWarning::UnreachableCode {
+                subroutine: format!("{}.{}", context.class_name, subroutine.get_name()),
+                line,
+            });
+        }
+    }
+    for statement in statements.iter() {
         compile_statement(output, statement, context)?;
     }
 
     Ok(())
 }
 
+/// Stop in Sys.error unless argument 0 is a plausible object: not null, and below the screen.
+/// Calling a method through its class leaves argument 0 holding the first real argument instead.
+fn compile_this_check(output: &mut VmWriter, context: &mut CompilationContext, line: u32) {
+    // main.this_ok
+    let this_ok = format!("{}.this_ok", context.subroutine_name);
+    context.record_name(this_ok.clone(), "this check", line);
+
+    // 0 < argument 0 < 16384
+    output.push("argument", 0);
+    output.push("constant", 0);
+    output.arithmetic("gt");
+    output.push("argument", 0);
+    output.push("constant", 16384);
+    output.arithmetic("lt");
+    output.arithmetic("and");
+    output.if_goto(&this_ok);
+
+    output.push("constant", THIS_CHECK_ERROR);
+    output.call("Sys.error", 1);
+    output.pop("temp", 0);
+    output.label(&this_ok);
+}
+
 fn compile_statement(
-    output: &mut Vec<String>,
+    output: &mut VmWriter,
     statement: &Statement,
     context: &mut CompilationContext,
 ) -> Result<(), CompilationError> {
+    if !matches!(statement, Statement::VarDecl(_)) {
+        output.set_source_line(statement.line());
+        context.line = statement.line();
+    }
     match statement {
         Statement::Let(details) => {
             // Find the correct variable
@@ -178,23 +796,25 @@ fn compile_statement(
                 .symbol_table()
                 .find_variable(details.identifier.get_name())
                 .ok_or(CompilationError::MissingVariable {
-                    var_name: details.identifier.get_name().to_owned(),
+                    var_name: details.identifier.get_name().to_string(),
                 })?;
 
-            let scope = match variable.scope() {
-                crate::symbol_table::Scope::Field => "this",
-                crate::symbol_table::Scope::Static => "static",
-                crate::symbol_table::Scope::Argument => "argument",
-                crate::symbol_table::Scope::Local => "local",
-            };
+            let scope = variable.scope().as_segment();
 
             let variable_index = variable.index();
 
+            // Storing into an array reads the variable holding its address
+            if details.identifier.get_index().is_some() {
+                context.record_read(&variable);
+            } else {
+                context.record_assignment(&variable);
+            }
+
             // Prepare to store in an Array if appropriate
             if let Some(index) = details.identifier.get_index() {
-                output.push(format!("push {} {}", scope, variable_index));
+                output.push(scope, variable_index);
                 compile_expression(output, index, context)?;
-                output.push("add".to_owned());
+                output.arithmetic("add");
             }
 
             // Put the expression into the stack
@@ -202,119 +822,85 @@ fn compile_statement(
 
             // If an array we need to store the expression result to setup the array access
             if details.identifier.get_index().is_some() {
-                output.push("pop temp 0".to_owned());
-                output.push("pop pointer 1".to_owned());
-                output.push("push temp 0".to_owned());
-                output.push("pop that 0".to_owned());
+                output.pop("temp", 0);
+                output.pop("pointer", 1);
+                output.push("temp", 0);
+                output.pop("that", 0);
             } else {
-                output.push(format!("pop {} {}", scope, variable_index));
+                output.pop(scope, variable_index);
             }
         }
         Statement::While(details) => {
             // Create a name for the while for labels
-            let while_label = context.next_while_label();
+            let while_label = context.next_while_label(details.line);
 
             // Label condition
-            output.push(format!("label {}.condition", while_label));
+            output.label(format_args!("{}.condition", while_label));
 
             // Condition
             compile_expression(output, details.get_condition(), context)?;
 
             // if-goto while_body
-            output.push(format!("if-goto {}.while_body", while_label));
+            output.if_goto(format_args!("{}.while_body", while_label));
 
             // goto while_end
-            output.push(format!("goto {}.while_end", while_label));
+            output.goto(format_args!("{}.while_end", while_label));
 
             // label while_body
-            output.push(format!("label {}.while_body", while_label));
+            output.label(format_args!("{}.while_body", while_label));
 
             // statements
-            for s in &details.body {
-                compile_statement(output, s, context)?;
-            }
+            compile_block(output, &details.body, context)?;
+            output.set_source_line(details.line);
 
             // goto condition
-            output.push(format!("goto {}.condition", while_label));
+            output.goto(format_args!("{}.condition", while_label));
 
             // label while_end
-            output.push(format!("label {}.while_end", while_label));
+            output.label(format_args!("{}.while_end", while_label));
         }
         Statement::Do(call) => {
-            let mut param_count = call.get_parameters().len();
-            let mut call_text = call.name_as_string();
-
-            // Check if the subroutine call is a method call or a function call
-            // main.draw() <- if main is variable then this is method call otherwise it's a function call
-            // draw() <- must be method call
-            match call.get_target() {
-                Some(target_name) => match context.symbol_table().find_variable(&target_name) {
-                    Some(variable) => {
-                        output.push(format!(
-                            "push {} {}",
-                            variable.scope().as_segment(),
-                            variable.index()
-                        ));
-
-                        param_count += 1;
-                        call_text = format!("{}.{}", variable.var_type(), call.get_name());
-                    }
-                    None => {}
-                },
-                None => {
-                    output.push("push pointer 0".to_owned());
-                    param_count += 1;
-                    call_text = format!("{}.{}", context.class_name, call.get_name());
-                }
-            };
-
-            for parameter in call.get_parameters() {
-                compile_expression(output, parameter, context)?;
-            }
-
-            output.push(format!("call {} {}", call_text, param_count,));
+            compile_call(output, call, context)?;
 
             // We aren't doing anything with the response so pop it
-            output.push("pop temp 0".to_owned());
+            output.pop("temp", 0);
         }
         Statement::If(details) => {
             // Get a label for the if statement
-            let if_label = context.next_if_label();
+            let if_label = context.next_if_label(details.line);
 
             // push constant 1
             // neg
             compile_expression(output, details.get_condition(), context)?;
 
             // if-goto main.if.0.if_body
-            output.push(format!("if-goto {}.if_body", if_label));
+            output.if_goto(format_args!("{}.if_body", if_label));
 
             if let Some(else_body) = details.get_else_body() {
-                for s in else_body {
-                    compile_statement(output, s, context)?;
-                }
+                compile_block(output, else_body, context)?;
+                output.set_source_line(details.line);
             }
 
             //     goto main.if.0.if_end
-            output.push(format!("goto {}.if_end", if_label));
+            output.goto(format_args!("{}.if_end", if_label));
 
             // label main.if.0.if_body
-            output.push(format!("label {}.if_body", if_label));
+            output.label(format_args!("{}.if_body", if_label));
 
-            for s in details.get_if_body() {
-                compile_statement(output, s, context)?;
-            }
+            compile_block(output, details.get_if_body(), context)?;
+            output.set_source_line(details.line);
 
             // label main.if.0.if_end
-            output.push(format!("label {}.if_end", if_label));
+            output.label(format_args!("{}.if_end", if_label));
         }
-        Statement::Return(return_statement) => {
-            if let Some(expr) = return_statement {
+        Statement::Return(details) => {
+            if let Some(expr) = details.get_value() {
                 compile_expression(output, expr, context)?;
-                output.push("return".to_owned());
             } else {
-                output.push("push constant 0".to_owned());
-                output.push("return".to_owned());
+                output.push("constant", 0);
             }
+            compile_disposals(output, context, details.line);
+            output.ret();
         }
         Statement::VarDecl(_) => {}
     }
@@ -323,142 +909,211 @@ fn compile_statement(
 }
 
 fn compile_expression(
-    output: &mut Vec<String>,
+    output: &mut VmWriter,
     expr: &Expr,
     context: &mut CompilationContext,
 ) -> Result<(), CompilationError> {
-    match expr {
-        Expr::Constant(Constant::Int(num_val)) => output.push(format!("push constant {}", num_val)),
-        Expr::Constant(Constant::String(text)) => {
-            output.push(format!("push constant {}", text.len()));
-            output.push("call String.new 1".to_owned());
+    compile_expression_node(output, expr.root(), context)
+}
+
+fn compile_expression_node(
+    output: &mut VmWriter,
+    expr: ExprRef,
+    context: &mut CompilationContext,
+) -> Result<(), CompilationError> {
+    match expr.kind() {
+        ExprKind::Constant(Constant::Int(num_val)) => output.push("constant", num_val),
+        ExprKind::Constant(Constant::String(text)) => {
+            if text.len() > LONG_STRING_LENGTH {
+                context.warnings.push(CompilationWarning::LongString {
+                    subroutine: format!("{}.{}", context.class_name, context.subroutine_name),
+                    length: text.len(),
+                });
+            }
+            output.push("constant", text.len());
+            output.call(&context.options.lowering.string_new, 1);
             for char in text.chars() {
-                output.push(format!("push constant {}", char as u8));
-                output.push("call String.appendChar 2".to_owned());
+                output.push("constant", char as u8);
+                output.call(&context.options.lowering.append_char, 2);
             }
         }
-        Expr::Constant(Constant::Keyword(keyword)) => match keyword {
+        ExprKind::Constant(Constant::Keyword(keyword)) => match keyword {
+            crate::ast::KeywordConstant::True if context.options.canonical_booleans => {
+                output.push("constant", 0);
+                output.arithmetic("not");
+            }
             crate::ast::KeywordConstant::True => {
-                output.push("push constant 1".to_owned());
-                output.push("neg".to_owned());
+                output.push("constant", 1);
+                output.arithmetic("neg");
             }
-            crate::ast::KeywordConstant::False => output.push("push constant 0".to_owned()),
-            crate::ast::KeywordConstant::Null => output.push("push constant 0".to_owned()),
-            crate::ast::KeywordConstant::This => output.push("push pointer 0".to_owned()),
+            crate::ast::KeywordConstant::False => output.push("constant", 0),
+            crate::ast::KeywordConstant::Null => output.push("constant", 0),
+            crate::ast::KeywordConstant::This => output.push("pointer", 0),
         },
-        Expr::VarRef(var) => {
+        ExprKind::VarRef(var) => {
             let variable = context.symbol_table().find_variable(var.get_name()).ok_or(
                 CompilationError::MissingVariable {
-                    var_name: var.get_name().to_owned(),
+                    var_name: var.get_name().to_string(),
                 },
             )?;
+            context.record_read(&variable);
 
-            let scope = match variable.scope() {
-                crate::symbol_table::Scope::Field => "this",
-                crate::symbol_table::Scope::Static => "static",
-                crate::symbol_table::Scope::Argument => "argument",
-                crate::symbol_table::Scope::Local => "local",
-            };
+            let scope = variable.scope().as_segment();
 
             let variable_index = variable.index();
 
             if let Some(index) = var.get_index() {
-                output.push(format!("push {} {}", scope, variable_index));
+                output.push(scope, variable_index);
                 compile_expression(output, index, context)?;
-                output.push("add".to_owned());
-                output.push("pop pointer 1".to_owned());
-                output.push("push that 0".to_owned());
+                output.arithmetic("add");
+                output.pop("pointer", 1);
+                output.push("that", 0);
             } else {
-                output.push(format!("push {} {}", scope, variable_index));
+                output.push(scope, variable_index);
             }
         }
-        Expr::UnaryExpr(op, expr) => {
-            compile_expression(output, expr, context)?;
+        ExprKind::UnaryExpr(op, expr) => {
+            compile_expression_node(output, expr, context)?;
             let operator = match op {
                 UnaryOp::Minus => "neg",
                 UnaryOp::Not => "not",
             };
-            output.push(format!("{}", operator));
-        }
-        Expr::BinaryExpr { lhs, op, rhs } => {
-            compile_expression(output, lhs, context)?;
-            compile_expression(output, rhs, context)?;
-            match op {
-                BinaryOp::Plus => output.push("add".to_owned()),
-                BinaryOp::Minus => output.push("sub".to_owned()),
-                BinaryOp::Mult => output.push("call Math.multiply 2".to_owned()),
-                BinaryOp::Div => output.push("call Math.divide 2".to_owned()),
-                BinaryOp::And => output.push("and".to_owned()),
-                BinaryOp::Or => output.push("or".to_owned()),
-                BinaryOp::Lt => output.push("lt".to_owned()),
-                BinaryOp::Gt => output.push("gt".to_owned()),
-                BinaryOp::Eq => output.push("eq".to_owned()),
-            }
+            output.arithmetic(operator);
         }
-        Expr::BracketedExpr(expr) => compile_expression(output, expr, context)?,
-        Expr::Call(call) => {
-            let mut param_count = call.get_parameters().len();
-            let mut call_text = call.name_as_string();
-
-            // If the call is a method then we need to push this
-            match call.get_target() {
-                Some(target_name) => match context.symbol_table().find_variable(&target_name) {
-                    Some(variable) => {
-                        output.push(format!(
-                            "push {} {}",
-                            variable.scope().as_segment(),
-                            variable.index()
-                        ));
-                        param_count += 1;
-                        call_text = format!("{}.{}", variable.var_type(), call.get_name());
-                    }
-                    None => {}
-                },
-                None => {
-                    output.push("push pointer 0".to_owned());
-                    param_count += 1;
-                    call_text = format!("{}.{}", context.class_name, call.get_name());
+        ExprKind::BinaryExpr { .. } => {
+            for visit in expr.postfix() {
+                match visit {
+                    Visit::Operand(operand) => compile_expression_node(output, operand, context)?,
+                    Visit::Operator(op) => match op {
+                        BinaryOp::Plus => output.arithmetic("add"),
+                        BinaryOp::Minus => output.arithmetic("sub"),
+                        BinaryOp::Mult => output.call(&context.options.lowering.multiply, 2),
+                        BinaryOp::Div => output.call(&context.options.lowering.divide, 2),
+                        BinaryOp::And => output.arithmetic("and"),
+                        BinaryOp::Or => output.arithmetic("or"),
+                        BinaryOp::Lt => output.arithmetic("lt"),
+                        BinaryOp::Gt => output.arithmetic("gt"),
+                        BinaryOp::Eq => output.arithmetic("eq"),
+                    },
                 }
-            };
-
-            for parameter in call.get_parameters() {
-                compile_expression(output, parameter, context)?;
             }
-
-            output.push(format!("call {} {}", call_text, param_count));
+        }
+        ExprKind::BracketedExpr(expr) => compile_expression_node(output, expr, context)?,
+        ExprKind::Call(call) => {
+            let callee = compile_call(output, call, context)?;
+            if context
+                .signatures
+                .get(&callee)
+                .is_some_and(|signature| signature.returns_void)
+            {
+                context.warnings.push(CompilationWarning::VoidResultUsed {
+                    subroutine: format!("{}.{}", context.class_name, context.subroutine_name),
+                    callee,
+                });
+            }
         }
     }
 
     Ok(())
 }
 
-fn find_var_decl_in_statement_tree(statement: &Statement, symbol_table: &mut SymbolTable) {
-    match statement {
-        Statement::Let(_) => {}
-        Statement::While(details) => {
-            for body_statement in &details.body {
-                find_var_decl_in_statement_tree(&body_statement, symbol_table);
-            }
-        }
-        Statement::Do(_) => {}
-        Statement::If(if_details) => {
-            for s in &if_details.if_body {
-                find_var_decl_in_statement_tree(s, symbol_table);
-            }
-            if let Some(else_body) = &if_details.else_body {
-                for s in else_body {
-                    find_var_decl_in_statement_tree(s, symbol_table);
-                }
+/// Compile a subroutine call, returning the full name of the subroutine called
+fn compile_call(
+    output: &mut VmWriter,
+    call: &SubroutineCall,
+    context: &mut CompilationContext,
+) -> Result<String, CompilationError> {
+    let mut param_count = call.get_parameters().len();
+
+    // Check if the subroutine call is a method call or a function call
+    // main.draw() <- if main is variable then this is method call otherwise it's a function call
+    // draw() <- must be method call
+    let call_text = match call.get_target() {
+        Some(target_name) => match context.symbol_table().find_variable(target_name) {
+            Some(variable) => {
+                context.record_read(&variable);
+                output.push(variable.scope().as_segment(), variable.index());
+                param_count += 1;
+                format!("{}.{}", variable.var_type(), call.get_name())
             }
+            None => call.name_as_string(),
+        },
+        None => {
+            output.push("pointer", 0);
+            param_count += 1;
+            format!("{}.{}", context.class_name, call.get_name())
         }
-        Statement::Return(_) => {}
-        Statement::VarDecl(var_details) => {
+    };
+
+    for parameter in call.get_parameters() {
+        compile_expression(output, parameter, context)?;
+    }
+
+    output.call(&call_text, param_count);
+
+    Ok(call_text)
+}
+
+/// Free the disposable locals which hold an object, leaving the return value on the stack
+fn compile_disposals(output: &mut VmWriter, context: &mut CompilationContext, line: u32) {
+    for (index, free) in context.disposals.clone() {
+        let dispose_label = context.next_dispose_label(line);
+        output.push("local", index);
+        output.if_goto(format_args!("{}.free", dispose_label));
+        output.goto(format_args!("{}.end", dispose_label));
+        output.label(format_args!("{}.free", dispose_label));
+        output.push("local", index);
+        output.call(&free, 1);
+        output.pop("temp", 0);
+        output.label(format_args!("{}.end", dispose_label));
+    }
+}
+
+/// Compile the body of an if or while statement, giving its var declarations their own scope
+fn compile_block(
+    output: &mut VmWriter,
+    statements: &[Statement],
+    context: &mut CompilationContext,
+) -> Result<(), CompilationError> {
+    context.symbol_table().create_block_scope();
+    for statement in statements {
+        if let Statement::VarDecl(var_details) = statement {
             for var in var_details.get_variables() {
-                symbol_table.add_local(
-                    var.get_identifier(),
-                    &format!("{}", var.get_type().to_string()),
-                );
+                add_local_variable(var, context.symbol_table());
             }
         }
     }
+
+    for statement in statements {
+        compile_statement(output, statement, context)?;
+    }
+
+    context.symbol_table().pop_scope();
+    Ok(())
+}
+
+fn add_local_variable(var: &Variable, symbol_table: &mut SymbolTable) {
+    symbol_table.add_local(var.get_identifier(), var.get_type().type_name());
+}
+
+/// Count the variables declared inside the if and while bodies of a statement
+fn count_nested_locals(statement: &Statement) -> usize {
+    let count_block = |statements: &[Statement]| -> usize {
+        statements
+            .iter()
+            .map(|statement| match statement {
+                Statement::VarDecl(var_details) => var_details.get_variables().len(),
+                _ => count_nested_locals(statement),
+            })
+            .sum()
+    };
+
+    match statement {
+        Statement::While(details) => count_block(&details.body),
+        Statement::If(details) => {
+            count_block(details.get_if_body())
+                + details.get_else_body().map_or(0, |body| count_block(body))
+        }
+        _ => 0,
+    }
 }
@@ -1,9 +1,12 @@
+use std::error::Error;
+
 use crate::{
     ast::{
         BinaryOp, Class, ClassVariable, Expr, Statement, Subroutine, SubroutineType, UnaryOp,
         Variable, VariableRef, VariableType,
     },
-    compiler::compile_class,
+    compiler::{compile_class, translate_ast, CodegenOptions, CompilationWarning},
+    signatures::Signatures,
 };
 
 #[test]
@@ -20,7 +23,7 @@ fn test_compile_function() {
             .add_statement(Statement::return_void()),
     );
 
-    let result = compile_class(&class).unwrap();
+    let result = compile_lines(&class);
 
     let expected: Vec<String> = r#"
         function Main.main 0
@@ -57,7 +60,7 @@ fn test_compile_simple_expression() {
             .add_statement(Statement::return_void()),
     );
 
-    let result = compile_class(&class).unwrap();
+    let result = compile_lines(&class);
 
     let expected: Vec<String> = r#"
         push constant 1
@@ -95,7 +98,7 @@ fn test_compile_complex_expression() {
             .add_statement(Statement::return_void()),
     );
 
-    let result = compile_class(&class).unwrap();
+    let result = compile_lines(&class);
 
     let expected: Vec<String> = r#"
         push constant 1
@@ -127,7 +130,7 @@ fn compile_var_statement() {
             .add_statement(Statement::return_void()),
     );
 
-    let result = compile_class(&class).unwrap();
+    let result = compile_lines(&class);
     let expected: Vec<String> = r#"
         function Main.main 1
         push constant 0
@@ -160,7 +163,7 @@ fn compile_let() {
             .add_statement(Statement::return_void()),
     );
 
-    let result = compile_class(&class).unwrap();
+    let result = compile_lines(&class);
     let expected: Vec<String> = r#"
         function Main.main 1
         push constant 3
@@ -203,7 +206,7 @@ fn compile_var_used_in_do_statement() {
             .add_statement(Statement::return_void()),
     );
 
-    let result = compile_class(&class).unwrap();
+    let result = compile_lines(&class);
 
     let expected: Vec<String> = r#"
         push constant 3
@@ -233,7 +236,7 @@ fn compile_unary_operation_test() {
             .add_statement(Statement::return_void()),
     );
 
-    let result = compile_class(&class).unwrap();
+    let result = compile_lines(&class);
 
     let expected: Vec<String> = r#"
         push constant 3
@@ -281,7 +284,7 @@ fn compile_multiple_functions() {
                 .add_statement(Statement::return_expr(Expr::var(VariableRef::new("value")))),
         );
 
-    let result = compile_class(&class).unwrap();
+    let result = compile_lines(&class);
 
     let expected: Vec<String> = r#"
         function Main.main 1
@@ -320,7 +323,7 @@ fn compile_function_with_args() {
             )))),
     );
 
-    let result = compile_class(&class).unwrap();
+    let result = compile_lines(&class);
 
     let expected: Vec<String> = r#"
         function Main.main 0
@@ -360,7 +363,7 @@ fn compile_while_loop() {
             .add_statement(Statement::return_void()),
     );
 
-    let result = compile_class(&class).unwrap();
+    let result = compile_lines(&class);
 
     let expected: Vec<String> = r#"
             function Main.main 0
@@ -420,7 +423,7 @@ fn compile_if_statement() {
             .add_statement(Statement::return_void()),
     );
 
-    let result = compile_class(&class).unwrap();
+    let result = compile_lines(&class);
 
     let expected: Vec<String> = r#"
             function Main.main 0
@@ -475,7 +478,7 @@ fn compile_let_with_call() {
             .add_statement(Statement::return_void()),
     );
 
-    let result = compile_class(&class).unwrap();
+    let result = compile_lines(&class);
 
     let expected: Vec<String> = r#"
         function Main.main 1
@@ -494,6 +497,20 @@ fn compile_let_with_call() {
 }
 
 #[allow(dead_code)]
+fn compile_lines(class: &Class) -> Vec<String> {
+    compile_class(
+        class,
+        &Signatures::new([class]),
+        &CodegenOptions::default(),
+        &Default::default(),
+    )
+    .unwrap()
+    .vm_code
+    .lines()
+    .map(|line| line.to_owned())
+    .collect()
+}
+
 fn contains_commands(result: &Vec<String>, expected: &Vec<String>) -> bool {
     result
         .windows(expected.len())
@@ -509,7 +526,7 @@ fn compile_class_with_constructor() {
         .add_subroutine(
             Subroutine::new("new")
                 .subroutine_type(crate::ast::SubroutineType::Constructor)
-                .return_type(crate::ast::ReturnType::ClassName("Point".to_string()))
+                .return_type(crate::ast::ReturnType::ClassName("Point".into()))
                 .add_parameter(Variable::new("ax", crate::ast::VariableType::Int))
                 .add_parameter(Variable::new("ay", crate::ast::VariableType::Int))
                 .add_statement(
@@ -527,7 +544,7 @@ fn compile_class_with_constructor() {
                 .add_statement(Statement::return_expr(Expr::this())),
         );
 
-    let result = compile_class(&class).unwrap();
+    let result = compile_lines(&class);
 
     let expected: Vec<String> = r#"
         function Point.new 0
@@ -572,7 +589,7 @@ fn test_method() {
         .add_subroutine(
             Subroutine::new("new")
                 .subroutine_type(crate::ast::SubroutineType::Constructor)
-                .return_type(crate::ast::ReturnType::ClassName("Adder".to_string()))
+                .return_type(crate::ast::ReturnType::ClassName("Adder".into()))
                 .add_parameter(Variable::new("aa", crate::ast::VariableType::Int))
                 .add_parameter(Variable::new("ab", crate::ast::VariableType::Int))
                 .add_statement(
@@ -600,7 +617,7 @@ fn test_method() {
                 ))),
         );
 
-    let result = compile_class(&class).unwrap();
+    let result = compile_lines(&class);
 
     let expected: Vec<String> = r#"
         function Adder.add 0
@@ -638,7 +655,7 @@ fn call_method_on_object() {
                 Statement::var()
                     .add_var(Variable::new(
                         "square",
-                        VariableType::ClassName("Square".to_owned()),
+                        VariableType::ClassName("Square".into()),
                     ))
                     .as_statement(),
             )
@@ -657,7 +674,7 @@ fn call_method_on_object() {
             .add_statement(Statement::return_void()),
     );
 
-    let result = compile_class(&class).unwrap();
+    let result = compile_lines(&class);
 
     let expected: Vec<String> = r#"
         function Main.main 1
@@ -697,13 +714,13 @@ fn call_method_on_field_object() {
     let class = Class::new("Game")
         .add_variable(
             ClassVariable::new("ball")
-                .var_type(crate::ast::VariableType::ClassName("Ball".to_owned()))
+                .var_type(crate::ast::VariableType::ClassName("Ball".into()))
                 .visibility(crate::ast::ClassVariableVisibility::Field),
         )
         .add_subroutine(
             Subroutine::new("new")
                 .subroutine_type(SubroutineType::Constructor)
-                .return_type(crate::ast::ReturnType::ClassName("Game".to_owned()))
+                .return_type(crate::ast::ReturnType::ClassName("Game".into()))
                 .add_statement(
                     Statement::let_statement()
                         .id(VariableRef::new("ball"))
@@ -724,7 +741,7 @@ fn call_method_on_field_object() {
                 .add_statement(Statement::return_void()),
         );
 
-    let result = compile_class(&class).unwrap();
+    let result = compile_lines(&class);
 
     let expected: Vec<String> = r#"
         function Game.new 0
@@ -774,7 +791,7 @@ fn test_chained_methods() {
         .add_subroutine(
             Subroutine::new("new")
                 .subroutine_type(crate::ast::SubroutineType::Constructor)
-                .return_type(crate::ast::ReturnType::ClassName("Adder".to_string()))
+                .return_type(crate::ast::ReturnType::ClassName("Adder".into()))
                 .add_statement(Statement::return_expr(Expr::this())),
         )
         .add_subroutine(
@@ -792,7 +809,7 @@ fn test_chained_methods() {
                 .add_statement(Statement::return_expr(Expr::int(3))),
         );
 
-    let result = compile_class(&class).unwrap();
+    let result = compile_lines(&class);
 
     let expected: Vec<String> = r#"
         function Adder.first 0
@@ -842,7 +859,7 @@ fn test_chained_methods_using_do() {
         .add_subroutine(
             Subroutine::new("new")
                 .subroutine_type(crate::ast::SubroutineType::Constructor)
-                .return_type(crate::ast::ReturnType::ClassName("Adder".to_string()))
+                .return_type(crate::ast::ReturnType::ClassName("Adder".into()))
                 .add_statement(Statement::return_expr(Expr::this())),
         )
         .add_subroutine(
@@ -857,7 +874,7 @@ fn test_chained_methods_using_do() {
                 .add_statement(Statement::return_void()),
         );
 
-    let result = compile_class(&class).unwrap();
+    let result = compile_lines(&class);
 
     let expected: Vec<String> = r#"
         function Adder.first 0
@@ -907,7 +924,7 @@ fn compile_array_test() {
             .add_statement(Statement::return_void()),
     );
 
-    let result = compile_class(&class).unwrap();
+    let result = compile_lines(&class);
 
     let expected: Vec<String> = r#"
         function Main.main 0
@@ -980,7 +997,7 @@ fn test_array_values() {
             .add_statement(Statement::return_void()),
     );
 
-    let result = compile_class(&class).unwrap();
+    let result = compile_lines(&class);
 
     let expected: Vec<String> = r#"
         function Main.main 1
@@ -1053,7 +1070,7 @@ fn test_array_to_array_equality() {
             .add_statement(Statement::return_void()),
     );
 
-    let result = compile_class(&class).unwrap();
+    let result = compile_lines(&class);
 
     let expected: Vec<String> = r#"
         function Main.main 1
@@ -1125,7 +1142,7 @@ fn test_static_class_variables() {
                 .add_statement(Statement::return_void()),
         );
 
-    let result = compile_class(&class).unwrap();
+    let result = compile_lines(&class);
 
     let expected: Vec<String> = r#"
         function Counter.initialize 0
@@ -1152,7 +1169,7 @@ fn test_class_method_arguments() {
                 Statement::var()
                     .add_var(Variable::new(
                         "ball",
-                        VariableType::ClassName("Ball".to_owned()),
+                        VariableType::ClassName("Ball".into()),
                     ))
                     .as_statement(),
             )
@@ -1173,7 +1190,7 @@ fn test_class_method_arguments() {
             .add_statement(Statement::return_void()),
     );
 
-    let result = compile_class(&class).unwrap();
+    let result = compile_lines(&class);
 
     let expected: Vec<String> = r#"
         function Main.main 1
@@ -1194,3 +1211,1230 @@ fn test_class_method_arguments() {
 
     assert_eq!(result, expected);
 }
+
+#[test]
+fn test_classes_are_output_in_source_order() {
+    let sources: Vec<(String, String)> = (0..32)
+        .map(|i| {
+            (
+                format!("Class{}.jack", i),
+                format!(
+                    "class Class{} {{ function int value() {{ return {}; }} }}",
+                    i, i
+                ),
+            )
+        })
+        .collect();
+    let inputs: Vec<(&str, &str)> = sources
+        .iter()
+        .map(|(name, contents)| (name.as_str(), contents.as_str()))
+        .collect();
+
+    let output = crate::compile_strings(&inputs).unwrap();
+
+    for (i, (filename, vm_code)) in output.iter().enumerate() {
+        assert_eq!(filename, &format!("Class{}.vm", i));
+        assert!(vm_code.starts_with(&format!("function Class{}.value 0", i)));
+    }
+
+    // With several failing classes the first in source order is reported
+    let error = crate::compile_strings(&[
+        ("A.jack", "class A { function void f() { return; } }"),
+        ("B.jack", "class B { function int f() { return first; } }"),
+        ("C.jack", "class C { function int f() { return second; } }"),
+    ])
+    .unwrap_err();
+    assert_eq!(
+        error.source().map(|e| e.to_string()),
+        Some("Variable first has not been declared".to_owned())
+    );
+}
+
+#[test]
+fn test_using_the_result_of_a_void_call_warns() {
+    let ast = crate::parse_strings(&[(
+        "Main.jack",
+        "class Main {
+            function void main() {
+                var int x;
+                do Output.printInt(3);
+                do Main.draw();
+                let x = Output.printInt(3);
+                let x = Main.draw() + Math.abs(x);
+                return;
+            }
+            function void draw() { return; }
+        }",
+    )])
+    .unwrap();
+
+    let output = translate_ast(&ast, &CodegenOptions::default()).unwrap();
+
+    assert_eq!(
+        output[0].warnings,
+        vec![
+            CompilationWarning::VoidResultUsed {
+                subroutine: "Main.main".to_owned(),
+                callee: "Output.printInt".to_owned(),
+            },
+            CompilationWarning::VoidResultUsed {
+                subroutine: "Main.main".to_owned(),
+                callee: "Main.draw".to_owned(),
+            },
+        ]
+    );
+}
+
+#[test]
+fn test_unqualified_calls_must_match_a_subroutine_in_the_class() {
+    let error = crate::compile_strings(&[(
+        "Main.jack",
+        "class Main {
+            method void run() { do draww(); return; }
+            method void draw() { return; }
+        }",
+    )])
+    .unwrap_err();
+
+    assert_eq!(
+        error.source().map(|e| e.to_string()),
+        Some("Class Main has no subroutine called draww".to_owned())
+    );
+}
+
+#[test]
+fn test_canonical_booleans() {
+    let class = Class::new("Main").add_subroutine(
+        Subroutine::new("isTrue")
+            .return_type(crate::ast::ReturnType::Boolean)
+            .add_statement(Statement::return_expr(Expr::true_c())),
+    );
+    let options = CodegenOptions {
+        canonical_booleans: true,
+        ..Default::default()
+    };
+
+    let vm_code = compile_class(
+        &class,
+        &Signatures::new([&class]),
+        &options,
+        &Default::default(),
+    )
+    .unwrap()
+    .vm_code;
+
+    assert_eq!(
+        vm_code,
+        "function Main.isTrue 0\npush constant 0\nnot\nreturn"
+    );
+}
+
+#[test]
+fn test_lowering_retargets_os_calls() {
+    let ast = crate::parse_strings(&[(
+        "Point.jack",
+        "class Point {
+            field int x;
+            constructor Point new(int a) {
+                let x = a * 3 / 2;
+                do Output.printString(\"hi\");
+                return this;
+            }
+        }",
+    )])
+    .unwrap();
+    let mut options = CodegenOptions::default();
+    options.lowering.set("multiply", "Fast.mul").unwrap();
+    options.lowering.set("alloc", "Heap.alloc").unwrap();
+    options.lowering.set("append-char", "Text.push").unwrap();
+
+    let output = translate_ast(&ast, &options).unwrap();
+    let vm_code = &output[0].vm_code;
+
+    assert!(vm_code.contains("call Heap.alloc 1"));
+    assert!(vm_code.contains("call Fast.mul 2"));
+    assert!(vm_code.contains("call Math.divide 2"));
+    assert!(vm_code.contains("call String.new 1"));
+    assert!(vm_code.contains("call Text.push 2"));
+    assert!(!vm_code.contains("Math.multiply"));
+    assert!(!vm_code.contains("Memory.alloc"));
+}
+
+#[test]
+fn test_block_scoped_variables() {
+    let ast = crate::parse_strings(&[(
+        "Main.jack",
+        "class Main {
+            function int main() {
+                var int x;
+                let x = 1;
+                if (true) {
+                    var int x;
+                    let x = 2;
+                } else {
+                    var int y;
+                    let y = x;
+                }
+                return x;
+            }
+        }",
+    )])
+    .unwrap();
+
+    // Without extensions the declarations are hoisted, so the inner x replaces the outer one
+    let output = translate_ast(&ast, &CodegenOptions::default()).unwrap();
+    let lines: Vec<String> = output[0].vm_code.lines().map(str::to_owned).collect();
+    assert_eq!(lines[0], "function Main.main 3");
+    assert!(contains_commands(
+        &lines,
+        &vec!["push local 1".to_owned(), "pop local 2".to_owned()]
+    ));
+    assert!(contains_commands(
+        &lines,
+        &vec!["push constant 2".to_owned(), "pop local 1".to_owned()]
+    ));
+    assert_eq!(lines[lines.len() - 2], "push local 1");
+
+    let options = CodegenOptions {
+        extensions: true,
+        ..Default::default()
+    };
+    let output = translate_ast(&ast, &options).unwrap();
+    let lines: Vec<&str> = output[0].vm_code.lines().collect();
+
+    assert_eq!(lines[0], "function Main.main 3");
+    // The else body is compiled first so y is local 1 and the inner x is local 2
+    let lines: Vec<String> = lines.iter().map(|s| s.to_string()).collect();
+    assert!(contains_commands(
+        &lines,
+        &vec!["push local 0".to_owned(), "pop local 1".to_owned()]
+    ));
+    assert!(contains_commands(
+        &lines,
+        &vec!["push constant 2".to_owned(), "pop local 2".to_owned()]
+    ));
+    assert_eq!(lines[lines.len() - 2], "push local 0");
+}
+
+#[test]
+fn test_long_string_constants_warn() {
+    let class = Class::new("Main").add_subroutine(
+        Subroutine::new("main")
+            .add_statement(
+                Statement::do_statement()
+                    .set_target("Output")
+                    .name("printString")
+                    .add_parameter(Expr::string(&"a".repeat(300)))
+                    .as_statement(),
+            )
+            .add_statement(Statement::return_void()),
+    );
+
+    let warnings = compile_class(
+        &class,
+        &Signatures::new([&class]),
+        &CodegenOptions::default(),
+        &Default::default(),
+    )
+    .unwrap()
+    .warnings;
+
+    assert_eq!(
+        warnings,
+        vec![CompilationWarning::LongString {
+            subroutine: "Main.main".to_owned(),
+            length: 300
+        }]
+    );
+}
+
+#[test]
+fn test_fields_read_but_never_constructed_warn() {
+    let ast = crate::parse_strings(&[(
+        "Ball.jack",
+        "class Ball {
+            field int x, y, speed;
+            field Array trail;
+            constructor Ball new(int speed) {
+                let x = 0;
+                let speed = speed;
+                return this;
+            }
+            method int area() { return x * y; }
+            method void move() {
+                let trail[0] = x;
+                let speed = speed + 1;
+                return;
+            }
+        }",
+    )])
+    .unwrap();
+
+    let output = translate_ast(&ast, &CodegenOptions::default()).unwrap();
+
+    // x is assigned by the constructor. The parameter shadows speed, so the field never is.
+    assert_eq!(
+        output[0].warnings,
+        vec![
+            CompilationWarning::UninitializedField {
+                method: "Ball.area".to_owned(),
+                field: "y".to_owned(),
+            },
+            CompilationWarning::UninitializedField {
+                method: "Ball.move".to_owned(),
+                field: "speed".to_owned(),
+            },
+            CompilationWarning::UninitializedField {
+                method: "Ball.move".to_owned(),
+                field: "trail".to_owned(),
+            },
+        ]
+    );
+}
+
+#[test]
+fn test_compile_jack_source_returns_structured_results() {
+    let output = crate::compile_jack_source(
+        "Main.jack",
+        "class Main {
+            function void main() {
+                do Output.printString(\"hi\");
+                return;
+            }
+        }",
+        &CodegenOptions::default(),
+    )
+    .unwrap();
+    assert_eq!(output.vm_filename(), "Main.vm");
+    assert!(output.vm_code.starts_with("function Main.main 0\n"));
+    assert!(output.warnings.is_empty());
+
+    let error = crate::compile_jack_source(
+        "Main.jack",
+        "class Main {
+            function void main() {
+                if (true { return; }
+            }
+        }",
+        &CodegenOptions::default(),
+    )
+    .unwrap_err();
+    match error {
+        crate::ErrorType::ParsingError(error) => {
+            assert_eq!(error.file, "Main.jack");
+            assert_eq!((error.line, error.column), (3, 26));
+            assert_eq!(error.message, "expected ')' in if");
+        }
+        other => panic!("Expected a parse error, got {:?}", other),
+    }
+}
+
+#[test]
+fn test_errors_point_at_the_source() {
+    let error = crate::compile_jack_source(
+        "Main.jack",
+        "class Main {\n    function void main() {\n        let x = 1\n        return;\n    }\n}",
+        &CodegenOptions::default(),
+    )
+    .unwrap_err();
+    assert_eq!(
+        error.to_string(),
+        "error[J0001]: expected ';' in let\n \
+         --> Main.jack:3:18\n  \
+         |\n\
+         3 |         let x = 1\n  \
+         |                  ^"
+    );
+
+    let error = crate::compile_jack_source(
+        "Main.jack",
+        "class Main {\n    function void main() {\n        let x = 1;\n        return;\n    }\n}",
+        &CodegenOptions::default(),
+    )
+    .unwrap_err();
+    assert_eq!(
+        error.to_string(),
+        "error[J0101]: failed to compile Main.main\n \
+         --> Main.jack:3:13\n  \
+         |\n\
+         3 |         let x = 1;\n  \
+         |             ^"
+    );
+    assert_eq!(
+        error.source().map(|e| e.to_string()),
+        Some("Variable x has not been declared".to_owned())
+    );
+
+    // Errors found before codegen point at the call too
+    let error = crate::compile_jack_source(
+        "Main.jack",
+        "class Main {\n    method void helper() { return; }\n    function void main() {\n        if (true) { do helper(); }\n        return;\n    }\n}",
+        &CodegenOptions::default(),
+    )
+    .unwrap_err();
+    assert!(error.to_string().starts_with(
+        "error[J0107]: failed to compile Main.main\n \
+         --> Main.jack:4:24\n"
+    ));
+}
+
+#[test]
+fn test_name_report_traces_names_to_source_lines() {
+    let output = crate::compile_jack_source(
+        "Main.jack",
+        "class Main {
+            static int count;
+
+            function void main() {
+                while (count < 3) {
+                    let count = count + 1;
+                }
+                return;
+            }
+        }",
+        &CodegenOptions::default(),
+    )
+    .unwrap();
+
+    assert_eq!(
+        output.name_report(),
+        "Main.0\tstatic Main.count\tMain.jack:2
+Main.main\tfunction Main.main\tMain.jack:4
+Main.main$main.while.0.condition\twhile loop in Main.main\tMain.jack:5
+Main.main$main.while.0.while_body\twhile loop in Main.main\tMain.jack:5
+Main.main$main.while.0.while_end\twhile loop in Main.main\tMain.jack:5
+"
+    );
+}
+
+#[test]
+fn test_source_map_traces_vm_commands_to_source_lines() {
+    let output = crate::compile_jack_source(
+        "Main.jack",
+        "class Main {
+            function void main() {
+                var int x;
+                let x = 1;
+                if (x) {
+                    do Output.printInt(x);
+                }
+                return;
+            }
+        }",
+        &CodegenOptions::default(),
+    )
+    .unwrap();
+
+    // The jumps and labels of the if statement belong to its line
+    assert_eq!(output.source_lines, [2, 4, 4, 5, 5, 5, 5, 6, 6, 6, 5, 8, 8]);
+    assert!(output
+        .source_map()
+        .starts_with("1\tMain.jack:2\n2\tMain.jack:4\n"));
+}
+
+#[test]
+fn test_calls_are_checked_across_the_program() {
+    let check = |main: &str| {
+        let point = "class Point {
+            field int x;
+            constructor Point new(int ax) { let x = ax; return this; }
+            method int getX() { return x; }
+        }";
+        crate::compile_strings(&[("Main.jack", main), ("Point.jack", point)])
+            .map_err(|error| error.source().map(|e| e.to_string()))
+    };
+
+    assert!(check(
+        "class Main {
+            function void main() {
+                var Point p;
+                let p = Point.new(3);
+                do Output.printInt(p.getX());
+                return;
+            }
+        }"
+    )
+    .is_ok());
+
+    assert_eq!(
+        check("class Main { function void main() { do Pointt.new(1); return; } }"),
+        Err(Some(
+            "Main.main calls a subroutine of Pointt, which is not a class".to_owned()
+        ))
+    );
+    assert_eq!(
+        check("class Main { function void main() { do Point.neww(1); return; } }"),
+        Err(Some("Class Point has no subroutine called neww".to_owned()))
+    );
+    assert_eq!(
+        check(
+            "class Main {
+                function void main() {
+                    var Point p;
+                    if (true) { do Output.printInt(p.getX(1)); }
+                    return;
+                }
+            }"
+        ),
+        Err(Some(
+            "Main.main calls Point.getX with 1 arguments but it takes 0".to_owned()
+        ))
+    );
+    assert_eq!(
+        check("class Main { function void main() { do Math.max(1); return; } }"),
+        Err(Some(
+            "Main.main calls Math.max with 1 arguments but it takes 2".to_owned()
+        ))
+    );
+    assert_eq!(
+        check("class Main { function void main() { var int x; do x.run(); return; } }"),
+        Err(Some(
+            "Main.main calls a subroutine of Int, which is not a class".to_owned()
+        ))
+    );
+
+    // Only methods are called on an object, and an unqualified call passes `this`
+    assert_eq!(
+        check("class Main { function void main() { do Output.printInt(Point.getX()); return; } }"),
+        Err(Some(
+            "Main.main calls method Point.getX without an object to call it on".to_owned()
+        ))
+    );
+    assert_eq!(
+        check(
+            "class Main {
+                function void main() { var Point p; let p = p.new(1); return; }
+            }"
+        ),
+        Err(Some(
+            "Main.main calls Point.new on an object, but it's a function or constructor rather than a method"
+                .to_owned()
+        ))
+    );
+    assert_eq!(
+        check(
+            "class Main {
+                function void main() { do helper(); return; }
+                function void helper() { return; }
+            }"
+        ),
+        Err(Some(
+            "Main.main calls Main.helper on an object, but it's a function or constructor rather than a method"
+                .to_owned()
+        ))
+    );
+    assert!(check(
+        "class Main {
+            function void main() { var String s; let s = String.new(1); do s.appendChar(65); return; }
+        }"
+    )
+    .is_ok());
+}
+
+#[test]
+fn test_strict_types() {
+    let check = |main: &str| {
+        let point = "class Point {
+            field int x;
+            constructor Point new(int ax) { let x = ax; return this; }
+            method int getX() { return x; }
+            method void setX(int ax) { let x = ax; return; }
+        }";
+        let ast = crate::parse_strings(&[("Main.jack", main), ("Point.jack", point)]).unwrap();
+        let options = CodegenOptions {
+            strict_types: true,
+            ..Default::default()
+        };
+        crate::compile_ast_with_options(&ast, &options)
+            .map(|_| ())
+            .map_err(|error| error.source().map(|e| e.to_string()))
+    };
+
+    assert_eq!(
+        check(
+            "class Main {
+                function void main() {
+                    var Point p;
+                    var char c;
+                    var Array a;
+                    var boolean done;
+                    let p = Point.new(3);
+                    let c = 65 + p.getX();
+                    let a = Array.new(2);
+                    let a[0] = p;
+                    let p = a[0];
+                    let done = (c > 3) & ~(p = null);
+                    while (~done) { let done = Keyboard.keyPressed(); }
+                    do Output.printString(\"ok\");
+                    return;
+                }
+            }"
+        ),
+        Ok(())
+    );
+
+    let main = |body: &str| {
+        format!(
+            "class Main {{ function int main() {{ var int x; var Point p; {} return 0; }} }}",
+            body
+        )
+    };
+    assert_eq!(
+        check(&main("let x = true;")),
+        Err(Some(
+            "Main.main assigns a value of type boolean to x, which is int".to_owned()
+        ))
+    );
+    assert_eq!(
+        check(&main("do x.run();")),
+        Err(Some(
+            "Main.main calls run on x, which is int rather than an object".to_owned()
+        ))
+    );
+    assert_eq!(
+        check(&main("if (x) { let x = 1; }")),
+        Err(Some(
+            "Main.main uses a value of type int as an if condition".to_owned()
+        ))
+    );
+    assert_eq!(
+        check(&main("do p.setX(x < 1);")),
+        Err(Some(
+            "Main.main passes a value of type boolean to Point.setX as ax, which is int".to_owned()
+        ))
+    );
+    assert_eq!(
+        check(&main("let x = p.setX(1);")),
+        Err(Some(
+            "Main.main uses the result of p.setX, which returns void".to_owned()
+        ))
+    );
+    assert_eq!(
+        check("class Main { function void main() { return 1; } }"),
+        Err(Some(
+            "Main.main returns a value but is declared void".to_owned()
+        ))
+    );
+    assert_eq!(
+        check("class Main { function int main() { return \"one\"; } }"),
+        Err(Some(
+            "Main.main returns a value of type String but is declared to return int".to_owned()
+        ))
+    );
+}
+
+#[test]
+fn test_auto_dispose() {
+    let ast = crate::parse_strings(&[(
+        "Main.jack",
+        "class Main {
+            function int length() {
+                var String name;
+                var int n;
+                let name = \"Jack\";
+                do name.appendChar(33);
+                let n = 5;
+                return n;
+            }
+        }",
+    )])
+    .unwrap();
+    let options = CodegenOptions {
+        auto_dispose: true,
+        ..Default::default()
+    };
+    let vm_code = &crate::compile_ast_with_options(&ast, &options).unwrap()[0].1;
+    let lines: Vec<&str> = vm_code.lines().map(str::trim).collect();
+
+    // The return value stays on the stack while the string is freed
+    assert!(lines.ends_with(&[
+        "push local 1",
+        "push local 0",
+        "if-goto length.dispose.0.free",
+        "goto length.dispose.0.end",
+        "label length.dispose.0.free",
+        "push local 0",
+        "call String.dispose 1",
+        "pop temp 0",
+        "label length.dispose.0.end",
+        "return",
+    ]));
+
+    let plain = &crate::compile_ast_with_options(&ast, &CodegenOptions::default()).unwrap()[0];
+    assert!(!plain.1.contains("dispose"));
+}
+
+#[test]
+fn test_constant_folding() {
+    let ast = crate::parse_strings(&[(
+        "Main.jack",
+        "class Main {
+            function int main() {
+                var int x;
+                let x = (2 * 8) + 1;
+                if (false) {
+                    do Output.printInt(x);
+                } else {
+                    let x = x - (3 - 10);
+                }
+                while (1 > 2) {
+                    let x = 0;
+                }
+                return x + (7 / 2);
+            }
+        }",
+    )])
+    .unwrap();
+    let options = CodegenOptions {
+        optimize: true,
+        ..Default::default()
+    };
+    let vm_code = &crate::compile_ast_with_options(&ast, &options).unwrap()[0].1;
+    let lines: Vec<&str> = vm_code.lines().map(str::trim).collect();
+
+    assert_eq!(
+        lines,
+        [
+            "function Main.main 1",
+            "push constant 17",
+            "pop local 0",
+            "push local 0",
+            "push constant 7",
+            "neg",
+            "sub",
+            "pop local 0",
+            "push local 0",
+            "push constant 3",
+            "add",
+            "return",
+        ]
+    );
+}
+
+#[test]
+fn test_unused_variables_are_reported() {
+    let ast = crate::parse_strings(&[(
+        "Main.jack",
+        "class Main {
+            field int count, total;
+            static int unused;
+            method int add(int amount, int scale) {
+                var int old, spare;
+                var Array items;
+                let old = count;
+                let spare = 0;
+                let items[0] = old + amount;
+                let total = count;
+                return count;
+            }
+        }",
+    )])
+    .unwrap();
+    let output = translate_ast(&ast, &CodegenOptions::default()).unwrap();
+    let warnings: Vec<(String, Option<u32>)> = output[0]
+        .warnings
+        .iter()
+        .filter(|warning| warning.code() == "J0204")
+        .map(|warning| (warning.to_string(), warning.line()))
+        .collect();
+
+    assert_eq!(
+        warnings,
+        [
+            ("Main.add: argument scale is never read".to_owned(), Some(4)),
+            ("Main.add: local spare is never read".to_owned(), Some(5)),
+            ("Main: field total is never read".to_owned(), Some(2)),
+            ("Main: static unused is never read".to_owned(), Some(3)),
+        ]
+    );
+}
+
+#[test]
+fn test_locals_read_before_assignment_are_reported() {
+    let ast = crate::parse_strings(&[(
+        "Main.jack",
+        "class Main {
+            function int sum(Array values, int length) {
+                var int i, total, last, sign;
+                var Array copy;
+                let copy[0] = 1;
+                if (length < 0) {
+                    let sign = -1;
+                } else {
+                    let sign = 1;
+                }
+                if (length = 0) {
+                    return sign;
+                } else {
+                    let last = 0;
+                }
+                while (i < length) {
+                    let total = total + values[i];
+                    let last = i;
+                    let i = i + 1;
+                }
+                return total + last;
+            }
+        }",
+    )])
+    .unwrap();
+    let output = translate_ast(&ast, &CodegenOptions::default()).unwrap();
+    let warnings: Vec<(String, Option<u32>)> = output[0]
+        .warnings
+        .iter()
+        .filter(|warning| warning.code() == "J0207")
+        .map(|warning| (warning.to_string(), warning.line()))
+        .collect();
+
+    assert_eq!(
+        warnings,
+        [
+            (
+                "Main.sum: local copy may be read before it's assigned".to_owned(),
+                Some(5)
+            ),
+            (
+                "Main.sum: local i may be read before it's assigned".to_owned(),
+                Some(16)
+            ),
+            (
+                "Main.sum: local total may be read before it's assigned".to_owned(),
+                Some(17)
+            ),
+        ]
+    );
+}
+
+#[test]
+fn test_compiling_twice_gives_identical_output() {
+    let dir = std::env::temp_dir().join(format!("compiler-reproducible-{}", std::process::id()));
+    let sources = dir.join("src");
+    std::fs::create_dir_all(&sources).unwrap();
+    std::fs::write(
+        sources.join("Main.jack"),
+        "class Main {
+            function void main() {
+                var int i;
+                while (i < 3) { if (i = 1) { do Counter.count(i); } let i = i + 1; }
+                return;
+            }
+        }",
+    )
+    .unwrap();
+    std::fs::write(
+        sources.join("Counter.jack"),
+        "class Counter {
+            static int total;
+            function void count(int n) { if (n > 0) { let total = total + n; } return; }
+            function int get() { while (total > 100) { let total = total - 100; } return total; }
+        }",
+    )
+    .unwrap();
+
+    let build = |name: &str| {
+        let out_dir = dir.join(name);
+        std::fs::create_dir_all(&out_dir).unwrap();
+        let reports = crate::Reports {
+            names: true,
+            source_map: true,
+            ..Default::default()
+        };
+        crate::process_source(
+            sources.to_str().unwrap(),
+            reports,
+            Default::default(),
+            &CodegenOptions::default(),
+            parse_utils::output::WriteMode::Write,
+            Some(&out_dir),
+        )
+        .unwrap();
+        let mut files: Vec<(String, Vec<u8>)> = std::fs::read_dir(&out_dir)
+            .unwrap()
+            .map(|entry| {
+                let path = entry.unwrap().path();
+                let name = path.file_name().unwrap().to_string_lossy().into_owned();
+                (name, std::fs::read(path).unwrap())
+            })
+            .collect();
+        files.sort();
+        files
+    };
+    let first = build("first");
+
+    assert_eq!(first.len(), 6);
+    assert_eq!(first, build("second"));
+    // Label counters restart in each subroutine
+    let (_, counter) = first.iter().find(|(name, _)| name == "Counter.vm").unwrap();
+    let counter = String::from_utf8_lossy(counter);
+    assert!(counter.contains("label count.if.0.if_body"));
+    assert!(counter.contains("label get.while.0.condition"));
+
+    std::fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn test_compiling_json_asts() {
+    let dir = std::env::temp_dir().join(format!("compiler-json-ast-{}", std::process::id()));
+    let sources = dir.join("src");
+    let asts = dir.join("asts");
+    std::fs::create_dir_all(&sources).unwrap();
+    std::fs::create_dir_all(&asts).unwrap();
+    std::fs::write(
+        sources.join("Main.jack"),
+        "class Main {
+            function void main() {
+                var Point p;
+                let p = Point.new(-1, 2 * (3 + 4));
+                do Output.printInt(p.sum());
+                return;
+            }
+        }",
+    )
+    .unwrap();
+    std::fs::write(
+        sources.join("Point.jack"),
+        "class Point {
+            field int x, y;
+            constructor Point new(int ax, int ay) { let x = ax; let y = ay; return this; }
+            method int sum() { if (~(x = 0)) { return x + y; } return y; }
+        }",
+    )
+    .unwrap();
+
+    let reports = crate::Reports {
+        ast_json: true,
+        ..Default::default()
+    };
+    let compile = |path: &std::path::Path, out_dir: &std::path::Path| {
+        crate::process_source(
+            path.to_str().unwrap(),
+            reports.clone(),
+            Default::default(),
+            &CodegenOptions::default(),
+            parse_utils::output::WriteMode::Write,
+            Some(out_dir),
+        )
+    };
+    compile(&sources, &sources).unwrap();
+    for class in ["Main", "Point"] {
+        std::fs::rename(
+            sources.join(class).with_extension("json"),
+            asts.join(class).with_extension("json"),
+        )
+        .unwrap();
+    }
+    // A directory without .jack files compiles the ASTs in it, as if they were the sources
+    compile(&asts, &asts).unwrap();
+    for class in ["Main", "Point"] {
+        let vm =
+            |dir: &std::path::Path| std::fs::read_to_string(dir.join(class).with_extension("vm"));
+        assert_eq!(vm(&asts).unwrap(), vm(&sources).unwrap());
+    }
+
+    std::fs::write(asts.join("Main.json"), "{\"identifier\": 3}").unwrap();
+    let error = compile(&asts.join("Main.json"), &asts).unwrap_err();
+    assert!(matches!(error, crate::ErrorType::AstJsonError { .. }));
+
+    std::fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn test_check_this() {
+    let ast = crate::parse_strings(&[(
+        "Point.jack",
+        "class Point {
+            field int x;
+            method int getX() { return x; }
+            function int origin() { return 0; }
+        }",
+    )])
+    .unwrap();
+    let options = CodegenOptions {
+        check_this: true,
+        ..Default::default()
+    };
+    let vm_code = &crate::compile_ast_with_options(&ast, &options).unwrap()[0].1;
+    let lines: Vec<&str> = vm_code.lines().map(str::trim).collect();
+
+    assert_eq!(
+        lines[..16],
+        [
+            "function Point.getX 0",
+            "push argument 0",
+            "push constant 0",
+            "gt",
+            "push argument 0",
+            "push constant 16384",
+            "lt",
+            "and",
+            "if-goto getX.this_ok",
+            "push constant 21",
+            "call Sys.error 1",
+            "pop temp 0",
+            "label getX.this_ok",
+            "push argument 0",
+            "pop pointer 0",
+            "push this 0",
+        ]
+    );
+    // Functions have no object to check
+    assert_eq!(vm_code.matches("Sys.error").count(), 1);
+}
+
+#[test]
+fn test_ast_json_round_trip() {
+    let chain = vec!["i"; 200].join(" + ");
+    let source = format!(
+        "class Shape {{
+            static int count;
+            field Array points;
+            field boolean closed;
+
+            constructor Shape new(int size, char tag) {{
+                let points = Array.new(size);
+                let closed = false;
+                let count = count + 1;
+                return this;
+            }}
+
+            method Shape grow(Shape other) {{
+                var int i;
+                var String name;
+                let name = \"shape \\\\ {{1}}\";
+                while (~(i > 3) & (points[i] < -1)) {{
+                    if ((i = 0) | closed) {{
+                        let points[i * 2] = other.area() / 2;
+                    }} else {{
+                        do Output.printInt(-i);
+                    }}
+                    let i = {};
+                }}
+                if (name = null) {{ return this; }}
+                return other;
+            }}
+
+            method int area() {{ return 0; }}
+            function void reset() {{ let count = 0; return; }}
+        }}",
+        chain
+    );
+    let ast = crate::parse_strings(&[("Shape.jack", &source)]).unwrap();
+    let class = &ast.classes[0].class;
+
+    let json = serde_json::to_string(class).unwrap();
+    let loaded = Class::from_json(&json, crate::DEFAULT_MAX_EXPRESSION_DEPTH).unwrap();
+    assert_eq!(serde_json::to_string(&loaded).unwrap(), json);
+
+    // Line numbers aren't serialized, but the code is otherwise the same
+    let loaded = crate::AST {
+        classes: vec![crate::ast::CompiledClass {
+            class: loaded,
+            source_filename: "Shape.jack".to_owned(),
+            source: String::new(),
+        }],
+        siblings: Vec::new(),
+    };
+    assert_eq!(
+        crate::compile_ast(&loaded).unwrap(),
+        crate::compile_ast(&ast).unwrap()
+    );
+}
+
+#[test]
+fn test_eliminate_dead_code() {
+    let sources = [
+        (
+            "Main.jack",
+            "class Main {
+    function void main() {
+        var Counter counter;
+        let counter = Counter.new();
+        do counter.add(2);
+        return;
+        do Main.unused();
+    }
+
+    function void unused() {
+        return;
+    }
+}
+",
+        ),
+        (
+            "Counter.jack",
+            "class Counter {
+    field int total;
+
+    constructor Counter new() {
+        let total = 0;
+        return this;
+    }
+
+    method void add(int n) {
+        if (n > 0) {
+            let total = total + n;
+            return;
+            let total = 0;
+        }
+        return;
+    }
+
+    method int get() {
+        return total;
+    }
+}
+",
+        ),
+    ];
+    let options = CodegenOptions {
+        eliminate_dead_code: true,
+        ..Default::default()
+    };
+    let outputs = crate::compile_jack_sources(&sources, &options).unwrap();
+    let (main, counter) = (&outputs[0], &outputs[1]);
+
+    assert!(!main.vm_code.contains("function Main.unused"));
+    assert!(!main.vm_code.contains("call Main.unused"));
+    assert!(counter.vm_code.contains("function Counter.add"));
+    assert!(!counter.vm_code.contains("function Counter.get"));
+    assert_eq!(
+        main.warnings,
+        [
+            CompilationWarning::UnreachableCode {
+                subroutine: "Main.main".to_owned(),
+                line: 7
+            },
+            CompilationWarning::UnusedSubroutine {
+                subroutine: "Main.unused".to_owned(),
+                line: 10
+            },
+        ]
+    );
+    assert_eq!(
+        counter.warnings,
+        [
+            CompilationWarning::UnreachableCode {
+                subroutine: "Counter.add".to_owned(),
+                line: 13
+            },
+            CompilationWarning::UnusedSubroutine {
+                subroutine: "Counter.get".to_owned(),
+                line: 18
+            },
+        ]
+    );
+
+    // Without an entry point every subroutine is kept
+    let library = crate::compile_jack_sources(&sources[1..], &options).unwrap();
+    assert!(library[0].vm_code.contains("function Counter.get"));
+}
+
+#[test]
+fn test_ast_json_nesting_is_limited() {
+    let class = |expression: &str| {
+        format!(
+            r#"{{"identifier":"Main","subroutines":[{{"subroutine_type":"function","identifier":"main","parameters":[],"return_type":"int","statements":[{{"Return":{}}}]}}],"variables":[]}}"#,
+            expression
+        )
+    };
+    let nested = |depth| {
+        format!(
+            "{}{{\"Constant\":{{\"Int\":1}}}}{}",
+            r#"{"BracketedExpr":"#.repeat(depth),
+            "}".repeat(depth)
+        )
+    };
+
+    assert!(Class::from_json(&class(&nested(3)), 3).is_ok());
+    let error = Class::from_json(&class(&nested(4)), 3).unwrap_err();
+    assert_eq!(
+        error.to_string(),
+        "an expression is nested more than 3 deep"
+    );
+
+    // Far deeper than the stack would allow reading recursively
+    assert!(Class::from_json(&class(&nested(200_000)), 256).is_err());
+
+    // A long chain of operators nests the JSON deeply too, but not the expression
+    let chain = format!(
+        r#"{}{{"VarRef":{{"name":"i"}}}}{}"#,
+        r#"{"BinaryExpr":{"lhs":{"Constant":{"Int":1}},"op":"Plus","rhs":"#.repeat(10_000),
+        "}}".repeat(10_000)
+    );
+    assert!(Class::from_json(&class(&chain), 1).is_ok());
+}
+
+#[test]
+fn test_long_expressions_compile() {
+    // Every pass walks a chain of operators without recursing, so its length isn't limited
+    let chain = vec!["i"; 10_000].join(" + ");
+    let source = format!(
+        "class Main {{
+            function int main() {{
+                var int i;
+                let i = 1;
+                return {};
+            }}
+        }}",
+        chain
+    );
+    let ast = crate::parse_strings(&[("Main.jack", &source)]).unwrap();
+    let options = CodegenOptions {
+        strict_types: true,
+        auto_dispose: true,
+        optimize: true,
+        eliminate_dead_code: true,
+        ..Default::default()
+    };
+    let vm_code = &crate::compile_ast_with_options(&ast, &options).unwrap()[0].1;
+    assert_eq!(vm_code.matches("push local 0").count(), 10_000);
+    assert_eq!(vm_code.matches("add").count(), 9_999);
+    assert!(crate::format::format_jack("Main.jack", &source).is_ok());
+}
+
+#[test]
+fn test_compiling_one_file_of_a_project() {
+    let dir = std::env::temp_dir().join(format!("compiler-one-file-{}", std::process::id()));
+    std::fs::create_dir_all(&dir).unwrap();
+    std::fs::write(
+        dir.join("Game.jack"),
+        "class Game {
+            constructor Game new() { return this; }
+            method void run(int turns) { return; }
+        }",
+    )
+    .unwrap();
+    // A sibling which doesn't parse is reported when it's compiled, not when its neighbours are
+    std::fs::write(dir.join("Broken.jack"), "class Broken {").unwrap();
+    let compile = |main: &str| {
+        std::fs::write(dir.join("Main.jack"), main).unwrap();
+        crate::process_source(
+            dir.join("Main.jack").to_str().unwrap(),
+            Default::default(),
+            Default::default(),
+            &CodegenOptions::default(),
+            parse_utils::output::WriteMode::Write,
+            None,
+        )
+    };
+
+    compile(
+        "class Main {
+            function void main() { var Game game; let game = Game.new(); do game.run(3); return; }
+        }",
+    )
+    .unwrap();
+    assert!(dir.join("Main.vm").exists());
+    assert!(!dir.join("Game.vm").exists());
+
+    // Calls into the rest of the project are still checked
+    let error = compile(
+        "class Main {
+            function void main() { var Game game; let game = Game.new(); do game.run(); return; }
+        }",
+    )
+    .unwrap_err();
+    assert_eq!(error.code(), Some("J0105"));
+
+    std::fs::remove_dir_all(&dir).unwrap();
+}
@@ -0,0 +1,208 @@
+use rustc_hash::{FxHashMap, FxHashSet};
+
+use crate::ast::{Class, ExprKind, ExprRef, Identifier, Statement, Subroutine, SubroutineCall};
+use crate::compiler::CodegenOptions;
+use crate::semantics::Scopes;
+
+/// Where a program starts: the VM translator's bootstrap calls Sys.init, and the OS's Sys.init
+/// calls Main.main
+const ENTRY_POINTS: [&str; 2] = ["Sys.init", "Main.main"];
+
+/// The full names of the subroutines which nothing reachable from an entry point calls. A program
+/// without an entry point, such as a library compiled on its own, has none.
+///
+/// Calls the compiler emits itself, for operators, constructors, string constants, `--check-this`
+/// and `--auto-dispose`, count as reachable. The calls must already have been checked.
+pub fn dead_subroutines<'a>(
+    classes: impl IntoIterator<Item = &'a Class>,
+    options: &CodegenOptions,
+) -> FxHashSet<String> {
+    let program: FxHashMap<String, (&Class, &Subroutine)> = classes
+        .into_iter()
+        .flat_map(|class| {
+            class.subroutines().iter().map(move |subroutine| {
+                (
+                    format!("{}.{}", class.get_name(), subroutine.get_name()),
+                    (class, subroutine),
+                )
+            })
+        })
+        .collect();
+    if !ENTRY_POINTS.iter().any(|name| program.contains_key(*name)) {
+        return FxHashSet::default();
+    }
+
+    let lowering = &options.lowering;
+    let mut pending: Vec<String> = ENTRY_POINTS
+        .iter()
+        .map(|name| name.to_string())
+        .chain([
+            lowering.multiply.clone(),
+            lowering.divide.clone(),
+            lowering.alloc.clone(),
+            lowering.string_new.clone(),
+            lowering.append_char.clone(),
+        ])
+        .collect();
+    if options.check_this {
+        pending.push("Sys.error".to_owned());
+    }
+    if options.auto_dispose {
+        pending.push("Memory.deAlloc".to_owned());
+        pending.extend(
+            program
+                .keys()
+                .filter(|name| name.ends_with(".dispose"))
+                .cloned(),
+        );
+    }
+
+    let mut reached = FxHashSet::default();
+    while let Some(name) = pending.pop() {
+        let Some(&(class, subroutine)) = program.get(&name) else {
+            // A subroutine of the OS, which isn't part of the program
+            continue;
+        };
+        if !reached.insert(name) {
+            continue;
+        }
+        let mut callees = Callees {
+            class,
+            scopes: Scopes::new(class, subroutine, |type_name| type_name),
+            found: Vec::new(),
+        };
+        callees.block(subroutine.get_statements());
+        pending.extend(callees.found);
+    }
+
+    program
+        .into_keys()
+        .filter(|name| !reached.contains(name))
+        .collect()
+}
+
+/// The full names of the subroutines called by a subroutine
+struct Callees<'a> {
+    class: &'a Class,
+    scopes: Scopes<'a, Identifier>,
+    found: Vec<String>,
+}
+
+impl<'a> Callees<'a> {
+    fn block(&mut self, statements: &'a [Statement]) {
+        self.scopes.push();
+        // Calls after a return are left out along with the rest of the statements there
+        for statement in statements {
+            self.statement(statement);
+            if matches!(statement, Statement::Return(_)) {
+                break;
+            }
+        }
+        self.scopes.pop();
+    }
+
+    fn statement(&mut self, statement: &'a Statement) {
+        match statement {
+            Statement::VarDecl(details) => {
+                for variable in details.get_variables() {
+                    self.scopes.declare(
+                        variable.get_identifier().as_str(),
+                        variable.get_type().type_name(),
+                    );
+                }
+            }
+            Statement::Let(details) => {
+                if let Some(index) = details.get_identifier().get_index() {
+                    self.expression(index.root());
+                }
+                self.expression(details.get_expression().root());
+            }
+            Statement::Do(call) => self.call(call),
+            Statement::Return(details) => {
+                if let Some(value) = details.get_value() {
+                    self.expression(value.root());
+                }
+            }
+            Statement::While(details) => {
+                self.expression(details.get_condition().root());
+                self.block(details.get_body());
+            }
+            Statement::If(details) => {
+                self.expression(details.get_condition().root());
+                self.block(details.get_if_body());
+                if let Some(body) = details.get_else_body() {
+                    self.block(body);
+                }
+            }
+        }
+    }
+
+    fn expression(&mut self, expr: ExprRef<'a>) {
+        match expr.kind() {
+            ExprKind::Constant(_) => {}
+            ExprKind::VarRef(variable) => {
+                if let Some(index) = variable.get_index() {
+                    self.expression(index.root());
+                }
+            }
+            ExprKind::UnaryExpr(_, expr) | ExprKind::BracketedExpr(expr) => self.expression(expr),
+            ExprKind::BinaryExpr { .. } => {
+                for operand in expr.operands() {
+                    self.expression(operand);
+                }
+            }
+            ExprKind::Call(call) => self.call(call),
+        }
+    }
+
+    fn call(&mut self, call: &'a SubroutineCall) {
+        let class_name = match call.get_target() {
+            Some(target) => match self.scopes.find(target) {
+                Some(type_name) => type_name.to_string(),
+                None => target.to_string(),
+            },
+            None => self.class.get_name().to_string(),
+        };
+        self.found
+            .push(format!("{}.{}", class_name, call.get_name()));
+        for parameter in call.get_parameters() {
+            self.expression(parameter.root());
+        }
+    }
+}
+
+/// The statements of a block without those following a return, which can never run. The line of
+/// the first statement removed from each block is added to `removed`.
+pub fn reachable_statements(statements: &[Statement], removed: &mut Vec<u32>) -> Vec<Statement> {
+    let mut reachable = Vec::with_capacity(statements.len());
+    for (index, statement) in statements.iter().enumerate() {
+        match statement {
+            Statement::While(details) => {
+                let mut details = details.clone();
+                details.body = reachable_statements(&details.body, removed);
+                reachable.push(Statement::While(details));
+            }
+            Statement::If(details) => {
+                let mut details = details.clone();
+                details.if_body = reachable_statements(&details.if_body, removed);
+                details.else_body = details
+                    .else_body
+                    .map(|body| reachable_statements(&body, removed));
+                reachable.push(Statement::If(details));
+            }
+            Statement::Return(_) => {
+                reachable.push(statement.clone());
+                // Declarations don't run, so only statements which would are reported
+                if let Some(first) = statements[index + 1..]
+                    .iter()
+                    .find(|statement| !matches!(statement, Statement::VarDecl(_)))
+                {
+                    removed.push(first.line());
+                }
+                break;
+            }
+            _ => reachable.push(statement.clone()),
+        }
+    }
+    reachable
+}
@@ -0,0 +1,131 @@
+use std::fmt;
+
+/// An error pinned to a position in a source file, displayed like rustc does:
+///
+/// ```text
+/// error: expected ';'
+///  --> Main.jack:3:18
+///   |
+/// 3 |         let x = 1
+///   |                  ^
+/// ```
+#[derive(Debug, Clone, PartialEq)]
+pub struct Diagnostic {
+    pub file: String,
+    /// 1-based line and column
+    pub line: u32,
+    pub column: usize,
+    pub message: String,
+    /// The text of the line the error is on
+    pub source_line: String,
+    /// The code `--explain` describes the error under, e.g. `J0101`
+    pub code: Option<&'static str>,
+}
+
+impl Diagnostic {
+    pub fn new(file: &str, source: &str, line: u32, column: usize, message: String) -> Self {
+        let source_line = source
+            .lines()
+            .nth((line as usize).saturating_sub(1))
+            .unwrap_or_default()
+            .to_owned();
+        Self {
+            file: file.to_owned(),
+            line,
+            column,
+            message,
+            source_line,
+            code: None,
+        }
+    }
+
+    pub fn with_code(mut self, code: &'static str) -> Self {
+        self.code = Some(code);
+        self
+    }
+
+    /// Point at the first non-whitespace character of a line
+    pub fn at_line(file: &str, source: &str, line: u32, message: String) -> Self {
+        let mut diagnostic = Self::new(file, source, line, 1, message);
+        diagnostic.column = diagnostic
+            .source_line
+            .chars()
+            .take_while(|c| c.is_whitespace())
+            .count()
+            + 1;
+        diagnostic
+    }
+
+    /// Point at the first time `word` appears on a line as a whole word, or the line's first
+    /// non-whitespace character if it doesn't
+    pub fn at_word(file: &str, source: &str, line: u32, word: &str, message: String) -> Self {
+        let mut diagnostic = Self::at_line(file, source, line, message);
+        let is_identifier = |c: char| c.is_ascii_alphanumeric() || c == '_';
+        let text = &diagnostic.source_line;
+        let found = text.match_indices(word).map(|(at, _)| at).find(|&at| {
+            !text[..at].ends_with(is_identifier)
+                && !text[at + word.len()..].starts_with(is_identifier)
+        });
+        if let Some(at) = found {
+            diagnostic.column = text[..at].chars().count() + 1;
+        }
+        diagnostic
+    }
+}
+
+impl fmt::Display for Diagnostic {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let gutter = " ".repeat(self.line.to_string().len());
+        // Tabs are kept so the caret lines up with the source however wide the terminal shows them
+        let padding: String = self
+            .source_line
+            .chars()
+            .take(self.column.saturating_sub(1))
+            .map(|c| if c == '\t' { '\t' } else { ' ' })
+            .collect();
+
+        match self.code {
+            Some(code) => writeln!(f, "error[{}]: {}", code, self.message)?,
+            None => writeln!(f, "error: {}", self.message)?,
+        }
+        writeln!(
+            f,
+            "{}--> {}:{}:{}",
+            gutter, self.file, self.line, self.column
+        )?;
+        writeln!(f, "{} |", gutter)?;
+        writeln!(f, "{} | {}", self.line, self.source_line)?;
+        write!(f, "{} | {}^", gutter, padding)
+    }
+}
+
+#[test]
+fn test_diagnostic_points_at_the_column() {
+    let source = "class Main {\n\tfunction void main() {\n\t\tlet x = 1\n\t}\n}";
+    let diagnostic = Diagnostic::new("Main.jack", source, 3, 12, "expected ';'".to_owned());
+
+    assert_eq!(
+        diagnostic.to_string(),
+        "error: expected ';'\n \
+         --> Main.jack:3:12\n  \
+         |\n\
+         3 | \t\tlet x = 1\n  \
+         | \t\t         ^"
+    );
+    assert_eq!(
+        Diagnostic::at_line("Main.jack", source, 2, String::new()).column,
+        2
+    );
+    assert_eq!(
+        Diagnostic::at_word("Main.jack", source, 3, "x", String::new()).column,
+        7
+    );
+    assert_eq!(
+        Diagnostic::at_word("Main.jack", source, 3, "le", String::new()).column,
+        3
+    );
+    assert!(diagnostic
+        .with_code("J0001")
+        .to_string()
+        .starts_with("error[J0001]: expected ';'\n"));
+}
@@ -0,0 +1,226 @@
+use rustc_hash::{FxHashMap, FxHashSet};
+
+use crate::ast::{
+    Constant, Expr, ExprKind, ExprRef, Identifier, KeywordConstant, Statement, Subroutine,
+    SubroutineCall, VariableType,
+};
+
+/// A local variable whose objects can be freed when its subroutine returns
+#[derive(Debug, Clone, PartialEq)]
+pub struct Disposable {
+    pub name: Identifier,
+    pub class: Identifier,
+}
+
+/// Find the object locals of a subroutine which never let their object escape. Such a local is
+/// only assigned new objects of its own class, string constants or null, and is otherwise only
+/// indexed or used to call methods other than `dispose` in do statements. Anything else, e.g.
+/// passing it to a subroutine, storing it or returning it, could leave another reference to the
+/// object. So could using the result of one of its methods, which may be `this`.
+///
+/// Methods are assumed not to store `this`. Locals declared in if and while bodies are skipped.
+pub fn disposable_locals(subroutine: &Subroutine) -> Vec<Disposable> {
+    let mut candidates: FxHashMap<&str, Identifier> = FxHashMap::default();
+    let mut order = Vec::new();
+    for statement in subroutine.get_statements() {
+        if let Statement::VarDecl(details) = statement {
+            for variable in details.get_variables() {
+                let class = match variable.get_type() {
+                    VariableType::Array => Identifier::new("Array"),
+                    VariableType::ClassName(class) => class.clone(),
+                    _ => continue,
+                };
+                let name = variable.get_identifier();
+                candidates.insert(name.as_str(), class.clone());
+                order.push(Disposable {
+                    name: name.clone(),
+                    class,
+                });
+            }
+        }
+    }
+
+    let mut analysis = Analysis {
+        candidates,
+        escaped: FxHashSet::default(),
+    };
+    for statement in subroutine.get_statements() {
+        analysis.statement(statement, false);
+    }
+
+    order.retain(|local| !analysis.escaped.contains(local.name.as_str()));
+    order
+}
+
+struct Analysis<'a> {
+    /// Each object local, with its class
+    candidates: FxHashMap<&'a str, Identifier>,
+    escaped: FxHashSet<&'a str>,
+}
+
+impl<'a> Analysis<'a> {
+    fn statement(&mut self, statement: &'a Statement, nested: bool) {
+        match statement {
+            Statement::VarDecl(details) if nested => {
+                // A shadowing declaration would make uses ambiguous
+                for variable in details.get_variables() {
+                    self.escape(variable.get_identifier().as_str());
+                }
+            }
+            Statement::VarDecl(_) => {}
+            Statement::Let(details) => {
+                let target = details.get_identifier();
+                let value = details.get_expression();
+                if let Some(index) = target.get_index() {
+                    self.expr(index.root());
+                    self.expr(value.root());
+                } else if self.candidates.contains_key(target.get_name().as_str()) {
+                    self.assignment(target.get_name().as_str(), value);
+                } else {
+                    self.expr(value.root());
+                }
+            }
+            Statement::Do(call) => self.call(call, false),
+            Statement::Return(details) => {
+                if let Some(value) = details.get_value() {
+                    self.expr(value.root());
+                }
+            }
+            Statement::While(details) => {
+                self.expr(details.get_condition().root());
+                for statement in details.get_body() {
+                    self.statement(statement, true);
+                }
+            }
+            Statement::If(details) => {
+                self.expr(details.get_condition().root());
+                let else_body = details.get_else_body().into_iter().flatten();
+                for statement in details.get_if_body().iter().chain(else_body) {
+                    self.statement(statement, true);
+                }
+            }
+        }
+    }
+
+    /// Assigning a fresh object to a candidate doesn't make it escape
+    fn assignment(&mut self, name: &'a str, value: &'a Expr) {
+        let class = self.candidates[name].clone();
+        let class = class.as_str();
+        match value.kind() {
+            ExprKind::Call(call)
+                if call.get_name().as_str() == "new"
+                    && call
+                        .get_target()
+                        .as_ref()
+                        .is_some_and(|target| target.as_str() == class) =>
+            {
+                for parameter in call.get_parameters() {
+                    self.expr(parameter.root());
+                }
+            }
+            ExprKind::Constant(Constant::Keyword(KeywordConstant::Null)) => {}
+            ExprKind::Constant(Constant::String(_)) if class == "String" => {}
+            _ => {
+                self.escape(name);
+                self.expr(value.root());
+            }
+        }
+    }
+
+    /// A method whose result is `used` may return its object, e.g. `String.appendChar`
+    fn call(&mut self, call: &'a SubroutineCall, used: bool) {
+        if let Some(target) = call.get_target() {
+            if used || call.get_name().as_str() == "dispose" {
+                self.escape(target.as_str());
+            }
+        }
+        for parameter in call.get_parameters() {
+            self.expr(parameter.root());
+        }
+    }
+
+    fn expr(&mut self, expr: ExprRef<'a>) {
+        match expr.kind() {
+            ExprKind::VarRef(var) => match var.get_index() {
+                Some(index) => self.expr(index.root()),
+                None => self.escape(var.get_name().as_str()),
+            },
+            ExprKind::Call(call) => self.call(call, true),
+            ExprKind::BinaryExpr { .. } => {
+                for operand in expr.operands() {
+                    self.expr(operand);
+                }
+            }
+            ExprKind::UnaryExpr(_, expr) | ExprKind::BracketedExpr(expr) => self.expr(expr),
+            ExprKind::Constant(_) => {}
+        }
+    }
+
+    fn escape(&mut self, name: &'a str) {
+        if self.candidates.contains_key(name) {
+            self.escaped.insert(name);
+        }
+    }
+}
+
+#[test]
+fn test_disposable_locals() {
+    let ast = crate::parse_strings(&[(
+        "Main.jack",
+        "class Main {
+            function void main() {
+                var Point kept, passed, returned, aliased, disposed;
+                var Array scratch;
+                var String name;
+                var int i;
+                let kept = Point.new(1, 2);
+                do kept.draw();
+                let passed = Point.new(3, 4);
+                do Output.printInt(passed.getX());
+                do Screen.show(passed);
+                let aliased = passed;
+                let disposed = Point.new(5, 6);
+                do disposed.dispose();
+                let scratch = Array.new(10);
+                let scratch[i] = scratch[i + 1];
+                let name = \"Jack\";
+                let name = null;
+                let returned = Point.new(0, 0);
+                return returned;
+            }
+        }",
+    )])
+    .unwrap();
+    let names: Vec<String> = disposable_locals(&ast.classes[0].class.subroutines()[0])
+        .into_iter()
+        .map(|local| format!("{} {}", local.class, local.name))
+        .collect();
+
+    assert_eq!(names, ["Point kept", "Array scratch", "String name"]);
+}
+
+#[test]
+fn test_method_results_escape() {
+    let ast = crate::parse_strings(&[(
+        "Main.jack",
+        "class Main {
+            function String greet() {
+                var String s, t, u;
+                var int length;
+                let s = String.new(5);
+                let t = String.new(5);
+                let u = String.new(5);
+                do u.appendChar(72);
+                let length = t.length();
+                return s.appendChar(72);
+            }
+        }",
+    )])
+    .unwrap();
+    let names: Vec<String> = disposable_locals(&ast.classes[0].class.subroutines()[0])
+        .into_iter()
+        .map(|local| local.name.to_string())
+        .collect();
+
+    assert_eq!(names, ["u"]);
+}
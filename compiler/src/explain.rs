@@ -0,0 +1,365 @@
+/// The longer explanation of each diagnostic code, shown by `--explain`
+pub(crate) const EXPLANATIONS: &[(&str, &str)] = &[
+    (
+        "J0001",
+        "The source doesn't follow Jack's grammar.
+
+The parser stopped at the position shown because what it found there can't continue
+the construct it was reading. The message says what it expected, e.g. a ';' to end a
+statement or a ')' to close a condition.
+
+Erroneous code example:
+
+    class Main {
+        function void main() {
+            let x = 1
+            return;
+        }
+    }
+
+Every statement ends with a semicolon:
+
+    let x = 1;
+
+The error is sometimes reported a little after the real mistake, so check the end of
+the previous line too.",
+    ),
+    (
+        "J0101",
+        "A variable is used without being declared.
+
+Every name read or assigned must be a local (`var`), a parameter, a field or a static
+of the class.
+
+Erroneous code example:
+
+    function int double(int n) {
+        let result = n + n;
+        return result;
+    }
+
+Declare the variable at the top of the subroutine:
+
+    function int double(int n) {
+        var int result;
+        let result = n + n;
+        return result;
+    }",
+    ),
+    (
+        "J0102",
+        "A subroutine is called without a class or object, but the class has no subroutine
+with that name.
+
+A call like `draw()` always means a subroutine of the current class.
+
+Erroneous code example:
+
+    class Main {
+        function void main() {
+            do draw();
+            return;
+        }
+    }
+
+Call the subroutine through the class which declares it, or add it to this class:
+
+    do Screen.drawPixel(0, 0);",
+    ),
+    (
+        "J0104",
+        "A subroutine is called through a name which is neither a variable nor a class of the
+program.
+
+`name.sub()` calls a method when `name` is a variable and a function or constructor of
+the class `name` otherwise, so a misspelt variable is also reported this way.
+
+Erroneous code example:
+
+    var Point p;
+    let p = Point.new(1, 2);
+    do P.draw();
+
+Check the spelling, and that the class's .jack file is compiled along with the rest
+of the program:
+
+    do p.draw();",
+    ),
+    (
+        "J0105",
+        "A subroutine is called with the wrong number of arguments.
+
+Erroneous code example:
+
+    class Point {
+        constructor Point new(int ax, int ay) { ... }
+    }
+
+    let p = Point.new(1);
+
+Pass one argument for each parameter the subroutine declares:
+
+    let p = Point.new(1, 2);",
+    ),
+    (
+        "J0106",
+        "A value is used as the wrong type. Types are only checked with --strict-types.
+
+Erroneous code example:
+
+    var int count;
+    let count = true;
+
+Use a value of the declared type, or change the declaration:
+
+    var boolean done;
+    let done = true;",
+    ),
+    (
+        "J0107",
+        "A method is called without an object.
+
+A method works on the object it's called on, which it sees as `this`. Calling it through
+its class name, or by name alone from a function, gives it no object.
+
+Erroneous code example:
+
+    var Point p;
+    let p = Point.new(1, 2);
+    do Point.draw();
+
+Call the method on the object:
+
+    do p.draw();",
+    ),
+    (
+        "J0108",
+        "A function or constructor is called as if it were a method.
+
+Calling a subroutine on a variable, or by name alone, passes an object as an extra first
+argument, which only a method expects.
+
+Erroneous code example:
+
+    class Point {
+        function int distance(Point a, Point b) { ... }
+        method int distanceTo(Point other) { return distance(this, other); }
+    }
+
+Call functions and constructors through their class:
+
+    return Point.distance(this, other);",
+    ),
+    (
+        "J0201",
+        "The result of a void subroutine is used as a value.
+
+A void subroutine returns 0, which is never meaningful.
+
+Erroneous code example:
+
+    let x = Output.printInt(3);
+
+Call it with `do` instead:
+
+    do Output.printInt(3);",
+    ),
+    (
+        "J0202",
+        "A string constant is longer than a string the OS will create.
+
+String constants are built with String.new and appendChar, and the OS may refuse a
+string of more than 255 characters.
+
+Erroneous code example:
+
+    do Output.printString(\"...a very long message...\");
+
+Split the text into several shorter strings and print them one after another.",
+    ),
+    (
+        "J0203",
+        "A method reads a field which no constructor of the class assigns.
+
+Memory.alloc doesn't clear the memory it returns, so the field starts out holding
+whatever was there before.
+
+Erroneous code example:
+
+    class Counter {
+        field int count;
+        constructor Counter new() { return this; }
+        method void increment() { let count = count + 1; return; }
+    }
+
+Assign the field in the constructor:
+
+    constructor Counter new() { let count = 0; return this; }",
+    ),
+    (
+        "J0204",
+        "A local, parameter, field or static is declared but its value is never read.
+
+Assigning a variable doesn't count as using it, so a variable which is only ever
+assigned is reported too. This is often a sign of a typo or of code left over from a
+change.
+
+Erroneous code example:
+
+    function int area(int width, int height) {
+        var int result;
+        let result = width * width;
+        return result;
+    }
+
+Use the variable, or remove it:
+
+    let result = width * height;
+
+Compile with --deny-warnings to treat this and every other warning as an error.",
+    ),
+    (
+        "J0205",
+        "Statements follow a return in the same block, so they can never run.
+
+Reported by --eliminate-dead-code, which leaves the statements out of the VM code.
+
+Erroneous code example:
+
+    function int double(int x) {
+        return x + x;
+        do Output.printInt(x);
+    }
+
+Move the statements before the return, or remove them.",
+    ),
+    (
+        "J0206",
+        "A subroutine is never called by anything which runs from Main.main or Sys.init.
+
+Reported by --eliminate-dead-code, which leaves the subroutine out of the VM code. Only
+calls written in the program count, so a subroutine kept for a test harness to call is
+reported too.
+
+Erroneous code example:
+
+    class Main {
+        function void main() { return; }
+        function void greet() { do Output.printString(\"Hello\"); return; }
+    }
+
+Call the subroutine, or remove it.",
+    ),
+    (
+        "J0207",
+        "A local variable may be read before any let statement assigns it.
+
+Locals start out as 0, so the program is valid, but reading one before assigning it
+is almost always a mistake. A local only counts as assigned after an if statement if
+both branches assign it, and never after a while loop, whose body may not run.
+
+Erroneous code example:
+
+    function int sum(Array values, int length) {
+        var int i, total;
+        while (i < length) {
+            let total = total + values[i];
+            let i = i + 1;
+        }
+        return total;
+    }
+
+Assign the locals first:
+
+    let i = 0;
+    let total = 0;",
+    ),
+];
+
+/// The longer explanation of a diagnostic code such as `J0101`, in any case
+pub fn explain(code: &str) -> Option<&'static str> {
+    EXPLANATIONS
+        .iter()
+        .find(|(known, _)| known.eq_ignore_ascii_case(code))
+        .map(|(_, explanation)| *explanation)
+}
+
+#[test]
+fn test_every_code_is_explained() {
+    use crate::{CompilationError, CompilationWarning};
+
+    let errors = [
+        CompilationError::MissingVariable {
+            var_name: String::new(),
+        },
+        CompilationError::MissingSubroutine {
+            class_name: String::new(),
+            subroutine_name: String::new(),
+        },
+        CompilationError::UnknownClass {
+            subroutine: String::new(),
+            class_name: String::new(),
+        },
+        CompilationError::WrongArgumentCount {
+            subroutine: String::new(),
+            callee: String::new(),
+            expected: 0,
+            found: 1,
+        },
+        CompilationError::TypeError {
+            subroutine: String::new(),
+            message: String::new(),
+        },
+        CompilationError::MethodWithoutObject {
+            subroutine: String::new(),
+            callee: String::new(),
+        },
+        CompilationError::NotAMethod {
+            subroutine: String::new(),
+            callee: String::new(),
+        },
+    ];
+    let warnings = [
+        CompilationWarning::VoidResultUsed {
+            subroutine: String::new(),
+            callee: String::new(),
+        },
+        CompilationWarning::LongString {
+            subroutine: String::new(),
+            length: 0,
+        },
+        CompilationWarning::UninitializedField {
+            method: String::new(),
+            field: String::new(),
+        },
+        CompilationWarning::UnusedVariable {
+            scope: String::new(),
+            kind: "local",
+            name: String::new(),
+            line: 0,
+        },
+        CompilationWarning::UnreachableCode {
+            subroutine: String::new(),
+            line: 0,
+        },
+        CompilationWarning::UnusedSubroutine {
+            subroutine: String::new(),
+            line: 0,
+        },
+        CompilationWarning::UninitializedLocal {
+            subroutine: String::new(),
+            name: String::new(),
+            line: 0,
+        },
+    ];
+    let codes: Vec<&str> = std::iter::once(crate::ParseError::CODE)
+        .chain(errors.iter().map(CompilationError::code))
+        .chain(warnings.iter().map(CompilationWarning::code))
+        .collect();
+
+    assert_eq!(codes.len(), EXPLANATIONS.len());
+    for code in codes {
+        assert!(explain(code).is_some(), "{} is not explained", code);
+    }
+    assert_eq!(explain("j0101"), explain("J0101"));
+}
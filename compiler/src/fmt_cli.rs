@@ -0,0 +1,82 @@
+use std::fs;
+use std::path::Path;
+
+use clap::{Arg, ArgAction, ArgMatches, Command, ValueHint};
+use parse_utils::output::write_atomic;
+
+use crate::{find_jack_files, format_jack, ErrorType};
+
+/// The command line interface of the formatter, shared by jackfmt and n2t
+pub fn command() -> Command {
+    Command::new("jackfmt")
+        .about("Format Jack source files")
+        .arg(
+            Arg::new("SOURCE")
+                .required(true)
+                .num_args(1..)
+                .value_name("FILE")
+                .value_hint(ValueHint::AnyPath)
+                .help("Jack source files or directories of them"),
+        )
+        .arg(
+            Arg::new("check")
+                .long("check")
+                .action(ArgAction::SetTrue)
+                .conflicts_with("in_place")
+                .help("List the files which aren't formatted, failing if there are any, without changing them"),
+        )
+        .arg(
+            Arg::new("in_place")
+                .long("in-place")
+                .short('i')
+                .action(ArgAction::SetTrue)
+                .help("Rewrite the files rather than printing the formatted source"),
+        )
+}
+
+pub fn run(matches: &ArgMatches) -> Result<(), ErrorType> {
+    let check = matches.get_flag("check");
+    let in_place = matches.get_flag("in_place");
+
+    let mut unformatted = 0;
+    for source in matches
+        .get_many::<String>("SOURCE")
+        .expect("User to provide a source file")
+    {
+        for file in find_jack_files(source)? {
+            let path = Path::new(&file);
+            let contents = fs::read_to_string(path).map_err(|source| ErrorType::ReadError {
+                path: path.to_owned(),
+                source,
+            })?;
+            let filename = path
+                .file_name()
+                .map(|name| name.to_string_lossy().into_owned())
+                .unwrap_or_default();
+            let formatted = format_jack(&filename, &contents)?;
+
+            if check {
+                if formatted != contents {
+                    println!("{} is not formatted", path.display());
+                    unformatted += 1;
+                }
+            } else if in_place {
+                if formatted != contents {
+                    write_atomic(path, formatted.as_bytes()).map_err(|source| {
+                        ErrorType::WriteError {
+                            path: path.to_owned(),
+                            source,
+                        }
+                    })?;
+                }
+            } else {
+                print!("{}", formatted);
+            }
+        }
+    }
+
+    if unformatted > 0 {
+        return Err(ErrorType::Unformatted(unformatted));
+    }
+    Ok(())
+}
@@ -0,0 +1,206 @@
+use crate::ast::{
+    BinaryOp, Constant, Expr, ExprKind, ExprRef, KeywordConstant, Statement, SubroutineCall,
+    UnaryOp, VariableRef, Visit,
+};
+use crate::lowering::Lowering;
+
+/// The value of an expression made only of constants
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Value {
+    Int(i16),
+    Bool(bool),
+}
+
+/// Folds constant arithmetic and removes branches which can never run
+pub struct Folder {
+    /// `*` and `/` are only folded when they go to the OS, as a program's own routines may differ
+    fold_multiply: bool,
+    fold_divide: bool,
+}
+
+impl Folder {
+    pub fn new(lowering: &Lowering) -> Self {
+        let default = Lowering::default();
+        Folder {
+            fold_multiply: lowering.multiply == default.multiply,
+            fold_divide: lowering.divide == default.divide,
+        }
+    }
+
+    pub fn fold_statements(&self, statements: &[Statement]) -> Vec<Statement> {
+        let mut folded = Vec::with_capacity(statements.len());
+        for statement in statements {
+            match statement {
+                Statement::Let(details) => {
+                    let mut details = details.clone();
+                    details.identifier = self.fold_variable(&details.identifier);
+                    details.expression = self.fold_expression(&details.expression);
+                    folded.push(Statement::Let(details));
+                }
+                Statement::While(details) => {
+                    let condition = self.fold_expression(&details.condition);
+                    if value_of(&condition) == Some(Value::Bool(false)) {
+                        continue;
+                    }
+                    let mut details = details.clone();
+                    details.condition = condition;
+                    details.body = self.fold_statements(&details.body);
+                    folded.push(Statement::While(details));
+                }
+                Statement::If(details) => {
+                    let condition = self.fold_expression(&details.condition);
+                    let if_body = self.fold_statements(&details.if_body);
+                    let else_body = details
+                        .else_body
+                        .as_ref()
+                        .map(|body| self.fold_statements(body));
+
+                    // A branch is only spliced in when that doesn't widen the scope of its vars
+                    let taken = match value_of(&condition) {
+                        Some(Value::Bool(true)) => Some(if_body.clone()),
+                        Some(Value::Bool(false)) => Some(else_body.clone().unwrap_or_default()),
+                        _ => None,
+                    };
+                    match taken {
+                        Some(body) if !body.iter().any(is_var_decl) => folded.extend(body),
+                        _ => {
+                            let mut details = details.clone();
+                            details.condition = condition;
+                            details.if_body = if_body;
+                            details.else_body = else_body;
+                            folded.push(Statement::If(details));
+                        }
+                    }
+                }
+                Statement::Do(call) => folded.push(Statement::Do(self.fold_call(call))),
+                Statement::Return(details) => {
+                    let mut details = details.clone();
+                    details.value = details.value.map(|value| self.fold_expression(&value));
+                    folded.push(Statement::Return(details));
+                }
+                Statement::VarDecl(_) => folded.push(statement.clone()),
+            }
+        }
+        folded
+    }
+
+    pub fn fold_expression(&self, expr: &Expr) -> Expr {
+        self.fold(expr.root())
+    }
+
+    fn fold(&self, expr: ExprRef) -> Expr {
+        match expr.kind() {
+            ExprKind::Constant(constant) => constant.clone().as_expr(),
+            ExprKind::VarRef(var) => Expr::var(self.fold_variable(var)),
+            ExprKind::Call(call) => Expr::from_call(self.fold_call(call)),
+            ExprKind::BracketedExpr(inner) => {
+                let inner = self.fold(inner);
+                match value_of(&inner) {
+                    Some(_) => inner,
+                    None => Expr::brackets(inner),
+                }
+            }
+            ExprKind::UnaryExpr(op, operand) => {
+                let operand = self.fold(operand);
+                let value = match (op, value_of(&operand)) {
+                    (UnaryOp::Minus, Some(Value::Int(n))) => Some(Value::Int(n.wrapping_neg())),
+                    (UnaryOp::Not, Some(Value::Int(n))) => Some(Value::Int(!n)),
+                    (UnaryOp::Not, Some(Value::Bool(b))) => Some(Value::Bool(!b)),
+                    _ => None,
+                };
+                value
+                    .and_then(to_expr)
+                    .unwrap_or_else(|| Expr::unary_op(op, operand))
+            }
+            ExprKind::BinaryExpr { .. } => {
+                let mut folded = Vec::new();
+                for visit in expr.postfix() {
+                    match visit {
+                        Visit::Operand(operand) => folded.push(self.fold(operand)),
+                        Visit::Operator(op) => {
+                            let rhs = folded.pop().expect("Every operator has two operands");
+                            let lhs = folded.pop().expect("Every operator has two operands");
+                            let value = match (value_of(&lhs), value_of(&rhs)) {
+                                (Some(a), Some(b)) => self.binary(a, op, b),
+                                _ => None,
+                            };
+                            folded.push(
+                                value
+                                    .and_then(to_expr)
+                                    .unwrap_or_else(|| Expr::binary_op(lhs, op, rhs)),
+                            );
+                        }
+                    }
+                }
+                folded.pop().expect("A chain folds into one expression")
+            }
+        }
+    }
+
+    fn binary(&self, lhs: Value, op: BinaryOp, rhs: Value) -> Option<Value> {
+        use Value::{Bool, Int};
+        let value = match (lhs, op, rhs) {
+            (Int(a), BinaryOp::Plus, Int(b)) => Int(a.wrapping_add(b)),
+            (Int(a), BinaryOp::Minus, Int(b)) => Int(a.wrapping_sub(b)),
+            (Int(a), BinaryOp::Mult, Int(b)) if self.fold_multiply => Int(a.wrapping_mul(b)),
+            // The OS may round negative quotients either way, so only those it agrees on
+            (Int(a), BinaryOp::Div, Int(b)) if self.fold_divide && a >= 0 && b > 0 => Int(a / b),
+            (Int(a), BinaryOp::And, Int(b)) => Int(a & b),
+            (Int(a), BinaryOp::Or, Int(b)) => Int(a | b),
+            (Int(a), BinaryOp::Lt, Int(b)) => Bool(a < b),
+            (Int(a), BinaryOp::Gt, Int(b)) => Bool(a > b),
+            (Int(a), BinaryOp::Eq, Int(b)) => Bool(a == b),
+            (Bool(a), BinaryOp::And, Bool(b)) => Bool(a && b),
+            (Bool(a), BinaryOp::Or, Bool(b)) => Bool(a || b),
+            (Bool(a), BinaryOp::Eq, Bool(b)) => Bool(a == b),
+            _ => return None,
+        };
+        Some(value)
+    }
+
+    fn fold_variable(&self, var: &VariableRef) -> VariableRef {
+        match var.get_index() {
+            Some(index) => {
+                VariableRef::new_with_index(var.get_name().as_str(), self.fold_expression(index))
+            }
+            None => var.clone(),
+        }
+    }
+
+    fn fold_call(&self, call: &SubroutineCall) -> SubroutineCall {
+        let parameters = call
+            .get_parameters()
+            .iter()
+            .map(|parameter| self.fold_expression(parameter))
+            .collect();
+        call.with_parameters(parameters)
+    }
+}
+
+fn value_of(expr: &Expr) -> Option<Value> {
+    match expr.kind() {
+        ExprKind::Constant(Constant::Int(n)) => i16::try_from(*n).ok().map(Value::Int),
+        ExprKind::Constant(Constant::Keyword(KeywordConstant::True)) => Some(Value::Bool(true)),
+        ExprKind::Constant(Constant::Keyword(KeywordConstant::False)) => Some(Value::Bool(false)),
+        ExprKind::UnaryExpr(UnaryOp::Minus, operand) => match operand.kind() {
+            ExprKind::Constant(Constant::Int(n)) => i16::try_from(-*n).ok().map(Value::Int),
+            _ => None,
+        },
+        _ => None,
+    }
+}
+
+/// The expression for a value. -32768 has no constant to negate, so it isn't folded.
+fn to_expr(value: Value) -> Option<Expr> {
+    match value {
+        Value::Bool(true) => Some(Expr::true_c()),
+        Value::Bool(false) => Some(Expr::false_c()),
+        Value::Int(i16::MIN) => None,
+        Value::Int(n) if n < 0 => Some(Expr::unary_op(UnaryOp::Minus, Expr::int(-(n as i32)))),
+        Value::Int(n) => Some(Expr::int(n as i32)),
+    }
+}
+
+fn is_var_decl(statement: &Statement) -> bool {
+    matches!(statement, Statement::VarDecl(_))
+}
@@ -0,0 +1,574 @@
+/*
+ * Pretty-printing Jack source.
+ *
+ * The class is parsed and printed back from the AST, which doesn't keep comments or blank lines.
+ * Both are put back by lining up the tokens of the source with those of the printed class, which
+ * come in the same order: a comment stays on the line of the token it followed, or goes on its
+ * own line above the token it preceded.
+ */
+
+use parse_utils::tokens::{Token, TokenKind};
+
+use crate::ast::{
+    BinaryOp, Class, ClassVariable, ClassVariableVisibility, Constant, ExprKind, ExprRef,
+    KeywordConstant, ReturnType, Statement, Subroutine, SubroutineCall, SubroutineType, UnaryOp,
+    VariableRef, VariableType, Visit,
+};
+use crate::parser::{parse_jack, FileInput};
+use crate::{tokenize_jack, ErrorType, ParseOptions};
+
+const INDENT: &str = "    ";
+
+/// Format the source of a Jack class
+pub fn format_jack(filename: &str, source: &str) -> Result<String, ErrorType> {
+    let ast = parse_jack(
+        vec![FileInput::new(filename, source)],
+        ParseOptions::default(),
+    )?;
+    let mut printer = Printer::default();
+    printer.class(&ast.classes[0].class);
+
+    restore_comments(source, &printer.out).map_err(|line| ErrorType::FormatMismatch {
+        file: filename.to_owned(),
+        line,
+    })
+}
+
+#[derive(Default)]
+struct Printer {
+    out: String,
+    depth: usize,
+}
+
+impl Printer {
+    fn line(&mut self, text: &str) {
+        if !text.is_empty() {
+            self.out.push_str(&INDENT.repeat(self.depth));
+            self.out.push_str(text);
+        }
+        self.out.push('\n');
+    }
+
+    fn class(&mut self, class: &Class) {
+        self.line(&format!("class {} {{", class.get_name()));
+        self.depth += 1;
+
+        // The parser splits `field int x, y;` into a variable each, all on the same line
+        let variables = class.variables();
+        let mut start = 0;
+        while start < variables.len() {
+            let first = &variables[start];
+            let same_declaration = |variable: &&ClassVariable| {
+                variable.get_line() == first.get_line()
+                    && variable.get_visibility() == first.get_visibility()
+                    && type_name(&variable.get_var_type()) == type_name(&first.get_var_type())
+            };
+            let count = variables[start..]
+                .iter()
+                .take_while(same_declaration)
+                .count();
+            let names: Vec<&str> = variables[start..start + count]
+                .iter()
+                .map(|variable| variable.get_identifier().as_str())
+                .collect();
+            let visibility = match first.get_visibility() {
+                ClassVariableVisibility::Field => "field",
+                ClassVariableVisibility::Static => "static",
+            };
+            self.line(&format!(
+                "{} {} {};",
+                visibility,
+                type_name(&first.get_var_type()),
+                names.join(", ")
+            ));
+            start += count;
+        }
+
+        for (index, subroutine) in class.subroutines().iter().enumerate() {
+            if index > 0 || !variables.is_empty() {
+                self.line("");
+            }
+            self.subroutine(subroutine);
+        }
+
+        self.depth -= 1;
+        self.line("}");
+    }
+
+    fn subroutine(&mut self, subroutine: &Subroutine) {
+        let kind = match subroutine.get_subroutine_type() {
+            SubroutineType::Function => "function",
+            SubroutineType::Constructor => "constructor",
+            SubroutineType::Method => "method",
+        };
+        let return_type = match subroutine.get_return_type() {
+            ReturnType::Int => "int",
+            ReturnType::Char => "char",
+            ReturnType::Boolean => "boolean",
+            ReturnType::Void => "void",
+            ReturnType::ClassName(name) => name.as_str(),
+        };
+        let parameters: Vec<String> = subroutine
+            .get_parameters()
+            .iter()
+            .map(|parameter| {
+                format!(
+                    "{} {}",
+                    type_name(parameter.get_type()),
+                    parameter.get_identifier()
+                )
+            })
+            .collect();
+        self.line(&format!(
+            "{} {} {}({}) {{",
+            kind,
+            return_type,
+            subroutine.get_name(),
+            parameters.join(", ")
+        ));
+        self.block(subroutine.get_statements());
+        self.line("}");
+    }
+
+    fn block(&mut self, statements: &[Statement]) {
+        self.depth += 1;
+        for statement in statements {
+            self.statement(statement);
+        }
+        self.depth -= 1;
+    }
+
+    fn statement(&mut self, statement: &Statement) {
+        match statement {
+            Statement::VarDecl(details) => {
+                let variables = details.get_variables();
+                let names: Vec<&str> = variables
+                    .iter()
+                    .map(|variable| variable.get_identifier().as_str())
+                    .collect();
+                self.line(&format!(
+                    "var {} {};",
+                    type_name(variables[0].get_type()),
+                    names.join(", ")
+                ));
+            }
+            Statement::Let(details) => self.line(&format!(
+                "let {} = {};",
+                variable(details.get_identifier()),
+                expression(details.get_expression().root())
+            )),
+            Statement::Do(call) => self.line(&format!("do {};", subroutine_call(call))),
+            Statement::Return(details) => match details.get_value() {
+                Some(value) => self.line(&format!("return {};", expression(value.root()))),
+                None => self.line("return;"),
+            },
+            Statement::While(details) => {
+                let condition = expression(details.get_condition().root());
+                if details.get_body().is_empty() {
+                    self.line(&format!("while ({}) {{}}", condition));
+                    return;
+                }
+                self.line(&format!("while ({}) {{", condition));
+                self.block(details.get_body());
+                self.line("}");
+            }
+            Statement::If(details) => {
+                let condition = expression(details.get_condition().root());
+                if details.get_if_body().is_empty() && details.get_else_body().is_none() {
+                    self.line(&format!("if ({}) {{}}", condition));
+                    return;
+                }
+                self.line(&format!("if ({}) {{", condition));
+                self.block(details.get_if_body());
+                match details.get_else_body() {
+                    Some(else_body) if else_body.is_empty() => self.line("} else {}"),
+                    Some(else_body) => {
+                        self.line("} else {");
+                        self.block(else_body);
+                        self.line("}");
+                    }
+                    None => self.line("}"),
+                }
+            }
+        }
+    }
+}
+
+fn type_name(var_type: &VariableType) -> String {
+    match var_type {
+        VariableType::Array => "Array".to_owned(),
+        VariableType::Int => "int".to_owned(),
+        VariableType::Char => "char".to_owned(),
+        VariableType::Boolean => "boolean".to_owned(),
+        VariableType::ClassName(name) => name.to_string(),
+    }
+}
+
+fn variable(var: &VariableRef) -> String {
+    match var.get_index() {
+        Some(index) => format!("{}[{}]", var.get_name(), expression(index.root())),
+        None => var.get_name().to_string(),
+    }
+}
+
+fn subroutine_call(call: &SubroutineCall) -> String {
+    let arguments: Vec<String> = call
+        .get_parameters()
+        .iter()
+        .map(|argument| expression(argument.root()))
+        .collect();
+    format!("{}({})", call.name_as_string(), arguments.join(", "))
+}
+
+fn expression(expr: ExprRef) -> String {
+    match expr.kind() {
+        ExprKind::Constant(Constant::Int(value)) => value.to_string(),
+        ExprKind::Constant(Constant::String(text)) => format!("\"{}\"", text),
+        ExprKind::Constant(Constant::Keyword(keyword)) => match keyword {
+            KeywordConstant::True => "true",
+            KeywordConstant::False => "false",
+            KeywordConstant::Null => "null",
+            KeywordConstant::This => "this",
+        }
+        .to_owned(),
+        ExprKind::VarRef(var) => variable(var),
+        ExprKind::UnaryExpr(op, operand) => {
+            let op = match op {
+                UnaryOp::Minus => "-",
+                UnaryOp::Not => "~",
+            };
+            format!("{}{}", op, expression(operand))
+        }
+        ExprKind::BinaryExpr { .. } => {
+            let mut printed: Vec<String> = Vec::new();
+            for visit in expr.postfix() {
+                match visit {
+                    Visit::Operand(operand) => printed.push(expression(operand)),
+                    Visit::Operator(op) => {
+                        let rhs = printed.pop().expect("Every operator has two operands");
+                        let lhs = printed.pop().expect("Every operator has two operands");
+                        printed.push(format!("{} {} {}", lhs, binary_operator(op), rhs));
+                    }
+                }
+            }
+            printed.pop().expect("A chain prints as one expression")
+        }
+        ExprKind::BracketedExpr(inner) => format!("({})", expression(inner)),
+        ExprKind::Call(call) => subroutine_call(call),
+    }
+}
+
+fn binary_operator(op: BinaryOp) -> &'static str {
+    match op {
+        BinaryOp::Plus => "+",
+        BinaryOp::Minus => "-",
+        BinaryOp::Mult => "*",
+        BinaryOp::Div => "/",
+        BinaryOp::And => "&",
+        BinaryOp::Or => "|",
+        BinaryOp::Lt => "<",
+        BinaryOp::Gt => ">",
+        BinaryOp::Eq => "=",
+    }
+}
+
+/// A comment on a line of its own, above the token it precedes
+struct Leading {
+    text: String,
+    blank_before: bool,
+}
+
+/// Put the comments and blank lines of `source` into `formatted`, which has the same tokens
+/// without any comments. Fails with the source line of the first token which doesn't match.
+fn restore_comments(source: &str, formatted: &str) -> Result<String, usize> {
+    let (comments, code): (Vec<Token>, Vec<Token>) = tokenize_jack(source)
+        .into_iter()
+        .partition(|token| token.kind == TokenKind::Comment);
+    let printed = tokenize_jack(formatted);
+
+    let text =
+        |text: &str, token: &Token| text[token.offset..token.offset + token.length].to_owned();
+    let source_line = |offset: usize| source[..offset].matches('\n').count() + 1;
+    for (index, token) in code.iter().enumerate() {
+        let matches = printed.get(index).is_some_and(|output| {
+            text(source, token) == text(formatted, output)
+                // Numbers are printed without leading zeros
+                || (token.kind == TokenKind::Number && output.kind == TokenKind::Number)
+        });
+        if !matches {
+            return Err(source_line(token.offset));
+        }
+    }
+    if printed.len() != code.len() {
+        return Err(source_line(source.len()));
+    }
+
+    // Sort the comments by the code token they follow or precede
+    let count = code.len();
+    let mut leading: Vec<Vec<Leading>> = (0..=count).map(|_| Vec::new()).collect();
+    let mut inline: Vec<Vec<String>> = vec![Vec::new(); count + 1];
+    let mut trailing: Vec<Vec<String>> = vec![Vec::new(); count + 1];
+    let mut blank_before = vec![false; count + 1];
+    let mut next_code = 0;
+    let mut previous_end = 0;
+    let blank_between = |from: usize, to: usize| source[from..to].matches('\n').count() >= 2;
+    for comment in &comments {
+        while next_code < count && code[next_code].offset < comment.offset {
+            blank_before[next_code] = blank_between(previous_end, code[next_code].offset);
+            previous_end = code[next_code].offset + code[next_code].length;
+            next_code += 1;
+        }
+        let comment_end = comment.offset + comment.length;
+        let after_code = next_code > 0 && {
+            let previous = &code[next_code - 1];
+            !source[previous.offset + previous.length..comment.offset].contains('\n')
+        };
+        let before_code =
+            next_code < count && !source[comment_end..code[next_code].offset].contains('\n');
+        let comment_text = text(source, comment);
+
+        if after_code && before_code {
+            inline[next_code].push(comment_text);
+        } else if after_code {
+            trailing[next_code - 1].push(comment_text);
+        } else {
+            leading[next_code].push(Leading {
+                text: comment_text,
+                blank_before: blank_between(previous_end, comment.offset),
+            });
+        }
+        previous_end = comment_end;
+    }
+    while next_code < count {
+        blank_before[next_code] = blank_between(previous_end, code[next_code].offset);
+        previous_end = code[next_code].offset + code[next_code].length;
+        next_code += 1;
+    }
+
+    // An empty block is printed as `{}`, but the comments inside one need the `}` on a line of
+    // its own to stay inside it
+    let mut expanded = String::with_capacity(formatted.len());
+    let mut copied = 0;
+    for index in 1..count {
+        let empty_block =
+            text(formatted, &printed[index - 1]) == "{" && text(formatted, &printed[index]) == "}";
+        if empty_block && (!trailing[index - 1].is_empty() || !leading[index].is_empty()) {
+            let offset = printed[index].offset;
+            let line_start = formatted[..offset]
+                .rfind('\n')
+                .map_or(0, |newline| newline + 1);
+            let line = &formatted[line_start..offset];
+            expanded.push_str(&formatted[copied..offset]);
+            expanded.push('\n');
+            expanded.push_str(&line[..line.len() - line.trim_start().len()]);
+            copied = offset;
+        }
+    }
+    expanded.push_str(&formatted[copied..]);
+    let formatted = expanded.as_str();
+    let printed = tokenize_jack(formatted);
+
+    let mut lines: Vec<String> = Vec::new();
+    // Blank lines are kept between statements, but not at the start or end of a block
+    let push_blank = |lines: &mut Vec<String>| {
+        if lines
+            .last()
+            .is_some_and(|line| !line.is_empty() && !line.ends_with('{'))
+        {
+            lines.push(String::new());
+        }
+    };
+
+    let mut token = 0;
+    let mut line_start = 0;
+    for line in formatted.lines() {
+        let line_end = line_start + line.len();
+        let first = token;
+        while token < count && printed[token].offset < line_end {
+            token += 1;
+        }
+        let on_line = first..token;
+
+        if on_line.is_empty() {
+            push_blank(&mut lines);
+            line_start = line_end + 1;
+            continue;
+        }
+
+        let indent = &line[..line.len() - line.trim_start().len()];
+        let closes_block = line.trim_start().starts_with('}');
+        for index in on_line.clone() {
+            for comment in &leading[index] {
+                if comment.blank_before && index == first {
+                    push_blank(&mut lines);
+                }
+                // A comment at the end of a block belongs inside it
+                let comment_indent = if closes_block && index == first {
+                    format!("{}{}", indent, INDENT)
+                } else {
+                    indent.to_owned()
+                };
+                lines.push(format!(
+                    "{}{}",
+                    comment_indent,
+                    normalize_comment(&comment.text, &comment_indent)
+                ));
+            }
+        }
+        if blank_before[first] && leading[first].is_empty() && !closes_block {
+            push_blank(&mut lines);
+        }
+
+        let mut text = String::new();
+        let mut copied = line_start;
+        for index in on_line.clone() {
+            for comment in &inline[index] {
+                text.push_str(&formatted[copied..printed[index].offset]);
+                copied = printed[index].offset;
+                text.push_str(&normalize_comment(comment, indent));
+                text.push(' ');
+            }
+        }
+        text.push_str(&formatted[copied..line_end]);
+        for index in on_line {
+            for comment in &trailing[index] {
+                text.push(' ');
+                text.push_str(&normalize_comment(comment, indent));
+            }
+        }
+        lines.push(text);
+        line_start = line_end + 1;
+    }
+
+    for comment in &leading[count] {
+        if comment.blank_before {
+            push_blank(&mut lines);
+        }
+        lines.push(normalize_comment(&comment.text, ""));
+    }
+
+    let mut output = lines.join("\n");
+    output.push('\n');
+    Ok(output)
+}
+
+/// Trim a comment, put a space after `//`, and line up the `*`s of a block comment
+fn normalize_comment(comment: &str, indent: &str) -> String {
+    if let Some(body) = comment.strip_prefix("//") {
+        let body = body.trim_end();
+        if body.is_empty() || body.starts_with([' ', '/']) {
+            format!("//{}", body)
+        } else {
+            format!("// {}", body)
+        }
+    } else {
+        let mut lines = comment.lines();
+        let mut normalized = lines.next().unwrap_or_default().trim_end().to_owned();
+        for line in lines {
+            let line = line.trim();
+            normalized.push('\n');
+            normalized.push_str(indent);
+            if line.starts_with('*') {
+                normalized.push(' ');
+            }
+            normalized.push_str(line);
+        }
+        normalized
+    }
+}
+
+#[test]
+fn test_format_jack() {
+    let source = "// A point\nclass Point{field int x,y;   static int count;
+/** Make a point
+      * at x, y */
+constructor Point new(int ax,int ay){let x=ax;   //the x
+let y=ay;
+
+  let count=count+(1*2); return this; }
+  method void draw(){ var Array a; if(~(x<0)){do Screen.drawPixel(x,y); } else {let a[0]=-x; }
+  while (/* forever */ true) { do draw(); }
+  while (x) { }
+  // done
+  return; }
+}
+";
+    let expected = "// A point
+class Point {
+    field int x, y;
+    static int count;
+
+    /** Make a point
+     * at x, y */
+    constructor Point new(int ax, int ay) {
+        let x = ax; // the x
+        let y = ay;
+
+        let count = count + (1 * 2);
+        return this;
+    }
+
+    method void draw() {
+        var Array a;
+        if (~(x < 0)) {
+            do Screen.drawPixel(x, y);
+        } else {
+            let a[0] = -x;
+        }
+        while (/* forever */ true) {
+            do draw();
+        }
+        while (x) {}
+        // done
+        return;
+    }
+}
+";
+    let formatted = format_jack("Point.jack", source).unwrap();
+    assert_eq!(formatted, expected);
+    assert_eq!(format_jack("Point.jack", &formatted).unwrap(), expected);
+
+    // Two declarations sharing a line look like one to the printer
+    assert!(matches!(
+        format_jack("Main.jack", "class Main {\n field int x; field int y;\n}"),
+        Err(ErrorType::FormatMismatch { line: 2, .. })
+    ));
+}
+
+#[test]
+fn test_comments_in_empty_blocks() {
+    let source = "class Main {
+    function void main() {
+        if (x) {
+            // note
+        }
+        while (x) { // spin
+        }
+        if (x) {} else {
+            /* nothing */
+        }
+        if (x) {}
+        return;
+    }
+}
+";
+    let expected = "class Main {
+    function void main() {
+        if (x) {
+            // note
+        }
+        while (x) { // spin
+        }
+        if (x) {
+        } else {
+            /* nothing */
+        }
+        if (x) {}
+        return;
+    }
+}
+";
+    let formatted = format_jack("Main.jack", source).unwrap();
+    assert_eq!(formatted, expected);
+    assert_eq!(format_jack("Main.jack", &formatted).unwrap(), expected);
+}
@@ -0,0 +1,108 @@
+use crate::ast::{Class, Statement};
+
+/// Move the `var` declarations of each subroutine to its top, in the order they appear, when any
+/// are nested in if or while bodies. Without `--extensions` Jack has no block scopes, so a nested
+/// variable belongs to the whole subroutine, and every pass sees it that way once it's hoisted.
+/// Returns `None` if there is nothing to move.
+pub fn hoist_var_decls(class: &Class) -> Option<Class> {
+    let nested = class
+        .subroutines()
+        .iter()
+        .flat_map(|subroutine| subroutine.get_statements())
+        .any(has_nested_var_decl);
+    if !nested {
+        return None;
+    }
+
+    let subroutines = class
+        .subroutines()
+        .iter()
+        .map(|subroutine| {
+            let mut declarations = Vec::new();
+            let body = without_var_decls(subroutine.get_statements(), &mut declarations);
+            declarations.extend(body);
+            subroutine.with_statements(declarations)
+        })
+        .collect();
+    Some(
+        Class::new(class.get_name().as_str())
+            .add_variables(class.variables().clone())
+            .add_subroutines(subroutines),
+    )
+}
+
+fn has_nested_var_decl(statement: &Statement) -> bool {
+    let declares = |body: &[Statement]| {
+        body.iter().any(|statement| {
+            matches!(statement, Statement::VarDecl(_)) || has_nested_var_decl(statement)
+        })
+    };
+    match statement {
+        Statement::While(details) => declares(&details.body),
+        Statement::If(details) => {
+            declares(&details.if_body) || details.else_body.as_deref().is_some_and(declares)
+        }
+        _ => false,
+    }
+}
+
+/// The statements with every var declaration, however deeply nested, moved to `declarations`
+fn without_var_decls(
+    statements: &[Statement],
+    declarations: &mut Vec<Statement>,
+) -> Vec<Statement> {
+    let mut kept = Vec::with_capacity(statements.len());
+    for statement in statements {
+        match statement {
+            Statement::VarDecl(_) => declarations.push(statement.clone()),
+            Statement::While(details) => {
+                let mut details = details.clone();
+                details.body = without_var_decls(&details.body, declarations);
+                kept.push(Statement::While(details));
+            }
+            Statement::If(details) => {
+                let mut details = details.clone();
+                details.if_body = without_var_decls(&details.if_body, declarations);
+                details.else_body = details
+                    .else_body
+                    .map(|body| without_var_decls(&body, declarations));
+                kept.push(Statement::If(details));
+            }
+            _ => kept.push(statement.clone()),
+        }
+    }
+    kept
+}
+
+#[test]
+fn test_hoist_var_decls() {
+    let ast = crate::parse_strings(&[(
+        "Main.jack",
+        "class Main {
+            function void main() {
+                var int a;
+                while (true) {
+                    var int b;
+                    if (false) { var int c; } else { var int d; }
+                }
+                return;
+            }
+        }",
+    )])
+    .unwrap();
+    let hoisted = hoist_var_decls(&ast.classes[0].class).unwrap();
+    let statements = hoisted.subroutines()[0].get_statements();
+
+    let names: Vec<String> = statements
+        .iter()
+        .take_while(|statement| matches!(statement, Statement::VarDecl(_)))
+        .map(|statement| match statement {
+            Statement::VarDecl(details) => details.get_variables()[0].get_identifier().to_string(),
+            _ => unreachable!(),
+        })
+        .collect();
+    assert_eq!(names, ["a", "b", "c", "d"]);
+    assert!(!statements.iter().any(has_nested_var_decl));
+
+    assert!(hoist_var_decls(&hoisted).is_none());
+}
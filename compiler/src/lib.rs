@@ -0,0 +1,501 @@
+pub mod ast;
+pub mod cli;
+mod compiler;
+mod dead_code;
+mod diagnostics;
+mod escape;
+mod explain;
+pub mod fmt_cli;
+mod fold;
+mod format;
+mod hoist;
+mod lowering;
+mod messages;
+mod metrics;
+mod order;
+mod parser;
+mod sarif;
+mod semantics;
+mod signatures;
+mod symbol_table;
+mod type_check;
+mod uninitialized;
+mod unused;
+mod vm_writer;
+
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+pub use ast::AST;
+use ast::{Class, CompiledClass};
+use compiler::{run_passes, Passes};
+pub use compiler::{
+    CodegenOptions, CompilationError, CompilationOutput, CompilationWarning,
+    LocatedCompilationError, MangledName, THIS_CHECK_ERROR,
+};
+pub use diagnostics::Diagnostic;
+pub use explain::explain;
+pub use format::format_jack;
+pub use lowering::Lowering;
+pub use messages::{Language, LANGUAGE_VARIABLE};
+pub use metrics::{class_metrics, metrics_table, SubroutineMetrics};
+use parse_utils::output::{write_output, WriteMode};
+use parser::{parse_jack, FileInput};
+pub use parser::{tokenize_jack, ParseError, ParseOptions, DEFAULT_MAX_EXPRESSION_DEPTH};
+use thiserror::Error;
+use tracing::debug;
+
+#[cfg(test)]
+mod compiler_tests;
+
+#[derive(Debug, Error)]
+pub enum ErrorType {
+    #[error("Failed to read {}", .path.display())]
+    ReadError {
+        path: PathBuf,
+        #[source]
+        source: io::Error,
+    },
+    #[error("Failed to write {}", .path.display())]
+    WriteError {
+        path: PathBuf,
+        #[source]
+        source: io::Error,
+    },
+    #[error(transparent)]
+    ParsingError(#[from] ParseError),
+    #[error("Failed to serialize the AST to JSON")]
+    SerdeError(#[source] serde_json::Error),
+    #[error("{} doesn't hold the AST of a class", .path.display())]
+    AstJsonError {
+        path: PathBuf,
+        #[source]
+        source: serde_json::Error,
+    },
+    #[error("Unable to read the file extension of {}", .0.display())]
+    FileExtensionError(PathBuf),
+    #[error("Unable to find the directory containing {}", .0.display())]
+    InvalidPath(PathBuf),
+    #[error(transparent)]
+    CompilationError(#[from] LocatedCompilationError),
+    #[error("Unable to format {file}: the layout around line {line} can't be reproduced")]
+    FormatMismatch { file: String, line: usize },
+    #[error("{0} files are not formatted")]
+    Unformatted(usize),
+    #[error("{0} is not a diagnostic code")]
+    UnknownCode(String),
+    #[error("Stopped because of {0} warnings, as warnings are denied")]
+    DeniedWarnings(usize),
+    #[error("{file} compiles differently depending on the order the files are found in: {detail}")]
+    OrderDependent { file: String, detail: String },
+    /// A compilation error whose message has been translated
+    #[error("{0}")]
+    Localized(Box<Diagnostic>),
+}
+
+impl ErrorType {
+    /// The code `--explain` describes the error under, if it's a problem with the program
+    pub fn code(&self) -> Option<&'static str> {
+        match self {
+            ErrorType::ParsingError(_) => Some(ParseError::CODE),
+            ErrorType::CompilationError(located) => Some(located.error.code()),
+            ErrorType::Localized(diagnostic) => diagnostic.code,
+            _ => None,
+        }
+    }
+
+    /// Translate a compilation error, pointing at the same place with the translated cause as its
+    /// message. Other errors are left in English.
+    pub fn localized(self, language: Language) -> ErrorType {
+        match self {
+            ErrorType::CompilationError(located) if language != Language::English => {
+                let mut diagnostic = located.diagnostic;
+                diagnostic.message = located.error.localized(language);
+                ErrorType::Localized(diagnostic)
+            }
+            error => error,
+        }
+    }
+}
+
+/// What is produced besides the .vm files
+#[derive(Debug, Clone, Default)]
+pub struct Reports {
+    /// Write each class's AST to a .json file
+    pub ast_json: bool,
+    /// Write a .names file per class, see [`CompilationOutput::name_report`]
+    pub names: bool,
+    /// Print the size and complexity of each subroutine, see [`SubroutineMetrics`]
+    pub metrics: bool,
+    /// Write a .vm.map file per class, see [`CompilationOutput::source_map`]
+    pub source_map: bool,
+    /// Write the warnings to this file as a SARIF log
+    pub sarif: Option<PathBuf>,
+}
+
+pub fn process_source(
+    path_str: &str,
+    reports: Reports,
+    parse_options: ParseOptions,
+    options: &CodegenOptions,
+    mode: WriteMode,
+    out_dir: Option<&Path>,
+) -> Result<(), ErrorType> {
+    let jack_files = find_jack_files(path_str)?;
+
+    // Outputs go next to the sources unless there's a build directory
+    let output_dir = match out_dir {
+        Some(out_dir) => out_dir,
+        None => get_source_dir(path_str)?,
+    };
+
+    let json_files = find_ast_files(path_str, &jack_files)?;
+    let ast = if json_files.is_empty() {
+        let mut ast = parse_files(&jack_files, parse_options)?;
+        ast.siblings = parse_siblings(path_str, parse_options);
+        ast
+    } else {
+        load_ast_files(&json_files, parse_options.max_expression_depth)?
+    };
+    // An AST loaded from JSON would only be written back over itself
+    let reports = Reports {
+        ast_json: reports.ast_json && json_files.is_empty(),
+        ..reports
+    };
+
+    let source_dir = get_source_dir(path_str)?;
+    process_ast(ast, source_dir, output_dir, reports, options, mode)?;
+    Ok(())
+}
+
+/// The other classes in the directory of a single .jack file, so that its calls to them can be
+/// checked. Files which fail to parse are left out, as they're reported when they're compiled.
+fn parse_siblings(path_str: &str, parse_options: ParseOptions) -> Vec<Class> {
+    let path = Path::new(path_str);
+    let Some(dir) = path.parent().filter(|_| path.is_file()) else {
+        return Vec::new();
+    };
+    let dir = if dir.as_os_str().is_empty() {
+        Path::new(".")
+    } else {
+        dir
+    };
+    let Ok(entries) = dir.read_dir() else {
+        return Vec::new();
+    };
+    let mut sibling_files: Vec<PathBuf> = entries
+        .filter_map(|entry| Some(entry.ok()?.path()))
+        .filter(|file| {
+            file.extension()
+                .is_some_and(|extension| extension == "jack")
+        })
+        .filter(|file| file.file_name() != path.file_name())
+        .collect();
+    sibling_files.sort();
+
+    let mut siblings = Vec::new();
+    for file in sibling_files {
+        let Some(file) = file.to_str() else {
+            continue;
+        };
+        if let Ok(ast) = parse_files(&[file.to_owned()], parse_options) {
+            siblings.extend(ast.classes.into_iter().map(|compiled| compiled.class));
+        }
+    }
+    siblings
+}
+
+/// The .json ASTs to compile instead of Jack sources: a .json file given directly, or those in a
+/// directory without any .jack files
+fn find_ast_files(path_str: &str, jack_files: &[String]) -> Result<Vec<PathBuf>, ErrorType> {
+    let path = Path::new(path_str);
+    if !path.is_dir() {
+        let is_json = path
+            .extension()
+            .is_some_and(|extension| extension == "json");
+        return Ok(if is_json {
+            vec![path.to_owned()]
+        } else {
+            Vec::new()
+        });
+    }
+    if !jack_files.is_empty() {
+        return Ok(Vec::new());
+    }
+
+    let read_error = |source| ErrorType::ReadError {
+        path: path.to_owned(),
+        source,
+    };
+    let mut json_files = Vec::new();
+    for file in path.read_dir().map_err(read_error)? {
+        let file_path = file.map_err(read_error)?.path();
+        if file_path
+            .extension()
+            .is_some_and(|extension| extension == "json")
+        {
+            json_files.push(file_path);
+        }
+    }
+    json_files.sort();
+    Ok(json_files)
+}
+
+/// Load classes from the JSON written by `--ast_output`, so that tools can generate or transform
+/// Jack ASTs and compile them without going through Jack source. Expressions may nest as deeply
+/// as parsing allows.
+pub fn load_ast_files(paths: &[PathBuf], max_expression_depth: usize) -> Result<AST, ErrorType> {
+    let mut classes = Vec::with_capacity(paths.len());
+    for path in paths {
+        let contents = fs::read_to_string(path).map_err(|source| ErrorType::ReadError {
+            path: path.clone(),
+            source,
+        })?;
+        let class = Class::from_json(&contents, max_expression_depth).map_err(|source| {
+            ErrorType::AstJsonError {
+                path: path.clone(),
+                source,
+            }
+        })?;
+        let source_filename = path
+            .file_name()
+            .map(|name| name.to_string_lossy().into_owned())
+            .unwrap_or_default();
+        // There's no Jack source for errors to point into
+        classes.push(CompiledClass {
+            class,
+            source_filename,
+            source: String::new(),
+        });
+    }
+    Ok(AST {
+        classes,
+        siblings: Vec::new(),
+    })
+}
+
+/// Parse and compile a single Jack class held in memory. Nothing is printed: the VM code comes
+/// back along with any warnings, and failures are returned as structured errors.
+pub fn compile_jack_source(
+    filename: &str,
+    source: &str,
+    options: &CodegenOptions,
+) -> Result<CompilationOutput, ErrorType> {
+    let mut outputs = compile_jack_sources(&[(filename, source)], options)?;
+    Ok(outputs.remove(0))
+}
+
+/// Parse and compile several Jack classes held in memory, which may call each other
+pub fn compile_jack_sources(
+    sources: &[(&str, &str)],
+    options: &CodegenOptions,
+) -> Result<Vec<CompilationOutput>, ErrorType> {
+    let ast = parse_strings(sources)?;
+    order::translate(&ast, options)
+}
+
+/// Compile Jack classes held in memory, given as (file name, contents) pairs. Returns the VM code
+/// for each class paired with the name of the .vm file it would be written to.
+pub fn compile_strings(sources: &[(&str, &str)]) -> Result<Vec<(String, String)>, ErrorType> {
+    compile_ast(&parse_strings(sources)?)
+}
+
+/// Parse Jack classes held in memory without compiling them
+pub fn parse_strings(sources: &[(&str, &str)]) -> Result<AST, ErrorType> {
+    let inputs = sources
+        .iter()
+        .map(|(filename, contents)| FileInput::new(filename, contents))
+        .collect();
+    Ok(parse_jack(inputs, ParseOptions::default())?)
+}
+
+/// Generate the VM code for each class of a parsed program
+pub fn compile_ast(ast: &AST) -> Result<Vec<(String, String)>, ErrorType> {
+    compile_ast_with_options(ast, &CodegenOptions::default())
+}
+
+/// Generate the VM code for each class of a parsed program with non-default codegen options
+pub fn compile_ast_with_options(
+    ast: &AST,
+    options: &CodegenOptions,
+) -> Result<Vec<(String, String)>, ErrorType> {
+    let vm_output = order::translate(ast, options)?;
+
+    Ok(vm_output
+        .into_iter()
+        .map(|vm_file| (vm_file.vm_filename(), vm_file.vm_code))
+        .collect())
+}
+
+/// Check the calls of a parsed program, and its types with [`CodegenOptions::strict_types`],
+/// without generating any code
+pub fn check_ast(ast: &AST, options: &CodegenOptions) -> Result<(), ErrorType> {
+    let classes: Vec<&CompiledClass> = ast.classes.iter().collect();
+    run_passes(&classes, &ast.siblings, options, Passes::Check)?;
+    Ok(())
+}
+
+/// Generate the VM code for each class of a program which [`check_ast`] has accepted, without
+/// checking it again
+pub fn compile_checked_ast(
+    ast: &AST,
+    options: &CodegenOptions,
+) -> Result<Vec<(String, String)>, ErrorType> {
+    let classes: Vec<&CompiledClass> = ast.classes.iter().collect();
+    let vm_output = run_passes(&classes, &ast.siblings, options, Passes::Codegen)?;
+
+    Ok(vm_output
+        .into_iter()
+        .map(|vm_file| (vm_file.vm_filename(), vm_file.vm_code))
+        .collect())
+}
+
+fn parse_files(path_str: &[String], parse_options: ParseOptions) -> Result<AST, ErrorType> {
+    let mut file_names = Vec::with_capacity(path_str.len());
+    for single_file in path_str {
+        let path = Path::new(single_file);
+        let contents = fs::read_to_string(path).map_err(|source| ErrorType::ReadError {
+            path: path.to_owned(),
+            source,
+        })?;
+        let filename = path.file_name().to_owned().unwrap().to_str().unwrap();
+        file_names.push(FileInput::new(filename, &contents));
+    }
+
+    Ok(parse_jack(file_names, parse_options)?)
+}
+
+fn process_ast(
+    result: AST,
+    source_dir: &Path,
+    output_dir: &Path,
+    reports: Reports,
+    options: &CodegenOptions,
+    mode: WriteMode,
+) -> Result<(), ErrorType> {
+    // Print the json AST output
+    if reports.ast_json {
+        for single_file in &result.classes {
+            let compiled_json =
+                serde_json::to_string_pretty(&single_file.class).map_err(ErrorType::SerdeError)?;
+
+            let mut original_file_path = PathBuf::from(&single_file.source_filename);
+            original_file_path.set_extension("json");
+            let output_file_name = PathBuf::from(output_dir);
+            let output_file = output_file_name.join(original_file_path);
+            write_file(&output_file, compiled_json, mode)?;
+        }
+    }
+
+    // Compile to VM commands
+    let vm_output = order::translate(&result, options)?;
+
+    if reports.metrics {
+        let metrics: Vec<_> = result
+            .classes
+            .iter()
+            .flat_map(|compiled| class_metrics(&compiled.class))
+            .collect();
+        print!("{}", metrics_table(&metrics));
+    }
+
+    let mut warning_count = 0;
+    for vm_file in &vm_output {
+        for warning in &vm_file.warnings {
+            let location = match warning.line() {
+                Some(line) => format!("{}:{}", vm_file.source_filename, line),
+                None => vm_file.source_filename.clone(),
+            };
+            eprintln!(
+                "warning[{}]: {}: {}",
+                warning.code(),
+                location,
+                warning.localized(options.language)
+            );
+            warning_count += 1;
+        }
+    }
+    // Written even when warnings are denied, so the review shows why the build failed
+    if let Some(sarif_path) = &reports.sarif {
+        let log = sarif::sarif_log(&vm_output, source_dir, options.language);
+        let log = serde_json::to_string_pretty(&log).map_err(ErrorType::SerdeError)?;
+        write_file(sarif_path, log, mode)?;
+    }
+    if options.deny_warnings && warning_count > 0 {
+        return Err(ErrorType::DeniedWarnings(warning_count));
+    }
+
+    for vm_file in vm_output {
+        if reports.names {
+            let report_path = output_dir
+                .join(&vm_file.source_filename)
+                .with_extension("names");
+            write_file(&report_path, vm_file.name_report(), mode)?;
+        }
+        if reports.source_map {
+            let map_path = output_dir
+                .join(&vm_file.source_filename)
+                .with_extension("vm.map");
+            write_file(&map_path, vm_file.source_map(), mode)?;
+        }
+        let bytecode = vm_file.vm_code;
+
+        let mut original_file_path = PathBuf::from(&vm_file.source_filename);
+        original_file_path.set_extension("vm");
+        let output_file_name = PathBuf::from(output_dir);
+        let output_file = output_file_name.join(original_file_path);
+        write_file(&output_file, bytecode, mode)?;
+    }
+
+    Ok(())
+}
+
+fn find_jack_files(path_str: &str) -> Result<Vec<String>, ErrorType> {
+    let path = Path::new(path_str);
+    let mut jack_files = Vec::new();
+    if path.is_dir() {
+        let read_error = |source| ErrorType::ReadError {
+            path: path.to_owned(),
+            source,
+        };
+        for file in path.read_dir().map_err(read_error)? {
+            let file_path = file.map_err(read_error)?.path();
+            if file_path.is_dir() {
+                continue;
+            }
+            let extension = file_path
+                .extension()
+                .ok_or_else(|| ErrorType::FileExtensionError(file_path.clone()))?;
+            if extension == "jack" {
+                jack_files.push(file_path.to_str().unwrap().to_owned());
+            }
+        }
+    } else {
+        jack_files.push(path_str.to_owned());
+    }
+
+    // Directory order depends on the file system, but errors and warnings shouldn't
+    jack_files.sort();
+    Ok(jack_files)
+}
+
+fn get_source_dir(path_str: &str) -> Result<&Path, ErrorType> {
+    let path = Path::new(path_str);
+    let source_dir = if path.is_dir() {
+        path
+    } else {
+        path.parent()
+            .ok_or_else(|| ErrorType::InvalidPath(path.to_owned()))?
+    };
+
+    Ok(source_dir)
+}
+
+fn write_file(path: &Path, contents: String, mode: WriteMode) -> Result<(), ErrorType> {
+    debug!(path = %path.display(), bytes = contents.len(), "writing output");
+    write_output(path, contents.as_bytes(), mode).map_err(|source| ErrorType::WriteError {
+        path: path.to_owned(),
+        source,
+    })
+}
@@ -0,0 +1,81 @@
+/// The subroutines the compiler calls for operations the VM has no command for.
+///
+/// These default to the Jack OS but can be pointed at a program's own routines, e.g. a faster
+/// multiply.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Lowering {
+    /// Called with two arguments for `*`
+    pub multiply: String,
+    /// Called with two arguments for `/`
+    pub divide: String,
+    /// Called by constructors with the number of fields
+    pub alloc: String,
+    /// Called with the length of a string constant
+    pub string_new: String,
+    /// Called with the string and each character of a string constant
+    pub append_char: String,
+}
+
+impl Default for Lowering {
+    fn default() -> Self {
+        Self {
+            multiply: "Math.multiply".to_owned(),
+            divide: "Math.divide".to_owned(),
+            alloc: "Memory.alloc".to_owned(),
+            string_new: "String.new".to_owned(),
+            append_char: "String.appendChar".to_owned(),
+        }
+    }
+}
+
+impl Lowering {
+    pub const OPERATIONS: [&'static str; 5] =
+        ["multiply", "divide", "alloc", "string-new", "append-char"];
+
+    /// Send an operation, named as in [`Lowering::OPERATIONS`], to `target`
+    pub fn set(&mut self, operation: &str, target: &str) -> Result<(), String> {
+        let slot = match operation {
+            "multiply" => &mut self.multiply,
+            "divide" => &mut self.divide,
+            "alloc" => &mut self.alloc,
+            "string-new" => &mut self.string_new,
+            "append-char" => &mut self.append_char,
+            _ => {
+                return Err(format!(
+                    "{} can't be lowered, expected one of {}",
+                    operation,
+                    Self::OPERATIONS.join(", ")
+                ))
+            }
+        };
+        if !target.contains('.') {
+            return Err(format!(
+                "{} is not a subroutine name like Class.function",
+                target
+            ));
+        }
+        *slot = target.to_owned();
+        Ok(())
+    }
+}
+
+/// Parse an `OPERATION=Class.function` argument
+pub fn parse_lowering(text: &str) -> Result<(String, String), String> {
+    let (operation, target) = text
+        .split_once('=')
+        .ok_or_else(|| format!("expected OPERATION=Class.function but found {}", text))?;
+    let (operation, target) = (operation.trim(), target.trim());
+    Lowering::default().set(operation, target)?;
+    Ok((operation.to_owned(), target.to_owned()))
+}
+
+#[test]
+fn test_parse_lowering() {
+    assert_eq!(
+        parse_lowering("multiply=FastMath.mul"),
+        Ok(("multiply".to_owned(), "FastMath.mul".to_owned()))
+    );
+    assert!(parse_lowering("multiply").is_err());
+    assert!(parse_lowering("modulo=Math.mod").is_err());
+    assert!(parse_lowering("divide=divide").is_err());
+}
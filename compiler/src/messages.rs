@@ -0,0 +1,310 @@
+use std::str::FromStr;
+
+use crate::compiler::{CompilationError, CompilationWarning, LONG_STRING_LENGTH};
+
+/// The environment variable which picks the language of diagnostics when `--lang` isn't given
+pub const LANGUAGE_VARIABLE: &str = "N2T_LANG";
+
+/// A language diagnostics can be shown in. Only the messages change: codes such as `J0101` and
+/// the `error`/`warning` labels stay the same so that tools can still match them.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Language {
+    #[default]
+    English,
+    Spanish,
+    French,
+}
+
+impl Language {
+    pub const NAMES: [&'static str; 3] = ["en", "es", "fr"];
+
+    /// The language named by [`LANGUAGE_VARIABLE`], if it's set to one which is supported
+    pub fn from_env() -> Option<Language> {
+        std::env::var(LANGUAGE_VARIABLE).ok()?.parse().ok()
+    }
+
+    fn catalog(self) -> &'static [(&'static str, &'static str)] {
+        match self {
+            Language::English => &[],
+            Language::Spanish => SPANISH,
+            Language::French => FRENCH,
+        }
+    }
+
+    /// Fill in the template for `key`, replacing each `{name}` with its argument
+    fn message(self, key: &str, arguments: &[(&str, String)]) -> Option<String> {
+        let (_, template) = self.catalog().iter().find(|(known, _)| *known == key)?;
+        let mut message = template.to_string();
+        for (name, value) in arguments {
+            message = message.replace(&format!("{{{}}}", name), value);
+        }
+        Some(message)
+    }
+}
+
+impl FromStr for Language {
+    type Err = String;
+
+    /// Accepts a name from [`Language::NAMES`] or a locale such as `es_ES.UTF-8`
+    fn from_str(text: &str) -> Result<Self, Self::Err> {
+        let name = text
+            .split(['_', '-', '.'])
+            .next()
+            .unwrap_or_default()
+            .to_ascii_lowercase();
+        match name.as_str() {
+            "en" => Ok(Language::English),
+            "es" => Ok(Language::Spanish),
+            "fr" => Ok(Language::French),
+            _ => Err(format!(
+                "{} is not a supported language, expected one of {}",
+                text,
+                Language::NAMES.join(", ")
+            )),
+        }
+    }
+}
+
+impl CompilationError {
+    /// The message in `language`, or in English if it has no translation
+    pub fn localized(&self, language: Language) -> String {
+        let arguments = match self {
+            CompilationError::MissingVariable { var_name } => vec![("var_name", var_name.clone())],
+            CompilationError::MissingSubroutine {
+                class_name,
+                subroutine_name,
+            } => vec![
+                ("class_name", class_name.clone()),
+                ("subroutine_name", subroutine_name.clone()),
+            ],
+            CompilationError::UnknownClass {
+                subroutine,
+                class_name,
+            } => vec![
+                ("subroutine", subroutine.clone()),
+                ("class_name", class_name.clone()),
+            ],
+            CompilationError::WrongArgumentCount {
+                subroutine,
+                callee,
+                expected,
+                found,
+            } => vec![
+                ("subroutine", subroutine.clone()),
+                ("callee", callee.clone()),
+                ("expected", expected.to_string()),
+                ("found", found.to_string()),
+            ],
+            CompilationError::MethodWithoutObject { subroutine, callee }
+            | CompilationError::NotAMethod { subroutine, callee } => vec![
+                ("subroutine", subroutine.clone()),
+                ("callee", callee.clone()),
+            ],
+            // The details come from the type checker in English
+            CompilationError::TypeError { .. } => vec![],
+        };
+        language
+            .message(self.code(), &arguments)
+            .unwrap_or_else(|| self.to_string())
+    }
+}
+
+impl CompilationWarning {
+    /// The message in `language`, or in English if it has no translation
+    pub fn localized(&self, language: Language) -> String {
+        let arguments = match self {
+            CompilationWarning::VoidResultUsed { subroutine, callee } => vec![
+                ("subroutine", subroutine.clone()),
+                ("callee", callee.clone()),
+            ],
+            CompilationWarning::LongString { subroutine, length } => vec![
+                ("subroutine", subroutine.clone()),
+                ("length", length.to_string()),
+                ("limit", LONG_STRING_LENGTH.to_string()),
+            ],
+            CompilationWarning::UninitializedField { method, field } => {
+                vec![("method", method.clone()), ("field", field.clone())]
+            }
+            CompilationWarning::UnusedVariable {
+                scope, kind, name, ..
+            } => {
+                let kind = language
+                    .message(&format!("kind-{}", kind), &[])
+                    .unwrap_or_else(|| kind.to_string());
+                vec![
+                    ("scope", scope.clone()),
+                    ("kind", kind),
+                    ("name", name.clone()),
+                ]
+            }
+            CompilationWarning::UnreachableCode { subroutine, line } => {
+                vec![
+                    ("subroutine", subroutine.clone()),
+                    ("line", line.to_string()),
+                ]
+            }
+            CompilationWarning::UnusedSubroutine { subroutine, .. } => {
+                vec![("subroutine", subroutine.clone())]
+            }
+            CompilationWarning::UninitializedLocal {
+                subroutine, name, ..
+            } => vec![("subroutine", subroutine.clone()), ("name", name.clone())],
+        };
+        language
+            .message(self.code(), &arguments)
+            .unwrap_or_else(|| self.to_string())
+    }
+}
+
+const SPANISH: &[(&str, &str)] = &[
+    ("J0101", "La variable {var_name} no ha sido declarada"),
+    (
+        "J0102",
+        "La clase {class_name} no tiene ninguna subrutina llamada {subroutine_name}",
+    ),
+    (
+        "J0104",
+        "{subroutine} llama a una subrutina de {class_name}, que no es una clase",
+    ),
+    (
+        "J0105",
+        "{subroutine} llama a {callee} con {found} argumentos, pero recibe {expected}",
+    ),
+    (
+        "J0107",
+        "{subroutine} llama al método {callee} sin un objeto sobre el que llamarlo",
+    ),
+    (
+        "J0108",
+        "{subroutine} llama a {callee} sobre un objeto, pero es una función o un constructor y no un método",
+    ),
+    (
+        "J0201",
+        "{subroutine}: se usa el resultado de {callee}, pero devuelve void",
+    ),
+    (
+        "J0202",
+        "{subroutine}: una constante de cadena tiene {length} caracteres, más de {limit}",
+    ),
+    (
+        "J0203",
+        "{method}: se lee el campo {field}, pero ningún constructor lo asigna",
+    ),
+    ("J0204", "{scope}: {kind} {name} nunca se lee"),
+    (
+        "J0205",
+        "{subroutine}: las instrucciones desde la línea {line} siguen a un return y nunca se ejecutan",
+    ),
+    (
+        "J0206",
+        "{subroutine} nunca se llama desde Main.main ni Sys.init",
+    ),
+    (
+        "J0207",
+        "{subroutine}: la variable local {name} puede leerse antes de asignarse",
+    ),
+    ("kind-local", "la variable local"),
+    ("kind-argument", "el argumento"),
+    ("kind-field", "el campo"),
+    ("kind-static", "la variable estática"),
+];
+
+const FRENCH: &[(&str, &str)] = &[
+    ("J0101", "La variable {var_name} n'a pas été déclarée"),
+    (
+        "J0102",
+        "La classe {class_name} n'a pas de sous-routine nommée {subroutine_name}",
+    ),
+    (
+        "J0104",
+        "{subroutine} appelle une sous-routine de {class_name}, qui n'est pas une classe",
+    ),
+    (
+        "J0105",
+        "{subroutine} appelle {callee} avec {found} arguments, mais elle en attend {expected}",
+    ),
+    (
+        "J0107",
+        "{subroutine} appelle la méthode {callee} sans objet sur lequel l'appeler",
+    ),
+    (
+        "J0108",
+        "{subroutine} appelle {callee} sur un objet, mais c'est une fonction ou un constructeur et non une méthode",
+    ),
+    (
+        "J0201",
+        "{subroutine} : le résultat de {callee} est utilisé alors qu'elle renvoie void",
+    ),
+    (
+        "J0202",
+        "{subroutine} : une chaîne constante fait {length} caractères, plus que {limit}",
+    ),
+    (
+        "J0203",
+        "{method} : le champ {field} est lu mais aucun constructeur ne l'initialise",
+    ),
+    ("J0204", "{scope} : la valeur de {kind} {name} n'est jamais lue"),
+    (
+        "J0205",
+        "{subroutine} : les instructions à partir de la ligne {line} suivent un return et ne s'exécutent jamais",
+    ),
+    (
+        "J0206",
+        "{subroutine} n'est jamais appelée depuis Main.main ou Sys.init",
+    ),
+    (
+        "J0207",
+        "{subroutine} : la variable locale {name} peut être lue avant d'être affectée",
+    ),
+    ("kind-local", "la variable locale"),
+    ("kind-argument", "l'argument"),
+    ("kind-field", "l'attribut"),
+    ("kind-static", "la variable statique"),
+];
+
+#[test]
+fn test_localized_messages() {
+    let error = CompilationError::WrongArgumentCount {
+        subroutine: "Main.main".to_owned(),
+        callee: "Point.new".to_owned(),
+        expected: 2,
+        found: 1,
+    };
+    assert_eq!(error.localized(Language::English), error.to_string());
+    assert_eq!(
+        error.localized(Language::Spanish),
+        "Main.main llama a Point.new con 1 argumentos, pero recibe 2"
+    );
+
+    let warning = CompilationWarning::UnusedVariable {
+        scope: "Main.main".to_owned(),
+        kind: "argument",
+        name: "x".to_owned(),
+        line: 2,
+    };
+    assert_eq!(
+        warning.localized(Language::French),
+        "Main.main : la valeur de l'argument x n'est jamais lue"
+    );
+
+    assert_eq!("es_ES.UTF-8".parse(), Ok(Language::Spanish));
+    assert!("xx".parse::<Language>().is_err());
+}
+
+#[test]
+fn test_every_message_is_translated() {
+    for language in [Language::Spanish, Language::French] {
+        for (code, _) in crate::explain::EXPLANATIONS {
+            // Parse errors and type errors are worded by the parser and type checker
+            if *code == "J0001" || *code == "J0106" {
+                continue;
+            }
+            assert!(
+                language.catalog().iter().any(|(key, _)| key == code),
+                "{} has no {:?} message",
+                code,
+                language
+            );
+        }
+    }
+}
@@ -0,0 +1,145 @@
+use std::fmt::Write;
+
+use crate::ast::{BinaryOp, Class, ExprKind, ExprRef, Statement, Visit};
+
+/// Size and shape measurements of a subroutine, for spotting ones which should be split up
+#[derive(Debug, Clone, PartialEq)]
+pub struct SubroutineMetrics {
+    /// The full name, e.g. `Main.main`
+    pub name: String,
+    /// Statements at any depth, not counting `var` declarations
+    pub statements: usize,
+    /// How deeply if and while bodies nest, 0 for a subroutine without any
+    pub nesting: usize,
+    /// McCabe's cyclomatic complexity: one more than the number of decisions, where each if,
+    /// while and `&` or `|` in their conditions is a decision
+    pub complexity: usize,
+}
+
+/// The metrics of each subroutine in a class, in declaration order
+pub fn class_metrics(class: &Class) -> Vec<SubroutineMetrics> {
+    class
+        .subroutines()
+        .iter()
+        .map(|subroutine| {
+            let mut metrics = SubroutineMetrics {
+                name: format!("{}.{}", class.get_name(), subroutine.get_name()),
+                statements: 0,
+                nesting: 0,
+                complexity: 1,
+            };
+            measure_block(subroutine.get_statements(), 0, &mut metrics);
+            metrics
+        })
+        .collect()
+}
+
+fn measure_block(statements: &[Statement], depth: usize, metrics: &mut SubroutineMetrics) {
+    metrics.nesting = metrics.nesting.max(depth);
+    for statement in statements {
+        match statement {
+            Statement::VarDecl(_) => continue,
+            Statement::While(details) => {
+                metrics.complexity += 1 + conditions(details.get_condition().root());
+                measure_block(details.get_body(), depth + 1, metrics);
+            }
+            Statement::If(details) => {
+                metrics.complexity += 1 + conditions(details.get_condition().root());
+                measure_block(details.get_if_body(), depth + 1, metrics);
+                if let Some(body) = details.get_else_body() {
+                    measure_block(body, depth + 1, metrics);
+                }
+            }
+            Statement::Let(_) | Statement::Do(_) | Statement::Return(_) => {}
+        }
+        metrics.statements += 1;
+    }
+}
+
+/// The number of `&` and `|` operators in a condition, each of which is an extra path
+fn conditions(expr: ExprRef) -> usize {
+    match expr.kind() {
+        ExprKind::BinaryExpr { .. } => expr
+            .postfix()
+            .map(|visit| match visit {
+                Visit::Operand(operand) => conditions(operand),
+                Visit::Operator(op) => usize::from(matches!(op, BinaryOp::And | BinaryOp::Or)),
+            })
+            .sum(),
+        ExprKind::UnaryExpr(_, expr) | ExprKind::BracketedExpr(expr) => conditions(expr),
+        ExprKind::Constant(_) | ExprKind::VarRef(_) | ExprKind::Call(_) => 0,
+    }
+}
+
+/// Format metrics as a table with a row per subroutine
+pub fn metrics_table(metrics: &[SubroutineMetrics]) -> String {
+    let width = metrics
+        .iter()
+        .map(|metrics| metrics.name.len())
+        .chain(["subroutine".len()])
+        .max()
+        .unwrap_or_default();
+
+    let mut table = format!(
+        "{:width$}  statements  nesting  complexity\n",
+        "subroutine",
+        width = width
+    );
+    for metrics in metrics {
+        writeln!(
+            table,
+            "{:width$}  {:>10}  {:>7}  {:>10}",
+            metrics.name,
+            metrics.statements,
+            metrics.nesting,
+            metrics.complexity,
+            width = width
+        )
+        .expect("Writing to a String cannot fail");
+    }
+    table
+}
+
+#[test]
+fn test_class_metrics() {
+    let ast = crate::parse_strings(&[(
+        "Main.jack",
+        "class Main {
+            function void main() {
+                var int i;
+                let i = 0;
+                while ((i < 10) & (i > -1)) {
+                    if (i = 5) { do Output.printInt(i); } else { let i = i + 1; }
+                }
+                return;
+            }
+            function int one() { return 1; }
+        }",
+    )])
+    .unwrap();
+    let metrics = class_metrics(&ast.classes[0].class);
+
+    assert_eq!(
+        metrics,
+        [
+            SubroutineMetrics {
+                name: "Main.main".to_owned(),
+                statements: 6,
+                nesting: 2,
+                complexity: 4,
+            },
+            SubroutineMetrics {
+                name: "Main.one".to_owned(),
+                statements: 1,
+                nesting: 0,
+                complexity: 1,
+            },
+        ]
+    );
+    assert_eq!(
+        metrics_table(&metrics),
+        "subroutine  statements  nesting  complexity\n\
+         Main.main            6        2           4\n\
+         Main.one             1        0           1\n"
+    );
+}
@@ -0,0 +1,105 @@
+//! Checking that a program compiles the same whatever order its files are found in, for
+//! `--check-order`.
+
+use crate::ast::{CompiledClass, AST};
+use crate::compiler::{translate_ast, translate_classes, CodegenOptions, CompilationOutput};
+use crate::ErrorType;
+
+/// Compile the program, and with [`CodegenOptions::check_order`] compile it again with the classes
+/// in reverse order, failing if any class's code or warnings differ between the two
+pub fn translate(ast: &AST, options: &CodegenOptions) -> Result<Vec<CompilationOutput>, ErrorType> {
+    let outputs = translate_ast(ast, options)?;
+    if !options.check_order {
+        return Ok(outputs);
+    }
+
+    let reversed: Vec<&CompiledClass> = ast.classes.iter().rev().collect();
+    let reversed_outputs =
+        translate_classes(&reversed, &ast.siblings, options).map_err(|error| {
+            ErrorType::OrderDependent {
+                file: error.diagnostic.file.clone(),
+                detail: format!("it only fails to compile in reverse order: {}", error),
+            }
+        })?;
+    for output in &outputs {
+        let other = reversed_outputs
+            .iter()
+            .find(|other| other.source_filename == output.source_filename)
+            .expect("Both orders compile the same classes");
+        if let Some(detail) = difference(output, other) {
+            return Err(ErrorType::OrderDependent {
+                file: output.source_filename.clone(),
+                detail,
+            });
+        }
+    }
+    Ok(outputs)
+}
+
+/// How the output of a class compiled in discovery order differs from compiling it in reverse
+fn difference(output: &CompilationOutput, reversed: &CompilationOutput) -> Option<String> {
+    let mut reversed_lines = reversed.vm_code.lines();
+    for (index, line) in output.vm_code.lines().enumerate() {
+        match reversed_lines.next() {
+            Some(other) if other == line => {}
+            other => {
+                return Some(format!(
+                    "line {} of its VM code is `{}`, but `{}` in reverse order",
+                    index + 1,
+                    line,
+                    other.unwrap_or("")
+                ))
+            }
+        }
+    }
+    if reversed_lines.next().is_some() {
+        return Some("its VM code is longer in reverse order".to_owned());
+    }
+    if output.warnings != reversed.warnings {
+        return Some(format!(
+            "it has {} warnings, but {} in reverse order",
+            output.warnings.len(),
+            reversed.warnings.len()
+        ));
+    }
+    None
+}
+
+#[test]
+fn test_check_order() {
+    // Each class calls the other, so one of them always refers to a class compiled after it
+    let ast = crate::parse_strings(&[
+        (
+            "Main.jack",
+            "class Main {
+                function void main() { do Counter.add(Main.step()); return; }
+                function int step() { return 2; }
+            }",
+        ),
+        (
+            "Counter.jack",
+            "class Counter {
+                static int total;
+                function void add(int n) { let total = total + n + Main.step(); return; }
+            }",
+        ),
+    ])
+    .unwrap();
+    let options = CodegenOptions {
+        check_order: true,
+        ..Default::default()
+    };
+    let outputs = translate(&ast, &options).unwrap();
+    let expected = translate(&ast, &CodegenOptions::default()).unwrap();
+    assert_eq!(outputs.len(), 2);
+    for (output, expected) in outputs.iter().zip(&expected) {
+        assert_eq!(output.vm_code, expected.vm_code);
+        assert_eq!(difference(output, expected), None);
+    }
+
+    let mut changed = outputs[0].clone();
+    changed.vm_code = changed.vm_code.replacen("call", "push constant 0\ncall", 1);
+    assert!(difference(&outputs[0], &changed)
+        .unwrap()
+        .contains("in reverse order"));
+}
@@ -1,36 +1,45 @@
 use nom::branch::alt;
 use nom::character::complete::char;
 use nom::combinator::{cut, map, value};
-use nom::error::{context, VerboseError};
-use nom::sequence::delimited;
-use nom::IResult;
-use nom_locate::LocatedSpan;
+use nom::error::{context, ContextError, VerboseError, VerboseErrorKind};
+use nom::sequence::{delimited, pair, terminated};
+use nom::{IResult, Slice};
 
-use crate::ast::{BinaryOp, Constant, Expr, KeywordConstant, UnaryOp, VariableRef};
+use crate::ast::{BinaryOp, Constant, Expr, KeywordConstant, SubroutineCall, UnaryOp, VariableRef};
 
-use super::parse_utils::{all_whitespace0, parse_identifier, parse_subroutine_call};
+use super::parse_utils::{all_whitespace0, parse_identifier};
 use super::Span;
 
 use nom::bytes::complete::{tag, take_while};
 
-fn parse_constant(i: Span) -> IResult<Span, Expr, VerboseError<Span>> {
+fn parse_string_constant(i: Span) -> IResult<Span, Expr, VerboseError<Span>> {
     fn is_not_quote(c: char) -> bool {
         return c != '"';
     }
 
+    let (s, text) = delimited(char('"'), take_while(is_not_quote), char('"'))(i)?;
+
+    // Only printable ASCII can be typed into a string. Anything else would be mangled by codegen.
+    if let Some(offset) = text.fragment().find(|c: char| !(' '..='~').contains(&c)) {
+        let message = if text.fragment()[offset..].starts_with(|c: char| c.is_ascii()) {
+            "string constant contains a character outside the Hack character set"
+        } else {
+            "string constant contains a non-ASCII character"
+        };
+        return Err(nom::Err::Failure(VerboseError {
+            errors: vec![(text.slice(offset..), VerboseErrorKind::Context(message))],
+        }));
+    }
+
+    Ok((s, Expr::constant(Constant::String(text.to_string()))))
+}
+
+fn parse_constant(i: Span) -> IResult<Span, Expr, VerboseError<Span>> {
     alt((
-        context(
-            "string constant",
-            map(
-                delimited(char('\"'), take_while(is_not_quote), char('\"')),
-                |s: LocatedSpan<&str>| Expr::Constant(Constant::String(s.to_string())),
-            ),
-        ),
+        context("string constant", parse_string_constant),
         context(
             "integer constant",
-            map(nom::character::complete::i32, |val| {
-                Expr::Constant(Constant::Int(val))
-            }),
+            map(nom::character::complete::i32, Expr::int),
         ),
         context(
             "keyword constant",
@@ -41,7 +50,7 @@ fn parse_constant(i: Span) -> IResult<Span, Expr, VerboseError<Span>> {
                     value(KeywordConstant::Null, tag("null")),
                     value(KeywordConstant::This, tag("this")),
                 )),
-                |keyword| Expr::Constant(Constant::Keyword(keyword)),
+                |keyword| Expr::constant(Constant::Keyword(keyword)),
             ),
         ),
     ))(i)
@@ -61,139 +70,299 @@ fn parse_binary_operator(i: Span) -> IResult<Span, BinaryOp, VerboseError<Span>>
     ))(i)
 }
 
-fn parse_binary_operation(i: Span) -> IResult<Span, Expr, VerboseError<Span>> {
-    let (s, lhs) = context("binary-op lhs", parse_sub_expression)(i)?;
-    let (s, operator) = delimited(all_whitespace0, parse_binary_operator, all_whitespace0)(s)?;
-    let (s, rhs) = context("binary-op rhs", parse_expression)(s)?;
-
-    Ok((
-        s,
-        Expr::BinaryExpr {
-            lhs: Box::new(lhs),
-            op: operator,
-            rhs: Box::new(rhs),
-        },
-    ))
+/// How tightly an operator binds with `--precedence`: `*` and `/` before `+` and `-`, then the
+/// comparisons, `=`, `&` and finally `|`
+fn binding_power(op: BinaryOp) -> u8 {
+    match op {
+        BinaryOp::Mult | BinaryOp::Div => 5,
+        BinaryOp::Plus | BinaryOp::Minus => 4,
+        BinaryOp::Lt | BinaryOp::Gt => 3,
+        BinaryOp::Eq => 2,
+        BinaryOp::And => 1,
+        BinaryOp::Or => 0,
+    }
 }
 
-fn parse_brackets(i: Span) -> IResult<Span, Expr, VerboseError<Span>> {
-    let (s, _) = char('(')(i)?;
-    let (s, expr) = cut(context("parsing bracketed expression", parse_expression))(s)?;
-    let (s, _) = cut(char(')'))(s)?;
+/// Fold a chain of operands and operators ending in `last` into a tree. Without precedence it
+/// groups right to left like the Jack spec, so `a - b - c` is `a - (b - c)`. With it, operators
+/// group by [`binding_power`] and then left to right, using a stack rather than recursion.
+fn fold_chain(chain: Vec<(Expr, BinaryOp)>, last: Expr, precedence: bool) -> Expr {
+    if !precedence {
+        return chain
+            .into_iter()
+            .rev()
+            .fold(last, |rhs, (lhs, op)| Expr::binary_op(lhs, op, rhs));
+    }
 
-    Ok((s, Expr::BracketedExpr(Box::new(expr))))
+    let mut operands: Vec<Expr> = Vec::with_capacity(chain.len() + 1);
+    let mut operators: Vec<BinaryOp> = Vec::with_capacity(chain.len());
+    let reduce = |operands: &mut Vec<Expr>, op| {
+        let rhs = operands.pop().expect("Every operator has two operands");
+        let lhs = operands.pop().expect("Every operator has two operands");
+        operands.push(Expr::binary_op(lhs, op, rhs));
+    };
+    for (operand, op) in chain {
+        operands.push(operand);
+        while let Some(&top) = operators.last() {
+            if binding_power(top) < binding_power(op) {
+                break;
+            }
+            operators.pop();
+            reduce(&mut operands, top);
+        }
+        operators.push(op);
+    }
+    operands.push(last);
+    while let Some(op) = operators.pop() {
+        reduce(&mut operands, op);
+    }
+    operands.pop().expect("A chain folds into one expression")
 }
 
-fn parse_unary_op(i: Span) -> IResult<Span, Expr, VerboseError<Span>> {
-    let (s, operator) = alt((
-        value(UnaryOp::Minus, char('-')),
-        value(UnaryOp::Not, char('~')),
-    ))(i)?;
+/// Whatever an expression being parsed is nested inside of
+enum Opener {
+    Root,
+    Bracket,
+    Unary(UnaryOp),
+    Index(String),
+    Call(SubroutineCall, Vec<Expr>),
+}
 
-    let (s, expr) = cut(context("Unary expression", parse_expression))(s)?;
+/// An expression which has been opened but not yet closed. The operands and operators seen so far
+/// are kept in `chain` and folded together by [`fold_chain`] once the expression ends.
+struct Frame {
+    opener: Opener,
+    chain: Vec<(Expr, BinaryOp)>,
+}
 
-    Ok((s, Expr::UnaryExpr(operator, Box::new(expr))))
+impl Frame {
+    fn new(opener: Opener) -> Self {
+        Self {
+            opener,
+            chain: Vec::new(),
+        }
+    }
+
+    fn context(&self) -> &'static str {
+        match self.opener {
+            Opener::Root => "expression",
+            Opener::Bracket => "parsing bracketed expression",
+            Opener::Unary(_) => "Unary expression",
+            Opener::Index(_) => "index expression",
+            Opener::Call(..) => "subroutine call argument",
+        }
+    }
 }
 
-fn parse_indexed_identifier(i: Span) -> IResult<Span, Expr, VerboseError<Span>> {
-    let (s, identifier) = parse_identifier(i)?;
-    let (s, _) = delimited(all_whitespace0, char('['), all_whitespace0)(s)?;
-    let (s, index) = cut(context("index expression", parse_expression))(s)?;
-    let (s, _) = cut(delimited(all_whitespace0, char(']'), all_whitespace0))(s)?;
+enum Operand {
+    Open(Opener),
+    Value(Expr),
+}
 
-    Ok((
-        s,
-        Expr::VarRef(VariableRef::new_with_index(&identifier, index)),
-    ))
+/// The start of a subroutine call up to and including the opening bracket
+fn parse_call_head(i: Span) -> IResult<Span, SubroutineCall, VerboseError<Span>> {
+    alt((
+        map(terminated(parse_identifier, char('(')), |name| {
+            SubroutineCall::new().name(&name)
+        }),
+        map(
+            pair(
+                terminated(parse_identifier, char('.')),
+                terminated(parse_identifier, char('(')),
+            ),
+            |(target, name)| SubroutineCall::new().name(&name).set_target(&target),
+        ),
+    ))(i)
 }
 
-fn parse_sub_expression(i: Span) -> IResult<Span, Expr, VerboseError<Span>> {
+fn parse_operand(i: Span) -> IResult<Span, Operand, VerboseError<Span>> {
     context(
         "sub-expression",
         alt((
-            parse_brackets,
-            parse_unary_op,
-            map(parse_subroutine_call, |details| Expr::Call(details)),
-            parse_constant,
-            parse_indexed_identifier,
+            map(char('('), |_| Operand::Open(Opener::Bracket)),
+            map(
+                alt((
+                    value(UnaryOp::Minus, char('-')),
+                    value(UnaryOp::Not, char('~')),
+                )),
+                |op| Operand::Open(Opener::Unary(op)),
+            ),
+            map(parse_call_head, |call| {
+                Operand::Open(Opener::Call(call, Vec::new()))
+            }),
+            map(parse_constant, Operand::Value),
+            map(
+                terminated(
+                    parse_identifier,
+                    delimited(all_whitespace0, char('['), all_whitespace0),
+                ),
+                |name| Operand::Open(Opener::Index(name)),
+            ),
             map(parse_identifier, |name| {
-                Expr::VarRef(VariableRef::new(&name))
+                Operand::Value(Expr::var(VariableRef::new(&name)))
             }),
         )),
     )(i)
 }
 
+fn nested_too_deeply(i: Span) -> nom::Err<VerboseError<Span>> {
+    nom::Err::Failure(VerboseError {
+        errors: vec![(
+            i,
+            VerboseErrorKind::Context("expression is nested too deeply"),
+        )],
+    })
+}
+
+/// Parse an expression without recursing, so that deeply nested or very long expressions can't
+/// overflow the stack. Every open bracket, unary operator, index and call argument counts towards
+/// the nesting depth, which is limited by the parse options. Binary operators don't, so a long
+/// chain of them is fine.
 pub fn parse_expression(i: Span) -> IResult<Span, Expr, VerboseError<Span>> {
-    context(
-        "expression",
-        alt((
-            parse_binary_operation,
-            parse_brackets,
-            parse_unary_op,
-            map(parse_subroutine_call, |details| Expr::Call(details)),
-            parse_constant,
-            parse_indexed_identifier,
-            map(parse_identifier, |name| {
-                Expr::VarRef(VariableRef::new(&name))
-            }),
-        )),
-    )(i)
+    let max_depth = i.extra.max_expression_depth;
+    let precedence = i.extra.precedence;
+    let mut frames = vec![Frame::new(Opener::Root)];
+    let mut depth = 0;
+    let mut input = i;
+
+    // The input from before the last binary operator. If no operand follows the operator, the
+    // expression ends before it instead.
+    let mut before_operator: Option<Span> = None;
+
+    loop {
+        let (mut expr, mut try_operator) = match parse_operand(input) {
+            Ok((s, Operand::Value(expr))) => {
+                input = s;
+                (expr, true)
+            }
+            Ok((s, Operand::Open(Opener::Call(call, _)))) if s.starts_with(')') => {
+                input = s.slice(1..);
+                (Expr::from_call(call), true)
+            }
+            Ok((s, Operand::Open(opener))) => {
+                depth += 1;
+                if depth > max_depth {
+                    return Err(nested_too_deeply(input));
+                }
+                let (s, _) = match opener {
+                    Opener::Call(..) => all_whitespace0(s)?,
+                    _ => (s, ()),
+                };
+                frames.push(Frame::new(opener));
+                before_operator = None;
+                input = s;
+                continue;
+            }
+            Err(nom::Err::Error(e)) => {
+                let is_root = frames.len() == 1;
+                let frame = frames.last_mut().unwrap();
+                match before_operator {
+                    Some(s) => {
+                        let (lhs, _) = frame.chain.pop().unwrap();
+                        input = s;
+                        (lhs, false)
+                    }
+                    None if is_root => {
+                        return Err(nom::Err::Error(VerboseError::add_context(
+                            i,
+                            "expression",
+                            e,
+                        )))
+                    }
+                    None => {
+                        let message = frame.context();
+                        return Err(nom::Err::Failure(VerboseError::add_context(
+                            input, message, e,
+                        )));
+                    }
+                }
+            }
+            Err(e) => return Err(e),
+        };
+        before_operator = None;
+
+        // Close expressions until one of them carries on with a binary operator. With precedence a
+        // unary operator applies to the next term alone, binding tighter than `*` and `/`.
+        loop {
+            let unary = matches!(frames.last().unwrap().opener, Opener::Unary(_));
+            if try_operator && !(precedence && unary) {
+                let operator =
+                    delimited(all_whitespace0, parse_binary_operator, all_whitespace0)(input);
+                if let Ok((s, op)) = operator {
+                    frames.last_mut().unwrap().chain.push((expr, op));
+                    before_operator = Some(input);
+                    input = s;
+                    break;
+                }
+            }
+            try_operator = true;
+
+            let frame = frames.pop().unwrap();
+            expr = fold_chain(frame.chain, expr, precedence);
+
+            match frame.opener {
+                Opener::Root => return Ok((input, expr)),
+                Opener::Bracket => {
+                    (input, _) = cut(char(')'))(input)?;
+                    expr = Expr::brackets(expr);
+                }
+                Opener::Unary(op) => expr = Expr::unary_op(op, expr),
+                Opener::Index(name) => {
+                    (input, _) =
+                        cut(delimited(all_whitespace0, char(']'), all_whitespace0))(input)?;
+                    expr = Expr::var(VariableRef::new_with_index(&name, expr));
+                }
+                Opener::Call(call, mut arguments) => {
+                    arguments.push(expr);
+                    (input, _) = all_whitespace0(input)?;
+                    if let Ok((s, _)) = char::<_, VerboseError<Span>>(',')(input) {
+                        (input, _) = all_whitespace0(s)?;
+                        frames.push(Frame::new(Opener::Call(call, arguments)));
+                        break;
+                    }
+                    (input, _) = cut(char(')'))(input)?;
+                    expr = Expr::from_call(call.add_parameters(arguments));
+                }
+            }
+            depth -= 1;
+        }
+    }
 }
 
 #[test]
 fn test_expression() {
     let expr = |r: IResult<Span, Expr, VerboseError<Span>>| r.unwrap().1;
-    let span = |val| Span::new(val);
+    let span = |val| Span::new_extra(val, Default::default());
+    let var = |name| Expr::var(VariableRef::new(name));
 
-    assert_eq!(
-        expr(parse_expression(span("3"))),
-        Expr::Constant(Constant::Int(3))
-    );
-    assert_eq!(
-        expr(parse_expression(span("i"))),
-        Expr::VarRef(VariableRef::new("i"))
-    );
+    assert_eq!(expr(parse_expression(span("3"))), Expr::int(3));
+    assert_eq!(expr(parse_expression(span("i"))), var("i"));
     assert_eq!(
         expr(parse_expression(span("i < 3"))),
-        Expr::BinaryExpr {
-            lhs: Box::new(Expr::VarRef(VariableRef::new("i"))),
-            op: crate::ast::BinaryOp::Lt,
-            rhs: Box::new(Expr::Constant(Constant::Int(3)))
-        }
+        Expr::binary_op(var("i"), BinaryOp::Lt, Expr::int(3))
     );
     assert_eq!(
         expr(parse_expression(span("a[ i + 1 ]"))),
-        Expr::VarRef(VariableRef::new_with_index(
+        Expr::var(VariableRef::new_with_index(
             "a",
-            Expr::BinaryExpr {
-                lhs: Box::new(Expr::VarRef(VariableRef::new("i"))),
-                op: BinaryOp::Plus,
-                rhs: Box::new(Expr::Constant(Constant::Int(1)))
-            }
+            Expr::binary_op(var("i"), BinaryOp::Plus, Expr::int(1))
         ))
     );
     assert_eq!(
         expr(parse_expression(span("read()"))),
-        Expr::Call(crate::ast::SubroutineCall::new().name("read"))
+        crate::ast::SubroutineCall::new().name("read").as_expr()
     );
 
     assert_eq!(
         expr(parse_expression(span("-i"))),
-        Expr::UnaryExpr(
-            UnaryOp::Minus,
-            Box::new(Expr::VarRef(VariableRef::new("i")))
-        )
+        Expr::unary_op(UnaryOp::Minus, var("i"))
     );
 
     assert_eq!(
         expr(parse_expression(span("~(b | c)"))),
-        Expr::UnaryExpr(
+        Expr::unary_op(
             UnaryOp::Not,
-            Box::new(Expr::BracketedExpr(Box::new(Expr::BinaryExpr {
-                lhs: Box::new(Expr::VarRef(VariableRef::new("b"))),
-                op: BinaryOp::Or,
-                rhs: Box::new(Expr::VarRef(VariableRef::new("c")))
-            })))
+            Expr::brackets(Expr::binary_op(var("b"), BinaryOp::Or, var("c")))
         )
     );
 
@@ -201,34 +370,222 @@ fn test_expression() {
         expr(parse_expression(span(
             "(((y + size) < 254) & ((x + size) < 510))"
         ))),
-        Expr::BracketedExpr(Box::new(Expr::BinaryExpr {
+        Expr::brackets(Expr::binary_op(
             // ((y + size) < 254)
-            lhs: Box::new(Expr::BracketedExpr(Box::new(
-                // (y + size) < 254
-                Expr::BinaryExpr {
-                    // (y + size)
-                    lhs: Box::new(Expr::BracketedExpr(Box::new(Expr::binary_op(
-                        Expr::VarRef(VariableRef::new("y")),
-                        BinaryOp::Plus,
-                        Expr::VarRef(VariableRef::new("size"))
-                    )))),
-                    op: BinaryOp::Lt,
-                    rhs: Box::new(Expr::Constant(Constant::Int(254)))
-                }
-            ))),
-            op: BinaryOp::And,
+            Expr::brackets(Expr::binary_op(
+                Expr::brackets(Expr::binary_op(var("y"), BinaryOp::Plus, var("size"))),
+                BinaryOp::Lt,
+                Expr::int(254)
+            )),
+            BinaryOp::And,
             // ((x + size) < 510)
-            rhs: Box::new(Expr::BracketedExpr(Box::new(Expr::BinaryExpr {
-                lhs: Box::new(Expr::BracketedExpr(Box::new(Expr::BinaryExpr {
-                    lhs: Box::new(Expr::VarRef(VariableRef::new("x"))),
-                    op: BinaryOp::Plus,
-                    rhs: Box::new(Expr::VarRef(VariableRef::new("size")))
-                }))),
-                op: BinaryOp::Lt,
-                rhs: Box::new(Expr::Constant(Constant::Int(510)))
-            })))
-        }))
+            Expr::brackets(Expr::binary_op(
+                Expr::brackets(Expr::binary_op(var("x"), BinaryOp::Plus, var("size"))),
+                BinaryOp::Lt,
+                Expr::int(510)
+            ))
+        ))
     );
 
     assert_eq!(expr(parse_expression(span("true"))), Expr::true_c());
 }
+
+#[test]
+fn test_expression_operands_keep_their_sides() {
+    use crate::ast::ExprKind;
+
+    let expr = |r: IResult<Span, Expr, VerboseError<Span>>| r.unwrap().1;
+    let span = |val| Span::new_extra(val, Default::default());
+
+    // The right hand side is the larger arena here, so the left operand is appended to it
+    let parsed = expr(parse_expression(span("1 - (2 * 3)")));
+    match parsed.kind() {
+        ExprKind::BinaryExpr { lhs, op, rhs } => {
+            assert_eq!(lhs.kind(), Expr::int(1).kind());
+            assert_eq!(op, BinaryOp::Minus);
+            assert!(matches!(rhs.kind(), ExprKind::BracketedExpr(_)));
+        }
+        other => panic!("Expected a binary expression, got {:?}", other),
+    }
+}
+
+#[test]
+fn test_precedence() {
+    use super::ParseOptions;
+
+    let var = |name| Expr::var(VariableRef::new(name));
+    let parse = |source, precedence| {
+        let options = ParseOptions {
+            precedence,
+            ..Default::default()
+        };
+        parse_expression(Span::new_extra(source, options))
+            .unwrap()
+            .1
+    };
+
+    assert_eq!(
+        parse("a - b * c + d", true),
+        Expr::binary_op(
+            Expr::binary_op(
+                var("a"),
+                BinaryOp::Minus,
+                Expr::binary_op(var("b"), BinaryOp::Mult, var("c"))
+            ),
+            BinaryOp::Plus,
+            var("d")
+        )
+    );
+    assert_eq!(
+        parse("a - b * c + d", false),
+        Expr::binary_op(
+            var("a"),
+            BinaryOp::Minus,
+            Expr::binary_op(
+                var("b"),
+                BinaryOp::Mult,
+                Expr::binary_op(var("c"), BinaryOp::Plus, var("d"))
+            )
+        )
+    );
+
+    assert_eq!(
+        parse("x < 1 | y = 2 & z", true),
+        Expr::binary_op(
+            Expr::binary_op(var("x"), BinaryOp::Lt, Expr::int(1)),
+            BinaryOp::Or,
+            Expr::binary_op(
+                Expr::binary_op(var("y"), BinaryOp::Eq, Expr::int(2)),
+                BinaryOp::And,
+                var("z")
+            )
+        )
+    );
+
+    // Unary operators bind tighter than any binary operator
+    assert_eq!(
+        parse("-a * b + c", true),
+        Expr::binary_op(
+            Expr::binary_op(
+                Expr::unary_op(UnaryOp::Minus, var("a")),
+                BinaryOp::Mult,
+                var("b")
+            ),
+            BinaryOp::Plus,
+            var("c")
+        )
+    );
+    assert_eq!(
+        parse("~a & b", true),
+        Expr::binary_op(
+            Expr::unary_op(UnaryOp::Not, var("a")),
+            BinaryOp::And,
+            var("b")
+        )
+    );
+    assert_eq!(
+        parse("~a & b", false),
+        Expr::unary_op(
+            UnaryOp::Not,
+            Expr::binary_op(var("a"), BinaryOp::And, var("b"))
+        )
+    );
+    assert_eq!(
+        parse("a - -b * c", true),
+        Expr::binary_op(
+            var("a"),
+            BinaryOp::Minus,
+            Expr::binary_op(
+                Expr::unary_op(UnaryOp::Minus, var("b")),
+                BinaryOp::Mult,
+                var("c")
+            )
+        )
+    );
+
+    // Brackets and arguments are still expressions of their own
+    assert_eq!(
+        parse("(a + b) * f(c - d - e)", true),
+        Expr::binary_op(
+            Expr::brackets(Expr::binary_op(var("a"), BinaryOp::Plus, var("b"))),
+            BinaryOp::Mult,
+            crate::ast::SubroutineCall::new()
+                .name("f")
+                .add_parameters(vec![Expr::binary_op(
+                    Expr::binary_op(var("c"), BinaryOp::Minus, var("d")),
+                    BinaryOp::Minus,
+                    var("e")
+                )])
+                .as_expr()
+        )
+    );
+}
+
+#[test]
+fn test_string_constants_must_be_printable_ascii() {
+    let error_message = |source| match parse_expression(Span::new_extra(source, Default::default()))
+    {
+        Err(nom::Err::Failure(error)) => match error.errors[0] {
+            (at, VerboseErrorKind::Context(message)) => (at.location_offset(), message),
+            _ => panic!("Expected a context error"),
+        },
+        other => panic!("Expected a failure, got {:?}", other),
+    };
+
+    assert_eq!(
+        parse_expression(Span::new_extra("\"Hello, World! ~{}\"", Default::default(),))
+            .unwrap()
+            .1,
+        Expr::string("Hello, World! ~{}")
+    );
+    assert_eq!(
+        error_message("\"tab\there\""),
+        (
+            4,
+            "string constant contains a character outside the Hack character set"
+        )
+    );
+    assert_eq!(
+        error_message("\"caf\u{e9}\""),
+        (4, "string constant contains a non-ASCII character")
+    );
+}
+
+#[test]
+fn test_deeply_nested_expressions() {
+    use super::ParseOptions;
+
+    fn parse(
+        source: &str,
+        max_expression_depth: usize,
+    ) -> IResult<Span<'_>, Expr, VerboseError<Span<'_>>> {
+        parse_expression(Span::new_extra(
+            source,
+            ParseOptions {
+                max_expression_depth,
+                ..Default::default()
+            },
+        ))
+    }
+
+    // Far deeper than the stack would allow a recursive parser to go
+    let depth = 10_000;
+    let source = format!("{}1{}", "(-".repeat(depth), ")".repeat(depth));
+    let (rest, _) = parse(&source, 2 * depth).unwrap();
+    assert_eq!(rest.fragment(), &"");
+
+    // Operators in a chain don't nest, however long it is
+    let chain = format!("a{}", " + a".repeat(depth));
+    assert!(parse(&chain, 1).is_ok());
+    assert!(parse(&format!("-({})", chain), 2).is_ok());
+
+    let too_deep = |result: IResult<Span, Expr, VerboseError<Span>>| match result {
+        Err(nom::Err::Failure(error)) => {
+            error.errors[0].1 == VerboseErrorKind::Context("expression is nested too deeply")
+        }
+        _ => false,
+    };
+    assert!(too_deep(parse(&format!("-({})", chain), 1)));
+    assert!(too_deep(parse("f(a[(1)])", 2)));
+    assert!(parse("f(a[(1)])", 3).is_ok());
+}
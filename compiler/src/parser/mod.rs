@@ -1,9 +1,124 @@
 mod expression;
 mod parse_utils;
 mod parser;
+mod tokens;
 
+use nom::error::{VerboseError, VerboseErrorKind};
 use nom_locate::LocatedSpan;
+use thiserror::Error;
 
-pub type Span<'a> = LocatedSpan<&'a str>;
+use crate::diagnostics::Diagnostic;
+
+/// Source text being parsed. The parse options ride along with every slice of it.
+pub type Span<'a> = LocatedSpan<&'a str, ParseOptions>;
+
+/// The default limit on how deeply expressions may nest
+pub const DEFAULT_MAX_EXPRESSION_DEPTH: usize = 256;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct ParseOptions {
+    /// How many brackets, unary operators, indices and call arguments an expression may nest
+    /// before it is rejected
+    pub max_expression_depth: usize,
+    /// Group binary operators by conventional precedence, left to right within a level, rather
+    /// than right to left without precedence as the Jack spec does. Unary operators apply to the
+    /// next term alone.
+    pub precedence: bool,
+}
+
+impl Default for ParseOptions {
+    fn default() -> Self {
+        Self {
+            max_expression_depth: DEFAULT_MAX_EXPRESSION_DEPTH,
+            precedence: false,
+        }
+    }
+}
+
+/// A Jack file which failed to parse
+#[derive(Debug, Clone, PartialEq, Error)]
+#[error("{}", self.diagnostic())]
+pub struct ParseError {
+    pub file: String,
+    /// 1-based position of the innermost error
+    pub line: u32,
+    pub column: usize,
+    /// What went wrong at that position, e.g. `expected ')' in if condition`
+    pub message: String,
+    /// The text of the line the error is on
+    pub source_line: String,
+    /// Every parser which failed, innermost first
+    pub trace: String,
+}
+
+impl ParseError {
+    /// The code `--explain` describes parse errors under
+    pub const CODE: &'static str = "J0001";
+
+    fn new(file: String, source: &str, error: &VerboseError<Span>) -> Self {
+        let (line, column) = match error.errors.first() {
+            // A character missing from the end of a line is reported there rather than at the
+            // start of the next line
+            Some((at, VerboseErrorKind::Char(_)))
+                if source[..at.location_offset()]
+                    .trim_end_matches([' ', '\t'])
+                    .ends_with('\n') =>
+            {
+                let before = source[..at.location_offset()].trim_end();
+                let line = before.matches('\n').count() as u32 + 1;
+                let column = before
+                    .rsplit('\n')
+                    .next()
+                    .unwrap_or_default()
+                    .chars()
+                    .count()
+                    + 1;
+                (line, column)
+            }
+            Some((at, _)) => (at.location_line(), at.get_utf8_column()),
+            None => (1, 1),
+        };
+
+        let context = error.errors.iter().find_map(|(_, kind)| match kind {
+            VerboseErrorKind::Context(context) => Some(*context),
+            _ => None,
+        });
+        let message = match (error.errors.first().map(|(_, kind)| kind), context) {
+            (Some(VerboseErrorKind::Context(context)), _) => context.to_string(),
+            (Some(VerboseErrorKind::Char(c)), Some(context)) => {
+                format!("expected '{}' in {}", c, context)
+            }
+            (Some(VerboseErrorKind::Char(c)), None) => format!("expected '{}'", c),
+            (Some(VerboseErrorKind::Nom(kind)), Some(context)) => {
+                format!("{} failed in {}", kind.description(), context)
+            }
+            (Some(VerboseErrorKind::Nom(kind)), None) => format!("{} failed", kind.description()),
+            (None, _) => "unknown error".to_owned(),
+        };
+
+        let diagnostic = Diagnostic::new(&file, source, line, column, message);
+        Self {
+            file,
+            line,
+            column,
+            message: diagnostic.message,
+            source_line: diagnostic.source_line,
+            trace: error.to_string(),
+        }
+    }
+
+    /// The error as a rustc style message with the offending line and a caret
+    pub fn diagnostic(&self) -> Diagnostic {
+        Diagnostic {
+            file: self.file.clone(),
+            line: self.line,
+            column: self.column,
+            message: self.message.clone(),
+            source_line: self.source_line.clone(),
+            code: Some(Self::CODE),
+        }
+    }
+}
 
 pub use parser::{parse_jack, FileInput};
+pub use tokens::tokenize_jack;
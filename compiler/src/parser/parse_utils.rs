@@ -3,14 +3,13 @@ use crate::ast::{Expr, SubroutineCall, VariableRef};
 use super::expression::parse_expression;
 use super::Span;
 use nom::branch::alt;
-use nom::bytes::complete::{is_not, tag, take_until};
-use nom::character::complete::{alpha1, alphanumeric1, char, multispace1};
-use nom::combinator::value;
+use nom::character::complete::char;
+use nom::combinator::map;
 use nom::error::VerboseError;
-use nom::multi::{fold_many0, fold_many1, many0, separated_list0};
-use nom::sequence::{delimited, terminated, tuple};
+use nom::multi::separated_list0;
+use nom::sequence::{delimited, terminated};
 use nom::IResult;
-use nom::Parser;
+use parse_utils::{identifier, whitespace0, whitespace1};
 
 pub fn parse_indexed_identifier(i: Span) -> IResult<Span, VariableRef, VerboseError<Span>> {
     let (s, name) = parse_identifier(i)?;
@@ -22,47 +21,15 @@ pub fn parse_indexed_identifier(i: Span) -> IResult<Span, VariableRef, VerboseEr
 }
 
 pub fn parse_identifier(i: Span) -> IResult<Span, String, VerboseError<Span>> {
-    let (s, part1) = alt((alpha1, tag("_")))(i)?;
-    let (s, part2) = many0(alt((alphanumeric1, tag("_"))))(s)?;
-
-    let mut part1_str = part1.to_string();
-    let part2_str = part2
-        .iter()
-        .map(|s| s.to_string())
-        .collect::<Vec<String>>()
-        .join("");
-
-    part1_str.push_str(&part2_str);
-
-    Ok((s, part1_str))
-}
-
-fn comment(i: Span) -> IResult<Span, (), VerboseError<Span>> {
-    value((), tuple((tag("//"), is_not("\n"), multispace1))).parse(i)
-}
-
-fn multiline_comment(i: Span) -> IResult<Span, (), VerboseError<Span>> {
-    value((), tuple((tag("/*"), take_until("*/"), tag("*/")))).parse(i)
-}
-
-fn whitespace(i: Span) -> IResult<Span, (), VerboseError<Span>> {
-    value((), multispace1).parse(i)
+    map(identifier(""), |name: Span| name.to_string())(i)
 }
 
 pub fn all_whitespace1(i: Span) -> IResult<Span, (), VerboseError<Span>> {
-    fold_many1(
-        alt((multiline_comment, comment, whitespace)),
-        || (),
-        |_, _| (),
-    )(i)
+    whitespace1(i)
 }
 
 pub fn all_whitespace0(i: Span) -> IResult<Span, (), VerboseError<Span>> {
-    fold_many0(
-        alt((multiline_comment, comment, whitespace)),
-        || (),
-        |_, _| (),
-    )(i)
+    whitespace0(i)
 }
 
 fn parse_parameter_list(i: Span) -> IResult<Span, Vec<Expr>, VerboseError<Span>> {
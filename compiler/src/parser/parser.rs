@@ -6,18 +6,19 @@ use nom::error::{context, VerboseError};
 use nom::multi::{fold_many0, separated_list0, separated_list1};
 use nom::sequence::{delimited, pair, preceded, terminated, tuple};
 use nom::{Finish, IResult};
+use tracing::info_span;
 
 use super::expression::parse_expression;
 use super::parse_utils::{
     all_whitespace0, all_whitespace1, parse_identifier, parse_indexed_identifier,
     parse_subroutine_call,
 };
-use super::Span;
+use super::{ParseError, ParseOptions, Span};
 
 use crate::ast::{
     Class, ClassVariable, ClassVariableVisibility, CompiledClass, IfDetails, LetDetails,
-    ReturnType, Statement, Subroutine, SubroutineType, Variable, VariableRef, VariableType,
-    WhileDetails, AST,
+    ReturnDetails, ReturnType, Statement, Subroutine, SubroutineType, Variable, VariableRef,
+    VariableType, WhileDetails, AST,
 };
 
 pub struct FileInput {
@@ -40,7 +41,7 @@ fn parse_return_type(i: Span) -> IResult<Span, ReturnType, VerboseError<Span>> {
         "bool" => ReturnType::Boolean,
         "char" => ReturnType::Char,
         "int" => ReturnType::Int,
-        _ => ReturnType::ClassName(name),
+        _ => ReturnType::ClassName(name.into()),
     })(i)
 }
 
@@ -50,7 +51,7 @@ fn var_type(i: Span) -> IResult<Span, VariableType, VerboseError<Span>> {
         "boolean" => Some(VariableType::Boolean),
         "char" => Some(VariableType::Char),
         "int" => Some(VariableType::Int),
-        _ => Some(VariableType::ClassName(name)),
+        _ => Some(VariableType::ClassName(name.into())),
     })(i)
 }
 
@@ -74,11 +75,12 @@ fn parse_var_decl(i: Span) -> IResult<Span, Statement, VerboseError<Span>> {
 
     let (s, _) = cut(preceded(all_whitespace0, char(';')))(s)?;
 
+    let line = i.location_line();
     let mut var_details =
-        Statement::var().add_var(Variable::new(&first_var_name, var_type.clone()));
+        Statement::var().add_var(Variable::new(&first_var_name, var_type.clone()).line(line));
 
     for var in other_vars {
-        var_details = var_details.add_var(Variable::new(&var, var_type.clone()));
+        var_details = var_details.add_var(Variable::new(&var, var_type.clone()).line(line));
     }
 
     Ok((s, var_details.as_statement()))
@@ -92,7 +94,13 @@ fn parse_return(i: Span) -> IResult<Span, Statement, VerboseError<Span>> {
         all_whitespace0,
     ))(s)?;
     let (s, _) = char(';')(s)?;
-    Ok((s, Statement::Return(expr)))
+    Ok((
+        s,
+        Statement::Return(ReturnDetails {
+            value: expr,
+            line: i.location_line(),
+        }),
+    ))
 }
 
 fn parse_else(i: Span) -> IResult<Span, Vec<Statement>, VerboseError<Span>> {
@@ -122,6 +130,7 @@ fn parse_if(i: Span) -> IResult<Span, Statement, VerboseError<Span>> {
             condition,
             if_body,
             else_body,
+            line: i.location_line(),
         }),
     ))
 }
@@ -142,15 +151,17 @@ fn parse_let(i: Span) -> IResult<Span, Statement, VerboseError<Span>> {
         Statement::Let(LetDetails {
             identifier,
             expression,
+            line: i.location_line(),
         }),
     ))
 }
 
 fn parse_do(i: Span) -> IResult<Span, Statement, VerboseError<Span>> {
     let (s, _) = tuple((tag("do"), all_whitespace1))(i)?;
-    let (s, call) = parse_subroutine_call(s)?;
+    let (s, mut call) = parse_subroutine_call(s)?;
     let (s, _) = tuple((all_whitespace0, char(';')))(s)?;
 
+    call.line = i.location_line();
     Ok((s, Statement::Do(call)))
 }
 
@@ -166,7 +177,14 @@ fn parse_while(i: Span) -> IResult<Span, Statement, VerboseError<Span>> {
     let (s, body) = parse_statements(s)?;
     let (s, _) = char('}')(s)?;
 
-    Ok((s, Statement::While(WhileDetails { condition, body })))
+    Ok((
+        s,
+        Statement::While(WhileDetails {
+            condition,
+            body,
+            line: i.location_line(),
+        }),
+    ))
 }
 
 fn parse_statements(i: Span) -> IResult<Span, Vec<Statement>, VerboseError<Span>> {
@@ -194,7 +212,10 @@ fn parse_parameter(i: Span) -> IResult<Span, Variable, VerboseError<Span>> {
     let (s, var_type) = terminated(var_type, all_whitespace1)(i)?;
     let (s, identifier) = parse_identifier(s)?;
 
-    Ok((s, Variable::new(&identifier, var_type)))
+    Ok((
+        s,
+        Variable::new(&identifier, var_type).line(i.location_line()),
+    ))
 }
 
 fn parse_function(i: Span) -> IResult<Span, Subroutine, VerboseError<Span>> {
@@ -226,7 +247,8 @@ fn parse_function(i: Span) -> IResult<Span, Subroutine, VerboseError<Span>> {
             .return_type(return_type)
             .subroutine_type(subroutine_type)
             .add_parameters(parameters)
-            .add_statements(statements),
+            .add_statements(statements)
+            .line(i.location_line()),
     ))
 }
 
@@ -256,6 +278,7 @@ fn parse_variable(i: Span) -> IResult<Span, Vec<ClassVariable>, VerboseError<Spa
                 ClassVariable::new(&identifier)
                     .visibility(visibility)
                     .var_type(var_type.clone())
+                    .line(i.location_line())
             })
             .collect(),
     ))
@@ -283,25 +306,24 @@ fn parse_class(i: Span) -> IResult<Span, Class, VerboseError<Span>> {
     ))
 }
 
-pub fn parse_jack(files: Vec<FileInput>) -> Result<AST, String> {
+pub fn parse_jack(files: Vec<FileInput>, options: ParseOptions) -> Result<AST, ParseError> {
     let mut result = Vec::with_capacity(files.len());
     for file in files {
-        let input = Span::new(&file.contents);
+        let _span = info_span!("parse", file = %file.filename).entered();
+        let input = Span::new_extra(&file.contents, options);
         let output = all_consuming(parse_class)(input);
 
         match output.finish() {
             Ok(compiled_class) => result.push(CompiledClass {
                 class: compiled_class.1,
                 source_filename: file.filename,
+                source: file.contents.clone(),
             }),
-            Err(e) => {
-                return Err(format!(
-                    "Failed to compile with error in file {}:\n{}",
-                    file.filename,
-                    e.to_string()
-                ));
-            }
+            Err(e) => return Err(ParseError::new(file.filename, &file.contents, &e)),
         }
     }
-    Ok(AST { classes: result })
+    Ok(AST {
+        classes: result,
+        siblings: Vec::new(),
+    })
 }
@@ -0,0 +1,129 @@
+use nom::branch::alt;
+use nom::bytes::complete::{tag, take_till};
+use nom::character::complete::{digit1, multispace1};
+use nom::combinator::{opt, recognize};
+use nom::sequence::tuple;
+use nom::IResult;
+use parse_utils::tokens::{Token, TokenKind};
+use parse_utils::{block_comment, identifier, line_comment};
+
+const KEYWORDS: [&str; 21] = [
+    "class",
+    "constructor",
+    "function",
+    "method",
+    "field",
+    "static",
+    "var",
+    "int",
+    "char",
+    "boolean",
+    "void",
+    "true",
+    "false",
+    "null",
+    "this",
+    "let",
+    "do",
+    "if",
+    "else",
+    "while",
+    "return",
+];
+
+const SYMBOLS: &str = "{}()[].,;+-*/&|<>=~";
+
+fn comment(i: &str) -> IResult<&str, &str> {
+    alt((line_comment, block_comment))(i)
+}
+
+fn string_constant(i: &str) -> IResult<&str, &str> {
+    recognize(tuple((
+        tag("\""),
+        take_till(|c| c == '"' || c == '\n'),
+        opt(tag("\"")),
+    )))(i)
+}
+
+/// Split Jack source into classified tokens for highlighting.
+///
+/// Unlike the parser this never fails: characters which aren't part of any token are skipped.
+pub fn tokenize_jack(source: &str) -> Vec<Token> {
+    let mut tokens = Vec::new();
+    let mut rest = source;
+
+    while let Some(next_char) = rest.chars().next() {
+        let offset = source.len() - rest.len();
+
+        if let Ok((remaining, _)) = multispace1::<&str, nom::error::Error<&str>>(rest) {
+            rest = remaining;
+        } else if let Ok((remaining, text)) = comment(rest) {
+            tokens.push(Token::new(offset, text.len(), TokenKind::Comment));
+            rest = remaining;
+        } else if let Ok((remaining, text)) = string_constant(rest) {
+            tokens.push(Token::new(offset, text.len(), TokenKind::String));
+            rest = remaining;
+        } else if let Ok((remaining, text)) = digit1::<&str, nom::error::Error<&str>>(rest) {
+            tokens.push(Token::new(offset, text.len(), TokenKind::Number));
+            rest = remaining;
+        } else if let Ok((remaining, text)) = identifier::<&str, nom::error::Error<&str>>("")(rest)
+        {
+            let kind = classify_identifier(text, remaining);
+            tokens.push(Token::new(offset, text.len(), kind));
+            rest = remaining;
+        } else {
+            if SYMBOLS.contains(next_char) {
+                tokens.push(Token::new(offset, 1, TokenKind::Operator));
+            }
+            rest = &rest[next_char.len_utf8()..];
+        }
+    }
+
+    tokens
+}
+
+fn classify_identifier(text: &str, remaining: &str) -> TokenKind {
+    if KEYWORDS.contains(&text) {
+        TokenKind::Keyword
+    } else if remaining.trim_start().starts_with('(') {
+        TokenKind::Function
+    } else if text.starts_with(|c: char| c.is_ascii_uppercase()) {
+        // Class names are capitalised by convention
+        TokenKind::Type
+    } else {
+        TokenKind::Variable
+    }
+}
+
+#[test]
+fn test_tokenize_jack() {
+    let source = "let x = Main.run(\"a<b\", 12); // done";
+    let kinds: Vec<(&str, TokenKind)> = tokenize_jack(source)
+        .into_iter()
+        .map(|token| {
+            (
+                &source[token.offset..token.offset + token.length],
+                token.kind,
+            )
+        })
+        .collect();
+
+    assert_eq!(
+        kinds,
+        vec![
+            ("let", TokenKind::Keyword),
+            ("x", TokenKind::Variable),
+            ("=", TokenKind::Operator),
+            ("Main", TokenKind::Type),
+            (".", TokenKind::Operator),
+            ("run", TokenKind::Function),
+            ("(", TokenKind::Operator),
+            ("\"a<b\"", TokenKind::String),
+            (",", TokenKind::Operator),
+            ("12", TokenKind::Number),
+            (")", TokenKind::Operator),
+            (";", TokenKind::Operator),
+            ("// done", TokenKind::Comment),
+        ]
+    );
+}
@@ -0,0 +1,87 @@
+//! Warnings as a SARIF 2.1.0 log, for code review tools which show them as annotations on the
+//! lines they're about.
+
+use std::path::Path;
+
+use serde_json::{json, Value};
+
+use crate::compiler::CompilationOutput;
+use crate::messages::Language;
+
+const SCHEMA: &str = "https://json.schemastore.org/sarif-2.1.0.json";
+
+/// A SARIF log of the warnings of `outputs`, with each class's file found in `source_dir`
+pub fn sarif_log(outputs: &[CompilationOutput], source_dir: &Path, language: Language) -> Value {
+    let mut results = Vec::new();
+    let mut rules: Vec<&str> = Vec::new();
+    for output in outputs {
+        let uri = source_dir
+            .join(&output.source_filename)
+            .to_string_lossy()
+            .replace('\\', "/");
+        for warning in &output.warnings {
+            let mut location = json!({ "artifactLocation": { "uri": uri } });
+            if let Some(line) = warning.line() {
+                location["region"] = json!({ "startLine": line });
+            }
+            results.push(json!({
+                "ruleId": warning.code(),
+                "level": "warning",
+                "message": { "text": warning.localized(language) },
+                "locations": [{ "physicalLocation": location }],
+            }));
+            rules.push(warning.code());
+        }
+    }
+    rules.sort();
+    rules.dedup();
+
+    json!({
+        "$schema": SCHEMA,
+        "version": "2.1.0",
+        "runs": [{
+            "tool": {
+                "driver": {
+                    "name": "jack-compiler",
+                    "version": env!("CARGO_PKG_VERSION"),
+                    "rules": rules
+                        .iter()
+                        .map(|code| json!({ "id": code }))
+                        .collect::<Vec<_>>(),
+                },
+            },
+            "results": results,
+        }],
+    })
+}
+
+#[test]
+fn test_sarif_log() {
+    let outputs = crate::compile_jack_sources(
+        &[(
+            "Main.jack",
+            "class Main {
+    function void main() {
+        var int unused;
+        return;
+    }
+}",
+        )],
+        &Default::default(),
+    )
+    .unwrap();
+
+    let log = sarif_log(&outputs, Path::new("src"), Language::default());
+    let results = &log["runs"][0]["results"];
+    assert_eq!(log["version"], "2.1.0");
+    assert_eq!(results.as_array().unwrap().len(), 1);
+    assert_eq!(results[0]["ruleId"], "J0204");
+    assert_eq!(
+        results[0]["locations"][0]["physicalLocation"],
+        json!({ "artifactLocation": { "uri": "src/Main.jack" }, "region": { "startLine": 3 } })
+    );
+    assert_eq!(
+        log["runs"][0]["tool"]["driver"]["rules"],
+        json!([{ "id": "J0204" }])
+    );
+}
@@ -0,0 +1,226 @@
+use rustc_hash::FxHashMap;
+
+use crate::{
+    ast::{
+        Class, ExprKind, ExprRef, Identifier, Statement, Subroutine, SubroutineCall, SubroutineType,
+    },
+    compiler::{CompilationError, SubroutineError},
+    signatures::Signatures,
+};
+
+/// Something known about each variable visible at a point in a subroutine, innermost scope last
+pub struct Scopes<'a, T> {
+    scopes: Vec<FxHashMap<&'a str, T>>,
+}
+
+impl<'a, T: Clone> Scopes<'a, T> {
+    /// The class variables and the parameters of a subroutine
+    pub fn new(
+        class: &'a Class,
+        subroutine: &'a Subroutine,
+        info: impl Fn(Identifier) -> T,
+    ) -> Self {
+        let class_scope = class
+            .variables()
+            .iter()
+            .map(|variable| {
+                (
+                    variable.get_identifier().as_str(),
+                    info(variable.get_var_type().type_name()),
+                )
+            })
+            .collect();
+        let parameters = subroutine
+            .get_parameters()
+            .iter()
+            .map(|parameter| {
+                (
+                    parameter.get_identifier().as_str(),
+                    info(parameter.get_type().type_name()),
+                )
+            })
+            .collect();
+        Self {
+            scopes: vec![class_scope, parameters],
+        }
+    }
+
+    pub fn push(&mut self) {
+        self.scopes.push(FxHashMap::default());
+    }
+
+    pub fn pop(&mut self) {
+        self.scopes.pop();
+    }
+
+    pub fn declare(&mut self, name: &'a str, value: T) {
+        self.scopes
+            .last_mut()
+            .expect("There is always a scope for the parameters")
+            .insert(name, value);
+    }
+
+    pub fn find(&self, name: &str) -> Option<&T> {
+        self.scopes.iter().rev().find_map(|scope| scope.get(name))
+    }
+}
+
+struct CallChecker<'a> {
+    class: &'a Class,
+    subroutine: &'a Subroutine,
+    signatures: &'a Signatures,
+    scopes: Scopes<'a, Identifier>,
+    /// The line of the statement being checked
+    line: u32,
+}
+
+/// Check that every call in a class names a class and subroutine which exist, passes the number
+/// of arguments the subroutine declares, and has an object exactly when it calls a method.
+///
+/// Errors come with the subroutine the bad call is in.
+pub fn check_calls<'a>(
+    class: &'a Class,
+    signatures: &Signatures,
+) -> Result<(), SubroutineError<'a>> {
+    for subroutine in class.subroutines() {
+        let mut checker = CallChecker {
+            class,
+            subroutine,
+            signatures,
+            scopes: Scopes::new(class, subroutine, |type_name| type_name),
+            line: 0,
+        };
+        checker
+            .check_block(subroutine.get_statements())
+            .map_err(|error| SubroutineError {
+                subroutine,
+                line: checker.line,
+                error,
+            })?;
+    }
+    Ok(())
+}
+
+impl<'a> CallChecker<'a> {
+    fn check_block(&mut self, statements: &'a [Statement]) -> Result<(), CompilationError> {
+        self.scopes.push();
+        for statement in statements {
+            self.check_statement(statement)?;
+        }
+        self.scopes.pop();
+        Ok(())
+    }
+
+    fn check_statement(&mut self, statement: &'a Statement) -> Result<(), CompilationError> {
+        if !matches!(statement, Statement::VarDecl(_)) {
+            self.line = statement.line();
+        }
+        match statement {
+            Statement::VarDecl(details) => {
+                for variable in details.get_variables() {
+                    self.scopes.declare(
+                        variable.get_identifier().as_str(),
+                        variable.get_type().type_name(),
+                    );
+                }
+                Ok(())
+            }
+            Statement::Let(details) => {
+                if let Some(index) = details.get_identifier().get_index() {
+                    self.check_expression(index.root())?;
+                }
+                self.check_expression(details.get_expression().root())
+            }
+            Statement::Do(call) => self.check_call(call),
+            Statement::Return(details) => details
+                .get_value()
+                .map_or(Ok(()), |value| self.check_expression(value.root())),
+            Statement::While(details) => {
+                self.check_expression(details.get_condition().root())?;
+                self.check_block(details.get_body())
+            }
+            Statement::If(details) => {
+                self.check_expression(details.get_condition().root())?;
+                self.check_block(details.get_if_body())?;
+                details
+                    .get_else_body()
+                    .map_or(Ok(()), |body| self.check_block(body))
+            }
+        }
+    }
+
+    fn check_expression(&mut self, expr: ExprRef<'a>) -> Result<(), CompilationError> {
+        match expr.kind() {
+            ExprKind::Constant(_) => Ok(()),
+            ExprKind::VarRef(variable) => variable
+                .get_index()
+                .map_or(Ok(()), |index| self.check_expression(index.root())),
+            ExprKind::UnaryExpr(_, expr) | ExprKind::BracketedExpr(expr) => {
+                self.check_expression(expr)
+            }
+            ExprKind::BinaryExpr { .. } => expr
+                .operands()
+                .try_for_each(|operand| self.check_expression(operand)),
+            ExprKind::Call(call) => self.check_call(call),
+        }
+    }
+
+    fn check_call(&mut self, call: &'a SubroutineCall) -> Result<(), CompilationError> {
+        // A call by name alone passes `this`, which a function doesn't have
+        let (class_name, passes_object, has_object) = match call.get_target() {
+            Some(target) => match self.scopes.find(target) {
+                Some(type_name) => (type_name.to_string(), true, true),
+                None => (target.to_string(), false, false),
+            },
+            None => (
+                self.class.get_name().to_string(),
+                true,
+                self.subroutine.get_subroutine_type() != SubroutineType::Function,
+            ),
+        };
+
+        if !self.signatures.has_class(&class_name) {
+            return Err(CompilationError::UnknownClass {
+                subroutine: self.subroutine_name(),
+                class_name,
+            });
+        }
+        let signature = self
+            .signatures
+            .get(&format!("{}.{}", class_name, call.get_name()))
+            .ok_or_else(|| CompilationError::MissingSubroutine {
+                class_name: class_name.clone(),
+                subroutine_name: call.get_name().to_string(),
+            })?;
+        let callee = || format!("{}.{}", class_name, call.get_name());
+        let is_method = signature.kind == SubroutineType::Method;
+        if is_method && !has_object {
+            return Err(CompilationError::MethodWithoutObject {
+                subroutine: self.subroutine_name(),
+                callee: callee(),
+            });
+        }
+        if !is_method && passes_object {
+            return Err(CompilationError::NotAMethod {
+                subroutine: self.subroutine_name(),
+                callee: callee(),
+            });
+        }
+        if signature.parameters != call.get_parameters().len() {
+            return Err(CompilationError::WrongArgumentCount {
+                subroutine: self.subroutine_name(),
+                callee: callee(),
+                expected: signature.parameters,
+                found: call.get_parameters().len(),
+            });
+        }
+
+        call.get_parameters()
+            .iter()
+            .try_for_each(|parameter| self.check_expression(parameter.root()))
+    }
+
+    fn subroutine_name(&self) -> String {
+        format!("{}.{}", self.class.get_name(), self.subroutine.get_name())
+    }
+}
@@ -0,0 +1,171 @@
+use rustc_hash::{FxHashMap, FxHashSet};
+
+use crate::ast::{Class, ReturnType, SubroutineType};
+use SubroutineType::{Constructor, Function, Method};
+
+/// Subroutines provided by the Jack OS, their kind, whether they return void and how many
+/// parameters they declare
+const OS_SUBROUTINES: &[(&str, SubroutineType, bool, usize)] = &[
+    ("Array.new", Function, false, 1),
+    ("Array.dispose", Method, true, 0),
+    ("Keyboard.init", Function, true, 0),
+    ("Keyboard.keyPressed", Function, false, 0),
+    ("Keyboard.readChar", Function, false, 0),
+    ("Keyboard.readLine", Function, false, 1),
+    ("Keyboard.readInt", Function, false, 1),
+    ("Math.init", Function, true, 0),
+    ("Math.abs", Function, false, 1),
+    ("Math.multiply", Function, false, 2),
+    ("Math.divide", Function, false, 2),
+    ("Math.min", Function, false, 2),
+    ("Math.max", Function, false, 2),
+    ("Math.sqrt", Function, false, 1),
+    ("Memory.init", Function, true, 0),
+    ("Memory.peek", Function, false, 1),
+    ("Memory.poke", Function, true, 2),
+    ("Memory.alloc", Function, false, 1),
+    ("Memory.deAlloc", Function, true, 1),
+    ("Output.init", Function, true, 0),
+    ("Output.moveCursor", Function, true, 2),
+    ("Output.printChar", Function, true, 1),
+    ("Output.printString", Function, true, 1),
+    ("Output.printInt", Function, true, 1),
+    ("Output.println", Function, true, 0),
+    ("Output.backSpace", Function, true, 0),
+    ("Screen.init", Function, true, 0),
+    ("Screen.clearScreen", Function, true, 0),
+    ("Screen.setColor", Function, true, 1),
+    ("Screen.drawPixel", Function, true, 2),
+    ("Screen.drawLine", Function, true, 4),
+    ("Screen.drawRectangle", Function, true, 4),
+    ("Screen.drawCircle", Function, true, 3),
+    ("String.new", Constructor, false, 1),
+    ("String.dispose", Method, true, 0),
+    ("String.length", Method, false, 0),
+    ("String.charAt", Method, false, 1),
+    ("String.setCharAt", Method, true, 2),
+    ("String.appendChar", Method, false, 1),
+    ("String.eraseLastChar", Method, true, 0),
+    ("String.intValue", Method, false, 0),
+    ("String.setInt", Method, true, 1),
+    ("String.backSpace", Function, false, 0),
+    ("String.doubleQuote", Function, false, 0),
+    ("String.newLine", Function, false, 0),
+    ("Sys.init", Function, true, 0),
+    ("Sys.halt", Function, true, 0),
+    ("Sys.error", Function, true, 1),
+    ("Sys.wait", Function, true, 1),
+];
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Signature {
+    pub kind: SubroutineType,
+    pub returns_void: bool,
+    /// Declared parameters, not counting `this` for methods
+    pub parameters: usize,
+}
+
+/// Every subroutine a program can call, keyed by its full name e.g. `Output.printInt`.
+///
+/// Classes in the program take precedence over the OS so that a project can supply its own
+/// versions of the OS classes.
+#[derive(Debug, Default)]
+pub struct Signatures {
+    subroutines: FxHashMap<String, Signature>,
+    classes: FxHashSet<String>,
+}
+
+impl Signatures {
+    pub fn new<'a>(classes: impl IntoIterator<Item = &'a Class>) -> Self {
+        let mut subroutines: FxHashMap<String, Signature> = OS_SUBROUTINES
+            .iter()
+            .map(|(name, kind, returns_void, parameters)| {
+                (
+                    name.to_string(),
+                    Signature {
+                        kind: *kind,
+                        returns_void: *returns_void,
+                        parameters: *parameters,
+                    },
+                )
+            })
+            .collect();
+        let mut class_names: FxHashSet<String> = OS_SUBROUTINES
+            .iter()
+            .filter_map(|(name, ..)| name.split_once('.'))
+            .map(|(class, _)| class.to_owned())
+            .collect();
+
+        for class in classes {
+            class_names.insert(class.get_name().to_string());
+            for subroutine in class.subroutines() {
+                subroutines.insert(
+                    format!("{}.{}", class.get_name(), subroutine.get_name()),
+                    Signature {
+                        kind: subroutine.get_subroutine_type(),
+                        returns_void: matches!(subroutine.get_return_type(), ReturnType::Void),
+                        parameters: subroutine.get_parameters().len(),
+                    },
+                );
+            }
+        }
+
+        // Sys.init starts the program by calling Main.main, so the OS can be compiled without it
+        if !class_names.contains("Main") {
+            subroutines.insert(
+                "Main.main".to_owned(),
+                Signature {
+                    kind: Function,
+                    returns_void: true,
+                    parameters: 0,
+                },
+            );
+            class_names.insert("Main".to_owned());
+        }
+
+        Self {
+            subroutines,
+            classes: class_names,
+        }
+    }
+
+    pub fn get(&self, name: &str) -> Option<&Signature> {
+        self.subroutines.get(name)
+    }
+
+    /// Whether a class of this name is in the program or the OS
+    pub fn has_class(&self, name: &str) -> bool {
+        self.classes.contains(name)
+    }
+}
+
+#[test]
+fn test_program_classes_override_the_os() {
+    use crate::ast::Subroutine;
+
+    let class = crate::ast::Class::new("Output")
+        .add_subroutine(Subroutine::new("printInt").return_type(ReturnType::Int));
+    let signatures = Signatures::new([&class]);
+
+    assert_eq!(
+        signatures.get("Output.printInt"),
+        Some(&Signature {
+            kind: Function,
+            returns_void: false,
+            parameters: 0
+        })
+    );
+    assert_eq!(
+        signatures.get("Sys.halt"),
+        Some(&Signature {
+            kind: Function,
+            returns_void: true,
+            parameters: 0
+        })
+    );
+    assert_eq!(signatures.get("Output.missing"), None);
+    assert_eq!(signatures.get("String.length").unwrap().kind, Method);
+    assert!(signatures.has_class("Output"));
+    assert!(signatures.has_class("Sys"));
+    assert!(!signatures.has_class("Int"));
+}
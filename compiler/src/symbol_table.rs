@@ -1,3 +1,7 @@
+use rustc_hash::FxHashMap;
+
+use crate::ast::Identifier;
+
 #[derive(Debug, Copy, Clone, PartialEq)]
 pub enum Scope {
     Field,
@@ -7,35 +11,37 @@ pub enum Scope {
 }
 
 impl Scope {
-    pub fn as_segment(&self) -> String {
+    pub fn as_segment(&self) -> &'static str {
         match self {
-            Scope::Field => "this".to_owned(),
-            Scope::Static => "static".to_owned(),
-            Scope::Argument => "argument".to_owned(),
-            Scope::Local => "local".to_owned(),
+            Scope::Field => "this",
+            Scope::Static => "static",
+            Scope::Argument => "argument",
+            Scope::Local => "local",
         }
     }
 }
 
 #[derive(Debug, Clone)]
 pub struct SymbolTableVariable {
-    name: String,
+    // Lookups go through the frame's map, but the name is kept so that a looked up field can be
+    // tracked by name, e.g. to warn about fields read before they are initialized
+    name: Identifier,
     scope: Scope,
-    var_type: String,
+    var_type: Identifier,
     index: i32,
 }
 
 impl SymbolTableVariable {
-    pub fn new(name: &str, var_type: &str, scope: Scope, index: i32) -> Self {
+    pub fn new(name: Identifier, var_type: Identifier, scope: Scope, index: i32) -> Self {
         Self {
-            name: name.to_owned(),
-            var_type: var_type.to_owned(),
+            name,
+            var_type,
             scope,
             index,
         }
     }
 
-    pub fn name(&self) -> &str {
+    pub fn name(&self) -> &Identifier {
         &self.name
     }
 
@@ -43,7 +49,7 @@ impl SymbolTableVariable {
         self.scope
     }
 
-    pub fn var_type(&self) -> &str {
+    pub fn var_type(&self) -> &Identifier {
         &self.var_type
     }
 
@@ -63,100 +69,121 @@ impl SymbolTableVariable {
 /// When a new scope is entered it can segment off the variables in that segment.
 #[derive(Debug)]
 pub struct SymbolTable {
-    vars: Vec<SymbolTableVariable>,
-    scopes: Vec<usize>,
+    /// The outermost frame holds variables added before any scope was created
+    frames: Vec<Frame>,
+}
+
+/// The variables of a single scope, with the next index to hand out for each kind.
+///
+/// Identifiers are short, so the Fx hasher is used rather than the default SipHash.
+#[derive(Debug, Default)]
+struct Frame {
+    vars: FxHashMap<Identifier, SymbolTableVariable>,
+    counts: [i32; 4],
+    /// Block frames carry on numbering locals from the frame they are nested in
+    block: bool,
+}
+
+impl Frame {
+    fn count(&self, scope: Scope) -> i32 {
+        self.counts[scope as usize]
+    }
 }
 
 impl SymbolTable {
     pub fn new() -> Self {
         Self {
-            vars: Vec::new(),
-            scopes: Vec::new(),
+            frames: vec![Frame::default()],
         }
     }
 
-    pub fn add_field(&mut self, var_name: &str, var_type: &str) {
-        self.vars.push(SymbolTableVariable::new(
-            var_name,
-            var_type,
-            Scope::Field,
-            self.find_next_index(Scope::Field),
-        ));
+    pub fn add_field(&mut self, var_name: impl Into<Identifier>, var_type: impl Into<Identifier>) {
+        self.add(var_name.into(), var_type.into(), Scope::Field);
     }
 
-    pub fn add_static(&mut self, var_name: &str, var_type: &str) {
-        self.vars.push(SymbolTableVariable::new(
-            var_name,
-            var_type,
-            Scope::Static,
-            self.find_next_index(Scope::Static),
-        ));
+    pub fn add_static(&mut self, var_name: impl Into<Identifier>, var_type: impl Into<Identifier>) {
+        self.add(var_name.into(), var_type.into(), Scope::Static);
     }
 
-    pub fn add_argument(&mut self, var_name: &str, var_type: &str) {
-        self.vars.push(SymbolTableVariable::new(
-            var_name,
-            var_type,
-            Scope::Argument,
-            self.find_next_index(Scope::Argument),
-        ));
+    pub fn add_argument(
+        &mut self,
+        var_name: impl Into<Identifier>,
+        var_type: impl Into<Identifier>,
+    ) {
+        self.add(var_name.into(), var_type.into(), Scope::Argument);
     }
 
-    pub fn add_local(&mut self, var_name: &str, var_type: &str) {
-        self.vars.push(SymbolTableVariable::new(
-            var_name,
-            var_type,
-            Scope::Local,
-            self.find_next_index(Scope::Local),
-        ));
+    pub fn add_local(&mut self, var_name: impl Into<Identifier>, var_type: impl Into<Identifier>) {
+        self.add(var_name.into(), var_type.into(), Scope::Local);
     }
 
     pub fn count_locals(&self) -> i32 {
-        self.vars
+        self.frames
             .iter()
-            .filter(|var| var.scope() == Scope::Local)
-            .count() as i32
+            .filter(|frame| !frame.block)
+            .map(|frame| frame.count(Scope::Local))
+            .sum()
     }
 
     pub fn count_fields(&self) -> i32 {
-        self.vars
+        self.frames
             .iter()
-            .filter(|var| var.scope() == Scope::Field)
-            .count() as i32
+            .map(|frame| frame.count(Scope::Field))
+            .sum()
     }
 
     pub fn find_variable(&self, var_name: &str) -> Option<SymbolTableVariable> {
-        self.vars
+        self.frames
             .iter()
             .rev()
-            .find(|var| var.name() == var_name)
-            .map(|var| var.clone())
+            .find_map(|frame| frame.vars.get(var_name))
+            .cloned()
     }
 
     pub fn create_scope(&mut self) {
-        self.scopes.push(self.vars.len());
+        self.frames.push(Frame::default());
+    }
+
+    /// Create a scope for the body of an if or while statement. Its locals are numbered after
+    /// every local declared so far, so each variable in a subroutine gets its own slot.
+    pub fn create_block_scope(&mut self) {
+        let parent = self
+            .frames
+            .last()
+            .expect("The outermost frame is never popped");
+        let mut counts = [0; 4];
+        counts[Scope::Local as usize] = parent.count(Scope::Local);
+        self.frames.push(Frame {
+            vars: FxHashMap::default(),
+            counts,
+            block: true,
+        });
     }
 
     pub fn pop_scope(&mut self) {
-        let scope_index = self.scopes.pop();
-        if let Some(index) = scope_index {
-            // Remove all the variables from the end to the index
-            let num_pops = self.vars.len() - index;
-            for _ in 0..num_pops {
-                self.vars.pop();
+        if self.frames.len() > 1 {
+            let frame = self.frames.pop().expect("There is more than one frame");
+            if frame.block {
+                let parent = self
+                    .frames
+                    .last_mut()
+                    .expect("The outermost frame is never popped");
+                parent.counts[Scope::Local as usize] = frame.count(Scope::Local);
             }
         }
     }
 
-    fn find_next_index(&self, scope: Scope) -> i32 {
-        let scope_start_index = self.scopes.last().unwrap_or(&0usize).clone();
-        let scope_end_index = self.vars.len();
-        let max_index = self.vars[scope_start_index..scope_end_index]
-            .iter()
-            .filter(|var| var.scope() == scope)
-            .max_by_key(|var| var.index())
-            .map(|var| var.index());
-        max_index.map(|index| index + 1).unwrap_or(0)
+    fn add(&mut self, name: Identifier, var_type: Identifier, scope: Scope) {
+        let frame = self
+            .frames
+            .last_mut()
+            .expect("The outermost frame is never popped");
+        let index = frame.count(scope);
+        frame.counts[scope as usize] += 1;
+        frame.vars.insert(
+            name.clone(),
+            SymbolTableVariable::new(name, var_type, scope, index),
+        );
     }
 }
 
@@ -232,3 +259,36 @@ fn count_field_vars() {
 
     assert_eq!(table.count_fields(), 2);
 }
+
+#[test]
+fn redeclaring_a_variable_shadows_it() {
+    let mut table = SymbolTable::new();
+    table.add_local("x", "int");
+    table.add_local("x", "boolean");
+
+    let x = table.find_variable("x").unwrap();
+    assert_eq!(x.index(), 1);
+    assert_eq!(x.var_type(), "boolean");
+    assert_eq!(table.count_locals(), 2);
+}
+
+#[test]
+fn block_scopes_number_locals_after_the_enclosing_scope() {
+    let mut table = SymbolTable::new();
+    table.create_scope();
+    table.add_local("x", "int");
+
+    table.create_block_scope();
+    table.add_local("x", "boolean");
+    assert_eq!(table.find_variable("x").unwrap().index(), 1);
+    table.pop_scope();
+
+    table.create_block_scope();
+    table.add_local("y", "int");
+    assert_eq!(table.find_variable("y").unwrap().index(), 2);
+    table.pop_scope();
+
+    assert!(table.find_variable("y").is_none());
+    assert_eq!(table.find_variable("x").unwrap().index(), 0);
+    assert_eq!(table.count_locals(), 3);
+}
@@ -0,0 +1,409 @@
+use std::fmt;
+
+use rustc_hash::FxHashMap;
+
+use crate::{
+    ast::{
+        BinaryOp, Class, Constant, ExprKind, ExprRef, Identifier, KeywordConstant, ReturnType,
+        Statement, Subroutine, SubroutineCall, Visit,
+    },
+    compiler::{CompilationError, SubroutineError},
+    semantics::Scopes,
+    signatures::Signatures,
+};
+
+/// The type of an expression as far as the checker can tell
+#[derive(Debug, Clone, PartialEq)]
+enum JackType {
+    Int,
+    Char,
+    Boolean,
+    Class(String),
+    /// `null`, array elements and the results of OS calls
+    Any,
+    Void,
+}
+
+impl JackType {
+    /// From a name in the symbol table, see [`crate::ast::VariableType::type_name`]
+    fn from_type_name(name: &Identifier) -> Self {
+        match name.as_str() {
+            "Int" => JackType::Int,
+            "Char" => JackType::Char,
+            "Bool" => JackType::Boolean,
+            name => JackType::Class(name.to_owned()),
+        }
+    }
+
+    fn from_return_type(return_type: &ReturnType) -> Self {
+        match return_type {
+            ReturnType::Int => JackType::Int,
+            ReturnType::Char => JackType::Char,
+            ReturnType::Boolean => JackType::Boolean,
+            ReturnType::Void => JackType::Void,
+            ReturnType::ClassName(name) if name.as_str() == "boolean" => JackType::Boolean,
+            ReturnType::ClassName(name) => JackType::Class(name.to_string()),
+        }
+    }
+
+    /// Arrays count as they double as pointers, e.g. in the OS's heap code
+    fn is_numeric(&self) -> bool {
+        match self {
+            JackType::Int | JackType::Char | JackType::Any => true,
+            JackType::Class(name) => name == "Array",
+            _ => false,
+        }
+    }
+
+    /// Whether a value of this type can be stored where `target` is expected. Ints and chars mix
+    /// freely and an `Array` can hold anything but a boolean, as Jack programs rely on both.
+    fn assignable_to(&self, target: &JackType) -> bool {
+        match (self, target) {
+            (JackType::Void, _) | (_, JackType::Void) => false,
+            (JackType::Any, _) | (_, JackType::Any) => true,
+            (JackType::Boolean, target) => *target == JackType::Boolean,
+            (_, JackType::Boolean) => false,
+            (_, JackType::Class(name)) | (JackType::Class(name), _) if name == "Array" => true,
+            (JackType::Class(_), JackType::Class(_)) => self == target,
+            (JackType::Class(_), _) | (_, JackType::Class(_)) => false,
+            _ => true,
+        }
+    }
+}
+
+impl fmt::Display for JackType {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            JackType::Int => write!(f, "int"),
+            JackType::Char => write!(f, "char"),
+            JackType::Boolean => write!(f, "boolean"),
+            JackType::Class(name) => write!(f, "{}", name),
+            JackType::Any => write!(f, "any type"),
+            JackType::Void => write!(f, "void"),
+        }
+    }
+}
+
+struct TypeChecker<'a> {
+    class: &'a Class,
+    subroutine: &'a Subroutine,
+    /// Subroutines declared in the program by full name
+    program: &'a FxHashMap<String, &'a Subroutine>,
+    signatures: &'a Signatures,
+    scopes: Scopes<'a, JackType>,
+    /// The line of the statement being checked
+    line: u32,
+}
+
+/// Every subroutine declared by the program's classes, for [`check_types`]
+pub fn program_subroutines<'a>(
+    classes: impl IntoIterator<Item = &'a Class>,
+) -> FxHashMap<String, &'a Subroutine> {
+    classes
+        .into_iter()
+        .flat_map(|class| {
+            class.subroutines().iter().map(move |subroutine| {
+                (
+                    format!("{}.{}", class.get_name(), subroutine.get_name()),
+                    subroutine,
+                )
+            })
+        })
+        .collect()
+}
+
+/// Check that the values in a class are used as their declared types: booleans aren't
+/// assigned to ints, methods are only called on objects, conditions are booleans and return
+/// statements match their subroutine.
+///
+/// OS subroutines aren't declared in Jack so their arguments and results aren't checked.
+pub fn check_types<'a>(
+    class: &'a Class,
+    program: &FxHashMap<String, &Subroutine>,
+    signatures: &Signatures,
+) -> Result<(), SubroutineError<'a>> {
+    for subroutine in class.subroutines() {
+        let mut checker = TypeChecker {
+            class,
+            subroutine,
+            program,
+            signatures,
+            scopes: Scopes::new(class, subroutine, |type_name| {
+                JackType::from_type_name(&type_name)
+            }),
+            line: 0,
+        };
+        checker
+            .check_block(subroutine.get_statements())
+            .map_err(|error| SubroutineError {
+                subroutine,
+                line: checker.line,
+                error,
+            })?;
+    }
+    Ok(())
+}
+
+impl<'a> TypeChecker<'a> {
+    fn check_block(&mut self, statements: &'a [Statement]) -> Result<(), CompilationError> {
+        self.scopes.push();
+        for statement in statements {
+            self.check_statement(statement)?;
+        }
+        self.scopes.pop();
+        Ok(())
+    }
+
+    fn check_statement(&mut self, statement: &'a Statement) -> Result<(), CompilationError> {
+        if !matches!(statement, Statement::VarDecl(_)) {
+            self.line = statement.line();
+        }
+        match statement {
+            Statement::VarDecl(details) => {
+                for variable in details.get_variables() {
+                    self.scopes.declare(
+                        variable.get_identifier().as_str(),
+                        JackType::from_type_name(&variable.get_type().type_name()),
+                    );
+                }
+                Ok(())
+            }
+            Statement::Let(details) => {
+                let variable = details.get_identifier();
+                let target = match variable.get_index() {
+                    Some(index) => {
+                        self.expect_numeric(index.root(), "an array index")?;
+                        JackType::Any
+                    }
+                    None => self.variable_type(variable.get_name()),
+                };
+                let value = self.expression_type(details.get_expression().root())?;
+                if !value.assignable_to(&target) {
+                    return Err(self.error(format!(
+                        "assigns a value of type {} to {}, which is {}",
+                        value,
+                        variable.get_name(),
+                        target
+                    )));
+                }
+                Ok(())
+            }
+            Statement::Do(call) => self.call_type(call).map(|_| ()),
+            Statement::Return(details) => {
+                let declared = JackType::from_return_type(self.subroutine.get_return_type());
+                match (details.get_value(), &declared) {
+                    (None, JackType::Void) => Ok(()),
+                    (None, _) => Err(self.error(format!(
+                        "returns without a value but is declared to return {}",
+                        declared
+                    ))),
+                    (Some(_), JackType::Void) => {
+                        Err(self.error("returns a value but is declared void".to_owned()))
+                    }
+                    (Some(value), _) => {
+                        let value = self.expression_type(value.root())?;
+                        if !value.assignable_to(&declared) {
+                            return Err(self.error(format!(
+                                "returns a value of type {} but is declared to return {}",
+                                value, declared
+                            )));
+                        }
+                        Ok(())
+                    }
+                }
+            }
+            Statement::While(details) => {
+                self.expect_boolean(details.get_condition().root(), "a while condition")?;
+                self.check_block(details.get_body())
+            }
+            Statement::If(details) => {
+                self.expect_boolean(details.get_condition().root(), "an if condition")?;
+                self.check_block(details.get_if_body())?;
+                details
+                    .get_else_body()
+                    .map_or(Ok(()), |body| self.check_block(body))
+            }
+        }
+    }
+
+    fn expression_type(&mut self, expr: ExprRef<'a>) -> Result<JackType, CompilationError> {
+        match expr.kind() {
+            ExprKind::Constant(Constant::Int(_)) => Ok(JackType::Int),
+            ExprKind::Constant(Constant::String(_)) => Ok(JackType::Class("String".to_owned())),
+            ExprKind::Constant(Constant::Keyword(keyword)) => Ok(match keyword {
+                KeywordConstant::True | KeywordConstant::False => JackType::Boolean,
+                KeywordConstant::Null => JackType::Any,
+                KeywordConstant::This => JackType::Class(self.class.get_name().to_string()),
+            }),
+            ExprKind::VarRef(variable) => match variable.get_index() {
+                Some(index) => {
+                    self.expect_numeric(index.root(), "an array index")?;
+                    Ok(JackType::Any)
+                }
+                None => Ok(self.variable_type(variable.get_name())),
+            },
+            ExprKind::BracketedExpr(expr) => self.expression_type(expr),
+            ExprKind::UnaryExpr(_, expr) => {
+                let operand = self.expression_type(expr)?;
+                if matches!(operand, JackType::Class(_) | JackType::Void) {
+                    return Err(self.error(format!(
+                        "applies a unary operator to a value of type {}",
+                        operand
+                    )));
+                }
+                Ok(operand)
+            }
+            ExprKind::BinaryExpr { .. } => {
+                let mut types = Vec::new();
+                for visit in expr.postfix() {
+                    match visit {
+                        Visit::Operand(operand) => types.push(self.expression_type(operand)?),
+                        Visit::Operator(op) => {
+                            let rhs = types.pop().expect("Every operator has two operands");
+                            let lhs = types.pop().expect("Every operator has two operands");
+                            types.push(self.binary_type(lhs, op, rhs)?);
+                        }
+                    }
+                }
+                Ok(types.pop().expect("A chain has one type"))
+            }
+            ExprKind::Call(call) => {
+                let result = self.call_type(call)?;
+                if result == JackType::Void {
+                    return Err(self.error(format!(
+                        "uses the result of {}, which returns void",
+                        call.name_as_string()
+                    )));
+                }
+                Ok(result)
+            }
+        }
+    }
+
+    fn binary_type(
+        &self,
+        lhs: JackType,
+        op: BinaryOp,
+        rhs: JackType,
+    ) -> Result<JackType, CompilationError> {
+        match op {
+            BinaryOp::Plus
+            | BinaryOp::Minus
+            | BinaryOp::Mult
+            | BinaryOp::Div
+            | BinaryOp::Lt
+            | BinaryOp::Gt => {
+                if let Some(operand) = [&lhs, &rhs].into_iter().find(|t| !t.is_numeric()) {
+                    return Err(
+                        self.error(format!("uses a value of type {} in arithmetic", operand))
+                    );
+                }
+                Ok(match op {
+                    BinaryOp::Lt | BinaryOp::Gt => JackType::Boolean,
+                    _ => JackType::Int,
+                })
+            }
+            BinaryOp::And | BinaryOp::Or | BinaryOp::Eq => {
+                if !lhs.assignable_to(&rhs) {
+                    return Err(self.error(format!(
+                        "combines a value of type {} with a value of type {}",
+                        lhs, rhs
+                    )));
+                }
+                Ok(match (op, lhs, rhs) {
+                    (BinaryOp::Eq, _, _) => JackType::Boolean,
+                    (_, JackType::Boolean, _) | (_, _, JackType::Boolean) => JackType::Boolean,
+                    (_, JackType::Any, _) | (_, _, JackType::Any) => JackType::Any,
+                    _ => JackType::Int,
+                })
+            }
+        }
+    }
+
+    fn call_type(&mut self, call: &'a SubroutineCall) -> Result<JackType, CompilationError> {
+        let class_name = match call.get_target() {
+            Some(target) => match self.scopes.find(target).cloned() {
+                Some(JackType::Class(class_name)) => class_name,
+                Some(target_type) => {
+                    return Err(self.error(format!(
+                        "calls {} on {}, which is {} rather than an object",
+                        call.get_name(),
+                        target,
+                        target_type
+                    )))
+                }
+                None => target.to_string(),
+            },
+            None => self.class.get_name().to_string(),
+        };
+        let name = format!("{}.{}", class_name, call.get_name());
+
+        let arguments = call
+            .get_parameters()
+            .iter()
+            .map(|argument| self.expression_type(argument.root()))
+            .collect::<Result<Vec<_>, _>>()?;
+
+        if let Some(subroutine) = self.program.get(&name) {
+            for (argument, parameter) in arguments.iter().zip(subroutine.get_parameters()) {
+                let expected = JackType::from_type_name(&parameter.get_type().type_name());
+                if !argument.assignable_to(&expected) {
+                    return Err(self.error(format!(
+                        "passes a value of type {} to {} as {}, which is {}",
+                        argument,
+                        name,
+                        parameter.get_identifier(),
+                        expected
+                    )));
+                }
+            }
+            return Ok(JackType::from_return_type(subroutine.get_return_type()));
+        }
+        Ok(match self.signatures.get(&name) {
+            Some(signature) if signature.returns_void => JackType::Void,
+            _ => JackType::Any,
+        })
+    }
+
+    fn expect_boolean(&mut self, expr: ExprRef<'a>, what: &str) -> Result<(), CompilationError> {
+        let found = self.expression_type(expr)?;
+        if !found.assignable_to(&JackType::Boolean) {
+            return Err(self.error(format!("uses a value of type {} as {}", found, what)));
+        }
+        Ok(())
+    }
+
+    fn expect_numeric(&mut self, expr: ExprRef<'a>, what: &str) -> Result<(), CompilationError> {
+        let found = self.expression_type(expr)?;
+        if !found.is_numeric() {
+            return Err(self.error(format!("uses a value of type {} as {}", found, what)));
+        }
+        Ok(())
+    }
+
+    /// Unknown variables are reported by code generation, so they're accepted here
+    fn variable_type(&self, name: &Identifier) -> JackType {
+        self.scopes.find(name).cloned().unwrap_or(JackType::Any)
+    }
+
+    fn error(&self, message: String) -> CompilationError {
+        CompilationError::TypeError {
+            subroutine: format!("{}.{}", self.class.get_name(), self.subroutine.get_name()),
+            message,
+        }
+    }
+}
+
+#[test]
+fn test_assignability() {
+    let class = |name: &str| JackType::Class(name.to_owned());
+    assert!(JackType::Char.assignable_to(&JackType::Int));
+    assert!(!JackType::Boolean.assignable_to(&JackType::Int));
+    assert!(!JackType::Int.assignable_to(&JackType::Boolean));
+    assert!(JackType::Int.assignable_to(&class("Array")));
+    assert!(class("Array").assignable_to(&class("Ball")));
+    assert!(!class("String").assignable_to(&class("Ball")));
+    assert!(!class("String").assignable_to(&JackType::Int));
+    assert!(JackType::Any.assignable_to(&JackType::Boolean));
+    assert!(!JackType::Void.assignable_to(&JackType::Any));
+}
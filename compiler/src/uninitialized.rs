@@ -0,0 +1,114 @@
+use rustc_hash::FxHashSet;
+
+use crate::ast::{Class, Statement};
+use crate::compiler::CompilationWarning;
+use crate::unused::{call_reads, expr_reads, locals};
+
+/// The locals assigned on every path to a statement
+type Assigned<'a> = FxHashSet<&'a str>;
+
+/// Warn about locals which are read before a let assigns them on every path to the read. The VM
+/// zeroes locals, so this is legal Jack, but it's almost always a mistake. Each local is reported
+/// at its first such read.
+pub fn uninitialized_locals(class: &Class) -> Vec<CompilationWarning> {
+    let mut warnings = Vec::new();
+    for subroutine in class.subroutines() {
+        let mut checker = Checker {
+            subroutine: format!("{}.{}", class.get_name(), subroutine.get_name()),
+            locals: locals(subroutine.get_statements())
+                .into_iter()
+                .map(|local| local.get_identifier().as_str())
+                .collect(),
+            reported: FxHashSet::default(),
+            warnings: &mut warnings,
+        };
+        checker.block(subroutine.get_statements(), Assigned::default());
+    }
+    warnings
+}
+
+struct Checker<'a, 'w> {
+    subroutine: String,
+    locals: FxHashSet<&'a str>,
+    reported: FxHashSet<&'a str>,
+    warnings: &'w mut Vec<CompilationWarning>,
+}
+
+impl<'a> Checker<'a, '_> {
+    /// Check a block entered with `assigned`, returning what's assigned once it finishes, or
+    /// `None` if it always returns
+    fn block(
+        &mut self,
+        statements: &'a [Statement],
+        mut assigned: Assigned<'a>,
+    ) -> Option<Assigned<'a>> {
+        for statement in statements {
+            let mut reads = FxHashSet::default();
+            match statement {
+                Statement::Let(details) => {
+                    if let Some(index) = details.identifier.get_index() {
+                        reads.insert(details.identifier.get_name().as_str());
+                        expr_reads(index.root(), &mut reads);
+                    }
+                    expr_reads(details.expression.root(), &mut reads);
+                    self.check(reads, &assigned, statement.line());
+                    if details.identifier.get_index().is_none() {
+                        assigned.insert(details.identifier.get_name().as_str());
+                    }
+                }
+                Statement::While(details) => {
+                    expr_reads(details.condition.root(), &mut reads);
+                    self.check(reads, &assigned, statement.line());
+                    // The body may not run at all, so nothing it assigns counts afterwards
+                    self.block(&details.body, assigned.clone());
+                }
+                Statement::If(details) => {
+                    expr_reads(details.condition.root(), &mut reads);
+                    self.check(reads, &assigned, statement.line());
+                    let if_assigned = self.block(&details.if_body, assigned.clone());
+                    let else_assigned = match &details.else_body {
+                        Some(else_body) => self.block(else_body, assigned.clone()),
+                        None => Some(assigned.clone()),
+                    };
+                    assigned = match (if_assigned, else_assigned) {
+                        (Some(if_assigned), Some(else_assigned)) => {
+                            if_assigned.intersection(&else_assigned).copied().collect()
+                        }
+                        (Some(only), None) | (None, Some(only)) => only,
+                        (None, None) => return None,
+                    };
+                }
+                Statement::Do(call) => {
+                    call_reads(call, &mut reads);
+                    self.check(reads, &assigned, statement.line());
+                }
+                Statement::Return(details) => {
+                    if let Some(value) = &details.value {
+                        expr_reads(value.root(), &mut reads);
+                    }
+                    self.check(reads, &assigned, statement.line());
+                    return None;
+                }
+                Statement::VarDecl(_) => {}
+            }
+        }
+        Some(assigned)
+    }
+
+    fn check(&mut self, reads: FxHashSet<&'a str>, assigned: &Assigned<'a>, line: u32) {
+        let mut unassigned: Vec<&str> = reads
+            .into_iter()
+            .filter(|name| self.locals.contains(name) && !assigned.contains(name))
+            .collect();
+        unassigned.sort_unstable();
+        for name in unassigned {
+            if self.reported.insert(name) {
+                self.warnings.push(CompilationWarning::UninitializedLocal {
+                    subroutine: self.subroutine.clone(),
+                    name: name.to_owned(),
+                    line,
+                });
+            }
+        }
+    }
+}
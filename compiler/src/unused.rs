@@ -0,0 +1,147 @@
+use rustc_hash::FxHashSet;
+
+use crate::ast::{
+    Class, ClassVariableVisibility, ExprKind, ExprRef, Statement, SubroutineCall, Variable,
+};
+use crate::compiler::CompilationWarning;
+
+/// Warn about the locals, parameters, fields and statics of a class which are declared but never
+/// read. Assigning a variable doesn't count as reading it, but assigning an element of an array
+/// does, as it reads the array.
+pub fn unused_variables(class: &Class) -> Vec<CompilationWarning> {
+    let mut warnings = Vec::new();
+    // Class variables read by a subroutine without a local or parameter of the same name
+    let mut class_reads: FxHashSet<&str> = FxHashSet::default();
+
+    for subroutine in class.subroutines() {
+        let scope = format!("{}.{}", class.get_name(), subroutine.get_name());
+        let mut reads = FxHashSet::default();
+        for statement in subroutine.get_statements() {
+            statement_reads(statement, &mut reads);
+        }
+
+        let locals = locals(subroutine.get_statements());
+        let declared: Vec<(&Variable, &'static str)> = subroutine
+            .get_parameters()
+            .iter()
+            .map(|parameter| (parameter, "argument"))
+            .chain(locals.into_iter().map(|local| (local, "local")))
+            .collect();
+        for &(variable, kind) in &declared {
+            let name = variable.get_identifier().as_str();
+            if !reads.contains(name) {
+                warnings.push(CompilationWarning::UnusedVariable {
+                    scope: scope.clone(),
+                    kind,
+                    name: name.to_owned(),
+                    line: variable.get_line(),
+                });
+            }
+        }
+        class_reads.extend(reads.into_iter().filter(|name| {
+            !declared
+                .iter()
+                .any(|(variable, _)| variable.get_identifier().as_str() == *name)
+        }));
+    }
+
+    for variable in class.variables() {
+        let name = variable.get_identifier().as_str();
+        if !class_reads.contains(name) {
+            let kind = match variable.get_visibility() {
+                ClassVariableVisibility::Field => "field",
+                ClassVariableVisibility::Static => "static",
+            };
+            warnings.push(CompilationWarning::UnusedVariable {
+                scope: class.get_name().to_string(),
+                kind,
+                name: name.to_owned(),
+                line: variable.get_line(),
+            });
+        }
+    }
+    warnings
+}
+
+/// The locals declared anywhere in a subroutine's statements
+pub fn locals(statements: &[Statement]) -> Vec<&Variable> {
+    let mut locals = Vec::new();
+    for statement in statements {
+        match statement {
+            Statement::VarDecl(details) => locals.extend(details.get_variables()),
+            Statement::While(details) => locals.extend(self::locals(&details.body)),
+            Statement::If(details) => {
+                locals.extend(self::locals(&details.if_body));
+                if let Some(else_body) = &details.else_body {
+                    locals.extend(self::locals(else_body));
+                }
+            }
+            _ => {}
+        }
+    }
+    locals
+}
+
+fn statement_reads<'a>(statement: &'a Statement, reads: &mut FxHashSet<&'a str>) {
+    match statement {
+        Statement::Let(details) => {
+            if let Some(index) = details.identifier.get_index() {
+                reads.insert(details.identifier.get_name().as_str());
+                expr_reads(index.root(), reads);
+            }
+            expr_reads(details.expression.root(), reads);
+        }
+        Statement::While(details) => {
+            expr_reads(details.condition.root(), reads);
+            for statement in &details.body {
+                statement_reads(statement, reads);
+            }
+        }
+        Statement::If(details) => {
+            expr_reads(details.condition.root(), reads);
+            let else_body = details.else_body.iter().flatten();
+            for statement in details.if_body.iter().chain(else_body) {
+                statement_reads(statement, reads);
+            }
+        }
+        Statement::Do(call) => call_reads(call, reads),
+        Statement::Return(details) => {
+            if let Some(value) = &details.value {
+                expr_reads(value.root(), reads);
+            }
+        }
+        Statement::VarDecl(_) => {}
+    }
+}
+
+pub fn expr_reads<'a>(expr: ExprRef<'a>, reads: &mut FxHashSet<&'a str>) {
+    match expr.kind() {
+        ExprKind::Constant(_) => {}
+        ExprKind::VarRef(var) => {
+            reads.insert(var.get_name().as_str());
+            if let Some(index) = var.get_index() {
+                expr_reads(index.root(), reads);
+            }
+        }
+        ExprKind::UnaryExpr(_, operand) | ExprKind::BracketedExpr(operand) => {
+            expr_reads(operand, reads)
+        }
+        ExprKind::BinaryExpr { .. } => {
+            for operand in expr.operands() {
+                expr_reads(operand, reads);
+            }
+        }
+        ExprKind::Call(call) => call_reads(call, reads),
+    }
+}
+
+/// A method called through a variable reads it. The target may be a class instead, which is
+/// harmless to record.
+pub fn call_reads<'a>(call: &'a SubroutineCall, reads: &mut FxHashSet<&'a str>) {
+    if let Some(target) = call.get_target() {
+        reads.insert(target.as_str());
+    }
+    for parameter in call.get_parameters() {
+        expr_reads(parameter.root(), reads);
+    }
+}
@@ -0,0 +1,99 @@
+use std::fmt::{self, Display, Write};
+
+/// Writes VM commands straight into a single buffer rather than allocating a string per command
+pub struct VmWriter {
+    buffer: String,
+    /// The Jack line each command was written for
+    source_lines: Vec<u32>,
+    source_line: u32,
+}
+
+impl VmWriter {
+    pub fn with_capacity(capacity: usize) -> Self {
+        Self {
+            buffer: String::with_capacity(capacity),
+            source_lines: Vec::new(),
+            source_line: 0,
+        }
+    }
+
+    /// Attribute the commands written from now on to a line of the Jack source
+    pub fn set_source_line(&mut self, line: u32) {
+        self.source_line = line;
+    }
+
+    pub fn push(&mut self, segment: &str, index: impl Display) {
+        self.line(format_args!("push {} {}", segment, index));
+    }
+
+    pub fn pop(&mut self, segment: &str, index: impl Display) {
+        self.line(format_args!("pop {} {}", segment, index));
+    }
+
+    /// Write an arithmetic or logical command such as `add` or `not`
+    pub fn arithmetic(&mut self, command: &str) {
+        self.line(format_args!("{}", command));
+    }
+
+    pub fn label(&mut self, label: impl Display) {
+        self.line(format_args!("label {}", label));
+    }
+
+    pub fn goto(&mut self, label: impl Display) {
+        self.line(format_args!("goto {}", label));
+    }
+
+    pub fn if_goto(&mut self, label: impl Display) {
+        self.line(format_args!("if-goto {}", label));
+    }
+
+    pub fn call(&mut self, name: impl Display, num_args: usize) {
+        self.line(format_args!("call {} {}", name, num_args));
+    }
+
+    pub fn function(&mut self, name: impl Display, num_locals: impl Display) {
+        self.line(format_args!("function {} {}", name, num_locals));
+    }
+
+    pub fn ret(&mut self) {
+        self.line(format_args!("return"));
+    }
+
+    /// The VM code written so far, one command per line without a trailing newline, along with
+    /// the Jack line of each command
+    pub fn finish(mut self) -> (String, Vec<u32>) {
+        if self.buffer.ends_with('\n') {
+            self.buffer.pop();
+        }
+        (self.buffer, self.source_lines)
+    }
+
+    fn line(&mut self, command: fmt::Arguments) {
+        self.buffer
+            .write_fmt(command)
+            .expect("Writing to a String cannot fail");
+        self.buffer.push('\n');
+        self.source_lines.push(self.source_line);
+    }
+}
+
+#[test]
+fn test_vm_writer() {
+    let mut writer = VmWriter::with_capacity(0);
+    writer.set_source_line(2);
+    writer.function("Main.main", 1);
+    writer.set_source_line(3);
+    writer.push("constant", 3);
+    writer.pop("local", 0);
+    writer.label(format_args!("{}.while_end", "main.while.0"));
+    writer.arithmetic("not");
+    writer.call("Output.printInt", 1);
+    writer.ret();
+
+    let (code, source_lines) = writer.finish();
+    assert_eq!(
+        code,
+        "function Main.main 1\npush constant 3\npop local 0\nlabel main.while.0.while_end\nnot\ncall Output.printInt 1\nreturn"
+    );
+    assert_eq!(source_lines, [2, 3, 3, 3, 3, 3, 3]);
+}
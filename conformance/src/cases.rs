@@ -0,0 +1,274 @@
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use crate::normalize::{
+    first_difference, normalize_asm_labels, normalize_lines, normalize_vm_labels,
+};
+use crate::ErrorType;
+
+pub enum Outcome {
+    Pass,
+    Fail(String),
+    Skip(String),
+}
+
+/// The result of checking a single expected output file
+pub struct CaseResult {
+    pub expected: PathBuf,
+    pub outcome: Outcome,
+}
+
+#[derive(Clone, Copy)]
+enum OutputKind {
+    Vm,
+    Asm,
+    Hack,
+}
+
+/// Walk `root` and check every expected output file found against the toolchain
+pub fn run_cases(root: &Path) -> Result<Vec<CaseResult>, ErrorType> {
+    let mut results = Vec::new();
+    let mut dirs = vec![root.to_owned()];
+
+    while let Some(dir) = dirs.pop() {
+        let mut files = Vec::new();
+        let read_error = |source| ErrorType::ReadError {
+            path: dir.clone(),
+            source,
+        };
+        // Files are kept in directory order, which is the order the tools see them in
+        for entry in dir.read_dir().map_err(read_error)? {
+            let path = entry.map_err(read_error)?.path();
+            if path.is_dir() {
+                dirs.push(path);
+            } else {
+                files.push(path);
+            }
+        }
+
+        check_directory(&dir, &files, &mut results)?;
+    }
+
+    results.sort_by(|a, b| a.expected.cmp(&b.expected));
+    Ok(results)
+}
+
+fn check_directory(
+    dir: &Path,
+    files: &[PathBuf],
+    results: &mut Vec<CaseResult>,
+) -> Result<(), ErrorType> {
+    let jack_files = with_extension(files, "jack");
+    let vm_files = with_extension(files, "vm");
+
+    if !jack_files.is_empty() {
+        // Jack sources are compiled together as a program and each class is checked against
+        // its .vm file
+        let sources = read_sources(&jack_files)?;
+        let borrowed = borrow_sources(&sources);
+        // Expected .vm files normally come from the reference compiler, so match its encoding
+        let options = compiler::CodegenOptions {
+            canonical_booleans: true,
+            ..Default::default()
+        };
+        let compiled = compiler::parse_strings(&borrowed)
+            .and_then(|ast| compiler::compile_ast_with_options(&ast, &options));
+        match compiled {
+            Ok(vm_files) => {
+                for (vm_name, vm_code) in vm_files {
+                    let expected = dir.join(vm_name);
+                    if expected.is_file() {
+                        results.push(compare(&expected, &vm_code, OutputKind::Vm)?);
+                    }
+                }
+            }
+            Err(err) => results.push(CaseResult {
+                expected: dir.to_owned(),
+                outcome: Outcome::Fail(err.to_string()),
+            }),
+        }
+    } else if !vm_files.is_empty() {
+        let program_asm = dir
+            .file_name()
+            .map(|name| dir.join(name).with_extension("asm"))
+            .filter(|path| path.is_file());
+
+        if let Some(expected) = program_asm {
+            // A <Dir>.asm file is the output of translating the whole directory
+            let sources = read_sources(&vm_files)?;
+            let borrowed = borrow_sources(&sources);
+            results.push(check(
+                &expected,
+                vm_translator::translate_program(&borrowed),
+                OutputKind::Asm,
+            )?);
+        } else {
+            for vm_file in &vm_files {
+                let expected = vm_file.with_extension("asm");
+                if expected.is_file() {
+                    let (name, contents) = read_source(vm_file)?;
+                    results.push(check(
+                        &expected,
+                        vm_translator::translate_string(&name, &contents),
+                        OutputKind::Asm,
+                    )?);
+                }
+            }
+        }
+    }
+
+    for asm_file in with_extension(files, "asm") {
+        let expected = asm_file.with_extension("hack");
+        if expected.is_file() {
+            let (_, contents) = read_source(&asm_file)?;
+            results.push(check(
+                &expected,
+                assembler::assemble_string(&contents),
+                OutputKind::Hack,
+            )?);
+        }
+    }
+
+    for cmp_file in with_extension(files, "cmp") {
+        let outcome = if cmp_file.with_extension("hdl").is_file() {
+            Outcome::Skip("chips need a hardware simulator to test".to_owned())
+        } else {
+            match test_script(&cmp_file, !vm_files.is_empty()) {
+                Some(script) => match emulator::run_test_script(&script, false) {
+                    Ok(_) => Outcome::Pass,
+                    Err(err) => Outcome::Fail(err.to_string()),
+                },
+                None => Outcome::Skip("there is no test script to produce it".to_owned()),
+            }
+        };
+        results.push(CaseResult {
+            expected: cmp_file,
+            outcome,
+        });
+    }
+
+    Ok(())
+}
+
+/// The script which checks a program against `cmp_file`: `Name.tst` for the CPU emulator, or
+/// `NameVME.tst` for the VM emulator. The CPU scripts of VM programs load the assembly a
+/// translator would write, so the VM emulator's script is run for those.
+fn test_script(cmp_file: &Path, vm_program: bool) -> Option<PathBuf> {
+    let stem = cmp_file.file_stem()?.to_string_lossy();
+    let cpu_script = cmp_file.with_extension("tst");
+    let vm_script = cmp_file.with_file_name(format!("{}VME.tst", stem));
+    let scripts = match vm_program {
+        true => [vm_script, cpu_script],
+        false => [cpu_script, vm_script],
+    };
+    scripts.into_iter().find(|script| script.is_file())
+}
+
+fn check<E: std::error::Error>(
+    expected: &Path,
+    actual: Result<String, E>,
+    kind: OutputKind,
+) -> Result<CaseResult, ErrorType> {
+    match actual {
+        Ok(actual) => compare(expected, &actual, kind),
+        Err(err) => Ok(CaseResult {
+            expected: expected.to_owned(),
+            outcome: Outcome::Fail(err.to_string()),
+        }),
+    }
+}
+
+fn compare(expected: &Path, actual: &str, kind: OutputKind) -> Result<CaseResult, ErrorType> {
+    let (_, expected_contents) = read_source(expected)?;
+    let normalize = |text: &str| {
+        let lines = normalize_lines(text);
+        match kind {
+            OutputKind::Vm => normalize_vm_labels(lines),
+            OutputKind::Asm => normalize_asm_labels(lines),
+            OutputKind::Hack => lines,
+        }
+    };
+
+    let outcome = match first_difference(&normalize(&expected_contents), &normalize(actual)) {
+        Some(difference) => Outcome::Fail(difference),
+        None => Outcome::Pass,
+    };
+
+    Ok(CaseResult {
+        expected: expected.to_owned(),
+        outcome,
+    })
+}
+
+fn with_extension(files: &[PathBuf], extension: &str) -> Vec<PathBuf> {
+    files
+        .iter()
+        .filter(|file| file.extension().is_some_and(|ext| ext == extension))
+        .cloned()
+        .collect()
+}
+
+fn read_sources(files: &[PathBuf]) -> Result<Vec<(String, String)>, ErrorType> {
+    files.iter().map(|file| read_source(file)).collect()
+}
+
+fn borrow_sources(sources: &[(String, String)]) -> Vec<(&str, &str)> {
+    sources
+        .iter()
+        .map(|(name, contents)| (name.as_str(), contents.as_str()))
+        .collect()
+}
+
+fn read_source(path: &Path) -> Result<(String, String), ErrorType> {
+    let contents = fs::read_to_string(path).map_err(|source| ErrorType::ReadError {
+        path: path.to_owned(),
+        source,
+    })?;
+    let name = path
+        .file_name()
+        .map(|name| name.to_string_lossy().into_owned())
+        .unwrap_or_default();
+
+    Ok((name, contents))
+}
+
+#[test]
+fn test_comparison_files_are_checked_with_their_test_script() {
+    let dir = std::env::temp_dir().join(format!("conformance-cases-{}", std::process::id()));
+    fs::create_dir_all(&dir).unwrap();
+    fs::write(dir.join("Seven.vm"), "push constant 7\npop local 0\n").unwrap();
+    fs::write(
+        dir.join("SevenVME.tst"),
+        "load Seven.vm,
+output-file Seven.out,
+compare-to Seven.cmp,
+output-list local[0];
+set local 300,
+repeat 2 { vmstep; }
+output;
+",
+    )
+    .unwrap();
+    fs::write(dir.join("Seven.cmp"), "|local[0]|\n|      7 |\n").unwrap();
+    fs::write(dir.join("Chip.hdl"), "CHIP Chip {}").unwrap();
+    fs::write(dir.join("Chip.cmp"), "|a|\n").unwrap();
+
+    let results = run_cases(&dir).unwrap();
+    fs::remove_dir_all(&dir).unwrap();
+    let outcome = |name: &str| {
+        let result = results
+            .iter()
+            .find(|result| result.expected.ends_with(name))
+            .unwrap();
+        match &result.outcome {
+            Outcome::Pass => "pass".to_owned(),
+            Outcome::Fail(reason) => format!("fail: {}", reason),
+            Outcome::Skip(reason) => format!("skip: {}", reason),
+        }
+    };
+    assert_eq!(outcome("Seven.cmp"), "pass");
+    assert_eq!(
+        outcome("Chip.cmp"),
+        "skip: chips need a hardware simulator to test"
+    );
+}
@@ -0,0 +1,75 @@
+mod cases;
+mod normalize;
+
+use cases::{run_cases, Outcome};
+use clap::{Arg, Command, ValueHint};
+use std::io;
+use std::path::{Path, PathBuf};
+use thiserror::Error;
+
+#[derive(Debug, Error)]
+pub enum ErrorType {
+    #[error("Failed to read {}", .path.display())]
+    ReadError {
+        path: PathBuf,
+        #[source]
+        source: io::Error,
+    },
+}
+
+fn main() {
+    let matches = Command::new("conformance")
+        .about("Check the toolchain against a directory of course projects with expected outputs")
+        .arg(
+            Arg::new("DIR")
+                .index(1)
+                .required(true)
+                .value_hint(ValueHint::DirPath)
+                .help("A directory containing .jack, .vm or .asm sources and their expected .vm, .asm or .hack outputs, and test scripts with their .cmp files"),
+        )
+        .arg_required_else_help(true)
+        .get_matches();
+
+    let root = matches
+        .get_one::<String>("DIR")
+        .expect("User to provide a directory");
+
+    let results = match run_cases(Path::new(root)) {
+        Ok(results) => results,
+        Err(err) => {
+            println!("{}", err);
+            if let Some(source) = std::error::Error::source(&err) {
+                println!("  caused by: {}", source);
+            }
+            std::process::exit(1);
+        }
+    };
+
+    let (mut passed, mut failed, mut skipped) = (0, 0, 0);
+    for result in &results {
+        let path = result.expected.display();
+        match &result.outcome {
+            Outcome::Pass => {
+                passed += 1;
+                println!("PASS {}", path);
+            }
+            Outcome::Fail(reason) => {
+                failed += 1;
+                println!("FAIL {}: {}", path, reason);
+            }
+            Outcome::Skip(reason) => {
+                skipped += 1;
+                println!("SKIP {}: {}", path, reason);
+            }
+        }
+    }
+
+    println!(
+        "\n{} passed, {} failed, {} skipped",
+        passed, failed, skipped
+    );
+
+    if failed > 0 {
+        std::process::exit(1);
+    }
+}
@@ -0,0 +1,126 @@
+use std::collections::HashMap;
+
+/// Strip comments, surrounding whitespace, repeated spaces and blank lines
+pub fn normalize_lines(text: &str) -> Vec<String> {
+    text.lines()
+        .map(|line| match line.find("//") {
+            Some(index) => &line[..index],
+            None => line,
+        })
+        .map(|line| line.split_whitespace().collect::<Vec<_>>().join(" "))
+        .filter(|line| !line.is_empty())
+        .collect()
+}
+
+/// Rename VM labels to L0, L1, ... in the order they first appear. Labels are scoped to the
+/// function they are declared in so the numbering restarts at each function.
+pub fn normalize_vm_labels(lines: Vec<String>) -> Vec<String> {
+    let mut labels: HashMap<String, String> = HashMap::new();
+
+    lines
+        .into_iter()
+        .map(|line| {
+            let mut parts = line.splitn(2, ' ');
+            let command = parts.next().unwrap_or_default();
+            let argument = parts.next().unwrap_or_default();
+            match command {
+                "function" => {
+                    labels.clear();
+                    line
+                }
+                "label" | "goto" | "if-goto" => {
+                    let next_label = format!("L{}", labels.len());
+                    let label = labels.entry(argument.to_owned()).or_insert(next_label);
+                    format!("{} {}", command, label)
+                }
+                _ => line,
+            }
+        })
+        .collect()
+}
+
+/// Rename assembly labels to L0, L1, ... in the order they are declared. Variables and built-in
+/// symbols are left alone.
+pub fn normalize_asm_labels(lines: Vec<String>) -> Vec<String> {
+    let mut labels: HashMap<String, String> = HashMap::new();
+    for line in &lines {
+        if let Some(label) = line.strip_prefix('(').and_then(|l| l.strip_suffix(')')) {
+            let next_label = format!("L{}", labels.len());
+            labels.entry(label.to_owned()).or_insert(next_label);
+        }
+    }
+
+    lines
+        .into_iter()
+        .map(|line| {
+            if let Some(label) = line.strip_prefix('(').and_then(|l| l.strip_suffix(')')) {
+                return format!("({})", labels[label]);
+            }
+            match line.strip_prefix('@').and_then(|symbol| labels.get(symbol)) {
+                Some(label) => format!("@{}", label),
+                None => line,
+            }
+        })
+        .collect()
+}
+
+/// Describe the first difference between the expected and actual output, if there is one
+pub fn first_difference(expected: &[String], actual: &[String]) -> Option<String> {
+    for (index, (expected_line, actual_line)) in expected.iter().zip(actual).enumerate() {
+        if expected_line != actual_line {
+            return Some(format!(
+                "line {}: expected `{}` but found `{}`",
+                index + 1,
+                expected_line,
+                actual_line
+            ));
+        }
+    }
+
+    if expected.len() != actual.len() {
+        return Some(format!(
+            "expected {} lines but found {}",
+            expected.len(),
+            actual.len()
+        ));
+    }
+
+    None
+}
+
+#[test]
+fn test_normalize_lines() {
+    let lines = normalize_lines("// header\n  push   constant 1 // one\n\n\tadd\n");
+    assert_eq!(lines, vec!["push constant 1", "add"]);
+}
+
+#[test]
+fn test_vm_labels_restart_per_function() {
+    let expected = normalize_vm_labels(normalize_lines(
+        "function A.f 0\nlabel WHILE_EXP0\ngoto WHILE_EXP0\nfunction A.g 0\nlabel WHILE_EXP0",
+    ));
+    let actual = normalize_vm_labels(normalize_lines(
+        "function A.f 0\nlabel f.while.0\ngoto f.while.0\nfunction A.g 0\nlabel g.while.1",
+    ));
+    assert_eq!(first_difference(&expected, &actual), None);
+}
+
+#[test]
+fn test_asm_labels_keep_variables() {
+    let lines = normalize_asm_labels(normalize_lines("@LOOP\n0;JMP\n(LOOP)\n@i\nM=1\n@SP"));
+    assert_eq!(lines, vec!["@L0", "0;JMP", "(L0)", "@i", "M=1", "@SP"]);
+}
+
+#[test]
+fn test_first_difference() {
+    let expected = normalize_lines("push constant 1\nadd");
+    let actual = normalize_lines("push constant 2\nadd");
+    assert_eq!(
+        first_difference(&expected, &actual),
+        Some("line 1: expected `push constant 1` but found `push constant 2`".to_owned())
+    );
+    assert_eq!(
+        first_difference(&expected, &expected[..1]),
+        Some("expected 2 lines but found 1".to_owned())
+    );
+}
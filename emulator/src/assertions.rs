@@ -0,0 +1,47 @@
+use std::fmt;
+
+/// The functions of the Assert class which the VM emulator checks itself, and the number of
+/// arguments each takes
+pub const ASSERTIONS: [(&str, u16); 5] = [
+    ("Assert.equals", 2),
+    ("Assert.notEquals", 2),
+    ("Assert.isTrue", 1),
+    ("Assert.isFalse", 1),
+    ("Assert.fail", 0),
+];
+
+/// An assertion which didn't hold
+#[derive(Debug, Clone, PartialEq)]
+pub struct AssertionFailure {
+    /// The function which made the assertion
+    pub function: String,
+    pub message: String,
+}
+
+impl fmt::Display for AssertionFailure {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}: {}", self.function, self.message)
+    }
+}
+
+/// Whether `function` is an assertion called with `args` on the stack
+pub(crate) fn is_assertion(function: &str, num_args: u16) -> bool {
+    ASSERTIONS.contains(&(function, num_args))
+}
+
+/// Check an assertion, returning why it failed
+pub(crate) fn check(function: &str, args: &[u16]) -> Result<(), String> {
+    let signed = |index: usize| args[index] as i16;
+    match function {
+        "Assert.equals" if args[0] != args[1] => {
+            Err(format!("expected {} but found {}", signed(0), signed(1)))
+        }
+        "Assert.notEquals" if args[0] == args[1] => {
+            Err(format!("expected anything but {}", signed(0)))
+        }
+        "Assert.isTrue" if args[0] == 0 => Err("expected true but found false".to_owned()),
+        "Assert.isFalse" if args[0] != 0 => Err(format!("expected false but found {}", signed(0))),
+        "Assert.fail" => Err("failed".to_owned()),
+        _ => Ok(()),
+    }
+}
@@ -0,0 +1,149 @@
+use std::collections::HashMap;
+use std::fmt;
+
+use serde::Deserialize;
+
+use crate::{Cpu, ErrorType, Stop};
+
+/// A point a program should reach within a number of cycles, for grading how fast it is
+#[derive(Debug, Clone, PartialEq)]
+pub struct Budget {
+    /// The checkpoint as it was given, for reports
+    pub name: String,
+    pub checkpoint: Checkpoint,
+    pub cycles: u64,
+    /// The number of cycles run before the checkpoint was first reached
+    pub reached: Option<u64>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Checkpoint {
+    /// The instruction at this ROM address is about to run
+    Address(u16),
+    /// The program has halted
+    Halt,
+}
+
+/// The labels of the .symbols.json file the assembler saves with --symbol-map
+#[derive(Debug, Default, Deserialize)]
+pub struct SymbolMap {
+    labels: HashMap<String, u16>,
+}
+
+impl SymbolMap {
+    pub fn parse(json: &str) -> serde_json::Result<SymbolMap> {
+        serde_json::from_str(json)
+    }
+}
+
+impl Budget {
+    /// A budget for `name`, which is a ROM address, `halt` or one of the labels in `symbol_map`
+    pub fn new(name: &str, cycles: u64, symbol_map: &SymbolMap) -> Result<Budget, ErrorType> {
+        let checkpoint = if name == "halt" {
+            Checkpoint::Halt
+        } else if let Ok(address) = name.parse::<u16>() {
+            Checkpoint::Address(address)
+        } else {
+            let address = symbol_map
+                .labels
+                .get(name)
+                .ok_or_else(|| ErrorType::UnknownCheckpoint(name.to_owned()))?;
+            Checkpoint::Address(*address)
+        };
+        Ok(Budget {
+            name: name.to_owned(),
+            checkpoint,
+            cycles,
+            reached: None,
+        })
+    }
+
+    pub fn is_met(&self) -> bool {
+        self.reached.is_some_and(|cycles| cycles <= self.cycles)
+    }
+}
+
+impl fmt::Display for Budget {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.reached {
+            Some(cycles) => {
+                let verdict = if self.is_met() { "within" } else { "over" };
+                write!(
+                    f,
+                    "{}: reached after {} cycles, {} the budget of {}",
+                    self.name, cycles, verdict, self.cycles
+                )
+            }
+            None => write!(
+                f,
+                "{}: not reached, with a budget of {} cycles",
+                self.name, self.cycles
+            ),
+        }
+    }
+}
+
+/// Run like [`Cpu::run`], noting when each budget's checkpoint is first reached
+pub fn run_with_budgets(
+    cpu: &mut Cpu,
+    max_cycles: u64,
+    budgets: &mut [Budget],
+) -> Result<Stop, ErrorType> {
+    for _ in 0..max_cycles {
+        for budget in budgets.iter_mut() {
+            if budget.reached.is_none() && budget.checkpoint == Checkpoint::Address(cpu.pc()) {
+                budget.reached = Some(cpu.cycles());
+            }
+        }
+        if let Some(stop) = cpu.step()? {
+            if stop == Stop::Halted {
+                for budget in budgets.iter_mut() {
+                    if budget.checkpoint == Checkpoint::Halt {
+                        budget.reached = Some(cpu.cycles());
+                    }
+                }
+            }
+            return Ok(stop);
+        }
+    }
+    Ok(Stop::CycleLimit)
+}
+
+#[test]
+fn test_budgets() {
+    // Count R0 down to zero, then halt
+    let program = crate::parse_hack(
+        "0000000000000000
+        1111110000010000
+        0000000000001000
+        1110001100000010
+        0000000000000000
+        1111110010001000
+        0000000000000000
+        1110101010000111
+        0000000000001000
+        1110101010000111",
+    )
+    .unwrap();
+    let symbol_map = SymbolMap::parse(r#"{"labels": {"END": 8}, "variables": {}}"#).unwrap();
+    let mut budgets = [
+        Budget::new("END", 100, &symbol_map).unwrap(),
+        Budget::new("halt", 20, &symbol_map).unwrap(),
+        Budget::new("4", 1000, &symbol_map).unwrap(),
+    ];
+    assert!(matches!(
+        Budget::new("LOOP", 10, &symbol_map),
+        Err(ErrorType::UnknownCheckpoint(name)) if name == "LOOP"
+    ));
+
+    let mut cpu = Cpu::new(program);
+    cpu.poke(0, 5);
+    assert_eq!(
+        run_with_budgets(&mut cpu, 1000, &mut budgets).unwrap(),
+        Stop::Halted
+    );
+    assert_eq!(budgets[0].reached, Some(44));
+    assert!(budgets[0].is_met());
+    assert!(!budgets[1].is_met());
+    assert_eq!(budgets[2].reached, Some(4));
+}
@@ -0,0 +1,589 @@
+use std::fs;
+use std::io::{self, BufWriter, Write};
+use std::path::{Path, PathBuf};
+
+use clap::builder::{PossibleValuesParser, TypedValueParser};
+use clap::{value_parser, Arg, ArgAction, ArgMatches, Command, ValueHint};
+use parse_utils::output::write_atomic;
+
+use crate::{
+    input_log, load_banks, load_vm, read_input_log, run_test_script, run_with_budgets,
+    run_with_trace, screen_to_pbm, Budget, Division, ErrorType, OsCompat, PixelBounds, Stop,
+    StringOverflow, SymbolMap, VmMachine,
+};
+
+/// The command line interface of the emulator, shared by the standalone binary and n2t
+pub fn command() -> Command {
+    Command::new("Hack Emulator")
+        .about("Run a Hack program, or VM code without translating it")
+        .arg(
+            Arg::new("INPUT")
+                .index(1)
+                .required(true)
+                .value_name("FILE")
+                .value_hint(ValueHint::FilePath)
+                .help("A .hack or .asm file, a .vm file or a directory of .vm files, or a .tst test script from the course to run"),
+        )
+        .arg(
+            Arg::new("bank")
+                .long("bank")
+                .value_name("FILE")
+                .action(ArgAction::Append)
+                .value_hint(ValueHint::FilePath)
+                .help("Load a .hack file into the next ROM bank, after the program in bank 0. The program switches banks by writing the BANK register (RAM[24577]) before a jump"),
+        )
+        .arg(
+            Arg::new("cycles")
+                .long("cycles")
+                .value_name("COUNT")
+                .value_parser(value_parser!(u64))
+                .default_value("10000000")
+                .help("Stop after this many instructions or VM commands if the program hasn't halted"),
+        )
+        .arg(
+            Arg::new("ram")
+                .long("ram")
+                .value_name("ADDRESS[-ADDRESS]")
+                .action(ArgAction::Append)
+                .value_parser(parse_range)
+                .help("Print a RAM address or an inclusive range of them once the program stops. Defaults to R0-R15"),
+        )
+        .arg(
+            Arg::new("set")
+                .long("set")
+                .value_name("ADDRESS=VALUE")
+                .action(ArgAction::Append)
+                .value_parser(parse_assignment)
+                .help("Store a value in RAM before the program starts"),
+        )
+        .arg(
+            Arg::new("key")
+                .long("key")
+                .value_name("CODE")
+                .value_parser(value_parser!(u16))
+                .help("Hold down the key with this code for the whole run"),
+        )
+        .arg(
+            Arg::new("record")
+                .long("record")
+                .value_name("FILE")
+                .value_hint(ValueHint::FilePath)
+                .help("Save the keys pressed during the run, and when, to replay them with --replay. Only for VM programs"),
+        )
+        .arg(
+            Arg::new("replay")
+                .long("replay")
+                .value_name("FILE")
+                .value_hint(ValueHint::FilePath)
+                .conflicts_with("key")
+                .help("Press the keys saved by --record at the same points of the run as before, so that it goes exactly the same way. Only for VM programs"),
+        )
+        .arg(
+            Arg::new("back")
+                .long("back")
+                .value_name("COUNT")
+                .value_parser(value_parser!(usize))
+                .help("Step back over the last COUNT VM commands once the program stops, to show the state which led to the end or an error"),
+        )
+        .arg(
+            Arg::new("with_os")
+                .long("with-os")
+                .action(ArgAction::SetTrue)
+                .required(false)
+                .help("Link in the built-in Jack OS classes which VM code doesn't provide itself"),
+        )
+        .arg(
+            Arg::new("os_divide")
+                .long("os-divide")
+                .value_name("ROUNDING")
+                .value_parser(PossibleValuesParser::new(Division::NAMES).map(|name| {
+                    name.parse::<Division>()
+                        .expect("Only possible values are parsed")
+                }))
+                .default_value("os")
+                .help("How Math.divide rounds in VM code: with the program's own OS code, toward zero like the official OS, or down"),
+        )
+        .arg(
+            Arg::new("os_string_overflow")
+                .long("os-string-overflow")
+                .value_name("BEHAVIOR")
+                .value_parser(PossibleValuesParser::new(StringOverflow::NAMES).map(|name| {
+                    name.parse::<StringOverflow>()
+                        .expect("Only possible values are parsed")
+                }))
+                .default_value("os")
+                .help("What String.appendChar does to a full string in VM code: whatever the program's own OS code does, drop the character, or stop with an error"),
+        )
+        .arg(
+            Arg::new("os_pixel_bounds")
+                .long("os-pixel-bounds")
+                .value_name("BEHAVIOR")
+                .value_parser(PossibleValuesParser::new(PixelBounds::NAMES).map(|name| {
+                    name.parse::<PixelBounds>()
+                        .expect("Only possible values are parsed")
+                }))
+                .default_value("os")
+                .help("What Screen.drawPixel does off the screen in VM code: whatever the program's own OS code does, draw nothing, wrap around, or stop with an error"),
+        )
+        .arg(
+            Arg::new("screen")
+                .long("screen")
+                .value_name("FILE")
+                .value_hint(ValueHint::FilePath)
+                .help("Save the screen as a PBM image once the program stops"),
+        )
+        .arg(
+            Arg::new("heatmap")
+                .long("heatmap")
+                .value_name("FILE")
+                .value_hint(ValueHint::FilePath)
+                .value_parser(parse_heatmap_path)
+                .help("Count the reads and writes of each RAM address and save them as a .csv table or a .ppm image with 256 addresses to a row. Only for .hack programs"),
+        )
+        .arg(
+            Arg::new("ram_size")
+                .long("ram-size")
+                .value_name("WORDS")
+                .value_parser(parse_ram_size)
+                .help("Give the CPU this many words of RAM instead of 32768, e.g. 65536 for variants which address RAM with all 16 bits of A. Only for .hack programs"),
+        )
+        .arg(
+            Arg::new("trap_unmapped")
+                .long("trap-unmapped")
+                .action(ArgAction::SetTrue)
+                .required(false)
+                .help("Stop with an error when the program reads or writes an address above the keyboard which isn't mapped to memory, instead of reading zeros. Only for .hack programs"),
+        )
+        .arg(
+            Arg::new("budget")
+                .long("budget")
+                .value_name("CHECKPOINT=CYCLES")
+                .action(ArgAction::Append)
+                .value_parser(parse_budget)
+                .help("Fail unless the program reaches CHECKPOINT within CYCLES cycles, for grading how fast it is. CHECKPOINT is a ROM address, a label from the symbol map, or halt. Only for .hack programs"),
+        )
+        .arg(
+            Arg::new("cpu_trace")
+                .long("cpu-trace")
+                .action(ArgAction::SetTrue)
+                .conflicts_with("budget")
+                .help("Print the PC, A, D and any RAM write of each cycle, e.g. to compare with a trace from the official CPUEmulator. Only for .hack and .asm programs"),
+        )
+        .arg(
+            Arg::new("trace_ram")
+                .long("trace-ram")
+                .value_name("ADDRESS[-ADDRESS]")
+                .action(ArgAction::Append)
+                .value_parser(parse_range)
+                .requires("cpu_trace")
+                .help("Only trace the cycles which write to this RAM address or inclusive range of them"),
+        )
+        .arg(
+            Arg::new("symbol_map")
+                .long("symbol-map")
+                .value_name("FILE")
+                .value_hint(ValueHint::FilePath)
+                .help("The .symbols.json file saved by the assembler's --symbol-map to look up --budget labels in. Defaults to the one next to the program"),
+        )
+        .arg(
+            Arg::new("export_state")
+                .long("export-state")
+                .value_name("FILE")
+                .value_hint(ValueHint::FilePath)
+                .help("Keep a JSON snapshot of the segments, heap blocks and screen in this file while the program runs, for frontends which visualize it. It's replaced every --export-every commands and once the program stops. Only for VM programs"),
+        )
+        .arg(
+            Arg::new("export_every")
+                .long("export-every")
+                .value_name("COMMANDS")
+                .value_parser(value_parser!(u64).range(1..))
+                .default_value("100000")
+                .help("How many commands to run between the snapshots written by --export-state"),
+        )
+}
+
+pub fn run(matches: &ArgMatches) -> Result<(), ErrorType> {
+    let path = Path::new(
+        matches
+            .get_one::<String>("INPUT")
+            .expect("User to provide an input path"),
+    );
+    let max_cycles = *matches
+        .get_one::<u64>("cycles")
+        .expect("cycles has a default");
+    let assignments: Vec<(u16, u16)> = matches
+        .get_many::<(u16, u16)>("set")
+        .into_iter()
+        .flatten()
+        .copied()
+        .collect();
+    let key = matches.get_one::<u16>("key").copied();
+
+    if path.extension().is_some_and(|extension| extension == "tst") {
+        let compared = run_test_script(path, matches.get_flag("with_os"))?;
+        if compared > 0 {
+            println!("End of script - Comparison ended successfully");
+        } else {
+            println!("End of script");
+        }
+        return Ok(());
+    }
+    if path.is_dir() || path.extension().is_some_and(|extension| extension == "vm") {
+        if matches.contains_id("heatmap") {
+            return Err(ErrorType::HeatmapNeedsHackProgram);
+        }
+        if matches.contains_id("bank") {
+            return Err(ErrorType::BanksNeedHackProgram);
+        }
+        if matches.contains_id("ram_size") || matches.get_flag("trap_unmapped") {
+            return Err(ErrorType::RamOptionsNeedHackProgram);
+        }
+        if matches.contains_id("budget") {
+            return Err(ErrorType::BudgetNeedsHackProgram);
+        }
+        if matches.get_flag("cpu_trace") {
+            return Err(ErrorType::TraceNeedsHackProgram);
+        }
+        check_addresses(matches, &assignments, crate::MEMORY_SIZE)?;
+        let mut vm = load_vm(path, matches.get_flag("with_os"))?;
+        vm.set_os_compat(OsCompat {
+            division: *matches
+                .get_one::<Division>("os_divide")
+                .expect("os-divide has a default"),
+            string_overflow: *matches
+                .get_one::<StringOverflow>("os_string_overflow")
+                .expect("os-string-overflow has a default"),
+            pixel_bounds: *matches
+                .get_one::<PixelBounds>("os_pixel_bounds")
+                .expect("os-pixel-bounds has a default"),
+        });
+        for (address, value) in assignments {
+            vm.poke(address, value);
+        }
+        let record = matches.get_one::<String>("record").map(Path::new);
+        if record.is_some() {
+            vm.record_input();
+        }
+        if let Some(key) = key {
+            vm.set_keyboard(key);
+        }
+        if let Some(replay) = matches.get_one::<String>("replay") {
+            vm.replay_input(read_input_log(Path::new(replay))?);
+        }
+
+        let back = matches.get_one::<usize>("back").copied().unwrap_or(0);
+        vm.set_history_limit(back);
+
+        // An error is reported after the state, which is what explains it
+        let export = matches.get_one::<String>("export_state").map(Path::new);
+        let every = match export {
+            Some(_) => *matches
+                .get_one::<u64>("export_every")
+                .expect("export-every has a default"),
+            None => max_cycles,
+        };
+        let mut remaining = max_cycles;
+        let result = loop {
+            let chunk = remaining.min(every);
+            remaining -= chunk;
+            let result = vm.run(chunk);
+            match (&result, export) {
+                (Ok(Stop::CycleLimit), Some(export)) if remaining > 0 => export_state(&vm, export)?,
+                _ => break result,
+            }
+        };
+        match result {
+            Ok(stop) => report_stop(stop, vm.cycles(), "commands"),
+            Err(_) => println!("Stopped by an error after {} commands", vm.cycles()),
+        }
+        if let Some(record) = record {
+            let log = input_log(vm.recorded_input());
+            write_atomic(record, log.as_bytes()).map_err(|source| ErrorType::WriteError {
+                path: record.to_owned(),
+                source,
+            })?;
+        }
+        if back > 0 {
+            let mut stepped = 0;
+            while stepped < back && vm.step_back() {
+                stepped += 1;
+            }
+            println!("Stepped back {} commands", stepped);
+        }
+        if let Some(function) = vm.current_function() {
+            println!("In {}", function);
+        }
+        if let Some(command) = vm.current_command() {
+            println!("Next: {}", command);
+        }
+        let stack: Vec<String> = vm
+            .stack()
+            .iter()
+            .map(|value| (*value as i16).to_string())
+            .collect();
+        println!("Stack: [{}]", stack.join(", "));
+        print_ram(matches, |address| vm.peek(address));
+        save_screen(matches, vm.screen())?;
+        if let Some(export) = export {
+            export_state(&vm, export)?;
+        }
+        result.map(|_| ())
+    } else {
+        if matches.contains_id("export_state") {
+            return Err(ErrorType::ExportStateNeedsVmProgram);
+        }
+        if matches.contains_id("record") || matches.contains_id("replay") {
+            return Err(ErrorType::InputLogNeedsVmProgram);
+        }
+        let mut banks = vec![path];
+        banks.extend(
+            matches
+                .get_many::<String>("bank")
+                .into_iter()
+                .flatten()
+                .map(Path::new),
+        );
+        let mut cpu = load_banks(&banks)?;
+        if let Some(&words) = matches.get_one::<usize>("ram_size") {
+            cpu.set_ram_size(words);
+        }
+        if matches.get_flag("trap_unmapped") {
+            cpu.trap_unmapped();
+        }
+        check_addresses(matches, &assignments, cpu.ram_size())?;
+        for (address, value) in assignments {
+            cpu.poke(address, value);
+        }
+        if let Some(key) = key {
+            cpu.set_keyboard(key);
+        }
+
+        let heatmap = matches.get_one::<String>("heatmap").map(Path::new);
+        if heatmap.is_some() {
+            cpu.track_access();
+        }
+
+        let mut budgets = load_budgets(matches, path)?;
+
+        // As with VM code, an error is reported after the state
+        let result = if matches.get_flag("cpu_trace") {
+            let ranges: Vec<(u16, u16)> = matches
+                .get_many::<(u16, u16)>("trace_ram")
+                .into_iter()
+                .flatten()
+                .copied()
+                .collect();
+            let mut out = BufWriter::new(io::stdout().lock());
+            let result = run_with_trace(&mut cpu, max_cycles, &ranges, &mut out);
+            out.flush().map_err(ErrorType::TraceOutput)?;
+            result
+        } else if budgets.is_empty() {
+            cpu.run(max_cycles)
+        } else {
+            run_with_budgets(&mut cpu, max_cycles, &mut budgets)
+        };
+        match result {
+            Ok(stop) => report_stop(stop, cpu.cycles(), "cycles"),
+            Err(_) => println!("Stopped by an error after {} cycles", cpu.cycles()),
+        }
+        if banks.len() > 1 {
+            println!(
+                "A={} D={} PC={} BANK={}",
+                cpu.a(),
+                cpu.d(),
+                cpu.pc(),
+                cpu.bank()
+            );
+        } else {
+            println!("A={} D={} PC={}", cpu.a(), cpu.d(), cpu.pc());
+        }
+        print_ram(matches, |address| cpu.peek(address));
+        save_screen(matches, cpu.screen())?;
+        if let (Some(heatmap), Some(access)) = (heatmap, cpu.access()) {
+            let contents = if heatmap
+                .extension()
+                .is_some_and(|extension| extension == "csv")
+            {
+                access.to_csv().into_bytes()
+            } else {
+                access.to_ppm()
+            };
+            write_atomic(heatmap, &contents).map_err(|source| ErrorType::WriteError {
+                path: heatmap.to_owned(),
+                source,
+            })?;
+        }
+        for budget in &budgets {
+            println!("Budget {}", budget);
+        }
+        result?;
+        let exceeded = budgets.iter().filter(|budget| !budget.is_met()).count();
+        if exceeded > 0 {
+            return Err(ErrorType::BudgetsExceeded(exceeded));
+        }
+        Ok(())
+    }
+}
+
+/// The --budget checkpoints, with labels looked up in the symbol map
+fn load_budgets(matches: &ArgMatches, program: &Path) -> Result<Vec<Budget>, ErrorType> {
+    let Some(budgets) = matches.get_many::<(String, u64)>("budget") else {
+        return Ok(Vec::new());
+    };
+    let symbol_map_path = match matches.get_one::<String>("symbol_map") {
+        Some(path) => Some(PathBuf::from(path)),
+        None => Some(program.with_extension("symbols.json")).filter(|path| path.is_file()),
+    };
+    let symbol_map = match symbol_map_path {
+        Some(path) => {
+            let contents = fs::read_to_string(&path).map_err(|source| ErrorType::ReadError {
+                path: path.clone(),
+                source,
+            })?;
+            SymbolMap::parse(&contents)
+                .map_err(|source| ErrorType::SymbolMapError { path, source })?
+        }
+        None => SymbolMap::default(),
+    };
+    budgets
+        .map(|(checkpoint, cycles)| Budget::new(checkpoint, *cycles, &symbol_map))
+        .collect()
+}
+
+fn report_stop(stop: Stop, count: u64, unit: &str) {
+    match stop {
+        Stop::Halted => println!("Halted after {} {}", count, unit),
+        Stop::EndOfProgram => println!("Ran off the end of the program after {} {}", count, unit),
+        Stop::CycleLimit => println!("Stopped at the limit of {} {}", count, unit),
+    }
+}
+
+/// Check the addresses given to --ram and --set fit in a RAM of `size` words
+fn check_addresses(
+    matches: &ArgMatches,
+    assignments: &[(u16, u16)],
+    size: usize,
+) -> Result<(), ErrorType> {
+    let ranges = matches.get_many::<(u16, u16)>("ram").into_iter().flatten();
+    let highest = ranges
+        .map(|(_, end)| *end)
+        .chain(assignments.iter().map(|(address, _)| *address))
+        .max();
+    match highest {
+        Some(address) if address as usize >= size => {
+            Err(ErrorType::AddressOutsideRam { address, size })
+        }
+        _ => Ok(()),
+    }
+}
+
+fn print_ram(matches: &ArgMatches, peek: impl Fn(u16) -> u16) {
+    let ranges: Vec<(u16, u16)> = match matches.get_many::<(u16, u16)>("ram") {
+        Some(ranges) => ranges.copied().collect(),
+        None => vec![(0, 15)],
+    };
+    for (start, end) in ranges {
+        for address in start..=end {
+            println!("RAM[{}] = {}", address, peek(address) as i16);
+        }
+    }
+}
+
+fn save_screen(matches: &ArgMatches, screen: &[u16]) -> Result<(), ErrorType> {
+    if let Some(screen_path) = matches.get_one::<String>("screen") {
+        let screen_path = Path::new(screen_path);
+        write_atomic(screen_path, &screen_to_pbm(screen)).map_err(|source| {
+            ErrorType::WriteError {
+                path: screen_path.to_owned(),
+                source,
+            }
+        })?;
+    }
+    Ok(())
+}
+
+fn export_state(vm: &VmMachine, path: &Path) -> Result<(), ErrorType> {
+    let json = serde_json::to_vec(&vm.state()).expect("A VM state always serializes");
+    write_atomic(path, &json).map_err(|source| ErrorType::WriteError {
+        path: path.to_owned(),
+        source,
+    })
+}
+
+fn parse_budget(text: &str) -> Result<(String, u64), String> {
+    let (checkpoint, cycles) = text
+        .rsplit_once('=')
+        .ok_or_else(|| format!("expected CHECKPOINT=CYCLES but found {}", text))?;
+    let cycles = cycles
+        .trim()
+        .parse::<u64>()
+        .map_err(|_| format!("{} is not a number of cycles", cycles))?;
+    Ok((checkpoint.trim().to_owned(), cycles))
+}
+
+fn parse_heatmap_path(text: &str) -> Result<String, String> {
+    match Path::new(text).extension() {
+        Some(extension) if extension == "csv" || extension == "ppm" => Ok(text.to_owned()),
+        _ => Err(format!("{} should end in .csv or .ppm", text)),
+    }
+}
+
+/// Addresses are checked against the size of the RAM once it's known
+fn parse_address(text: &str) -> Result<u16, String> {
+    text.trim()
+        .parse::<u16>()
+        .map_err(|_| format!("{} is not a RAM address", text))
+}
+
+fn parse_ram_size(text: &str) -> Result<usize, String> {
+    match text.trim().parse::<usize>() {
+        Ok(words) if (crate::BANK as usize..=crate::MAX_RAM_SIZE).contains(&words) => Ok(words),
+        _ => Err(format!(
+            "{} is not a RAM size between {} and {} words",
+            text,
+            crate::BANK,
+            crate::MAX_RAM_SIZE
+        )),
+    }
+}
+
+fn parse_range(text: &str) -> Result<(u16, u16), String> {
+    match text.split_once('-') {
+        Some((start, end)) => {
+            let (start, end) = (parse_address(start)?, parse_address(end)?);
+            if start > end {
+                return Err(format!("{} is an empty range", text));
+            }
+            Ok((start, end))
+        }
+        None => parse_address(text).map(|address| (address, address)),
+    }
+}
+
+fn parse_assignment(text: &str) -> Result<(u16, u16), String> {
+    let (address, value) = text
+        .split_once('=')
+        .ok_or_else(|| format!("expected ADDRESS=VALUE but found {}", text))?;
+    let value = value
+        .trim()
+        .parse::<i16>()
+        .map(|value| value as u16)
+        .or_else(|_| value.trim().parse::<u16>())
+        .map_err(|_| format!("{} is not a 16-bit value", value))?;
+    Ok((parse_address(address)?, value))
+}
+
+#[test]
+fn test_parse_arguments() {
+    assert_eq!(parse_range("256"), Ok((256, 256)));
+    assert_eq!(parse_range("0-15"), Ok((0, 15)));
+    assert!(parse_range("15-0").is_err());
+    assert_eq!(parse_range("40000"), Ok((40000, 40000)));
+    assert!(parse_range("70000").is_err());
+    assert_eq!(parse_ram_size("65536"), Ok(65536));
+    assert!(parse_ram_size("1024").is_err());
+    assert_eq!(parse_assignment("0=-1"), Ok((0, 0xFFFF)));
+    assert_eq!(parse_assignment("3=40000"), Ok((3, 40000)));
+    assert_eq!(parse_budget("LOOP=500"), Ok(("LOOP".to_owned(), 500)));
+    assert!(parse_budget("halt").is_err());
+}
@@ -0,0 +1,516 @@
+use crate::heatmap::MemoryAccess;
+use crate::peripheral::Peripheral;
+use crate::ErrorType;
+
+/// The first word of the screen memory map
+pub const SCREEN: u16 = 0x4000;
+/// The number of words in the screen memory map: 256 rows of 32 words
+pub const SCREEN_WORDS: usize = 8192;
+/// The keyboard register, which holds the code of the key currently pressed
+pub const KEYBOARD: u16 = 0x6000;
+/// The bank-switch register of a computer with more than one ROM bank. The bank written to it is
+/// switched to by the next jump, so a program can set it up and then jump anywhere in that bank.
+pub const BANK: u16 = 0x6001;
+/// The Hack ROM and the addressable RAM are both 32K words
+pub const MEMORY_SIZE: usize = 0x8000;
+/// The largest RAM a 16-bit A register can address, used by some research variants of the platform
+pub const MAX_RAM_SIZE: usize = 0x10000;
+
+/// Why a run stopped
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Stop {
+    /// The program reached a loop which can never exit, the usual way Hack programs end
+    Halted,
+    /// The PC moved past the last instruction of the program
+    EndOfProgram,
+    /// The cycle limit was reached
+    CycleLimit,
+}
+
+/// The Hack computer: the CPU with its A, D and PC registers, the ROM holding the program, and the
+/// RAM with the screen and keyboard mapped into it
+pub struct Cpu {
+    a: u16,
+    d: u16,
+    pc: u16,
+    /// Extended variants of the platform switch between several ROMs of 32K
+    banks: Vec<Vec<u16>>,
+    bank: usize,
+    ram: Vec<u16>,
+    cycles: u64,
+    access: Option<MemoryAccess>,
+    trap_unmapped: bool,
+    /// The address and value written by the last instruction, if it wrote to memory
+    last_write: Option<(u16, u16)>,
+    peripherals: Vec<Box<dyn Peripheral>>,
+}
+
+impl Cpu {
+    /// Load a program into ROM. The program must fit in the 32K of ROM.
+    pub fn new(rom: Vec<u16>) -> Self {
+        Self::with_banks(vec![rom])
+    }
+
+    /// Load a program which spans several ROM banks, starting in the first. With more than one
+    /// bank the program can write the `BANK` register.
+    pub fn with_banks(banks: Vec<Vec<u16>>) -> Self {
+        assert!(
+            banks.iter().all(|rom| rom.len() <= MEMORY_SIZE),
+            "program is larger than the ROM"
+        );
+        Self {
+            a: 0,
+            d: 0,
+            pc: 0,
+            banks,
+            bank: 0,
+            ram: vec![0; MEMORY_SIZE],
+            cycles: 0,
+            access: None,
+            trap_unmapped: false,
+            last_write: None,
+            peripherals: Vec::new(),
+        }
+    }
+
+    /// Change the number of words of RAM, clearing it. RAM of more than 32K is addressed with all
+    /// 16 bits of A and is ordinary memory above the 32K of the standard memory map.
+    pub fn set_ram_size(&mut self, words: usize) {
+        assert!(
+            (BANK as usize..=MAX_RAM_SIZE).contains(&words),
+            "RAM must hold the memory map and fit a 16-bit address"
+        );
+        self.ram = vec![0; words];
+    }
+
+    /// The number of words of RAM
+    pub fn ram_size(&self) -> usize {
+        self.ram.len()
+    }
+
+    /// Stop with an error when the program reads or writes an address which isn't mapped to
+    /// anything, rather than reading zeros and ignoring writes like the course's tools
+    pub fn trap_unmapped(&mut self) {
+        self.trap_unmapped = true;
+    }
+
+    pub fn a(&self) -> u16 {
+        self.a
+    }
+
+    pub fn d(&self) -> u16 {
+        self.d
+    }
+
+    pub fn pc(&self) -> u16 {
+        self.pc
+    }
+
+    /// The ROM bank the PC points into
+    pub fn bank(&self) -> usize {
+        self.bank
+    }
+
+    /// The number of instructions executed so far
+    pub fn cycles(&self) -> u64 {
+        self.cycles
+    }
+
+    /// The address and value the last instruction wrote to memory, if it did
+    pub fn last_write(&self) -> Option<(u16, u16)> {
+        self.last_write
+    }
+
+    /// Read RAM. Addresses past the end of a RAM of less than 64K read as 0 rather than mirroring
+    /// the start of it. Peripherals aren't read, as reading them may change them.
+    pub fn peek(&self, address: u16) -> u16 {
+        self.ram.get(address as usize).copied().unwrap_or(0)
+    }
+
+    pub(crate) fn ram(&self) -> &[u16] {
+        &self.ram
+    }
+
+    /// Carry on from another instruction, e.g. to run a single function of a program
+    pub(crate) fn jump(&mut self, pc: u16) {
+        self.pc = pc;
+    }
+
+    pub(crate) fn set_a(&mut self, value: u16) {
+        self.a = value;
+    }
+
+    pub(crate) fn set_d(&mut self, value: u16) {
+        self.d = value;
+    }
+
+    /// Write to RAM from outside the program, e.g. to set up a test. Unlike the program, this can
+    /// write the keyboard register. Writes past the end of RAM are ignored.
+    pub fn poke(&mut self, address: u16, value: u16) {
+        if let Some(word) = self.ram.get_mut(address as usize) {
+            *word = value;
+        }
+    }
+
+    /// Map a device into RAM. Its addresses are taken from RAM, the memory map or earlier devices,
+    /// so the program's reads and writes of them go to the device instead.
+    pub fn add_peripheral(&mut self, peripheral: Box<dyn Peripheral>) {
+        self.peripherals.insert(0, peripheral);
+    }
+
+    /// Count the program's reads and writes of each RAM address from now on
+    pub fn track_access(&mut self) {
+        self.access.get_or_insert_with(MemoryAccess::default);
+    }
+
+    /// The accesses counted since `track_access` was called
+    pub fn access(&self) -> Option<&MemoryAccess> {
+        self.access.as_ref()
+    }
+
+    /// Press a key, or release every key with 0
+    pub fn set_keyboard(&mut self, key: u16) {
+        self.poke(KEYBOARD, key);
+    }
+
+    /// The screen memory map, 32 words per row with the least significant bit of each word the
+    /// leftmost pixel
+    pub fn screen(&self) -> &[u16] {
+        &self.ram[SCREEN as usize..SCREEN as usize + SCREEN_WORDS]
+    }
+
+    /// Execute a single instruction. Returns a reason to stop if the program has ended, or an
+    /// error if it touched unmapped memory while `trap_unmapped` is on.
+    pub fn step(&mut self) -> Result<Option<Stop>, ErrorType> {
+        let rom = self.banks.get(self.bank);
+        let Some(&instruction) = rom.and_then(|rom| rom.get(self.pc as usize)) else {
+            return Ok(Some(Stop::EndOfProgram));
+        };
+        self.cycles += 1;
+        self.last_write = None;
+        for peripheral in &mut self.peripherals {
+            peripheral.tick();
+        }
+
+        if instruction & 0x8000 == 0 {
+            self.a = instruction;
+            self.pc = self.pc.wrapping_add(1);
+            return Ok(None);
+        }
+
+        // Every part of the instruction works on the registers as they were before it
+        let address = if self.ram.len() > MEMORY_SIZE {
+            self.a
+        } else {
+            self.a & 0x7FFF
+        };
+        let touches_memory = instruction & 0x1008 != 0;
+        if self.trap_unmapped && touches_memory && !self.is_mapped(address) {
+            return Err(ErrorType::UnmappedAccess {
+                address,
+                pc: self.pc,
+            });
+        }
+        let y = if instruction & 0x1000 != 0 {
+            if let Some(access) = &mut self.access {
+                access.record_read(address);
+            }
+            match self.peripheral(address) {
+                Some(peripheral) => peripheral.read(address),
+                None => self.peek(address),
+            }
+        } else {
+            self.a
+        };
+        let result = alu(self.d, y, (instruction >> 6) as u8 & 0x3F);
+
+        if instruction & 0x0008 != 0 {
+            self.write(address, result);
+        }
+        if instruction & 0x0010 != 0 {
+            self.d = result;
+        }
+        let jump_target = self.a;
+        if instruction & 0x0020 != 0 {
+            self.a = result;
+        }
+
+        let negative = result & 0x8000 != 0;
+        let jump = match instruction & 0x7 {
+            0b000 => false,
+            0b001 => !negative && result != 0,
+            0b010 => result == 0,
+            0b011 => !negative,
+            0b100 => negative,
+            0b101 => result != 0,
+            0b110 => negative || result == 0,
+            _ => true,
+        };
+
+        if !jump {
+            self.pc = self.pc.wrapping_add(1);
+        } else if self.is_halt_loop(instruction, jump_target) {
+            return Ok(Some(Stop::Halted));
+        } else {
+            self.pc = jump_target & 0x7FFF;
+            if self.banks.len() > 1 {
+                self.bank = self.peek(BANK) as usize;
+            }
+        }
+        Ok(None)
+    }
+
+    /// Run until the program ends or `max_cycles` more instructions have been executed
+    pub fn run(&mut self, max_cycles: u64) -> Result<Stop, ErrorType> {
+        for _ in 0..max_cycles {
+            if let Some(stop) = self.step()? {
+                return Ok(stop);
+            }
+        }
+        Ok(Stop::CycleLimit)
+    }
+
+    /// Whether a jump goes back to a point from which only A-instructions lead to it, e.g.
+    /// `(END) @END 0;JMP`. Nothing can change in such a loop so the program has finished.
+    fn is_halt_loop(&self, instruction: u16, target: u16) -> bool {
+        let unconditional = instruction & 0x7 == 0x7 && instruction & 0x0038 == 0;
+        let same_bank = self.banks.len() == 1 || self.peek(BANK) as usize == self.bank;
+        let (target, pc) = (target as usize, self.pc as usize);
+        unconditional
+            && same_bank
+            && target <= pc
+            && self.banks[self.bank][target..pc]
+                .iter()
+                .all(|instruction| instruction & 0x8000 == 0)
+    }
+
+    /// The device mapped to an address, if there is one
+    fn peripheral(&mut self, address: u16) -> Option<&mut Box<dyn Peripheral>> {
+        self.peripherals
+            .iter_mut()
+            .find(|peripheral| peripheral.addresses().contains(&address))
+    }
+
+    /// Whether an address is RAM, the screen, the keyboard, the bank register when there are banks
+    /// to switch between, RAM above the standard memory map, or a peripheral. Addresses past the
+    /// end of the RAM read as 0 and ignore writes.
+    fn is_mapped(&self, address: u16) -> bool {
+        let peripheral = self
+            .peripherals
+            .iter()
+            .any(|peripheral| peripheral.addresses().contains(&address));
+        let address = address as usize;
+        peripheral
+            || address <= KEYBOARD as usize
+            || (address == BANK as usize && self.banks.len() > 1)
+            || (MEMORY_SIZE..self.ram.len()).contains(&address)
+    }
+
+    /// The keyboard register and everything above it is read only to the program, apart from the
+    /// bank register and any RAM above the standard memory map
+    fn write(&mut self, address: u16, value: u16) {
+        self.last_write = Some((address, value));
+        if let Some(access) = &mut self.access {
+            access.record_write(address);
+        }
+        if let Some(peripheral) = self.peripheral(address) {
+            peripheral.write(address, value);
+        } else if address != KEYBOARD && self.is_mapped(address) {
+            self.ram[address as usize] = value;
+        }
+    }
+}
+
+/// The Hack ALU. `control` holds the zx, nx, zy, ny, f and no bits, most significant first.
+fn alu(x: u16, y: u16, control: u8) -> u16 {
+    let x = if control & 0b100000 != 0 { 0 } else { x };
+    let x = if control & 0b010000 != 0 { !x } else { x };
+    let y = if control & 0b001000 != 0 { 0 } else { y };
+    let y = if control & 0b000100 != 0 { !y } else { y };
+    let out = if control & 0b000010 != 0 {
+        x.wrapping_add(y)
+    } else {
+        x & y
+    };
+    if control & 0b000001 != 0 {
+        !out
+    } else {
+        out
+    }
+}
+
+#[cfg(test)]
+fn assemble(source: &str) -> Cpu {
+    let hack = assembler::assemble_string(source).unwrap();
+    Cpu::new(crate::parse_hack(&hack).unwrap())
+}
+
+#[test]
+fn test_add_two_numbers() {
+    let mut cpu = assemble(
+        "@R0
+        D=M
+        @R1
+        D=D+M
+        @R2
+        M=D
+        (END)
+        @END
+        0;JMP",
+    );
+    cpu.poke(0, 7);
+    cpu.poke(1, 0xFFFE);
+
+    assert_eq!(cpu.run(100).unwrap(), Stop::Halted);
+    assert_eq!(cpu.peek(2), 5);
+    assert_eq!(cpu.cycles(), 8);
+}
+
+#[test]
+fn test_jumps_use_the_a_register_from_before_the_instruction() {
+    // Multiply R0 by R1 with repeated addition. Stores into M and jumps both use the old A.
+    let mut cpu = assemble(
+        "@R2
+        M=0
+        (LOOP)
+        @R1
+        MD=M-1
+        @END
+        D;JLT
+        @R0
+        D=M
+        @R2
+        M=D+M
+        @LOOP
+        0;JMP
+        (END)
+        @END
+        0;JMP",
+    );
+    cpu.poke(0, 6);
+    cpu.poke(1, 7);
+
+    assert_eq!(cpu.run(1000).unwrap(), Stop::Halted);
+    assert_eq!(cpu.peek(2), 42);
+}
+
+#[test]
+fn test_memory_map() {
+    let mut cpu = assemble(
+        "@KBD
+        D=M
+        @SCREEN
+        M=D
+        @KBD
+        M=0
+        @5",
+    );
+    cpu.set_keyboard(65);
+
+    assert_eq!(cpu.run(100).unwrap(), Stop::EndOfProgram);
+    assert_eq!(cpu.screen()[0], 65);
+    assert_eq!(cpu.peek(KEYBOARD), 65);
+    assert_eq!(cpu.a(), 5);
+}
+
+#[test]
+fn test_bank_switching() {
+    // Bank 0 calls into bank 1, which stores to R0 and switches back to the halt loop
+    let bank0 = assembler::assemble_string(
+        "@1
+        D=A
+        @24577
+        M=D
+        @0
+        0;JMP
+        (END)
+        @END
+        0;JMP",
+    )
+    .unwrap();
+    let bank1 = assembler::assemble_string(
+        "@42
+        D=A
+        @R0
+        M=D
+        @24577
+        M=0
+        @6
+        0;JMP",
+    )
+    .unwrap();
+    let mut cpu = Cpu::with_banks(vec![
+        crate::parse_hack(&bank0).unwrap(),
+        crate::parse_hack(&bank1).unwrap(),
+    ]);
+
+    assert_eq!(cpu.run(100).unwrap(), Stop::Halted);
+    assert_eq!(cpu.peek(0), 42);
+    assert_eq!(cpu.bank(), 0);
+    assert_eq!(cpu.pc(), 7);
+
+    // A single ROM has no bank register
+    let mut cpu = assemble("@24577\nM=1\n@0\n0;JMP");
+    cpu.run(4).unwrap();
+    assert_eq!((cpu.peek(BANK), cpu.bank()), (0, 0));
+}
+
+#[test]
+fn test_alu() {
+    // D-1, A|D (as D|A), -A and !D
+    assert_eq!(alu(5, 3, 0b001110), 4);
+    assert_eq!(alu(0b1100, 0b1010, 0b010101), 0b1110);
+    assert_eq!(alu(5, 3, 0b110011), 0xFFFD);
+    assert_eq!(alu(5, 3, 0b001101), !5);
+}
+
+#[test]
+fn test_ram_size_and_unmapped_traps() {
+    // With 64K of RAM, A is a full 16-bit address
+    let mut cpu = assemble("@7\nD=A\n@0\nA=!A\nM=D");
+    cpu.set_ram_size(MAX_RAM_SIZE);
+    assert_eq!(cpu.run(100).unwrap(), Stop::EndOfProgram);
+    assert_eq!(cpu.peek(0x7FFF), 0);
+    assert_eq!(cpu.peek(0xFFFF), 7);
+
+    // Reads above the keyboard give zeros unless they're trapped
+    let program = "@24578\nD=M\n@R0\nM=D";
+    let mut cpu = assemble(program);
+    assert_eq!(cpu.run(100).unwrap(), Stop::EndOfProgram);
+
+    let mut cpu = assemble(program);
+    cpu.trap_unmapped();
+    assert!(matches!(
+        cpu.run(100),
+        Err(ErrorType::UnmappedAccess {
+            address: 24578,
+            pc: 1
+        })
+    ));
+
+    // RAM between 32K and 64K doesn't mirror its start past its end
+    // 50000 is too large for an A-instruction, so it's loaded as !15535
+    let program = "@10000\nM=-1\n@15535\nA=!A\nD=M\n@R0\nM=D";
+    let mut cpu = assemble(program);
+    cpu.set_ram_size(40000);
+    assert_eq!(cpu.run(100).unwrap(), Stop::EndOfProgram);
+    assert_eq!(cpu.peek(10000), 0xFFFF);
+    assert_eq!(cpu.peek(50000), 0);
+    assert_eq!(cpu.peek(0), 0);
+
+    let mut cpu = assemble(program);
+    cpu.set_ram_size(40000);
+    cpu.trap_unmapped();
+    assert!(matches!(
+        cpu.run(100),
+        Err(ErrorType::UnmappedAccess {
+            address: 50000,
+            pc: 4
+        })
+    ));
+
+    // The bank register is only mapped with banks, and loading A alone never traps
+    let mut cpu = assemble("@24577\nD=A\n@KBD\nD=M");
+    cpu.trap_unmapped();
+    assert_eq!(cpu.run(100).unwrap(), Stop::EndOfProgram);
+}
@@ -0,0 +1,79 @@
+use std::io::{self, BufRead, Write};
+use std::path::Path;
+
+use clap::{Arg, ArgAction, ArgMatches, Command, ValueHint};
+
+use crate::debugger::{Debugger, HELP};
+use crate::{load_vm, read_input_log, ErrorType};
+
+/// The command line interface of the VM debugger, shared by vm-debug and n2t
+pub fn command() -> Command {
+    Command::new("vm-debug")
+        .about("Step through VM code, with breakpoints on functions and the segments to inspect")
+        .arg(
+            Arg::new("INPUT")
+                .index(1)
+                .required(true)
+                .value_name("FILE")
+                .value_hint(ValueHint::AnyPath)
+                .help("A .vm file or a directory of .vm files"),
+        )
+        .arg(
+            Arg::new("with_os")
+                .long("with-os")
+                .action(ArgAction::SetTrue)
+                .required(false)
+                .help("Link in the built-in Jack OS classes which VM code doesn't provide itself"),
+        )
+        .arg(
+            Arg::new("break")
+                .long("break")
+                .short('b')
+                .value_name("FUNCTION")
+                .action(ArgAction::Append)
+                .help("Stop whenever this function is called"),
+        )
+        .arg(
+            Arg::new("replay")
+                .long("replay")
+                .value_name("FILE")
+                .value_hint(ValueHint::FilePath)
+                .help("Press the keys saved by the emulator's --record at the same points as before, to debug that run again"),
+        )
+}
+
+pub fn run(matches: &ArgMatches) -> Result<(), ErrorType> {
+    let path = Path::new(
+        matches
+            .get_one::<String>("INPUT")
+            .expect("User to provide an input path"),
+    );
+    let mut vm = load_vm(path, matches.get_flag("with_os"))?;
+    if let Some(replay) = matches.get_one::<String>("replay") {
+        vm.replay_input(read_input_log(Path::new(replay))?);
+    }
+    let mut debugger = Debugger::new(vm);
+    for function in matches.get_many::<String>("break").into_iter().flatten() {
+        debugger.add_breakpoint(function)?;
+    }
+
+    println!("{}\n", HELP);
+    println!("{}", debugger.location());
+    let mut last = String::new();
+    let mut lines = io::stdin().lock().lines();
+    loop {
+        print!("(vm-debug) ");
+        io::stdout().flush().ok();
+        let Some(Ok(line)) = lines.next() else {
+            return Ok(());
+        };
+        if !line.trim().is_empty() {
+            last = line;
+        }
+        match debugger.execute(&last) {
+            Some(reply) if reply.is_empty() => {}
+            Some(reply) => println!("{}", reply),
+            None => return Ok(()),
+        }
+    }
+}
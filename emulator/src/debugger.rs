@@ -0,0 +1,238 @@
+use std::collections::BTreeSet;
+
+use crate::{ErrorType, Stop, VmMachine};
+
+pub const HELP: &str = "Commands:
+  break FUNCTION     stop whenever FUNCTION is called, or list the breakpoints
+  delete FUNCTION    remove a breakpoint
+  step               run the next command, into calls
+  next               run the next command, over calls
+  finish             run until the current function returns
+  continue           run until a breakpoint or the end of the program
+  back [N]           undo the last N commands, or the last one
+  print SEGMENT [I]  show a segment, or one entry of it: stack, local, argument, this, that,
+                     static, temp or pointer. this and that need an index
+  where              show the functions being executed, innermost first
+  quit
+Commands can be shortened to their first letter, with b N going back and b FUNCTION setting a
+breakpoint, and an empty line repeats the last one";
+
+/// How many commands `back` can undo
+const HISTORY_LIMIT: usize = 100_000;
+
+/// Steps through a VM program a command at a time, stopping at calls of chosen functions
+pub struct Debugger {
+    vm: VmMachine,
+    breakpoints: BTreeSet<String>,
+}
+
+/// When to stop running, other than at a breakpoint or the end of the program
+enum Until {
+    NextCommand,
+    /// The call depth is at most this
+    Depth(usize),
+    Breakpoint,
+}
+
+impl Debugger {
+    pub fn new(mut vm: VmMachine) -> Debugger {
+        vm.set_history_limit(HISTORY_LIMIT);
+        Debugger {
+            vm,
+            breakpoints: BTreeSet::new(),
+        }
+    }
+
+    pub fn add_breakpoint(&mut self, function: &str) -> Result<(), ErrorType> {
+        if !self.vm.has_function(function) {
+            return Err(ErrorType::UnknownFunction(function.to_owned()));
+        }
+        self.breakpoints.insert(function.to_owned());
+        Ok(())
+    }
+
+    /// Run a line of debugger input, returning what to show, or `None` to quit
+    pub fn execute(&mut self, line: &str) -> Option<String> {
+        let mut words = line.split_whitespace();
+        let Some(command) = words.next() else {
+            return Some(String::new());
+        };
+        let arguments: Vec<&str> = words.collect();
+        let depth = self.vm.call_depth();
+        let reply = match (command, arguments.as_slice()) {
+            ("q" | "quit", []) => return None,
+            ("h" | "help", []) => HELP.to_owned(),
+            ("b" | "break", []) if self.breakpoints.is_empty() => "No breakpoints".to_owned(),
+            ("b" | "break", []) => self
+                .breakpoints
+                .iter()
+                .map(String::as_str)
+                .collect::<Vec<_>>()
+                .join("\n"),
+            ("back", []) => self.back(1),
+            ("b" | "back", [count]) if count.parse::<usize>().is_ok() => {
+                self.back(count.parse().unwrap_or_default())
+            }
+            ("b" | "break", [function]) => match self.add_breakpoint(function) {
+                Ok(()) => format!("Breakpoint at {}", function),
+                Err(error) => error.to_string(),
+            },
+            ("d" | "delete", [function]) if self.breakpoints.remove(*function) => {
+                format!("Deleted the breakpoint at {}", function)
+            }
+            ("d" | "delete", [function]) => format!("There is no breakpoint at {}", function),
+            ("s" | "step", []) => self.resume(Until::NextCommand),
+            ("n" | "next", []) => self.resume(Until::Depth(depth)),
+            ("f" | "finish", []) if depth == 0 => "Not in a function call".to_owned(),
+            ("f" | "finish", []) => self.resume(Until::Depth(depth - 1)),
+            ("c" | "continue", []) => self.resume(Until::Breakpoint),
+            ("p" | "print", [segment]) => self.print(segment, None),
+            ("p" | "print", [segment, index]) => match index.parse() {
+                Ok(index) => self.print(segment, Some(index)),
+                Err(_) => format!("{} is not an index", index),
+            },
+            ("w" | "where", []) => self.vm.backtrace().join("\n"),
+            _ => format!("Unknown command `{}`, try help", line.trim()),
+        };
+        Some(reply)
+    }
+
+    /// The function and command execution is at
+    pub fn location(&self) -> String {
+        match (self.vm.current_function(), self.vm.current_command()) {
+            (Some(function), Some(command)) => format!("{}: {}", function, command),
+            (None, Some(command)) => command.to_owned(),
+            (_, None) => "At the end of the program".to_owned(),
+        }
+    }
+
+    fn resume(&mut self, until: Until) -> String {
+        loop {
+            match self.vm.step() {
+                Ok(None) => {}
+                Ok(Some(Stop::Halted)) => {
+                    return format!("Halted after {} commands", self.vm.cycles())
+                }
+                Ok(Some(_)) => {
+                    return format!("The program ended after {} commands", self.vm.cycles())
+                }
+                Err(error) => return format!("{}\n{}", error, self.location()),
+            }
+            if let Some(function) = self.vm.entering_function() {
+                if self.breakpoints.contains(function) {
+                    return format!("Breakpoint at {}\n{}", function, self.location());
+                }
+            }
+            let stop = match until {
+                Until::NextCommand => true,
+                Until::Depth(depth) => self.vm.call_depth() <= depth,
+                Until::Breakpoint => false,
+            };
+            if stop {
+                return self.location();
+            }
+        }
+    }
+
+    fn back(&mut self, count: usize) -> String {
+        for _ in 0..count {
+            if !self.vm.step_back() {
+                return format!("No earlier commands to go back to\n{}", self.location());
+            }
+        }
+        self.location()
+    }
+
+    fn print(&self, segment: &str, index: Option<usize>) -> String {
+        let state = self.vm.state();
+        let signed = |words: &[u16]| words.iter().map(|word| *word as i16).collect::<Vec<_>>();
+        let values = match segment {
+            "stack" => state.stack,
+            "local" => state.local,
+            "argument" => state.argument,
+            "temp" => state.temp,
+            "static" => signed(self.vm.statics()),
+            "pointer" => vec![state.pointers.this as i16, state.pointers.that as i16],
+            "this" | "that" => {
+                let Some(index) = index else {
+                    return format!("{} needs an index, e.g. print {} 0", segment, segment);
+                };
+                let base = match segment {
+                    "this" => state.pointers.this,
+                    _ => state.pointers.that,
+                };
+                let address = base.wrapping_add(index as u16);
+                return format!(
+                    "{} {} = {} (RAM[{}])",
+                    segment,
+                    index,
+                    self.vm.peek(address) as i16,
+                    address
+                );
+            }
+            _ => return format!("There is no segment called {}", segment),
+        };
+        match index {
+            None => format!("{}: {:?}", segment, values),
+            Some(index) => match values.get(index) {
+                Some(value) => format!("{} {} = {}", segment, index, value),
+                None => format!("{} only has {} entries", segment, values.len()),
+            },
+        }
+    }
+}
+
+#[test]
+fn test_debugger() {
+    let main = "function Main.main 1
+push constant 3
+call Main.double 1
+pop local 0
+push local 0
+pop static 1
+call Sys.halt 0
+function Main.double 0
+push argument 0
+push argument 0
+add
+return";
+    let vm = VmMachine::load(&[("Main.vm", main)]).unwrap();
+    let mut debugger = Debugger::new(vm);
+    let mut run = |line: &str| debugger.execute(line).unwrap();
+
+    assert_eq!(
+        run("break Main.nothing"),
+        "There is no function called Main.nothing"
+    );
+    assert_eq!(run("b Main.double"), "Breakpoint at Main.double");
+    assert_eq!(run("step"), "Main.main: push constant 3");
+    assert_eq!(run("back"), "Main.main: function Main.main 1");
+    assert_eq!(
+        run("b 2"),
+        "No earlier commands to go back to\nMain.main: function Main.main 1"
+    );
+    assert_eq!(run("step"), "Main.main: push constant 3");
+    assert_eq!(run("next"), "Main.main: call Main.double 1");
+    assert_eq!(
+        run("continue"),
+        "Breakpoint at Main.double\nMain.double: function Main.double 0"
+    );
+    assert_eq!(run("where"), "Main.double\nMain.main");
+    assert_eq!(run("s"), "Main.double: push argument 0");
+    assert_eq!(run("print argument"), "argument: [3]");
+    assert_eq!(run("finish"), "Main.main: pop local 0");
+    assert_eq!(run("print stack"), "stack: [0, 6]");
+    assert_eq!(run("n"), "Main.main: push local 0");
+    assert_eq!(run("back 2"), "Main.double: return");
+    assert_eq!(run("where"), "Main.double\nMain.main");
+    assert_eq!(run("n"), "Main.main: pop local 0");
+    assert_eq!(run("n"), "Main.main: push local 0");
+    assert_eq!(run("print local"), "local: [6]");
+    assert_eq!(run("p this"), "this needs an index, e.g. print this 0");
+    assert_eq!(run("n"), "Main.main: pop static 1");
+    assert_eq!(run("n"), "Main.main: call Sys.halt 0");
+    assert_eq!(run("print static"), "static: [0, 6]");
+    assert_eq!(run("c"), "Halted after 12 commands");
+    assert_eq!(run("jump"), "Unknown command `jump`, try help");
+    assert_eq!(debugger.execute("quit"), None);
+}
@@ -0,0 +1,102 @@
+use std::fmt::Write;
+
+use crate::MEMORY_SIZE;
+
+/// The width of a heatmap image in words. Each row of 256 words is a stack-sized block, so the
+/// stack, heap, screen and keyboard regions start on row boundaries.
+const HEATMAP_WIDTH: usize = 256;
+
+/// How many times the program read and wrote each RAM address
+#[derive(Debug, Clone, PartialEq)]
+pub struct MemoryAccess {
+    reads: Vec<u64>,
+    writes: Vec<u64>,
+}
+
+impl Default for MemoryAccess {
+    fn default() -> Self {
+        Self {
+            reads: vec![0; MEMORY_SIZE],
+            writes: vec![0; MEMORY_SIZE],
+        }
+    }
+}
+
+impl MemoryAccess {
+    pub fn record_read(&mut self, address: u16) {
+        self.reads[address as usize % MEMORY_SIZE] += 1;
+    }
+
+    pub fn record_write(&mut self, address: u16) {
+        self.writes[address as usize % MEMORY_SIZE] += 1;
+    }
+
+    pub fn reads(&self, address: u16) -> u64 {
+        self.reads[address as usize % MEMORY_SIZE]
+    }
+
+    pub fn writes(&self, address: u16) -> u64 {
+        self.writes[address as usize % MEMORY_SIZE]
+    }
+
+    /// One `address,reads,writes` line for every address which was used
+    pub fn to_csv(&self) -> String {
+        let mut csv = String::from("address,reads,writes\n");
+        for address in 0..MEMORY_SIZE {
+            let (reads, writes) = (self.reads[address], self.writes[address]);
+            if reads > 0 || writes > 0 {
+                writeln!(csv, "{},{},{}", address, reads, writes)
+                    .expect("Writing to a String cannot fail");
+            }
+        }
+        csv
+    }
+
+    /// A PPM image with a pixel per address, 256 to a row. Writes are red and reads are green,
+    /// brighter for more accesses on a log scale.
+    pub fn to_ppm(&self) -> Vec<u8> {
+        let rows = MEMORY_SIZE / HEATMAP_WIDTH;
+        let mut image = format!("P6\n{} {}\n255\n", HEATMAP_WIDTH, rows).into_bytes();
+
+        let max = self
+            .reads
+            .iter()
+            .chain(&self.writes)
+            .copied()
+            .max()
+            .unwrap_or(0);
+        let scale = |count: u64| -> u8 {
+            if count == 0 {
+                0
+            } else {
+                // Anything used at all is visible
+                let brightness = ((count as f64).ln_1p() / (max as f64).ln_1p()) * 191.0 + 64.0;
+                brightness as u8
+            }
+        };
+        for address in 0..MEMORY_SIZE {
+            image.extend([scale(self.writes[address]), scale(self.reads[address]), 0]);
+        }
+        image
+    }
+}
+
+#[test]
+fn test_memory_access_exports() {
+    let mut access = MemoryAccess::default();
+    access.record_write(0);
+    access.record_read(0);
+    access.record_read(0);
+    access.record_write(crate::SCREEN);
+
+    assert_eq!(access.to_csv(), "address,reads,writes\n0,2,1\n16384,0,1\n");
+
+    let image = access.to_ppm();
+    let header = b"P6\n256 128\n255\n";
+    assert_eq!(&image[..header.len()], header);
+    assert_eq!(image.len(), header.len() + MEMORY_SIZE * 3);
+    let pixel = |address: usize| &image[header.len() + address * 3..][..3];
+    assert_eq!(pixel(0), &[184, 255, 0]);
+    assert_eq!(pixel(1), &[0, 0, 0]);
+    assert_eq!(pixel(crate::SCREEN as usize), &[184, 0, 0]);
+}
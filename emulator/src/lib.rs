@@ -0,0 +1,331 @@
+mod assertions;
+mod budget;
+pub mod cli;
+mod cpu;
+pub mod debug_cli;
+mod debugger;
+mod heatmap;
+mod os_compat;
+mod peripheral;
+mod repl;
+pub mod repl_cli;
+mod replay;
+mod state;
+mod test_script;
+mod trace;
+mod verify;
+mod vm;
+
+pub use assertions::{AssertionFailure, ASSERTIONS};
+pub use budget::{run_with_budgets, Budget, Checkpoint, SymbolMap};
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+pub use cpu::{Cpu, Stop, BANK, KEYBOARD, MAX_RAM_SIZE, MEMORY_SIZE, SCREEN, SCREEN_WORDS};
+pub use debugger::Debugger;
+pub use heatmap::MemoryAccess;
+pub use os_compat::{Division, OsCompat, PixelBounds, StringOverflow};
+pub use peripheral::Peripheral;
+pub use repl::Repl;
+pub use replay::{input_log, parse_input_log, read_input_log, KeyPress};
+pub use state::{HeapBlock, Pointers, VmState};
+pub use test_script::run_test_script;
+use thiserror::Error;
+pub use trace::run_with_trace;
+pub use verify::{verify_translation, FunctionCheck, Outcome};
+pub use vm::{VmMachine, STACK_BASE};
+
+#[derive(Debug, Error)]
+pub enum ErrorType {
+    #[error("Failed to read {}", .path.display())]
+    ReadError {
+        path: PathBuf,
+        #[source]
+        source: io::Error,
+    },
+    #[error("Failed to write {}", .path.display())]
+    WriteError {
+        path: PathBuf,
+        #[source]
+        source: io::Error,
+    },
+    #[error("Line {line}: expected 16 binary digits but found {text:?}")]
+    InvalidInstruction { line: usize, text: String },
+    #[error("The program has {0} instructions but the ROM only holds {MEMORY_SIZE}")]
+    ProgramTooLarge(usize),
+    #[error("Failed to parse {file}: {message}")]
+    VmParsingError { file: String, message: String },
+    #[error("There is no function called {0}")]
+    UnknownFunction(String),
+    #[error("`{0}` can only be used in a .vm file")]
+    NotInteractive(String),
+    #[error("{function} has no label called {label}")]
+    UnknownLabel { function: String, label: String },
+    #[error("{function}: `{command}` accesses memory outside the segment")]
+    InvalidAccess { function: String, command: String },
+    #[error("{function}: {message}")]
+    OsError { function: String, message: String },
+    #[error("--heatmap counts the memory accesses of the CPU, so it needs a .hack program")]
+    HeatmapNeedsHackProgram,
+    #[error("--bank adds ROM banks to the CPU, so it needs a .hack program")]
+    BanksNeedHackProgram,
+    #[error("--ram-size and --trap-unmapped change the CPU's RAM, so they need a .hack program")]
+    RamOptionsNeedHackProgram,
+    #[error("--budget counts the cycles of the CPU, so it needs a .hack program")]
+    BudgetNeedsHackProgram,
+    #[error("{0} is not a ROM address, halt or a label in the symbol map")]
+    UnknownCheckpoint(String),
+    #[error("{} is not a symbol map", .path.display())]
+    SymbolMapError {
+        path: PathBuf,
+        #[source]
+        source: serde_json::Error,
+    },
+    #[error("{0} cycle budgets were exceeded")]
+    BudgetsExceeded(usize),
+    #[error("--export-state snapshots the segments and heap of VM code, so it needs a VM program")]
+    ExportStateNeedsVmProgram,
+    #[error("RAM[{address}] is outside the {size} words of RAM")]
+    AddressOutsideRam { address: u16, size: usize },
+    #[error("The instruction at {pc} accessed RAM[{address}], which isn't mapped to any memory")]
+    UnmappedAccess { address: u16, pc: u16 },
+    #[error("--cpu-trace follows the CPU's registers, so it needs a .hack or .asm program")]
+    TraceNeedsHackProgram,
+    #[error("Failed to write the trace")]
+    TraceOutput(#[source] io::Error),
+    #[error("{}:{line}: {message}", .path.display())]
+    TestScriptError {
+        path: PathBuf,
+        line: usize,
+        message: String,
+    },
+    #[error("{}:{line}: {message}", .path.display())]
+    InvalidInputLog {
+        path: PathBuf,
+        line: usize,
+        message: String,
+    },
+    #[error("--record and --replay log the keys given to VM code, so they need a VM program")]
+    InputLogNeedsVmProgram,
+    #[error("Comparison failure at line {line}: expected {expected:?} but found {actual:?}")]
+    ComparisonFailure {
+        line: usize,
+        expected: String,
+        actual: String,
+    },
+    #[error("Failed to translate the program")]
+    TranslationError(#[from] vm_translator::ErrorType),
+    #[error("Failed to assemble the program")]
+    AssemblyError(#[from] assembler::ErrorType),
+}
+
+/// Parse the text of a .hack file, one 16 digit binary word per line, into a ROM image
+pub fn parse_hack(contents: &str) -> Result<Vec<u16>, ErrorType> {
+    let mut rom = Vec::new();
+    for (index, text) in contents.lines().enumerate() {
+        let word = text.trim();
+        if word.is_empty() {
+            continue;
+        }
+        if word.len() != 16 || !word.bytes().all(|digit| digit == b'0' || digit == b'1') {
+            return Err(ErrorType::InvalidInstruction {
+                line: index + 1,
+                text: text.to_owned(),
+            });
+        }
+        rom.push(u16::from_str_radix(word, 2).expect("Only binary digits remain"));
+    }
+
+    if rom.len() > MEMORY_SIZE {
+        return Err(ErrorType::ProgramTooLarge(rom.len()));
+    }
+    Ok(rom)
+}
+
+/// Load a .hack file into a new computer
+pub fn load_file(path: &Path) -> Result<Cpu, ErrorType> {
+    load_banks(&[path])
+}
+
+/// Load a .hack file for each ROM bank of a new computer, starting with the first bank. .asm files
+/// are assembled first.
+pub fn load_banks(paths: &[&Path]) -> Result<Cpu, ErrorType> {
+    let mut banks = Vec::with_capacity(paths.len());
+    for path in paths {
+        let contents = fs::read_to_string(path).map_err(|source| ErrorType::ReadError {
+            path: path.to_path_buf(),
+            source,
+        })?;
+        if path.extension().is_some_and(|extension| extension == "asm") {
+            banks.push(assembler::assemble(&contents).map_err(assembler::ErrorType::from)?);
+        } else {
+            banks.push(parse_hack(&contents)?);
+        }
+    }
+    Ok(Cpu::with_banks(banks))
+}
+
+/// Load a .vm file, or every .vm file in a directory, into a VM emulator, optionally with the
+/// built-in OS
+pub fn load_vm(path: &Path, with_os: bool) -> Result<VmMachine, ErrorType> {
+    let sources = read_vm_sources(path)?;
+    let mut sources: Vec<(&str, &str)> = sources
+        .iter()
+        .map(|(name, contents)| (name.as_str(), contents.as_str()))
+        .collect();
+    if with_os {
+        sources = vm_translator::link_os(&sources);
+    }
+    VmMachine::load(&sources)
+}
+
+/// Read a .vm file, or every .vm file in a directory, as (file name, contents) pairs
+pub fn read_vm_sources(path: &Path) -> Result<Vec<(String, String)>, ErrorType> {
+    let read_error = |source| ErrorType::ReadError {
+        path: path.to_owned(),
+        source,
+    };
+    let mut files = Vec::new();
+    if path.is_dir() {
+        for entry in fs::read_dir(path).map_err(read_error)? {
+            let file = entry.map_err(read_error)?.path();
+            if file.extension().is_some_and(|extension| extension == "vm") {
+                files.push(file);
+            }
+        }
+        // Statics are allocated file by file so keep the order stable
+        files.sort();
+    } else {
+        files.push(path.to_owned());
+    }
+
+    let mut sources = Vec::with_capacity(files.len());
+    for file in &files {
+        let contents = fs::read_to_string(file).map_err(|source| ErrorType::ReadError {
+            path: file.clone(),
+            source,
+        })?;
+        let name = file
+            .file_name()
+            .map(|name| name.to_string_lossy().into_owned())
+            .unwrap_or_default();
+        sources.push((name, contents));
+    }
+    Ok(sources)
+}
+
+/// Render the screen as a binary PBM image, 512 by 256 pixels
+pub fn screen_to_pbm(screen: &[u16]) -> Vec<u8> {
+    let mut image = b"P4\n512 256\n".to_vec();
+    // Pixels run from the least significant bit of each word but from the most significant bit of
+    // each PBM byte
+    for word in screen {
+        image.push((*word as u8).reverse_bits());
+        image.push(((*word >> 8) as u8).reverse_bits());
+    }
+    image
+}
+
+#[test]
+fn test_parse_hack() {
+    assert_eq!(
+        parse_hack("0000000000000101\n1110110000010000\n\n").unwrap(),
+        vec![5, 0b1110110000010000]
+    );
+    assert!(matches!(
+        parse_hack("0000000000000101\n111011000001000"),
+        Err(ErrorType::InvalidInstruction { line: 2, .. })
+    ));
+}
+
+#[test]
+fn test_screen_to_pbm() {
+    let mut screen = vec![0; SCREEN_WORDS];
+    screen[0] = 0b1000_0000_0000_0001;
+
+    let image = screen_to_pbm(&screen);
+    assert_eq!(image.len(), 11 + 512 * 256 / 8);
+    assert_eq!(&image[11..13], &[0b1000_0000, 0b0000_0001]);
+}
+
+#[test]
+fn test_peephole_optimization_keeps_the_results() {
+    // Multiply, divide and fill an array through the built-in OS, keeping the results in statics
+    let main = "function Main.main 2
+        push constant 6
+        push constant 7
+        call Math.multiply 2
+        pop static 0
+        push constant 100
+        push constant 7
+        call Math.divide 2
+        pop static 1
+        push constant 10
+        call Memory.alloc 1
+        pop local 0
+        push constant 0
+        pop local 1
+        label LOOP
+        push local 1
+        push constant 10
+        lt
+        not
+        if-goto END
+        push local 0
+        push local 1
+        add
+        pop pointer 1
+        push local 1
+        push local 1
+        call Math.multiply 2
+        pop that 0
+        push local 1
+        push constant 1
+        add
+        pop local 1
+        goto LOOP
+        label END
+        push local 0
+        pop static 2
+        push constant 0
+        return";
+    let run = |peephole: bool| {
+        let options = vm_translator::TranslationOptions {
+            with_os: true,
+            peephole,
+            ..Default::default()
+        };
+        let asm =
+            vm_translator::translate_program_with_options(&[("Main", main)], &options).unwrap();
+        let (hack, symbols) = assembler::assemble_string_with_symbols(&asm).unwrap();
+        let mut cpu = Cpu::new(parse_hack(&hack).unwrap());
+        // Sys.halt loops forever with a conditional jump, so run until it's called
+        let halt = symbols.labels["Sys.halt"];
+        while cpu.pc() != halt {
+            assert_eq!(cpu.step().unwrap(), None);
+            assert!(cpu.cycles() < 10_000_000, "the program never halted");
+        }
+        let statics = [0, 1, 2].map(|index| symbols.variables[&format!("Main.{}", index)]);
+        (cpu, statics, asm.lines().count())
+    };
+    let (plain, statics, plain_size) = run(false);
+    let (optimized, optimized_statics, optimized_size) = run(true);
+    assert!(optimized_size < plain_size);
+    assert_eq!(optimized_statics, statics);
+
+    // The stack holds return addresses, which move as the code shrinks, and the translator's
+    // scratch registers R13 to R15 are dead between commands
+    let live = (0..13).chain(16..256).chain(2048..KEYBOARD);
+    for address in live {
+        assert_eq!(
+            optimized.peek(address),
+            plain.peek(address),
+            "RAM[{}] differs",
+            address
+        );
+    }
+    let [product, quotient, array] = statics.map(|address| plain.peek(address));
+    assert_eq!((product, quotient), (42, 14));
+    assert_eq!(plain.peek(array + 9), 81);
+}
@@ -0,0 +1,11 @@
+use emulator::cli;
+use parse_utils::cli::print_error;
+
+fn main() {
+    let matches = cli::command().arg_required_else_help(true).get_matches();
+
+    if let Err(err) = cli::run(&matches) {
+        print_error(&err);
+        std::process::exit(1);
+    }
+}
@@ -0,0 +1,159 @@
+use std::str::FromStr;
+
+/// How `Math.divide` rounds a quotient which isn't whole
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Division {
+    /// Run the program's own Math.divide
+    #[default]
+    Os,
+    /// Like the official OS, e.g. -7 / 2 is -3
+    TowardZero,
+    /// Round down, e.g. -7 / 2 is -4
+    Floor,
+}
+
+/// What `String.appendChar` does when the string is already at its maximum length
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum StringOverflow {
+    /// Run the program's own String.appendChar
+    #[default]
+    Os,
+    /// Drop the character and return the string unchanged
+    Ignore,
+    /// Stop with an error, as the official OS does
+    Error,
+}
+
+/// What `Screen.drawPixel` does with a pixel off the screen
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum PixelBounds {
+    /// Run the program's own Screen.drawPixel
+    #[default]
+    Os,
+    /// Draw nothing
+    Clip,
+    /// Wrap the coordinates around onto the screen
+    Wrap,
+    /// Stop with an error, as the official OS does
+    Error,
+}
+
+/// Switches for OS edge cases on which implementations disagree. The VM emulator handles the
+/// affected calls itself rather than running the OS code, so that a program behaves as it would
+/// with the official tools or with the OS it was written against.
+///
+/// String handling assumes the field layout of the built-in OS: the characters, the length and
+/// then the maximum length.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct OsCompat {
+    pub division: Division,
+    pub string_overflow: StringOverflow,
+    pub pixel_bounds: PixelBounds,
+}
+
+/// An OS call the emulator steps in for
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum OsCall {
+    Divide(Division),
+    AppendChar(StringOverflow),
+    DrawPixel(PixelBounds),
+}
+
+impl OsCompat {
+    pub(crate) fn intercept(&self, function: &str) -> Option<OsCall> {
+        match function {
+            "Math.divide" if self.division != Division::Os => Some(OsCall::Divide(self.division)),
+            "String.appendChar" if self.string_overflow != StringOverflow::Os => {
+                Some(OsCall::AppendChar(self.string_overflow))
+            }
+            "Screen.drawPixel" if self.pixel_bounds != PixelBounds::Os => {
+                Some(OsCall::DrawPixel(self.pixel_bounds))
+            }
+            _ => None,
+        }
+    }
+}
+
+impl Division {
+    pub const NAMES: [&'static str; 3] = ["os", "toward-zero", "floor"];
+
+    /// The quotient of a division by anything but 0
+    pub(crate) fn divide(self, x: i16, y: i16) -> i16 {
+        let quotient = x.wrapping_div(y);
+        let inexact = x.wrapping_rem(y) != 0;
+        if self == Division::Floor && inexact && (x < 0) != (y < 0) {
+            quotient - 1
+        } else {
+            quotient
+        }
+    }
+}
+
+impl StringOverflow {
+    pub const NAMES: [&'static str; 3] = ["os", "ignore", "error"];
+}
+
+impl PixelBounds {
+    pub const NAMES: [&'static str; 4] = ["os", "clip", "wrap", "error"];
+}
+
+fn unknown(kind: &str, name: &str, names: &[&str]) -> String {
+    format!(
+        "{} is not a {}, expected one of {}",
+        name,
+        kind,
+        names.join(", ")
+    )
+}
+
+impl FromStr for Division {
+    type Err = String;
+
+    fn from_str(name: &str) -> Result<Self, Self::Err> {
+        match name {
+            "os" => Ok(Division::Os),
+            "toward-zero" => Ok(Division::TowardZero),
+            "floor" => Ok(Division::Floor),
+            _ => Err(unknown("rounding", name, &Self::NAMES)),
+        }
+    }
+}
+
+impl FromStr for StringOverflow {
+    type Err = String;
+
+    fn from_str(name: &str) -> Result<Self, Self::Err> {
+        match name {
+            "os" => Ok(StringOverflow::Os),
+            "ignore" => Ok(StringOverflow::Ignore),
+            "error" => Ok(StringOverflow::Error),
+            _ => Err(unknown("string overflow behavior", name, &Self::NAMES)),
+        }
+    }
+}
+
+impl FromStr for PixelBounds {
+    type Err = String;
+
+    fn from_str(name: &str) -> Result<Self, Self::Err> {
+        match name {
+            "os" => Ok(PixelBounds::Os),
+            "clip" => Ok(PixelBounds::Clip),
+            "wrap" => Ok(PixelBounds::Wrap),
+            "error" => Ok(PixelBounds::Error),
+            _ => Err(unknown("pixel bounds behavior", name, &Self::NAMES)),
+        }
+    }
+}
+
+#[test]
+fn test_division_rounding() {
+    assert_eq!(Division::TowardZero.divide(-7, 2), -3);
+    assert_eq!(Division::Floor.divide(-7, 2), -4);
+    assert_eq!(Division::Floor.divide(7, -2), -4);
+    assert_eq!(Division::Floor.divide(-8, 2), -4);
+    assert_eq!(Division::Floor.divide(7, 2), 3);
+    assert_eq!(Division::TowardZero.divide(i16::MIN, -1), i16::MIN);
+    assert_eq!("floor".parse(), Ok(Division::Floor));
+    assert!("up".parse::<Division>().is_err());
+}
@@ -0,0 +1,105 @@
+use std::ops::RangeInclusive;
+
+/// A memory-mapped device added to the computer, e.g. a UART or a timer on an extended Hack
+/// system. The program's reads and writes of the device's addresses go to it rather than to RAM.
+pub trait Peripheral {
+    /// The RAM addresses the device is mapped to
+    fn addresses(&self) -> RangeInclusive<u16>;
+
+    /// The value the program reads from one of the device's addresses
+    fn read(&mut self, address: u16) -> u16;
+
+    /// The program writes a value to one of the device's addresses
+    fn write(&mut self, address: u16, value: u16);
+
+    /// Called once for each instruction the CPU executes, before it runs
+    fn tick(&mut self) {}
+}
+
+#[cfg(test)]
+mod devices {
+    use super::Peripheral;
+    use std::cell::RefCell;
+    use std::ops::RangeInclusive;
+    use std::rc::Rc;
+
+    /// Counts the instructions executed, which the program can reset by writing to it
+    pub struct Timer(pub u16);
+
+    impl Peripheral for Timer {
+        fn addresses(&self) -> RangeInclusive<u16> {
+            0x6003..=0x6003
+        }
+
+        fn read(&mut self, _: u16) -> u16 {
+            self.0
+        }
+
+        fn write(&mut self, _: u16, value: u16) {
+            self.0 = value;
+        }
+
+        fn tick(&mut self) {
+            self.0 = self.0.wrapping_add(1);
+        }
+    }
+
+    /// Sends each word written to its data register, and reads as ready in its status register
+    pub struct Uart(pub Rc<RefCell<Vec<u16>>>);
+
+    impl Peripheral for Uart {
+        fn addresses(&self) -> RangeInclusive<u16> {
+            0x6001..=0x6002
+        }
+
+        fn read(&mut self, address: u16) -> u16 {
+            (address == 0x6002) as u16
+        }
+
+        fn write(&mut self, address: u16, value: u16) {
+            if address == 0x6001 {
+                self.0.borrow_mut().push(value);
+            }
+        }
+    }
+}
+
+#[test]
+fn test_peripherals() {
+    use std::cell::RefCell;
+    use std::rc::Rc;
+
+    // Send 72 and 105 through the UART, then copy the timer and the UART status into R0 and R1
+    let hack = assembler::assemble_string(
+        "@72
+        D=A
+        @24577
+        M=D
+        @105
+        D=A
+        @24577
+        M=D
+        @24579
+        D=M
+        @R0
+        M=D
+        @24578
+        D=M
+        @R1
+        M=D",
+    )
+    .unwrap();
+    let sent = Rc::new(RefCell::new(Vec::new()));
+    let mut cpu = crate::Cpu::new(crate::parse_hack(&hack).unwrap());
+    cpu.add_peripheral(Box::new(devices::Timer(0)));
+    cpu.add_peripheral(Box::new(devices::Uart(sent.clone())));
+    cpu.trap_unmapped();
+
+    assert_eq!(cpu.run(100).unwrap(), crate::Stop::EndOfProgram);
+    assert_eq!(*sent.borrow(), vec![72, 105]);
+    // The timer is read by the tenth instruction
+    assert_eq!(cpu.peek(0), 10);
+    assert_eq!(cpu.peek(1), 1);
+    // Writes to a device don't reach RAM
+    assert_eq!(cpu.peek(0x6001), 0);
+}
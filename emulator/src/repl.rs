@@ -0,0 +1,129 @@
+use crate::{ErrorType, Stop, VmMachine};
+
+pub const HELP: &str =
+    "Type VM commands such as push constant 7, add or call Math.multiply 2 to run them one at
+a time. After each, the stack is shown along with any segment the command changed. A call runs
+until the function returns. Functions, labels and jumps can only be used in .vm files.
+  help
+  quit";
+
+/// The number of entries in the local, argument and static segments of typed commands
+const SEGMENT_SIZE: u16 = 8;
+/// How many commands a call can run before it's given up on
+const MAX_CYCLES: u64 = 10_000_000;
+/// Run before anything is typed so that the OS classes, if loaded, are ready to use. Sys.init
+/// would also run Main.main.
+const OS_INIT: [&str; 5] = [
+    "Memory.init",
+    "Math.init",
+    "Screen.init",
+    "Output.init",
+    "Keyboard.init",
+];
+
+/// Runs VM commands as they are typed, showing what each did to the stack and segments
+pub struct Repl {
+    vm: VmMachine,
+    /// Where the stack starts, above the locals
+    stack_base: u16,
+}
+
+impl Repl {
+    /// Start with the functions of `vm` available to call
+    pub fn new(mut vm: VmMachine) -> Result<Repl, ErrorType> {
+        vm.enter_interactive(SEGMENT_SIZE, SEGMENT_SIZE);
+        for function in OS_INIT {
+            if vm.has_function(function) {
+                vm.execute_line(&format!("call {} 0", function), MAX_CYCLES)?;
+            }
+        }
+        // Start again from an empty stack, without the results of the calls
+        vm.enter_interactive(SEGMENT_SIZE, SEGMENT_SIZE);
+        let stack_base = vm.peek(0);
+        Ok(Repl { vm, stack_base })
+    }
+
+    /// Run a line of input, returning what to show, or `None` to quit
+    pub fn execute(&mut self, line: &str) -> Option<String> {
+        match line.trim() {
+            "" => return Some(String::new()),
+            "q" | "quit" => return None,
+            "h" | "help" => return Some(HELP.to_owned()),
+            _ => {}
+        }
+
+        let before = self.segments();
+        let mut reply = match self.vm.execute_line(line, MAX_CYCLES) {
+            Ok(None) => Vec::new(),
+            Ok(Some(Stop::CycleLimit)) => {
+                vec![format!("Gave up after {} commands", MAX_CYCLES)]
+            }
+            Ok(Some(Stop::Halted)) => vec!["The program halted".to_owned()],
+            Ok(Some(Stop::EndOfProgram)) => vec!["The program ended".to_owned()],
+            Err(error) => return Some(error.to_string()),
+        };
+        reply.push(format!("stack: {:?}", self.stack()));
+        for ((name, old), (_, new)) in before.iter().zip(self.segments()) {
+            if *old != new {
+                reply.push(format!("{}: {:?}", name, new));
+            }
+        }
+        Some(reply.join("\n"))
+    }
+
+    fn stack(&self) -> Vec<i16> {
+        (self.stack_base..self.vm.peek(0))
+            .map(|address| self.vm.peek(address) as i16)
+            .collect()
+    }
+
+    fn segments(&self) -> [(&'static str, Vec<i16>); 5] {
+        let words = |base: u16, size: u16| {
+            (base..base.saturating_add(size))
+                .map(|address| self.vm.peek(address) as i16)
+                .collect()
+        };
+        let statics = self.vm.interactive_statics() as u16;
+        [
+            ("local", words(self.vm.peek(1), SEGMENT_SIZE)),
+            ("argument", words(self.vm.peek(2), SEGMENT_SIZE)),
+            ("static", words(statics, SEGMENT_SIZE)),
+            ("temp", words(5, 8)),
+            ("pointer", words(3, 2)),
+        ]
+    }
+}
+
+#[test]
+fn test_repl() {
+    let math = "function Math.double 0
+push argument 0
+push argument 0
+add
+return";
+    let vm = VmMachine::load(&[("Math.vm", math)]).unwrap();
+    let mut repl = Repl::new(vm).unwrap();
+    let mut run = |line: &str| repl.execute(line).unwrap();
+
+    assert_eq!(run("push constant 7"), "stack: [7]");
+    assert_eq!(run("push constant 3"), "stack: [7, 3]");
+    assert_eq!(run("sub"), "stack: [4]");
+    assert_eq!(run("call Math.double 1"), "stack: [8]");
+    assert_eq!(
+        run("pop local 1"),
+        "stack: []\nlocal: [0, 8, 0, 0, 0, 0, 0, 0]"
+    );
+    assert_eq!(
+        run("push local 1\npop temp 2"),
+        "stack: []\ntemp: [0, 0, 8, 0, 0, 0, 0, 0]"
+    );
+    assert_eq!(
+        run("label LOOP"),
+        "`label LOOP` can only be used in a .vm file"
+    );
+    assert_eq!(
+        run("call Math.triple 1"),
+        "There is no function called Math.triple"
+    );
+    assert_eq!(repl.execute("quit"), None);
+}
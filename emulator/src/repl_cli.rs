@@ -0,0 +1,53 @@
+use std::io::{self, BufRead, Write};
+use std::path::Path;
+
+use clap::{Arg, ArgAction, ArgMatches, Command, ValueHint};
+
+use crate::repl::{Repl, HELP};
+use crate::{load_vm, ErrorType, VmMachine};
+
+/// The command line interface of the VM REPL, shared by vm-repl and n2t
+pub fn command() -> Command {
+    Command::new("vm-repl")
+        .about("Type VM commands one at a time and watch the stack and segments change")
+        .arg(
+            Arg::new("INPUT")
+                .index(1)
+                .required(false)
+                .value_name("FILE")
+                .value_hint(ValueHint::AnyPath)
+                .help("A .vm file or a directory of .vm files whose functions can be called"),
+        )
+        .arg(
+            Arg::new("with_os")
+                .long("with-os")
+                .action(ArgAction::SetTrue)
+                .required(false)
+                .help("Link in the built-in Jack OS classes so that they can be called"),
+        )
+}
+
+pub fn run(matches: &ArgMatches) -> Result<(), ErrorType> {
+    let with_os = matches.get_flag("with_os");
+    let vm = match matches.get_one::<String>("INPUT") {
+        Some(path) => load_vm(Path::new(path), with_os)?,
+        None if with_os => VmMachine::load(vm_translator::OS_FILES)?,
+        None => VmMachine::load(&[])?,
+    };
+    let mut repl = Repl::new(vm)?;
+
+    println!("{}\n", HELP);
+    let mut lines = io::stdin().lock().lines();
+    loop {
+        print!("(vm) ");
+        io::stdout().flush().ok();
+        let Some(Ok(line)) = lines.next() else {
+            return Ok(());
+        };
+        match repl.execute(&line) {
+            Some(reply) if reply.is_empty() => {}
+            Some(reply) => println!("{}", reply),
+            None => return Ok(()),
+        }
+    }
+}
@@ -0,0 +1,97 @@
+//! Logs of the keys pressed while a VM program ran, so that a run of an interactive program can be
+//! recorded once and replayed exactly, e.g. to debug something which only happens now and then.
+//!
+//! The keyboard is the only input a program has. A log has a line for each change of the key held
+//! down, giving the number of commands run beforehand and the key's code:
+//!
+//! ```text
+//! 1200 65
+//! 1450 0
+//! ```
+
+use std::fs;
+use std::path::Path;
+
+use crate::ErrorType;
+
+const HEADER: &str = "// Commands run, then the key held down from then on";
+
+/// The key held down from a point in a run
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct KeyPress {
+    /// The number of commands run before the key was pressed
+    pub cycle: u64,
+    /// The key's code, or 0 once every key is released
+    pub key: u16,
+}
+
+/// The text of a log of `presses`
+pub fn input_log(presses: &[KeyPress]) -> String {
+    let mut log = format!("{}\n", HEADER);
+    for press in presses {
+        log.push_str(&format!("{} {}\n", press.cycle, press.key));
+    }
+    log
+}
+
+/// Parse a log, or give the line and description of the first error
+pub fn parse_input_log(text: &str) -> Result<Vec<KeyPress>, (usize, String)> {
+    let mut presses: Vec<KeyPress> = Vec::new();
+    for (index, line) in text.lines().enumerate() {
+        let line_number = index + 1;
+        let line = line.split("//").next().unwrap_or("").trim();
+        if line.is_empty() {
+            continue;
+        }
+        let press = match line.split_whitespace().collect::<Vec<_>>()[..] {
+            [cycle, key] => match (cycle.parse(), key.parse()) {
+                (Ok(cycle), Ok(key)) => KeyPress { cycle, key },
+                _ => return Err((line_number, format!("{} is not a count and a key", line))),
+            },
+            _ => return Err((line_number, "expected a count and a key".to_owned())),
+        };
+        if presses.last().is_some_and(|last| last.cycle > press.cycle) {
+            return Err((line_number, "the presses are out of order".to_owned()));
+        }
+        presses.push(press);
+    }
+    Ok(presses)
+}
+
+pub fn read_input_log(path: &Path) -> Result<Vec<KeyPress>, ErrorType> {
+    let contents = fs::read_to_string(path).map_err(|source| ErrorType::ReadError {
+        path: path.to_owned(),
+        source,
+    })?;
+    parse_input_log(&contents).map_err(|(line, message)| ErrorType::InvalidInputLog {
+        path: path.to_owned(),
+        line,
+        message,
+    })
+}
+
+#[test]
+fn test_input_log() {
+    let presses = vec![
+        KeyPress { cycle: 0, key: 0 },
+        KeyPress {
+            cycle: 1200,
+            key: 65,
+        },
+        KeyPress {
+            cycle: 1450,
+            key: 0,
+        },
+    ];
+    let log = input_log(&presses);
+    assert_eq!(parse_input_log(&log), Ok(presses));
+
+    assert_eq!(
+        parse_input_log("10 65\n5 0"),
+        Err((2, "the presses are out of order".to_owned()))
+    );
+    assert_eq!(
+        parse_input_log("10 up"),
+        Err((1, "10 up is not a count and a key".to_owned()))
+    );
+}
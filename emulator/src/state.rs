@@ -0,0 +1,110 @@
+//! Snapshots of a VM program's memory, written by `--export-state` for frontends which draw it.
+
+use std::collections::HashSet;
+
+use serde::Serialize;
+
+/// The heap managed by the built-in OS's Memory class
+const HEAP_BASE: u16 = 2048;
+const HEAP_END: u16 = 16384;
+
+/// The state of a VM program between two commands. Values are signed, as Jack sees them.
+#[derive(Debug, Serialize)]
+pub struct VmState {
+    pub cycles: u64,
+    pub function: Option<String>,
+    pub command: Option<String>,
+    pub pointers: Pointers,
+    pub local: Vec<i16>,
+    pub argument: Vec<i16>,
+    pub temp: Vec<i16>,
+    pub stack: Vec<i16>,
+    /// Only known while the heap is laid out the way the built-in OS lays it out
+    pub heap: Option<Vec<HeapBlock>>,
+    /// The 8192 words of the screen, 32 to a row with the leftmost pixel in the lowest bit
+    pub screen: Vec<u16>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct Pointers {
+    pub sp: u16,
+    pub lcl: u16,
+    pub arg: u16,
+    pub this: u16,
+    pub that: u16,
+}
+
+#[derive(Debug, PartialEq, Serialize)]
+pub struct HeapBlock {
+    /// The address of the block's size word. An object allocated in it starts at the next word.
+    pub address: u16,
+    /// The size of the block, including the size word
+    pub size: u16,
+    pub free: bool,
+}
+
+/// Walk the blocks of the heap, which follow each other from 2048 to the end of the heap, marking
+/// those in the free list starting at `free_list`. Returns `None` if RAM doesn't hold a heap laid
+/// out that way, e.g. before Memory.init has run.
+pub(crate) fn heap_blocks(ram: &[u16], free_list: u16) -> Option<Vec<HeapBlock>> {
+    let mut blocks = Vec::new();
+    let mut address = HEAP_BASE;
+    while address < HEAP_END {
+        let size = *ram.get(address as usize)?;
+        if size == 0 || size > HEAP_END - address {
+            return None;
+        }
+        blocks.push(HeapBlock {
+            address,
+            size,
+            free: false,
+        });
+        address += size;
+    }
+
+    let starts: HashSet<u16> = blocks.iter().map(|block| block.address).collect();
+    let mut free = HashSet::new();
+    let mut block = free_list;
+    while block != 0 {
+        // A list which loops back on itself is never going to end
+        if !starts.contains(&block) || !free.insert(block) {
+            return None;
+        }
+        block = ram[block as usize + 1];
+    }
+    for block in &mut blocks {
+        block.free = free.contains(&block.address);
+    }
+    Some(blocks)
+}
+
+#[test]
+fn test_heap_blocks() {
+    let mut ram = vec![0; 0x8000];
+    assert_eq!(heap_blocks(&ram, 2048), None);
+
+    // A used block of 3 words at the start and the rest free
+    ram[2048] = 3;
+    ram[2051] = 14333;
+    let blocks = heap_blocks(&ram, 2051).unwrap();
+    assert_eq!(
+        blocks,
+        [
+            HeapBlock {
+                address: 2048,
+                size: 3,
+                free: false
+            },
+            HeapBlock {
+                address: 2051,
+                size: 14333,
+                free: true
+            },
+        ]
+    );
+
+    // A free list pointing into the middle of a block, or looping, isn't a heap
+    assert_eq!(heap_blocks(&ram, 2049), None);
+    ram[2052] = 2051;
+    assert_eq!(heap_blocks(&ram, 2051), None);
+}
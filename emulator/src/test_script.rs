@@ -0,0 +1,929 @@
+//! Running the course's .tst test scripts for the CPU emulator and VM emulator, which set up
+//! memory, run the program and compare its output with a .cmp file.
+//!
+//! As well as the course's commands, `assert-screen X Y PIXELS` checks the screen from the point
+//! (X, Y) at that step of the script, so that graphical programs can be tested without looking at
+//! them. PIXELS is either rows such as `0110/1001`, with 1 for black, or a .pbm reference image.
+
+use std::fs;
+use std::iter::Peekable;
+use std::path::{Path, PathBuf};
+use std::vec::IntoIter;
+
+use parse_utils::output::write_atomic;
+
+use crate::{load_banks, load_vm, Cpu, ErrorType, VmMachine, SCREEN};
+
+/// The format of an output-list entry which doesn't give one
+const DEFAULT_FORMAT: Format = Format {
+    radix: 'D',
+    left: 1,
+    width: 6,
+    right: 1,
+};
+const SCREEN_WIDTH: usize = 512;
+const SCREEN_HEIGHT: usize = 256;
+
+#[derive(Debug, Clone, PartialEq)]
+enum Token {
+    Word(String),
+    Text(String),
+    /// `,` between the commands of a step or `;` ending one
+    Separator,
+    Open,
+    Close,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+struct Line {
+    number: usize,
+    statement: Statement,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Statement {
+    /// A program to load, or the .vm files of the script's directory
+    Load(Option<String>),
+    OutputFile(String),
+    CompareTo(String),
+    OutputList(Vec<Column>),
+    Set(Variable, u16),
+    Repeat(u64, Vec<Line>),
+    While(Condition, Vec<Line>),
+    /// The first half of a clock cycle, which the emulators don't need
+    Tick,
+    /// The second half of a clock cycle, which runs an instruction
+    Tock,
+    TickTock,
+    VmStep,
+    Output,
+    Echo(String),
+    ClearEcho,
+    /// Check the pixels of the screen from the top-left corner (x, y) at this point of the script
+    AssertScreen {
+        x: usize,
+        y: usize,
+        pixels: Pixels,
+    },
+}
+
+/// The pixels expected of a region of the screen
+#[derive(Debug, Clone, PartialEq)]
+enum Pixels {
+    /// Rows of pixels, each true when black
+    Rows(Vec<Vec<bool>>),
+    /// A reference image, as a .pbm file
+    Image(String),
+}
+
+/// Something a script can read or set
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Variable {
+    Ram(u16),
+    /// An entry of the segment whose base address is held in RAM[pointer], e.g. `local[2]`
+    Segment {
+        pointer: u16,
+        index: u16,
+    },
+    A,
+    D,
+    Pc,
+    /// The number of instructions or VM commands run
+    Time,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+struct Condition {
+    variable: Variable,
+    comparison: Comparison,
+    value: u16,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Comparison {
+    Equal,
+    NotEqual,
+    Less,
+    Greater,
+    LessOrEqual,
+    GreaterOrEqual,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+struct Column {
+    name: String,
+    variable: Variable,
+    format: Format,
+}
+
+/// How a column is written: `%D1.6.1` is a decimal value right-aligned in 6 characters, with a
+/// space either side
+#[derive(Debug, Clone, Copy, PartialEq)]
+struct Format {
+    /// B, D, X or S for binary, decimal, hex or text
+    radix: char,
+    left: usize,
+    width: usize,
+    right: usize,
+}
+
+enum Machine {
+    Empty,
+    Cpu(Cpu),
+    Vm(Box<VmMachine>),
+}
+
+/// Run a test script. Returns the number of output lines compared, which is 0 without a
+/// compare-to command.
+///
+/// Files are found relative to the script. The .out file is written as far as the script got,
+/// even when a comparison fails. `with_os` links the built-in OS into VM programs.
+pub fn run_test_script(path: &Path, with_os: bool) -> Result<usize, ErrorType> {
+    let contents = fs::read_to_string(path).map_err(|source| ErrorType::ReadError {
+        path: path.to_owned(),
+        source,
+    })?;
+    let lines = parse_script(&contents).map_err(|(line, message)| ErrorType::TestScriptError {
+        path: path.to_owned(),
+        line,
+        message,
+    })?;
+
+    let mut runner = Runner {
+        script: path,
+        dir: path
+            .parent()
+            .filter(|dir| !dir.as_os_str().is_empty())
+            .unwrap_or(Path::new(".")),
+        with_os,
+        machine: Machine::Empty,
+        columns: Vec::new(),
+        output_file: None,
+        output: String::new(),
+        compare_to: None,
+        compared: 0,
+    };
+    let result = runner.run(&lines);
+    if let Some(output_file) = &runner.output_file {
+        write_atomic(output_file, runner.output.as_bytes()).map_err(|source| {
+            ErrorType::WriteError {
+                path: output_file.clone(),
+                source,
+            }
+        })?;
+    }
+    result.map(|_| runner.compared)
+}
+
+struct Runner<'a> {
+    script: &'a Path,
+    dir: &'a Path,
+    with_os: bool,
+    machine: Machine,
+    columns: Vec<Column>,
+    output_file: Option<PathBuf>,
+    output: String,
+    /// The lines of the .cmp file
+    compare_to: Option<Vec<String>>,
+    compared: usize,
+}
+
+impl Runner<'_> {
+    fn run(&mut self, lines: &[Line]) -> Result<(), ErrorType> {
+        for line in lines {
+            self.execute(line)?;
+        }
+        Ok(())
+    }
+
+    fn execute(&mut self, line: &Line) -> Result<(), ErrorType> {
+        match &line.statement {
+            Statement::Load(file) => {
+                let path = match file {
+                    Some(file) => self.dir.join(file),
+                    None => self.dir.to_owned(),
+                };
+                let extension = path.extension().and_then(|extension| extension.to_str());
+                self.machine = match extension {
+                    Some("hack" | "asm") => Machine::Cpu(load_banks(&[&path])?),
+                    _ if extension == Some("vm") || path.is_dir() => {
+                        Machine::Vm(Box::new(load_vm(&path, self.with_os)?))
+                    }
+                    _ => {
+                        let message = format!("can't load {}", path.display());
+                        return Err(self.error(line, message));
+                    }
+                };
+            }
+            Statement::OutputFile(file) => self.output_file = Some(self.dir.join(file)),
+            Statement::CompareTo(file) => {
+                let path = self.dir.join(file);
+                let contents = fs::read_to_string(&path)
+                    .map_err(|source| ErrorType::ReadError { path, source })?;
+                self.compare_to = Some(contents.lines().map(str::to_owned).collect());
+            }
+            Statement::OutputList(columns) => {
+                self.columns = columns.clone();
+                let header = columns.iter().map(|column| {
+                    let width = column.format.left + column.format.width + column.format.right;
+                    let name: String = column.name.chars().take(width).collect();
+                    let left = (width - name.chars().count()) / 2;
+                    format!("{:left$}{:<rest$}", "", name, rest = width - left)
+                });
+                self.emit(header.collect())?;
+            }
+            Statement::Set(variable, value) => self.set(line, *variable, *value)?,
+            Statement::Repeat(count, body) => {
+                for _ in 0..*count {
+                    self.run(body)?;
+                }
+            }
+            Statement::While(condition, body) => {
+                while condition.holds(self.get(line, condition.variable)?) {
+                    self.run(body)?;
+                }
+            }
+            Statement::Tick => {}
+            Statement::Tock | Statement::TickTock => match &mut self.machine {
+                Machine::Cpu(cpu) => {
+                    cpu.step()?;
+                }
+                _ => return Err(self.error(line, "ticktock needs a .hack or .asm program")),
+            },
+            Statement::VmStep => match &mut self.machine {
+                Machine::Vm(vm) => {
+                    vm.step()?;
+                }
+                _ => return Err(self.error(line, "vmstep needs a VM program")),
+            },
+            Statement::Output => {
+                if self.columns.is_empty() {
+                    return Err(self.error(line, "output needs an output-list first"));
+                }
+                let mut cells = Vec::with_capacity(self.columns.len());
+                for column in &self.columns {
+                    let format = column.format;
+                    let value = format.value(self.get(line, column.variable)?);
+                    cells.push(format!(
+                        "{:left$}{:>width$}{:right$}",
+                        "",
+                        value,
+                        "",
+                        left = format.left,
+                        width = format.width,
+                        right = format.right
+                    ));
+                }
+                self.emit(cells)?;
+            }
+            Statement::Echo(message) => println!("{}", message),
+            Statement::ClearEcho => {}
+            Statement::AssertScreen { x, y, pixels } => {
+                let rows = match pixels {
+                    Pixels::Rows(rows) => rows.clone(),
+                    Pixels::Image(file) => {
+                        let path = self.dir.join(file);
+                        let image = fs::read(&path)
+                            .map_err(|source| ErrorType::ReadError { path, source })?;
+                        parse_pbm(&image).ok_or_else(|| {
+                            self.error(line, format!("{} is not a binary .pbm image", file))
+                        })?
+                    }
+                };
+                self.assert_screen(line, *x, *y, &rows)?;
+            }
+        }
+        Ok(())
+    }
+
+    fn assert_screen(
+        &self,
+        line: &Line,
+        x: usize,
+        y: usize,
+        rows: &[Vec<bool>],
+    ) -> Result<(), ErrorType> {
+        if matches!(self.machine, Machine::Empty) {
+            return Err(self.error(line, "no program has been loaded"));
+        }
+        let width = rows.first().map_or(0, Vec::len);
+        if x + width > SCREEN_WIDTH || y + rows.len() > SCREEN_HEIGHT {
+            return Err(self.error(line, "the region doesn't fit on the screen"));
+        }
+        for (row, pixels) in rows.iter().enumerate() {
+            for (column, expected) in pixels.iter().enumerate() {
+                let (x, y) = (x + column, y + row);
+                let word = self
+                    .machine
+                    .peek(SCREEN + (y * SCREEN_WIDTH / 16 + x / 16) as u16);
+                if ((word >> (x % 16)) & 1 == 1) != *expected {
+                    let colour = |black| if black { "black" } else { "white" };
+                    let message = format!(
+                        "the pixel at ({}, {}) is {} but should be {}",
+                        x,
+                        y,
+                        colour(!expected),
+                        colour(*expected)
+                    );
+                    return Err(self.error(line, message));
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Add a row to the output, comparing it with the .cmp file
+    fn emit(&mut self, cells: Vec<String>) -> Result<(), ErrorType> {
+        let row = format!("|{}|", cells.join("|"));
+        self.output.push_str(&row);
+        self.output.push('\n');
+        if let Some(expected) = &self.compare_to {
+            let line = self.compared + 1;
+            let expected = expected
+                .get(self.compared)
+                .map_or("", |line| line.trim_end());
+            if expected != row {
+                return Err(ErrorType::ComparisonFailure {
+                    line,
+                    expected: expected.to_owned(),
+                    actual: row,
+                });
+            }
+            self.compared = line;
+        }
+        Ok(())
+    }
+
+    fn get(&self, line: &Line, variable: Variable) -> Result<u16, ErrorType> {
+        let value = match (&self.machine, variable) {
+            (Machine::Empty, _) => return Err(self.error(line, "no program has been loaded")),
+            (Machine::Cpu(cpu), Variable::A) => cpu.a(),
+            (Machine::Cpu(cpu), Variable::D) => cpu.d(),
+            (Machine::Cpu(cpu), Variable::Pc) => cpu.pc(),
+            (Machine::Cpu(cpu), Variable::Time) => cpu.cycles() as u16,
+            (Machine::Vm(vm), Variable::Time) => vm.cycles() as u16,
+            (Machine::Vm(_), Variable::A | Variable::D | Variable::Pc) => {
+                return Err(self.error(line, "A, D and PC need a .hack or .asm program"))
+            }
+            (machine, Variable::Ram(address)) => machine.peek(address),
+            (machine, Variable::Segment { pointer, index }) => {
+                machine.peek(machine.peek(pointer).wrapping_add(index))
+            }
+        };
+        Ok(value)
+    }
+
+    fn set(&mut self, line: &Line, variable: Variable, value: u16) -> Result<(), ErrorType> {
+        match (&mut self.machine, variable) {
+            (Machine::Empty, _) => return Err(self.error(line, "no program has been loaded")),
+            (Machine::Cpu(cpu), Variable::A) => cpu.set_a(value),
+            (Machine::Cpu(cpu), Variable::D) => cpu.set_d(value),
+            (Machine::Cpu(cpu), Variable::Pc) => cpu.jump(value),
+            (_, Variable::Time) => return Err(self.error(line, "time can't be set")),
+            (Machine::Vm(_), Variable::A | Variable::D | Variable::Pc) => {
+                return Err(self.error(line, "A, D and PC need a .hack or .asm program"))
+            }
+            (machine, Variable::Ram(address)) => machine.poke(address, value),
+            (machine, Variable::Segment { pointer, index }) => {
+                let address = machine.peek(pointer).wrapping_add(index);
+                machine.poke(address, value);
+            }
+        }
+        Ok(())
+    }
+
+    fn error(&self, line: &Line, message: impl Into<String>) -> ErrorType {
+        ErrorType::TestScriptError {
+            path: self.script.to_owned(),
+            line: line.number,
+            message: message.into(),
+        }
+    }
+}
+
+impl Machine {
+    fn peek(&self, address: u16) -> u16 {
+        match self {
+            Machine::Empty => 0,
+            Machine::Cpu(cpu) => cpu.peek(address),
+            Machine::Vm(vm) => vm.peek(address),
+        }
+    }
+
+    fn poke(&mut self, address: u16, value: u16) {
+        match self {
+            Machine::Empty => {}
+            Machine::Cpu(cpu) => cpu.poke(address, value),
+            Machine::Vm(vm) => vm.poke(address, value),
+        }
+    }
+}
+
+impl Condition {
+    fn holds(&self, value: u16) -> bool {
+        let (value, target) = (value as i16, self.value as i16);
+        match self.comparison {
+            Comparison::Equal => value == target,
+            Comparison::NotEqual => value != target,
+            Comparison::Less => value < target,
+            Comparison::Greater => value > target,
+            Comparison::LessOrEqual => value <= target,
+            Comparison::GreaterOrEqual => value >= target,
+        }
+    }
+}
+
+impl Format {
+    fn value(&self, value: u16) -> String {
+        let digits = match self.radix {
+            'B' => format!("{:016b}", value),
+            'X' => format!("{:04X}", value),
+            _ => return (value as i16).to_string(),
+        };
+        // Narrower columns show the low digits
+        digits[digits.len().saturating_sub(self.width)..].to_owned()
+    }
+}
+
+/// Parse a script into its commands, or the line and description of the first error
+fn parse_script(text: &str) -> Result<Vec<Line>, (usize, String)> {
+    let mut tokens = tokenize(text)?.into_iter().peekable();
+    parse_block(&mut tokens, None)
+}
+
+fn tokenize(text: &str) -> Result<Vec<(usize, Token)>, (usize, String)> {
+    let mut tokens = Vec::new();
+    let mut line = 1;
+    let mut chars = text.chars().peekable();
+    while let Some(c) = chars.next() {
+        match c {
+            '\n' => line += 1,
+            c if c.is_whitespace() => {}
+            '/' if chars.peek() == Some(&'/') => while chars.next_if(|c| *c != '\n').is_some() {},
+            '/' if chars.peek() == Some(&'*') => {
+                chars.next();
+                let mut last = ' ';
+                loop {
+                    match chars.next() {
+                        Some('/') if last == '*' => break,
+                        Some(c) => {
+                            line += (c == '\n') as usize;
+                            last = c;
+                        }
+                        None => return Err((line, "unterminated comment".to_owned())),
+                    }
+                }
+            }
+            ',' | ';' | '!' => tokens.push((line, Token::Separator)),
+            '{' => tokens.push((line, Token::Open)),
+            '}' => tokens.push((line, Token::Close)),
+            '"' => {
+                let mut text = String::new();
+                loop {
+                    match chars.next() {
+                        Some('"') => break,
+                        Some('\n') | None => return Err((line, "unterminated string".to_owned())),
+                        Some(c) => text.push(c),
+                    }
+                }
+                tokens.push((line, Token::Text(text)));
+            }
+            c => {
+                let mut word = c.to_string();
+                while let Some(c) = chars.next_if(|c| !c.is_whitespace() && !",;!{}\"".contains(*c))
+                {
+                    word.push(c);
+                }
+                tokens.push((line, Token::Word(word)));
+            }
+        }
+    }
+    Ok(tokens)
+}
+
+type Tokens = Peekable<IntoIter<(usize, Token)>>;
+
+/// Parse commands up to the end of the script, or to the `}` closing a block opened on `opened`
+fn parse_block(tokens: &mut Tokens, opened: Option<usize>) -> Result<Vec<Line>, (usize, String)> {
+    let mut lines = Vec::new();
+    loop {
+        match tokens.next() {
+            None => match opened {
+                Some(line) => return Err((line, "this block has no closing }".to_owned())),
+                None => return Ok(lines),
+            },
+            Some((_, Token::Close)) if opened.is_some() => return Ok(lines),
+            Some((_, Token::Separator)) => {}
+            Some((number, Token::Word(command))) => {
+                let statement = parse_command(number, &command, tokens)?;
+                lines.push(Line { number, statement });
+            }
+            Some((number, token)) => return Err((number, format!("unexpected {:?}", token))),
+        }
+    }
+}
+
+fn parse_command(
+    line: usize,
+    command: &str,
+    tokens: &mut Tokens,
+) -> Result<Statement, (usize, String)> {
+    let mut arguments = Vec::new();
+    while let Some((_, token)) =
+        tokens.next_if(|(_, token)| matches!(token, Token::Word(_) | Token::Text(_)))
+    {
+        arguments.push(token);
+    }
+    let words: Vec<&str> = arguments
+        .iter()
+        .filter_map(|token| match token {
+            Token::Word(word) => Some(word.as_str()),
+            _ => None,
+        })
+        .collect();
+    let error = |message: String| Err((line, message));
+    if words.len() != arguments.len() && command != "echo" {
+        return error(format!("{} doesn't take text", command));
+    }
+
+    let statement = match (command, words.as_slice()) {
+        ("load", []) => Statement::Load(None),
+        ("load", [file]) => Statement::Load(Some(file.to_string())),
+        ("output-file", [file]) => Statement::OutputFile(file.to_string()),
+        ("compare-to", [file]) => Statement::CompareTo(file.to_string()),
+        ("output-list", columns) if !columns.is_empty() => Statement::OutputList(
+            columns
+                .iter()
+                .map(|column| parse_column(column).map_err(|message| (line, message)))
+                .collect::<Result<_, _>>()?,
+        ),
+        ("set", [variable, value]) => Statement::Set(
+            parse_variable(variable).map_err(|message| (line, message))?,
+            parse_value(value).ok_or_else(|| (line, format!("{} is not a value", value)))?,
+        ),
+        ("repeat", [count]) => {
+            let Ok(count) = count.parse() else {
+                return error(format!("{} is not a number of repeats", count));
+            };
+            Statement::Repeat(count, parse_body(line, tokens)?)
+        }
+        ("while", [variable, comparison, value]) => {
+            let comparison = match *comparison {
+                "=" => Comparison::Equal,
+                "<>" => Comparison::NotEqual,
+                "<" => Comparison::Less,
+                ">" => Comparison::Greater,
+                "<=" => Comparison::LessOrEqual,
+                ">=" => Comparison::GreaterOrEqual,
+                _ => return error(format!("{} is not a comparison", comparison)),
+            };
+            let condition = Condition {
+                variable: parse_variable(variable).map_err(|message| (line, message))?,
+                comparison,
+                value: parse_value(value)
+                    .ok_or_else(|| (line, format!("{} is not a value", value)))?,
+            };
+            Statement::While(condition, parse_body(line, tokens)?)
+        }
+        ("tick", []) => Statement::Tick,
+        ("tock", []) => Statement::Tock,
+        ("ticktock", []) => Statement::TickTock,
+        ("vmstep", []) => Statement::VmStep,
+        ("output", []) => Statement::Output,
+        ("clear-echo", []) => Statement::ClearEcho,
+        ("echo", []) => match arguments.as_slice() {
+            [Token::Text(message)] => Statement::Echo(message.clone()),
+            _ => return error("echo takes a \"message\"".to_owned()),
+        },
+        ("assert-screen", [x, y, pixels]) => {
+            let (Ok(x), Ok(y)) = (x.parse(), y.parse()) else {
+                return error(format!("{} {} is not a point on the screen", x, y));
+            };
+            let pixels = if pixels.ends_with(".pbm") {
+                Pixels::Image(pixels.to_string())
+            } else {
+                Pixels::Rows(parse_rows(pixels).map_err(|message| (line, message))?)
+            };
+            Statement::AssertScreen { x, y, pixels }
+        }
+        (
+            "load" | "output-file" | "compare-to" | "output-list" | "set" | "repeat" | "while"
+            | "tick" | "tock" | "ticktock" | "vmstep" | "output" | "clear-echo" | "echo"
+            | "assert-screen",
+            _,
+        ) => return error(format!("wrong arguments for {}", command)),
+        _ => return error(format!("{} is not a command", command)),
+    };
+    Ok(statement)
+}
+
+fn parse_body(line: usize, tokens: &mut Tokens) -> Result<Vec<Line>, (usize, String)> {
+    match tokens.next() {
+        Some((opened, Token::Open)) => parse_block(tokens, Some(opened)),
+        _ => Err((line, "expected { after the condition".to_owned())),
+    }
+}
+
+/// `name%D1.6.1`, where the format is optional
+fn parse_column(text: &str) -> Result<Column, String> {
+    let (name, format) = match text.split_once('%') {
+        Some((name, format)) => {
+            let invalid = || format!("{} is not a column format", format);
+            let mut chars = format.chars();
+            let radix = chars
+                .next()
+                .filter(|radix| "BDXS".contains(*radix))
+                .ok_or_else(invalid)?;
+            let sizes: Vec<usize> = chars
+                .as_str()
+                .split('.')
+                .map(|size| size.parse().map_err(|_| invalid()))
+                .collect::<Result<_, _>>()?;
+            let [left, width, right] = sizes[..] else {
+                return Err(invalid());
+            };
+            let format = Format {
+                radix,
+                left,
+                width,
+                right,
+            };
+            (name, format)
+        }
+        None => (text, DEFAULT_FORMAT),
+    };
+    Ok(Column {
+        name: name.to_owned(),
+        variable: parse_variable(name)?,
+        format,
+    })
+}
+
+fn parse_variable(name: &str) -> Result<Variable, String> {
+    let unknown = || format!("{} is not a variable", name);
+    let register = |name: &str| match name {
+        "sp" => Some(0),
+        "local" => Some(1),
+        "argument" => Some(2),
+        "this" => Some(3),
+        "that" => Some(4),
+        _ => None,
+    };
+    let variable = match name {
+        "A" => Variable::A,
+        "D" => Variable::D,
+        "PC" => Variable::Pc,
+        "time" => Variable::Time,
+        _ => match name.strip_suffix(']').and_then(|name| name.split_once('[')) {
+            Some((array, index)) => {
+                let index: u16 = index.parse().map_err(|_| unknown())?;
+                match array {
+                    "RAM" => Variable::Ram(index),
+                    "temp" if index < 8 => Variable::Ram(5 + index),
+                    _ => Variable::Segment {
+                        pointer: register(array)
+                            .filter(|_| array != "sp")
+                            .ok_or_else(unknown)?,
+                        index,
+                    },
+                }
+            }
+            None => Variable::Ram(register(name).ok_or_else(unknown)?),
+        },
+    };
+    Ok(variable)
+}
+
+/// Rows of pixels separated by `/`, with 1 for black and 0 for white, e.g. `0110/1001`
+fn parse_rows(text: &str) -> Result<Vec<Vec<bool>>, String> {
+    let rows: Vec<Vec<bool>> = text
+        .split('/')
+        .map(|row| {
+            row.chars()
+                .map(|pixel| match pixel {
+                    '0' => Ok(false),
+                    '1' => Ok(true),
+                    _ => Err(format!("{} is not a row of 0 and 1 pixels", row)),
+                })
+                .collect()
+        })
+        .collect::<Result<_, _>>()?;
+    if rows
+        .iter()
+        .any(|row| row.is_empty() || row.len() != rows[0].len())
+    {
+        return Err(format!("the rows of {} aren't all the same width", text));
+    }
+    Ok(rows)
+}
+
+/// The rows of pixels of a binary (P4) .pbm image, as written by `screen_to_pbm`
+fn parse_pbm(image: &[u8]) -> Option<Vec<Vec<bool>>> {
+    // The header is the magic number, width and height, each followed by a single whitespace
+    let mut fields = Vec::new();
+    let mut start = 0;
+    while fields.len() < 3 {
+        let end = start
+            + image
+                .get(start..)?
+                .iter()
+                .position(u8::is_ascii_whitespace)?;
+        fields.push(std::str::from_utf8(&image[start..end]).ok()?);
+        start = end + 1;
+    }
+    let (width, height): (usize, usize) = match fields[..] {
+        ["P4", width, height] => (width.parse().ok()?, height.parse().ok()?),
+        _ => return None,
+    };
+    let row_bytes = width.div_ceil(8);
+    let data = image.get(start..start + row_bytes * height)?;
+    let rows = data
+        .chunks(row_bytes)
+        .map(|row| {
+            (0..width)
+                .map(|x| row[x / 8] & (0x80 >> (x % 8)) != 0)
+                .collect()
+        })
+        .collect();
+    Some(rows)
+}
+
+/// A decimal value, or one in binary, decimal or hex after `%B`, `%D` or `%X`
+fn parse_value(text: &str) -> Option<u16> {
+    let (digits, radix) = match text.get(..2) {
+        Some("%B") => (&text[2..], 2),
+        Some("%X") => (&text[2..], 16),
+        Some("%D") => (&text[2..], 10),
+        _ => (text, 10),
+    };
+    if radix != 10 {
+        return u16::from_str_radix(digits, radix).ok();
+    }
+    digits
+        .parse::<i16>()
+        .map(|value| value as u16)
+        .or_else(|_| digits.parse::<u16>())
+        .ok()
+}
+
+#[test]
+fn test_parse_script() {
+    let script = "// Adds RAM[0] to RAM[1]
+load Add.asm,
+output-file Add.out,
+compare-to Add.cmp,
+output-list RAM[0]%D2.6.2 local[1] time%X1.4.1;
+
+set RAM[0] %X10, /* multi
+line */ set sp -1;
+repeat 3 {
+  ticktock;
+}
+while RAM[0] <> 0 { vmstep; }
+echo \"done\";";
+    let lines = parse_script(script).unwrap();
+    let statements: Vec<&Statement> = lines.iter().map(|line| &line.statement).collect();
+    assert_eq!(statements[0], &Statement::Load(Some("Add.asm".to_owned())));
+    assert_eq!(
+        statements[3],
+        &Statement::OutputList(vec![
+            Column {
+                name: "RAM[0]".to_owned(),
+                variable: Variable::Ram(0),
+                format: Format {
+                    radix: 'D',
+                    left: 2,
+                    width: 6,
+                    right: 2
+                }
+            },
+            Column {
+                name: "local[1]".to_owned(),
+                variable: Variable::Segment {
+                    pointer: 1,
+                    index: 1
+                },
+                format: DEFAULT_FORMAT
+            },
+            Column {
+                name: "time".to_owned(),
+                variable: Variable::Time,
+                format: Format {
+                    radix: 'X',
+                    left: 1,
+                    width: 4,
+                    right: 1
+                }
+            },
+        ])
+    );
+    assert_eq!(statements[4], &Statement::Set(Variable::Ram(0), 16));
+    assert_eq!(statements[5], &Statement::Set(Variable::Ram(0), 0xFFFF));
+    assert_eq!(lines[5].number, 8);
+    assert_eq!(
+        statements[6],
+        &Statement::Repeat(
+            3,
+            vec![Line {
+                number: 10,
+                statement: Statement::TickTock
+            }]
+        )
+    );
+    assert!(matches!(statements[7], Statement::While(..)));
+    assert_eq!(statements[8], &Statement::Echo("done".to_owned()));
+
+    assert_eq!(
+        parse_script("repeat 3 { ticktock;"),
+        Err((1, "this block has no closing }".to_owned()))
+    );
+    assert_eq!(
+        parse_script("set\nRAM[0] 1;\nfly;"),
+        Err((3, "fly is not a command".to_owned()))
+    );
+    assert_eq!(
+        parse_script("assert-screen 3 4 011/100;").unwrap()[0].statement,
+        Statement::AssertScreen {
+            x: 3,
+            y: 4,
+            pixels: Pixels::Rows(vec![vec![false, true, true], vec![true, false, false]])
+        }
+    );
+    assert_eq!(
+        parse_script("assert-screen 0 0 01/1;"),
+        Err((1, "the rows of 01/1 aren't all the same width".to_owned()))
+    );
+}
+
+#[test]
+fn test_run_test_script() {
+    let dir = std::env::temp_dir().join(format!("emulator-test-script-{}", std::process::id()));
+    fs::create_dir_all(&dir).unwrap();
+    fs::write(dir.join("Add.asm"), "@0\nD=M\n@1\nD=D+M\n@2\nM=D\n").unwrap();
+    fs::write(
+        dir.join("Add.tst"),
+        "load Add.asm,
+output-file Add.out,
+compare-to Add.cmp,
+output-list RAM[0]%D2.6.2 RAM[2]%D2.6.2 RAM[2]%B1.16.1;
+set RAM[0] 2, set RAM[1] -5;
+repeat 6 { ticktock; }
+output;
+",
+    )
+    .unwrap();
+    let expected = "|  RAM[0]  |  RAM[2]  |      RAM[2]      |
+|       2  |      -3  | 1111111111111101 |
+";
+    fs::write(dir.join("Add.cmp"), expected).unwrap();
+    assert_eq!(run_test_script(&dir.join("Add.tst"), false).unwrap(), 2);
+    assert_eq!(fs::read_to_string(dir.join("Add.out")).unwrap(), expected);
+
+    fs::write(dir.join("Add.cmp"), expected.replace("-3", "-4")).unwrap();
+    assert!(matches!(
+        run_test_script(&dir.join("Add.tst"), false),
+        Err(ErrorType::ComparisonFailure { line: 2, .. })
+    ));
+
+    // VM programs are loaded from the script's directory
+    fs::write(dir.join("Basic.vm"), "push constant 7\npop local 2\n").unwrap();
+    fs::write(
+        dir.join("Basic.tst"),
+        "load Basic.vm,
+output-file Basic.out,
+output-list local[2];
+set local 300,
+repeat 2 { vmstep; }
+output;
+",
+    )
+    .unwrap();
+    assert_eq!(run_test_script(&dir.join("Basic.tst"), false).unwrap(), 0);
+    assert_eq!(
+        fs::read_to_string(dir.join("Basic.out")).unwrap(),
+        "|local[2]|\n|      7 |\n"
+    );
+
+    // The first 16 pixels of the screen are drawn black
+    fs::write(dir.join("Fill.asm"), "@SCREEN\nM=-1\n").unwrap();
+    fs::write(dir.join("Row.pbm"), b"P4\n3 1\n\xe0").unwrap();
+    fs::write(
+        dir.join("Fill.tst"),
+        "load Fill.asm,
+assert-screen 0 0 0;
+repeat 2 { ticktock; }
+assert-screen 14 0 110/000,
+assert-screen 13 0 Row.pbm;
+assert-screen 15 0 1/1;
+",
+    )
+    .unwrap();
+    match run_test_script(&dir.join("Fill.tst"), false) {
+        Err(ErrorType::TestScriptError { line, message, .. }) => {
+            assert_eq!(line, 6);
+            assert_eq!(message, "the pixel at (15, 1) is white but should be black");
+        }
+        result => panic!("expected the last assertion to fail, got {:?}", result),
+    }
+    fs::remove_dir_all(&dir).unwrap();
+}
@@ -0,0 +1,84 @@
+use std::io::Write;
+
+use crate::{Cpu, ErrorType, Stop};
+
+/// Run like [`Cpu::run`], writing a line for each cycle: its number, the address of the
+/// instruction, A and D once it has run, and what it wrote to RAM. Values are signed, as the
+/// official CPUEmulator shows them.
+///
+/// With `ranges`, inclusive ranges of RAM addresses, only the cycles which write to them are
+/// written out.
+pub fn run_with_trace(
+    cpu: &mut Cpu,
+    max_cycles: u64,
+    ranges: &[(u16, u16)],
+    out: &mut impl Write,
+) -> Result<Stop, ErrorType> {
+    for _ in 0..max_cycles {
+        let pc = cpu.pc();
+        if let Some(stop) = cpu.step()? {
+            return Ok(stop);
+        }
+        let write = cpu.last_write();
+        let wanted = ranges.is_empty()
+            || write.is_some_and(|(address, _)| {
+                ranges
+                    .iter()
+                    .any(|(start, end)| (*start..=*end).contains(&address))
+            });
+        if !wanted {
+            continue;
+        }
+
+        let mut line = format!(
+            "{:>8}  PC={:<5}  A={:<6}  D={:<6}",
+            cpu.cycles(),
+            pc,
+            cpu.a() as i16,
+            cpu.d() as i16
+        );
+        if let Some((address, value)) = write {
+            line.push_str(&format!("  RAM[{}]={}", address, value as i16));
+        }
+        writeln!(out, "{}", line.trim_end()).map_err(ErrorType::TraceOutput)?;
+    }
+    Ok(Stop::CycleLimit)
+}
+
+#[test]
+fn test_trace() {
+    // RAM[16] = RAM[0] - 1, then halt
+    let program = crate::parse_hack(
+        "0000000000000000
+        1111110000010000
+        0000000000010000
+        1110001110001000
+        0000000000000100
+        1110101010000111",
+    )
+    .unwrap();
+    let mut cpu = Cpu::new(program.clone());
+    cpu.poke(0, 5);
+    let mut out = Vec::new();
+    assert_eq!(
+        run_with_trace(&mut cpu, 100, &[], &mut out).unwrap(),
+        Stop::Halted
+    );
+    let trace = String::from_utf8(out).unwrap();
+    let lines: Vec<&str> = trace.lines().collect();
+    assert_eq!(lines.len(), 5);
+    assert_eq!(lines[0], "       1  PC=0      A=0       D=0");
+    assert_eq!(lines[1], "       2  PC=1      A=0       D=5");
+    assert_eq!(
+        lines[3],
+        "       4  PC=3      A=16      D=5       RAM[16]=4"
+    );
+
+    let mut cpu = Cpu::new(program);
+    let mut out = Vec::new();
+    run_with_trace(&mut cpu, 100, &[(16, 20)], &mut out).unwrap();
+    assert_eq!(
+        String::from_utf8(out).unwrap(),
+        "       4  PC=3      A=16      D=0       RAM[16]=-1\n"
+    );
+}
@@ -0,0 +1,317 @@
+//! Checking the VM translator by running each function both as VM commands and as the assembly
+//! they're translated to, for `n2t translate --verify`.
+
+use std::collections::BTreeSet;
+use std::fmt;
+
+use vm_translator::ast::{MemorySegment, Operation};
+use vm_translator::{Bootstrap, TranslationOptions};
+
+use crate::{parse_hack, Cpu, ErrorType, VmMachine, MEMORY_SIZE, STACK_BASE};
+
+/// Commands run of each function before it's taken to match
+const MAX_COMMANDS: usize = 10_000;
+/// Instructions the translation of a single command may take, including a call's jump
+const MAX_INSTRUCTIONS: usize = 1_000;
+/// The words up to the temp segment, which both machines use the same way. R13 to R15 are the
+/// translator's scratch registers, which the VM doesn't have.
+const REGISTERS: [&str; 13] = [
+    "SP", "LCL", "ARG", "THIS", "THAT", "temp 0", "temp 1", "temp 2", "temp 3", "temp 4", "temp 5",
+    "temp 6", "temp 7",
+];
+/// Where the heap starts, after the stack
+const HEAP_BASE: usize = 2048;
+/// Synthetic values are picked from the heap, so that those used as pointers stay out of the
+/// registers, statics and stack
+const SYNTHETIC_VALUES: std::ops::Range<u16> = 2048..8192;
+
+/// How a function ran once translated
+#[derive(Debug, Clone, PartialEq)]
+pub enum Outcome {
+    /// Every command left memory as the VM does, until the function returned or halted, or
+    /// [`MAX_COMMANDS`] had run
+    Matched,
+    /// The first command after which memory differed
+    Diverged { command: String, detail: String },
+    /// The function couldn't be run with synthetic inputs, e.g. because it calls a function which
+    /// isn't part of the program
+    Skipped(String),
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct FunctionCheck {
+    pub function: String,
+    pub outcome: Outcome,
+}
+
+impl fmt::Display for FunctionCheck {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match &self.outcome {
+            Outcome::Matched => write!(f, "{}: ok", self.function),
+            Outcome::Diverged { command, detail } => {
+                write!(f, "{}: after `{}`, {}", self.function, command, detail)
+            }
+            Outcome::Skipped(reason) => write!(f, "{}: skipped, {}", self.function, reason),
+        }
+    }
+}
+
+/// Run every function of a program on the VM emulator and, translated and assembled, on the CPU
+/// emulator, comparing memory after each command.
+///
+/// Both machines start a function from the same synthetic memory and arguments. The program is
+/// translated without optimizations, as they leave the stack different between some commands.
+pub fn verify_translation(sources: &[(&str, &str)]) -> Result<Vec<FunctionCheck>, ErrorType> {
+    let options = TranslationOptions {
+        bootstrap: Bootstrap::None,
+        no_halt: true,
+        ..Default::default()
+    };
+    let asm = vm_translator::translate_program_with_options(sources, &options)?;
+    let addresses = vm_translator::command_addresses(&asm, sources);
+    let (hack, symbol_map) = assembler::assemble_string_with_symbols(&asm)?;
+    let rom = parse_hack(&hack)?;
+    let vm = VmMachine::load(sources)?;
+
+    // Each static as the VM lays it out, paired with the address the assembler gave it
+    let mut statics = Vec::new();
+    for (file, _) in sources {
+        let prefix = format!("{}.", file);
+        for (name, &address) in &symbol_map.variables {
+            let index = name
+                .strip_prefix(&prefix)
+                .and_then(|index| index.parse().ok());
+            if let Some(vm_address) = index.and_then(|index| vm.static_address(file, index)) {
+                statics.push((vm_address, address as usize));
+            }
+        }
+    }
+
+    let mut checks = Vec::new();
+    let mut start = 0;
+    for (file, contents) in sources {
+        let statements =
+            vm_translator::parse_vm(contents).map_err(|message| ErrorType::VmParsingError {
+                file: file.to_string(),
+                message,
+            })?;
+        for (offset, statement) in statements.iter().enumerate() {
+            let Operation::Function(function) = &statement.operation else {
+                continue;
+            };
+            let body = statements[offset + 1..]
+                .iter()
+                .take_while(|statement| !matches!(statement.operation, Operation::Function(_)));
+            let run = Run {
+                vm: vm.clone(),
+                cpu: Cpu::new(rom.clone()),
+                addresses: &addresses,
+                statics: &statics,
+                end: rom.len(),
+            };
+            checks.push(FunctionCheck {
+                function: function.name.clone(),
+                outcome: run.check(&function.name, start + offset, arguments_used(body)),
+            });
+        }
+        start += statements.len();
+    }
+    Ok(checks)
+}
+
+/// The number of arguments a function reads or writes
+fn arguments_used<'a>(body: impl Iterator<Item = &'a vm_translator::ast::Stmt>) -> usize {
+    body.filter_map(|statement| match &statement.operation {
+        Operation::Push(address) | Operation::Pop(address)
+            if address.memory_segment == MemorySegment::Arguments =>
+        {
+            Some(address.address as usize + 1)
+        }
+        _ => None,
+    })
+    .max()
+    .unwrap_or(0)
+}
+
+/// A function being run on both machines
+struct Run<'a> {
+    vm: VmMachine,
+    cpu: Cpu,
+    /// The ROM address of each command
+    addresses: &'a [Option<usize>],
+    /// The address of each static on the VM and once assembled
+    statics: &'a [(usize, usize)],
+    /// The ROM address the outermost call returns to, just past the program
+    end: usize,
+}
+
+impl Run<'_> {
+    fn check(mut self, function: &str, index: usize, num_args: usize) -> Outcome {
+        let Some(entry) = self.addresses[index] else {
+            return Outcome::Skipped("its translation couldn't be found".to_owned());
+        };
+
+        // A simple generator is enough, and gives the same inputs on every run
+        let mut seed: u32 = 0x2545_F491;
+        let mut synthetic = || {
+            seed ^= seed << 13;
+            seed ^= seed >> 17;
+            seed ^= seed << 5;
+            SYNTHETIC_VALUES.start
+                + (seed % (SYNTHETIC_VALUES.end - SYNTHETIC_VALUES.start) as u32) as u16
+        };
+        for address in 1..crate::SCREEN {
+            self.vm.poke(address, synthetic());
+        }
+        let arguments: Vec<u16> = (0..num_args).map(|_| synthetic()).collect();
+        if let Err(error) = self.vm.start_with_arguments(function, &arguments) {
+            return Outcome::Skipped(error.to_string());
+        }
+        for (address, value) in self.vm.ram().iter().enumerate().take(MEMORY_SIZE) {
+            self.cpu.poke(address as u16, *value);
+        }
+        for &(vm_address, cpu_address) in self.statics {
+            self.cpu.poke(cpu_address as u16, self.vm.ram()[vm_address]);
+        }
+        // The return addresses in the frames differ, as one counts commands and one instructions.
+        // They're still there once popped, which matters when the stack has run into the heap.
+        let first_slot = STACK_BASE as usize + num_args;
+        let mut return_slots = BTreeSet::from([first_slot]);
+        self.cpu.poke(first_slot as u16, self.end as u16);
+        self.cpu.jump(entry as u16);
+
+        for _ in 0..MAX_COMMANDS {
+            let command = self.vm.current_command().unwrap_or_default().to_owned();
+            let from = self.address(self.vm.pc());
+            match self.vm.step() {
+                Ok(None) => {}
+                Ok(Some(_)) => return Outcome::Matched,
+                Err(error) => return Outcome::Skipped(error.to_string()),
+            }
+
+            let target = self.address(self.vm.pc());
+            let Some(target) = target else {
+                return Outcome::Skipped(format!(
+                    "the translation of `{}` couldn't be found",
+                    command
+                ));
+            };
+            // A command without instructions, such as a label, is already done
+            if from != Some(target) || self.cpu.pc() as usize != target {
+                if let Err(detail) = self.run_cpu_to(target) {
+                    return Outcome::Diverged { command, detail };
+                }
+            }
+
+            if command.starts_with("call ") {
+                return_slots.insert(self.vm.ram()[1] as usize - 5);
+            }
+            if let Some(detail) = self.difference(&return_slots) {
+                return Outcome::Diverged { command, detail };
+            }
+        }
+        Outcome::Matched
+    }
+
+    /// The ROM address of a command, or of the end of the program once the function has returned
+    fn address(&self, pc: usize) -> Option<usize> {
+        match self.addresses.get(pc) {
+            Some(address) => *address,
+            None => Some(self.end),
+        }
+    }
+
+    fn run_cpu_to(&mut self, target: usize) -> Result<(), String> {
+        for _ in 0..MAX_INSTRUCTIONS {
+            match self.cpu.step() {
+                Ok(None) => {}
+                Ok(Some(stop)) => return Err(format!("the translation stopped: {:?}", stop)),
+                Err(error) => return Err(error.to_string()),
+            }
+            if self.cpu.pc() as usize == target {
+                return Ok(());
+            }
+        }
+        Err(format!(
+            "the translation didn't reach ROM[{}], where the next command starts",
+            target
+        ))
+    }
+
+    /// The first word which differs, other than the return addresses in `return_slots` and the
+    /// stack between SP and the heap
+    fn difference(&self, return_slots: &BTreeSet<usize>) -> Option<String> {
+        let (vm, cpu) = (self.vm.ram(), self.cpu.ram());
+        let differs = |name: String, vm: u16, cpu: u16| {
+            (vm != cpu).then(|| format!("{} is {} but {} once translated", name, vm, cpu))
+        };
+
+        for (address, name) in REGISTERS.iter().enumerate() {
+            if let Some(detail) = differs(name.to_string(), vm[address], cpu[address]) {
+                return Some(detail);
+            }
+        }
+        for &(vm_address, cpu_address) in self.statics {
+            let name = format!("the static at RAM[{}]", vm_address);
+            if let Some(detail) = differs(name, vm[vm_address], cpu[cpu_address]) {
+                return Some(detail);
+            }
+        }
+        // Memory is compared a slice at a time, as this runs after every command. A deep enough
+        // stack runs on into the heap.
+        let unused = vm[0] as usize..HEAP_BASE;
+        let mut start = STACK_BASE as usize;
+        let ends = return_slots.iter().copied().chain([MEMORY_SIZE]);
+        for end in ends {
+            let below = start..end.min(unused.start);
+            let above = start.max(unused.end)..end;
+            for range in [below, above].into_iter().filter(|range| !range.is_empty()) {
+                if vm[range.clone()] != cpu[range.clone()] {
+                    let address = range
+                        .clone()
+                        .find(|&address| vm[address] != cpu[address])
+                        .expect("The slices differ");
+                    return differs(format!("RAM[{}]", address), vm[address], cpu[address]);
+                }
+            }
+            start = end + 1;
+        }
+        None
+    }
+}
+
+#[test]
+fn test_verify_translation() {
+    let main = "function Main.main 2
+push constant 7
+pop local 1
+push argument 1
+push local 1
+call Main.add 2
+pop static 0
+push constant 0
+return
+function Main.add 0
+push argument 0
+push argument 1
+add
+return";
+    let checks = verify_translation(&[("Main.vm", main)]).unwrap();
+    assert_eq!(
+        checks,
+        [
+            FunctionCheck {
+                function: "Main.main".to_owned(),
+                outcome: Outcome::Matched
+            },
+            FunctionCheck {
+                function: "Main.add".to_owned(),
+                outcome: Outcome::Matched
+            },
+        ]
+    );
+
+    let calls_os = "function Main.main 0\ncall Math.abs 0\nreturn";
+    let checks = verify_translation(&[("Main.vm", calls_os)]).unwrap();
+    assert!(matches!(checks[0].outcome, Outcome::Skipped(_)));
+}
@@ -0,0 +1,1152 @@
+use std::collections::{HashMap, VecDeque};
+
+use vm_translator::ast::{Address, MemorySegment, Operation};
+
+use crate::assertions::{self, AssertionFailure};
+use crate::os_compat::{OsCall, OsCompat, PixelBounds, StringOverflow};
+use crate::replay::KeyPress;
+use crate::state::{self, Pointers, VmState};
+use crate::{ErrorType, Stop, KEYBOARD, MEMORY_SIZE, SCREEN, SCREEN_WORDS};
+
+/// Where the stack starts, as set up by the bootstrap code
+pub const STACK_BASE: u16 = 256;
+/// Statics are given addresses from here up, file by file, as the assembler would
+const STATIC_BASE: usize = 16;
+
+const SP: usize = 0;
+const LCL: usize = 1;
+const ARG: usize = 2;
+const THIS: usize = 3;
+const THAT: usize = 4;
+const TEMP: usize = 5;
+
+const SCREEN_WIDTH: i16 = 512;
+const SCREEN_HEIGHT: i16 = 256;
+
+#[derive(Clone)]
+struct Command {
+    operation: Operation,
+    text: String,
+    /// The function the command belongs to, which scopes its labels
+    function: usize,
+    /// The address of static 0 for the file the command came from
+    static_base: usize,
+}
+
+/// What a command changed, so that it can be undone
+#[derive(Clone)]
+struct Delta {
+    pc: usize,
+    cycles: u64,
+    /// Each RAM word the command wrote, with its value beforehand
+    writes: Vec<(usize, u16)>,
+    /// The number of calls in progress beforehand, and the innermost return address
+    return_depth: usize,
+    innermost_return: Option<usize>,
+}
+
+/// Runs VM commands directly, with the segments and stack held in the same 32K of RAM as the Hack
+/// computer so that results can be compared with the translated program
+#[derive(Clone)]
+pub struct VmMachine {
+    commands: Vec<Command>,
+    function_names: Vec<String>,
+    functions: HashMap<String, usize>,
+    /// Labels keyed by function then label name
+    labels: HashMap<(usize, String), usize>,
+    /// The address of static 0 of each file, by file name
+    static_bases: HashMap<String, usize>,
+    /// Each file's name and the index of its first command, in the order they were loaded
+    files: Vec<(String, usize)>,
+    /// One past the address of the last static
+    statics_end: usize,
+    /// The return address of each call in progress. They are also pushed to the stack, but a
+    /// program can have more commands than a RAM word can address.
+    return_addresses: Vec<usize>,
+    ram: Vec<u16>,
+    pc: usize,
+    cycles: u64,
+    /// The most recent commands, oldest first, so they can be stepped back over
+    history: VecDeque<Delta>,
+    history_limit: usize,
+    /// The writes of the command being executed, while history is kept
+    writes: Vec<(usize, u16)>,
+    os_compat: OsCompat,
+    /// Whether calls to the Assert class are checked rather than run
+    check_assertions: bool,
+    assertion_failures: Vec<AssertionFailure>,
+    /// How many times each command has run, once counting has been turned on
+    executions: Option<Vec<u64>>,
+    /// The keys pressed since recording was turned on
+    recording: Option<Vec<KeyPress>>,
+    /// The keys to press as the program runs, and how many of them have been pressed
+    replay: Vec<KeyPress>,
+    replayed: usize,
+}
+
+impl VmMachine {
+    /// Load the .vm files of a program, given as (file name, contents) pairs. If the program has
+    /// Sys.init it is called as the bootstrap code would, otherwise execution starts at the first
+    /// command.
+    pub fn load(sources: &[(&str, &str)]) -> Result<Self, ErrorType> {
+        let mut machine = Self {
+            commands: Vec::new(),
+            function_names: vec![String::new()],
+            functions: HashMap::new(),
+            labels: HashMap::new(),
+            static_bases: HashMap::new(),
+            files: Vec::new(),
+            statics_end: STATIC_BASE,
+            return_addresses: Vec::new(),
+            ram: vec![0; MEMORY_SIZE],
+            pc: 0,
+            cycles: 0,
+            history: VecDeque::new(),
+            history_limit: 0,
+            writes: Vec::new(),
+            os_compat: OsCompat::default(),
+            check_assertions: false,
+            assertion_failures: Vec::new(),
+            executions: None,
+            recording: None,
+            replay: Vec::new(),
+            replayed: 0,
+        };
+
+        let mut static_base = STATIC_BASE;
+        for (file, contents) in sources {
+            machine.static_bases.insert(file.to_string(), static_base);
+            machine
+                .files
+                .push((file.to_string(), machine.commands.len()));
+            let statements =
+                vm_translator::parse_vm(contents).map_err(|message| ErrorType::VmParsingError {
+                    file: file.to_string(),
+                    message,
+                })?;
+
+            let mut function = 0;
+            let mut statics = 0;
+            for statement in statements {
+                match &statement.operation {
+                    Operation::Function(definition) => {
+                        function = machine.function_names.len();
+                        machine.function_names.push(definition.name.clone());
+                        machine
+                            .functions
+                            .insert(definition.name.clone(), machine.commands.len());
+                    }
+                    Operation::Label(label) => {
+                        machine
+                            .labels
+                            .insert((function, label.clone()), machine.commands.len());
+                    }
+                    Operation::Push(address) | Operation::Pop(address)
+                        if address.memory_segment == MemorySegment::Static =>
+                    {
+                        statics = statics.max(address.address as usize + 1);
+                    }
+                    _ => {}
+                }
+                machine.commands.push(Command {
+                    operation: statement.operation,
+                    text: statement.text,
+                    function,
+                    static_base,
+                });
+            }
+            static_base += statics;
+        }
+        machine.statics_end = static_base;
+
+        machine.ram[SP] = STACK_BASE;
+        if let Some(&sys_init) = machine.functions.get("Sys.init") {
+            // Returning from Sys.init ends the program
+            machine.pc = machine.commands.len();
+            machine.call(sys_init, 0);
+        }
+        Ok(machine)
+    }
+
+    /// The number of commands executed so far
+    pub fn cycles(&self) -> u64 {
+        self.cycles
+    }
+
+    pub fn peek(&self, address: u16) -> u16 {
+        self.ram[address as usize % MEMORY_SIZE]
+    }
+
+    pub(crate) fn ram(&self) -> &[u16] {
+        &self.ram
+    }
+
+    /// The index of the command about to be executed, counting from the first command of the
+    /// first file. It's one past the last command once the program has ended.
+    pub(crate) fn pc(&self) -> usize {
+        self.pc
+    }
+
+    /// The address of static `index` of a file, as this machine lays statics out
+    pub(crate) fn static_address(&self, file: &str, index: u16) -> Option<usize> {
+        self.static_bases
+            .get(file)
+            .map(|base| base + index as usize)
+    }
+
+    /// The statics of the file the command about to be executed came from
+    pub(crate) fn statics(&self) -> &[u16] {
+        let Some(command) = self.commands.get(self.pc) else {
+            return &[];
+        };
+        let base = command.static_base;
+        let end = self
+            .static_bases
+            .values()
+            .copied()
+            .filter(|next| *next > base)
+            .min()
+            .unwrap_or(self.statics_end);
+        &self.ram[base..end]
+    }
+
+    /// The number of calls in progress
+    pub(crate) fn call_depth(&self) -> usize {
+        self.return_addresses.len()
+    }
+
+    /// The function whose `function` command is about to be executed
+    pub(crate) fn entering_function(&self) -> Option<&str> {
+        match &self.commands.get(self.pc)?.operation {
+            Operation::Function(function) => Some(&function.name),
+            _ => None,
+        }
+    }
+
+    /// The functions being executed, innermost first
+    pub(crate) fn backtrace(&self) -> Vec<&str> {
+        let callers = self.return_addresses.iter().rev().filter_map(|address| {
+            let command = self.commands.get(*address)?;
+            (command.function != 0).then(|| self.function_names[command.function].as_str())
+        });
+        self.current_function().into_iter().chain(callers).collect()
+    }
+
+    /// Write to RAM from outside the program, e.g. to set up a test
+    pub fn poke(&mut self, address: u16, value: u16) {
+        self.ram[address as usize % MEMORY_SIZE] = value;
+    }
+
+    /// Press a key, or release every key with 0
+    pub fn set_keyboard(&mut self, key: u16) {
+        if let Some(recording) = &mut self.recording {
+            // Only the last of several presses between two commands makes any difference
+            if recording
+                .last()
+                .is_some_and(|last| last.cycle == self.cycles)
+            {
+                recording.pop();
+            }
+            recording.push(KeyPress {
+                cycle: self.cycles,
+                key,
+            });
+        }
+        self.poke(KEYBOARD, key);
+    }
+
+    /// Record the keys pressed from now on, starting with the one held down already
+    pub fn record_input(&mut self) {
+        self.recording = Some(Vec::new());
+        self.set_keyboard(self.peek(KEYBOARD));
+    }
+
+    /// The keys pressed since `record_input` was called
+    pub fn recorded_input(&self) -> &[KeyPress] {
+        self.recording.as_deref().unwrap_or(&[])
+    }
+
+    /// Press each of `presses` once the program has run its number of commands, as if they were
+    /// typed at the same points as when they were recorded
+    pub fn replay_input(&mut self, presses: Vec<KeyPress>) {
+        self.replay = presses;
+        self.replayed = 0;
+    }
+
+    pub fn screen(&self) -> &[u16] {
+        &self.ram[SCREEN as usize..SCREEN as usize + SCREEN_WORDS]
+    }
+
+    /// The values on the stack, from the bottom up
+    pub fn stack(&self) -> &[u16] {
+        let top = (self.ram[SP] as usize).clamp(STACK_BASE as usize, MEMORY_SIZE);
+        &self.ram[STACK_BASE as usize..top]
+    }
+
+    /// The command about to be executed
+    pub fn current_command(&self) -> Option<&str> {
+        self.commands
+            .get(self.pc)
+            .map(|command| command.text.trim())
+    }
+
+    /// A snapshot of the segments, heap and screen
+    pub fn state(&self) -> VmState {
+        let signed = |words: &[u16]| words.iter().map(|word| *word as i16).collect();
+        let (lcl, arg) = (self.ram[LCL] as usize, self.ram[ARG] as usize);
+        let locals = self
+            .current_function()
+            .and_then(
+                |name| match &self.commands[self.functions[name]].operation {
+                    Operation::Function(definition) => Some(definition.num as usize),
+                    _ => None,
+                },
+            )
+            .unwrap_or(0);
+        // The arguments run up to the caller's frame saved below the locals
+        let argument = if arg + 5 <= lcl {
+            &self.ram[arg..lcl - 5]
+        } else {
+            &[]
+        };
+        let heap = self.functions.get("Memory.alloc").and_then(|&alloc| {
+            // The built-in Memory class keeps its free list in static 1
+            let free_list = self.ram[self.commands[alloc].static_base + 1];
+            state::heap_blocks(&self.ram, free_list)
+        });
+
+        VmState {
+            cycles: self.cycles,
+            function: self.current_function().map(str::to_owned),
+            command: self.current_command().map(str::to_owned),
+            pointers: Pointers {
+                sp: self.ram[SP],
+                lcl: self.ram[LCL],
+                arg: self.ram[ARG],
+                this: self.ram[THIS],
+                that: self.ram[THAT],
+            },
+            local: signed(&self.ram[lcl..(lcl + locals).min(MEMORY_SIZE)]),
+            argument: signed(argument),
+            temp: signed(&self.ram[TEMP..TEMP + 8]),
+            stack: signed(self.stack()),
+            heap,
+            screen: self.screen().to_vec(),
+        }
+    }
+
+    /// Keep what the last `limit` commands changed so they can be undone with `step_back`. 0, the
+    /// default, keeps nothing.
+    pub fn set_history_limit(&mut self, limit: usize) {
+        self.history_limit = limit;
+        while self.history.len() > limit {
+            self.history.pop_front();
+        }
+    }
+
+    /// Handle the OS edge cases in `compat` natively rather than with the program's OS code
+    pub fn set_os_compat(&mut self, compat: OsCompat) {
+        self.os_compat = compat;
+    }
+
+    /// Check calls to the functions of the Assert class in [`crate::ASSERTIONS`] natively,
+    /// collecting the failures rather than running the program's Assert class
+    pub fn check_assertions(&mut self) {
+        self.check_assertions = true;
+    }
+
+    /// The assertions which have failed since this was last called
+    pub fn take_assertion_failures(&mut self) -> Vec<AssertionFailure> {
+        std::mem::take(&mut self.assertion_failures)
+    }
+
+    /// Count how many times each command runs from now on
+    pub fn count_executions(&mut self) {
+        let commands = self.commands.len();
+        self.executions.get_or_insert_with(|| vec![0; commands]);
+    }
+
+    /// For each file, how many times each of its commands has run since `count_executions` was
+    /// called, in the order of the file's commands
+    pub fn executions(&self) -> Vec<(&str, &[u64])> {
+        let Some(executions) = &self.executions else {
+            return Vec::new();
+        };
+        self.files
+            .iter()
+            .enumerate()
+            .map(|(index, (file, start))| {
+                let end = self
+                    .files
+                    .get(index + 1)
+                    .map_or(executions.len(), |(_, next)| *next);
+                (file.as_str(), &executions[*start..end])
+            })
+            .collect()
+    }
+
+    pub fn has_function(&self, name: &str) -> bool {
+        self.functions.contains_key(name)
+    }
+
+    /// Abandon whatever is running and call `function` with no arguments on an empty stack. RAM
+    /// is otherwise left as it is, so this can follow the OS's own initialization.
+    pub fn start(&mut self, function: &str) -> Result<(), ErrorType> {
+        self.start_with_arguments(function, &[])
+    }
+
+    /// Like [`VmMachine::start`], but first push `arguments` for the function
+    pub fn start_with_arguments(
+        &mut self,
+        function: &str,
+        arguments: &[u16],
+    ) -> Result<(), ErrorType> {
+        let target = self
+            .functions
+            .get(function)
+            .copied()
+            .ok_or_else(|| ErrorType::UnknownFunction(function.to_owned()))?;
+        self.return_addresses.clear();
+        self.history.clear();
+        self.ram[SP] = STACK_BASE;
+        for argument in arguments {
+            self.push(*argument);
+        }
+        // Returning from the function ends the program
+        self.pc = self.commands.len();
+        self.call(target, arguments.len() as u16);
+        Ok(())
+    }
+
+    /// Get ready for commands typed in one at a time, which run as though they were the body of a
+    /// function with `arguments` arguments and `locals` locals that nothing called
+    pub(crate) fn enter_interactive(&mut self, arguments: u16, locals: u16) {
+        self.return_addresses.clear();
+        self.history.clear();
+        self.ram[SP] = STACK_BASE;
+        self.ram[ARG] = STACK_BASE;
+        for _ in 0..arguments {
+            self.push(0);
+        }
+        // Where a caller's frame would be saved
+        for _ in 0..5 {
+            self.push(0);
+        }
+        self.ram[LCL] = self.ram[SP];
+        for _ in 0..locals {
+            self.push(0);
+        }
+        self.pc = self.commands.len();
+    }
+
+    /// Run a command typed in after [`VmMachine::enter_interactive`]. A call runs until the
+    /// function returns, or for at most `max_cycles` commands. Typed commands have statics of
+    /// their own, after those of the program.
+    pub(crate) fn execute_line(
+        &mut self,
+        line: &str,
+        max_cycles: u64,
+    ) -> Result<Option<Stop>, ErrorType> {
+        let statements =
+            vm_translator::parse_vm(line).map_err(|message| ErrorType::VmParsingError {
+                file: "the input".to_owned(),
+                message,
+            })?;
+        for statement in statements {
+            // Control flow needs the rest of the function to be known
+            if matches!(
+                statement.operation,
+                Operation::Function(_)
+                    | Operation::Return
+                    | Operation::Label(_)
+                    | Operation::Jump(_)
+                    | Operation::ConditionalJump(_)
+            ) {
+                return Err(ErrorType::NotInteractive(statement.text.trim().to_owned()));
+            }
+            self.commands.push(Command {
+                operation: statement.operation,
+                text: statement.text,
+                function: 0,
+                static_base: self.statics_end,
+            });
+            self.pc = self.commands.len() - 1;
+
+            let depth = self.return_addresses.len();
+            let mut finished = false;
+            for _ in 0..max_cycles {
+                if let Some(stop) = self.step()? {
+                    return Ok(Some(stop));
+                }
+                if self.pc == self.commands.len() && self.return_addresses.len() == depth {
+                    finished = true;
+                    break;
+                }
+            }
+            if !finished {
+                return Ok(Some(Stop::CycleLimit));
+            }
+        }
+        Ok(None)
+    }
+
+    /// The address of static 0 for commands typed in interactively
+    pub(crate) fn interactive_statics(&self) -> usize {
+        self.statics_end
+    }
+
+    /// Undo the last command executed. Returns false once there is no more history.
+    pub fn step_back(&mut self) -> bool {
+        let Some(delta) = self.history.pop_back() else {
+            return false;
+        };
+        // Keys pressed by the command are pressed again when it's run again
+        while self.replayed > 0 && self.replay[self.replayed - 1].cycle >= delta.cycles {
+            self.replayed -= 1;
+        }
+        for (address, value) in delta.writes.into_iter().rev() {
+            self.ram[address] = value;
+        }
+        // A command makes at most one call or return
+        if self.return_addresses.len() > delta.return_depth {
+            self.return_addresses.pop();
+        } else if self.return_addresses.len() < delta.return_depth {
+            self.return_addresses
+                .push(delta.innermost_return.expect("A return was popped"));
+        }
+        self.pc = delta.pc;
+        self.cycles = delta.cycles;
+        true
+    }
+
+    /// The function being executed, if execution has reached one
+    pub fn current_function(&self) -> Option<&str> {
+        let command = self.commands.get(self.pc)?;
+        (command.function != 0).then(|| self.function_names[command.function].as_str())
+    }
+
+    /// Execute a single command. Returns a reason to stop if the program has ended.
+    pub fn step(&mut self) -> Result<Option<Stop>, ErrorType> {
+        if self.history_limit == 0 {
+            return self.execute();
+        }
+
+        let (pc, cycles) = (self.pc, self.cycles);
+        let return_depth = self.return_addresses.len();
+        let innermost_return = self.return_addresses.last().copied();
+        let result = self.execute();
+        if self.cycles != cycles {
+            if self.history.len() == self.history_limit {
+                self.history.pop_front();
+            }
+            self.history.push_back(Delta {
+                pc,
+                cycles,
+                writes: std::mem::take(&mut self.writes),
+                return_depth,
+                innermost_return,
+            });
+        }
+        result
+    }
+
+    fn execute(&mut self) -> Result<Option<Stop>, ErrorType> {
+        if self.pc < self.commands.len() {
+            self.press_replayed_keys();
+        }
+        let Some(command) = self.commands.get(self.pc) else {
+            return Ok(Some(Stop::EndOfProgram));
+        };
+        if let Some(count) = self
+            .executions
+            .as_mut()
+            .and_then(|executions| executions.get_mut(self.pc))
+        {
+            *count += 1;
+        }
+        self.cycles += 1;
+        let mut next = self.pc + 1;
+
+        match &command.operation {
+            Operation::Push(address) => {
+                let value = match address.memory_segment {
+                    MemorySegment::Constant => address.address as u16,
+                    _ => {
+                        let address = self.resolve(address)?;
+                        self.ram[address]
+                    }
+                };
+                self.push(value);
+            }
+            Operation::Pop(address) => {
+                let address = self.resolve(address)?;
+                let value = self.pop();
+                self.write(address, value);
+            }
+            Operation::Add => self.binary(u16::wrapping_add),
+            Operation::Sub => self.binary(u16::wrapping_sub),
+            Operation::And => self.binary(|x, y| x & y),
+            Operation::Or => self.binary(|x, y| x | y),
+            Operation::Eq => self.binary(|x, y| truth(x == y)),
+            Operation::Gt => self.binary(|x, y| truth(x as i16 > y as i16)),
+            Operation::Lt => self.binary(|x, y| truth((x as i16) < y as i16)),
+            Operation::Neg => self.unary(u16::wrapping_neg),
+            Operation::Not => self.unary(|x| !x),
+            Operation::Label(_) => {}
+            Operation::Jump(label) => {
+                let target = self.label(label)?;
+                if self.is_halt_loop(target) {
+                    return Ok(Some(Stop::Halted));
+                }
+                next = target;
+            }
+            Operation::ConditionalJump(label) => {
+                let target = self.label(label)?;
+                if self.pop() != 0 {
+                    next = target;
+                }
+            }
+            Operation::Function(function) => {
+                for _ in 0..function.num {
+                    self.push(0);
+                }
+            }
+            // The OS ends programs by spinning in Sys.halt
+            Operation::Call(function) if function.name == "Sys.halt" => {
+                return Ok(Some(Stop::Halted));
+            }
+            Operation::Call(function)
+                if self.check_assertions
+                    && assertions::is_assertion(&function.name, function.num as u16) =>
+            {
+                let num_args = function.num as u16;
+                let sp = self.ram[SP];
+                let args: Vec<u16> = (0..num_args)
+                    .map(|index| self.peek(sp.wrapping_sub(num_args - index)))
+                    .collect();
+                if let Err(message) = assertions::check(&function.name, &args) {
+                    self.assertion_failures.push(AssertionFailure {
+                        function: self.function_names[command.function].clone(),
+                        message: format!("{}: {}", function.name, message),
+                    });
+                }
+                self.set(SP, sp.wrapping_sub(num_args));
+                // Every assertion is void
+                self.push(0);
+            }
+            Operation::Call(function) => {
+                let num_args = function.num as u16;
+                let target = self
+                    .functions
+                    .get(&function.name)
+                    .copied()
+                    .ok_or_else(|| ErrorType::UnknownFunction(function.name.clone()));
+                if let Some(os_call) = self.os_compat.intercept(&function.name) {
+                    if self.emulate_os_call(os_call, num_args)? {
+                        self.pc = next;
+                        return Ok(None);
+                    }
+                }
+                self.pc = next;
+                self.call(target?, num_args);
+                return Ok(None);
+            }
+            Operation::Return => {
+                let Some(return_address) = self.return_addresses.pop() else {
+                    return Ok(Some(Stop::EndOfProgram));
+                };
+                let lcl = self.ram[LCL] as usize;
+                let value = self.pop();
+                let arg = self.ram[ARG] as usize;
+                self.write(arg, value);
+                self.set(SP, (arg as u16).wrapping_add(1));
+                for (offset, register) in [THAT, THIS, ARG, LCL].into_iter().enumerate() {
+                    self.set(
+                        register,
+                        self.ram[lcl.wrapping_sub(offset + 1) % MEMORY_SIZE],
+                    );
+                }
+                next = return_address;
+            }
+        }
+
+        self.pc = next;
+        Ok(None)
+    }
+
+    /// Run until the program ends or `max_cycles` more commands have been executed
+    pub fn run(&mut self, max_cycles: u64) -> Result<Stop, ErrorType> {
+        for _ in 0..max_cycles {
+            if let Some(stop) = self.step()? {
+                return Ok(stop);
+            }
+        }
+        Ok(Stop::CycleLimit)
+    }
+
+    /// Call the function starting at `target` with the top `num_args` values of the stack as its
+    /// arguments, saving the caller's frame as the translated code would
+    fn call(&mut self, target: usize, num_args: u16) {
+        let return_address = self.pc;
+        self.push(return_address as u16);
+        for register in [LCL, ARG, THIS, THAT] {
+            self.push(self.ram[register]);
+        }
+        let sp = self.ram[SP];
+        self.set(ARG, sp.wrapping_sub(5 + num_args));
+        self.set(LCL, sp);
+        self.return_addresses.push(return_address);
+        self.pc = target;
+    }
+
+    fn label(&self, label: &str) -> Result<usize, ErrorType> {
+        let function = self.commands[self.pc].function;
+        self.labels
+            .get(&(function, label.to_owned()))
+            .copied()
+            .ok_or_else(|| ErrorType::UnknownLabel {
+                function: self.function_names[function].clone(),
+                label: label.to_owned(),
+            })
+    }
+
+    /// Whether a goto leads back to itself with only labels in between, e.g. the
+    /// `label END / goto END` at the end of a program
+    fn is_halt_loop(&self, target: usize) -> bool {
+        target <= self.pc
+            && self.commands[target..self.pc]
+                .iter()
+                .all(|command| matches!(command.operation, Operation::Label(_)))
+    }
+
+    /// The RAM address of a segment entry
+    fn resolve(&self, address: &Address) -> Result<usize, ErrorType> {
+        let index = address.address as usize;
+        let command = &self.commands[self.pc];
+        let resolved = match address.memory_segment {
+            MemorySegment::Local => Some(self.ram[LCL] as usize + index),
+            MemorySegment::Arguments => Some(self.ram[ARG] as usize + index),
+            MemorySegment::This => Some(self.ram[THIS] as usize + index),
+            MemorySegment::That => Some(self.ram[THAT] as usize + index),
+            MemorySegment::Static => Some(command.static_base + index),
+            MemorySegment::Pointer if index < 2 => Some(THIS + index),
+            MemorySegment::Temp if index < 8 => Some(TEMP + index),
+            MemorySegment::Pointer | MemorySegment::Temp | MemorySegment::Constant => None,
+        };
+        resolved
+            .filter(|address| *address < MEMORY_SIZE)
+            .ok_or_else(|| ErrorType::InvalidAccess {
+                function: self.function_names[command.function].clone(),
+                command: command.text.trim().to_owned(),
+            })
+    }
+
+    /// Apply an OS compatibility switch to a call whose arguments are on the stack. Returns true
+    /// if the call was replaced by its result, or false if the OS function should still run.
+    fn emulate_os_call(&mut self, os_call: OsCall, num_args: u16) -> Result<bool, ErrorType> {
+        if num_args != 2 {
+            return Ok(false);
+        }
+        let sp = self.ram[SP];
+        let (first, second) = (self.peek(sp.wrapping_sub(2)), self.peek(sp.wrapping_sub(1)));
+
+        match os_call {
+            // Division by 0 is left to the OS to report
+            OsCall::Divide(_) if second == 0 => Ok(false),
+            OsCall::Divide(division) => {
+                let quotient = division.divide(first as i16, second as i16);
+                self.return_native(quotient as u16);
+                Ok(true)
+            }
+            OsCall::AppendChar(overflow) => {
+                let this = first as usize;
+                let length = self.ram[(this + 1) % MEMORY_SIZE];
+                let capacity = self.ram[(this + 2) % MEMORY_SIZE];
+                if length < capacity {
+                    return Ok(false);
+                }
+                match overflow {
+                    StringOverflow::Error => Err(ErrorType::OsError {
+                        function: "String.appendChar".to_owned(),
+                        message: format!("the string is full at {} characters", capacity),
+                    }),
+                    _ => {
+                        self.return_native(first);
+                        Ok(true)
+                    }
+                }
+            }
+            OsCall::DrawPixel(bounds) => {
+                let (x, y) = (first as i16, second as i16);
+                if (0..SCREEN_WIDTH).contains(&x) && (0..SCREEN_HEIGHT).contains(&y) {
+                    return Ok(false);
+                }
+                match bounds {
+                    PixelBounds::Wrap => {
+                        self.set(
+                            sp.wrapping_sub(2) as usize % MEMORY_SIZE,
+                            x.rem_euclid(SCREEN_WIDTH) as u16,
+                        );
+                        self.set(
+                            sp.wrapping_sub(1) as usize % MEMORY_SIZE,
+                            y.rem_euclid(SCREEN_HEIGHT) as u16,
+                        );
+                        Ok(false)
+                    }
+                    PixelBounds::Error => Err(ErrorType::OsError {
+                        function: "Screen.drawPixel".to_owned(),
+                        message: format!("({}, {}) is off the screen", x, y),
+                    }),
+                    _ => {
+                        self.return_native(0);
+                        Ok(true)
+                    }
+                }
+            }
+        }
+    }
+
+    /// Replace the two arguments of a call with its result, as returning from it would
+    fn return_native(&mut self, value: u16) {
+        self.pop();
+        self.pop();
+        self.push(value);
+    }
+
+    fn push(&mut self, value: u16) {
+        let sp = self.ram[SP];
+        self.write(sp as usize, value);
+        self.set(SP, sp.wrapping_add(1));
+    }
+
+    fn pop(&mut self) -> u16 {
+        let sp = self.ram[SP].wrapping_sub(1);
+        self.set(SP, sp);
+        self.peek(sp)
+    }
+
+    fn unary(&mut self, op: impl Fn(u16) -> u16) {
+        let x = self.pop();
+        self.push(op(x));
+    }
+
+    fn binary(&mut self, op: impl Fn(u16, u16) -> u16) {
+        let y = self.pop();
+        let x = self.pop();
+        self.push(op(x, y));
+    }
+
+    /// Press the keys of the replayed input which are due before the next command
+    fn press_replayed_keys(&mut self) {
+        while let Some(press) = self
+            .replay
+            .get(self.replayed)
+            .filter(|press| press.cycle <= self.cycles)
+        {
+            self.set(KEYBOARD as usize, press.key);
+            self.replayed += 1;
+        }
+    }
+
+    /// The keyboard register and everything above it is read only to the program
+    fn write(&mut self, address: usize, value: u16) {
+        if address < KEYBOARD as usize {
+            self.set(address, value);
+        }
+    }
+
+    /// Every write the program makes goes through here so that it can be undone
+    fn set(&mut self, address: usize, value: u16) {
+        if self.history_limit > 0 {
+            self.writes.push((address, self.ram[address]));
+        }
+        self.ram[address] = value;
+    }
+}
+
+/// VM comparisons produce -1 for true and 0 for false
+fn truth(condition: bool) -> u16 {
+    if condition {
+        0xFFFF
+    } else {
+        0
+    }
+}
+
+#[test]
+fn test_arithmetic() {
+    let mut vm = VmMachine::load(&[(
+        "Test.vm",
+        "push constant 7
+        push constant 8
+        add
+        push constant 3
+        push constant 5
+        lt
+        push constant 2
+        neg
+        push constant 1
+        gt",
+    )])
+    .unwrap();
+
+    assert_eq!(vm.run(100).unwrap(), Stop::EndOfProgram);
+    assert_eq!(vm.stack(), &[15, 0xFFFF, 0]);
+}
+
+#[cfg(test)]
+const MULTIPLY_PROGRAM: &[(&str, &str)] = &[
+    (
+        "Sys.vm",
+        "function Sys.init 0
+        push constant 6
+        push constant 7
+        call Main.multiply 2
+        pop static 0
+        label END
+        goto END",
+    ),
+    (
+        "Main.vm",
+        "function Main.multiply 1
+        label LOOP
+        push argument 1
+        if-goto BODY
+        push local 0
+        return
+        label BODY
+        push local 0
+        push argument 0
+        add
+        pop local 0
+        push argument 1
+        push constant 1
+        sub
+        pop argument 1
+        goto LOOP",
+    ),
+];
+
+#[test]
+fn test_calls_and_statics() {
+    let mut vm = VmMachine::load(MULTIPLY_PROGRAM).unwrap();
+
+    assert_eq!(vm.run(1000).unwrap(), Stop::Halted);
+    assert_eq!(vm.peek(16), 42);
+    // Only the frame of Sys.init remains on the stack
+    assert_eq!(vm.stack().len(), 5);
+    assert_eq!(vm.current_function(), Some("Sys.init"));
+}
+
+#[test]
+fn test_step_back() {
+    let mut vm = VmMachine::load(MULTIPLY_PROGRAM).unwrap();
+    vm.set_history_limit(20);
+    assert_eq!(vm.run(1000).unwrap(), Stop::Halted);
+    let cycles = vm.cycles();
+
+    // Replay a second machine to the point 20 commands back and compare every word of RAM
+    let mut replay = VmMachine::load(MULTIPLY_PROGRAM).unwrap();
+    replay.run(cycles - 20).unwrap();
+    for _ in 0..20 {
+        assert!(vm.step_back());
+    }
+    assert!(!vm.step_back());
+    assert_eq!(vm.cycles(), replay.cycles());
+    assert_eq!(vm.current_command(), replay.current_command());
+    assert_eq!(vm.ram, replay.ram);
+
+    // Running forward again ends the same way
+    assert_eq!(vm.run(1000).unwrap(), Stop::Halted);
+    assert_eq!(vm.peek(16), 42);
+}
+
+#[test]
+fn test_runtime_errors() {
+    let mut vm = VmMachine::load(&[("Main.vm", "push constant 1\ncall Math.abs 1")]).unwrap();
+    assert!(matches!(vm.run(10), Err(ErrorType::UnknownFunction(name)) if name == "Math.abs"));
+
+    let mut vm = VmMachine::load(&[("Main.vm", "push constant 1\npop temp 8")]).unwrap();
+    assert!(matches!(vm.run(10), Err(ErrorType::InvalidAccess { .. })));
+}
+
+#[test]
+fn test_runs_with_the_built_in_os() {
+    let sources = vm_translator::link_os(&[(
+        "Main.vm",
+        "function Main.main 0
+        push constant 8000
+        push constant 123
+        push constant 45
+        call Math.multiply 2
+        call Memory.poke 2
+        pop temp 0
+        push constant 8001
+        push constant 1000
+        neg
+        push constant 7
+        call Math.divide 2
+        call Memory.poke 2
+        pop temp 0
+        push constant 8002
+        push constant 1000
+        call Math.sqrt 1
+        call Memory.poke 2
+        pop temp 0
+        push constant 0
+        return",
+    )]);
+    let mut vm = VmMachine::load(&sources).unwrap();
+
+    assert_eq!(vm.run(10_000_000).unwrap(), Stop::Halted);
+    assert_eq!(vm.peek(8000), 5535);
+    assert_eq!(vm.peek(8001) as i16, -142);
+    assert_eq!(vm.peek(8002), 31);
+}
+
+#[test]
+fn test_state() {
+    let mut vm = VmMachine::load(MULTIPLY_PROGRAM).unwrap();
+    // Stop inside Main.multiply
+    vm.run(12).unwrap();
+    let state = vm.state();
+
+    assert_eq!(state.function.as_deref(), Some("Main.multiply"));
+    assert_eq!(state.argument, [6, 7]);
+    assert_eq!(state.local.len(), 1);
+    assert_eq!(state.pointers.lcl as usize, STACK_BASE as usize + 5 + 7);
+    assert_eq!(state.heap, None);
+    assert_eq!(state.screen.len(), SCREEN_WORDS);
+
+    let sources = vm_translator::link_os(&[(
+        "Main.vm",
+        "function Main.main 0
+        push constant 3
+        call Memory.alloc 1
+        pop temp 0
+        label END
+        goto END",
+    )]);
+    let mut vm = VmMachine::load(&sources).unwrap();
+    assert_eq!(vm.run(1_000_000).unwrap(), Stop::Halted);
+    let heap = vm.state().heap.unwrap();
+    // The OS's own allocations come first
+    let block = heap
+        .iter()
+        .find(|block| block.address + 1 == vm.peek(5))
+        .unwrap();
+    assert_eq!(block.size, 4);
+    assert!(!block.free);
+    assert!(heap.iter().any(|block| block.free));
+}
+
+#[test]
+fn test_os_compat() {
+    let sources = vm_translator::link_os(&[(
+        "Main.vm",
+        "function Main.main 0
+        push constant 8000
+        push constant 7
+        neg
+        push constant 2
+        call Math.divide 2
+        call Memory.poke 2
+        pop temp 0
+        push constant 1
+        call String.new 1
+        push constant 65
+        call String.appendChar 2
+        push constant 66
+        call String.appendChar 2
+        pop temp 0
+        push constant 600
+        push constant 1
+        call Screen.drawPixel 2
+        pop temp 0
+        push constant 0
+        return",
+    )]);
+    let run = |compat: OsCompat| {
+        let mut vm = VmMachine::load(&sources).unwrap();
+        vm.set_os_compat(compat);
+        vm.run(10_000_000)
+            .map(|stop| (stop, vm.peek(8000) as i16, vm.screen()[32 + 5]))
+    };
+
+    // The built-in OS rounds toward zero, then halts in Sys.error when the string overflows
+    let (stop, quotient, _) = run(OsCompat::default()).unwrap();
+    assert_eq!((stop, quotient), (Stop::Halted, -3));
+
+    let lenient = OsCompat {
+        division: crate::Division::Floor,
+        string_overflow: StringOverflow::Ignore,
+        pixel_bounds: PixelBounds::Clip,
+    };
+    assert_eq!(run(lenient).unwrap(), (Stop::Halted, -4, 0));
+
+    // 600 wraps to x = 88, the 9th pixel of the 6th word of row 1
+    let wrapping = OsCompat {
+        division: crate::Division::TowardZero,
+        pixel_bounds: PixelBounds::Wrap,
+        ..lenient
+    };
+    assert_eq!(run(wrapping).unwrap(), (Stop::Halted, -3, 1 << 8));
+
+    let strict = OsCompat {
+        pixel_bounds: PixelBounds::Error,
+        ..lenient
+    };
+    assert!(
+        matches!(run(strict), Err(ErrorType::OsError { function, .. }) if function == "Screen.drawPixel")
+    );
+}
+
+#[test]
+fn test_replay_input() {
+    // Adds up the key held down each time round the loop
+    let sources = [(
+        "Main.vm",
+        "push constant 24576
+pop pointer 1
+label LOOP
+push static 0
+push that 0
+add
+pop static 0
+goto LOOP",
+    )];
+    let mut recorded = VmMachine::load(&sources).unwrap();
+    let mut replayed = recorded.clone();
+    recorded.record_input();
+    recorded.run(20).unwrap();
+    recorded.set_keyboard(3);
+    recorded.run(13).unwrap();
+    recorded.set_keyboard(0);
+    recorded.run(20).unwrap();
+    assert_eq!(
+        recorded.recorded_input(),
+        [
+            KeyPress { cycle: 0, key: 0 },
+            KeyPress { cycle: 20, key: 3 },
+            KeyPress { cycle: 33, key: 0 }
+        ]
+    );
+
+    replayed.replay_input(recorded.recorded_input().to_vec());
+    replayed.set_history_limit(100);
+    replayed.run(53).unwrap();
+    assert_eq!(replayed.peek(16), recorded.peek(16));
+    assert_eq!(replayed.peek(16), 6);
+
+    // Going back over a key press takes it back, and it's pressed again on the way forward
+    for _ in 0..30 {
+        replayed.step_back();
+    }
+    assert_eq!(replayed.peek(KEYBOARD), 3);
+    for _ in 0..5 {
+        replayed.step_back();
+    }
+    assert_eq!(replayed.peek(KEYBOARD), 0);
+    replayed.run(35).unwrap();
+    assert_eq!(replayed.peek(16), 6);
+}
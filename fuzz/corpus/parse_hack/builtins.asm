@@ -0,0 +1,7 @@
+@SCREEN
+D=A
+@KBD
+AMD=D|M;JNE
+@R15
+M=!M
+0;JMP
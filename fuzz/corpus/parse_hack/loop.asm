@@ -0,0 +1,14 @@
+// c
+@i
+M=1
+(LOOP)
+@i
+D=M
+@100
+D=D-A
+@END
+D;JGT
+@LOOP
+0;JMP
+(END)
+AM=M+1
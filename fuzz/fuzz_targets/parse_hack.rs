@@ -0,0 +1,10 @@
+#![no_main]
+
+use libfuzzer_sys::fuzz_target;
+
+// Parse & assemble a .asm file. Any input must produce Ok or Err, never a panic.
+fuzz_target!(|data: &[u8]| {
+    if let Ok(source) = std::str::from_utf8(data) {
+        let _ = assembler::assemble_string(source);
+    }
+});
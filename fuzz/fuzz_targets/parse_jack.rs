@@ -0,0 +1,10 @@
+#![no_main]
+
+use libfuzzer_sys::fuzz_target;
+
+// Parse & compile a single class. Any input must produce Ok or Err, never a panic.
+fuzz_target!(|data: &[u8]| {
+    if let Ok(source) = std::str::from_utf8(data) {
+        let _ = compiler::compile_strings(&[("Main.jack", source)]);
+    }
+});
@@ -0,0 +1,10 @@
+#![no_main]
+
+use libfuzzer_sys::fuzz_target;
+
+// Parse & translate a .vm file. Any input must produce Ok or Err, never a panic.
+fuzz_target!(|data: &[u8]| {
+    if let Ok(source) = std::str::from_utf8(data) {
+        let _ = vm_translator::translate_string("Main.vm", source);
+    }
+});
@@ -0,0 +1,582 @@
+use compiler::ast::{
+    Class, ClassVariableVisibility, ReturnType, Statement, Subroutine, SubroutineType,
+    VariableType, AST,
+};
+use compiler::{
+    compile_jack_sources, parse_strings, tokenize_jack, CodegenOptions, CompilationWarning,
+    ErrorType, ParseError,
+};
+use lsp_types::{
+    Diagnostic, DiagnosticSeverity, DocumentSymbol, NumberOrString, Position, Range, SymbolKind,
+};
+use parse_utils::tokens::{Token, TokenKind};
+
+/// Where go-to-definition leads
+#[derive(Debug, Clone, PartialEq)]
+pub enum Definition {
+    /// A position in the same file
+    Here(Range),
+    /// A class, or one of its subroutines, which may be defined in another file
+    Class {
+        class: String,
+        subroutine: Option<String>,
+    },
+}
+
+/// A Jack file with its tokens and, if it parses, its class
+pub struct SourceFile {
+    text: String,
+    /// Every token but comments
+    tokens: Vec<Token>,
+    line_starts: Vec<usize>,
+    ast: Option<AST>,
+}
+
+impl SourceFile {
+    pub fn new(file_name: &str, text: String) -> Self {
+        let tokens = tokenize_jack(&text)
+            .into_iter()
+            .filter(|token| token.kind != TokenKind::Comment)
+            .collect();
+        let line_starts = std::iter::once(0)
+            .chain(text.match_indices('\n').map(|(index, _)| index + 1))
+            .collect();
+        let ast = parse_strings(&[(file_name, &text)]).ok();
+        Self {
+            text,
+            tokens,
+            line_starts,
+            ast,
+        }
+    }
+
+    fn class(&self) -> Option<&Class> {
+        self.ast.as_ref().map(|ast| &ast.classes[0].class)
+    }
+
+    fn token_text(&self, token: &Token) -> &str {
+        &self.text[token.offset..token.offset + token.length]
+    }
+
+    fn range(&self, token: &Token) -> Range {
+        Range::new(
+            self.position(token.offset),
+            self.position(token.offset + token.length),
+        )
+    }
+
+    /// Positions count UTF-16 code units, as LSP clients do by default
+    pub fn position(&self, offset: usize) -> Position {
+        let line = self.line_starts.partition_point(|start| *start <= offset) - 1;
+        let character = self.text[self.line_starts[line]..offset]
+            .encode_utf16()
+            .count();
+        Position::new(line as u32, character as u32)
+    }
+
+    pub fn offset(&self, position: Position) -> usize {
+        let Some(&start) = self.line_starts.get(position.line as usize) else {
+            return self.text.len();
+        };
+        let mut units = 0;
+        for (index, c) in self.text[start..].char_indices() {
+            if units >= position.character as usize || c == '\n' {
+                return start + index;
+            }
+            units += c.len_utf16();
+        }
+        self.text.len()
+    }
+
+    /// The byte offset of a 1-based line
+    fn line_offset(&self, line: u32) -> usize {
+        let index = (line as usize).saturating_sub(1);
+        self.line_starts
+            .get(index)
+            .copied()
+            .unwrap_or(self.text.len())
+    }
+
+    /// The first identifier called `name` between two offsets. Jack declares everything before
+    /// using it, so within a declaration's scope this is the declaration.
+    fn find_name(&self, name: &str, start: usize, end: usize) -> Option<&Token> {
+        self.tokens.iter().find(|token| {
+            token.offset >= start
+                && token.offset < end
+                && token.kind != TokenKind::Keyword
+                && self.token_text(token) == name
+        })
+    }
+
+    fn class_name_token(&self) -> Option<&Token> {
+        let name = self.class()?.get_name();
+        self.find_name(name.as_str(), 0, self.text.len())
+    }
+
+    /// The byte range of each subroutine, from the start of its first line to the start of the
+    /// next subroutine
+    fn subroutine_spans(&self) -> Vec<(usize, usize)> {
+        let Some(class) = self.class() else {
+            return Vec::new();
+        };
+        let starts: Vec<usize> = class
+            .subroutines()
+            .iter()
+            .map(|subroutine| self.line_offset(subroutine.get_line()))
+            .collect();
+        let ends = starts.iter().skip(1).copied().chain([self.text.len()]);
+        starts.iter().copied().zip(ends).collect()
+    }
+
+    /// Where the class variables are declared, before the first subroutine
+    fn class_body_end(&self) -> usize {
+        self.subroutine_spans()
+            .first()
+            .map_or(self.text.len(), |(start, _)| *start)
+    }
+
+    fn subroutine_name_token(&self, name: &str) -> Option<&Token> {
+        let class = self.class()?;
+        let index = class
+            .subroutines()
+            .iter()
+            .position(|subroutine| subroutine.get_name().as_str() == name)?;
+        let (start, end) = self.subroutine_spans()[index];
+        self.find_name(name, start, end)
+    }
+
+    /// The class name, or one of its subroutines' names, for a definition in this file
+    pub fn class_definition(&self, subroutine: Option<&str>) -> Option<Range> {
+        let token = match subroutine {
+            Some(name) => self.subroutine_name_token(name)?,
+            None => self.class_name_token()?,
+        };
+        Some(self.range(token))
+    }
+
+    /// The declaration and type of a variable visible at an offset: a parameter or local of the
+    /// enclosing subroutine, or a class variable
+    fn variable(&self, name: &str, offset: usize) -> Option<(&Token, String)> {
+        let class = self.class()?;
+        let spans = self.subroutine_spans();
+        let enclosing = spans
+            .iter()
+            .position(|(start, end)| (*start..*end).contains(&offset));
+        if let Some(index) = enclosing {
+            let subroutine = &class.subroutines()[index];
+            if let Some(var_type) = local_type(subroutine, name) {
+                let (start, end) = spans[index];
+                let name_token = self.find_name(subroutine.get_name().as_str(), start, end)?;
+                let declaration = self.find_name(name, name_token.offset + 1, end)?;
+                return Some((declaration, var_type));
+            }
+        }
+        let variable = class
+            .variables()
+            .iter()
+            .find(|variable| variable.get_identifier().as_str() == name)?;
+        let start = self.line_offset(variable.get_line());
+        let declaration = self.find_name(name, start, self.class_body_end())?;
+        Some((declaration, variable.get_var_type().type_name().to_string()))
+    }
+
+    /// Resolve the name at a position to its declaration
+    pub fn definition(&self, position: Position) -> Option<Definition> {
+        let class = self.class()?;
+        let offset = self.offset(position);
+        // The cursor may be just after the name
+        let index = self.tokens.iter().position(|token| {
+            (token.offset..=token.offset + token.length).contains(&offset)
+                && matches!(
+                    token.kind,
+                    TokenKind::Type | TokenKind::Function | TokenKind::Variable
+                )
+        })?;
+        let token = &self.tokens[index];
+        let name = self.token_text(token);
+        let text_at = |index: Option<usize>| index.and_then(|index| self.tokens.get(index));
+        let previous = text_at(index.checked_sub(1)).map(|token| self.token_text(token));
+        let next = text_at(Some(index + 1)).map(|token| self.token_text(token));
+
+        // A subroutine of a class, or of the class of a variable: `Output.printInt` or `p.getX`
+        if previous == Some(".") {
+            let qualifier = text_at(index.checked_sub(2))?;
+            let qualifier = self.token_text(qualifier);
+            let class_name = match self.variable(qualifier, offset) {
+                Some((_, var_type)) => var_type,
+                None => qualifier.to_owned(),
+            };
+            return Some(self.in_class(class, class_name, Some(name.to_owned())));
+        }
+        // A subroutine of this class
+        if next == Some("(") {
+            return Some(self.in_class(class, class.get_name().to_string(), Some(name.to_owned())));
+        }
+        if let Some((declaration, _)) = self.variable(name, offset) {
+            return Some(Definition::Here(self.range(declaration)));
+        }
+        // Anything else which isn't a variable names a class
+        Some(self.in_class(class, name.to_owned(), None))
+    }
+
+    fn in_class(&self, class: &Class, name: String, subroutine: Option<String>) -> Definition {
+        if class.get_name().as_str() == name {
+            if let Some(range) = self.class_definition(subroutine.as_deref()) {
+                return Definition::Here(range);
+            }
+        }
+        Definition::Class {
+            class: name,
+            subroutine,
+        }
+    }
+
+    /// The class with its variables and subroutines, for an outline of the file
+    #[allow(deprecated)]
+    pub fn document_symbols(&self) -> Vec<DocumentSymbol> {
+        let (Some(class), Some(class_token)) = (self.class(), self.class_name_token()) else {
+            return Vec::new();
+        };
+        let whole_file = Range::new(Position::new(0, 0), self.position(self.text.len()));
+        let mut children = Vec::new();
+
+        for variable in class.variables() {
+            let name = variable.get_identifier().as_str();
+            let start = self.line_offset(variable.get_line());
+            let Some(token) = self.find_name(name, start, self.class_body_end()) else {
+                continue;
+            };
+            let kind = match variable.get_visibility() {
+                ClassVariableVisibility::Field => SymbolKind::FIELD,
+                ClassVariableVisibility::Static => SymbolKind::VARIABLE,
+            };
+            children.push(DocumentSymbol {
+                name: name.to_owned(),
+                detail: Some(variable.get_var_type().type_name().to_string()),
+                kind,
+                tags: None,
+                deprecated: None,
+                range: self.range(token),
+                selection_range: self.range(token),
+                children: None,
+            });
+        }
+
+        for (subroutine, (start, end)) in class.subroutines().iter().zip(self.subroutine_spans()) {
+            let name = subroutine.get_name().as_str();
+            let Some(token) = self.find_name(name, start, end) else {
+                continue;
+            };
+            let kind = match subroutine.get_subroutine_type() {
+                SubroutineType::Constructor => SymbolKind::CONSTRUCTOR,
+                SubroutineType::Method => SymbolKind::METHOD,
+                SubroutineType::Function => SymbolKind::FUNCTION,
+            };
+            children.push(DocumentSymbol {
+                name: name.to_owned(),
+                detail: Some(signature(subroutine)),
+                kind,
+                tags: None,
+                deprecated: None,
+                range: Range::new(self.position(start), self.position(end)),
+                selection_range: self.range(token),
+                children: None,
+            });
+        }
+
+        vec![DocumentSymbol {
+            name: class.get_name().to_string(),
+            detail: None,
+            kind: SymbolKind::CLASS,
+            tags: None,
+            deprecated: None,
+            range: whole_file,
+            selection_range: self.range(class_token),
+            children: Some(children),
+        }]
+    }
+
+    /// Parse and compile the file along with the other classes of its program, reporting the
+    /// first error in this file or else its warnings. Other classes which don't parse are left
+    /// out, so that they don't hide problems in this one.
+    pub fn diagnostics(&self, file_name: &str, others: &[(String, String)]) -> Vec<Diagnostic> {
+        let error = |line: u32, column: usize, message: String, code: &str| {
+            let offset = self.line_offset(line) + column.saturating_sub(1);
+            let start = self.position(offset.min(self.text.len()));
+            let end = self
+                .tokens
+                .iter()
+                .find(|token| token.offset == offset)
+                .map_or(start, |token| self.range(token).end);
+            Diagnostic {
+                range: Range::new(start, end),
+                severity: Some(DiagnosticSeverity::ERROR),
+                code: Some(NumberOrString::String(code.to_owned())),
+                source: Some("jack".to_owned()),
+                message,
+                ..Default::default()
+            }
+        };
+
+        if let Err(ErrorType::ParsingError(parse_error)) = parse_strings(&[(file_name, &self.text)])
+        {
+            return vec![error(
+                parse_error.line,
+                parse_error.column,
+                parse_error.message,
+                ParseError::CODE,
+            )];
+        }
+        let mut sources = vec![(file_name, self.text.as_str())];
+        sources.extend(
+            others
+                .iter()
+                .map(|(name, text)| (name.as_str(), text.as_str()))
+                .filter(|source| parse_strings(&[*source]).is_ok()),
+        );
+
+        match compile_jack_sources(&sources, &CodegenOptions::default()) {
+            Ok(outputs) => outputs
+                .iter()
+                .filter(|output| output.source_filename == file_name)
+                .flat_map(|output| &output.warnings)
+                .map(|warning| Diagnostic {
+                    range: self.warning_range(warning),
+                    severity: Some(DiagnosticSeverity::WARNING),
+                    code: Some(NumberOrString::String(warning.code().to_owned())),
+                    source: Some("jack".to_owned()),
+                    message: warning.to_string(),
+                    ..Default::default()
+                })
+                .collect(),
+            Err(ErrorType::CompilationError(located)) if located.diagnostic.file == file_name => {
+                let diagnostic = located.diagnostic;
+                vec![error(
+                    diagnostic.line,
+                    diagnostic.column,
+                    located.error.to_string(),
+                    located.error.code(),
+                )]
+            }
+            // The error is in another class, where it's reported when that file is open
+            Err(_) => Vec::new(),
+        }
+    }
+
+    /// Most warnings name the subroutine they're in rather than a position, so point at its name
+    fn warning_range(&self, warning: &CompilationWarning) -> Range {
+        let qualified = match warning {
+            CompilationWarning::VoidResultUsed { subroutine, .. }
+            | CompilationWarning::LongString { subroutine, .. }
+            | CompilationWarning::UnusedSubroutine { subroutine, .. } => subroutine,
+            CompilationWarning::UninitializedField { method, .. } => method,
+            CompilationWarning::UnusedVariable { name, line, .. }
+            | CompilationWarning::UninitializedLocal { name, line, .. } => {
+                let start = self.line_offset(*line);
+                let end = self.line_offset(line + 1);
+                return self
+                    .find_name(name, start, end)
+                    .map_or_else(Range::default, |token| self.range(token));
+            }
+            // The whole of the first line which can never run, without its indentation
+            CompilationWarning::UnreachableCode { line, .. } => {
+                let text = &self.text[self.line_offset(*line)..self.line_offset(line + 1)];
+                let start = self.line_offset(*line) + text.len() - text.trim_start().len();
+                let end = start + text.trim().len();
+                return Range::new(self.position(start), self.position(end));
+            }
+        };
+        let name = qualified
+            .rsplit_once('.')
+            .map_or(qualified.as_str(), |(_, name)| name);
+        match self.subroutine_name_token(name) {
+            Some(token) => self.range(token),
+            None => Range::default(),
+        }
+    }
+}
+
+/// The type of a parameter or local variable of a subroutine
+fn local_type(subroutine: &Subroutine, name: &str) -> Option<String> {
+    let parameter = subroutine
+        .get_parameters()
+        .iter()
+        .find(|parameter| parameter.get_identifier().as_str() == name);
+    if let Some(parameter) = parameter {
+        return Some(parameter.get_type().type_name().to_string());
+    }
+    subroutine
+        .get_statements()
+        .iter()
+        .filter_map(|statement| match statement {
+            Statement::VarDecl(details) => Some(details.get_variables()),
+            _ => None,
+        })
+        .flatten()
+        .find(|variable| variable.get_identifier().as_str() == name)
+        .map(|variable| variable.get_type().type_name().to_string())
+}
+
+/// e.g. `int (int x, Point p)`
+fn signature(subroutine: &Subroutine) -> String {
+    let return_type = match subroutine.get_return_type() {
+        ReturnType::Int => "int".to_owned(),
+        ReturnType::Char => "char".to_owned(),
+        ReturnType::Boolean => "boolean".to_owned(),
+        ReturnType::Void => "void".to_owned(),
+        ReturnType::ClassName(name) => name.to_string(),
+    };
+    let parameters: Vec<String> = subroutine
+        .get_parameters()
+        .iter()
+        .map(|parameter| {
+            let var_type = match parameter.get_type() {
+                VariableType::Int => "int".to_owned(),
+                VariableType::Char => "char".to_owned(),
+                VariableType::Boolean => "boolean".to_owned(),
+                other => other.type_name().to_string(),
+            };
+            format!("{} {}", var_type, parameter.get_identifier())
+        })
+        .collect();
+    format!("{} ({})", return_type, parameters.join(", "))
+}
+
+#[cfg(test)]
+const POINT: &str = "class Point {
+    field int x, y;
+    static Point origin;
+
+    constructor Point new(int ax, int ay) {
+        let x = ax;
+        let y = ay;
+        return this;
+    }
+
+    method int getX() {
+        return x;
+    }
+
+    method Point add(Point other) {
+        var Point sum;
+        let sum = Point.new(x + other.getX(), y);
+        do Output.printInt(sum.getX());
+        return sum;
+    }
+}
+";
+
+#[test]
+fn test_definition() {
+    let file = SourceFile::new("Point.jack", POINT.to_owned());
+    // The position of the n-th occurrence of a word
+    let at = |word: &str, n: usize| {
+        let offset = POINT.match_indices(word).nth(n).unwrap().0;
+        file.definition(file.position(offset))
+    };
+    // The range of the identifier starting the n-th occurrence
+    let here = |word: &str, n: usize| {
+        let offset = POINT.match_indices(word).nth(n).unwrap().0;
+        let length = word.chars().take_while(|c| c.is_alphanumeric()).count();
+        Some(Definition::Here(Range::new(
+            file.position(offset),
+            file.position(offset + length),
+        )))
+    };
+
+    // A field, a parameter and a local
+    assert_eq!(at("x = ax", 0), here("x, y", 0));
+    assert_eq!(at("ax;", 0), here("ax,", 0));
+    assert_eq!(at("sum", 1), here("sum", 0));
+    // Subroutines called through the class, a variable, and this class's own
+    assert_eq!(at("new(x", 0), here("new", 0));
+    assert_eq!(at("getX()", 2), here("getX", 0));
+    assert_eq!(at("getX()", 1), here("getX", 0));
+    assert_eq!(
+        at("printInt", 0),
+        Some(Definition::Class {
+            class: "Output".to_owned(),
+            subroutine: Some("printInt".to_owned()),
+        })
+    );
+    // A class used as a type
+    assert_eq!(at("Point other", 0), here("Point", 0));
+    assert_eq!(at("let", 0), None);
+}
+
+#[test]
+fn test_document_symbols() {
+    let file = SourceFile::new("Point.jack", POINT.to_owned());
+    let symbols = file.document_symbols();
+    let children: Vec<(&str, SymbolKind, Option<&str>)> = symbols[0]
+        .children
+        .iter()
+        .flatten()
+        .map(|symbol| (symbol.name.as_str(), symbol.kind, symbol.detail.as_deref()))
+        .collect();
+
+    assert_eq!(symbols[0].name, "Point");
+    assert_eq!(
+        children,
+        [
+            ("x", SymbolKind::FIELD, Some("Int")),
+            ("y", SymbolKind::FIELD, Some("Int")),
+            ("origin", SymbolKind::VARIABLE, Some("Point")),
+            (
+                "new",
+                SymbolKind::CONSTRUCTOR,
+                Some("Point (int ax, int ay)")
+            ),
+            ("getX", SymbolKind::METHOD, Some("int ()")),
+            ("add", SymbolKind::METHOD, Some("Point (Point other)")),
+        ]
+    );
+}
+
+#[test]
+fn test_diagnostics() {
+    let file = SourceFile::new("Point.jack", POINT.to_owned());
+    let diagnostics = file.diagnostics("Point.jack", &[]);
+    assert_eq!(diagnostics.len(), 1);
+    assert_eq!(diagnostics[0].severity, Some(DiagnosticSeverity::WARNING));
+    assert_eq!(diagnostics[0].message, "Point: static origin is never read");
+    assert_eq!(diagnostics[0].range.start, Position::new(2, 17));
+
+    let source = POINT.replace("let y = ay;", "let y = ay");
+    let file = SourceFile::new("Point.jack", source);
+    let diagnostics = file.diagnostics("Point.jack", &[]);
+    assert_eq!(diagnostics.len(), 1);
+    assert_eq!(diagnostics[0].severity, Some(DiagnosticSeverity::ERROR));
+    assert_eq!(diagnostics[0].range.start, Position::new(6, 18));
+
+    let source = POINT.replace("return x;", "return z;");
+    let file = SourceFile::new("Point.jack", source);
+    let diagnostics = file.diagnostics("Point.jack", &[]);
+    assert_eq!(diagnostics[0].message, "Variable z has not been declared");
+    // Compilation errors point at the code which caused them
+    assert_eq!(diagnostics[0].range.start, Position::new(11, 15));
+}
+
+#[test]
+fn test_diagnostics_use_the_other_classes() {
+    let main = "class Main {
+    function void main() {
+        var Point p;
+        let p = Point.new(1, 2);
+        do p.getY();
+        return;
+    }
+}
+";
+    let file = SourceFile::new("Main.jack", main.to_owned());
+    let point = [("Point.jack".to_owned(), POINT.to_owned())];
+    let diagnostics = file.diagnostics("Main.jack", &point);
+
+    assert_eq!(diagnostics.len(), 1);
+    assert_eq!(
+        diagnostics[0].message,
+        "Class Point has no subroutine called getY"
+    );
+    let fixed = SourceFile::new("Main.jack", main.replace("getY", "getX"));
+    assert_eq!(fixed.diagnostics("Main.jack", &point), []);
+}
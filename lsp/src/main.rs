@@ -0,0 +1,341 @@
+mod analysis;
+
+use std::collections::HashMap;
+use std::error::Error;
+use std::fs;
+use std::path::PathBuf;
+use std::str::FromStr;
+
+use analysis::{Definition, SourceFile};
+use lsp_server::{Connection, ExtractError, Message, Notification, Request, RequestId, Response};
+use lsp_types::notification::{
+    DidChangeTextDocument, DidCloseTextDocument, DidOpenTextDocument,
+    Notification as NotificationType, PublishDiagnostics,
+};
+use lsp_types::request::{DocumentSymbolRequest, GotoDefinition, Request as RequestType};
+use lsp_types::{
+    DocumentSymbolResponse, GotoDefinitionResponse, Location, OneOf, PublishDiagnosticsParams,
+    ServerCapabilities, TextDocumentSyncCapability, TextDocumentSyncKind, Uri,
+};
+
+type BoxError = Box<dyn Error + Sync + Send>;
+
+/// A language server for Jack over stdin and stdout. Logs go to stderr, as stdout carries the
+/// protocol.
+fn main() -> Result<(), BoxError> {
+    let (connection, io_threads) = Connection::stdio();
+    let capabilities = ServerCapabilities {
+        text_document_sync: Some(TextDocumentSyncCapability::Kind(TextDocumentSyncKind::FULL)),
+        definition_provider: Some(OneOf::Left(true)),
+        document_symbol_provider: Some(OneOf::Left(true)),
+        ..Default::default()
+    };
+    connection.initialize(serde_json::to_value(capabilities)?)?;
+
+    Server {
+        connection,
+        documents: HashMap::new(),
+    }
+    .run()?;
+    io_threads.join()?;
+    Ok(())
+}
+
+struct Server {
+    connection: Connection,
+    /// The text of each open document, which may be ahead of the file on disk
+    documents: HashMap<Uri, String>,
+}
+
+impl Server {
+    fn run(mut self) -> Result<(), BoxError> {
+        let receiver = self.connection.receiver.clone();
+        for message in &receiver {
+            match message {
+                Message::Request(request) => {
+                    if self.connection.handle_shutdown(&request)? {
+                        return Ok(());
+                    }
+                    self.handle_request(request)?;
+                }
+                Message::Notification(notification) => self.handle_notification(notification)?,
+                Message::Response(_) => {}
+            }
+        }
+        Ok(())
+    }
+
+    fn handle_request(&self, request: Request) -> Result<(), BoxError> {
+        let request = match cast::<GotoDefinition>(request) {
+            Ok((id, params)) => {
+                let params = params.text_document_position_params;
+                let location = self.definition(&params.text_document.uri, params.position);
+                let response = location.map(GotoDefinitionResponse::Scalar);
+                return self.respond(id, serde_json::to_value(response)?);
+            }
+            Err(NotCast::Other(request)) => request,
+            Err(NotCast::BadParams(id, message)) => return self.reject(id, message),
+        };
+        let request = match cast::<DocumentSymbolRequest>(request) {
+            Ok((id, params)) => {
+                let uri = params.text_document.uri;
+                let response = self
+                    .source_file(&uri)
+                    .map(|file| DocumentSymbolResponse::Nested(file.document_symbols()));
+                return self.respond(id, serde_json::to_value(response)?);
+            }
+            Err(NotCast::Other(request)) => request,
+            Err(NotCast::BadParams(id, message)) => return self.reject(id, message),
+        };
+
+        let response = Response::new_err(
+            request.id,
+            lsp_server::ErrorCode::MethodNotFound as i32,
+            format!("{} is not supported", request.method),
+        );
+        self.connection.sender.send(response.into())?;
+        Ok(())
+    }
+
+    fn handle_notification(&mut self, notification: Notification) -> Result<(), BoxError> {
+        match notification.method.as_str() {
+            DidOpenTextDocument::METHOD => {
+                let Some(params) = notification_params::<DidOpenTextDocument>(notification) else {
+                    return Ok(());
+                };
+                let document = params.text_document;
+                self.documents.insert(document.uri.clone(), document.text);
+                self.publish_diagnostics(document.uri)
+            }
+            DidChangeTextDocument::METHOD => {
+                let Some(params) = notification_params::<DidChangeTextDocument>(notification)
+                else {
+                    return Ok(());
+                };
+                // Changes are always the whole document, as that's the sync kind asked for
+                let uri = params.text_document.uri;
+                if let Some(change) = params.content_changes.into_iter().last() {
+                    self.documents.insert(uri.clone(), change.text);
+                }
+                self.publish_diagnostics(uri)
+            }
+            DidCloseTextDocument::METHOD => {
+                let Some(params) = notification_params::<DidCloseTextDocument>(notification) else {
+                    return Ok(());
+                };
+                let uri = params.text_document.uri;
+                self.documents.remove(&uri);
+                self.send_diagnostics(uri, Vec::new())
+            }
+            _ => Ok(()),
+        }
+    }
+
+    fn publish_diagnostics(&self, uri: Uri) -> Result<(), BoxError> {
+        let diagnostics = match self.source_file(&uri) {
+            Some(file) => file.diagnostics(file_name(&uri), &self.other_classes(&uri)),
+            None => Vec::new(),
+        };
+        self.send_diagnostics(uri, diagnostics)
+    }
+
+    /// The other .jack files in the directory of a file, which make up its program
+    fn other_classes(&self, uri: &Uri) -> Vec<(String, String)> {
+        let Some(directory) = uri_to_path(uri).and_then(|path| Some(path.parent()?.to_owned()))
+        else {
+            return Vec::new();
+        };
+        let Ok(entries) = fs::read_dir(&directory) else {
+            return Vec::new();
+        };
+        let mut classes = Vec::new();
+        for entry in entries.flatten() {
+            let path = entry.path();
+            let name = entry.file_name().to_string_lossy().into_owned();
+            let is_jack = path
+                .extension()
+                .is_some_and(|extension| extension == "jack");
+            if !is_jack || name == file_name(uri) {
+                continue;
+            }
+            let open = self
+                .documents
+                .iter()
+                .find(|(open_uri, _)| uri_to_path(open_uri).as_deref() == Some(path.as_path()));
+            let text = match open {
+                Some((_, text)) => text.clone(),
+                None => match fs::read_to_string(&path) {
+                    Ok(text) => text,
+                    Err(_) => continue,
+                },
+            };
+            classes.push((name, text));
+        }
+        classes
+    }
+
+    fn send_diagnostics(
+        &self,
+        uri: Uri,
+        diagnostics: Vec<lsp_types::Diagnostic>,
+    ) -> Result<(), BoxError> {
+        let params = PublishDiagnosticsParams::new(uri, diagnostics, None);
+        let notification = Notification::new(PublishDiagnostics::METHOD.to_owned(), params);
+        self.connection.sender.send(notification.into())?;
+        Ok(())
+    }
+
+    fn respond(&self, id: RequestId, result: serde_json::Value) -> Result<(), BoxError> {
+        let response = Response::new_ok(id, result);
+        self.connection.sender.send(response.into())?;
+        Ok(())
+    }
+
+    /// Answer a request whose params couldn't be read, and carry on
+    fn reject(&self, id: RequestId, message: String) -> Result<(), BoxError> {
+        eprintln!("{}", message);
+        let response = Response::new_err(id, lsp_server::ErrorCode::InvalidParams as i32, message);
+        self.connection.sender.send(response.into())?;
+        Ok(())
+    }
+
+    /// An open document, or else the file on disk
+    fn source_file(&self, uri: &Uri) -> Option<SourceFile> {
+        let text = match self.documents.get(uri) {
+            Some(text) => text.clone(),
+            None => fs::read_to_string(uri_to_path(uri)?).ok()?,
+        };
+        Some(SourceFile::new(file_name(uri), text))
+    }
+
+    fn definition(&self, uri: &Uri, position: lsp_types::Position) -> Option<Location> {
+        match self.source_file(uri)?.definition(position)? {
+            Definition::Here(range) => Some(Location::new(uri.clone(), range)),
+            Definition::Class { class, subroutine } => {
+                // Classes are looked for next to the file, as the compiler does
+                let (directory, _) = uri.as_str().rsplit_once('/')?;
+                let class_uri = Uri::from_str(&format!("{}/{}.jack", directory, class)).ok()?;
+                let file = self.source_file(&class_uri)?;
+                let range = file
+                    .class_definition(subroutine.as_deref())
+                    .or_else(|| file.class_definition(None))?;
+                Some(Location::new(class_uri, range))
+            }
+        }
+    }
+}
+
+/// Why a request couldn't be cast to a particular type
+enum NotCast {
+    /// The request is for something else, so is handed back
+    Other(Request),
+    /// The request's params can't be read, for which the id and the reason are given
+    BadParams(RequestId, String),
+}
+
+/// The id and params of a request for `R`
+fn cast<R>(request: Request) -> Result<(RequestId, R::Params), NotCast>
+where
+    R: RequestType,
+{
+    let id = request.id.clone();
+    match request.extract(R::METHOD) {
+        Ok(extracted) => Ok(extracted),
+        Err(ExtractError::MethodMismatch(request)) => Err(NotCast::Other(request)),
+        Err(ExtractError::JsonError { method, error }) => Err(NotCast::BadParams(
+            id,
+            format!("Ignoring {} request with bad params: {}", method, error),
+        )),
+    }
+}
+
+/// The params of a notification for `N`. Bad params are logged and the notification dropped,
+/// rather than stopping the server.
+fn notification_params<N>(notification: Notification) -> Option<N::Params>
+where
+    N: NotificationType,
+{
+    match serde_json::from_value(notification.params) {
+        Ok(params) => Some(params),
+        Err(error) => {
+            eprintln!(
+                "Ignoring {} notification with bad params: {}",
+                N::METHOD,
+                error
+            );
+            None
+        }
+    }
+}
+
+/// The last segment of a URI, e.g. `Main.jack`
+fn file_name(uri: &Uri) -> &str {
+    let path = uri.path().as_str();
+    path.rsplit_once('/').map_or(path, |(_, name)| name)
+}
+
+/// The local path of a `file:` URI
+fn uri_to_path(uri: &Uri) -> Option<PathBuf> {
+    if uri.scheme()?.as_str() != "file" {
+        return None;
+    }
+    let path = uri.path().as_estr().decode().into_string().ok()?;
+    Some(PathBuf::from(path.as_ref()))
+}
+
+#[test]
+fn test_bad_notifications_are_ignored() {
+    let (server, client) = Connection::memory();
+    let server = std::thread::spawn(move || {
+        Server {
+            connection: server,
+            documents: HashMap::new(),
+        }
+        .run()
+        .map_err(|error| error.to_string())
+    });
+
+    let uri = "file:///nonexistent/Main.jack";
+    let bad_change = Notification::new(
+        DidChangeTextDocument::METHOD.to_owned(),
+        serde_json::json!({ "textDocument": { "uri": uri }, "contentChanges": "oops" }),
+    );
+    client.sender.send(bad_change.into()).unwrap();
+    let bad_request = Request::new(
+        RequestId::from(1),
+        GotoDefinition::METHOD.to_owned(),
+        serde_json::json!({ "position": 3 }),
+    );
+    client.sender.send(bad_request.into()).unwrap();
+    let open = Notification::new(
+        DidOpenTextDocument::METHOD.to_owned(),
+        serde_json::json!({ "textDocument": {
+            "uri": uri,
+            "languageId": "jack",
+            "version": 1,
+            "text": "class Main { function void main() { return; } }",
+        }}),
+    );
+    client.sender.send(open.into()).unwrap();
+
+    match client.receiver.recv().unwrap() {
+        Message::Response(response) => {
+            assert_eq!(response.id, RequestId::from(1));
+            assert_eq!(
+                response.error.map(|error| error.code),
+                Some(lsp_server::ErrorCode::InvalidParams as i32)
+            );
+        }
+        other => panic!("Expected a response, got {:?}", other),
+    }
+    // The server is still running, so the good notification is handled
+    match client.receiver.recv().unwrap() {
+        Message::Notification(notification) => {
+            assert_eq!(notification.method, PublishDiagnostics::METHOD)
+        }
+        other => panic!("Expected diagnostics, got {:?}", other),
+    }
+
+    drop(client);
+    assert_eq!(server.join().unwrap(), Ok(()));
+}
@@ -0,0 +1,108 @@
+use std::path::Path;
+use std::time::{Duration, Instant};
+
+use clap::{value_parser, Arg, ArgMatches, Command, ValueHint};
+
+use compiler::CodegenOptions;
+
+use crate::{project, ErrorType};
+
+const STAGES: [&str; 5] = ["parse", "check", "codegen", "translate", "assemble"];
+
+pub fn command() -> Command {
+    Command::new("bench")
+        .about("Time each stage of the toolchain on a Jack project")
+        .arg(
+            Arg::new("PROJECT")
+                .index(1)
+                .required(true)
+                .value_hint(ValueHint::AnyPath)
+                .help("A .jack file or a directory of .jack files"),
+        )
+        .arg(
+            Arg::new("iterations")
+                .short('n')
+                .long("iterations")
+                .value_parser(value_parser!(u32).range(1..))
+                .default_value("10")
+                .help("The number of times to run the whole pipeline"),
+        )
+}
+
+pub fn run(matches: &ArgMatches) -> Result<(), ErrorType> {
+    let project = Path::new(
+        matches
+            .get_one::<String>("PROJECT")
+            .expect("User to provide a project"),
+    );
+    let iterations = *matches
+        .get_one::<u32>("iterations")
+        .expect("iterations has a default");
+
+    let sources = project::read_jack_sources(project)?;
+    let sources: Vec<(&str, &str)> = sources
+        .iter()
+        .map(|(name, contents)| (name.as_str(), contents.as_str()))
+        .collect();
+
+    let options = CodegenOptions::default();
+    let mut timings: Vec<Vec<Duration>> = STAGES
+        .iter()
+        .map(|_| Vec::with_capacity(iterations as usize))
+        .collect();
+    for _ in 0..iterations {
+        let start = Instant::now();
+        let ast = compiler::parse_strings(&sources)?;
+        timings[0].push(start.elapsed());
+
+        let start = Instant::now();
+        compiler::check_ast(&ast, &options)?;
+        timings[1].push(start.elapsed());
+
+        let start = Instant::now();
+        let vm_files = compiler::compile_checked_ast(&ast, &options)?;
+        timings[2].push(start.elapsed());
+
+        let vm_sources: Vec<(&str, &str)> = vm_files
+            .iter()
+            .map(|(name, contents)| (name.as_str(), contents.as_str()))
+            .collect();
+        let start = Instant::now();
+        let asm = vm_translator::translate_program(&vm_sources)?;
+        timings[3].push(start.elapsed());
+
+        let start = Instant::now();
+        assembler::assemble_string(&asm)?;
+        timings[4].push(start.elapsed());
+    }
+
+    println!("{} classes, {} iterations\n", sources.len(), iterations);
+    println!(
+        "{:<10} {:>12} {:>12} {:>12} {:>12}",
+        "stage", "total", "mean", "min", "max"
+    );
+    for (stage, durations) in STAGES.iter().zip(&timings) {
+        print_row(stage, durations);
+    }
+    let totals: Vec<Duration> = (0..iterations as usize)
+        .map(|i| timings.iter().map(|durations| durations[i]).sum())
+        .collect();
+    print_row("all", &totals);
+
+    Ok(())
+}
+
+fn print_row(stage: &str, durations: &[Duration]) {
+    let total: Duration = durations.iter().sum();
+    let mean = total / durations.len() as u32;
+    let min = durations.iter().min().copied().unwrap_or_default();
+    let max = durations.iter().max().copied().unwrap_or_default();
+    println!(
+        "{:<10} {:>12} {:>12} {:>12} {:>12}",
+        stage,
+        format!("{:.2?}", total),
+        format!("{:.2?}", mean),
+        format!("{:.2?}", min),
+        format!("{:.2?}", max)
+    );
+}
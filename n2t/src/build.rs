@@ -0,0 +1,312 @@
+use std::path::{Path, PathBuf};
+
+use clap::{Arg, ArgAction, ArgMatches, Command, ValueHint};
+use compiler::CodegenOptions;
+use parse_utils::cli::write_mode;
+use parse_utils::output::{write_output, WriteMode};
+
+use crate::{project, workspace, ErrorType};
+
+pub fn command() -> Command {
+    Command::new("build")
+        .about("Compile, translate and assemble a Jack project into a .hack file")
+        .arg(
+            Arg::new("PROJECT")
+                .index(1)
+                .required(true)
+                .value_hint(ValueHint::AnyPath)
+                .help("A .jack file or a directory of .jack files. .vm files in the directory, such as the OS, are linked in too. A directory with an n2t.toml manifest, or the manifest itself, builds each of its targets"),
+        )
+        .arg(
+            Arg::new("output")
+                .short('o')
+                .long("output")
+                .value_name("FILE")
+                .value_hint(ValueHint::FilePath)
+                .help("Where to write the .hack file. Defaults to one named after the project, inside it"),
+        )
+        .arg(
+            Arg::new("target")
+                .short('t')
+                .long("target")
+                .value_name("NAME")
+                .action(ArgAction::Append)
+                .help("Only build this target of the manifest. Can be given more than once"),
+        )
+        .arg(
+            Arg::new("with_os")
+                .long("with-os")
+                .action(ArgAction::SetTrue)
+                .required(false)
+                .help("Link in the built-in Jack OS classes which the project doesn't provide itself"),
+        )
+        .arg(
+            Arg::new("dry_run")
+                .long("dry-run")
+                .action(ArgAction::SetTrue)
+                .required(false)
+                .help("Report the file which would be written without writing it"),
+        )
+}
+
+pub fn run(matches: &ArgMatches) -> Result<(), ErrorType> {
+    let project = Path::new(
+        matches
+            .get_one::<String>("PROJECT")
+            .expect("User to provide a project"),
+    );
+
+    if let Some(manifest) = workspace::find_manifest(project) {
+        if matches.contains_id("output") {
+            return Err(ErrorType::OutputWithManifest);
+        }
+        let targets: Vec<String> = matches
+            .get_many::<String>("target")
+            .into_iter()
+            .flatten()
+            .cloned()
+            .collect();
+        return build_workspace(
+            &manifest,
+            &targets,
+            matches.get_flag("with_os"),
+            write_mode(matches),
+        );
+    }
+
+    let jack_sources = project::read_jack_sources(project)?;
+    let linked = if project.is_dir() {
+        project::read_sources(project, "vm")?
+    } else {
+        Vec::new()
+    };
+    let hack = build_hack(&jack_sources, linked, matches.get_flag("with_os"), project)?;
+
+    let output = match matches.get_one::<String>("output") {
+        Some(output) => PathBuf::from(output),
+        None => default_output(project)?,
+    };
+    write(&output, &hack, write_mode(matches))
+}
+
+/// Build the targets of a manifest, or only those named in `targets`
+fn build_workspace(
+    manifest_path: &Path,
+    targets: &[String],
+    with_os: bool,
+    mode: WriteMode,
+) -> Result<(), ErrorType> {
+    let manifest = workspace::read_manifest(manifest_path)?;
+    let root = manifest_path.parent().unwrap_or(Path::new("."));
+    if let Some(unknown) = targets
+        .iter()
+        .find(|name| !manifest.targets.iter().any(|target| target.name == **name))
+    {
+        return Err(ErrorType::UnknownTarget(unknown.clone()));
+    }
+
+    for target in &manifest.targets {
+        if !targets.is_empty() && !targets.contains(&target.name) {
+            continue;
+        }
+        let sources = target.sources(root, &manifest.shared)?;
+        let hack = build_hack(
+            &sources.jack,
+            sources.vm,
+            with_os || manifest.with_os,
+            &root.join(&target.path),
+        )?;
+        write(&target.output_file(root), &hack, mode)?;
+    }
+    Ok(())
+}
+
+/// Compile, translate and assemble a program. `linked` are .vm files to link in, e.g. the OS,
+/// which are left out if one of the classes compiles to a file of the same name.
+fn build_hack(
+    jack_sources: &[(String, String)],
+    linked: Vec<(String, String)>,
+    with_os: bool,
+    project: &Path,
+) -> Result<String, ErrorType> {
+    let jack_sources: Vec<(&str, &str)> = jack_sources
+        .iter()
+        .map(|(name, contents)| (name.as_str(), contents.as_str()))
+        .collect();
+    let mut vm_files = Vec::with_capacity(jack_sources.len());
+    for output in compiler::compile_jack_sources(&jack_sources, &CodegenOptions::default())? {
+        for warning in &output.warnings {
+            eprintln!("warning: {}: {}", output.source_filename, warning);
+        }
+        vm_files.push((output.vm_filename(), output.vm_code));
+    }
+
+    // Link in any .vm files which weren't compiled from the project's own classes
+    for (name, contents) in linked {
+        if !vm_files.iter().any(|(compiled, _)| *compiled == name) {
+            vm_files.push((name, contents));
+        }
+    }
+
+    let mut vm_sources: Vec<(&str, &str)> = vm_files
+        .iter()
+        .map(|(name, contents)| (name.as_str(), contents.as_str()))
+        .collect();
+    if with_os {
+        vm_sources = vm_translator::link_os(&vm_sources);
+    }
+
+    // The bootstrap jumps to Sys.init, so without it the program would run off into nothing
+    if !vm_sources
+        .iter()
+        .any(|(_, contents)| defines_sys_init(contents))
+    {
+        return Err(ErrorType::MissingSysInit(project.to_owned()));
+    }
+    let asm = vm_translator::translate_program(&vm_sources)?;
+    Ok(assembler::assemble_string(&asm)?)
+}
+
+fn write(output: &Path, hack: &str, mode: WriteMode) -> Result<(), ErrorType> {
+    write_output(output, hack.as_bytes(), mode).map_err(|source| ErrorType::WriteError {
+        path: output.to_owned(),
+        source,
+    })
+}
+
+fn defines_sys_init(vm_code: &str) -> bool {
+    vm_code.lines().any(|line| {
+        let mut words = line.split_whitespace();
+        words.next() == Some("function") && words.next() == Some("Sys.init")
+    })
+}
+
+/// `Project/Project.hack` for a directory and `Main.hack` next to `Main.jack` for a single file
+fn default_output(project: &Path) -> Result<PathBuf, ErrorType> {
+    if !project.is_dir() {
+        return Ok(project.with_extension("hack"));
+    }
+
+    let name = project
+        .canonicalize()
+        .ok()
+        .and_then(|path| path.file_name().map(|name| name.to_owned()))
+        .ok_or_else(|| ErrorType::InvalidPath(project.to_owned()))?;
+    let mut file_name = PathBuf::from(name);
+    file_name.set_extension("hack");
+    Ok(project.join(file_name))
+}
+
+#[test]
+fn test_defines_sys_init() {
+    assert!(defines_sys_init(
+        "function Main.main 0\nfunction  Sys.init 1\n"
+    ));
+    assert!(!defines_sys_init(
+        "function Sys.initialise 0\ncall Sys.init 0\n"
+    ));
+}
+
+#[test]
+fn test_built_in_os_is_compiled_from_its_jack_source() {
+    let sources = [
+        (
+            "Array.jack",
+            include_str!("../../vm-translator/os/Array.jack"),
+        ),
+        (
+            "Keyboard.jack",
+            include_str!("../../vm-translator/os/Keyboard.jack"),
+        ),
+        (
+            "Math.jack",
+            include_str!("../../vm-translator/os/Math.jack"),
+        ),
+        (
+            "Memory.jack",
+            include_str!("../../vm-translator/os/Memory.jack"),
+        ),
+        (
+            "Output.jack",
+            include_str!("../../vm-translator/os/Output.jack"),
+        ),
+        (
+            "Screen.jack",
+            include_str!("../../vm-translator/os/Screen.jack"),
+        ),
+        (
+            "String.jack",
+            include_str!("../../vm-translator/os/String.jack"),
+        ),
+        ("Sys.jack", include_str!("../../vm-translator/os/Sys.jack")),
+    ];
+    let outputs = compiler::compile_jack_sources(&sources, &CodegenOptions::default()).unwrap();
+
+    assert_eq!(outputs.len(), vm_translator::OS_FILES.len());
+    for output in outputs {
+        let (_, vm_code) = vm_translator::OS_FILES
+            .iter()
+            .find(|(file, _)| *file == output.vm_filename())
+            .unwrap();
+        assert!(
+            output.vm_code == *vm_code,
+            "{} is out of date, recompile vm-translator/os",
+            output.vm_filename()
+        );
+        // The OS has to build with --deny-warnings along with the programs linked to it
+        assert!(
+            output.warnings.is_empty(),
+            "{} has warnings",
+            output.source_filename
+        );
+    }
+}
+
+#[test]
+fn test_workspace_targets_share_classes() {
+    let dir = std::env::temp_dir().join(format!("n2t-workspace-{}", std::process::id()));
+    for directory in ["common", "one", "two"] {
+        std::fs::create_dir_all(dir.join(directory)).unwrap();
+    }
+    std::fs::write(
+        dir.join(workspace::MANIFEST),
+        r#"shared = ["common"]
+with_os = true
+
+[[target]]
+name = "One"
+path = "one"
+
+[[target]]
+name = "Two"
+path = "two"
+output = "out"
+"#,
+    )
+    .unwrap();
+    std::fs::write(
+        dir.join("common/Score.jack"),
+        "class Score { function int get() { return 1; } }",
+    )
+    .unwrap();
+    let main = "class Main { function void main() { do Output.printInt(Score.get()); return; } }";
+    std::fs::write(dir.join("one/Main.jack"), main).unwrap();
+    std::fs::write(dir.join("two/Main.jack"), main).unwrap();
+    // A target's own class replaces the shared one
+    std::fs::write(
+        dir.join("two/Score.jack"),
+        "class Score { function int get() { return 2; } }",
+    )
+    .unwrap();
+
+    let manifest = workspace::find_manifest(&dir).unwrap();
+    assert!(matches!(
+        build_workspace(&manifest, &["Three".to_owned()], false, WriteMode::Write),
+        Err(ErrorType::UnknownTarget(_))
+    ));
+    build_workspace(&manifest, &[], false, WriteMode::Write).unwrap();
+    let one = std::fs::read_to_string(dir.join("build/One/One.hack")).unwrap();
+    let two = std::fs::read_to_string(dir.join("out/Two.hack")).unwrap();
+    std::fs::remove_dir_all(&dir).unwrap();
+    assert_ne!(one, two);
+}
@@ -0,0 +1,199 @@
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+
+use clap::{value_parser, Arg, ArgAction, ArgMatches, Command, ValueHint};
+use compiler::{CodegenOptions, CompilationOutput};
+use emulator::VmMachine;
+use parse_utils::cli::write_mode;
+use parse_utils::output::write_output;
+
+use crate::{project, ErrorType};
+
+pub fn command() -> Command {
+    Command::new("coverage")
+        .about("Run a Jack project and report which lines of it ran as an lcov tracefile")
+        .arg(
+            Arg::new("PROJECT")
+                .index(1)
+                .required(true)
+                .value_hint(ValueHint::AnyPath)
+                .help("A .jack file or a directory of .jack files. .vm files in the directory are run too, but not reported on"),
+        )
+        .arg(
+            Arg::new("output")
+                .short('o')
+                .long("output")
+                .value_name("FILE")
+                .value_hint(ValueHint::FilePath)
+                .default_value("lcov.info")
+                .help("Where to write the tracefile, which genhtml can turn into an HTML report"),
+        )
+        .arg(
+            Arg::new("with_os")
+                .long("with-os")
+                .action(ArgAction::SetTrue)
+                .required(false)
+                .help("Link in the built-in Jack OS classes which the project doesn't provide itself"),
+        )
+        .arg(
+            Arg::new("max_cycles")
+                .long("max-cycles")
+                .value_parser(value_parser!(u64))
+                .default_value("10000000")
+                .help("Stop after this many VM commands, for programs which wait for input"),
+        )
+        .arg(
+            Arg::new("dry_run")
+                .long("dry-run")
+                .action(ArgAction::SetTrue)
+                .required(false)
+                .help("Report the file which would be written without writing it"),
+        )
+}
+
+pub fn run(matches: &ArgMatches) -> Result<(), ErrorType> {
+    let project = Path::new(
+        matches
+            .get_one::<String>("PROJECT")
+            .expect("User to provide a project"),
+    );
+    let max_cycles = *matches
+        .get_one::<u64>("max_cycles")
+        .expect("max_cycles has a default");
+
+    let jack_sources = project::read_jack_sources(project)?;
+    let jack_sources: Vec<(&str, &str)> = jack_sources
+        .iter()
+        .map(|(name, contents)| (name.as_str(), contents.as_str()))
+        .collect();
+    let outputs = compiler::compile_jack_sources(&jack_sources, &CodegenOptions::default())?;
+
+    let mut vm_files: Vec<(String, String)> = outputs
+        .iter()
+        .map(|output| (output.vm_filename(), output.vm_code.clone()))
+        .collect();
+    if project.is_dir() {
+        for (name, contents) in project::read_sources(project, "vm")? {
+            if !vm_files.iter().any(|(compiled, _)| *compiled == name) {
+                vm_files.push((name, contents));
+            }
+        }
+    }
+    let mut vm_sources: Vec<(&str, &str)> = vm_files
+        .iter()
+        .map(|(name, contents)| (name.as_str(), contents.as_str()))
+        .collect();
+    if matches.get_flag("with_os") {
+        vm_sources = vm_translator::link_os(&vm_sources);
+    }
+
+    let mut vm = VmMachine::load(&vm_sources)?;
+    vm.count_executions();
+    // The lines which ran before an error are still worth reporting
+    let result = vm.run(max_cycles);
+
+    let source_dir = if project.is_dir() {
+        project
+    } else {
+        project.parent().unwrap_or(Path::new(""))
+    };
+    let tracefile = lcov(&line_counts(&outputs, &vm), source_dir);
+    let output = PathBuf::from(
+        matches
+            .get_one::<String>("output")
+            .expect("output has a default"),
+    );
+    write_output(&output, tracefile.as_bytes(), write_mode(matches)).map_err(|source| {
+        ErrorType::WriteError {
+            path: output,
+            source,
+        }
+    })?;
+    result?;
+    Ok(())
+}
+
+/// How many times each Jack line of each class ran, by source file name. A line which compiled to
+/// several commands counts the most any of them ran, so the condition of a loop counts each time
+/// round it.
+fn line_counts<'a>(
+    outputs: &'a [CompilationOutput],
+    vm: &VmMachine,
+) -> Vec<(&'a str, BTreeMap<u32, u64>)> {
+    let executions = vm.executions();
+    outputs
+        .iter()
+        .map(|output| {
+            let vm_filename = output.vm_filename();
+            let counts = executions
+                .iter()
+                .find(|(file, _)| *file == vm_filename)
+                .map_or(&[][..], |(_, counts)| *counts);
+            let mut lines = BTreeMap::new();
+            // Code the compiler adds for the class as a whole has no line
+            for (line, count) in output.source_lines.iter().zip(counts) {
+                if *line > 0 {
+                    let runs = lines.entry(*line).or_insert(0);
+                    *runs = (*runs).max(*count);
+                }
+            }
+            (output.source_filename.as_str(), lines)
+        })
+        .collect()
+}
+
+/// The lines of each source file as an lcov tracefile
+fn lcov(files: &[(&str, BTreeMap<u32, u64>)], source_dir: &Path) -> String {
+    let mut tracefile = String::new();
+    for (file, lines) in files {
+        tracefile.push_str("TN:\n");
+        tracefile.push_str(&format!("SF:{}\n", source_dir.join(file).display()));
+        for (line, count) in lines {
+            tracefile.push_str(&format!("DA:{},{}\n", line, count));
+        }
+        let hit = lines.values().filter(|count| **count > 0).count();
+        tracefile.push_str(&format!("LH:{}\nLF:{}\nend_of_record\n", hit, lines.len()));
+    }
+    tracefile
+}
+
+#[test]
+fn test_lcov() {
+    let main = "class Main {
+    function void main() {
+        var int x;
+        let x = 1;
+        if (x > 2) {
+            let x = 3;
+        }
+        while (x < 4) {
+            let x = x + 1;
+        }
+        return;
+    }
+}";
+    let outputs =
+        compiler::compile_jack_sources(&[("Main.jack", main)], &CodegenOptions::default()).unwrap();
+    let mut vm = VmMachine::load(&[("Main.vm", &outputs[0].vm_code)]).unwrap();
+    vm.start("Main.main").unwrap();
+    vm.count_executions();
+    vm.run(1000).unwrap();
+
+    let tracefile = lcov(&line_counts(&outputs, &vm), Path::new("src"));
+    assert_eq!(
+        tracefile,
+        "TN:
+SF:src/Main.jack
+DA:2,1
+DA:4,1
+DA:5,1
+DA:6,0
+DA:8,4
+DA:9,3
+DA:11,1
+LH:6
+LF:7
+end_of_record
+"
+    );
+}
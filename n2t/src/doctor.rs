@@ -0,0 +1,153 @@
+use std::error::Error;
+use std::panic::{self, AssertUnwindSafe};
+use std::time::Instant;
+
+use clap::{ArgMatches, Command};
+use emulator::Cpu;
+
+use crate::ErrorType;
+
+const STAGES: [&str; 4] = ["compile", "translate", "assemble", "emulate"];
+
+/// A program small enough to run without the OS: Main.main sums the first 10 Fibonacci numbers
+/// into RAM[RESULT_ADDRESS]
+const PROJECT: [(&str, &str); 2] = [
+    (
+        "Main.jack",
+        "class Main {
+    function int fib(int n) {
+        if (n < 2) {
+            return n;
+        }
+        return Main.fib(n - 1) + Main.fib(n - 2);
+    }
+
+    function void main() {
+        var Array ram;
+        var int i, sum;
+        let ram = 0;
+        while (i < 10) {
+            let sum = sum + Main.fib(i);
+            let i = i + 1;
+        }
+        let ram[8000] = sum;
+        return;
+    }
+}
+",
+    ),
+    (
+        "Sys.jack",
+        "class Sys {
+    function void init() {
+        do Main.main();
+        while (true) {}
+        return;
+    }
+}
+",
+    ),
+];
+const RESULT_ADDRESS: u16 = 8000;
+const EXPECTED_RESULT: u16 = 88;
+const MAX_CYCLES: u64 = 1_000_000;
+
+pub fn command() -> Command {
+    Command::new("doctor")
+        .about("Check the toolchain works by taking a small built-in project through every stage in memory")
+}
+
+pub fn run(_matches: &ArgMatches) -> Result<(), ErrorType> {
+    println!(
+        "n2t {} on {} {}",
+        env!("CARGO_PKG_VERSION"),
+        std::env::consts::OS,
+        std::env::consts::ARCH
+    );
+    self_test().map_err(ErrorType::SelfTestFailed)
+}
+
+/// Run the project through each stage, printing how it went. Returns the stage which failed.
+fn self_test() -> Result<(), &'static str> {
+    let result = (|| {
+        let vm_files = stage("compile", || {
+            let vm_files = compiler::compile_strings(&PROJECT)?;
+            Ok((format!("{} classes", vm_files.len()), vm_files))
+        })?;
+        let asm = stage("translate", || {
+            let vm_sources: Vec<(&str, &str)> = vm_files
+                .iter()
+                .map(|(name, contents)| (name.as_str(), contents.as_str()))
+                .collect();
+            let asm = vm_translator::translate_program(&vm_sources)?;
+            Ok((format!("{} lines of assembly", asm.lines().count()), asm))
+        })?;
+        let hack = stage("assemble", || {
+            let hack = assembler::assemble_string(&asm)?;
+            let rom = emulator::parse_hack(&hack)?;
+            Ok((format!("{} instructions", rom.len()), rom))
+        })?;
+        stage("emulate", || {
+            let mut cpu = Cpu::new(hack);
+            // Sys.init never returns, so the program only stops at the limit
+            cpu.run(MAX_CYCLES)?;
+            let result = cpu.peek(RESULT_ADDRESS);
+            if result != EXPECTED_RESULT {
+                return Err(format!(
+                    "RAM[{}] is {} after {} cycles but should be {}",
+                    RESULT_ADDRESS,
+                    result,
+                    cpu.cycles(),
+                    EXPECTED_RESULT
+                )
+                .into());
+            }
+            Ok((format!("RAM[{}] = {}", RESULT_ADDRESS, result), ()))
+        })
+    })();
+
+    if let Err(failed) = result {
+        let skipped = STAGES.iter().skip_while(|stage| **stage != failed).skip(1);
+        for stage in skipped {
+            println!("{:<10} skipped", stage);
+        }
+    }
+    result
+}
+
+/// Run a stage, which returns a summary of its output along with the output itself. A panic is
+/// reported as a failure so that the stages after it are still listed.
+fn stage<T>(
+    name: &'static str,
+    run: impl FnOnce() -> Result<(String, T), Box<dyn Error>>,
+) -> Result<T, &'static str> {
+    let start = Instant::now();
+    match panic::catch_unwind(AssertUnwindSafe(run)) {
+        Ok(Ok((summary, output))) => {
+            println!(
+                "{:<10} ok        {} in {:.2?}",
+                name,
+                summary,
+                start.elapsed()
+            );
+            Ok(output)
+        }
+        Ok(Err(error)) => {
+            println!("{:<10} FAILED", name);
+            crate::print_error(error.as_ref());
+            Err(name)
+        }
+        Err(_) => {
+            println!(
+                "{:<10} FAILED    panicked, please report this as a bug",
+                name
+            );
+            Err(name)
+        }
+    }
+}
+
+#[test]
+fn test_self_test_passes() {
+    assert_eq!(self_test(), Ok(()));
+}
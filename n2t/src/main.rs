@@ -0,0 +1,126 @@
+mod bench;
+mod build;
+mod coverage;
+mod doctor;
+mod project;
+mod tokens;
+mod unit;
+mod verify;
+mod workspace;
+
+use clap::Command;
+use parse_utils::cli::print_error;
+use std::error::Error;
+use std::io;
+use std::path::PathBuf;
+use thiserror::Error;
+
+#[derive(Debug, Error)]
+pub enum ErrorType {
+    #[error("Failed to read {}", .path.display())]
+    ReadError {
+        path: PathBuf,
+        #[source]
+        source: io::Error,
+    },
+    #[error("Failed to write {}", .path.display())]
+    WriteError {
+        path: PathBuf,
+        #[source]
+        source: io::Error,
+    },
+    #[error("Unable to name the output for {}", .0.display())]
+    InvalidPath(PathBuf),
+    #[error("Nothing in {} defines Sys.init. Add the OS .vm files to the project or use --with-os", .0.display())]
+    MissingSysInit(PathBuf),
+    #[error("Expected a .jack, .vm or .asm file but found {}", .0.display())]
+    UnknownFileType(PathBuf),
+    #[error("No .jack files found in {}", .0.display())]
+    NoJackFiles(PathBuf),
+    #[error("{} is not a valid manifest: {message}", .path.display())]
+    InvalidManifest { path: PathBuf, message: String },
+    #[error("The manifest has no target called {0}")]
+    UnknownTarget(String),
+    #[error("--output names a single .hack file, but a manifest builds each target into its own directory")]
+    OutputWithManifest,
+    #[error("{0} tests failed")]
+    TestsFailed(usize),
+    #[error("The translation of {0} functions differs from running them as VM code")]
+    TranslationDiverges(usize),
+    #[error("The self-test failed at the {0} stage")]
+    SelfTestFailed(&'static str),
+    #[error(transparent)]
+    CompilerError(#[from] compiler::ErrorType),
+    #[error(transparent)]
+    TranslatorError(#[from] vm_translator::ErrorType),
+    #[error(transparent)]
+    AssemblerError(#[from] assembler::ErrorType),
+    #[error(transparent)]
+    EmulatorError(#[from] emulator::ErrorType),
+}
+
+fn main() {
+    let matches = Command::new("n2t")
+        .about("The NandToTetris toolchain in a single binary")
+        .subcommand_required(true)
+        .arg_required_else_help(true)
+        .subcommand(
+            compiler::cli::command()
+                .name("compile")
+                .about("Compile Jack source into VM code"),
+        )
+        .subcommand(
+            compiler::fmt_cli::command()
+                .name("fmt")
+                .about("Format Jack source files"),
+        )
+        .subcommand(verify::command())
+        .subcommand(assembler::cli::command().name("assemble"))
+        .subcommand(
+            emulator::cli::command()
+                .name("emulate")
+                .visible_alias("run"),
+        )
+        .subcommand(
+            emulator::debug_cli::command()
+                .name("debug")
+                .about("Step through VM code with breakpoints"),
+        )
+        .subcommand(
+            emulator::repl_cli::command()
+                .name("repl")
+                .about("Run VM commands as they are typed"),
+        )
+        .subcommand(build::command())
+        .subcommand(tokens::command())
+        .subcommand(bench::command())
+        .subcommand(coverage::command())
+        .subcommand(unit::command())
+        .subcommand(doctor::command())
+        .get_matches();
+
+    let result: Result<(), Box<dyn Error>> = match matches.subcommand() {
+        Some(("compile", sub_matches)) => compiler::cli::run(sub_matches).map_err(Box::from),
+        Some(("fmt", sub_matches)) => compiler::fmt_cli::run(sub_matches).map_err(Box::from),
+        Some(("translate", sub_matches)) => verify::run(sub_matches).map_err(Box::from),
+        Some(("assemble", sub_matches)) => assembler::cli::run(sub_matches).map_err(Box::from),
+        Some(("emulate", sub_matches)) => emulator::cli::run(sub_matches).map_err(Box::from),
+        Some(("debug", sub_matches)) => emulator::debug_cli::run(sub_matches).map_err(Box::from),
+        Some(("repl", sub_matches)) => emulator::repl_cli::run(sub_matches).map_err(Box::from),
+        Some(("build", sub_matches)) => build::run(sub_matches).map_err(Box::from),
+        Some(("tokens", sub_matches)) => tokens::run(sub_matches).map_err(Box::from),
+        Some(("bench", sub_matches)) => bench::run(sub_matches).map_err(Box::from),
+        Some(("coverage", sub_matches)) => coverage::run(sub_matches).map_err(Box::from),
+        Some(("test", sub_matches)) => unit::run(sub_matches).map_err(Box::from),
+        Some(("doctor", sub_matches)) => doctor::run(sub_matches).map_err(Box::from),
+        _ => unreachable!("clap requires a subcommand"),
+    };
+
+    if let Err(err) = result {
+        match err.downcast_ref::<compiler::ErrorType>() {
+            Some(error) => compiler::cli::report_error(error, "n2t compile --explain"),
+            None => print_error(err.as_ref()),
+        }
+        std::process::exit(1);
+    }
+}
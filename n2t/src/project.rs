@@ -0,0 +1,57 @@
+use std::fs;
+use std::path::Path;
+
+use crate::ErrorType;
+
+/// Read the .jack files of a project, which is either a single file or a directory, as
+/// (file name, contents) pairs sorted by name
+pub fn read_jack_sources(project: &Path) -> Result<Vec<(String, String)>, ErrorType> {
+    let sources = if project.is_dir() {
+        read_sources(project, "jack")?
+    } else if has_extension(project, "jack") {
+        vec![read_source(project)?]
+    } else {
+        Vec::new()
+    };
+
+    if sources.is_empty() {
+        return Err(ErrorType::NoJackFiles(project.to_owned()));
+    }
+
+    Ok(sources)
+}
+
+/// Read every file in `directory` with the given extension, sorted by name
+pub fn read_sources(directory: &Path, extension: &str) -> Result<Vec<(String, String)>, ErrorType> {
+    let read_error = |source| ErrorType::ReadError {
+        path: directory.to_owned(),
+        source,
+    };
+
+    let mut files = Vec::new();
+    for entry in directory.read_dir().map_err(read_error)? {
+        let path = entry.map_err(read_error)?.path();
+        if path.is_file() && has_extension(&path, extension) {
+            files.push(path);
+        }
+    }
+    files.sort();
+
+    files.iter().map(|file| read_source(file)).collect()
+}
+
+fn read_source(file: &Path) -> Result<(String, String), ErrorType> {
+    let contents = fs::read_to_string(file).map_err(|source| ErrorType::ReadError {
+        path: file.to_owned(),
+        source,
+    })?;
+    let name = file
+        .file_name()
+        .map(|name| name.to_string_lossy().into_owned())
+        .unwrap_or_default();
+    Ok((name, contents))
+}
+
+fn has_extension(path: &Path, extension: &str) -> bool {
+    path.extension().is_some_and(|found| found == extension)
+}
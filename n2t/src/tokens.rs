@@ -0,0 +1,65 @@
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use clap::{Arg, ArgMatches, Command, ValueHint};
+use parse_utils::tokens::{encode_semantic_tokens, render_html, TokenKind};
+use serde_json::json;
+
+use crate::ErrorType;
+
+pub fn command() -> Command {
+    Command::new("tokens")
+        .about("Export highlighting tokens for a .jack, .vm or .asm file")
+        .arg(
+            Arg::new("INPUT")
+                .index(1)
+                .required(true)
+                .value_name("FILE")
+                .value_hint(ValueHint::FilePath)
+                .help("A Jack, VM or Hack assembly file"),
+        )
+        .arg(
+            Arg::new("format")
+                .long("format")
+                .value_parser(["semantic-tokens", "html"])
+                .default_value("semantic-tokens")
+                .help("Print LSP semantic tokens as JSON or the source as annotated HTML"),
+        )
+}
+
+pub fn run(matches: &ArgMatches) -> Result<(), ErrorType> {
+    let path = Path::new(
+        matches
+            .get_one::<String>("INPUT")
+            .expect("User to provide an input path"),
+    );
+
+    let source = fs::read_to_string(path).map_err(|source| ErrorType::ReadError {
+        path: PathBuf::from(path),
+        source,
+    })?;
+
+    let tokens = match path.extension().and_then(|extension| extension.to_str()) {
+        Some("jack") => compiler::tokenize_jack(&source),
+        Some("vm") => vm_translator::tokenize_vm(&source),
+        Some("asm") => assembler::tokenize_hack(&source),
+        _ => return Err(ErrorType::UnknownFileType(path.to_owned())),
+    };
+
+    match matches.get_one::<String>("format").map(|s| s.as_str()) {
+        Some("html") => print!("{}", render_html(&source, &tokens)),
+        _ => {
+            let legend: Vec<&str> = TokenKind::ALL.iter().map(|kind| kind.name()).collect();
+            let output = json!({
+                "legend": {
+                    "tokenTypes": legend,
+                    "tokenModifiers": [],
+                },
+                "data": encode_semantic_tokens(&source, &tokens),
+            });
+            println!("{}", output);
+        }
+    }
+
+    Ok(())
+}
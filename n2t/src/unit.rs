@@ -0,0 +1,253 @@
+use std::path::Path;
+
+use clap::{value_parser, Arg, ArgMatches, Command, ValueHint};
+use compiler::ast::SubroutineType;
+use emulator::{Stop, VmMachine};
+
+use crate::{project, ErrorType};
+
+/// The Assert class test functions call, for projects which don't have their own
+const ASSERT_CLASS: (&str, &str) = ("Assert.jack", include_str!("Assert.jack"));
+
+/// The OS classes initialized before the tests run, in the order Sys.init calls them
+const OS_INIT: [&str; 5] = [
+    "Memory.init",
+    "Math.init",
+    "Screen.init",
+    "Output.init",
+    "Keyboard.init",
+];
+
+pub fn command() -> Command {
+    Command::new("test")
+        .about("Run the test functions of a Jack project's *Test classes on the VM emulator")
+        .arg(
+            Arg::new("PROJECT")
+                .index(1)
+                .required(true)
+                .value_hint(ValueHint::AnyPath)
+                .help("A .jack file or a directory of .jack files. .vm files in the directory are linked in, along with the built-in OS"),
+        )
+        .arg(
+            Arg::new("filter")
+                .long("filter")
+                .value_name("TEXT")
+                .help("Only run the tests whose name, e.g. MathTest.testAdd, contains TEXT"),
+        )
+        .arg(
+            Arg::new("cycles")
+                .long("cycles")
+                .value_name("COUNT")
+                .value_parser(value_parser!(u64))
+                .default_value("1000000")
+                .help("Fail a test which hasn't returned after this many VM commands"),
+        )
+}
+
+pub fn run(matches: &ArgMatches) -> Result<(), ErrorType> {
+    let project = Path::new(
+        matches
+            .get_one::<String>("PROJECT")
+            .expect("User to provide a project"),
+    );
+    let filter = matches.get_one::<String>("filter").map(String::as_str);
+    let max_cycles = *matches
+        .get_one::<u64>("cycles")
+        .expect("cycles has a default");
+
+    let jack_sources = project::read_jack_sources(project)?;
+    let vm_sources = if project.is_dir() {
+        project::read_sources(project, "vm")?
+    } else {
+        Vec::new()
+    };
+    let jack_sources: Vec<(&str, &str)> = jack_sources
+        .iter()
+        .map(|(name, contents)| (name.as_str(), contents.as_str()))
+        .collect();
+    let vm_sources: Vec<(&str, &str)> = vm_sources
+        .iter()
+        .map(|(name, contents)| (name.as_str(), contents.as_str()))
+        .collect();
+
+    let results = run_tests(&jack_sources, &vm_sources, filter, max_cycles)?;
+    for result in &results {
+        let outcome = if result.failures.is_empty() {
+            "ok"
+        } else {
+            "FAILED"
+        };
+        println!("test {} ... {}", result.name, outcome);
+    }
+
+    let failed: Vec<&TestResult> = results
+        .iter()
+        .filter(|result| !result.failures.is_empty())
+        .collect();
+    if !failed.is_empty() {
+        println!("\nfailures:");
+        for result in &failed {
+            for failure in &result.failures {
+                println!("    {}: {}", result.name, failure);
+            }
+        }
+    }
+    println!(
+        "\ntest result: {}. {} passed; {} failed",
+        if failed.is_empty() { "ok" } else { "FAILED" },
+        results.len() - failed.len(),
+        failed.len()
+    );
+
+    if failed.is_empty() {
+        Ok(())
+    } else {
+        Err(ErrorType::TestsFailed(failed.len()))
+    }
+}
+
+struct TestResult {
+    /// The test function, e.g. `MathTest.testAdd`
+    name: String,
+    /// Why the test failed, if it did
+    failures: Vec<String>,
+}
+
+/// Compile a project and run each `test*` function of its `*Test` classes, starting each one from
+/// the same freshly initialized machine
+fn run_tests(
+    jack_sources: &[(&str, &str)],
+    vm_sources: &[(&str, &str)],
+    filter: Option<&str>,
+    max_cycles: u64,
+) -> Result<Vec<TestResult>, ErrorType> {
+    let mut jack_sources = jack_sources.to_vec();
+    if !jack_sources.iter().any(|(name, _)| *name == ASSERT_CLASS.0) {
+        jack_sources.push(ASSERT_CLASS);
+    }
+    let ast = compiler::parse_strings(&jack_sources)?;
+    let tests: Vec<String> = ast
+        .classes
+        .iter()
+        .map(|compiled| &compiled.class)
+        .filter(|class| class.get_name().as_str().ends_with("Test"))
+        .flat_map(|class| {
+            class
+                .subroutines()
+                .iter()
+                .filter(|subroutine| {
+                    subroutine.get_subroutine_type() == SubroutineType::Function
+                        && subroutine.get_name().as_str().starts_with("test")
+                        && subroutine.get_parameters().is_empty()
+                })
+                .map(move |subroutine| format!("{}.{}", class.get_name(), subroutine.get_name()))
+        })
+        .filter(|name| filter.is_none_or(|filter| name.contains(filter)))
+        .collect();
+
+    let vm_files = compiler::compile_ast(&ast)?;
+    let mut sources: Vec<(&str, &str)> = vm_files
+        .iter()
+        .map(|(name, contents)| (name.as_str(), contents.as_str()))
+        .collect();
+    for (name, contents) in vm_sources {
+        if !sources.iter().any(|(compiled, _)| compiled == name) {
+            sources.push((name, contents));
+        }
+    }
+    let sources = vm_translator::link_os(&sources);
+
+    // Every test starts from a copy of the machine as it is once the OS is initialized
+    let mut snapshot = VmMachine::load(&sources)?;
+    snapshot.check_assertions();
+    for init in OS_INIT {
+        if snapshot.has_function(init) {
+            snapshot.start(init)?;
+            snapshot.run(max_cycles)?;
+        }
+    }
+
+    let mut results = Vec::with_capacity(tests.len());
+    for name in tests {
+        let mut vm = snapshot.clone();
+        vm.start(&name)?;
+        let stop = vm.run(max_cycles);
+        let mut failures: Vec<String> = vm
+            .take_assertion_failures()
+            .into_iter()
+            .map(|failure| failure.message)
+            .collect();
+        match stop {
+            Ok(Stop::EndOfProgram) => {}
+            Ok(Stop::Halted) => failures.push("halted before returning".to_owned()),
+            Ok(Stop::CycleLimit) => {
+                failures.push(format!("didn't return within {} commands", max_cycles))
+            }
+            Err(error) => failures.push(error.to_string()),
+        }
+        results.push(TestResult { name, failures });
+    }
+    Ok(results)
+}
+
+#[test]
+fn test_run_tests() {
+    let sources = [
+        (
+            "Counter.jack",
+            "class Counter {
+                function int double(int x) { return x + x; }
+            }",
+        ),
+        (
+            "CounterTest.jack",
+            "class CounterTest {
+                function void testDouble() {
+                    do Assert.equals(8, Counter.double(4));
+                    return;
+                }
+                function void testWrong() {
+                    do Assert.equals(7, Counter.double(4));
+                    do Assert.isTrue(false);
+                    return;
+                }
+                function void testLoops() {
+                    while (true) {}
+                    return;
+                }
+                function void helper() { return; }
+            }",
+        ),
+    ];
+    let results = run_tests(&sources, &[], None, 10000).unwrap();
+    let outcomes: Vec<(&str, Vec<&str>)> = results
+        .iter()
+        .map(|result| {
+            (
+                result.name.as_str(),
+                result.failures.iter().map(String::as_str).collect(),
+            )
+        })
+        .collect();
+
+    assert_eq!(
+        outcomes,
+        [
+            ("CounterTest.testDouble", vec![]),
+            (
+                "CounterTest.testWrong",
+                vec![
+                    "Assert.equals: expected 7 but found 8",
+                    "Assert.isTrue: expected true but found false",
+                ]
+            ),
+            (
+                "CounterTest.testLoops",
+                vec!["didn't return within 10000 commands"]
+            ),
+        ]
+    );
+
+    let filtered = run_tests(&sources, &[], Some("Double"), 10000).unwrap();
+    assert_eq!(filtered.len(), 1);
+}
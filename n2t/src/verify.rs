@@ -0,0 +1,63 @@
+use std::path::Path;
+
+use clap::{Arg, ArgAction, ArgMatches, Command};
+use emulator::Outcome;
+
+use crate::ErrorType;
+
+/// `translate`, with `--verify` added. The check lives here rather than in the translator, as it
+/// needs the emulator, which depends on the translator.
+pub fn command() -> Command {
+    vm_translator::cli::command().name("translate").arg(
+        Arg::new("verify")
+            .long("verify")
+            .action(ArgAction::SetTrue)
+            .help("Also run each function as VM commands and as the translated assembly, reporting the first command after which memory differs"),
+    )
+}
+
+pub fn run(matches: &ArgMatches) -> Result<(), ErrorType> {
+    vm_translator::cli::run(matches)?;
+    if !matches.get_flag("verify") {
+        return Ok(());
+    }
+
+    let mut files = Vec::new();
+    for path in matches
+        .get_many::<String>("INPUT")
+        .expect("User to provide an input path")
+    {
+        files.extend(emulator::read_vm_sources(Path::new(path))?);
+    }
+    let sources: Vec<(&str, &str)> = files
+        .iter()
+        .map(|(name, contents)| (name.as_str(), contents.as_str()))
+        .collect();
+    let sources = if matches.get_flag("with_os") {
+        vm_translator::link_os(&sources)
+    } else {
+        sources
+    };
+
+    let checks = emulator::verify_translation(&sources)?;
+    let mut skipped = 0;
+    let mut diverged = 0;
+    for check in &checks {
+        match check.outcome {
+            Outcome::Matched => continue,
+            Outcome::Skipped(_) => skipped += 1,
+            Outcome::Diverged { .. } => diverged += 1,
+        }
+        println!("{}", check);
+    }
+    println!(
+        "{} functions verified, {} diverged, {} skipped",
+        checks.len() - diverged - skipped,
+        diverged,
+        skipped
+    );
+    if diverged > 0 {
+        return Err(ErrorType::TranslationDiverges(diverged));
+    }
+    Ok(())
+}
@@ -0,0 +1,113 @@
+//! Projects of several programs, e.g. games sharing a library of Jack classes, described by an
+//! `n2t.toml` manifest:
+//!
+//! ```toml
+//! shared = ["common"]
+//! with_os = true
+//!
+//! [[target]]
+//! name = "Pong"
+//! path = "pong"
+//! ```
+//!
+//! Paths are relative to the manifest. Each target is built from its own directory along with the
+//! shared ones, into `build/NAME/NAME.hack` unless it gives an `output` directory.
+
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use serde::Deserialize;
+
+use crate::{project, ErrorType};
+
+pub const MANIFEST: &str = "n2t.toml";
+
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Manifest {
+    /// Directories of classes which every target is built with
+    #[serde(default)]
+    pub shared: Vec<PathBuf>,
+    /// Link the built-in OS into every target
+    #[serde(default)]
+    pub with_os: bool,
+    #[serde(rename = "target")]
+    pub targets: Vec<Target>,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Target {
+    pub name: String,
+    /// The directory of the target's own classes
+    pub path: PathBuf,
+    /// The directory the .hack file is written to
+    pub output: Option<PathBuf>,
+}
+
+/// The sources of a target, as (file name, contents) pairs
+pub struct TargetSources {
+    pub jack: Vec<(String, String)>,
+    /// .vm files which are linked in as they are, e.g. a prebuilt OS
+    pub vm: Vec<(String, String)>,
+}
+
+/// The manifest `project` names, either directly or as the directory holding it
+pub fn find_manifest(project: &Path) -> Option<PathBuf> {
+    if project.is_dir() {
+        Some(project.join(MANIFEST)).filter(|manifest| manifest.is_file())
+    } else {
+        (project.file_name()? == MANIFEST).then(|| project.to_owned())
+    }
+}
+
+pub fn read_manifest(path: &Path) -> Result<Manifest, ErrorType> {
+    let contents = fs::read_to_string(path).map_err(|source| ErrorType::ReadError {
+        path: path.to_owned(),
+        source,
+    })?;
+    toml::from_str(&contents).map_err(|error| ErrorType::InvalidManifest {
+        path: path.to_owned(),
+        message: error.message().to_owned(),
+    })
+}
+
+impl Target {
+    /// Read the target's files and then those of the shared directories. A class of the target
+    /// replaces a shared class of the same name, and earlier shared directories win over later
+    /// ones.
+    pub fn sources(&self, root: &Path, shared: &[PathBuf]) -> Result<TargetSources, ErrorType> {
+        let mut sources = TargetSources {
+            jack: Vec::new(),
+            vm: Vec::new(),
+        };
+        for directory in std::iter::once(&self.path).chain(shared) {
+            let directory = root.join(directory);
+            add_missing(
+                &mut sources.jack,
+                project::read_sources(&directory, "jack")?,
+            );
+            add_missing(&mut sources.vm, project::read_sources(&directory, "vm")?);
+        }
+        if sources.jack.is_empty() {
+            return Err(ErrorType::NoJackFiles(root.join(&self.path)));
+        }
+        Ok(sources)
+    }
+
+    pub fn output_file(&self, root: &Path) -> PathBuf {
+        let directory = match &self.output {
+            Some(output) => root.join(output),
+            None => root.join("build").join(&self.name),
+        };
+        directory.join(format!("{}.hack", self.name))
+    }
+}
+
+fn add_missing(files: &mut Vec<(String, String)>, more: Vec<(String, String)>) {
+    for (name, contents) in more {
+        if !files.iter().any(|(existing, _)| *existing == name) {
+            files.push((name, contents));
+        }
+    }
+}
@@ -0,0 +1,50 @@
+//! Pieces of the command line interfaces shared by the tools.
+
+use std::error::Error;
+use std::io::{self, IsTerminal};
+
+use clap::ArgMatches;
+use tracing::Level;
+use tracing_subscriber::fmt::format::FmtSpan;
+
+use crate::output::WriteMode;
+
+/// Print an error followed by the chain of errors which caused it
+pub fn print_error(error: &dyn Error) {
+    println!("{}", error);
+    let mut source = error.source();
+    while let Some(cause) = source {
+        println!("  caused by: {}", cause);
+        source = cause.source();
+    }
+}
+
+/// Whether a tool's `dry_run` flag asks for its outputs to only be reported
+pub fn write_mode(matches: &ArgMatches) -> WriteMode {
+    if matches.get_flag("dry_run") {
+        WriteMode::DryRun
+    } else {
+        WriteMode::Write
+    }
+}
+
+/// Log to stderr when a tool's `trace` or `timings` flag is set: every event with `trace`, and
+/// how long each span took with `timings`
+pub fn init_tracing(matches: &ArgMatches) {
+    let trace = matches.get_flag("trace");
+    let timings = matches.get_flag("timings");
+    if !trace && !timings {
+        return;
+    }
+
+    tracing_subscriber::fmt()
+        .with_max_level(if trace { Level::TRACE } else { Level::INFO })
+        .with_span_events(if timings {
+            FmtSpan::CLOSE
+        } else {
+            FmtSpan::NONE
+        })
+        .with_writer(io::stderr)
+        .with_ansi(io::stderr().is_terminal())
+        .init();
+}
@@ -0,0 +1,167 @@
+//! Parser combinators shared by the assembler, VM translator and compiler front ends.
+//!
+//! Everything here is generic over the input type so it works on plain `&str` as well as
+//! `nom_locate::LocatedSpan<&str>`, which the compiler uses to keep track of positions.
+
+pub mod cli;
+pub mod output;
+pub mod source;
+pub mod tokens;
+pub mod watch;
+
+use std::ops::{Range, RangeFrom, RangeTo};
+
+use nom::branch::alt;
+use nom::bytes::complete::{tag, take_until, take_while};
+use nom::character::complete::{multispace1, not_line_ending, satisfy};
+use nom::combinator::{recognize, value};
+use nom::error::ParseError;
+use nom::multi::{fold_many0, fold_many1};
+use nom::sequence::{pair, tuple};
+use nom::{
+    Compare, FindSubstring, IResult, InputIter, InputLength, InputTake, InputTakeAtPosition,
+    Offset, Slice,
+};
+
+/// Text input which can be fed to the combinators in this crate.
+pub trait TextInput:
+    Clone
+    + Offset
+    + InputLength
+    + InputTake
+    + InputIter<Item = char>
+    + InputTakeAtPosition<Item = char>
+    + Slice<Range<usize>>
+    + Slice<RangeFrom<usize>>
+    + Slice<RangeTo<usize>>
+    + Compare<&'static str>
+    + FindSubstring<&'static str>
+{
+}
+
+impl<T> TextInput for T where
+    T: Clone
+        + Offset
+        + InputLength
+        + InputTake
+        + InputIter<Item = char>
+        + InputTakeAtPosition<Item = char>
+        + Slice<Range<usize>>
+        + Slice<RangeFrom<usize>>
+        + Slice<RangeTo<usize>>
+        + Compare<&'static str>
+        + FindSubstring<&'static str>
+{
+}
+
+/// Recognise an identifier.
+///
+/// Identifiers start with an ASCII letter or `_` and continue with letters, digits, `_` or
+/// any of the characters in `extra_chars`.
+pub fn identifier<I, E>(extra_chars: &'static str) -> impl FnMut(I) -> IResult<I, I, E>
+where
+    I: TextInput,
+    E: ParseError<I>,
+{
+    recognize(pair(
+        satisfy(|c| c.is_ascii_alphabetic() || c == '_'),
+        take_while(move |c: char| c.is_ascii_alphanumeric() || c == '_' || extra_chars.contains(c)),
+    ))
+}
+
+/// Recognise a `//` comment up to (but not including) the end of the line.
+pub fn line_comment<I, E>(i: I) -> IResult<I, I, E>
+where
+    I: TextInput,
+    E: ParseError<I>,
+{
+    recognize(pair(tag("//"), not_line_ending))(i)
+}
+
+/// Recognise a `/* */` comment, which may span several lines.
+pub fn block_comment<I, E>(i: I) -> IResult<I, I, E>
+where
+    I: TextInput,
+    E: ParseError<I>,
+{
+    recognize(tuple((tag("/*"), take_until("*/"), tag("*/"))))(i)
+}
+
+fn whitespace_or_comment<I, E>(i: I) -> IResult<I, (), E>
+where
+    I: TextInput,
+    E: ParseError<I>,
+{
+    alt((
+        value((), block_comment),
+        value((), line_comment),
+        value((), multispace1),
+    ))(i)
+}
+
+/// Skip zero or more whitespace characters and comments.
+pub fn whitespace0<I, E>(i: I) -> IResult<I, (), E>
+where
+    I: TextInput,
+    E: ParseError<I>,
+{
+    fold_many0(whitespace_or_comment, || (), |_, _| ())(i)
+}
+
+/// Skip at least one whitespace character or comment.
+pub fn whitespace1<I, E>(i: I) -> IResult<I, (), E>
+where
+    I: TextInput,
+    E: ParseError<I>,
+{
+    fold_many1(whitespace_or_comment, || (), |_, _| ())(i)
+}
+
+#[cfg(test)]
+type TestResult<'a, O> = IResult<&'a str, O, nom::error::Error<&'a str>>;
+
+#[test]
+fn test_identifier() {
+    let mut plain = identifier::<&str, nom::error::Error<&str>>("");
+    assert_eq!(plain("my_var1 = 2"), Ok((" = 2", "my_var1")));
+    assert_eq!(plain("_hidden"), Ok(("", "_hidden")));
+    assert_eq!(plain("Main.main"), Ok((".main", "Main")));
+    assert!(plain("1abc").is_err());
+
+    let mut symbol = identifier::<&str, nom::error::Error<&str>>(".$");
+    assert_eq!(symbol("Main.main$if_0"), Ok(("", "Main.main$if_0")));
+}
+
+#[test]
+fn test_identifier_on_span() {
+    use nom_locate::LocatedSpan;
+
+    let input = LocatedSpan::new("counter;");
+    let (rest, name) = identifier::<_, nom::error::Error<_>>("")(input).unwrap();
+    assert_eq!(*name.fragment(), "counter");
+    assert_eq!(rest.location_offset(), 7);
+}
+
+#[test]
+fn test_comments() {
+    let result: TestResult<&str> = line_comment("// a comment\nnext");
+    assert_eq!(result, Ok(("\nnext", "// a comment")));
+
+    let result: TestResult<&str> = line_comment("//");
+    assert_eq!(result, Ok(("", "//")));
+
+    let result: TestResult<&str> = block_comment("/* a\n * b */ rest");
+    assert_eq!(result, Ok((" rest", "/* a\n * b */")));
+}
+
+#[test]
+fn test_whitespace() {
+    let result: TestResult<()> = whitespace0("  // comment\n /** doc */\n\tclass");
+    assert_eq!(result, Ok(("class", ())));
+
+    let result: TestResult<()> = whitespace0("class");
+    assert_eq!(result, Ok(("class", ())));
+
+    let result: TestResult<()> = whitespace1("class");
+    assert!(result.is_err());
+}
@@ -0,0 +1,89 @@
+//! Writing the files produced by the tools.
+
+use std::ffi::OsString;
+use std::fs::{self, File};
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+
+/// Whether a tool writes its outputs or only reports them
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum WriteMode {
+    #[default]
+    Write,
+    /// Print the path and size of each output instead of writing it
+    DryRun,
+}
+
+/// Where to write an output: at `path`, next to the source it came from, or under the same
+/// name in `out_dir` if one was given
+pub fn in_out_dir(path: &Path, out_dir: Option<&Path>) -> PathBuf {
+    match (out_dir, path.file_name()) {
+        (Some(out_dir), Some(file_name)) => out_dir.join(file_name),
+        _ => path.to_owned(),
+    }
+}
+
+/// Write an output file according to `mode`, creating its directory if needed
+pub fn write_output(path: &Path, contents: &[u8], mode: WriteMode) -> io::Result<()> {
+    match mode {
+        WriteMode::Write => {
+            if let Some(dir) = path.parent().filter(|dir| !dir.as_os_str().is_empty()) {
+                fs::create_dir_all(dir)?;
+            }
+            write_atomic(path, contents)
+        }
+        WriteMode::DryRun => {
+            println!("Would write {} ({} bytes)", path.display(), contents.len());
+            Ok(())
+        }
+    }
+}
+
+/// Write to a temporary file next to `path` and rename it into place, so readers never see a
+/// partially written file
+pub fn write_atomic(path: &Path, contents: &[u8]) -> io::Result<()> {
+    let file_name = path
+        .file_name()
+        .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidInput, "path has no file name"))?;
+    let mut temp_name = OsString::from(".");
+    temp_name.push(file_name);
+    temp_name.push(format!(".{}.tmp", std::process::id()));
+    let temp_path = path.with_file_name(temp_name);
+
+    let result = File::create(&temp_path)
+        .and_then(|mut file| {
+            file.write_all(contents)?;
+            file.sync_all()
+        })
+        .and_then(|_| fs::rename(&temp_path, path));
+
+    if result.is_err() {
+        let _ = fs::remove_file(&temp_path);
+    }
+    result
+}
+
+#[test]
+fn test_write_modes() {
+    let dir = std::env::temp_dir().join(format!("parse-utils-output-{}", std::process::id()));
+    fs::create_dir_all(&dir).unwrap();
+    let path = dir.join("Main.vm");
+
+    write_output(&path, b"push constant 1", WriteMode::DryRun).unwrap();
+    assert!(!path.exists());
+
+    fs::write(&path, "old contents which are longer").unwrap();
+    write_output(&path, b"push constant 1", WriteMode::Write).unwrap();
+    assert_eq!(fs::read_to_string(&path).unwrap(), "push constant 1");
+
+    // Only the output is left behind
+    assert_eq!(fs::read_dir(&dir).unwrap().count(), 1);
+
+    let nested = in_out_dir(&path, Some(&dir.join("build")));
+    assert_eq!(nested, dir.join("build").join("Main.vm"));
+    write_output(&nested, b"push constant 2", WriteMode::Write).unwrap();
+    assert_eq!(fs::read_to_string(&nested).unwrap(), "push constant 2");
+    assert_eq!(in_out_dir(&path, None), path);
+
+    fs::remove_dir_all(&dir).unwrap();
+}
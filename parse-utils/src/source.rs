@@ -0,0 +1,82 @@
+//! Loading source files for the parsers.
+
+use std::fs::File;
+use std::io::{self, Read};
+use std::ops::Deref;
+use std::path::Path;
+
+use memmap2::Mmap;
+
+/// Files at least this large are memory-mapped rather than copied into a `String`
+pub const MMAP_THRESHOLD: u64 = 1024 * 1024;
+
+/// The UTF-8 contents of a source file, either read into memory or memory-mapped
+pub enum Source {
+    Read(String),
+    Mapped(Mmap),
+}
+
+impl Source {
+    /// Load a source file, memory-mapping it if it is at least [`MMAP_THRESHOLD`] bytes. Falls
+    /// back to reading the file when it can't be mapped.
+    pub fn open(path: &Path) -> io::Result<Self> {
+        let mut file = File::open(path)?;
+        let len = file.metadata()?.len();
+
+        if len >= MMAP_THRESHOLD {
+            // SAFETY: the map is read only and dropped with the Source. As with reading, the
+            // tools assume their input isn't being rewritten while they run.
+            if let Ok(map) = unsafe { Mmap::map(&file) } {
+                std::str::from_utf8(&map)
+                    .map_err(|err| io::Error::new(io::ErrorKind::InvalidData, err))?;
+                return Ok(Source::Mapped(map));
+            }
+        }
+
+        let mut contents = String::with_capacity(len as usize);
+        file.read_to_string(&mut contents)?;
+        Ok(Source::Read(contents))
+    }
+}
+
+impl Deref for Source {
+    type Target = str;
+
+    fn deref(&self) -> &str {
+        match self {
+            Source::Read(contents) => contents,
+            // SAFETY: the mapping was checked to be valid UTF-8 in Source::open
+            Source::Mapped(map) => unsafe { std::str::from_utf8_unchecked(map) },
+        }
+    }
+}
+
+#[test]
+fn test_open_small_and_large_files() {
+    let dir = std::env::temp_dir().join(format!("parse-utils-source-{}", std::process::id()));
+    std::fs::create_dir_all(&dir).unwrap();
+
+    let small = dir.join("small.asm");
+    std::fs::write(&small, "@i\nM=1").unwrap();
+    let source = Source::open(&small).unwrap();
+    assert!(matches!(source, Source::Read(_)));
+    assert_eq!(&*source, "@i\nM=1");
+
+    let large = dir.join("large.asm");
+    let contents = "D=M\n".repeat(MMAP_THRESHOLD as usize / 4);
+    std::fs::write(&large, &contents).unwrap();
+    let source = Source::open(&large).unwrap();
+    assert!(matches!(source, Source::Mapped(_)));
+    assert_eq!(&*source, contents);
+
+    let invalid = dir.join("invalid.asm");
+    let mut bytes = contents.into_bytes();
+    bytes[10] = 0xff;
+    std::fs::write(&invalid, bytes).unwrap();
+    assert_eq!(
+        Source::open(&invalid).err().unwrap().kind(),
+        io::ErrorKind::InvalidData
+    );
+
+    std::fs::remove_dir_all(&dir).unwrap();
+}
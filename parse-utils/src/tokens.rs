@@ -0,0 +1,199 @@
+//! Token classification shared by the three front ends, used for editor highlighting.
+
+/// The kind of a token, named after the standard LSP semantic token types
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TokenKind {
+    Keyword,
+    Type,
+    Function,
+    Label,
+    Variable,
+    Number,
+    String,
+    Comment,
+    Operator,
+}
+
+impl TokenKind {
+    /// Every token kind in the order used by the semantic token legend
+    pub const ALL: [TokenKind; 9] = [
+        TokenKind::Keyword,
+        TokenKind::Type,
+        TokenKind::Function,
+        TokenKind::Label,
+        TokenKind::Variable,
+        TokenKind::Number,
+        TokenKind::String,
+        TokenKind::Comment,
+        TokenKind::Operator,
+    ];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            TokenKind::Keyword => "keyword",
+            TokenKind::Type => "type",
+            TokenKind::Function => "function",
+            TokenKind::Label => "label",
+            TokenKind::Variable => "variable",
+            TokenKind::Number => "number",
+            TokenKind::String => "string",
+            TokenKind::Comment => "comment",
+            TokenKind::Operator => "operator",
+        }
+    }
+}
+
+/// A classified region of source text, as a byte offset and length
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Token {
+    pub offset: usize,
+    pub length: usize,
+    pub kind: TokenKind,
+}
+
+impl Token {
+    pub fn new(offset: usize, length: usize, kind: TokenKind) -> Self {
+        Self {
+            offset,
+            length,
+            kind,
+        }
+    }
+}
+
+/// Encode tokens in the LSP semantic token format.
+///
+/// Each token becomes five numbers: the line delta, the start column (relative to the previous
+/// token if it is on the same line), the length, the index into `TokenKind::ALL` and an empty
+/// modifier set. Tokens which span several lines (block comments) are split per line. Columns and
+/// lengths count UTF-16 code units, as LSP clients do by default.
+pub fn encode_semantic_tokens(source: &str, tokens: &[Token]) -> Vec<u32> {
+    let line_starts: Vec<usize> = std::iter::once(0)
+        .chain(source.match_indices('\n').map(|(index, _)| index + 1))
+        .collect();
+
+    let mut data = Vec::with_capacity(tokens.len() * 5);
+    let mut previous_line = 0;
+    let mut previous_column = 0;
+
+    for token in tokens {
+        let text = &source[token.offset..token.offset + token.length];
+        let mut offset = token.offset;
+        for part in text.split('\n') {
+            let line = line_starts.partition_point(|start| *start <= offset) - 1;
+            let column = source[line_starts[line]..offset].encode_utf16().count();
+
+            if !part.is_empty() {
+                let delta_line = line - previous_line;
+                let delta_column = if delta_line == 0 {
+                    column - previous_column
+                } else {
+                    column
+                };
+                let kind_index = TokenKind::ALL
+                    .iter()
+                    .position(|kind| *kind == token.kind)
+                    .unwrap_or_default();
+
+                data.extend([
+                    delta_line as u32,
+                    delta_column as u32,
+                    part.encode_utf16().count() as u32,
+                    kind_index as u32,
+                    0,
+                ]);
+                previous_line = line;
+                previous_column = column;
+            }
+
+            offset += part.len() + 1;
+        }
+    }
+
+    data
+}
+
+/// Render the source as an HTML `<pre>` block with a `<span class="tok-KIND">` per token
+pub fn render_html(source: &str, tokens: &[Token]) -> String {
+    let mut html = String::from("<pre class=\"n2t-source\">");
+    let mut position = 0;
+
+    for token in tokens {
+        html.push_str(&escape_html(&source[position..token.offset]));
+        html.push_str(&format!(
+            "<span class=\"tok-{}\">{}</span>",
+            token.kind.name(),
+            escape_html(&source[token.offset..token.offset + token.length])
+        ));
+        position = token.offset + token.length;
+    }
+    html.push_str(&escape_html(&source[position..]));
+    html.push_str("</pre>\n");
+
+    html
+}
+
+fn escape_html(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
+
+#[test]
+fn test_encode_semantic_tokens() {
+    let source = "push constant 7\n  add";
+    let tokens = vec![
+        Token::new(0, 4, TokenKind::Keyword),
+        Token::new(5, 8, TokenKind::Variable),
+        Token::new(14, 1, TokenKind::Number),
+        Token::new(18, 3, TokenKind::Keyword),
+    ];
+
+    assert_eq!(
+        encode_semantic_tokens(source, &tokens),
+        vec![0, 0, 4, 0, 0, 0, 5, 8, 4, 0, 0, 9, 1, 5, 0, 1, 2, 3, 0, 0]
+    );
+}
+
+#[test]
+fn test_encode_multiline_token() {
+    let source = "/* a\nbc */ x";
+    let tokens = vec![
+        Token::new(0, 10, TokenKind::Comment),
+        Token::new(11, 1, TokenKind::Variable),
+    ];
+
+    assert_eq!(
+        encode_semantic_tokens(source, &tokens),
+        vec![0, 0, 4, 7, 0, 1, 0, 5, 7, 0, 0, 6, 1, 4, 0]
+    );
+}
+
+#[test]
+fn test_encode_columns_in_utf16() {
+    // é is two bytes but one UTF-16 unit, and 𝄞 is four bytes but two units
+    let source = "\"é𝄞\" x";
+    let tokens = vec![
+        Token::new(0, 8, TokenKind::String),
+        Token::new(9, 1, TokenKind::Variable),
+    ];
+
+    assert_eq!(
+        encode_semantic_tokens(source, &tokens),
+        vec![0, 0, 5, 6, 0, 0, 6, 1, 4, 0]
+    );
+}
+
+#[test]
+fn test_render_html() {
+    let tokens = vec![
+        Token::new(0, 2, TokenKind::Keyword),
+        Token::new(3, 1, TokenKind::Operator),
+    ];
+
+    assert_eq!(
+        render_html("do <", &tokens),
+        "<pre class=\"n2t-source\"><span class=\"tok-keyword\">do</span> <span class=\"tok-operator\">&lt;</span></pre>\n"
+    );
+}
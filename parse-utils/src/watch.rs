@@ -0,0 +1,90 @@
+//! Rebuilding whenever the sources change, for `--watch`.
+
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::thread;
+use std::time::{Duration, SystemTime};
+
+/// How often the sources are checked for changes
+const POLL_INTERVAL: Duration = Duration::from_millis(250);
+
+/// Run `build`, and then again each time one of the sources changes, until the process is killed.
+///
+/// A file in `paths` is watched whatever its extension, and a directory for the files directly in
+/// it with one of `extensions`, so that files being added or removed are noticed too. Errors are
+/// passed to `report` rather than returned, as the next change may fix them.
+pub fn watch<E>(
+    paths: &[&Path],
+    extensions: &[&str],
+    mut build: impl FnMut() -> Result<(), E>,
+    report: impl Fn(&E),
+) -> ! {
+    let mut sources = snapshot(paths, extensions);
+    loop {
+        match build() {
+            Ok(()) => println!("Build succeeded"),
+            Err(error) => report(&error),
+        }
+        println!("Watching for changes, press Ctrl-C to stop");
+
+        loop {
+            thread::sleep(POLL_INTERVAL);
+            let current = snapshot(paths, extensions);
+            if current != sources {
+                sources = current;
+                break;
+            }
+        }
+        println!();
+        println!("Sources changed, rebuilding");
+    }
+}
+
+/// The modification time and length of each source. The length catches a change made within the
+/// resolution of the file system's timestamps.
+fn snapshot(paths: &[&Path], extensions: &[&str]) -> BTreeMap<PathBuf, Option<(SystemTime, u64)>> {
+    let mut files = Vec::new();
+    for path in paths {
+        match fs::read_dir(path) {
+            Ok(entries) => {
+                files.extend(entries.flatten().map(|entry| entry.path()).filter(|file| {
+                    file.extension()
+                        .is_some_and(|extension| extensions.iter().any(|known| extension == *known))
+                }))
+            }
+            Err(_) => files.push(path.to_path_buf()),
+        }
+    }
+    files
+        .into_iter()
+        .map(|file| {
+            let metadata = fs::metadata(&file).ok();
+            let stamp =
+                metadata.and_then(|metadata| Some((metadata.modified().ok()?, metadata.len())));
+            (file, stamp)
+        })
+        .collect()
+}
+
+#[test]
+fn test_snapshot_notices_changes() {
+    let dir = std::env::temp_dir().join(format!("parse-utils-watch-{}", std::process::id()));
+    fs::create_dir_all(&dir).unwrap();
+    fs::write(dir.join("Main.jack"), "class Main {}").unwrap();
+    fs::write(dir.join("Main.vm"), "").unwrap();
+    let take = || snapshot(&[&dir], &["jack"]);
+
+    let before = take();
+    assert_eq!(before.len(), 1);
+    fs::write(dir.join("Main.vm"), "function Main.main 0").unwrap();
+    assert_eq!(take(), before);
+    fs::write(dir.join("Main.jack"), "class Main { }").unwrap();
+    assert_ne!(take(), before);
+
+    // A missing file is watched for until it appears
+    let single = dir.join("Other.asm");
+    assert_eq!(snapshot(&[&single], &[])[&single], None);
+
+    fs::remove_dir_all(&dir).unwrap();
+}
@@ -0,0 +1,189 @@
+use std::str::FromStr;
+
+use crate::ast::Operation;
+use crate::parser::parse_line;
+
+/// The column explanations start at in fully annotated assembly
+const EXPLANATION_COLUMN: usize = 16;
+
+/// How much the written assembly is commented
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Annotation {
+    /// No comments at all
+    None,
+    /// A comment holding each VM command before its instructions
+    #[default]
+    Source,
+    /// Each VM command also notes the stack depth before and after it, and each instruction says
+    /// what it does
+    Full,
+}
+
+impl Annotation {
+    pub const NAMES: [&'static str; 3] = ["none", "source", "full"];
+}
+
+impl FromStr for Annotation {
+    type Err = String;
+
+    fn from_str(name: &str) -> Result<Self, Self::Err> {
+        match name {
+            "none" => Ok(Annotation::None),
+            "source" => Ok(Annotation::Source),
+            "full" => Ok(Annotation::Full),
+            _ => Err(format!(
+                "{} is not an annotation level, expected one of {}",
+                name,
+                Self::NAMES.join(", ")
+            )),
+        }
+    }
+}
+
+/// Rework the comments of translated assembly, which has a comment holding each VM command, for
+/// `annotation`
+pub(crate) fn annotate(asm: String, annotation: Annotation) -> String {
+    match annotation {
+        Annotation::Source => asm,
+        Annotation::None => asm
+            .lines()
+            .filter(|line| !line.trim_start().starts_with("//"))
+            .collect::<Vec<_>>()
+            .join("\n"),
+        Annotation::Full => {
+            // The depth of the stack above the current function's frame, while it's known
+            let mut depth = Some(0);
+            let mut lines = Vec::new();
+            for line in asm.lines() {
+                let trimmed = line.trim();
+                if let Some(command) = trimmed.strip_prefix("// ") {
+                    match parse_line(command) {
+                        Ok(Some(operation)) => {
+                            let after = stack_depth_after(&operation, depth);
+                            lines.push(format!(
+                                "{}  [stack {} -> {}]",
+                                line,
+                                show_depth(depth),
+                                show_depth(after)
+                            ));
+                            depth = after;
+                        }
+                        _ => lines.push(line.to_owned()),
+                    }
+                } else if trimmed.is_empty() || trimmed.starts_with(['(', '/']) {
+                    lines.push(line.to_owned());
+                } else {
+                    // Long instructions push the explanation along, but keep a space before it
+                    lines.push(format!(
+                        "{:width$} // {}",
+                        line,
+                        explain(trimmed),
+                        width = EXPLANATION_COLUMN - 1
+                    ));
+                }
+            }
+            lines.join("\n")
+        }
+    }
+}
+
+fn stack_depth_after(operation: &Operation, depth: Option<i32>) -> Option<i32> {
+    let change = match operation {
+        Operation::Function(function) => return Some(function.num as i32),
+        Operation::Return => return None,
+        Operation::Push(_) => 1,
+        Operation::Pop(_) | Operation::ConditionalJump(_) => -1,
+        Operation::Add
+        | Operation::Sub
+        | Operation::Eq
+        | Operation::Gt
+        | Operation::Lt
+        | Operation::And
+        | Operation::Or => -1,
+        Operation::Neg | Operation::Not | Operation::Label(_) | Operation::Jump(_) => 0,
+        Operation::Call(function) => 1 - function.num as i32,
+    };
+    depth.map(|depth| depth + change)
+}
+
+fn show_depth(depth: Option<i32>) -> String {
+    depth.map_or_else(|| "?".to_owned(), |depth| depth.to_string())
+}
+
+/// What a Hack instruction does, in words
+fn explain(instruction: &str) -> String {
+    if let Some(value) = instruction.strip_prefix('@') {
+        return format!("A = {}", value);
+    }
+    let (assignment, jump) = match instruction.split_once(';') {
+        Some((assignment, jump)) => (assignment, Some(jump)),
+        None => (instruction, None),
+    };
+    let (dest, comp) = match assignment.split_once('=') {
+        Some((dest, comp)) => (Some(dest), comp),
+        None => (None, assignment),
+    };
+    let comp = comp.replace('M', "RAM[A]");
+
+    let mut parts = Vec::new();
+    if let Some(dest) = dest {
+        let targets: Vec<&str> = dest
+            .chars()
+            .map(|register| match register {
+                'M' => "RAM[A]",
+                'A' => "A",
+                _ => "D",
+            })
+            .collect();
+        parts.push(format!("{} = {}", targets.join(", "), comp));
+    }
+    let condition = match jump {
+        None => None,
+        Some("JMP") => Some(String::new()),
+        Some(jump) => {
+            let test = match jump {
+                "JGT" => "> 0",
+                "JEQ" => "= 0",
+                "JGE" => ">= 0",
+                "JLT" => "< 0",
+                "JNE" => "!= 0",
+                _ => "<= 0",
+            };
+            Some(format!(" if {} {}", comp, test))
+        }
+    };
+    if let Some(condition) = condition {
+        parts.push(format!("jump to ROM[A]{}", condition));
+    }
+    parts.join(", then ")
+}
+
+#[test]
+fn test_annotate() {
+    let asm = "// function Main.main 1\n(Main.main)\n@SP\nAM=M+1\n// push constant 1\n@1\nD=A\n\
+               // add\n@SP\nAM=M-1\n// if-goto END\nD;JNE\n// return\n// halt\n0;JMP"
+        .to_owned();
+
+    assert_eq!(annotate(asm.clone(), Annotation::Source), asm);
+    assert_eq!(
+        annotate(asm.clone(), Annotation::None),
+        "(Main.main)\n@SP\nAM=M+1\n@1\nD=A\n@SP\nAM=M-1\nD;JNE\n0;JMP"
+    );
+
+    let full = annotate(asm, Annotation::Full);
+    let lines: Vec<&str> = full.lines().collect();
+    assert_eq!(lines[0], "// function Main.main 1  [stack 0 -> 1]");
+    assert_eq!(lines[1], "(Main.main)");
+    assert_eq!(lines[2], "@SP             // A = SP");
+    assert_eq!(lines[3], "AM=M+1          // A, RAM[A] = RAM[A]+1");
+    assert_eq!(lines[4], "// push constant 1  [stack 1 -> 2]");
+    assert_eq!(lines[7], "// add  [stack 2 -> 1]");
+    assert_eq!(lines[10], "// if-goto END  [stack 1 -> 0]");
+    assert_eq!(lines[11], "D;JNE           // jump to ROM[A] if D != 0");
+    assert_eq!(lines[12], "// return  [stack 0 -> ?]");
+    assert_eq!(lines[13], "// halt");
+    assert_eq!(lines[14], "0;JMP           // jump to ROM[A]");
+
+    let long = annotate("@SimpleAdd.vm.HALT".to_owned(), Annotation::Full);
+    assert_eq!(long, "@SimpleAdd.vm.HALT // A = SimpleAdd.vm.HALT");
+}
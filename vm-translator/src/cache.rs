@@ -0,0 +1,122 @@
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use parse_utils::output::write_atomic;
+use tracing::debug;
+
+use crate::TranslationOptions;
+
+/// The version of the translation each cache entry holds. Bump it when the assembly a file
+/// translates to changes, so that entries written by an older translator aren't used.
+const CACHE_FORMAT: u32 = 1;
+
+/// A directory of translated files, keyed by a hash of everything the translation of a single
+/// file depends on
+#[derive(Debug, Clone, PartialEq)]
+pub struct TranslationCache {
+    dir: PathBuf,
+}
+
+impl TranslationCache {
+    pub fn new(dir: &Path) -> Self {
+        Self {
+            dir: dir.to_owned(),
+        }
+    }
+
+    /// The file holding a translation. Its name is a hash which is the same for every build of
+    /// the translator, unlike the standard library's hashers.
+    fn entry(&self, file_name: &str, contents: &str, options: &TranslationOptions) -> PathBuf {
+        let mut hasher = Fnv1a::default();
+        hasher.write(&CACHE_FORMAT.to_le_bytes());
+        hasher.write_field(env!("CARGO_PKG_VERSION").as_bytes());
+        hasher.write_field(file_name.as_bytes());
+        hasher.write_field(contents.as_bytes());
+        hasher.write(&[options.optimization_level, options.peephole as u8]);
+        self.dir.join(format!("{:016x}.asm", hasher.0))
+    }
+
+    pub fn get(
+        &self,
+        file_name: &str,
+        contents: &str,
+        options: &TranslationOptions,
+    ) -> Option<String> {
+        let entry = self.entry(file_name, contents, options);
+        let asm = fs::read_to_string(&entry).ok()?;
+        debug!(file = file_name, entry = %entry.display(), "cache hit");
+        Some(asm)
+    }
+
+    /// Store a translation. The cache is only an optimisation, so failing to write it is logged
+    /// rather than reported.
+    pub fn put(&self, file_name: &str, contents: &str, options: &TranslationOptions, asm: &str) {
+        let entry = self.entry(file_name, contents, options);
+        let result =
+            fs::create_dir_all(&self.dir).and_then(|_| write_atomic(&entry, asm.as_bytes()));
+        if let Err(error) = result {
+            debug!(entry = %entry.display(), %error, "failed to write cache entry");
+        }
+    }
+}
+
+/// The 64-bit FNV-1a hash
+struct Fnv1a(u64);
+
+impl Default for Fnv1a {
+    fn default() -> Self {
+        Self(0xcbf29ce484222325)
+    }
+}
+
+impl Fnv1a {
+    fn write(&mut self, bytes: &[u8]) {
+        for byte in bytes {
+            self.0 ^= *byte as u64;
+            self.0 = self.0.wrapping_mul(0x100000001b3);
+        }
+    }
+
+    /// Hash a field along with its length, so that the bytes of neighbouring fields can't be
+    /// moved between them without changing the hash
+    fn write_field(&mut self, bytes: &[u8]) {
+        self.write(&(bytes.len() as u64).to_le_bytes());
+        self.write(bytes);
+    }
+}
+
+#[test]
+fn test_fnv1a() {
+    let hash = |bytes: &[u8]| {
+        let mut hasher = Fnv1a::default();
+        hasher.write(bytes);
+        hasher.0
+    };
+    assert_eq!(hash(b""), 0xcbf29ce484222325);
+    assert_eq!(hash(b"a"), 0xaf63dc4c8601ec8c);
+    assert_eq!(hash(b"foobar"), 0x85944171f73967e8);
+}
+
+#[test]
+fn test_cache_entries_depend_on_the_input() {
+    let dir = std::env::temp_dir().join(format!("vm-translator-cache-{}", std::process::id()));
+    let cache = TranslationCache::new(&dir);
+    let options = TranslationOptions::default();
+
+    assert_eq!(cache.get("Main.vm", "push constant 1", &options), None);
+    cache.put("Main.vm", "push constant 1", &options, "@1");
+    assert_eq!(
+        cache.get("Main.vm", "push constant 1", &options),
+        Some("@1".to_owned())
+    );
+
+    assert_eq!(cache.get("Main.vm", "push constant 2", &options), None);
+    assert_eq!(cache.get("Other.vm", "push constant 1", &options), None);
+    let optimized = TranslationOptions {
+        optimization_level: 1,
+        ..Default::default()
+    };
+    assert_eq!(cache.get("Main.vm", "push constant 1", &optimized), None);
+
+    fs::remove_dir_all(&dir).unwrap();
+}
@@ -0,0 +1,255 @@
+use std::fs;
+use std::path::Path;
+
+use clap::builder::{PossibleValuesParser, TypedValueParser};
+use clap::{value_parser, Arg, ArgAction, ArgMatches, Command, ValueHint};
+use parse_utils::cli::{init_tracing, print_error, write_mode};
+use parse_utils::watch::watch;
+
+use crate::{
+    index_vm, parse_and_convert_vm, Annotation, Bootstrap, ErrorType, TranslationCache,
+    TranslationOptions, BOOTSTRAP_PLACEHOLDER,
+};
+
+/// The command line interface of the VM translator, shared by the standalone binary and n2t
+pub fn command() -> Command {
+    Command::new("VM Translator")
+        .about("Translate VM code to Hack assembly")
+        .arg(
+            Arg::new("INPUT")
+                .index(1)
+                .required(true)
+                .num_args(1..)
+                .value_name("FILE")
+                .value_hint(ValueHint::FilePath)
+                .help("A VM language file or directory of files. Several files and directories are translated together as one program"),
+        )
+        .arg(
+            Arg::new("output")
+                .long("output")
+                .short('o')
+                .value_name("FILE")
+                .value_hint(ValueHint::FilePath)
+                .conflicts_with("out_dir")
+                .help("Write the assembly to FILE. Needed when translating several paths"),
+        )
+        .arg(
+            Arg::new("index")
+                .long("index")
+                .action(ArgAction::SetTrue)
+                .required(false)
+                .help("Print a JSON index of functions, labels, references and diagnostics instead of translating"),
+        )
+        .arg(
+            Arg::new("optimization_level")
+                .short('O')
+                .value_name("LEVEL")
+                .value_parser(value_parser!(u8).range(0..=1))
+                .default_value("0")
+                .help("Optimization level, which changes how commands are translated. 1 skips zeroing locals which are assigned before use and fuses comparisons with the if-goto which follows them. Independent of --optimize"),
+        )
+        .arg(
+            Arg::new("peephole")
+                .long("optimize")
+                .action(ArgAction::SetTrue)
+                .required(false)
+                .help("Remove stack pointer updates and loads which neighbouring commands undo from the generated assembly. This pass runs after translation, so it can be combined with any -O level"),
+        )
+        .arg(
+            Arg::new("no_halt")
+                .long("no-halt")
+                .action(ArgAction::SetTrue)
+                .required(false)
+                .help("Don't end a single translated file with an infinite halt loop"),
+        )
+        .arg(
+            Arg::new("bootstrap")
+                .long("bootstrap")
+                .value_name("KIND")
+                .value_parser(PossibleValuesParser::new(Bootstrap::NAMES).map(|name| {
+                    name.parse::<Bootstrap>()
+                        .expect("Only possible values are parsed")
+                }))
+                .default_value("simple")
+                .help("How a whole program starts: with nothing, by setting SP past an empty call frame and jumping to Sys.init, or by setting SP to 256 and calling Sys.init"),
+        )
+        .arg(
+            Arg::new("no_bootstrap")
+                .long("no-bootstrap")
+                .action(ArgAction::SetTrue)
+                .conflicts_with("bootstrap")
+                .required(false)
+                .help("Same as --bootstrap none"),
+        )
+        .arg(
+            Arg::new("bank_at")
+                .long("bank-at")
+                .value_name("FUNCTION")
+                .action(ArgAction::Append)
+                .help("Start a new ROM bank at this function, for Hack variants with a BANK register. Calls switch banks and the frame also saves the caller's bank. Assemble with the same --bank-at"),
+        )
+        .arg(
+            Arg::new("with_os")
+                .long("with-os")
+                .action(ArgAction::SetTrue)
+                .required(false)
+                .help("Link in the built-in Jack OS classes which the program doesn't provide itself"),
+        )
+        .arg(
+            Arg::new("prologue")
+                .long("prologue")
+                .value_name("FILE")
+                .value_hint(ValueHint::FilePath)
+                .help(format!("Put the assembly in FILE before the translated code instead of the bootstrap. {} in it is replaced by the bootstrap", BOOTSTRAP_PLACEHOLDER)),
+        )
+        .arg(
+            Arg::new("epilogue")
+                .long("epilogue")
+                .value_name("FILE")
+                .value_hint(ValueHint::FilePath)
+                .help("Put the assembly in FILE after the translated code"),
+        )
+        .arg(
+            Arg::new("annotate")
+                .long("annotate")
+                .value_name("LEVEL")
+                .value_parser(PossibleValuesParser::new(Annotation::NAMES).map(|name| {
+                    name.parse::<Annotation>()
+                        .expect("Only possible values are parsed")
+                }))
+                .default_value("source")
+                .help("How much to comment the assembly: not at all, with each VM command, or also with the stack depth around each command and what each instruction does"),
+        )
+        .arg(
+            Arg::new("source_map")
+                .long("source-map")
+                .action(ArgAction::SetTrue)
+                .required(false)
+                .help("Also write a .asm.map file giving the origin of each instruction: its Jack line where the compiler wrote a .vm.map beside the .vm file, or else its VM line"),
+        )
+        .arg(
+            Arg::new("listing")
+                .long("listing")
+                .action(ArgAction::SetTrue)
+                .required(false)
+                .help("Also write a .vm.lst file showing each VM command followed by the assembly it was translated to, with the ROM address of each instruction"),
+        )
+        .arg(
+            Arg::new("cache")
+                .long("cache")
+                .value_name("DIR")
+                .value_hint(ValueHint::DirPath)
+                .help("Keep the translation of each file in DIR and reuse it while the file is unchanged"),
+        )
+        .arg(
+            Arg::new("out_dir")
+                .long("out-dir")
+                .value_name("DIR")
+                .value_hint(ValueHint::DirPath)
+                .help("Write the .asm and .asm.map files to this directory instead of next to the sources"),
+        )
+        .arg(
+            Arg::new("dry_run")
+                .long("dry-run")
+                .action(ArgAction::SetTrue)
+                .required(false)
+                .help("Report the files which would be written without writing them"),
+        )
+        .arg(
+            Arg::new("trace")
+                .long("trace")
+                .action(ArgAction::SetTrue)
+                .required(false)
+                .help("Log every pass and file processed to stderr"),
+        )
+        .arg(
+            Arg::new("timings")
+                .long("timings")
+                .action(ArgAction::SetTrue)
+                .required(false)
+                .help("Report the time spent in each pass to stderr"),
+        )
+        .arg(
+            Arg::new("watch")
+                .long("watch")
+                .action(ArgAction::SetTrue)
+                .required(false)
+                .help("Translate again whenever one of the .vm files changes, until stopped with Ctrl-C"),
+        )
+}
+
+pub fn run(matches: &ArgMatches) -> Result<(), ErrorType> {
+    init_tracing(matches);
+
+    let paths: Vec<&str> = matches
+        .get_many::<String>("INPUT")
+        .expect("User to provide an input path")
+        .map(String::as_str)
+        .collect();
+
+    if matches.get_flag("watch") {
+        let watched: Vec<&Path> = paths.iter().map(Path::new).collect();
+        watch(
+            &watched,
+            &["vm"],
+            || translate(matches, &paths),
+            |error| print_error(error),
+        );
+    }
+    translate(matches, &paths)
+}
+
+fn translate(matches: &ArgMatches, paths: &[&str]) -> Result<(), ErrorType> {
+    if matches.get_flag("index") {
+        println!("{}", index_vm(paths)?);
+        return Ok(());
+    }
+
+    let options = TranslationOptions {
+        optimization_level: *matches
+            .get_one::<u8>("optimization_level")
+            .expect("optimization level has a default"),
+        no_halt: matches.get_flag("no_halt"),
+        bootstrap: if matches.get_flag("no_bootstrap") {
+            Bootstrap::None
+        } else {
+            *matches
+                .get_one::<Bootstrap>("bootstrap")
+                .expect("bootstrap has a default")
+        },
+        with_os: matches.get_flag("with_os"),
+        cache: matches
+            .get_one::<String>("cache")
+            .map(|dir| TranslationCache::new(Path::new(dir))),
+        peephole: matches.get_flag("peephole"),
+        bank_starts: matches
+            .get_many::<String>("bank_at")
+            .into_iter()
+            .flatten()
+            .cloned()
+            .collect(),
+        source_map: matches.get_flag("source_map"),
+        listing: matches.get_flag("listing"),
+        prologue: read_template(matches, "prologue")?,
+        epilogue: read_template(matches, "epilogue")?,
+        annotate: *matches
+            .get_one::<Annotation>("annotate")
+            .expect("annotate has a default"),
+    };
+
+    let out_dir = matches.get_one::<String>("out_dir").map(Path::new);
+    let output = matches.get_one::<String>("output").map(Path::new);
+    parse_and_convert_vm(paths, &options, write_mode(matches), out_dir, output)
+}
+
+fn read_template(matches: &ArgMatches, id: &str) -> Result<Option<String>, ErrorType> {
+    let Some(path) = matches.get_one::<String>(id).map(Path::new) else {
+        return Ok(None);
+    };
+    fs::read_to_string(path)
+        .map(Some)
+        .map_err(|source| ErrorType::ReadError {
+            path: path.to_owned(),
+            source,
+        })
+}
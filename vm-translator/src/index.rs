@@ -0,0 +1,208 @@
+use serde::Serialize;
+
+use crate::ast::Operation;
+use crate::parser::parse_line;
+
+/// Navigation information for a .vm file, intended for editors.
+///
+/// Line numbers are 1-based.
+#[derive(Debug, Default, Serialize)]
+pub struct SourceIndex {
+    pub file: String,
+    pub definitions: Vec<Definition>,
+    pub references: Vec<Reference>,
+    pub diagnostics: Vec<Diagnostic>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum SymbolKind {
+    Function,
+    Label,
+}
+
+#[derive(Debug, PartialEq, Serialize)]
+pub struct Definition {
+    pub name: String,
+    pub kind: SymbolKind,
+    pub line: usize,
+    /// The function a label belongs to, as each function has its own labels
+    pub function: Option<String>,
+}
+
+/// A `call`, `goto` or `if-goto` target
+#[derive(Debug, PartialEq, Serialize)]
+pub struct Reference {
+    pub name: String,
+    pub kind: SymbolKind,
+    pub line: usize,
+    /// The function a label is jumped to from
+    pub function: Option<String>,
+}
+
+#[derive(Debug, PartialEq, Serialize)]
+pub struct Diagnostic {
+    pub line: usize,
+    pub message: String,
+}
+
+/// Index the contents of a single .vm file, reporting every line which fails to parse
+pub fn index_source(file: &str, contents: &str) -> SourceIndex {
+    let mut index = SourceIndex {
+        file: file.to_owned(),
+        ..Default::default()
+    };
+    let mut function_name = None;
+
+    for (line_index, line) in contents.lines().enumerate() {
+        let line_number = line_index + 1;
+
+        let operation = match parse_line(line) {
+            Ok(Some(operation)) => operation,
+            Ok(None) => continue,
+            Err(message) => {
+                index.diagnostics.push(Diagnostic {
+                    line: line_number,
+                    message,
+                });
+                continue;
+            }
+        };
+
+        match operation {
+            Operation::Function(function) => {
+                function_name = Some(function.name.clone());
+                index.add_definition(function.name, SymbolKind::Function, line_number, None)
+            }
+            Operation::Label(label) => {
+                index.add_definition(label, SymbolKind::Label, line_number, function_name.clone())
+            }
+            Operation::Call(function) => index.references.push(Reference {
+                name: function.name,
+                kind: SymbolKind::Function,
+                line: line_number,
+                function: None,
+            }),
+            Operation::Jump(label) | Operation::ConditionalJump(label) => {
+                index.references.push(Reference {
+                    name: label,
+                    kind: SymbolKind::Label,
+                    line: line_number,
+                    function: function_name.clone(),
+                })
+            }
+            _ => {}
+        }
+    }
+
+    index
+}
+
+impl SourceIndex {
+    fn add_definition(
+        &mut self,
+        name: String,
+        kind: SymbolKind,
+        line: usize,
+        function: Option<String>,
+    ) {
+        if let Some(existing) = self
+            .definitions
+            .iter()
+            .find(|def| def.name == name && def.kind == kind && def.function == function)
+        {
+            self.diagnostics.push(Diagnostic {
+                line,
+                message: format!("{} is already defined on line {}", name, existing.line),
+            });
+        }
+        self.definitions.push(Definition {
+            name,
+            kind,
+            line,
+            function,
+        });
+    }
+}
+
+#[test]
+fn test_index_source() {
+    let index = index_source(
+        "Main.vm",
+        r#"function Main.main 0
+label LOOP
+    call Output.printInt 1
+    goto LOOP
+    push nowhere 3
+function Main.main 0"#,
+    );
+
+    assert_eq!(
+        index.definitions,
+        vec![
+            Definition {
+                name: "Main.main".to_owned(),
+                kind: SymbolKind::Function,
+                line: 1,
+                function: None
+            },
+            Definition {
+                name: "LOOP".to_owned(),
+                kind: SymbolKind::Label,
+                line: 2,
+                function: Some("Main.main".to_owned())
+            },
+            Definition {
+                name: "Main.main".to_owned(),
+                kind: SymbolKind::Function,
+                line: 6,
+                function: None
+            }
+        ]
+    );
+    assert_eq!(
+        index.references,
+        vec![
+            Reference {
+                name: "Output.printInt".to_owned(),
+                kind: SymbolKind::Function,
+                line: 3,
+                function: None
+            },
+            Reference {
+                name: "LOOP".to_owned(),
+                kind: SymbolKind::Label,
+                line: 4,
+                function: Some("Main.main".to_owned())
+            }
+        ]
+    );
+    assert_eq!(index.diagnostics.len(), 2);
+    assert_eq!(index.diagnostics[0].line, 5);
+    assert_eq!(
+        index.diagnostics[1].message,
+        "Main.main is already defined on line 1"
+    );
+}
+
+#[test]
+fn test_labels_are_scoped_to_their_function() {
+    let index = index_source(
+        "Main.vm",
+        r#"function Main.main 0
+label LOOP
+    goto LOOP
+function Main.draw 0
+label LOOP
+    goto LOOP
+label LOOP"#,
+    );
+
+    assert_eq!(index.diagnostics.len(), 1);
+    assert_eq!(index.diagnostics[0].line, 7);
+    assert_eq!(
+        index.diagnostics[0].message,
+        "LOOP is already defined on line 5"
+    );
+    assert_eq!(index.references[1].function.as_deref(), Some("Main.draw"));
+}
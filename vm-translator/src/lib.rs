@@ -0,0 +1,711 @@
+mod annotate;
+pub mod ast;
+mod cache;
+pub mod cli;
+mod index;
+mod os;
+mod parser;
+mod peephole;
+mod source_map;
+mod tokens;
+mod translate_ast;
+
+use std::collections::HashMap;
+use std::fmt::Write;
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+use std::{fs, io};
+
+pub use annotate::Annotation;
+pub use cache::TranslationCache;
+use index::index_source;
+pub use os::{link_os, OS_FILES};
+use parse_utils::output::{in_out_dir, write_output, WriteMode};
+use parse_utils::source::Source;
+pub use parser::parser as parse_vm;
+pub use source_map::{command_addresses, listing, source_map};
+use thiserror::Error;
+pub use tokens::tokenize_vm;
+use tracing::{debug, info_span};
+use translate_ast::{translate_ast, FunctionBanks};
+
+#[derive(Debug, Error)]
+pub enum ErrorType {
+    #[error("Failed to read {}", .path.display())]
+    ReadError {
+        path: PathBuf,
+        #[source]
+        source: io::Error,
+    },
+    #[error("Failed to write {}", .path.display())]
+    WriteError {
+        path: PathBuf,
+        #[source]
+        source: io::Error,
+    },
+    #[error("Failed to parse {file}: {message}")]
+    ParsingError { file: String, message: String },
+    #[error("Failed to translate {file}: {message}")]
+    TranslationError { file: String, message: String },
+    #[error("Invalid file name {}", .0.display())]
+    InvalidFileName(PathBuf),
+    #[error("Unable to read the file extension of {}", .0.display())]
+    FileExtensionError(PathBuf),
+    #[error("Failed to serialize the index to JSON")]
+    SerdeError(#[source] serde_json::Error),
+    #[error("There is no function called {0} to start a ROM bank at")]
+    UnknownBankFunction(String),
+    #[error("Translating several paths needs --output to name the .asm file")]
+    OutputNeeded,
+    #[error("Two of the files are called {0}, which would share their statics and labels")]
+    DuplicateFileName(String),
+    #[error("The program has {count} statics but RAM[16] to RAM[255] only holds {STATIC_WORDS} ({files})")]
+    TooManyStatics { count: usize, files: String },
+}
+
+/// The words from RAM[16] to RAM[255], where the assembler allocates statics
+const STATIC_WORDS: usize = 240;
+
+#[derive(Debug, Clone, Default)]
+pub struct TranslationOptions {
+    /// 0 translates every command as written. 1 also skips zeroing locals which are always
+    /// assigned before they are read, and jumps straight on a comparison which is only used by an
+    /// if-goto.
+    pub optimization_level: u8,
+    /// Don't end single files with an infinite loop
+    pub no_halt: bool,
+    /// How whole programs start
+    pub bootstrap: Bootstrap,
+    /// Link in the built-in OS classes which the program doesn't provide
+    pub with_os: bool,
+    /// Reuse the translations of files which haven't changed since an earlier run
+    pub cache: Option<TranslationCache>,
+    /// Run a peephole pass over the generated assembly, removing stack pointer updates and loads
+    /// which neighbouring commands undo. It cleans up after translation at any
+    /// `optimization_level`, which instead changes how commands are translated.
+    pub peephole: bool,
+    /// Functions which each start a new ROM bank, for Hack variants with a bank-switch register.
+    /// Calls then switch banks, which adds the caller's bank to the call frame.
+    pub bank_starts: Vec<String>,
+    /// Also write a .asm.map file giving the VM command, or Jack line, of each instruction
+    pub source_map: bool,
+    /// Also write a .vm.lst file listing each VM command followed by the instructions it was
+    /// translated to, with their ROM addresses
+    pub listing: bool,
+    /// Assembly to put before the translated code, e.g. to set up hardware. It replaces the
+    /// bootstrap of a whole program, and `{{bootstrap}}` in it stands for the bootstrap.
+    pub prologue: Option<String>,
+    /// Assembly to put after the translated code
+    pub epilogue: Option<String>,
+    /// How much the written .asm file is commented
+    pub annotate: Annotation,
+}
+
+/// The code a whole program starts with
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Bootstrap {
+    /// Start at the first command of the first file
+    None,
+    /// Set SP as if Sys.init had been called and jump to it, which the chapter 7 and early
+    /// chapter 8 test scripts expect
+    #[default]
+    Simple,
+    /// Set SP to 256 and really `call Sys.init 0`, as in the book
+    Full,
+}
+
+impl Bootstrap {
+    pub const NAMES: [&'static str; 3] = ["none", "simple", "full"];
+}
+
+impl FromStr for Bootstrap {
+    type Err = String;
+
+    fn from_str(name: &str) -> Result<Self, Self::Err> {
+        match name {
+            "none" => Ok(Bootstrap::None),
+            "simple" => Ok(Bootstrap::Simple),
+            "full" => Ok(Bootstrap::Full),
+            _ => Err(format!(
+                "{} is not a bootstrap, expected one of {}",
+                name,
+                Self::NAMES.join(", ")
+            )),
+        }
+    }
+}
+
+/// Stands for the default bootstrap in a prologue
+pub const BOOTSTRAP_PLACEHOLDER: &str = "{{bootstrap}}";
+
+/// Translate .vm files into a .asm file. A single file is translated on its own, unless the OS is
+/// linked in. A directory, or several paths, are translated as one program into `output`, which
+/// is needed for several paths.
+pub fn parse_and_convert_vm(
+    paths: &[&str],
+    options: &TranslationOptions,
+    mode: WriteMode,
+    out_dir: Option<&Path>,
+    output: Option<&Path>,
+) -> Result<(), ErrorType> {
+    // A dry run leaves the cache alone as well as the outputs
+    let options = &TranslationOptions {
+        cache: options.cache.clone().filter(|_| mode == WriteMode::Write),
+        ..options.clone()
+    };
+    let destination = |default: PathBuf| match output {
+        Some(output) => output.to_owned(),
+        None => in_out_dir(&default, out_dir),
+    };
+
+    let [path] = paths else {
+        let output = output.ok_or(ErrorType::OutputNeeded)?;
+        return translate_vm_files(&expand_paths(paths)?, output, options, mode);
+    };
+
+    let file = Path::new(path);
+    if file.is_file() && options.with_os {
+        // With the OS a single file is a whole program, which needs the bootstrap
+        translate_vm_files(
+            &[file.to_owned()],
+            &destination(file.with_extension("asm")),
+            options,
+            mode,
+        )?;
+    } else if file.is_file() {
+        let asm = compile_file(file, options)?;
+
+        // Create the output file path
+        let mut out_file = PathBuf::from(file);
+        out_file.set_extension("asm");
+        let out_file = destination(out_file);
+
+        if options.source_map || options.listing {
+            let (name, contents) = (file_name(file)?, read_file(file)?);
+            if options.source_map {
+                write_source_map(
+                    &asm,
+                    &[(&name, &contents)],
+                    &[file.to_owned()],
+                    &out_file,
+                    mode,
+                )?;
+            }
+            if options.listing {
+                let listing = listing(&asm, &[(&name, &contents)]);
+                write_file(&out_file.with_extension("vm.lst"), listing, mode)?;
+            }
+        }
+
+        // Write into a file
+        write_file(&out_file, annotate::annotate(asm, options.annotate), mode)?;
+    } else if file.is_dir() {
+        // Get the hack filename
+        let output_file_name = Path::new(path)
+            .file_stem()
+            .ok_or_else(|| ErrorType::InvalidFileName(file.to_owned()))?
+            .to_owned()
+            .into_string()
+            .map_err(|_| ErrorType::InvalidFileName(file.to_owned()))?;
+
+        let out_file = destination(file.join(format!("{}.asm", output_file_name)));
+        translate_vm_files(&find_vm_files(file)?, &out_file, options, mode)?;
+    }
+    Ok(())
+}
+
+/// Translate .vm files as one program and write it to `out_file`
+fn translate_vm_files(
+    vm_files: &[PathBuf],
+    out_file: &Path,
+    options: &TranslationOptions,
+    mode: WriteMode,
+) -> Result<(), ErrorType> {
+    let mut sources: Vec<(String, Source)> = Vec::with_capacity(vm_files.len());
+    for vm_file in vm_files.iter() {
+        let name = file_name(vm_file)?;
+        // Statics and labels are named after the file, so two files of the same name would clash
+        if sources.iter().any(|(other, _)| *other == name) {
+            return Err(ErrorType::DuplicateFileName(name));
+        }
+        sources.push((name, read_file(vm_file)?));
+    }
+    let sources = sources
+        .iter()
+        .map(|(name, contents)| (name.as_str(), &**contents))
+        .collect::<Vec<_>>();
+    let final_assembly = translate_program_with_options(&sources, options)?;
+
+    if options.source_map || options.listing {
+        let sources = if options.with_os {
+            link_os(&sources)
+        } else {
+            sources
+        };
+        if options.source_map {
+            write_source_map(&final_assembly, &sources, vm_files, out_file, mode)?;
+        }
+        if options.listing {
+            let listing = listing(&final_assembly, &sources);
+            write_file(&out_file.with_extension("vm.lst"), listing, mode)?;
+        }
+    }
+
+    // Write into a file
+    write_file(
+        out_file,
+        annotate::annotate(final_assembly, options.annotate),
+        mode,
+    )
+}
+
+/// Write the .asm.map of a translation next to its assembly, following the VM files on to Jack
+/// wherever the compiler left a .vm.map beside them
+fn write_source_map(
+    asm: &str,
+    sources: &[(&str, &str)],
+    vm_files: &[PathBuf],
+    out_file: &Path,
+    mode: WriteMode,
+) -> Result<(), ErrorType> {
+    let mut vm_maps = HashMap::new();
+    for vm_file in vm_files {
+        if let Ok(map) = fs::read_to_string(vm_file.with_extension("vm.map")) {
+            vm_maps.insert(file_name(vm_file)?, map);
+        }
+    }
+    let map = source_map(asm, sources, &vm_maps);
+    write_file(&out_file.with_extension("asm.map"), map, mode)
+}
+
+/// Produce a JSON index of the functions, labels, references and parse errors in .vm files and
+/// every .vm file in directories
+pub fn index_vm(paths: &[&str]) -> Result<String, ErrorType> {
+    let vm_files = expand_paths(paths)?;
+
+    let mut indexes = Vec::with_capacity(vm_files.len());
+    for file in &vm_files {
+        let contents = read_file(file)?;
+        indexes.push(index_source(&file.display().to_string(), &contents));
+    }
+
+    serde_json::to_string_pretty(&indexes).map_err(ErrorType::SerdeError)
+}
+
+/// The .vm files named by paths, with directories standing for the .vm files in them
+fn expand_paths(paths: &[&str]) -> Result<Vec<PathBuf>, ErrorType> {
+    let mut vm_files = Vec::new();
+    for path in paths.iter().map(Path::new) {
+        if path.is_dir() {
+            vm_files.extend(find_vm_files(path)?);
+        } else {
+            vm_files.push(path.to_owned());
+        }
+    }
+    Ok(vm_files)
+}
+
+fn find_vm_files(dir: &Path) -> Result<Vec<PathBuf>, ErrorType> {
+    let mut vm_files = Vec::new();
+    let read_error = |source| ErrorType::ReadError {
+        path: dir.to_owned(),
+        source,
+    };
+    for entry in dir.read_dir().map_err(read_error)? {
+        let file_path = entry.map_err(read_error)?.path();
+        if file_path.is_dir() {
+            continue;
+        }
+        let extension = file_path
+            .extension()
+            .ok_or_else(|| ErrorType::FileExtensionError(file_path.clone()))?;
+        if extension == "vm" {
+            vm_files.push(file_path);
+        }
+    }
+
+    Ok(vm_files)
+}
+
+fn compile_file(file: &Path, options: &TranslationOptions) -> Result<String, ErrorType> {
+    let file_contents = read_file(file)?;
+    let file_name = file_name(file)?;
+    check_statics(&[(&file_name, &file_contents)])?;
+    let banks = function_banks(&[(&file_name, &file_contents)], options)?;
+    // A single file has no bootstrap for the prologue to replace
+    let mut asm = prologue(options, "");
+    asm.push_str(&translate_cached(
+        &file_name,
+        &file_contents,
+        options,
+        banks.as_ref(),
+    )?);
+
+    // Without the bootstrap nothing stops the CPU running off the end of the program
+    if !options.no_halt {
+        write!(
+            asm,
+            "\n// halt\n({name}.HALT)\n@{name}.HALT\n0;JMP",
+            name = file_name
+        )
+        .expect("Writing to a String cannot fail");
+    }
+    push_epilogue(&mut asm, options);
+
+    Ok(asm)
+}
+
+/// The prologue of the options, or else the bootstrap, ready for the translated code to follow
+fn prologue(options: &TranslationOptions, bootstrap: &str) -> String {
+    let Some(prologue) = &options.prologue else {
+        return bootstrap.to_owned();
+    };
+    let mut asm = prologue.replace(BOOTSTRAP_PLACEHOLDER, bootstrap);
+    if !asm.is_empty() && !asm.ends_with('\n') {
+        asm.push('\n');
+    }
+    asm
+}
+
+fn push_epilogue(asm: &mut String, options: &TranslationOptions) {
+    if let Some(epilogue) = &options.epilogue {
+        if !asm.is_empty() && !asm.ends_with('\n') {
+            asm.push('\n');
+        }
+        asm.push_str(epilogue);
+    }
+}
+
+fn file_name(file: &Path) -> Result<String, ErrorType> {
+    file.file_name()
+        .ok_or_else(|| ErrorType::InvalidFileName(file.to_owned()))?
+        .to_owned()
+        .into_string()
+        .map_err(|_| ErrorType::InvalidFileName(file.to_owned()))
+}
+
+/// Translate the contents of a single .vm file held in memory into Hack assembly. The file name
+/// is used to namespace statics and generated labels.
+pub fn translate_string(file_name: &str, contents: &str) -> Result<String, ErrorType> {
+    translate_string_with_options(file_name, contents, &TranslationOptions::default())
+}
+
+/// Translate a single .vm file held in memory with non-default options
+pub fn translate_string_with_options(
+    file_name: &str,
+    contents: &str,
+    options: &TranslationOptions,
+) -> Result<String, ErrorType> {
+    let banks = function_banks(&[(file_name, contents)], options)?;
+    translate_file(file_name, contents, options, banks.as_ref())
+}
+
+fn translate_file(
+    file_name: &str,
+    contents: &str,
+    options: &TranslationOptions,
+    banks: Option<&FunctionBanks>,
+) -> Result<String, ErrorType> {
+    let _span = info_span!("translate", file = file_name).entered();
+
+    let statements = info_span!("parse").in_scope(|| {
+        parser::parser(contents).map_err(|message| ErrorType::ParsingError {
+            file: file_name.to_owned(),
+            message,
+        })
+    })?;
+    debug!(statements = statements.len(), "parsed");
+
+    let _span = info_span!("codegen").entered();
+    let asm = translate_ast(statements, file_name, options, banks).map_err(|message| {
+        ErrorType::TranslationError {
+            file: file_name.to_owned(),
+            message,
+        }
+    })?;
+
+    if options.peephole {
+        return Ok(info_span!("peephole").in_scope(|| peephole::optimize(&asm)));
+    }
+    Ok(asm)
+}
+
+/// Translate a whole program, given as (file name, contents) pairs, into a single assembly file
+/// which starts with the bootstrap code
+pub fn translate_program(sources: &[(&str, &str)]) -> Result<String, ErrorType> {
+    translate_program_with_options(sources, &TranslationOptions::default())
+}
+
+/// Translate a whole program with non-default options
+pub fn translate_program_with_options(
+    sources: &[(&str, &str)],
+    options: &TranslationOptions,
+) -> Result<String, ErrorType> {
+    let sources = if options.with_os {
+        link_os(sources)
+    } else {
+        sources.to_vec()
+    };
+    check_statics(&sources)?;
+    let banks = function_banks(&sources, options)?;
+    let bootstrap = bootstrap(options, banks.as_ref())?;
+    let mut final_assembly = prologue(options, &bootstrap);
+
+    for (file_name, contents) in sources {
+        let asm = translate_cached(file_name, contents, options, banks.as_ref())?;
+
+        final_assembly.push_str(&asm);
+        final_assembly.push('\n');
+    }
+    push_epilogue(&mut final_assembly, options);
+
+    Ok(final_assembly)
+}
+
+fn bootstrap(
+    options: &TranslationOptions,
+    banks: Option<&FunctionBanks>,
+) -> Result<String, ErrorType> {
+    /*
+    The simple bootstrap stands in for the code:
+        SP=256
+        Call Sys.init
+
+    The call will be non-functional but will consume 5 blocks (1 block == 2 bytes) from RAM. We don't need a
+    call stack but some tests rely on the stack frame being present. To emulate this we just add 5 blocks
+    to the stack & jump to Sys.init
+     */
+    match (options.bootstrap, banks) {
+        (Bootstrap::None, _) => Ok(String::new()),
+        (Bootstrap::Simple, None) => Ok(String::from(
+            r#"@261
+D=A
+@SP
+M=D
+@Sys.init
+0;JMP
+"#,
+        )),
+        // With banks the frame also holds the caller's bank, and Sys.init may be in any bank
+        (Bootstrap::Simple, Some(banks)) => Ok(format!(
+            "@262\nD=A\n@SP\nM=D\n@{}\nD=A\n@BANK\nM=D\n@Sys.init\n0;JMP\n",
+            banks.bank(Some("Sys.init"))
+        )),
+        (Bootstrap::Full, banks) => {
+            let call = parser::parser("call Sys.init 0").expect("The bootstrap call parses");
+            let call = translate_ast(call, "Bootstrap", options, banks).map_err(|message| {
+                ErrorType::TranslationError {
+                    file: "the bootstrap".to_owned(),
+                    message,
+                }
+            })?;
+            Ok(format!("@256\nD=A\n@SP\nM=D\n{}\n", call))
+        }
+    }
+}
+
+/// Check the statics of a program fit between the registers and the stack. Each static index a
+/// file uses becomes a variable of the assembler, so past RAM[255] they would overwrite the stack.
+fn check_statics(sources: &[(&str, &str)]) -> Result<(), ErrorType> {
+    let mut counts = Vec::new();
+    for (file_name, contents) in sources {
+        // A file which doesn't parse is reported when it's translated
+        let statements = parser::parser(contents).unwrap_or_default();
+        let mut indices: Vec<u32> = statements
+            .iter()
+            .filter_map(|stmt| match &stmt.operation {
+                ast::Operation::Push(address) | ast::Operation::Pop(address)
+                    if address.memory_segment == ast::MemorySegment::Static =>
+                {
+                    Some(address.address)
+                }
+                _ => None,
+            })
+            .collect();
+        indices.sort_unstable();
+        indices.dedup();
+        if !indices.is_empty() {
+            counts.push((*file_name, indices.len()));
+        }
+    }
+
+    let count = counts.iter().map(|(_, statics)| statics).sum();
+    if count <= STATIC_WORDS {
+        return Ok(());
+    }
+    counts.sort_by(|a, b| b.1.cmp(&a.1).then(a.0.cmp(b.0)));
+    let files: Vec<String> = counts
+        .iter()
+        .map(|(file_name, statics)| format!("{} uses {}", file_name, statics))
+        .collect();
+    Err(ErrorType::TooManyStatics {
+        count,
+        files: files.join(", "),
+    })
+}
+
+/// Place the functions of a program in ROM banks, if the options split it into banks
+fn function_banks(
+    sources: &[(&str, &str)],
+    options: &TranslationOptions,
+) -> Result<Option<FunctionBanks>, ErrorType> {
+    if options.bank_starts.is_empty() {
+        return Ok(None);
+    }
+    let mut statements = Vec::new();
+    for (_, contents) in sources {
+        // A file which doesn't parse is reported when it's translated
+        statements.extend(parser::parser(contents).unwrap_or_default());
+    }
+    let functions = statements.iter().filter_map(|stmt| match &stmt.operation {
+        ast::Operation::Function(function) => Some(function.name.as_str()),
+        _ => None,
+    });
+    FunctionBanks::new(functions, &options.bank_starts)
+        .map(Some)
+        .map_err(ErrorType::UnknownBankFunction)
+}
+
+/// Translate a single file, going through the cache if the options have one. The translation of
+/// a file split into banks depends on the rest of the program, so it isn't cached.
+fn translate_cached(
+    file_name: &str,
+    contents: &str,
+    options: &TranslationOptions,
+    banks: Option<&FunctionBanks>,
+) -> Result<String, ErrorType> {
+    let Some(cache) = options.cache.as_ref().filter(|_| banks.is_none()) else {
+        return translate_file(file_name, contents, options, banks);
+    };
+    if let Some(asm) = cache.get(file_name, contents, options) {
+        return Ok(asm);
+    }
+    let asm = translate_file(file_name, contents, options, None)?;
+    cache.put(file_name, contents, options, &asm);
+    Ok(asm)
+}
+
+fn read_file(path: &Path) -> Result<Source, ErrorType> {
+    Source::open(path).map_err(|source| ErrorType::ReadError {
+        path: path.to_owned(),
+        source,
+    })
+}
+
+fn write_file(path: &Path, contents: String, mode: WriteMode) -> Result<(), ErrorType> {
+    debug!(path = %path.display(), bytes = contents.len(), "writing output");
+    write_output(path, contents.as_bytes(), mode).map_err(|source| ErrorType::WriteError {
+        path: path.to_owned(),
+        source,
+    })
+}
+
+#[test]
+fn test_prologue_and_epilogue() {
+    let options = TranslationOptions {
+        prologue: Some("@100\nD=A\n@R5\nM=D\n{{bootstrap}}".to_owned()),
+        epilogue: Some("(END)\n@END\n0;JMP".to_owned()),
+        ..Default::default()
+    };
+    let asm = translate_program_with_options(
+        &[("Sys.vm", "function Sys.init 0\nlabel LOOP\ngoto LOOP")],
+        &options,
+    )
+    .unwrap();
+
+    assert!(asm.starts_with("@100\nD=A\n@R5\nM=D\n@261\nD=A\n@SP\nM=D\n@Sys.init\n0;JMP\n"));
+    assert!(asm.ends_with("\n(END)\n@END\n0;JMP"));
+}
+
+#[test]
+fn test_single_files_end_with_a_halt_loop() {
+    let dir = std::env::temp_dir().join(format!("vm-translator-halt-{}", std::process::id()));
+    std::fs::create_dir_all(&dir).unwrap();
+    let file = dir.join("SimpleAdd.vm");
+    std::fs::write(&file, "push constant 7\npush constant 8\nadd").unwrap();
+
+    let asm = compile_file(&file, &TranslationOptions::default()).unwrap();
+    assert!(asm.ends_with("\n// halt\n(SimpleAdd.vm.HALT)\n@SimpleAdd.vm.HALT\n0;JMP"));
+
+    let options = TranslationOptions {
+        no_halt: true,
+        ..Default::default()
+    };
+    let asm = compile_file(&file, &options).unwrap();
+    assert!(!asm.contains("HALT"));
+    assert!(asm.ends_with("M=D+M"));
+    std::fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn test_several_files_are_one_program() {
+    let dir = std::env::temp_dir().join(format!("vm-translator-files-{}", std::process::id()));
+    fs::create_dir_all(dir.join("os")).unwrap();
+    let sys = dir.join("os/Sys.vm");
+    let main = dir.join("Main.vm");
+    fs::write(
+        &sys,
+        "function Sys.init 0\ncall Main.main 0\nlabel END\ngoto END",
+    )
+    .unwrap();
+    fs::write(&main, "function Main.main 0\npush constant 7\nreturn").unwrap();
+    let paths = [main.to_str().unwrap(), sys.to_str().unwrap()];
+    let output = dir.join("Program.asm");
+    let options = TranslationOptions {
+        bootstrap: Bootstrap::None,
+        ..Default::default()
+    };
+
+    assert!(matches!(
+        parse_and_convert_vm(&paths, &options, WriteMode::Write, None, None),
+        Err(ErrorType::OutputNeeded)
+    ));
+    parse_and_convert_vm(&paths, &options, WriteMode::Write, None, Some(&output)).unwrap();
+    let asm = fs::read_to_string(&output).unwrap();
+    assert!(asm.starts_with("// function Main.main 0"));
+    assert!(asm.contains("(Sys.init)"));
+
+    fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn test_too_many_statics() {
+    let statics = |count: u32| {
+        (0..count)
+            .map(|index| format!("push static {}\npop static {}\n", index, index))
+            .collect::<String>()
+    };
+    let (main, screen) = (statics(200), statics(40));
+    let sources = [
+        ("Sys.vm", "function Sys.init 0\nlabel LOOP\ngoto LOOP"),
+        ("Main.vm", main.as_str()),
+        ("Screen.vm", screen.as_str()),
+    ];
+    assert!(translate_program(&sources).is_ok());
+
+    let screen = statics(41);
+    let sources = [("Main.vm", main.as_str()), ("Screen.vm", screen.as_str())];
+    let error = translate_program(&sources).unwrap_err();
+    assert_eq!(
+        error.to_string(),
+        "The program has 241 statics but RAM[16] to RAM[255] only holds 240 (Main.vm uses 200, Screen.vm uses 41)"
+    );
+}
+
+#[test]
+fn test_bootstraps() {
+    let sources = [("Sys.vm", "function Sys.init 0\nlabel LOOP\ngoto LOOP")];
+    let translate = |bootstrap| {
+        let options = TranslationOptions {
+            bootstrap,
+            ..Default::default()
+        };
+        translate_program_with_options(&sources, &options).unwrap()
+    };
+
+    assert!(translate(Bootstrap::None).starts_with("// function Sys.init 0"));
+    assert!(translate(Bootstrap::Simple).starts_with("@261\nD=A\n@SP\nM=D\n@Sys.init\n0;JMP\n"));
+    let full = translate(Bootstrap::Full);
+    assert!(full.starts_with("@256\nD=A\n@SP\nM=D\n// call Sys.init 0\n"));
+    assert!(full.contains("(Bootstrap.RETURN_ADDRESS_CALL_0)"));
+}
@@ -0,0 +1,38 @@
+//! The Jack OS, compiled to VM code from the classes in `os/`, so that programs can run without a
+//! copy of the OS in every project. After changing a class, regenerate its VM code by running the
+//! compiler on `vm-translator/os`.
+
+/// Each OS class as a (file name, VM code) pair
+pub const OS_FILES: &[(&str, &str)] = &[
+    ("Array.vm", include_str!("../os/Array.vm")),
+    ("Keyboard.vm", include_str!("../os/Keyboard.vm")),
+    ("Math.vm", include_str!("../os/Math.vm")),
+    ("Memory.vm", include_str!("../os/Memory.vm")),
+    ("Output.vm", include_str!("../os/Output.vm")),
+    ("Screen.vm", include_str!("../os/Screen.vm")),
+    ("String.vm", include_str!("../os/String.vm")),
+    ("Sys.vm", include_str!("../os/Sys.vm")),
+];
+
+/// Add the OS classes which the program doesn't provide itself
+pub fn link_os<'a>(sources: &[(&'a str, &'a str)]) -> Vec<(&'a str, &'a str)> {
+    let mut linked = sources.to_vec();
+    for (os_file, contents) in OS_FILES {
+        if !sources.iter().any(|(file, _)| file == os_file) {
+            linked.push((os_file, contents));
+        }
+    }
+    linked
+}
+
+#[test]
+fn test_program_classes_replace_the_os() {
+    let linked = link_os(&[("Main.vm", ""), ("Math.vm", "// mine")]);
+
+    assert_eq!(linked.len(), OS_FILES.len() + 1);
+    assert_eq!(
+        linked.iter().find(|(file, _)| *file == "Math.vm"),
+        Some(&("Math.vm", "// mine"))
+    );
+    assert!(linked.iter().any(|(file, _)| *file == "Sys.vm"));
+}
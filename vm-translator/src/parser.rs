@@ -1,18 +1,16 @@
 use crate::ast::{Address, Function, MemorySegment, Operation, Stmt};
-use nom::character::complete::{
-    anychar, line_ending, multispace0, not_line_ending, space0, space1, u32,
-};
+use nom::character::complete::{anychar, line_ending, multispace0, space0, space1, u32};
 use nom::combinator::{all_consuming, eof};
 use nom::multi::many_till;
 use nom::{branch::alt, bytes::complete::tag, combinator::map, sequence::tuple, IResult};
+use parse_utils::line_comment;
 
 pub fn parser(text: &str) -> Result<Vec<Stmt>, String> {
     let lines = text.lines();
 
     let mut statements = vec![];
     for line in lines {
-        let (_, operation) = parse_operation(line)
-            .map_err(|err| format!("Error occurred parsing line {}: {}", line, err))?;
+        let operation = parse_line(line)?;
 
         if let Some(op) = operation {
             statements.push(Stmt {
@@ -25,6 +23,14 @@ pub fn parser(text: &str) -> Result<Vec<Stmt>, String> {
     Ok(statements)
 }
 
+/// Parse a single line of VM code. Comments and blank lines produce `None`.
+pub fn parse_line(line: &str) -> Result<Option<Operation>, String> {
+    let (_, operation) = parse_operation(line)
+        .map_err(|err| format!("Error occurred parsing line {}: {}", line, err))?;
+
+    Ok(operation)
+}
+
 fn parse_operation(i: &str) -> IResult<&str, Option<Operation>> {
     alt((
         parse_push,
@@ -126,14 +132,14 @@ fn parse_call(i: &str) -> IResult<&str, Option<Operation>> {
 
 fn parse_unary_operations(i: &str) -> IResult<&str, Option<Operation>> {
     map(
-        tuple((space0, alt((tag("neg"), tag("not"))))),
-        |(_, tag)| {
-            Some(match tag {
-                "neg" => Operation::Neg,
-                "not" => Operation::Not,
-                _ => panic!("Unknown unary instruction {}", tag),
-            })
-        },
+        tuple((
+            space0,
+            alt((
+                map(tag("neg"), |_| Operation::Neg),
+                map(tag("not"), |_| Operation::Not),
+            )),
+        )),
+        |(_, operation)| Some(operation),
     )(i)
 }
 
@@ -142,59 +148,34 @@ fn parse_binary_operations(i: &str) -> IResult<&str, Option<Operation>> {
         tuple((
             space0,
             alt((
-                tag("add"),
-                tag("sub"),
-                tag("eq"),
-                tag("gt"),
-                tag("lt"),
-                tag("and"),
-                tag("or"),
+                map(tag("add"), |_| Operation::Add),
+                map(tag("sub"), |_| Operation::Sub),
+                map(tag("eq"), |_| Operation::Eq),
+                map(tag("gt"), |_| Operation::Gt),
+                map(tag("lt"), |_| Operation::Lt),
+                map(tag("and"), |_| Operation::And),
+                map(tag("or"), |_| Operation::Or),
             )),
         )),
-        |(_, operation)| {
-            let op = match operation {
-                "add" => Operation::Add,
-                "sub" => Operation::Sub,
-                "eq" => Operation::Eq,
-                "gt" => Operation::Gt,
-                "lt" => Operation::Lt,
-                "and" => Operation::And,
-                "or" => Operation::Or,
-                _ => panic!("Unknown binary operation {}", operation),
-            };
-            Some(op)
-        },
+        |(_, operation)| Some(operation),
     )(i)
 }
 
 fn parse_memory_segment(i: &str) -> IResult<&str, MemorySegment> {
-    map(
-        alt((
-            tag("argument"),
-            tag("local"),
-            tag("static"),
-            tag("constant"),
-            tag("this"),
-            tag("that"),
-            tag("pointer"),
-            tag("temp"),
-        )),
-        |tag| match tag {
-            "argument" => MemorySegment::Arguments,
-            "local" => MemorySegment::Local,
-            "static" => MemorySegment::Static,
-            "constant" => MemorySegment::Constant,
-            "this" => MemorySegment::This,
-            "that" => MemorySegment::That,
-            "pointer" => MemorySegment::Pointer,
-            "temp" => MemorySegment::Temp,
-            _ => panic!("Unknown memory segment tag {}", tag),
-        },
-    )(i)
+    alt((
+        map(tag("argument"), |_| MemorySegment::Arguments),
+        map(tag("local"), |_| MemorySegment::Local),
+        map(tag("static"), |_| MemorySegment::Static),
+        map(tag("constant"), |_| MemorySegment::Constant),
+        map(tag("this"), |_| MemorySegment::This),
+        map(tag("that"), |_| MemorySegment::That),
+        map(tag("pointer"), |_| MemorySegment::Pointer),
+        map(tag("temp"), |_| MemorySegment::Temp),
+    ))(i)
 }
 
 fn parse_comment(i: &str) -> IResult<&str, Option<Operation>> {
-    map(tuple((space0, tag("//"), not_line_ending)), |_| None)(i)
+    map(tuple((space0, line_comment)), |_| None)(i)
 }
 
 fn parse_empty_lines(i: &str) -> IResult<&str, Option<Operation>> {
@@ -368,3 +349,10 @@ fn test_call_parsing() {
         })
     );
 }
+
+#[test]
+fn test_unknown_commands_are_errors() {
+    assert!(parser("push nowhere 1").is_err());
+    assert!(parser("jump END").is_err());
+    assert!(parser("call").is_err());
+}
@@ -0,0 +1,203 @@
+/*
+ * Peephole optimization of the generated assembly.
+ *
+ * Each VM command is translated on its own, so neighbouring commands often undo each other's
+ * work, e.g. a push ends by incrementing SP and the following add starts by decrementing it:
+ *
+ *   @SP     // push
+ *   A=M
+ *   M=D
+ *   @SP
+ *   M=M+1
+ *   @SP     // add
+ *   AM=M-1
+ *   D=M
+ *   A=A-1
+ *   M=D+M
+ *
+ * The rules below only rely on the words above the stack pointer being dead, which holds for
+ * all code the translator generates. Labels and jumps are never moved past.
+ */
+
+/// How many instructions may separate an SP increment from the decrement it cancels
+const MAX_GAP: usize = 8;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Kind<'a> {
+    /// `@value`
+    Address(&'a str),
+    /// `dest=comp;jump`, without a jump
+    Compute { dest: &'a str, comp: &'a str },
+    /// Labels and jumps, which nothing is moved across
+    Barrier,
+}
+
+fn kind(line: &str) -> Kind<'_> {
+    if let Some(value) = line.strip_prefix('@') {
+        Kind::Address(value)
+    } else if line.starts_with('(') || line.contains(';') {
+        Kind::Barrier
+    } else {
+        let (dest, comp) = line.split_once('=').unwrap_or(("", line));
+        Kind::Compute { dest, comp }
+    }
+}
+
+fn is_stack_pointer(value: &str) -> bool {
+    matches!(value, "SP" | "R0" | "0")
+}
+
+/// Optimize translated assembly. Comments and blank lines are kept where they are.
+pub fn optimize(asm: &str) -> String {
+    let mut lines: Vec<Option<String>> = asm.lines().map(|line| Some(line.to_owned())).collect();
+
+    while apply_rules(&mut lines) {}
+
+    lines.into_iter().flatten().collect::<Vec<_>>().join("\n")
+}
+
+/// Make one pass over the program, returning whether anything changed
+fn apply_rules(lines: &mut [Option<String>]) -> bool {
+    let instructions: Vec<usize> = lines
+        .iter()
+        .enumerate()
+        .filter_map(|(index, line)| {
+            let line = line.as_deref()?.trim();
+            (!line.is_empty() && !line.starts_with("//")).then_some(index)
+        })
+        .collect();
+    let text = |lines: &[Option<String>], position: usize| -> String {
+        instructions
+            .get(position)
+            .and_then(|index| lines[*index].as_deref())
+            .map_or(String::new(), |line| line.trim().to_owned())
+    };
+
+    let mut changed = false;
+    let mut position = 0;
+    while position < instructions.len() {
+        let window: Vec<String> = (position..(position + MAX_GAP + 4).min(instructions.len()))
+            .map(|position| text(lines, position))
+            .collect();
+
+        if let Some(edits) = cancel_increment(&window)
+            .or_else(|| redundant_reload(&window))
+            .or_else(|| store_then_load(&window))
+        {
+            // Carry on after the edits so no window sees a line deleted in this pass
+            let last = edits.iter().map(|(offset, _)| *offset).max().unwrap_or(0);
+            for (offset, replacement) in edits {
+                lines[instructions[position + offset]] = replacement;
+            }
+            changed = true;
+            position += last;
+        }
+        position += 1;
+    }
+    changed
+}
+
+/// An edit to the instruction at an offset into the window: `None` deletes it
+type Edits = Vec<(usize, Option<String>)>;
+
+/// `@SP, M=M+1, ..., @SP, M=M-1` leaves SP where it was, so drop both updates. The instructions
+/// in between must not touch SP or memory, and mustn't depend on A being SP.
+fn cancel_increment(window: &[String]) -> Option<Edits> {
+    if window.len() < 4 || window[0] != "@SP" || window[1] != "M=M+1" {
+        return None;
+    }
+
+    for end in 2..window.len() - 1 {
+        if window[end] == "@SP" && matches!(window[end + 1].as_str(), "M=M-1" | "AM=M-1") {
+            let replacement = if window[end + 1] == "M=M-1" {
+                None
+            } else {
+                Some("A=M".to_owned())
+            };
+            return Some(vec![(0, None), (1, None), (end + 1, replacement)]);
+        }
+
+        let previous_sets_address = end > 2 && matches!(kind(&window[end - 1]), Kind::Address(_));
+        match kind(&window[end]) {
+            Kind::Address(value) if !is_stack_pointer(value) => {}
+            Kind::Compute { dest, comp }
+                if end > 2
+                    && !dest.contains('M')
+                    && (!comp.contains('M') || previous_sets_address) => {}
+            _ => return None,
+        }
+    }
+    None
+}
+
+/// Loading an address which is already in A. `@X, ..., @X` needs no second load if nothing
+/// between them writes A, and neither does `@SP, A=M, ..., @SP, A=M` as writes to the top of
+/// the stack can't change SP.
+fn redundant_reload(window: &[String]) -> Option<Edits> {
+    let Kind::Address(_) = kind(window.first()?) else {
+        return None;
+    };
+    let reloads_stack_top = window[0] == "@SP" && window.get(1).map(String::as_str) == Some("A=M");
+    let (start, load) = if reloads_stack_top {
+        (2, &window[..2])
+    } else {
+        (1, &window[..1])
+    };
+
+    for end in start..window.len() {
+        if window[end..].starts_with(load) {
+            return Some(
+                (end..end + load.len())
+                    .map(|offset| (offset, None))
+                    .collect(),
+            );
+        }
+        match kind(&window[end]) {
+            Kind::Compute { dest, .. } if !dest.contains('A') => {}
+            _ => return None,
+        }
+    }
+    None
+}
+
+/// `M=D, D=M` reads back the value just written
+fn store_then_load(window: &[String]) -> Option<Edits> {
+    (window.len() >= 2 && window[0] == "M=D" && window[1] == "D=M").then(|| vec![(1, None)])
+}
+
+#[test]
+fn test_push_then_add() {
+    let asm = "@7\nD=A\n@SP\nA=M\nM=D\n@SP\nM=M+1\n@SP\nAM=M-1\nD=M\nA=A-1\nM=D+M";
+    assert_eq!(optimize(asm), "@7\nD=A\n@SP\nA=M\nM=D\nA=A-1\nM=D+M");
+}
+
+#[test]
+fn test_push_then_pop() {
+    // push constant 5, pop temp 0
+    let asm = "// push constant 5\n@5\nD=A\n@SP\nA=M\nM=D\n@SP\nM=M+1\n\
+               // pop temp 0\n@SP\nM=M-1\nA=M\nD=M\n@R5\nM=D";
+    assert_eq!(
+        optimize(asm),
+        "// push constant 5\n@5\nD=A\n@SP\nA=M\nM=D\n// pop temp 0\n@R5\nM=D"
+    );
+
+    // push local 0, pop local 1 computes the address of local 1 in between
+    let asm = "@SP\nA=M\nM=D\n@SP\nM=M+1\n@1\nD=A\n@LCL\nD=D+M\n\
+               @SP\nM=M-1\nA=M+1\nM=D\nA=A-1\nD=M\nA=A+1\nA=M\nM=D";
+    assert_eq!(
+        optimize(asm),
+        "@SP\nA=M\nM=D\n@1\nD=A\n@LCL\nD=D+M\n@SP\nA=M+1\nM=D\nA=A-1\nD=M\nA=A+1\nA=M\nM=D"
+    );
+}
+
+#[test]
+fn test_labels_and_memory_writes_block_rules() {
+    let labelled = "@SP\nM=M+1\n(LOOP)\n@SP\nM=M-1";
+    assert_eq!(optimize(labelled), labelled);
+
+    let writes = "@SP\nM=M+1\n@R13\nM=D\n@SP\nM=M-1";
+    assert_eq!(optimize(writes), writes);
+
+    let jumps = "@SP\nA=M\nD;JEQ\n@SP\nA=M";
+    assert_eq!(optimize(jumps), jumps);
+}
@@ -0,0 +1,167 @@
+use std::collections::HashMap;
+
+use crate::parser::parse_line;
+
+/// Map each instruction of translated assembly back to the VM command it came from. Each line
+/// holds the ROM address and the source location, separated by a tab.
+///
+/// `sources` are the .vm files in the order they were translated. `vm_maps` holds the compiler's
+/// .vm.map of any of them, by file name, so that their instructions are traced on to Jack.
+/// Instructions which don't come from a VM command, such as the bootstrap, are left out.
+pub fn source_map(
+    asm: &str,
+    sources: &[(&str, &str)],
+    vm_maps: &HashMap<String, String>,
+) -> String {
+    let mut commands = vm_commands(sources);
+    let mut next = commands.next();
+
+    let jack: HashMap<&str, HashMap<usize, &str>> = vm_maps
+        .iter()
+        .map(|(file_name, map)| (file_name.as_str(), parse_vm_map(map)))
+        .collect();
+
+    let mut map = String::new();
+    let mut current = None;
+    let mut address = 0;
+    for line in asm.lines().map(str::trim) {
+        if let Some(comment) = line.strip_prefix("// ") {
+            current = None;
+            if let Some((file_name, vm_line, text)) = next {
+                if comment == text {
+                    current = Some((file_name, vm_line));
+                    next = commands.next();
+                }
+            }
+        } else if line.is_empty() || line.starts_with('(') || line.starts_with("//") {
+            continue;
+        } else {
+            if let Some((file_name, vm_line)) = current {
+                match jack.get(file_name).and_then(|lines| lines.get(&vm_line)) {
+                    Some(origin) => map.push_str(&format!("{}\t{}\n", address, origin)),
+                    None => map.push_str(&format!("{}\t{}:{}\n", address, file_name, vm_line)),
+                }
+            }
+            address += 1;
+        }
+    }
+    map
+}
+
+/// Interleave translated assembly with the VM commands it came from. Each command is followed by
+/// its instructions, each prefixed by its ROM address, and labels are indented to line up with the
+/// instructions.
+pub fn listing(asm: &str, sources: &[(&str, &str)]) -> String {
+    let mut commands = vm_commands(sources);
+    let mut next = commands.next();
+
+    let mut listing = String::new();
+    let mut address = 0;
+    for line in asm.lines().map(str::trim) {
+        if let Some(comment) = line.strip_prefix("// ") {
+            if !listing.is_empty() {
+                listing.push('\n');
+            }
+            match next {
+                Some((file_name, vm_line, text)) if comment == text => {
+                    listing.push_str(&format!("// {}:{}  {}\n", file_name, vm_line, text));
+                    next = commands.next();
+                }
+                _ => listing.push_str(&format!("// {}\n", comment)),
+            }
+        } else if line.is_empty() || line.starts_with("//") {
+            continue;
+        } else if line.starts_with('(') {
+            listing.push_str(&format!("       {}\n", line));
+        } else {
+            listing.push_str(&format!("{:>5}  {}\n", address, line));
+            address += 1;
+        }
+    }
+    listing
+}
+
+/// The ROM address each command of the sources starts at, in the order the commands appear. A
+/// command without instructions, such as a label, starts at the next instruction. `None` marks a
+/// command whose translation couldn't be found, e.g. because an optimization merged it away.
+pub fn command_addresses(asm: &str, sources: &[(&str, &str)]) -> Vec<Option<usize>> {
+    let commands: Vec<&str> = vm_commands(sources).map(|(_, _, text)| text).collect();
+    let mut addresses = vec![None; commands.len()];
+    let mut next = 0;
+    let mut address = 0;
+    for line in asm.lines().map(str::trim) {
+        if let Some(comment) = line.strip_prefix("// ") {
+            // Commands missing from the assembly are skipped over
+            if let Some(found) = commands[next..].iter().position(|text| *text == comment) {
+                addresses[next + found] = Some(address);
+                next += found + 1;
+            }
+        } else if !(line.is_empty() || line.starts_with('(') || line.starts_with("//")) {
+            address += 1;
+        }
+    }
+    addresses
+}
+
+/// The file, line number and text of each command of the sources. Each command is translated
+/// after a comment holding its text, in this order.
+fn vm_commands<'a>(
+    sources: &'a [(&'a str, &'a str)],
+) -> impl Iterator<Item = (&'a str, usize, &'a str)> + 'a {
+    sources.iter().flat_map(|(file_name, contents)| {
+        contents
+            .lines()
+            .enumerate()
+            .filter(|(_, line)| matches!(parse_line(line), Ok(Some(_))))
+            .map(move |(index, line)| (*file_name, index + 1, line))
+    })
+}
+
+/// Read the lines of a .vm.map file, skipping any which don't hold a line number and a location
+fn parse_vm_map(map: &str) -> HashMap<usize, &str> {
+    map.lines()
+        .filter_map(|line| {
+            let (vm_line, origin) = line.split_once('\t')?;
+            Some((vm_line.parse().ok()?, origin))
+        })
+        .collect()
+}
+
+#[test]
+fn test_source_map() {
+    let main = "function Main.main 0\n// a comment\npush constant 1\nreturn";
+    let asm = crate::translate_program(&[("Main.vm", main)]).unwrap();
+    let vm_maps = HashMap::from([(
+        "Main.vm".to_owned(),
+        "1\tMain.jack:2\n3\tMain.jack:3\n".to_owned(),
+    )]);
+    let map = source_map(&asm, &[("Main.vm", main)], &vm_maps);
+    let lines: Vec<&str> = map.lines().collect();
+
+    // The 6 bootstrap instructions come first. `return` isn't in the .vm.map, so it stops at VM.
+    assert_eq!(lines[0], "6\tMain.jack:2");
+    assert!(lines.iter().any(|line| line.ends_with("\tMain.jack:3")));
+    assert!(lines.last().unwrap().ends_with("\tMain.vm:4"));
+}
+
+#[test]
+fn test_command_addresses() {
+    let main = "function Main.main 0\npush constant 1\nlabel END\ngoto END";
+    let asm = crate::translate_program(&[("Main.vm", main)]).unwrap();
+    let addresses = command_addresses(&asm, &[("Main.vm", main)]);
+
+    // After the 6 bootstrap instructions. The label takes no room, so goto starts where it does.
+    assert_eq!(addresses, [Some(6), Some(12), Some(19), Some(19)]);
+}
+
+#[test]
+fn test_listing() {
+    let main = "function Main.main 0\npush constant 1\nlabel END\ngoto END";
+    let asm = crate::translate_program(&[("Main.vm", main)]).unwrap();
+    let listing = listing(&asm, &[("Main.vm", main)]);
+
+    assert!(listing.starts_with("    0  @"));
+    assert!(listing.contains("\n// Main.vm:1  function Main.main 0\n       (Main.main)\n"));
+    assert!(listing.contains("// Main.vm:2  push constant 1\n   12  @1\n   13  D=A\n"));
+    assert!(listing.contains("// Main.vm:3  label END\n       (Main.main$END)\n"));
+}
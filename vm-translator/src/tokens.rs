@@ -0,0 +1,87 @@
+use nom::bytes::complete::take_till1;
+use nom::character::complete::{digit1, multispace1};
+use nom::combinator::all_consuming;
+use nom::IResult;
+use parse_utils::line_comment;
+use parse_utils::tokens::{Token, TokenKind};
+
+const COMMANDS: [&str; 17] = [
+    "push", "pop", "add", "sub", "neg", "eq", "gt", "lt", "and", "or", "not", "label", "goto",
+    "if-goto", "function", "call", "return",
+];
+
+const SEGMENTS: [&str; 8] = [
+    "argument", "local", "static", "constant", "this", "that", "pointer", "temp",
+];
+
+fn word(i: &str) -> IResult<&str, &str> {
+    take_till1(|c: char| c.is_whitespace())(i)
+}
+
+/// Split VM code into classified tokens for highlighting.
+///
+/// Commands are keywords, memory segments are variables and the operand of `function`/`call`
+/// or `label`/`goto`/`if-goto` is a function or label respectively.
+pub fn tokenize_vm(source: &str) -> Vec<Token> {
+    let mut tokens = Vec::new();
+    let mut rest = source;
+    let mut previous_word = "";
+
+    while !rest.is_empty() {
+        let offset = source.len() - rest.len();
+
+        if let Ok((remaining, _)) = multispace1::<&str, nom::error::Error<&str>>(rest) {
+            rest = remaining;
+        } else if let Ok((remaining, text)) = line_comment::<&str, nom::error::Error<&str>>(rest) {
+            tokens.push(Token::new(offset, text.len(), TokenKind::Comment));
+            rest = remaining;
+        } else if let Ok((remaining, text)) = word(rest) {
+            let kind = match previous_word {
+                "function" | "call" => TokenKind::Function,
+                "label" | "goto" | "if-goto" => TokenKind::Label,
+                _ if COMMANDS.contains(&text) => TokenKind::Keyword,
+                _ if SEGMENTS.contains(&text) => TokenKind::Variable,
+                _ if all_consuming(digit1::<&str, nom::error::Error<&str>>)(text).is_ok() => {
+                    TokenKind::Number
+                }
+                _ => TokenKind::Variable,
+            };
+            tokens.push(Token::new(offset, text.len(), kind));
+            previous_word = text;
+            rest = remaining;
+        } else {
+            break;
+        }
+    }
+
+    tokens
+}
+
+#[test]
+fn test_tokenize_vm() {
+    let source = "function Main.main 2\n  push local 1 // x\n  if-goto END";
+    let kinds: Vec<(&str, TokenKind)> = tokenize_vm(source)
+        .into_iter()
+        .map(|token| {
+            (
+                &source[token.offset..token.offset + token.length],
+                token.kind,
+            )
+        })
+        .collect();
+
+    assert_eq!(
+        kinds,
+        vec![
+            ("function", TokenKind::Keyword),
+            ("Main.main", TokenKind::Function),
+            ("2", TokenKind::Number),
+            ("push", TokenKind::Keyword),
+            ("local", TokenKind::Variable),
+            ("1", TokenKind::Number),
+            ("// x", TokenKind::Comment),
+            ("if-goto", TokenKind::Keyword),
+            ("END", TokenKind::Label),
+        ]
+    );
+}
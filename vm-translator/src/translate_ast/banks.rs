@@ -0,0 +1,55 @@
+use std::collections::HashMap;
+
+/// The ROM bank each function is placed in when a program is split into banks. Calls between
+/// functions switch to the callee's bank and returns switch back to the caller's.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct FunctionBanks {
+    banks: HashMap<String, u16>,
+}
+
+impl FunctionBanks {
+    /// Place functions in banks in program order, moving to the next bank at each bank start. The
+    /// assembler splits the program at the same labels.
+    pub fn new<'a>(
+        functions: impl IntoIterator<Item = &'a str>,
+        bank_starts: &[String],
+    ) -> Result<Self, String> {
+        let mut banks = HashMap::new();
+        let mut bank = 0;
+        for function in functions {
+            if bank_starts.iter().any(|start| start == function) {
+                bank += 1;
+            }
+            banks.insert(function.to_owned(), bank);
+        }
+        match bank_starts.iter().find(|start| !banks.contains_key(*start)) {
+            Some(start) => Err(start.clone()),
+            None => Ok(Self { banks }),
+        }
+    }
+
+    /// The bank of a function, where code outside any function and functions defined elsewhere
+    /// are taken to be in the first bank
+    pub fn bank(&self, function: Option<&str>) -> u16 {
+        function
+            .and_then(|function| self.banks.get(function))
+            .copied()
+            .unwrap_or(0)
+    }
+}
+
+#[test]
+fn test_function_banks() {
+    let starts = ["Far.a".to_owned(), "Far.c".to_owned()];
+    let banks = FunctionBanks::new(["Main.main", "Far.a", "Far.b", "Far.c"], &starts).unwrap();
+
+    assert_eq!(banks.bank(Some("Main.main")), 0);
+    assert_eq!(banks.bank(Some("Far.b")), 1);
+    assert_eq!(banks.bank(Some("Far.c")), 2);
+    assert_eq!(banks.bank(Some("Sys.init")), 0);
+    assert_eq!(banks.bank(None), 0);
+    assert_eq!(
+        FunctionBanks::new(["Main.main"], &starts),
+        Err("Far.a".to_owned())
+    );
+}
@@ -0,0 +1,56 @@
+use crate::ast::{MemorySegment, Operation, Stmt};
+
+/// Work out which locals of a function have to be zeroed on entry.
+///
+/// `body` is everything after the function command. The analysis only follows the straight-line
+/// code at the start of the function: a local which is popped there before it is ever pushed is
+/// assigned on every path, so it doesn't need initializing. Once the code branches or returns,
+/// every local which hasn't been assigned yet is initialized.
+pub fn locals_needing_init(body: &[Stmt], num_locals: u32) -> Vec<bool> {
+    let mut needs_init = vec![true; num_locals as usize];
+    let mut seen = vec![false; num_locals as usize];
+
+    for stmt in body {
+        match &stmt.operation {
+            Operation::Push(address) if address.memory_segment == MemorySegment::Local => {
+                if let Some(seen) = seen.get_mut(address.address as usize) {
+                    *seen = true;
+                }
+            }
+            Operation::Pop(address) if address.memory_segment == MemorySegment::Local => {
+                let index = address.address as usize;
+                if index < seen.len() && !seen[index] {
+                    needs_init[index] = false;
+                    seen[index] = true;
+                }
+            }
+            Operation::Jump(_)
+            | Operation::ConditionalJump(_)
+            | Operation::Return
+            | Operation::Function(_) => break,
+            _ => {}
+        }
+    }
+
+    needs_init
+}
+
+#[test]
+fn test_locals_needing_init() {
+    let body = crate::parser::parser(
+        "push constant 1
+        pop local 0
+        push local 1
+        pop local 1
+        push local 0
+        pop local 2
+        if-goto END
+        pop local 3",
+    )
+    .unwrap();
+
+    assert_eq!(
+        locals_needing_init(&body, 4),
+        vec![false, true, false, true]
+    );
+}
@@ -1,5 +1,8 @@
+mod banks;
+mod definite_assignment;
 mod translate_ast;
 mod translate_pop;
 mod translate_push;
 
+pub use banks::FunctionBanks;
 pub use translate_ast::translate_ast;
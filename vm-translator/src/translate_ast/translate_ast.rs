@@ -1,17 +1,47 @@
-use super::{translate_pop::translate_pop, translate_push::translate_push};
+use super::{
+    banks::FunctionBanks, definite_assignment::locals_needing_init, translate_pop::translate_pop,
+    translate_push::translate_push,
+};
 use crate::ast::{Function, Operation, Stmt};
-
-pub fn translate_ast(ast: Vec<Stmt>, file_name: &str) -> Result<String, String> {
+use crate::TranslationOptions;
+
+pub fn translate_ast(
+    ast: Vec<Stmt>,
+    file_name: &str,
+    options: &TranslationOptions,
+    banks: Option<&FunctionBanks>,
+) -> Result<String, String> {
     let mut output = vec![];
     let mut eq_counter = 0;
     let mut gt_counter = 0;
     let mut lt_counter = 0;
     let mut return_counter = 0;
     let mut call_counter = 0;
-    for stmt in ast {
-        let mut asm_lines = match stmt.operation {
-            Operation::Push(address) => translate_push(&address, file_name)?,
-            Operation::Pop(address) => translate_pop(&address, file_name)?,
+    // Labels are scoped to the function they appear in
+    let mut function_name: Option<&str> = None;
+    let mut index = 0;
+    while index < ast.len() {
+        if options.optimization_level > 0 {
+            if let Some((jump, label, length)) = fused_comparison(&ast[index..]) {
+                for stmt in &ast[index..index + length] {
+                    output.push(format!("// {}", stmt.text));
+                }
+                output.append(&mut translate_compare_and_jump(
+                    jump,
+                    &scoped_label(function_name, label),
+                ));
+                index += length;
+                continue;
+            }
+        }
+
+        let stmt = &ast[index];
+        if let Operation::Function(function) = &stmt.operation {
+            function_name = Some(&function.name);
+        }
+        let mut asm_lines = match &stmt.operation {
+            Operation::Push(address) => translate_push(address, file_name)?,
+            Operation::Pop(address) => translate_pop(address, file_name)?,
             Operation::Add => translate_add(),
             Operation::Sub => translate_sub(),
             Operation::Neg => translate_neg(),
@@ -21,20 +51,40 @@ pub fn translate_ast(ast: Vec<Stmt>, file_name: &str) -> Result<String, String>
             Operation::And => translate_and(),
             Operation::Or => translate_or(),
             Operation::Not => translate_not(),
-            Operation::Label(label) => translate_label(&label),
-            Operation::ConditionalJump(label) => translate_if_goto(&label),
-            Operation::Jump(label) => translate_goto(&label),
-            Operation::Function(function) => translate_function(&function),
-            Operation::Return => translate_return(&mut return_counter, file_name),
-            Operation::Call(function) => translate_call(&function, &mut call_counter, file_name),
+            Operation::Label(label) => translate_label(&scoped_label(function_name, label)),
+            Operation::ConditionalJump(label) => {
+                translate_if_goto(&scoped_label(function_name, label))
+            }
+            Operation::Jump(label) => translate_goto(&scoped_label(function_name, label)),
+            Operation::Function(function) if options.optimization_level > 0 => {
+                let needs_init = locals_needing_init(&ast[index + 1..], function.num);
+                translate_function_with_init(function, &needs_init)
+            }
+            Operation::Function(function) => translate_function(function),
+            Operation::Return => translate_return(&mut return_counter, file_name, banks.is_some()),
+            Operation::Call(function) => {
+                let banks = banks
+                    .map(|banks| (banks.bank(function_name), banks.bank(Some(&function.name))));
+                translate_call(function, &mut call_counter, file_name, banks)
+            }
         };
         output.push(format!("// {}", stmt.text));
         output.append(&mut asm_lines);
+        index += 1;
     }
 
     Ok(output.join("\n"))
 }
 
+/// The assembly symbol for a label: `Function$label` inside a function, as labels of different
+/// functions may share a name
+fn scoped_label(function_name: Option<&str>, label: &str) -> String {
+    match function_name {
+        Some(function_name) => format!("{}${}", function_name, label),
+        None => label.to_owned(),
+    }
+}
+
 fn translate_add() -> Vec<String> {
     let mut asm = Vec::new();
 
@@ -127,6 +177,44 @@ fn translate_lt(lt_counter: &mut i32, file_name: &str) -> Vec<String> {
     asm
 }
 
+/// Match a comparison whose result only decides a conditional jump, with or without a `not` in
+/// between. Returns the jump condition, the label and the number of statements matched.
+fn fused_comparison(statements: &[Stmt]) -> Option<(&'static str, &str, usize)> {
+    let (jump, inverse) = match statements.first()?.operation {
+        Operation::Eq => ("JEQ", "JNE"),
+        Operation::Gt => ("JGT", "JLE"),
+        Operation::Lt => ("JLT", "JGE"),
+        _ => return None,
+    };
+
+    match (
+        statements.get(1).map(|stmt| &stmt.operation),
+        statements.get(2).map(|stmt| &stmt.operation),
+    ) {
+        (Some(Operation::ConditionalJump(label)), _) => Some((jump, label, 2)),
+        (Some(Operation::Not), Some(Operation::ConditionalJump(label))) => {
+            Some((inverse, label, 3))
+        }
+        _ => None,
+    }
+}
+
+/// Pop two values and jump on how they compare, without materializing a boolean
+fn translate_compare_and_jump(jump: &str, label: &str) -> Vec<String> {
+    let mut asm = Vec::new();
+
+    asm.push("@SP".to_owned());
+    asm.push("AM=M-1".to_owned());
+    asm.push("D=M".to_owned());
+    asm.push("@SP".to_owned());
+    asm.push("AM=M-1".to_owned());
+    asm.push("D=M-D".to_owned());
+    asm.push(format!("@{}", label));
+    asm.push(format!("D;{}", jump));
+
+    asm
+}
+
 fn translate_and() -> Vec<String> {
     let mut asm = Vec::new();
 
@@ -210,7 +298,36 @@ fn translate_function(function: &Function) -> Vec<String> {
     asm
 }
 
-fn translate_return(return_counter: &mut i32, file_name: &str) -> Vec<String> {
+/// Translate a function which only zeroes the locals that might be read before being assigned
+fn translate_function_with_init(function: &Function, needs_init: &[bool]) -> Vec<String> {
+    let mut asm = Vec::new();
+
+    asm.push(format!("({})", function.name));
+
+    if let Some(last) = needs_init.iter().rposition(|init| *init) {
+        asm.push("@LCL".to_owned());
+        asm.push("A=M".to_owned());
+        for (index, init) in needs_init[..=last].iter().enumerate() {
+            if index > 0 {
+                asm.push("A=A+1".to_owned());
+            }
+            if *init {
+                asm.push("M=0".to_owned());
+            }
+        }
+    }
+
+    asm.push(format!("@{}", function.num));
+    asm.push("D=A".to_owned());
+    asm.push("@SP".to_owned());
+    asm.push("M=D+M".to_owned());
+
+    asm
+}
+
+/// Return from a function. With banks, the frame holds the caller's bank between the return
+/// address and LCL.
+fn translate_return(return_counter: &mut i32, file_name: &str, banked: bool) -> Vec<String> {
     let mut asm = Vec::new();
 
     // endFrame = LCL
@@ -219,8 +336,8 @@ fn translate_return(return_counter: &mut i32, file_name: &str) -> Vec<String> {
     asm.push("@R13".to_owned());
     asm.push("M=D".to_owned());
 
-    // retAddress = *(endFrame - 5)
-    asm.push("@5".to_owned());
+    // retAddress = *(endFrame - 5), or endFrame - 6 with banks
+    asm.push(format!("@{}", if banked { 6 } else { 5 }));
     asm.push("D=A".to_owned());
     asm.push("@R13".to_owned());
     asm.push("A=M-D".to_owned());
@@ -273,6 +390,15 @@ fn translate_return(return_counter: &mut i32, file_name: &str) -> Vec<String> {
     ));
     asm.push("D;JGT".to_owned());
 
+    // BANK = *(endFrame - 5) // R13 was left at endFrame - 4 by the loop
+    if banked {
+        asm.push("@R13".to_owned());
+        asm.push("A=M-1".to_owned());
+        asm.push("D=M".to_owned());
+        asm.push("@BANK".to_owned());
+        asm.push("M=D".to_owned());
+    }
+
     // goto retAddress
     asm.push("@R15".to_owned());
     asm.push("A=M".to_owned());
@@ -283,7 +409,14 @@ fn translate_return(return_counter: &mut i32, file_name: &str) -> Vec<String> {
     asm
 }
 
-fn translate_call(function: &Function, call_count: &mut i32, file_name: &str) -> Vec<String> {
+/// Call a function. `banks` holds the caller's and the callee's bank when the program is split
+/// into banks.
+fn translate_call(
+    function: &Function,
+    call_count: &mut i32,
+    file_name: &str,
+    banks: Option<(u16, u16)>,
+) -> Vec<String> {
     let mut asm = Vec::new();
 
     // push returnAddress
@@ -294,6 +427,16 @@ fn translate_call(function: &Function, call_count: &mut i32, file_name: &str) ->
     asm.push("A=M-1".to_owned());
     asm.push("M=D".to_owned());
 
+    // push returnBank
+    if let Some((caller, _)) = banks {
+        asm.push(format!("@{}", caller));
+        asm.push("D=A".to_owned());
+        asm.push("@SP".to_owned());
+        asm.push("M=M+1".to_owned());
+        asm.push("A=M-1".to_owned());
+        asm.push("M=D".to_owned());
+    }
+
     // push LCL
     asm.push("@LCL".to_owned());
     asm.push("D=M".to_owned());
@@ -326,10 +469,11 @@ fn translate_call(function: &Function, call_count: &mut i32, file_name: &str) ->
     asm.push("A=M-1".to_owned());
     asm.push("M=D".to_owned());
 
-    // ARG = SP-5-nArgs // Reposition ARG
+    // ARG = SP-5-nArgs // Reposition ARG, one further back for the bank
+    let frame_size = if banks.is_some() { 6 } else { 5 };
     asm.push("@SP".to_owned());
     asm.push("D=M".to_owned());
-    asm.push(format!("@{}", 5 + function.num));
+    asm.push(format!("@{}", frame_size + function.num));
     asm.push("D=D-A".to_owned());
     asm.push("@ARG".to_owned());
     asm.push("M=D".to_owned());
@@ -340,6 +484,14 @@ fn translate_call(function: &Function, call_count: &mut i32, file_name: &str) ->
     asm.push("@LCL".to_owned());
     asm.push("M=D".to_owned());
 
+    // BANK = calleeBank // Switched to by the jump
+    if let Some((_, callee)) = banks {
+        asm.push(format!("@{}", callee));
+        asm.push("D=A".to_owned());
+        asm.push("@BANK".to_owned());
+        asm.push("M=D".to_owned());
+    }
+
     // goto functionName // Transfer control to the called function
     asm.push(format!("@{}", function.name));
     asm.push("0;JMP".to_owned());
@@ -354,3 +506,82 @@ fn translate_call(function: &Function, call_count: &mut i32, file_name: &str) ->
 
     asm
 }
+
+#[test]
+fn test_function_only_zeroes_locals_which_need_it() {
+    let function = Function {
+        name: "Main.main".to_owned(),
+        num: 4,
+    };
+
+    assert_eq!(
+        translate_function_with_init(&function, &[false, true, false, false]),
+        vec![
+            "(Main.main)",
+            "@LCL",
+            "A=M",
+            "A=A+1",
+            "M=0",
+            "@4",
+            "D=A",
+            "@SP",
+            "M=D+M"
+        ]
+    );
+    assert_eq!(
+        translate_function_with_init(&function, &[false; 4]),
+        vec!["(Main.main)", "@4", "D=A", "@SP", "M=D+M"]
+    );
+}
+
+#[test]
+fn test_comparisons_fuse_with_conditional_jumps() {
+    let optimized = TranslationOptions {
+        optimization_level: 1,
+        ..Default::default()
+    };
+    let translate = |source, options| {
+        translate_ast(
+            crate::parser::parser(source).unwrap(),
+            "Main",
+            options,
+            None,
+        )
+        .unwrap()
+    };
+
+    assert_eq!(
+        translate("lt\nif-goto LOOP", &optimized),
+        "// lt\n// if-goto LOOP\n@SP\nAM=M-1\nD=M\n@SP\nAM=M-1\nD=M-D\n@LOOP\nD;JLT"
+    );
+    assert!(translate("eq\nnot\nif-goto END", &optimized).ends_with("@END\nD;JNE"));
+
+    // A label in between could be jumped to, so the boolean is needed
+    assert!(translate("gt\nlabel L\nif-goto L", &optimized).contains("GT_END_0"));
+    assert!(translate("gt\nif-goto L", &TranslationOptions::default()).contains("GT_END_0"));
+}
+
+#[test]
+fn test_labels_are_scoped_to_their_function() {
+    let asm = translate_ast(
+        crate::parser::parser(
+            "function Array.new 0
+            label new.if.0
+            goto new.if.0
+            function String.new 0
+            push constant 0
+            if-goto new.if.0
+            label new.if.0",
+        )
+        .unwrap(),
+        "Main",
+        &TranslationOptions::default(),
+        None,
+    )
+    .unwrap();
+
+    assert!(asm.contains("(Array.new$new.if.0)"));
+    assert!(asm.contains("@Array.new$new.if.0\n0;JMP"));
+    assert!(asm.contains("@String.new$new.if.0\nD;JNE"));
+    assert!(asm.contains("(String.new$new.if.0)"));
+}
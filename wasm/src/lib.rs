@@ -0,0 +1,287 @@
+//! JavaScript bindings for the toolchain. Build with
+//! `wasm-pack build wasm --target web` to produce an npm package.
+
+use emulator::{Cpu, Stop, VmMachine};
+use wasm_bindgen::prelude::*;
+
+/// Compile a single Jack class into VM code. `filename` should be the name of the .jack file,
+/// e.g. `Main.jack`.
+#[wasm_bindgen]
+pub fn compile_string(filename: &str, source: &str) -> Result<String, JsError> {
+    let vm_files = compiler::compile_strings(&[(filename, source)]).map_err(to_js_error)?;
+
+    Ok(vm_files
+        .into_iter()
+        .map(|(_, vm_code)| vm_code)
+        .collect::<Vec<_>>()
+        .join("\n"))
+}
+
+/// Translate the contents of a .vm file into Hack assembly. `filename` should be the name of the
+/// .vm file, e.g. `Main.vm`, as it is used to name statics.
+#[wasm_bindgen]
+pub fn translate_string(filename: &str, source: &str) -> Result<String, JsError> {
+    vm_translator::translate_string(filename, source).map_err(to_js_error)
+}
+
+/// Assemble Hack assembly into the text form of a .hack file
+#[wasm_bindgen]
+pub fn assemble_string(source: &str) -> Result<String, JsError> {
+    assembler::assemble_string(source).map_err(to_js_error)
+}
+
+/// The Hack computer running a program, for stepping through it or running it from JavaScript
+#[wasm_bindgen]
+pub struct Emulator {
+    cpu: Cpu,
+}
+
+#[wasm_bindgen]
+impl Emulator {
+    /// Load the text form of a .hack file into ROM
+    #[wasm_bindgen(constructor)]
+    pub fn new(hack: &str) -> Result<Emulator, JsError> {
+        let rom = emulator::parse_hack(hack).map_err(to_js_error)?;
+        Ok(Self { cpu: Cpu::new(rom) })
+    }
+
+    /// Execute a single instruction. Returns `halted` or `end of program` if the program has
+    /// ended.
+    pub fn step(&mut self) -> Result<Option<String>, JsError> {
+        let stop = self.cpu.step().map_err(to_js_error)?;
+        Ok(stop.map(|stop| stop_name(stop).to_owned()))
+    }
+
+    /// Run until the program ends or `max_cycles` more instructions have been executed, returning
+    /// why it stopped: `halted`, `end of program` or `cycle limit`
+    pub fn run(&mut self, max_cycles: u64) -> Result<String, JsError> {
+        let stop = self.cpu.run(max_cycles).map_err(to_js_error)?;
+        Ok(stop_name(stop).to_owned())
+    }
+
+    pub fn peek(&self, address: u16) -> u16 {
+        self.cpu.peek(address)
+    }
+
+    pub fn poke(&mut self, address: u16, value: u16) {
+        self.cpu.poke(address, value);
+    }
+
+    /// Press a key, or release every key with 0
+    pub fn set_keyboard(&mut self, key: u16) {
+        self.cpu.set_keyboard(key);
+    }
+
+    /// The screen memory map, 32 words per row with the least significant bit of each word the
+    /// leftmost pixel
+    pub fn screen(&self) -> Vec<u16> {
+        self.cpu.screen().to_vec()
+    }
+
+    pub fn a(&self) -> u16 {
+        self.cpu.a()
+    }
+
+    pub fn d(&self) -> u16 {
+        self.cpu.d()
+    }
+
+    pub fn pc(&self) -> u16 {
+        self.cpu.pc()
+    }
+
+    /// The number of instructions executed so far
+    pub fn cycles(&self) -> u64 {
+        self.cpu.cycles()
+    }
+}
+
+/// The VM emulator running the output of the compiler, so that a program can be stepped through
+/// one VM command at a time with its segments in view
+#[wasm_bindgen]
+pub struct VmEmulator {
+    vm: VmMachine,
+}
+
+#[wasm_bindgen]
+impl VmEmulator {
+    /// Load .vm files, given as their file names and contents in the same order, optionally with
+    /// the built-in OS. Execution starts at Sys.init if the program has it.
+    #[wasm_bindgen(constructor)]
+    pub fn new(
+        names: Vec<String>,
+        sources: Vec<String>,
+        with_os: bool,
+    ) -> Result<VmEmulator, JsError> {
+        if names.len() != sources.len() {
+            return Err(JsError::new("Every .vm file needs a name"));
+        }
+        let mut files: Vec<(&str, &str)> = names
+            .iter()
+            .zip(&sources)
+            .map(|(name, source)| (name.as_str(), source.as_str()))
+            .collect();
+        if with_os {
+            files = vm_translator::link_os(&files);
+        }
+        let vm = VmMachine::load(&files).map_err(to_js_error)?;
+        Ok(Self { vm })
+    }
+
+    /// Abandon whatever is running and call `function` on an empty stack
+    pub fn start(&mut self, function: &str) -> Result<(), JsError> {
+        self.vm.start(function).map_err(to_js_error)
+    }
+
+    /// Execute a single command. Returns `end of program` once the program has ended.
+    pub fn step(&mut self) -> Result<Option<String>, JsError> {
+        let stop = self.vm.step().map_err(to_js_error)?;
+        Ok(stop.map(|stop| stop_name(stop).to_owned()))
+    }
+
+    /// Run until the program ends or `max_cycles` more commands have been executed, returning
+    /// why it stopped: `halted`, `end of program` or `cycle limit`
+    pub fn run(&mut self, max_cycles: u64) -> Result<String, JsError> {
+        let stop = self.vm.run(max_cycles).map_err(to_js_error)?;
+        Ok(stop_name(stop).to_owned())
+    }
+
+    /// Keep the last `limit` commands so they can be undone with `step_back`
+    pub fn set_history_limit(&mut self, limit: usize) {
+        self.vm.set_history_limit(limit);
+    }
+
+    /// Undo the last command executed. Returns false once there is no more history.
+    pub fn step_back(&mut self) -> bool {
+        self.vm.step_back()
+    }
+
+    pub fn peek(&self, address: u16) -> u16 {
+        self.vm.peek(address)
+    }
+
+    pub fn poke(&mut self, address: u16, value: u16) {
+        self.vm.poke(address, value);
+    }
+
+    /// Press a key, or release every key with 0
+    pub fn set_keyboard(&mut self, key: u16) {
+        self.vm.set_keyboard(key);
+    }
+
+    /// Record the keys pressed from now on, to save with `recorded_input`
+    pub fn record_input(&mut self) {
+        self.vm.record_input();
+    }
+
+    /// The keys pressed since `record_input`, as a log which the emulator's --replay reads
+    pub fn recorded_input(&self) -> String {
+        emulator::input_log(self.vm.recorded_input())
+    }
+
+    /// Press the keys of a log saved by `recorded_input` at the same points of the run as before
+    pub fn replay_input(&mut self, log: &str) -> Result<(), JsError> {
+        let presses = emulator::parse_input_log(log)
+            .map_err(|(line, message)| JsError::new(&format!("Line {}: {}", line, message)))?;
+        self.vm.replay_input(presses);
+        Ok(())
+    }
+
+    pub fn screen(&self) -> Vec<u16> {
+        self.vm.screen().to_vec()
+    }
+
+    /// The values on the stack, from the bottom up
+    pub fn stack(&self) -> Vec<u16> {
+        self.vm.stack().to_vec()
+    }
+
+    pub fn current_command(&self) -> Option<String> {
+        self.vm.current_command().map(str::to_owned)
+    }
+
+    pub fn current_function(&self) -> Option<String> {
+        self.vm.current_function().map(str::to_owned)
+    }
+
+    /// The pointers, segments, heap and screen as JSON, in the form written by the emulator's
+    /// `--export-state`
+    pub fn state(&self) -> String {
+        serde_json::to_string(&self.vm.state()).expect("A VM state always serializes")
+    }
+
+    /// The number of commands executed so far
+    pub fn cycles(&self) -> u64 {
+        self.vm.cycles()
+    }
+}
+
+/// How a stop is named to JavaScript
+fn stop_name(stop: Stop) -> &'static str {
+    match stop {
+        Stop::Halted => "halted",
+        Stop::EndOfProgram => "end of program",
+        Stop::CycleLimit => "cycle limit",
+    }
+}
+
+fn to_js_error(error: impl std::error::Error) -> JsError {
+    JsError::new(&error.to_string())
+}
+
+#[test]
+fn test_compile_translate_assemble() {
+    let vm = compile_string(
+        "Main.jack",
+        "class Main { function void main() { do Output.printInt(1 + 2); return; } }",
+    )
+    .unwrap();
+    assert!(vm.starts_with("function Main.main 0"));
+
+    let asm = translate_string("Main.vm", &vm).unwrap();
+    assert!(asm.contains("(Main.main)"));
+
+    let hack = assemble_string(&asm).unwrap();
+    assert!(hack
+        .lines()
+        .all(|line| line.len() == 16 && line.chars().all(|c| c == '0' || c == '1')));
+}
+
+#[test]
+fn test_emulator() {
+    let hack = assemble_string("@R0\nD=M\n@R1\nM=D+1\n(END)\n@END\n0;JMP").unwrap();
+    let mut emulator = Emulator::new(&hack).unwrap();
+    emulator.poke(0, 41);
+
+    assert_eq!(emulator.step().unwrap(), None);
+    assert_eq!((emulator.a(), emulator.pc()), (0, 1));
+    assert_eq!(emulator.run(100).unwrap(), "halted");
+    assert_eq!(emulator.peek(1), 42);
+    assert_eq!(emulator.cycles(), 6);
+    assert_eq!(emulator.screen().len(), emulator::SCREEN_WORDS);
+}
+
+#[test]
+fn test_vm_emulator() {
+    let vm = "function Main.main 1\npush constant 2\npush constant 3\nadd\npop local 0\npush local 0\nreturn\n";
+    let mut emulator =
+        VmEmulator::new(vec!["Main.vm".to_owned()], vec![vm.to_owned()], false).unwrap();
+    emulator.set_history_limit(10);
+    emulator.start("Main.main").unwrap();
+
+    assert_eq!(emulator.current_function().as_deref(), Some("Main.main"));
+    emulator.step().unwrap();
+    emulator.step().unwrap();
+    emulator.step().unwrap();
+    assert_eq!(emulator.current_command().as_deref(), Some("add"));
+    assert!(emulator.stack().ends_with(&[2, 3]));
+    assert!(emulator.step_back());
+    assert_eq!(
+        emulator.current_command().as_deref(),
+        Some("push constant 3")
+    );
+
+    assert_eq!(emulator.run(100).unwrap(), "end of program");
+    let state = emulator.state();
+    assert!(state.contains("\"pointers\""), "{state}");
+}